@@ -0,0 +1,41 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Synthetic corpora shared by the benchmarks: invoices of about 3 KB with a
+// header, line items over two pages and totals, like the demo data.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Corpus sizes every benchmark runs over
+pub const SIZES: [usize; 3] = [10, 1_000, 10_000];
+
+/// Invoice number `n`
+pub fn invoice(n: usize) -> String {
+    let mut text = format!("INVOICE INV-{:05}\nFrom: Vendor {}\nDate: 2025-11-{:02}\n\n", n, n % 40, n % 28 + 1);
+    for line in 0..40 {
+        if line == 20 {
+            text.push('\u{c}');
+        }
+        text.push_str(&format!("Item {:02}: consulting services, {} hours at R 950.00\n", line, line % 8 + 1));
+        if line % 10 == 9 {
+            text.push('\n');
+        }
+    }
+    text.push_str("\nSubtotal: R 19,950.00\nVAT (15%): R 2,992.50\nTotal: R 22,942.50\n");
+    text
+}
+
+/// A folder of `size` invoices under the temporary directory, written once and reused
+pub fn corpus_dir(size: usize) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("doc-ai-bench-{}", size));
+    let complete = dir.join(format!("inv_{:05}.txt", size - 1));
+    if !complete.exists() {
+        fs::create_dir_all(&dir).expect("cannot create the benchmark corpus");
+        for n in 0..size {
+            fs::write(dir.join(format!("inv_{:05}.txt", n)), invoice(n)).expect("cannot write the benchmark corpus");
+        }
+    }
+    dir
+}
@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// The stages of answering a question that do not involve the model, over
+// corpora of 10, 1k and 10k invoices (prompt assembly is in prompt.rs):
+//
+//   scanning   walking a collection folder and reading each document in
+//              chunks into its word set, as `index` does
+//   chunking   splitting documents into pages and chunks for citations
+//   lexical    the inverted index and BM25 term counts keyword retrieval
+//              ranks with, and file name scoring
+//   parsing    lenient JSON parsing of answers listing as many line items,
+//              valid and with the usual defects (fences, trailing commas, cut off)
+//
+//   cargo bench --bench pipeline [-- <group>]
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use doc_ai_server::chunking::chunk_document;
+use doc_ai_server::indexer::{word_counts, words};
+use doc_ai_server::json_repair::parse_lenient;
+use doc_ai_server::reader::stream_chunks;
+use doc_ai_server::scoring::{FilenameScorer, RelevanceScorer};
+
+mod common;
+use common::{corpus_dir, invoice, SIZES};
+
+const QUESTION: &str = "Which invoices from Vendor 7 list consulting services in November 2025?";
+
+/// Text files of a folder, in name order
+fn documents(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .expect("cannot read the benchmark corpus")
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("txt"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn scanning(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scanning");
+    group.sample_size(10);
+    for size in SIZES {
+        let dir = corpus_dir(size);
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("walk", size), &dir, |b, dir| b.iter(|| documents(dir)));
+        group.bench_with_input(BenchmarkId::new("read_words", size), &dir, |b, dir| {
+            b.iter(|| {
+                let mut found = 0;
+                for path in documents(dir) {
+                    for chunk in stream_chunks(&path).expect("unreadable document") {
+                        found += words(&chunk.expect("unreadable document").1).len();
+                    }
+                }
+                found
+            })
+        });
+    }
+    group.finish();
+}
+
+fn chunking(c: &mut Criterion) {
+    let mut group = c.benchmark_group("chunking");
+    group.sample_size(10);
+    for size in SIZES {
+        let texts: Vec<String> = (0..size).map(invoice).collect();
+        let bytes: usize = texts.iter().map(String::len).sum();
+        group.throughput(Throughput::Bytes(bytes as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &texts, |b, texts| {
+            b.iter(|| texts.iter().map(|t| chunk_document(t).len()).sum::<usize>())
+        });
+    }
+    group.finish();
+}
+
+fn lexical(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lexical");
+    group.sample_size(10);
+    for size in SIZES {
+        let texts: Vec<(PathBuf, String)> =
+            (0..size).map(|n| (PathBuf::from(format!("data/invoices/inv_{:05}.txt", n)), invoice(n))).collect();
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("inverted_index", size), &texts, |b, texts| {
+            b.iter(|| {
+                let mut index: HashMap<String, Vec<&Path>> = HashMap::new();
+                for (path, text) in texts {
+                    for word in words(text) {
+                        index.entry(word).or_default().push(path);
+                    }
+                }
+                index.len()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("term_counts", size), &texts, |b, texts| {
+            let terms: HashSet<String> = words(QUESTION);
+            b.iter(|| {
+                texts
+                    .iter()
+                    .map(|(_, text)| {
+                        let counts = word_counts(text);
+                        terms.iter().filter_map(|t| counts.get(t)).sum::<usize>()
+                    })
+                    .sum::<usize>()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("filename", size), &texts, |b, texts| {
+            b.iter(|| texts.iter().map(|(path, _)| FilenameScorer.score(QUESTION, path)).sum::<f32>())
+        });
+    }
+    group.finish();
+}
+
+/// An answer listing `items` line items, as the model writes it
+fn answer(items: usize) -> String {
+    let item = |n: usize| {
+        format!(
+            r#"{{"file": "inv_{:05}.txt", "description": "consulting services", "hours": {}, "amount": 950.00}}"#,
+            n,
+            n % 8 + 1
+        )
+    };
+    let lines: Vec<String> = (0..items).map(item).collect();
+    format!(r#"{{"status": "found", "items": [{}], "sources": ["inv_00000.txt#p1c1"]}}"#, lines.join(", "))
+}
+
+fn parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parsing");
+    for size in SIZES {
+        let valid = answer(size);
+        let defects = format!("```json\n{}\n```", valid.replace("}, {", "},{").replace("]}", ",]}"));
+        let cut_off = valid[..valid.len() * 2 / 3].to_string();
+        group.throughput(Throughput::Bytes(valid.len() as u64));
+        for (name, text) in [("valid", &valid), ("defects", &defects), ("cut_off", &cut_off)] {
+            group.bench_with_input(BenchmarkId::new(name, size), text, |b, text| b.iter(|| parse_lenient(text)));
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, scanning, chunking, lexical, parsing);
+criterion_main!(benches);
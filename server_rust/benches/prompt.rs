@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Prompt assembly for large corpora: the documents section built document by
+// document, then the template filled in. `assembly/current` is what the
+// pipeline does; `assembly/format` is the earlier way (a `format!` per
+// document and per chunk marker, a chained `replace` per placeholder), kept
+// here as the yardstick. Before timing, the allocations and bytes allocated
+// for one prompt are printed for both, counted by the allocator below.
+//
+//   cargo bench --bench prompt
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use doc_ai_server::ai::{build_prompt, push_document, DEFAULT_TEMPLATE};
+use doc_ai_server::chunking::{chunk_document, Chunk};
+use doc_ai_server::{Category, Collection};
+
+mod common;
+use common::{invoice, SIZES};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Allocations and bytes allocated while running `f`
+fn allocations(f: impl FnOnce()) -> (usize, usize) {
+    let (count, bytes) = (ALLOCATIONS.load(Ordering::Relaxed), BYTES.load(Ordering::Relaxed));
+    f();
+    (ALLOCATIONS.load(Ordering::Relaxed) - count, BYTES.load(Ordering::Relaxed) - bytes)
+}
+
+struct Document {
+    name: String,
+    text: String,
+    chunks: Vec<Chunk>,
+}
+
+fn corpus(size: usize) -> Vec<Document> {
+    (0..size)
+        .map(|n| {
+            let text = invoice(n);
+            Document { name: format!("inv_{:05}.txt", n), chunks: chunk_document(&text), text }
+        })
+        .collect()
+}
+
+const QUESTION: &str = "What is the total of all invoices from Vendor 7 in November 2025?";
+
+fn current(collection: &Collection, documents: &[Document]) -> String {
+    let mut contents = String::new();
+    for doc in documents {
+        push_document(&mut contents, &doc.name, None, &["checked against the PO"], &doc.text, &doc.chunks);
+    }
+    build_prompt(collection, &contents, QUESTION)
+}
+
+fn format_based(collection: &Collection, documents: &[Document]) -> String {
+    let mut contents = String::new();
+    for doc in documents {
+        let mut body = String::with_capacity(doc.text.len() + doc.chunks.len() * 8);
+        for chunk in &doc.chunks {
+            body.push_str(&format!("[{}]\n", chunk.id));
+            body.push_str(doc.text[chunk.start..chunk.end].trim_end());
+            body.push('\n');
+        }
+        let body = format!("[Reviewer notes: {}]\n{}", ["checked against the PO"].join("; "), body);
+        contents.push_str(&format!("\n--- {} ---\n{}", doc.name, body));
+    }
+    DEFAULT_TEMPLATE
+        .replace("{system_role}", &collection.instruction)
+        .replace("{rules}", &collection.strictness.rules())
+        .replace("{query}", QUESTION)
+        .replace("{contents}", &contents)
+}
+
+fn assembly(c: &mut Criterion) {
+    let collection = Collection::from_category(&Category::Invoices);
+    let mut group = c.benchmark_group("assembly");
+    group.sample_size(20);
+    for size in SIZES {
+        let documents = corpus(size);
+        let bytes: usize = documents.iter().map(|d| d.text.len()).sum();
+        assert_eq!(current(&collection, &documents), format_based(&collection, &documents));
+        let (now, now_bytes) = allocations(|| drop(black_box(current(&collection, &documents))));
+        let (before, before_bytes) = allocations(|| drop(black_box(format_based(&collection, &documents))));
+        eprintln!(
+            "{} documents ({} KB): {} allocations, {} KB allocated (format!-based: {} allocations, {} KB)",
+            size,
+            bytes / 1024,
+            now,
+            now_bytes / 1024,
+            before,
+            before_bytes / 1024
+        );
+
+        group.throughput(Throughput::Bytes(bytes as u64));
+        group.bench_with_input(BenchmarkId::new("current", size), &documents, |b, docs| {
+            b.iter(|| current(&collection, docs))
+        });
+        group.bench_with_input(BenchmarkId::new("format", size), &documents, |b, docs| {
+            b.iter(|| format_based(&collection, docs))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, assembly);
+criterion_main!(benches);
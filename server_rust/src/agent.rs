@@ -0,0 +1,206 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Agentic mode: instead of stuffing every relevant document into the prompt,
+// the model gets tools to search and read documents itself, and we run the
+// tool-call loop against /api/chat until it produces a final answer.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+use crate::ai::{chat_with_tools, ChatMessage, Strictness};
+use crate::calc;
+use crate::metadata::is_hidden;
+use crate::vat::parse_amount;
+use crate::{find_relevant_files, get_cached_content, Collection};
+
+/// Upper bound on model turns, so a confused model can't loop forever
+const MAX_STEPS: usize = 8;
+
+static TOOLS: Lazy<Value> = Lazy::new(|| {
+    json!([
+        {
+            "type": "function",
+            "function": {
+                "name": "search",
+                "description": "Find documents relevant to a keyword query. Returns matching file names.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "query": { "type": "string", "description": "Keywords to search for" }
+                    },
+                    "required": ["query"]
+                }
+            }
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": "read",
+                "description": "Read the full text of one document by file name.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "file": { "type": "string", "description": "File name returned by search" }
+                    },
+                    "required": ["file"]
+                }
+            }
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": "sum",
+                "description": "Add up a list of amounts exactly. Always use this instead of adding numbers yourself.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "values": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Amounts as written, e.g. \"R8,866.50\""
+                        }
+                    },
+                    "required": ["values"]
+                }
+            }
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": "calculate",
+                "description": "Evaluate an arithmetic expression exactly, e.g. \"(7710.00 + 9300.00) * 15%\". Always use this for any arithmetic.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "expression": { "type": "string", "description": "Expression using + - * / ( ) and %" }
+                    },
+                    "required": ["expression"]
+                }
+            }
+        }
+    ])
+});
+
+/// Outcome of an agent run
+#[derive(Debug, Clone)]
+pub struct AgentResult {
+    pub answer: String,
+    /// Documents the model actually read
+    pub used_files: Vec<String>,
+    pub steps: usize,
+}
+
+/// State shared by the tools during one run
+struct Toolbox<'a> {
+    collections: &'a [&'a Collection],
+    used_files: Vec<String>,
+}
+
+impl Toolbox<'_> {
+    fn call(&mut self, name: &str, args: &Value) -> String {
+        let result = match name {
+            "search" => self.search(args["query"].as_str().unwrap_or_default()),
+            "read" => self.read(args["file"].as_str().unwrap_or_default()),
+            "sum" => sum_values(&args["values"]),
+            "calculate" => calc::evaluate(args["expression"].as_str().unwrap_or_default())
+                .map(|v| json!({ "result": v.to_string() }).to_string()),
+            other => Err(format!("Unknown tool '{}'", other)),
+        };
+        result.unwrap_or_else(|e| json!({ "error": e }).to_string())
+    }
+
+    fn search(&self, query: &str) -> Result<String, String> {
+        let files: Vec<String> = self
+            .collections
+            .iter()
+            .flat_map(|c| find_relevant_files(query, c))
+            .filter_map(|p| p.file_name().map(|f| f.to_string_lossy().to_string()))
+            .collect();
+        Ok(json!({ "files": files }).to_string())
+    }
+
+    fn read(&mut self, file: &str) -> Result<String, String> {
+        // Only bare file names inside one of the selected collections
+        if file.is_empty() || file.contains(['/', '\\']) || file.contains("..") {
+            return Err(format!("Invalid file name '{}'", file));
+        }
+        let path = self
+            .collections
+            .iter()
+            .map(|c| c.folder.join(file))
+            .find(|p| p.is_file() && !is_hidden(p))
+            .ok_or_else(|| format!("No document named '{}'", file))?;
+
+        let text = get_cached_content(&path).map_err(|e| e.to_string())?;
+        if !self.used_files.iter().any(|f| f == file) {
+            self.used_files.push(file.to_string());
+        }
+        Ok(text)
+    }
+}
+
+/// Exact decimal sum of amounts such as "R1,156.50" or 42
+fn sum_values(values: &Value) -> Result<String, String> {
+    let items = values.as_array().ok_or("'values' must be an array")?;
+    let mut total = Decimal::ZERO;
+    for item in items {
+        let parsed = match item {
+            Value::Number(n) => Decimal::from_str(&n.to_string()).ok(),
+            Value::String(s) => Decimal::from_str(s.trim()).ok().or_else(|| parse_amount(s)),
+            _ => None,
+        };
+        let parsed = parsed.ok_or_else(|| format!("Not an amount: {}", item))?;
+        total = total.checked_add(parsed).ok_or("Sum overflows")?;
+    }
+    Ok(json!({ "sum": total.to_string() }).to_string())
+}
+
+/// True if the error means the model cannot call tools (so the caller should use the plain pipeline)
+pub fn is_tools_unsupported(err: &anyhow::Error) -> bool {
+    err.to_string().contains("does not support tools")
+}
+
+/// Answer `query` by letting the model call the tools over the given collections
+pub async fn run_agent(
+    model: &str,
+    query: &str,
+    collections: &[&Collection],
+    instruction: &str,
+    strictness: Strictness,
+) -> Result<AgentResult> {
+    let mut messages = vec![
+        ChatMessage::system(format!(
+            "{}\n\nThe documents are not included in this conversation. Use the `search` tool to find \
+             relevant files and the `read` tool to read them before answering. Never do arithmetic yourself: \
+             use `sum` to add amounts and `calculate` for anything else.\n\n{}",
+            instruction,
+            strictness.rules()
+        )),
+        ChatMessage::user(format!("Question: {}\n\nRespond with JSON only once you have the answer.", query)),
+    ];
+    let mut toolbox = Toolbox { collections, used_files: Vec::new() };
+
+    for step in 1..=MAX_STEPS {
+        let reply = chat_with_tools(model, &messages, &TOOLS).await?;
+
+        if reply.tool_calls.is_empty() {
+            return Ok(AgentResult {
+                answer: reply.content,
+                used_files: toolbox.used_files,
+                steps: step,
+            });
+        }
+
+        let calls = reply.tool_calls.clone();
+        messages.push(reply);
+        for call in calls {
+            let output = toolbox.call(&call.function.name, &call.function.arguments);
+            messages.push(ChatMessage::tool(call.function.name, output));
+        }
+    }
+
+    anyhow::bail!("Model did not produce an answer within {} tool-calling steps", MAX_STEPS)
+}
@@ -0,0 +1,250 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Deterministic aggregation over invoice records: group by vendor, month or
+// currency and compute sum/avg/min/max/count of the amounts, with exact
+// decimal arithmetic. Used by the `agg` command and, for aggregation
+// questions, by the query pipeline (the results go into the prompt so the
+// model does not have to add anything up itself).
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::fold::contains_folded;
+use crate::records::InvoiceRecord;
+use crate::versions::is_aggregation;
+
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    Vendor,
+    Month,
+    Currency,
+}
+
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Net,
+    Tax,
+    /// Gross amount (total due)
+    Total,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+/// What to compute; `count` is always reported
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Aggregation {
+    pub group_by: Vec<GroupBy>,
+    pub measures: Vec<(Metric, Field)>,
+    /// Only records whose vendor contains this (case-insensitive)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vendor: Option<String>,
+    /// Only records dated in this period: "2025", "2025-11" or "2025-Q1"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub period: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GroupRow {
+    /// Group-by values, in `group_by` order
+    pub key: Vec<String>,
+    pub count: usize,
+    /// "sum_total", "avg_net"... → value (absent when no record has the field)
+    pub values: BTreeMap<String, Decimal>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AggregationResult {
+    pub aggregation: Aggregation,
+    pub rows: Vec<GroupRow>,
+    /// Records that matched but lack a field being measured
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub incomplete: Vec<String>,
+}
+
+fn field_value(record: &InvoiceRecord, field: Field) -> Option<Decimal> {
+    match field {
+        Field::Net => record.net,
+        Field::Tax => record.tax,
+        Field::Total => record.gross,
+    }
+}
+
+fn group_value(record: &InvoiceRecord, group: GroupBy) -> String {
+    let value = match group {
+        GroupBy::Vendor => record.vendor.as_deref(),
+        GroupBy::Month => record.month(),
+        GroupBy::Currency => record.currency.as_deref(),
+    };
+    value.unwrap_or("(unknown)").to_string()
+}
+
+/// True if the ISO `date` falls in `period` ("2025", "2025-11" or "2025-Q1")
+pub fn in_period(date: &str, period: &str) -> bool {
+    match period.split_once("-Q").or_else(|| period.split_once("-q")) {
+        Some((year, quarter)) => {
+            let Ok(quarter) = quarter.parse::<u32>() else { return false };
+            let month: u32 = date.get(5..7).and_then(|m| m.parse().ok()).unwrap_or_default();
+            date.starts_with(year) && month.div_ceil(3) == quarter
+        }
+        None => date.starts_with(period),
+    }
+}
+
+fn matches(record: &InvoiceRecord, aggregation: &Aggregation) -> bool {
+    if let Some(vendor) = &aggregation.vendor
+        && !contains_folded(record.vendor.as_deref().unwrap_or_default(), vendor)
+    {
+        return false;
+    }
+    if let Some(period) = &aggregation.period
+        && !record.date.as_deref().is_some_and(|d| in_period(d, period))
+    {
+        return false;
+    }
+    true
+}
+
+/// Run the aggregation; rows are ordered by key
+pub fn aggregate(records: &[InvoiceRecord], aggregation: &Aggregation) -> AggregationResult {
+    let mut groups: BTreeMap<Vec<String>, Vec<&InvoiceRecord>> = BTreeMap::new();
+    let mut incomplete = Vec::new();
+
+    for record in records.iter().filter(|r| matches(r, aggregation)) {
+        let key = aggregation.group_by.iter().map(|g| group_value(record, *g)).collect();
+        groups.entry(key).or_default().push(record);
+        if aggregation.measures.iter().any(|(_, f)| field_value(record, *f).is_none()) {
+            incomplete.push(record.file.clone());
+        }
+    }
+
+    let rows = groups
+        .into_iter()
+        .map(|(key, members)| {
+            let mut values = BTreeMap::new();
+            for (metric, field) in &aggregation.measures {
+                let amounts: Vec<Decimal> = members.iter().filter_map(|r| field_value(r, *field)).collect();
+                if amounts.is_empty() {
+                    continue;
+                }
+                let value = match metric {
+                    Metric::Sum => amounts.iter().sum(),
+                    Metric::Avg => (amounts.iter().sum::<Decimal>() / Decimal::from(amounts.len())).round_dp(2),
+                    Metric::Min => *amounts.iter().min().unwrap(),
+                    Metric::Max => *amounts.iter().max().unwrap(),
+                };
+                values.insert(format!("{}_{}", name(metric), name(field)), value);
+            }
+            GroupRow { key, count: members.len(), values }
+        })
+        .collect();
+
+    AggregationResult { aggregation: aggregation.clone(), rows, incomplete }
+}
+
+fn name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default()
+}
+
+static YEAR_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b(20\d{2})\b").unwrap());
+static QUARTER_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\bQ([1-4])\b").unwrap());
+static MONTH_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(jan|feb|mar|apr|may|jun|jul|aug|sep|oct|nov|dec)[a-z]*\b").unwrap()
+});
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+/// The aggregation an aggregation question asks for, if it can be recognised.
+/// `vendors` are the known vendor names (to recognise "for Acme").
+pub fn plan(question: &str, vendors: &[String]) -> Option<Aggregation> {
+    if !is_aggregation(question) {
+        return None;
+    }
+    let lower = question.to_lowercase();
+    let has = |words: &[&str]| words.iter().any(|w| lower.contains(w));
+
+    let field = if has(&["vat", "tax"]) {
+        Field::Tax
+    } else if has(&["net", "subtotal", "before vat", "excluding vat"]) {
+        Field::Net
+    } else {
+        Field::Total
+    };
+    let mut metrics = Vec::new();
+    if has(&["average", "mean", "typical"]) {
+        metrics.push(Metric::Avg);
+    }
+    if has(&["highest", "largest", "biggest", "maximum", "most expensive"]) {
+        metrics.push(Metric::Max);
+    }
+    if has(&["lowest", "smallest", "minimum", "cheapest"]) {
+        metrics.push(Metric::Min);
+    }
+    if metrics.is_empty() || has(&["total", "sum", "how much", "spend", "spent", "altogether", "combined"]) {
+        metrics.insert(0, Metric::Sum);
+    }
+
+    let mut group_by = Vec::new();
+    if has(&["per vendor", "by vendor", "each vendor", "per supplier", "by supplier", "each supplier"]) {
+        group_by.push(GroupBy::Vendor);
+    }
+    if has(&["per month", "by month", "each month", "monthly"]) {
+        group_by.push(GroupBy::Month);
+    }
+    if has(&["per currency", "by currency", "each currency"]) {
+        group_by.push(GroupBy::Currency);
+    }
+
+    let vendor = vendors
+        .iter()
+        .filter_map(|v| v.split_whitespace().next())
+        .find(|first| first.len() > 2 && lower.split(|c: char| !c.is_alphanumeric()).any(|w| w == first.to_lowercase()))
+        .map(str::to_string);
+
+    let year = YEAR_RE.captures(question).map(|c| c[1].to_string());
+    let period = match (year, QUARTER_RE.captures(question), MONTH_RE.captures(question)) {
+        (Some(y), Some(q), _) => Some(format!("{}-Q{}", y, &q[1])),
+        (Some(y), None, Some(m)) => {
+            let month = MONTHS.iter().position(|n| m[1].to_lowercase() == *n).unwrap_or_default() + 1;
+            Some(format!("{}-{:02}", y, month))
+        }
+        (Some(y), None, None) => Some(y),
+        _ => None,
+    };
+
+    Some(Aggregation { group_by, measures: metrics.into_iter().map(|m| (m, field)).collect(), vendor, period })
+}
+
+impl AggregationResult {
+    /// Plain-text table, for the terminal and the prompt
+    pub fn to_table(&self) -> String {
+        let mut columns: Vec<String> = self.aggregation.group_by.iter().map(name).collect();
+        columns.push("count".to_string());
+        let value_names: Vec<String> =
+            self.aggregation.measures.iter().map(|(m, f)| format!("{}_{}", name(m), name(f))).collect();
+        columns.extend(value_names.iter().cloned());
+
+        let mut lines = vec![columns.join("\t")];
+        for row in &self.rows {
+            let mut cells = row.key.clone();
+            cells.push(row.count.to_string());
+            cells.extend(value_names.iter().map(|n| row.values.get(n).map(|v| v.to_string()).unwrap_or_default()));
+            lines.push(cells.join("\t"));
+        }
+        if !self.incomplete.is_empty() {
+            lines.push(format!("(amounts missing in: {})", self.incomplete.join(", ")));
+        }
+        lines.join("\n")
+    }
+}
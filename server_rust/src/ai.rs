@@ -1,11 +1,25 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+// Talking to Ollama: the prompt and its rules, the answer as parsed from the
+// model's text, and the async calls. The calls, and the prompts built from a
+// `Collection`, are in the `async` build only; the rest is shared with the
+// blocking client of the `minimal` build (blocking.rs).
+
+#[cfg(feature = "async")]
 use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+#[cfg(feature = "async")]
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::time::Instant;
 
-use crate::Category;
+use crate::chunking::{render_chunks_into, Chunk, Citation};
+use crate::GenerationOptions;
+#[cfg(feature = "async")]
+use crate::{events::EventSink, hosts, Collection};
+#[cfg(feature = "async")]
+use crate::json_repair::{is_cut_off, PartialJson};
 
 #[derive(Serialize)]
 pub struct OllamaRequest {
@@ -13,63 +27,586 @@ pub struct OllamaRequest {
     pub prompt: String,
     pub stream: bool,
     pub format: String,
-    pub options: Option<Value>,
+    pub options: Option<GenerationOptions>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct OllamaResponse {
     pub response: String,
     //pub done: bool,
+    /// Tokens generated
+    #[serde(default)]
+    pub eval_count: Option<usize>,
 }
 
-pub async fn query_ollama(
-    model: &str,
-    contents: String,
-    query: &str,
-    category: &Category,
-) -> Result<String> {
-    let client = Client::new();
-    let ollama_url = "http://localhost:11434/api/generate";
-
-    let prompt = format!(
-        r#"{system_role}
-
-Rules:
-- Answer using ONLY the provided documents.
-- Return ONLY valid JSON — no extra text outside the JSON object.
-- Always include a "sources" array with the file names used.
+/// Whether the documents answered the question (the answer's "status" field)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum AnswerStatus {
+    #[default]
+    Answered,
+    /// The documents do not contain the answer
+    NotFound,
+    /// Several documents or readings fit and the model cannot tell which is meant
+    Ambiguous,
+}
+
+impl AnswerStatus {
+    /// The status the model gave; without one, a null or missing "answer" means not found
+    pub fn of(answer: &Value) -> Self {
+        if let Some(status) = answer.get("status").and_then(|s| serde_json::from_value(s.clone()).ok()) {
+            return status;
+        }
+        match answer.get("answer") {
+            Some(Value::Null) => AnswerStatus::NotFound,
+            _ => AnswerStatus::Answered,
+        }
+    }
+
+    /// Exit code of the `ask` command
+    pub fn exit_code(self) -> i32 {
+        match self {
+            AnswerStatus::Answered => 0,
+            AnswerStatus::NotFound => 2,
+            AnswerStatus::Ambiguous => 3,
+        }
+    }
+}
+
+/// A model answer as returned to library callers
+#[derive(Serialize, Debug, Clone)]
+pub struct Answer {
+    /// Text exactly as produced by the model
+    pub raw: String,
+    /// The text parsed as JSON, if it was valid
+    pub json: Option<Value>,
+    /// File names from the answer's "sources" array
+    pub sources: Vec<String>,
+    /// The same sources with page and chunk, where the model gave them
+    pub citations: Vec<Citation>,
+    pub status: AnswerStatus,
+    pub model: String,
+    pub elapsed_ms: u64,
+    /// Tokens generated, as reported by Ollama
+    pub tokens: Option<usize>,
+}
+
+impl Answer {
+    pub fn new(raw: String, model: &str, started: Instant) -> Self {
+        let json: Option<Value> = crate::json_repair::parse_lenient(&raw);
+        let citations: Vec<Citation> = json
+            .as_ref()
+            .and_then(|v| v.get("sources"))
+            .and_then(|s| s.as_array())
+            .map(|items| items.iter().filter_map(Citation::from_value).collect())
+            .unwrap_or_default();
+        let mut sources: Vec<String> = citations.iter().map(|c| c.file.clone()).collect();
+        sources.dedup();
+        let status = json.as_ref().map(AnswerStatus::of).unwrap_or_default();
+
+        Self {
+            raw,
+            json,
+            sources,
+            citations,
+            status,
+            model: model.to_string(),
+            elapsed_ms: started.elapsed().as_millis() as u64,
+            tokens: None,
+        }
+    }
+
+    /// Parsed JSON, or `{"raw": ...}` when the model did not produce valid JSON
+    pub fn value(&self) -> Value {
+        self.json.clone().unwrap_or_else(|| json!({"raw": self.raw}))
+    }
+}
+
+/// One message of an `/api/chat` conversation
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ChatMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: String,
+    /// Tools the model wants called (assistant messages only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// Tool that produced this message (tool messages only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolCall {
+    pub function: ToolFunction,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolFunction {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self { role: "system".to_string(), content: content.into(), ..Default::default() }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self { role: "user".to_string(), content: content.into(), ..Default::default() }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self { role: "assistant".to_string(), content: content.into(), ..Default::default() }
+    }
+
+    /// Result of a tool call, sent back to the model
+    pub fn tool(name: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            role: "tool".to_string(),
+            content: content.into(),
+            tool_name: Some(name.into()),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct OllamaChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    pub options: Option<GenerationOptions>,
+    /// Tool definitions (JSON schema per function)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Value>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OllamaChatResponse {
+    pub message: ChatMessage,
+    #[serde(default)]
+    pub eval_count: Option<usize>,
+}
+
+/// Which Ollama endpoint is used for generation
+#[derive(clap::ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OllamaApi {
+    /// `/api/generate` with a single prompt (works with every Ollama version)
+    #[default]
+    Generate,
+    /// `/api/chat` with separate system and user messages
+    Chat,
+}
+
+impl OllamaApi {
+    /// Endpoint name under /api
+    pub fn endpoint(&self) -> &'static str {
+        match self {
+            OllamaApi::Generate => "generate",
+            OllamaApi::Chat => "chat",
+        }
+    }
+}
+
+/// Model used when neither the command line nor the config file names one
+pub const DEFAULT_MODEL: &str = "llama3.2";
+
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+
+/// Ollama server for this process, set once at startup
+static OLLAMA_URL: OnceCell<String> = OnceCell::new();
+
+/// Install the Ollama base URL (first call wins)
+pub fn init_ollama_url(url: &str) {
+    let _ = OLLAMA_URL.set(url.trim_end_matches('/').to_string());
+}
+
+/// Base URL of the Ollama server in use
+pub fn ollama_url() -> &'static str {
+    OLLAMA_URL.get().map(String::as_str).unwrap_or(DEFAULT_OLLAMA_URL)
+}
+
+/// Answering rules shared by the generate prompt and the chat system message,
+/// after the grounding rules of the chosen `Strictness`
+pub const RULES: &str = r#"- Return ONLY valid JSON — no extra text outside the JSON object.
+- Always include a "status": "answered", "not_found" (the documents do not contain the answer) or "ambiguous" (several documents or readings fit and you cannot tell which is meant; list the candidates).
+- Always include a "sources" array citing the evidence as objects {"file": "<file name>", "page": <page>, "chunk": "<chunk id>"}, taken from the [p<page>c<n>] markers before each part of a document.
 - Be concise, accurate, and quote exact wording when relevant.
 - Use clear, descriptive keys that make sense for the content (e.g. "total_due", "vendor", "issue", "policy", "leave_days").
-- If the question is about extraction or summary, include relevant fields naturally.
+- If the question is about extraction or summary, include relevant fields naturally."#;
+
+/// How hard the prompt pushes against hallucination
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Strictness {
+    /// Documents first, but general knowledge may fill gaps; always attempt an answer
+    Lax,
+    /// Documents only; "not found" is an acceptable answer
+    #[default]
+    Normal,
+    /// Documents only, every value verbatim; anything unsupported must be reported as not found
+    Strict,
+}
+
+impl Strictness {
+    fn grounding(self) -> &'static str {
+        match self {
+            Strictness::Lax => r#"- Base the answer on the provided documents first.
+- You may use general knowledge to explain terms or fill gaps the documents leave; put anything not taken from the documents under a "notes" key.
+- Always give your best answer; mark values you are unsure of with "uncertain": true, and use "not_found" only when nothing at all applies."#,
+            Strictness::Normal => r#"- Answer using ONLY the provided documents.
+- If the documents do not contain the answer, set "status": "not_found" with a short "reason" rather than guessing."#,
+            Strictness::Strict => r#"- Answer using ONLY the provided documents. Never use outside knowledge, assumptions or typical values.
+- Every name, date and amount in the answer must appear verbatim in the documents and be cited in "sources".
+- Do not infer, estimate or complete missing values. If anything asked for is not stated in the documents, return "status": "not_found", "answer": null and a "reason" naming what is missing.
+- When in doubt, say you don't know: a missing answer is better than a wrong one."#,
+        }
+    }
+
+    /// The full answering rules (what `{rules}` expands to)
+    pub fn rules(self) -> String {
+        format!("Rules:\n{}\n{}", self.grounding(), RULES)
+    }
+}
+
+/// Default prompt; collections may replace it with their own template
+pub const DEFAULT_TEMPLATE: &str = r#"{system_role}
+
+{rules}
 
 Documents:
 {contents}
 
 Question: {query}
 
-Respond with JSON only."#,
-        system_role = category.ai_instruction(),
-        contents = contents,
-        query = query,
-    );
+Respond with JSON only."#;
 
-    let request_body = OllamaRequest {
+#[cfg(feature = "async")]
+/// User message of the chat API with the default template
+const DOCUMENTS_MESSAGE: &str = "Documents:\n{contents}\n\nQuestion: {query}\n\nRespond with JSON only.";
+
+#[cfg(feature = "async")]
+/// Fill the collection's template (or the default one)
+pub fn build_prompt(collection: &Collection, contents: &str, query: &str) -> String {
+    let template = collection.template.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+    let rules = collection.strictness.rules();
+    fill_template(
+        template,
+        &[
+            ("{system_role}", collection.instruction.as_str()),
+            ("{rules}", rules.as_str()),
+            ("{query}", query),
+            ("{contents}", contents),
+        ],
+    )
+}
+
+/// `template` with its placeholders replaced, in one pass into a buffer of the
+/// final size: the documents (megabytes for large collections) are copied once,
+/// and placeholders that occur in the values themselves are left as they are
+pub fn fill_template(template: &str, values: &[(&str, &str)]) -> String {
+    let size = values.iter().map(|(name, value)| template.matches(name).count() * value.len()).sum::<usize>();
+    let mut out = String::with_capacity(template.len() + size);
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match values.iter().find(|(name, _)| rest.starts_with(name)) {
+            Some((name, value)) => {
+                out.push_str(value);
+                rest = &rest[name.len()..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Append one document to the documents section of a prompt: its header (with
+/// the version note, if any), the reviewers' notes and the text chunk by chunk
+pub fn push_document(contents: &mut String, name: &str, note: Option<&str>, notes: &[&str], text: &str, chunks: &[Chunk]) {
+    let notes_len = notes.iter().map(|n| n.len() + 2).sum::<usize>();
+    contents.reserve(name.len() + note.map_or(0, str::len) + notes_len + text.len() + chunks.len() * 8 + 32);
+    contents.push_str("\n--- ");
+    contents.push_str(name);
+    if let Some(note) = note {
+        contents.push_str(" (");
+        contents.push_str(note);
+        contents.push(')');
+    }
+    contents.push_str(" ---\n");
+    if !notes.is_empty() {
+        contents.push_str("[Reviewer notes: ");
+        contents.push_str(&notes.join("; "));
+        contents.push_str("]\n");
+    }
+    render_chunks_into(contents, text, chunks);
+}
+
+#[cfg(feature = "async")]
+/// Chat messages: instructions as the system message, documents and question as the user message.
+/// A custom collection template is sent whole as the user message.
+pub fn build_messages(collection: &Collection, contents: &str, query: &str) -> Vec<ChatMessage> {
+    let user = match collection.template {
+        Some(_) => build_prompt(collection, contents, query),
+        None => fill_template(DOCUMENTS_MESSAGE, &[("{contents}", contents), ("{query}", query)]),
+    };
+
+    vec![
+        ChatMessage::system(format!("{}\n\n{}", collection.instruction, collection.strictness.rules())),
+        ChatMessage::user(user),
+    ]
+}
+
+/// Temperature for normal answers: as deterministic as the model allows
+pub const DEFAULT_TEMPERATURE: f32 = 0.0;
+
+/// Options used unless a query overrides them
+pub fn default_options() -> GenerationOptions {
+    GenerationOptions::new().temperature(DEFAULT_TEMPERATURE).top_p(0.95)
+}
+
+#[cfg(feature = "async")]
+/// Read Ollama's streamed reply (one JSON object per line), passing each object to `each`
+async fn read_stream(mut res: reqwest::Response, mut each: impl FnMut(Value) -> Result<()>) -> Result<()> {
+    let mut buf: Vec<u8> = Vec::new();
+    let mut handle = |line: &[u8]| -> Result<()> {
+        if line.trim_ascii().is_empty() {
+            return Ok(());
+        }
+        let value: Value = serde_json::from_slice(line).context("Invalid Ollama response")?;
+        if let Some(error) = value.get("error").and_then(Value::as_str) {
+            anyhow::bail!("Ollama error: {}", error);
+        }
+        each(value)
+    };
+    let timeout = hosts::timeout();
+    loop {
+        let Ok(chunk) = tokio::time::timeout(timeout, res.chunk()).await else {
+            anyhow::bail!("Ollama stopped sending: nothing for {} s (timeout_secs)", timeout.as_secs());
+        };
+        let Some(chunk) = chunk.context("Ollama stopped sending")? else {
+            break;
+        };
+        buf.extend_from_slice(&chunk);
+        while let Some(end) = buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buf.drain(..=end).collect();
+            handle(&line)?;
+        }
+    }
+    handle(&buf)
+}
+
+#[cfg(feature = "async")]
+/// The answer text and token count of a streamed reply; `text` picks the text out of each line
+async fn collect_stream(
+    res: reqwest::Response,
+    events: &dyn EventSink,
+    text: fn(&Value) -> Option<&str>,
+) -> Result<(String, Option<usize>)> {
+    let mut answer = String::new();
+    let mut partial = PartialJson::new();
+    let mut tokens = None;
+    read_stream(res, |line| {
+        if let Some(delta) = text(&line).filter(|t| !t.is_empty()) {
+            events.on_token_delta(delta);
+            answer.push_str(delta);
+            if let Some(value) = partial.push(delta) {
+                events.on_partial_answer(&value);
+            }
+        }
+        if let Some(n) = line.get("eval_count").and_then(Value::as_u64) {
+            tokens = Some(n as usize);
+        }
+        Ok(())
+    })
+    .await?;
+    Ok((answer, tokens))
+}
+
+#[cfg(feature = "async")]
+/// Same as `query_ollama`, but through `/api/chat`.
+/// Falls back to `/api/generate` on Ollama versions without the chat endpoint.
+pub async fn query_ollama_chat(
+    model: &str,
+    contents: &str,
+    query: &str,
+    collection: &Collection,
+    options: &GenerationOptions,
+    events: &dyn EventSink,
+) -> Result<Answer> {
+    let started = Instant::now();
+    let client = hosts::client();
+    let stream = events.wants_token_deltas();
+
+    let request_body = OllamaChatRequest {
         model: model.to_string(),
-        prompt,
+        messages: build_messages(collection, contents, query),
+        stream,
+        format: Some("json".to_string()),
+        options: Some(options.clone()),
+        tools: None,
+    };
+
+    let (res, _lease) = hosts::post(&client, model, "/api/chat", &request_body).await?;
+    let conversation = request_body.messages;
+
+    let status = res.status();
+    if !status.is_success() {
+        let text = res.text().await.unwrap_or_default();
+        if status == reqwest::StatusCode::NOT_FOUND && chat_endpoint_missing(&text) {
+            eprintln!("Ollama has no /api/chat endpoint, falling back to /api/generate");
+            return query_ollama(model, contents, query, collection, options, events).await;
+        }
+        anyhow::bail!("Ollama error {}: {}", status, text);
+    }
+
+    let (text, tokens) = match stream {
+        true => collect_stream(res, events, |line| line.pointer("/message/content")?.as_str()).await?,
+        false => {
+            let chat_res: OllamaChatResponse = res.json().await.context("Invalid Ollama response")?;
+            (chat_res.message.content, chat_res.eval_count)
+        }
+    };
+    let (text, added) = complete_cut_off(&client, model, conversation, text, options, events).await?;
+    let mut answer = Answer::new(text, model, started);
+    answer.tokens = tokens.map(|t| t + added);
+    Ok(answer)
+}
+
+#[cfg(feature = "async")]
+/// A 404 body from a server without the endpoint ("404 page not found"), not one
+/// about the request: Ollama reports a model that is not pulled as {"error": "model ... not found"}
+fn chat_endpoint_missing(body: &str) -> bool {
+    serde_json::from_str::<Value>(body).ok().and_then(|v| v.get("error").cloned()).is_none()
+}
+
+#[cfg(feature = "async")]
+/// Continuation requests for one answer cut off at `num_predict`
+pub const MAX_CONTINUATIONS: usize = 3;
+
+#[cfg(feature = "async")]
+/// Characters compared to tell a continuation from a fresh start of the answer
+const RESTART_PREFIX_CHARS: usize = 40;
+
+#[cfg(feature = "async")]
+/// An answer cut off mid-object (long line-item lists stop at `num_predict`)
+/// is continued: the conversation is sent again with the partial answer as the
+/// assistant's last message, which Ollama carries on from, and the pieces are
+/// joined. Returns the text and the tokens the continuations took.
+async fn complete_cut_off(
+    client: &Client,
+    model: &str,
+    conversation: Vec<ChatMessage>,
+    mut text: String,
+    options: &GenerationOptions,
+    events: &dyn EventSink,
+) -> Result<(String, usize)> {
+    let mut added = 0;
+    for _ in 0..MAX_CONTINUATIONS {
+        if !is_cut_off(&text) {
+            break;
+        }
+        eprintln!("The answer was cut off after {} characters; asking for the rest", text.len());
+        let mut messages = conversation.clone();
+        messages.push(ChatMessage::assistant(text.clone()));
+        // No JSON format: its grammar would make the model start a new object
+        let request_body = OllamaChatRequest {
+            model: model.to_string(),
+            messages,
+            stream: false,
+            format: None,
+            options: Some(options.clone()),
+            tools: None,
+        };
+        let (res, _lease) = hosts::post(client, model, "/api/chat", &request_body).await?;
+        if !res.status().is_success() {
+            eprintln!("WARNING: could not continue the answer (Ollama error {})", res.status());
+            break;
+        }
+        let reply: OllamaChatResponse = res.json().await.context("Invalid Ollama response")?;
+        added += reply.eval_count.unwrap_or_default();
+        let piece = reply.message.content;
+        if piece.trim().is_empty() {
+            break;
+        }
+        let prefix: String = piece.trim_start().chars().take(RESTART_PREFIX_CHARS).collect();
+        if text.trim_start().starts_with(&prefix) {
+            // The model answered again from the start instead of continuing
+            text = piece;
+        } else {
+            events.on_token_delta(&piece);
+            text.push_str(&piece);
+        }
+    }
+    if is_cut_off(&text) {
+        eprintln!("WARNING: the answer is still cut off; raise num_predict (--max-answer-tokens)");
+    }
+    Ok((text, added))
+}
+
+#[cfg(feature = "async")]
+/// One `/api/chat` round trip with tool definitions; the reply may contain tool calls
+pub async fn chat_with_tools(model: &str, messages: &[ChatMessage], tools: &Value) -> Result<ChatMessage> {
+    let client = hosts::client();
+
+    let request_body = OllamaChatRequest {
+        model: model.to_string(),
+        messages: messages.to_vec(),
         stream: false,
+        format: None,
+        options: Some(default_options()),
+        tools: Some(tools.clone()),
+    };
+
+    let (res, _lease) = hosts::post(&client, model, "/api/chat", &request_body).await?;
+
+    let status = res.status();
+    if !status.is_success() {
+        let text = res.text().await.unwrap_or_default();
+        anyhow::bail!("Ollama error {}: {}", status, text);
+    }
+
+    let chat_res: OllamaChatResponse = res.json().await.context("Invalid Ollama response")?;
+    Ok(chat_res.message)
+}
+
+#[cfg(feature = "async")]
+/// Ask the model through `/api/generate`; the reply is streamed when `events` wants token deltas
+pub async fn query_ollama(
+    model: &str,
+    contents: &str,
+    query: &str,
+    collection: &Collection,
+    options: &GenerationOptions,
+    events: &dyn EventSink,
+) -> Result<Answer> {
+    let started = Instant::now();
+    let client = hosts::client();
+    let stream = events.wants_token_deltas();
+
+    let request_body = OllamaRequest {
+        model: model.to_string(),
+        prompt: build_prompt(collection, contents, query),
+        stream,
         format: "json".to_string(),
-        options: Some(json!({
-            "temperature": 0.0,
-            "top_p": 0.95,
-        })),
+        options: Some(options.clone()),
     };
 
-    let res = client
-        .post(ollama_url)
-        .json(&request_body)
-        .send()
-        .await
-        .context("Cannot reach Ollama")?;
+    let (res, _lease) = hosts::post(&client, model, "/api/generate", &request_body).await?;
+    // The prompt is sent; keep it for continuations without copying it
+    let conversation = vec![ChatMessage::user(request_body.prompt)];
 
     let status = res.status();
     if !status.is_success() {
@@ -77,6 +614,43 @@ Respond with JSON only."#,
         anyhow::bail!("Ollama error {}: {}", status, text);
     }
 
-    let ollama_res: OllamaResponse = res.json().await.context("Invalid Ollama response")?;
-    Ok(ollama_res.response)
-}
\ No newline at end of file
+    let (text, tokens) = match stream {
+        true => collect_stream(res, events, |line| line.get("response")?.as_str()).await?,
+        false => {
+            let ollama_res: OllamaResponse = res.json().await.context("Invalid Ollama response")?;
+            (ollama_res.response, ollama_res.eval_count)
+        }
+    };
+    let (text, added) = complete_cut_off(&client, model, conversation, text, options, events).await?;
+    let mut answer = Answer::new(text, model, started);
+    answer.tokens = tokens.map(|t| t + added);
+    Ok(answer)
+}
+#[cfg(feature = "async")]
+#[derive(Deserialize)]
+struct OllamaTags {
+    models: Vec<OllamaModel>,
+}
+
+#[cfg(feature = "async")]
+#[derive(Deserialize)]
+struct OllamaModel {
+    name: String,
+}
+
+#[cfg(feature = "async")]
+/// Models installed on the Ollama server at `base_url` (`/api/tags`)
+pub async fn list_models(base_url: &str) -> Result<Vec<String>> {
+    let url = format!("{}/api/tags", base_url.trim_end_matches('/'));
+    let res = Client::new()
+        .get(&url)
+        .timeout(std::time::Duration::from_secs(5))
+        .send()
+        .await
+        .with_context(|| format!("Cannot reach Ollama at {}", base_url))?;
+    if !res.status().is_success() {
+        anyhow::bail!("Ollama error {}", res.status());
+    }
+    let tags: OllamaTags = res.json().await.context("Invalid Ollama response")?;
+    Ok(tags.models.into_iter().map(|m| m.name).collect())
+}
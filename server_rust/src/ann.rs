@@ -0,0 +1,116 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Approximate nearest neighbours over the chunk vectors: an inverted file
+// (IVF). `index` clusters the normalised vectors with k-means into about √n
+// lists, each around a centroid; a question is compared with the centroids
+// and only the vectors of the `PROBE` nearest lists are scored, a few
+// thousand of 100k chunks instead of all of them. The lists are stored with
+// the embeddings and rebuilt whenever these change; below `MIN_VECTORS` a
+// full scan is fast enough and none are built.
+
+use serde::{Deserialize, Serialize};
+use std::thread;
+
+use crate::embeddings::int8;
+
+/// Fewest vectors worth building lists for
+pub const MIN_VECTORS: usize = 2_000;
+
+/// Lists scored per query
+pub const PROBE: usize = 8;
+
+/// Vectors k-means is trained on (evenly picked; all of them when fewer)
+const SAMPLE: usize = 16_384;
+const ITERATIONS: usize = 8;
+
+/// Position of a vector: document (in key order) and chunk
+pub type Entry = (u32, u32);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Ivf {
+    /// Vectors in the lists, to tell lists built for other embeddings
+    pub vectors: usize,
+    #[serde(serialize_with = "int8::serialize_all", deserialize_with = "int8::deserialize_all")]
+    pub centroids: Vec<Vec<f32>>,
+    pub lists: Vec<Vec<Entry>>,
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn normalized(v: &[f32]) -> Vec<f32> {
+    let norm = dot(v, v).sqrt();
+    if norm == 0.0 { v.to_vec() } else { v.iter().map(|x| x / norm).collect() }
+}
+
+/// Index of the centroid nearest to `v` (both normalised)
+fn nearest(centroids: &[Vec<f32>], v: &[f32]) -> usize {
+    let mut best = (0, f32::MIN);
+    for (i, c) in centroids.iter().enumerate() {
+        let d = dot(c, v);
+        if d > best.1 {
+            best = (i, d);
+        }
+    }
+    best.0
+}
+
+/// Nearest centroid of every vector, on all cores
+fn assign(centroids: &[Vec<f32>], vectors: &[Vec<f32>]) -> Vec<usize> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let per_thread = vectors.len().div_ceil(threads).max(1);
+    thread::scope(|s| {
+        let parts: Vec<_> = vectors
+            .chunks(per_thread)
+            .map(|part| s.spawn(move || part.iter().map(|v| nearest(centroids, v)).collect::<Vec<_>>()))
+            .collect();
+        parts.into_iter().flat_map(|p| p.join().expect("k-means thread panicked")).collect()
+    })
+}
+
+impl Ivf {
+    /// Cluster `vectors`; `None` when there are fewer than `MIN_VECTORS`
+    pub fn build(vectors: &[(Entry, &[f32])]) -> Option<Self> {
+        if vectors.len() < MIN_VECTORS {
+            return None;
+        }
+        let normal: Vec<Vec<f32>> = vectors.iter().map(|(_, v)| normalized(v)).collect();
+        let dim = normal[0].len();
+        let sample: Vec<Vec<f32>> = normal.iter().step_by((normal.len() / SAMPLE).max(1)).cloned().collect();
+        let k = ((normal.len() as f64).sqrt() as usize).clamp(1, sample.len());
+
+        // Evenly spread starting centroids: the same vectors always give the same lists
+        let mut centroids: Vec<Vec<f32>> = (0..k).map(|i| sample[i * sample.len() / k].clone()).collect();
+        for _ in 0..ITERATIONS {
+            let mut sums = vec![vec![0.0f32; dim]; k];
+            let mut counts = vec![0usize; k];
+            for (v, c) in sample.iter().zip(assign(&centroids, &sample)) {
+                for (sum, x) in sums[c].iter_mut().zip(v) {
+                    *sum += x;
+                }
+                counts[c] += 1;
+            }
+            for (i, sum) in sums.into_iter().enumerate() {
+                // An empty cluster keeps its centroid
+                if counts[i] > 0 {
+                    centroids[i] = normalized(&sum);
+                }
+            }
+        }
+
+        let mut lists = vec![Vec::new(); k];
+        for ((entry, _), c) in vectors.iter().zip(assign(&centroids, &normal)) {
+            lists[c].push(*entry);
+        }
+        Some(Self { vectors: vectors.len(), centroids, lists })
+    }
+
+    /// Positions of the vectors in the `probe` lists nearest to `query`
+    pub fn candidates(&self, query: &[f32], probe: usize) -> Vec<Entry> {
+        let query = normalized(query);
+        let mut order: Vec<(usize, f32)> = self.centroids.iter().map(|c| dot(c, &query)).enumerate().collect();
+        order.sort_by(|a, b| b.1.total_cmp(&a.1));
+        order.iter().take(probe).flat_map(|(i, _)| self.lists[*i].iter().copied()).collect()
+    }
+}
@@ -0,0 +1,138 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Two-person rule (`[approval]`): an invoice whose total is over `threshold`
+// (or whose total cannot be read) only becomes approved once `approvers`
+// different people have run `approve inv_001 --as <name>`; below it one
+// approval will do. Approvals are kept in the document metadata with the
+// status history. Invoices that got to approved without enough approvers
+// (with --force, or before the rule was configured) are listed by the reports.
+
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+use crate::get_cached_content;
+use crate::lifecycle::InvoiceStatus;
+use crate::metadata::Metadata;
+use crate::records::InvoiceRecord;
+
+/// `[approval]` section of the config file
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ApprovalConfig {
+    /// Total above which several people must approve; no rule without one
+    pub threshold: Option<Decimal>,
+    /// Different approvers needed above the threshold
+    pub approvers: usize,
+}
+
+impl Default for ApprovalConfig {
+    fn default() -> Self {
+        Self { threshold: None, approvers: 2 }
+    }
+}
+
+impl ApprovalConfig {
+    /// Different approvers an invoice with this total needs (0: none, `status` may approve it)
+    pub fn required(&self, gross: Option<Decimal>) -> usize {
+        match self.threshold {
+            Some(threshold) if gross.is_none_or(|g| g.abs() > threshold) => self.approvers.max(1),
+            _ => 0,
+        }
+    }
+
+    /// Approvers needed for the document at `path`, its total read from the text
+    pub fn required_for(&self, path: &Path) -> usize {
+        match self.threshold {
+            None => 0,
+            Some(_) => self.required(get_cached_content(path).ok().and_then(|text| InvoiceRecord::from_text("", path, &text).gross)),
+        }
+    }
+}
+
+/// One person's approval of a document
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Approval {
+    pub by: String,
+    /// Unix time of the `approve`
+    pub at: u64,
+}
+
+/// Error unless `approvals` are enough to approve a document that needs `required`
+pub fn check_approvals(approvals: &[Approval], required: usize) -> Result<()> {
+    if approvals.len() < required {
+        let so_far = match approvals {
+            [] => "none so far".to_string(),
+            some => format!("so far {}", some.iter().map(|a| a.by.as_str()).collect::<Vec<_>>().join(", ")),
+        };
+        anyhow::bail!(
+            "Over the approval threshold: {} different people must run `approve <doc> --as <name>` ({})",
+            required,
+            so_far
+        );
+    }
+    Ok(())
+}
+
+/// An invoice that got to approved with fewer approvers than the rule asks for
+#[derive(Serialize, Debug, Clone)]
+pub struct ApprovalViolation {
+    pub collection: String,
+    pub file: String,
+    pub gross: Option<Decimal>,
+    pub status: InvoiceStatus,
+    pub approvers: Vec<String>,
+    pub required: usize,
+}
+
+impl fmt::Display for ApprovalViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.gross.map_or("unknown".to_string(), |g| g.to_string());
+        let by = match self.approvers.as_slice() {
+            [] => "nobody".to_string(),
+            names => names.join(", "),
+        };
+        write!(
+            f,
+            "{}/{}: {} with total {}, approved by {} ({} approvers needed)",
+            self.collection,
+            self.file,
+            self.status.as_str(),
+            total,
+            by,
+            self.required
+        )
+    }
+}
+
+/// Invoices among `records` that are (or were) approved without enough approvers
+pub fn violations(records: &[InvoiceRecord], metadata: &Metadata, config: &ApprovalConfig) -> Vec<ApprovalViolation> {
+    let mut found = Vec::new();
+    for r in records {
+        let required = config.required(r.gross);
+        let meta = metadata.get(&r.path);
+        let status = metadata.status(&r.path);
+        // Withdrawn approvals (back to extracted) are gone with the approval itself
+        let approved = match status {
+            InvoiceStatus::Approved | InvoiceStatus::Paid => true,
+            InvoiceStatus::Archived => meta
+                .and_then(|m| m.status_history.last())
+                .is_some_and(|c| matches!(c.from, InvoiceStatus::Approved | InvoiceStatus::Paid)),
+            InvoiceStatus::Received | InvoiceStatus::Extracted => false,
+        };
+        let approvers: Vec<String> = meta.map(|m| m.approvals.iter().map(|a| a.by.clone()).collect()).unwrap_or_default();
+        if required > 0 && approved && approvers.len() < required {
+            found.push(ApprovalViolation {
+                collection: r.collection.clone(),
+                file: r.file.clone(),
+                gross: r.gross,
+                status,
+                approvers,
+                required,
+            });
+        }
+    }
+    found
+}
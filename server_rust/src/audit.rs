@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Audit trail of every change doc-ai makes (the "audit" document of the
+// store, one JSON entry per line): indexing and intake, tags and notes,
+// status changes and approvals, rm/archive/restore, verified extractions and
+// config changes. Entries are only ever appended, and each carries the SHA-256
+// of its own content chained to the hash of the entry before it, so editing,
+// removing or reordering an entry breaks the chain from there on;
+// `verify-audit` recomputes it. Cutting entries off the end leaves a valid
+// chain, so auditors should keep the last hash it prints.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+use crate::metadata::now;
+use crate::store::{self, store};
+
+/// `prev` of the first entry
+pub const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Position in the log, from 1
+    pub seq: u64,
+    /// Unix time
+    pub at: u64,
+    /// Who made the change: the approver, the API tenant or the OS user
+    pub actor: String,
+    /// e.g. "tag", "status", "approve", "rm", "index", "config"
+    pub action: String,
+    /// Document path, collection or file the action was on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub details: Value,
+    /// Hash of the entry before
+    pub prev: String,
+    /// SHA-256 of `prev` and this entry's other fields
+    pub hash: String,
+}
+
+impl AuditEntry {
+    /// The hash this entry should have
+    pub fn compute_hash(&self) -> String {
+        let content = serde_json::json!({
+            "seq": self.seq,
+            "at": self.at,
+            "actor": self.actor,
+            "action": self.action,
+            "target": self.target,
+            "details": self.details,
+        });
+        let digest = Sha256::digest(format!("{}\n{}", self.prev, content).as_bytes());
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// The OS user running doc-ai, for changes made from the command line
+pub fn local_actor() -> String {
+    std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_else(|_| "unknown".to_string())
+}
+
+fn parse(text: &str) -> Result<Vec<AuditEntry>> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| serde_json::from_str(line).with_context(|| format!("Audit entry {} is not valid", i + 1)))
+        .collect()
+}
+
+/// Every entry, oldest first
+pub fn entries() -> Result<Vec<AuditEntry>> {
+    parse(&store().load(store::AUDIT)?.unwrap_or_default())
+}
+
+/// Append an entry chained to the last one
+pub fn record(actor: &str, action: &str, target: Option<&str>, details: Value) -> Result<AuditEntry> {
+    let mut added = None;
+    store()
+        .update(store::AUDIT, &mut |text| {
+            let mut text = text.unwrap_or_default();
+            // Only the last line is needed to chain on
+            let last = text.lines().rev().find(|l| !l.trim().is_empty()).map(serde_json::from_str::<AuditEntry>).transpose()?;
+            let mut entry = AuditEntry {
+                seq: last.as_ref().map_or(1, |e| e.seq + 1),
+                at: now(),
+                actor: actor.to_string(),
+                action: action.to_string(),
+                target: target.map(str::to_string),
+                details: details.clone(),
+                prev: last.map_or(GENESIS.to_string(), |e| e.hash),
+                hash: String::new(),
+            };
+            entry.hash = entry.compute_hash();
+            if !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            text.push_str(&serde_json::to_string(&entry)?);
+            text.push('\n');
+            added = Some(entry);
+            Ok(text)
+        })
+        .context("Cannot write the audit log")?;
+    added.context("Audit entry not written")
+}
+
+/// `record` for changes that are already made: a failure is reported, not returned
+pub fn record_or_warn(actor: &str, action: &str, target: Option<&str>, details: Value) {
+    if let Err(e) = record(actor, action, target, details) {
+        eprintln!("WARNING: {:#}; the {} is not in the audit log", e, action);
+    }
+}
+
+/// A "config" entry when the config file differs from the one last recorded
+pub fn record_config(actor: &str, path: &Path) {
+    let Ok(text) = std::fs::read(path) else { return };
+    let hash: String = Sha256::digest(&text).iter().map(|b| format!("{:02x}", b)).collect();
+    let last = entries().ok().and_then(|log| log.into_iter().rev().find(|e| e.action == "config"));
+    if last.is_some_and(|e| e.details.get("sha256").and_then(Value::as_str) == Some(hash.as_str())) {
+        return;
+    }
+    let target = path.display().to_string();
+    record_or_warn(actor, "config", Some(&target), serde_json::json!({ "sha256": hash }));
+}
+
+/// Result of `verify`
+#[derive(Serialize, Debug, Clone)]
+pub struct AuditCheck {
+    pub entries: usize,
+    /// Hash of the last entry, to be kept by the auditor
+    pub last_hash: Option<String>,
+    /// Problems found, first break first
+    pub problems: Vec<String>,
+}
+
+/// Recompute the chain
+pub fn verify() -> Result<AuditCheck> {
+    let text = store().load(store::AUDIT)?.unwrap_or_default();
+    let mut problems = Vec::new();
+    let mut prev = GENESIS.to_string();
+    let mut count = 0;
+    for (i, line) in text.lines().filter(|l| !l.trim().is_empty()).enumerate() {
+        count += 1;
+        let entry: AuditEntry = match serde_json::from_str(line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                problems.push(format!("line {}: not a valid entry ({})", i + 1, e));
+                prev = String::new();
+                continue;
+            }
+        };
+        if entry.seq != i as u64 + 1 {
+            problems.push(format!("line {}: sequence number {} where {} was expected", i + 1, entry.seq, i + 1));
+        }
+        if entry.prev != prev {
+            problems.push(format!("entry {}: does not follow the entry before it (removed, inserted or changed)", entry.seq));
+        }
+        if entry.compute_hash() != entry.hash {
+            problems.push(format!("entry {}: content does not match its hash (changed)", entry.seq));
+        }
+        prev = entry.hash;
+    }
+    let last_hash = Some(prev).filter(|h| count > 0 && !h.is_empty());
+    Ok(AuditCheck { entries: count, last_hash, problems })
+}
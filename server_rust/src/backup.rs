@@ -0,0 +1,341 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// `backup <archive.tar.zst>` and `restore <archive.tar.zst>`: the state doc-ai
+// builds up (index, embeddings, document metadata, query and audit logs), the
+// rest of .doc-ai (key salt, checkpoints), the mirrors of remote collections
+// and the config file, in one zstd-compressed tar. Rebuilding the embeddings
+// of a large collection takes hours; restoring them takes seconds.
+//
+// Store documents are taken through the `Store` trait, so a backup of a SQLite
+// store restores into Postgres or files as well; with [encryption] on they
+// stay sealed in the archive. manifest.json lists every file with its size
+// and SHA-256; restore checks all of them, and the archive format version,
+// before it writes anything.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::encryption;
+use crate::metadata::now;
+use crate::storage::sha256;
+use crate::store::{self, store, STATE_DIR};
+
+/// Archive layout written by this version; older ones are still read
+pub const BACKUP_FORMAT: u32 = 1;
+
+/// Store documents in a backup
+pub const BACKED_UP_DOCUMENTS: [&str; 8] = [
+    store::METADATA,
+    store::INDEX,
+    store::EMBEDDINGS,
+    store::QUERIES,
+    store::AUDIT,
+    store::CONNECTORS,
+    store::INTAKE,
+    store::SCHEMA,
+];
+
+/// Mirrors of remote collections
+const REMOTE_DIR: &str = "data/.remote";
+
+const MANIFEST: &str = "manifest.json";
+
+/// Where `restore` writes the files before moving them into place
+const STAGING_DIR: &str = ".doc-ai-restore";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackupFile {
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Manifest {
+    pub format: u32,
+    /// doc-ai version that wrote the archive
+    pub version: String,
+    /// Unix time
+    pub created_at: u64,
+    /// Where the store documents came from
+    pub store: String,
+    /// Archive path → size and hash
+    pub files: BTreeMap<String, BackupFile>,
+}
+
+/// Files under `dir`, recursively, as (path, path relative to `dir`)
+fn files_under(dir: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let mut found = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&current) else { continue };
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.is_dir() {
+                pending.push(path);
+            } else if let Ok(relative) = path.strip_prefix(dir) {
+                found.push((path.clone(), relative.to_path_buf()));
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+/// Files of .doc-ai the store documents do not already cover
+fn other_state_file(relative: &Path) -> bool {
+    let name = relative.to_string_lossy();
+    let store_file = BACKED_UP_DOCUMENTS.iter().any(|d| name == format!("{}.json", d));
+    // The database is taken document by document; locks, temp files and logs are of this host only
+    !store_file && !name.starts_with("state.db") && !name.ends_with(".lock") && !name.ends_with(".tmp") && !name.ends_with(".log")
+}
+
+/// What a backup contains, before it is written
+#[derive(Debug, Clone, Default)]
+pub struct BackupSummary {
+    pub documents: usize,
+    pub state_files: usize,
+    pub cached_files: usize,
+    pub config: bool,
+    pub bytes: u64,
+}
+
+/// Write the archive (to a temporary file first, renamed when complete)
+pub fn backup(archive: &Path, config_file: Option<&Path>, with_cache: bool) -> Result<BackupSummary> {
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut summary = BackupSummary::default();
+    for name in BACKED_UP_DOCUMENTS {
+        if let Some(text) = store().load(name)? {
+            entries.push((format!("store/{}.json", name), encryption::seal(text.into_bytes())?));
+            summary.documents += 1;
+        }
+    }
+    for (path, relative) in files_under(Path::new(STATE_DIR)).into_iter().filter(|(_, r)| other_state_file(r)) {
+        let bytes = fs::read(&path).with_context(|| format!("Cannot read {}", path.display()))?;
+        entries.push((format!("state/{}", relative.to_string_lossy().replace('\\', "/")), bytes));
+        summary.state_files += 1;
+    }
+    if with_cache {
+        for (path, relative) in files_under(Path::new(REMOTE_DIR)) {
+            let bytes = fs::read(&path).with_context(|| format!("Cannot read {}", path.display()))?;
+            entries.push((format!("cache/{}", relative.to_string_lossy().replace('\\', "/")), bytes));
+            summary.cached_files += 1;
+        }
+    }
+    if let Some(path) = config_file.filter(|p| p.is_file()) {
+        entries.push(("config/doc-ai.toml".to_string(), fs::read(path)?));
+        summary.config = true;
+    }
+
+    let manifest = Manifest {
+        format: BACKUP_FORMAT,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: now(),
+        store: store().describe(),
+        files: entries
+            .iter()
+            .map(|(name, bytes)| (name.clone(), BackupFile { bytes: bytes.len() as u64, sha256: sha256(bytes) }))
+            .collect(),
+    };
+    summary.bytes = entries.iter().map(|(_, b)| b.len() as u64).sum();
+
+    // The manifest first, so a reader learns the format before anything else
+    let mut files = vec![(MANIFEST.to_string(), serde_json::to_string_pretty(&manifest)?.into_bytes())];
+    files.extend(entries);
+    write_archive(archive, manifest.created_at, &files)?;
+    Ok(summary)
+}
+
+/// Write `files` in order as a zstd-compressed tar, to a temporary file first,
+/// renamed when complete
+#[cfg(feature = "backup")]
+pub(crate) fn write_archive(archive: &Path, mtime: u64, files: &[(String, Vec<u8>)]) -> Result<()> {
+    let tmp = archive.with_extension("partial");
+    let file = fs::File::create(&tmp).with_context(|| format!("Cannot create {}", tmp.display()))?;
+    let mut tar = tar::Builder::new(zstd::stream::write::Encoder::new(file, 0)?);
+    for (name, bytes) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(bytes.len() as u64);
+        header.set_mode(0o600);
+        header.set_mtime(mtime);
+        header.set_cksum();
+        tar.append_data(&mut header, name, bytes.as_slice())?;
+    }
+    tar.into_inner()?.finish()?.sync_all()?;
+    fs::rename(&tmp, archive).with_context(|| format!("Cannot write {}", archive.display()))
+}
+
+/// Every file of a zstd-compressed tar, by name
+#[cfg(feature = "backup")]
+pub(crate) fn read_archive(archive: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    use std::io::Read;
+    let file = fs::File::open(archive).with_context(|| format!("Cannot open {}", archive.display()))?;
+    let mut tar = tar::Archive::new(zstd::stream::read::Decoder::new(file)?);
+    let mut files = BTreeMap::new();
+    for entry in tar.entries().with_context(|| format!("{} is not a doc-ai archive", archive.display()))? {
+        let mut entry = entry.with_context(|| format!("{} is damaged", archive.display()))?;
+        let name = entry.path()?.to_string_lossy().to_string();
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes).with_context(|| format!("{} is damaged", archive.display()))?;
+        files.insert(name, bytes);
+    }
+    Ok(files)
+}
+
+#[cfg(not(feature = "backup"))]
+pub(crate) fn write_archive(_archive: &Path, _mtime: u64, _files: &[(String, Vec<u8>)]) -> Result<()> {
+    anyhow::bail!("This build cannot write archives (enable the `backup` feature)")
+}
+
+#[cfg(not(feature = "backup"))]
+pub(crate) fn read_archive(_archive: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    anyhow::bail!("This build cannot read archives (enable the `backup` feature)")
+}
+
+/// A read archive whose files all match the manifest
+pub struct CheckedBackup {
+    pub manifest: Manifest,
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+/// Read the whole archive and check it against its manifest
+pub fn read_backup(archive: &Path) -> Result<CheckedBackup> {
+    let mut files = read_archive(archive)?;
+    let manifest: Manifest = match files.remove(MANIFEST) {
+        Some(bytes) => serde_json::from_slice(&bytes).context("The backup's manifest is not valid")?,
+        None => anyhow::bail!("{} has no {}; not a doc-ai backup", archive.display(), MANIFEST),
+    };
+    if manifest.format > BACKUP_FORMAT {
+        anyhow::bail!(
+            "{} was written by doc-ai {} in backup format {}; this version reads up to format {}. Upgrade doc-ai to restore it",
+            archive.display(),
+            manifest.version,
+            manifest.format,
+            BACKUP_FORMAT
+        );
+    }
+
+    let mut problems = Vec::new();
+    for (name, expected) in &manifest.files {
+        match files.get(name) {
+            None => problems.push(format!("{} is missing", name)),
+            Some(bytes) if bytes.len() as u64 != expected.bytes || sha256(bytes) != expected.sha256 => {
+                problems.push(format!("{} does not match its checksum", name))
+            }
+            Some(_) => {}
+        }
+    }
+    problems.extend(files.keys().filter(|name| !manifest.files.contains_key(*name)).map(|name| format!("{} is not in the manifest", name)));
+    // Names are joined to local folders on restore
+    problems.extend(
+        files
+            .keys()
+            .filter(|name| Path::new(name).components().any(|c| !matches!(c, std::path::Component::Normal(_))))
+            .map(|name| format!("{} is not a relative path", name)),
+    );
+    if !problems.is_empty() {
+        anyhow::bail!("{} failed its integrity check: {}", archive.display(), problems.join("; "));
+    }
+    Ok(CheckedBackup { manifest, files })
+}
+
+/// What `restore` wrote
+#[derive(Debug, Clone, Default)]
+pub struct RestoreSummary {
+    pub documents: Vec<String>,
+    pub state_files: usize,
+    pub cached_files: usize,
+    /// Where the backed-up config file was written
+    pub config: Option<PathBuf>,
+}
+
+impl CheckedBackup {
+    /// Store documents in the archive
+    pub fn documents(&self) -> Vec<&str> {
+        self.files.keys().filter_map(|name| name.strip_prefix("store/")?.strip_suffix(".json")).collect()
+    }
+
+    /// Write everything back. The config file is only written to `config_file`
+    /// when there is none; otherwise next to it, with ".restored" added.
+    /// Store documents are unsealed and files written to a staging folder
+    /// first, so an archive that cannot be unsealed leaves the current state as it is.
+    pub fn restore(&self, config_file: &Path) -> Result<RestoreSummary> {
+        let staging = Path::new(STAGING_DIR);
+        let _ = fs::remove_dir_all(staging);
+        let Staged { documents, moves, mut summary } = match self.stage(staging, config_file) {
+            Ok(staged) => staged,
+            Err(e) => {
+                let _ = fs::remove_dir_all(staging);
+                return Err(e);
+            }
+        };
+
+        for (from, to) in &moves {
+            if let Some(dir) = to.parent().filter(|d| !d.as_os_str().is_empty()) {
+                fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+            }
+            fs::rename(from, to).with_context(|| format!("Cannot write {}", to.display()))?;
+        }
+        let _ = fs::remove_dir_all(staging);
+        for (document, text) in &documents {
+            store().save(document, text)?;
+            summary.documents.push(document.clone());
+        }
+        // Older backups carry no format versions: the next start works them out from the documents
+        if !summary.documents.iter().any(|d| d == store::SCHEMA) {
+            store().save(store::SCHEMA, "{}")?;
+        }
+        Ok(summary)
+    }
+
+    /// Unseal the store documents and write every other file under `staging`
+    fn stage(&self, staging: &Path, config_file: &Path) -> Result<Staged> {
+        let mut summary = RestoreSummary::default();
+        let mut documents = Vec::new();
+        let mut moves = Vec::new();
+        for (name, bytes) in &self.files {
+            let target = if let Some(document) = name.strip_prefix("store/").and_then(|n| n.strip_suffix(".json")) {
+                let bytes = encryption::unseal(Path::new(name), bytes.clone())?;
+                let text = String::from_utf8(bytes).with_context(|| format!("{} is not UTF-8", name))?;
+                documents.push((document.to_string(), text));
+                continue;
+            } else if let Some(relative) = name.strip_prefix("state/") {
+                summary.state_files += 1;
+                Path::new(STATE_DIR).join(relative)
+            } else if let Some(relative) = name.strip_prefix("cache/") {
+                summary.cached_files += 1;
+                Path::new(REMOTE_DIR).join(relative)
+            } else if name == "config/doc-ai.toml" {
+                let target = match config_file.exists() {
+                    true => PathBuf::from(format!("{}.restored", config_file.display())),
+                    false => config_file.to_path_buf(),
+                };
+                summary.config = Some(target.clone());
+                target
+            } else {
+                continue;
+            };
+            let staged = staging.join(moves.len().to_string());
+            write(&staged, bytes)?;
+            moves.push((staged, target));
+        }
+        Ok(Staged { documents, moves, summary })
+    }
+}
+
+/// A restore ready to be written
+struct Staged {
+    /// (name, text) of the store documents, unsealed
+    documents: Vec<(String, String)>,
+    /// (staged file, where it goes)
+    moves: Vec<(PathBuf, PathBuf)>,
+    summary: RestoreSummary,
+}
+
+fn write(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+    }
+    fs::write(path, bytes).with_context(|| format!("Cannot write {}", path.display()))
+}
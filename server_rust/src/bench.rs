@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Retrieval benchmark: a labeled set of questions with the files that should
+// be retrieved for them, scored as precision and recall at top-k and mean
+// reciprocal rank (MRR), so retrieval modes can be compared on our own
+// documents. No model is called, except for embedding the questions.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::events::EventSink;
+use crate::find_collection;
+use crate::retrieval::{retrieve, RetrievalConfig, RetrievalMode};
+
+/// `[[query]]` entry of a benchmark file
+#[derive(Deserialize, Debug, Clone)]
+pub struct BenchCase {
+    pub question: String,
+    /// Collection searched (default: `--collection`, then "invoices")
+    pub collection: Option<String>,
+    /// File names that answer the question
+    pub expected: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct BenchFile {
+    #[serde(rename = "query")]
+    cases: Vec<BenchCase>,
+}
+
+pub fn load_cases(path: &Path) -> Result<Vec<BenchCase>> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let file: BenchFile = toml::from_str(&text).with_context(|| format!("Invalid benchmark file: {}", path.display()))?;
+    if file.cases.is_empty() {
+        anyhow::bail!("{} has no [[query]] entries", path.display());
+    }
+    Ok(file.cases)
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CaseResult {
+    pub question: String,
+    pub expected: Vec<String>,
+    /// Retrieved file names, best first
+    pub retrieved: Vec<String>,
+    pub precision: f64,
+    pub recall: f64,
+    /// 1 / rank of the first expected file retrieved; 0 if none was
+    pub reciprocal_rank: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BenchReport {
+    pub mode: RetrievalMode,
+    pub top_k: usize,
+    pub precision: f64,
+    pub recall: f64,
+    pub mrr: f64,
+    pub cases: Vec<CaseResult>,
+}
+
+fn score(case: &BenchCase, retrieved: Vec<String>) -> CaseResult {
+    let hits = retrieved.iter().filter(|f| case.expected.contains(f)).count() as f64;
+    let precision = if retrieved.is_empty() { 0.0 } else { hits / retrieved.len() as f64 };
+    let recall = if case.expected.is_empty() { 1.0 } else { hits / case.expected.len() as f64 };
+    let reciprocal_rank =
+        retrieved.iter().position(|f| case.expected.contains(f)).map(|i| 1.0 / (i + 1) as f64).unwrap_or_default();
+    CaseResult { question: case.question.clone(), expected: case.expected.clone(), retrieved, precision, recall, reciprocal_rank }
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, n) = values.fold((0.0, 0usize), |(s, n), v| (s + v, n + 1));
+    if n == 0 { 0.0 } else { sum / n as f64 }
+}
+
+/// Retrieve the top `top_k` files for every case with `config` and score them;
+/// retrieval warnings go to `events`
+pub async fn bench_retrieval(
+    cases: &[BenchCase],
+    default_collection: &str,
+    top_k: usize,
+    config: &RetrievalConfig,
+    events: &dyn EventSink,
+) -> Result<BenchReport> {
+    let mut results = Vec::new();
+    for case in cases {
+        let name = case.collection.as_deref().unwrap_or(default_collection);
+        let collection = find_collection(name).with_context(|| format!("Unknown collection '{}'", name))?;
+        let retrieved: Vec<String> = retrieve(&case.question, collection, top_k, config, events)
+            .await
+            .into_iter()
+            .filter_map(|(path, _)| path.file_name().map(|f| f.to_string_lossy().to_string()))
+            .collect();
+        results.push(score(case, retrieved));
+    }
+    Ok(BenchReport {
+        mode: config.mode,
+        top_k,
+        precision: mean(results.iter().map(|r| r.precision)),
+        recall: mean(results.iter().map(|r| r.recall)),
+        mrr: mean(results.iter().map(|r| r.reciprocal_rank)),
+        cases: results,
+    })
+}
+
+impl BenchReport {
+    /// Summary line and the questions that missed an expected file
+    pub fn to_table(&self) -> String {
+        let mut lines = vec![format!(
+            "{:<10} P@{:<3} {:.3}   R@{:<3} {:.3}   MRR {:.3}   ({} queries)",
+            format!("{:?}", self.mode).to_lowercase(),
+            self.top_k,
+            self.precision,
+            self.top_k,
+            self.recall,
+            self.mrr,
+            self.cases.len()
+        )];
+        for case in self.cases.iter().filter(|c| c.recall < 1.0) {
+            let missed: Vec<&str> =
+                case.expected.iter().filter(|f| !case.retrieved.contains(f)).map(String::as_str).collect();
+            lines.push(format!("    missed {} for \"{}\"", missed.join(", "), case.question));
+        }
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// doc-ai-ask: one question about the given files, answered by a local Ollama
+// model and printed as JSON. Built by the `minimal` feature, without tokio:
+//
+//   cargo build --release --no-default-features --features minimal
+//   doc-ai-ask "What is the total due?" data/invoices/inv_001.txt
+//   doc-ai-ask --category support --model phi3:mini "Who is waiting for a refund?" tickets/*.txt
+//
+// Exit code as for `doc-ai-server ask`: 0 answered, 1 error, 2 not found in
+// the documents, 3 ambiguous.
+
+use clap::Parser;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use doc_ai_server::ai::{DEFAULT_MODEL, DEFAULT_OLLAMA_URL};
+use doc_ai_server::{BlockingClient, Category, Strictness};
+
+/// Answer one question about the given files with a local Ollama model
+#[derive(Parser)]
+#[command(name = "doc-ai-ask", version)]
+struct Args {
+    question: String,
+
+    /// Documents to answer from
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// Ollama model name
+    #[arg(long, env = "DOC_AI_MODEL", default_value = DEFAULT_MODEL)]
+    model: String,
+
+    #[arg(long, env = "DOC_AI_OLLAMA_URL", default_value = DEFAULT_OLLAMA_URL)]
+    ollama_url: String,
+
+    /// Kind of documents, whose instructions the prompt starts with
+    #[arg(long, default_value = "invoices", value_parser = category)]
+    category: Category,
+
+    #[arg(long, value_enum)]
+    strictness: Option<Strictness>,
+
+    /// Seconds allowed for the answer
+    #[arg(long, default_value_t = 300)]
+    timeout_secs: u64,
+}
+
+fn category(name: &str) -> Result<Category, String> {
+    Category::from_api_value(name).ok_or_else(|| format!("expected {}", Category::all_api_values_human()))
+}
+
+fn main() {
+    let args = Args::parse();
+    let client = BlockingClient::new(&args.ollama_url)
+        .model(&args.model)
+        .category(args.category)
+        .strictness(args.strictness.unwrap_or_default())
+        .timeout(Duration::from_secs(args.timeout_secs));
+    match client.ask_files(&args.question, &args.files) {
+        Ok(answer) => {
+            println!("{}", serde_json::to_string_pretty(&answer.value()).unwrap_or_default());
+            std::process::exit(answer.status.exit_code());
+        }
+        Err(e) => {
+            eprintln!("ERROR: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
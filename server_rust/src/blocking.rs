@@ -0,0 +1,136 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Blocking Ollama client of the `minimal` build (feature `minimal`, usually
+// with `--no-default-features`): no tokio and no reqwest, one `/api/generate`
+// call through ureq. The question is asked about the documents the caller
+// names, with the pipeline's prompt template and answering rules, and the
+// reply is parsed into the same `Answer`. There is no retrieval, index, store
+// or config file, and no retries: for a shell script or a sync app that knows
+// which files to ask about. `doc-ai-ask` (src/bin/doc-ai-ask.rs) wraps it.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::ai::{default_options, fill_template, ollama_url, push_document, OllamaRequest, OllamaResponse, DEFAULT_MODEL};
+use crate::ai::{Answer, Strictness, DEFAULT_TEMPLATE};
+use crate::chunking::chunk_document;
+use crate::reader::read_document;
+use crate::{Category, GenerationOptions};
+
+/// Time allowed for a whole answer unless `timeout` says otherwise
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct BlockingClient {
+    url: String,
+    model: String,
+    category: Category,
+    strictness: Strictness,
+    options: GenerationOptions,
+    timeout: Duration,
+}
+
+impl Default for BlockingClient {
+    fn default() -> Self {
+        Self::new(ollama_url())
+    }
+}
+
+impl BlockingClient {
+    /// Client for the Ollama server at `url`, e.g. "http://localhost:11434",
+    /// asking llama3.2 with the invoices instructions
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            model: DEFAULT_MODEL.to_string(),
+            category: Category::Invoices,
+            strictness: Strictness::default(),
+            options: default_options(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// Kind of documents, whose instructions open the prompt
+    pub fn category(mut self, category: Category) -> Self {
+        self.category = category;
+        self
+    }
+
+    pub fn strictness(mut self, strictness: Strictness) -> Self {
+        self.strictness = strictness;
+        self
+    }
+
+    pub fn options(mut self, options: GenerationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Time allowed for the whole answer
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The prompt for `question` over `documents` (name, text), laid out as the pipeline lays it out
+    pub fn prompt(&self, question: &str, documents: &[(String, String)]) -> String {
+        let mut contents = String::new();
+        for (name, text) in documents {
+            push_document(&mut contents, name, None, &[], text, &chunk_document(text));
+        }
+        let rules = self.strictness.rules();
+        fill_template(
+            DEFAULT_TEMPLATE,
+            &[
+                ("{system_role}", self.category.ai_instruction()),
+                ("{rules}", rules.as_str()),
+                ("{query}", question),
+                ("{contents}", contents.as_str()),
+            ],
+        )
+    }
+
+    /// Ask `question` about `documents` (name, text)
+    pub fn ask(&self, question: &str, documents: &[(String, String)]) -> Result<Answer> {
+        let started = Instant::now();
+        let request = OllamaRequest {
+            model: self.model.clone(),
+            prompt: self.prompt(question, documents),
+            stream: false,
+            format: "json".to_string(),
+            options: Some(self.options.clone()),
+        };
+        let agent = ureq::AgentBuilder::new().timeout_connect(CONNECT_TIMEOUT).timeout(self.timeout).build();
+        let res = match agent.post(&format!("{}/api/generate", self.url)).send_json(&request) {
+            Ok(res) => res,
+            Err(ureq::Error::Status(status, res)) => {
+                let text = res.into_string().unwrap_or_default();
+                anyhow::bail!("Ollama error {}: {}", status, text);
+            }
+            Err(e) => return Err(e).with_context(|| format!("Cannot reach Ollama at {}", self.url)),
+        };
+        let reply: OllamaResponse = res.into_json().context("Invalid Ollama response")?;
+        let mut answer = Answer::new(reply.response, &self.model, started);
+        answer.tokens = reply.eval_count;
+        Ok(answer)
+    }
+
+    /// Ask `question` about the files at `paths`, read as the pipeline reads documents
+    pub fn ask_files(&self, question: &str, paths: &[impl AsRef<Path>]) -> Result<Answer> {
+        let mut documents = Vec::new();
+        for path in paths {
+            let path = path.as_ref();
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| path.display().to_string());
+            documents.push((name, read_document(path)?));
+        }
+        self.ask(question, &documents)
+    }
+}
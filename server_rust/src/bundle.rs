@@ -0,0 +1,353 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// `export-bundle <file.tar.zst>` and `import-bundle <file.tar.zst>`: a
+// collection (or all of them) as a portable bundle, to move it to another
+// machine or hand it to an auditor. Unlike `backup`, a bundle holds documents
+// rather than state: the text of every document, its metadata (tags, notes,
+// verified extraction, status and approvals, tombstone), the fields read from
+// it (extracted.json, for the reader; import reads them again from the text)
+// and, unless --no-embeddings, its embedding. Paths are "<collection>/<file>",
+// so the bundle does not depend on where the folders are.
+//
+// Import merges into the collections of the same name. A document that is
+// not there yet is added; one with the same text has its metadata merged (see
+// `DocumentMeta::merge`); one with different text is a conflict, settled by
+// --on-conflict: skip it (default), overwrite the local one, or import it next
+// to it as <name>-imported.txt. Embeddings are only taken when they come from
+// the model the local ones do, and only by a build with the `embeddings`
+// feature.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backup::{read_archive, write_archive, BackupFile};
+#[cfg(feature = "embeddings")]
+use crate::docid::doc_key;
+use crate::docid::is_text_file;
+#[cfg(feature = "embeddings")]
+use crate::embeddings::{EmbeddedDoc, EmbeddingIndex};
+use crate::metadata::{now, DocumentMeta, Metadata};
+use crate::records::InvoiceRecord;
+use crate::storage::{sha256, DocumentSource};
+use crate::{collections, find_collection, get_cached_content};
+
+/// Bundle layout written by this version
+pub const BUNDLE_FORMAT: u32 = 1;
+
+const MANIFEST: &str = "bundle.json";
+const METADATA: &str = "metadata.json";
+const EXTRACTED: &str = "extracted.json";
+const EMBEDDINGS: &str = "embeddings.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BundleManifest {
+    pub format: u32,
+    /// doc-ai version that wrote the bundle
+    pub version: String,
+    /// Unix time
+    pub created_at: u64,
+    pub collections: Vec<String>,
+    pub documents: usize,
+    /// Model of the embeddings, when they are in the bundle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding_model: Option<String>,
+    /// Bundle path → size and hash
+    pub files: BTreeMap<String, BackupFile>,
+}
+
+/// Read as they are, to be ignored, by a build without embeddings
+#[cfg(not(feature = "embeddings"))]
+type EmbeddedDoc = serde_json::Value;
+
+#[derive(Serialize, Deserialize, Default)]
+struct BundledEmbeddings {
+    model: String,
+    /// "<collection>/<file>" → vector
+    documents: BTreeMap<String, EmbeddedDoc>,
+}
+
+/// Documents of a collection folder, by name
+fn documents_in(folder: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(folder) else { return Vec::new() };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| is_text_file(p))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Write the documents of `collection` (all collections without one) to `bundle`
+pub fn export_bundle(bundle: &Path, collection: Option<&str>, with_embeddings: bool) -> Result<BundleManifest> {
+    let metadata = Metadata::load()?;
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut names = Vec::new();
+    let mut metas: BTreeMap<String, DocumentMeta> = BTreeMap::new();
+    let mut extracted = Vec::new();
+    let mut paths = Vec::new();
+
+    for c in collections().iter().filter(|c| collection.is_none_or(|name| c.matches(name))) {
+        names.push(c.name.clone());
+        for path in documents_in(&c.folder) {
+            let Some(file) = path.file_name().map(|f| f.to_string_lossy().to_string()) else { continue };
+            let name = format!("{}/{}", c.name, file);
+            let text = get_cached_content(&path).with_context(|| format!("Cannot read {}", path.display()))?;
+            if let Some(meta) = metadata.get(&path) {
+                metas.insert(name.clone(), meta.clone());
+            }
+            extracted.push(InvoiceRecord::from_text(&c.name, &path, &text));
+            entries.push((format!("documents/{}", name), text.into_bytes()));
+            paths.push((name, path));
+        }
+    }
+    if names.is_empty() {
+        anyhow::bail!("Unknown collection '{}'; use {}", collection.unwrap_or_default(), collections::all_collection_names_human());
+    }
+    let documents = entries.len();
+    entries.push((METADATA.to_string(), serde_json::to_vec_pretty(&metas)?));
+    entries.push((EXTRACTED.to_string(), serde_json::to_vec_pretty(&extracted)?));
+    let embedding_model = match local_embeddings(&paths, with_embeddings)? {
+        Some(embeddings) => {
+            entries.push((EMBEDDINGS.to_string(), serde_json::to_vec(&embeddings)?));
+            Some(embeddings.model)
+        }
+        None => None,
+    };
+
+    let manifest = BundleManifest {
+        format: BUNDLE_FORMAT,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: now(),
+        collections: names,
+        documents,
+        embedding_model,
+        files: entries
+            .iter()
+            .map(|(name, bytes)| (name.clone(), BackupFile { bytes: bytes.len() as u64, sha256: sha256(bytes) }))
+            .collect(),
+    };
+    let mut files = vec![(MANIFEST.to_string(), serde_json::to_vec_pretty(&manifest)?)];
+    files.extend(entries);
+    write_archive(bundle, manifest.created_at, &files)?;
+    Ok(manifest)
+}
+
+/// What to do with a bundled document whose name is taken by a different local one
+#[derive(clap::ValueEnum, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnConflict {
+    /// Keep the local document and its metadata
+    #[default]
+    Skip,
+    /// Replace the local document and its metadata
+    Overwrite,
+    /// Import it next to the local one as <name>-imported.txt
+    Rename,
+}
+
+/// What `import_bundle` did (or, with `dry_run`, would do), by "<collection>/<file>"
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ImportSummary {
+    pub added: Vec<String>,
+    /// Same text as the local document: only the metadata was merged
+    pub merged: Vec<String>,
+    pub overwritten: Vec<String>,
+    /// Bundled name → name it was imported under
+    pub renamed: Vec<(String, String)>,
+    /// Conflicts left as they were
+    pub skipped: Vec<String>,
+    pub embeddings: usize,
+    /// Why the bundle's embeddings were not taken
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embeddings_ignored: Option<String>,
+}
+
+enum Outcome {
+    /// Write the text, replace the metadata
+    Write,
+    /// Only merge the metadata
+    Merge,
+}
+
+/// First free "<stem>-imported[-n].txt" next to `path`
+fn free_name(path: &Path) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    (1..)
+        .map(|n| match n {
+            1 => path.with_file_name(format!("{}-imported.txt", stem)),
+            n => path.with_file_name(format!("{}-imported-{}.txt", stem, n)),
+        })
+        .find(|p| !p.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Check `bundle` against its manifest and merge it into the local collections
+pub fn import_bundle(bundle: &Path, on_conflict: OnConflict, dry_run: bool) -> Result<ImportSummary> {
+    let mut files = read_archive(bundle)?;
+    let manifest: BundleManifest = match files.remove(MANIFEST) {
+        Some(bytes) => serde_json::from_slice(&bytes).context("The bundle's manifest is not valid")?,
+        None => anyhow::bail!("{} has no {}; not a doc-ai bundle", bundle.display(), MANIFEST),
+    };
+    if manifest.format > BUNDLE_FORMAT {
+        anyhow::bail!(
+            "{} was written by doc-ai {} in bundle format {}; this version reads up to format {}",
+            bundle.display(),
+            manifest.version,
+            manifest.format,
+            BUNDLE_FORMAT
+        );
+    }
+    let mut problems: Vec<String> = manifest
+        .files
+        .iter()
+        .filter(|(name, expected)| files.get(*name).is_none_or(|b| sha256(b) != expected.sha256))
+        .map(|(name, _)| format!("{} is missing or does not match its checksum", name))
+        .collect();
+    problems.extend(files.keys().filter(|name| !manifest.files.contains_key(*name)).map(|n| format!("{} is not in the manifest", n)));
+    if !problems.is_empty() {
+        anyhow::bail!("{} failed its integrity check: {}", bundle.display(), problems.join("; "));
+    }
+
+    let unknown: Vec<&str> = manifest.collections.iter().filter(|c| find_collection(c).is_none()).map(String::as_str).collect();
+    if !unknown.is_empty() {
+        anyhow::bail!(
+            "{} holds collection(s) not configured here: {}; add them to the config file first ({} are)",
+            bundle.display(),
+            unknown.join(", "),
+            collections::all_collection_names_human()
+        );
+    }
+    let metas: BTreeMap<String, DocumentMeta> = match files.get(METADATA) {
+        Some(bytes) => serde_json::from_slice(bytes).context("The bundle's metadata is not valid")?,
+        None => BTreeMap::new(),
+    };
+
+    // Decide on every document before writing any
+    let mut summary = ImportSummary::default();
+    let mut plan: Vec<(String, PathBuf, Outcome)> = Vec::new();
+    for (name, bytes) in files.iter().filter_map(|(n, b)| Some((n.strip_prefix("documents/")?, b))) {
+        let (collection, file) = name.rsplit_once('/').with_context(|| format!("{} is not <collection>/<file>", name))?;
+        let c = find_collection(collection).with_context(|| format!("Unknown collection '{}'", collection))?;
+        if !matches!(c.source, DocumentSource::Local) {
+            anyhow::bail!("Collection '{}' is mirrored from elsewhere; import into its source instead", c.name);
+        }
+        if Path::new(file).file_name().is_none_or(|f| f != file) || file.contains("..") {
+            anyhow::bail!("{} is not a plain file name", name);
+        }
+        let local = c.folder.join(file);
+        if !local.exists() {
+            summary.added.push(name.to_string());
+            plan.push((name.to_string(), local, Outcome::Write));
+        } else if get_cached_content(&local).is_ok_and(|text| text.as_bytes() == bytes.as_slice()) {
+            summary.merged.push(name.to_string());
+            plan.push((name.to_string(), local, Outcome::Merge));
+        } else {
+            match on_conflict {
+                OnConflict::Skip => summary.skipped.push(name.to_string()),
+                OnConflict::Overwrite => {
+                    summary.overwritten.push(name.to_string());
+                    plan.push((name.to_string(), local, Outcome::Write));
+                }
+                OnConflict::Rename => {
+                    let renamed = free_name(&local);
+                    let new_name = format!("{}/{}", collection, renamed.file_name().unwrap_or_default().to_string_lossy());
+                    summary.renamed.push((name.to_string(), new_name));
+                    plan.push((name.to_string(), renamed, Outcome::Write));
+                }
+            }
+        }
+    }
+
+    let bundled_embeddings: Option<BundledEmbeddings> = match files.get(EMBEDDINGS) {
+        Some(bytes) => Some(serde_json::from_slice(bytes).context("The bundle's embeddings are not valid")?),
+        None => None,
+    };
+    let embeddings = match bundled_embeddings {
+        None => None,
+        Some(e) => match unusable_embeddings(&e)? {
+            None => Some(e),
+            Some(why) => {
+                summary.embeddings_ignored = Some(why);
+                None
+            }
+        },
+    };
+    summary.embeddings = embeddings.as_ref().map_or(0, |e| plan.iter().filter(|(n, _, _)| e.documents.contains_key(n)).count());
+    if dry_run {
+        return Ok(summary);
+    }
+
+    for (name, path, outcome) in &plan {
+        if matches!(outcome, Outcome::Write) {
+            let bytes = &files[&format!("documents/{}", name)];
+            fs::write(path, bytes).with_context(|| format!("Cannot write {}", path.display()))?;
+        }
+    }
+    Metadata::update(|metadata| {
+        for (name, path, outcome) in &plan {
+            let Some(meta) = metas.get(name) else { continue };
+            match outcome {
+                Outcome::Write => *metadata.entry(path) = meta.clone(),
+                Outcome::Merge => metadata.entry(path).merge(meta),
+            }
+        }
+    })?;
+    if let Some(bundled) = embeddings.filter(|_| summary.embeddings > 0) {
+        take_embeddings(bundled, &plan)?;
+    }
+    Ok(summary)
+}
+
+/// The local embeddings of `documents` ("<collection>/<file>", path), if asked for and there are any
+#[cfg(feature = "embeddings")]
+fn local_embeddings(documents: &[(String, PathBuf)], wanted: bool) -> Result<Option<BundledEmbeddings>> {
+    if !wanted {
+        return Ok(None);
+    }
+    let local = EmbeddingIndex::load()?;
+    let documents: BTreeMap<String, EmbeddedDoc> =
+        documents.iter().filter_map(|(name, path)| Some((name.clone(), local.get(path)?.clone()))).collect();
+    Ok((!documents.is_empty()).then_some(BundledEmbeddings { model: local.model, documents }))
+}
+
+#[cfg(not(feature = "embeddings"))]
+fn local_embeddings(_documents: &[(String, PathBuf)], _wanted: bool) -> Result<Option<BundledEmbeddings>> {
+    Ok(None)
+}
+
+/// Why the bundled embeddings cannot join the local ones, if they cannot
+#[cfg(feature = "embeddings")]
+fn unusable_embeddings(bundled: &BundledEmbeddings) -> Result<Option<String>> {
+    let local = EmbeddingIndex::load()?;
+    Ok((!local.documents.is_empty() && bundled.model != local.model)
+        .then(|| format!("made with {}, the local ones with {}", bundled.model, local.model)))
+}
+
+#[cfg(not(feature = "embeddings"))]
+fn unusable_embeddings(_bundled: &BundledEmbeddings) -> Result<Option<String>> {
+    Ok(Some("this build has no embeddings (enable the `embeddings` feature)".to_string()))
+}
+
+/// Add the bundled embeddings of the imported documents to the local ones
+#[cfg(feature = "embeddings")]
+fn take_embeddings(bundled: BundledEmbeddings, plan: &[(String, PathBuf, Outcome)]) -> Result<()> {
+    let mut local = EmbeddingIndex::load()?;
+    local.model = bundled.model;
+    for (name, path, _) in plan {
+        if let Some(doc) = bundled.documents.get(name) {
+            local.documents.insert(doc_key(path), doc.clone());
+        }
+    }
+    // The next `index` builds the nearest-neighbour lists again
+    local.ann = None;
+    local.save()
+}
+
+#[cfg(not(feature = "embeddings"))]
+fn take_embeddings(_bundled: BundledEmbeddings, _plan: &[(String, PathBuf, Outcome)]) -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,201 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Extraction events for downstream systems (ERP import, alerting): each
+// `[[publish]]` target gets a JSON event whenever a document of its
+// collections is extracted (indexed by `index`, or taken in by `intake
+// queue`) or verified (`verify`). Targets are webhooks (POST, optionally
+// signed), NATS subjects, Kafka topics or Redis streams; the brokers need the
+// feature of the same name. A target that cannot be reached is reported as a
+// warning and does not fail the run.
+//
+// The event carries the fields read from the document and its invoice checks,
+// and for `verified` the corrected extraction:
+//
+//   {"event": "extracted", "collection": "invoices", "file": "inv_001.txt",
+//    "document": "data/invoices/inv_001.txt", "record": {...}, "verification": {...}, "at": 1764000000}
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::collections;
+use crate::docid::doc_key;
+use crate::metadata::now;
+use crate::reader::read_document;
+use crate::records::InvoiceRecord;
+use crate::storage::{hex, hmac_sha256};
+use crate::vat::{check_invoice, VatReport};
+
+/// Header carrying the webhook signature: `sha256=<hex HMAC-SHA256 of the body>`
+pub const SIGNATURE_HEADER: &str = "X-Doc-Ai-Signature";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PublishTarget {
+    Webhook,
+    Nats,
+    Kafka,
+    Redis,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// Fields read from a new or changed document
+    Extracted,
+    /// A corrected extraction recorded with `verify`
+    Verified,
+}
+
+/// `[[publish]]` in doc-ai.toml
+#[derive(Deserialize, Debug, Clone)]
+pub struct PublishConfig {
+    pub target: PublishTarget,
+    /// Webhook URL, or the broker as in `[queue] url`
+    pub url: String,
+    /// Subject, topic or stream (not for webhooks)
+    #[serde(default)]
+    pub topic: String,
+    /// Only documents of these collections (all when empty)
+    #[serde(default)]
+    pub collections: Vec<String>,
+    /// Only these events (all when empty)
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+    /// Environment variable with the key webhook bodies are signed with
+    #[serde(default)]
+    pub secret_env: Option<String>,
+}
+
+impl PublishConfig {
+    pub fn wants(&self, event: &BusEvent) -> bool {
+        (self.collections.is_empty() || self.collections.iter().any(|c| c.eq_ignore_ascii_case(&event.collection)))
+            && (self.events.is_empty() || self.events.contains(&event.event))
+    }
+
+    fn describe(&self) -> String {
+        match self.target {
+            PublishTarget::Webhook => format!("webhook {}", self.url),
+            target => format!("{:?} {} at {}", target, self.topic, self.url).to_lowercase(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BusEvent {
+    pub event: EventKind,
+    pub collection: String,
+    pub file: String,
+    /// Document id, as in the index and metadata
+    pub document: String,
+    pub record: InvoiceRecord,
+    pub verification: VatReport,
+    /// The corrected extraction, for `verified`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extraction: Option<Value>,
+    /// Unix time
+    pub at: u64,
+}
+
+/// The event for the document at `path`; None outside the collection folders or when unreadable
+pub fn document_event(event: EventKind, path: &Path, extraction: Option<Value>) -> Option<BusEvent> {
+    let collection = collections().iter().find(|c| path.starts_with(&c.folder))?;
+    let text = read_document(path).ok()?;
+    let record = InvoiceRecord::from_text(&collection.name, path, &text);
+    Some(BusEvent {
+        event,
+        collection: collection.name.clone(),
+        verification: check_invoice(&record.file, &text),
+        file: record.file.clone(),
+        document: doc_key(path),
+        record,
+        extraction,
+        at: now(),
+    })
+}
+
+/// Send each target the events it wants; returns one message per target that failed
+pub async fn publish(targets: &[PublishConfig], events: &[BusEvent]) -> Vec<String> {
+    let mut failures = Vec::new();
+    for target in targets {
+        let wanted: Vec<&BusEvent> = events.iter().filter(|e| target.wants(e)).collect();
+        if wanted.is_empty() {
+            continue;
+        }
+        if let Err(e) = publish_to(target, &wanted).await {
+            failures.push(format!("{} event(s) not published to {}: {:#}", wanted.len(), target.describe(), e));
+        }
+    }
+    failures
+}
+
+/// `publish`, with the failures printed as warnings
+pub async fn publish_or_warn(targets: &[PublishConfig], events: &[BusEvent]) {
+    for failure in publish(targets, events).await {
+        eprintln!("WARNING: {}", failure);
+    }
+}
+
+async fn publish_to(target: &PublishConfig, events: &[&BusEvent]) -> Result<()> {
+    let payloads = events.iter().map(serde_json::to_vec).collect::<Result<Vec<Vec<u8>>, _>>()?;
+    match target.target {
+        PublishTarget::Webhook => {
+            let secret = match &target.secret_env {
+                Some(name) => Some(std::env::var(name).with_context(|| format!("{} is not set", name))?),
+                None => None,
+            };
+            let client = reqwest::Client::builder().timeout(TIMEOUT).build()?;
+            for payload in payloads {
+                let mut request = client.post(&target.url).header(reqwest::header::CONTENT_TYPE, "application/json");
+                if let Some(secret) = &secret {
+                    request = request.header(SIGNATURE_HEADER, signature(secret, &payload)?);
+                }
+                request.body(payload).send().await?.error_for_status()?;
+            }
+            Ok(())
+        }
+        #[cfg(feature = "nats")]
+        PublishTarget::Nats => {
+            let client = async_nats::connect(&target.url).await?;
+            for payload in payloads {
+                client.publish(target.topic.clone(), payload.into()).await?;
+            }
+            client.flush().await?;
+            Ok(())
+        }
+        #[cfg(feature = "kafka")]
+        PublishTarget::Kafka => {
+            use rdkafka::producer::{FutureProducer, FutureRecord};
+            let producer: FutureProducer = rdkafka::ClientConfig::new().set("bootstrap.servers", &target.url).create()?;
+            for payload in payloads {
+                let record = FutureRecord::<(), [u8]>::to(&target.topic).payload(&payload);
+                producer.send(record, TIMEOUT).await.map_err(|(e, _)| e)?;
+            }
+            Ok(())
+        }
+        #[cfg(feature = "redis")]
+        PublishTarget::Redis => {
+            use redis::AsyncCommands;
+            let client = redis::Client::open(target.url.as_str())?;
+            let mut connection = client.get_multiplexed_async_connection().await?;
+            for payload in payloads {
+                let _: String = connection.xadd(&target.topic, "*", &[("payload", payload)]).await?;
+            }
+            Ok(())
+        }
+        #[allow(unreachable_patterns)]
+        other => {
+            let feature = format!("{:?}", other).to_lowercase();
+            anyhow::bail!("This build has no {} support (enable the `{}` feature)", feature, feature)
+        }
+    }
+}
+
+/// `sha256=<hex HMAC-SHA256>` of a webhook body, for the receiver to check
+pub fn signature(secret: &str, body: &[u8]) -> Result<String> {
+    Ok(format!("sha256={}", hex(&hmac_sha256(secret.as_bytes(), body)?)))
+}
@@ -0,0 +1,151 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Exact decimal expression evaluator, used by the `calculate` tool so the
+// model never has to do arithmetic itself.
+// Supports + - * / ( ), unary minus, percentages ("15%" = 0.15),
+// thousands separators and currency prefixes ("R1,156.50").
+// Parentheses and signs nest at most MAX_DEPTH deep, so an expression from the
+// model cannot exhaust the stack.
+
+use rust_decimal::Decimal;
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+
+/// Deepest nesting of parentheses and unary signs accepted
+pub const MAX_DEPTH: usize = 64;
+
+/// Evaluate an arithmetic expression with exact decimal semantics
+pub fn evaluate(expression: &str) -> Result<Decimal, String> {
+    let mut parser = Parser { chars: expression.chars().peekable(), depth: 0 };
+    let value = parser.expr()?;
+    parser.skip_whitespace();
+    match parser.chars.peek() {
+        None => Ok(value.normalize()),
+        Some(c) => Err(format!("Unexpected '{}' in expression", c)),
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    /// Factors being parsed, one inside the other
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Decimal, String> {
+        let mut value = self.term()?;
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some('+') => {
+                    self.chars.next();
+                    value = value.checked_add(self.term()?).ok_or("Overflow")?;
+                }
+                Some('-') => {
+                    self.chars.next();
+                    value = value.checked_sub(self.term()?).ok_or("Overflow")?;
+                }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    // term := factor (('*' | 'x' | '/') factor)*
+    fn term(&mut self) -> Result<Decimal, String> {
+        let mut value = self.factor()?;
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some('*') | Some('×') | Some('x') => {
+                    self.chars.next();
+                    value = value.checked_mul(self.factor()?).ok_or("Overflow")?;
+                }
+                Some('/') | Some('÷') => {
+                    self.chars.next();
+                    let divisor = self.factor()?;
+                    if divisor.is_zero() {
+                        return Err("Division by zero".to_string());
+                    }
+                    value = value.checked_div(divisor).ok_or("Overflow")?;
+                }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    fn factor(&mut self) -> Result<Decimal, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!("Expression nested more than {} levels deep", MAX_DEPTH));
+        }
+        self.depth += 1;
+        let value = self.nested_factor();
+        self.depth -= 1;
+        value
+    }
+
+    // factor := ('-' | '+') factor | '(' expr ')' | number ['%']
+    fn nested_factor(&mut self) -> Result<Decimal, String> {
+        self.skip_whitespace();
+        let value = match self.chars.peek() {
+            Some('-') => {
+                self.chars.next();
+                return Ok(-self.factor()?);
+            }
+            Some('+') => {
+                self.chars.next();
+                return self.factor();
+            }
+            Some('(') => {
+                self.chars.next();
+                let value = self.expr()?;
+                self.skip_whitespace();
+                if self.chars.next() != Some(')') {
+                    return Err("Missing ')'".to_string());
+                }
+                value
+            }
+            Some(_) => self.number()?,
+            None => return Err("Unexpected end of expression".to_string()),
+        };
+
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'%') {
+            self.chars.next();
+            return Ok(value / Decimal::ONE_HUNDRED);
+        }
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Decimal, String> {
+        // Currency prefix such as "R", "ZAR", "$"
+        while self.chars.peek().is_some_and(|c| c.is_ascii_uppercase() || "$€£".contains(*c)) {
+            self.chars.next();
+        }
+        self.skip_whitespace();
+
+        let mut digits = String::new();
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                '0'..='9' | '.' => digits.push(c),
+                ',' | '_' => {}
+                _ => break,
+            }
+            self.chars.next();
+        }
+
+        if digits.is_empty() {
+            return Err(match self.chars.peek() {
+                Some(c) => format!("Expected a number, found '{}'", c),
+                None => "Expected a number".to_string(),
+            });
+        }
+        Decimal::from_str(&digits).map_err(|_| format!("Invalid number '{}'", digits))
+    }
+}
@@ -0,0 +1,171 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// /ws/chat: a chat session over a WebSocket, for the browser demo. Connect
+// (with `?session=<id>` to resume one), then send JSON messages:
+//
+//   {"type": "ask", "query": "...", ...}   any /query fields besides "query"
+//   {"type": "pin", "doc": "inv_001"}      {"type": "unpin", "doc": "inv_001"}
+//   {"type": "history"}                    {"type": "reset"}
+//
+// Replies: `session` (on connect and after every change), the query events
+// (`scan_start` ... `token_delta`, `partial_answer` ... `done`), `answer` with the /query
+// envelope, `history`, and `error` for messages that could not be handled.
+
+use rocket::futures::{SinkExt, StreamExt};
+use rocket::State;
+use rocket_ws::{Channel, Message, WebSocket};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use doc_ai_server::*;
+
+use crate::{build_query, shutdown};
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Ask {
+        #[serde(flatten)]
+        request: Box<QueryRequest>,
+    },
+    Pin {
+        doc: String,
+    },
+    Unpin {
+        doc: String,
+    },
+    History,
+    Reset,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage<'a> {
+    Session { id: &'a str, pinned: &'a [String], turns: usize },
+    Answer(Envelope),
+    History { turns: &'a [Turn] },
+    Error { message: String },
+}
+
+fn text(message: &impl Serialize) -> Message {
+    Message::Text(serde_json::to_string(message).unwrap_or_default())
+}
+
+fn session_message(session: &Session) -> Message {
+    text(&ServerMessage::Session { id: &session.id, pinned: &session.pinned, turns: session.history.len() })
+}
+
+fn error(message: impl Into<String>) -> Message {
+    text(&ServerMessage::Error { message: message.into() })
+}
+
+#[utoipa::path(
+    get,
+    path = "/ws/chat",
+    params(
+        ("session" = Option<String>, Query, description = "Session to resume"),
+        ("api_key" = Option<String>, Query, description = "Tenant API key, when the server has tenants"),
+    ),
+    responses((status = 101, description = "WebSocket; JSON messages as described in the README"))
+)]
+#[get("/ws/chat?<session>")]
+pub fn ws_chat(
+    ws: WebSocket,
+    session: Option<String>,
+    live: &State<Arc<LiveConfig>>,
+    sessions: &State<Arc<SessionStore>>,
+    tenancy: Tenancy,
+) -> Channel<'static> {
+    let live = live.inner().clone();
+    let sessions = sessions.inner().clone();
+    let mut session = sessions.open(session.as_deref(), tenancy.0.as_ref());
+
+    ws.channel(move |mut stream| {
+        Box::pin(async move {
+            stream.send(session_message(&session)).await?;
+            while let Some(message) = stream.next().await {
+                let message = match message? {
+                    Message::Text(text) => text,
+                    Message::Close(_) => break,
+                    _ => continue,
+                };
+                let message: ClientMessage = match serde_json::from_str(&message) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        stream.send(error(format!("Invalid message: {}", e))).await?;
+                        continue;
+                    }
+                };
+                let (state, file_config) = live.get();
+
+                match message {
+                    ClientMessage::Ask { mut request } => {
+                        if let Some(tenant) = &session.tenant {
+                            if let Some(reason) = QuotaStatus::of(&tenant.name).refusal() {
+                                stream.send(error(reason)).await?;
+                                continue;
+                            }
+                            tenant.scope_request(&mut request, state.collection.as_deref());
+                        }
+                        let query = match build_query(&request, &state, &file_config) {
+                            Ok(query) => session.prepare(query),
+                            Err(err) => {
+                                stream.send(text(&ServerMessage::Answer(Envelope::failure(err)))).await?;
+                                continue;
+                            }
+                        };
+                        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+                        let metered = Metered::new(Some(Arc::new(ChannelEvents(tx))));
+                        let query = Query { events: Some(metered.clone()), ..query };
+                        let tenant = session.tenant.as_ref().map(|t| t.name.clone());
+                        let task = tokio::spawn(async move {
+                            let in_flight = shutdown::InFlight::start();
+                            let result = query.run().await;
+                            in_flight.finish();
+                            if let Some(tenant) = tenant {
+                                quotas::record(&tenant, metered.tokens());
+                            }
+                            result
+                        });
+                        while let Some(event) = rx.recv().await {
+                            stream.send(text(&event)).await?;
+                        }
+                        let envelope = match task.await {
+                            Ok(Ok(response)) => {
+                                session.record(&request.query, &response);
+                                Envelope::success(response)
+                            }
+                            Ok(Err(err)) => Envelope::failure(err),
+                            Err(e) => {
+                                stream.send(error(e.to_string())).await?;
+                                continue;
+                            }
+                        };
+                        stream.send(text(&ServerMessage::Answer(envelope))).await?;
+                    }
+                    ClientMessage::Pin { doc } => {
+                        if let Err(e) = session.pin(&doc, state.collection.as_deref()) {
+                            stream.send(error(format!("{:#}", e))).await?;
+                            continue;
+                        }
+                    }
+                    ClientMessage::Unpin { doc } => {
+                        if !session.unpin(&doc) {
+                            stream.send(error(format!("{} is not pinned", doc))).await?;
+                            continue;
+                        }
+                    }
+                    ClientMessage::History => {
+                        stream.send(text(&ServerMessage::History { turns: &session.history })).await?;
+                        continue;
+                    }
+                    ClientMessage::Reset => session.reset(),
+                }
+                sessions.save(&session);
+                stream.send(session_message(&session)).await?;
+            }
+            sessions.save(&session);
+            Ok(())
+        })
+    })
+}
@@ -0,0 +1,182 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Splitting documents into pages and chunks so answers can cite evidence
+// precisely ("inv_001.txt, page 1, chunk p1c2") instead of whole files.
+// Pages are separated by form feeds, as produced by PDF text extraction.
+
+use serde::{Deserialize, Serialize};
+
+/// Target chunk size in bytes; paragraphs are never split, so chunks may be larger
+pub const MAX_CHUNK_CHARS: usize = 1_200;
+
+const PAGE_BREAK: char = '\u{c}';
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// "p<page>c<n>", unique within the document
+    pub id: String,
+    /// 1-based page number
+    pub page: usize,
+    /// Byte range in the document text
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A citation from the answer's "sources" array
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Citation {
+    pub file: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<String>,
+}
+
+impl Citation {
+    /// Accepts `{"file", "page", "chunk"}` objects and plain "file" / "file#p1c2" strings
+    pub fn from_value(value: &serde_json::Value) -> Option<Self> {
+        match value {
+            serde_json::Value::String(s) => {
+                let (file, chunk) = match s.split_once('#') {
+                    Some((f, c)) => (f.to_string(), Some(c.to_string())),
+                    None => (s.clone(), None),
+                };
+                let page = chunk.as_deref().and_then(page_of);
+                Some(Self { file, page, chunk })
+            }
+            serde_json::Value::Object(_) => {
+                let mut citation: Self = serde_json::from_value(value.clone()).ok()?;
+                if citation.page.is_none() {
+                    citation.page = citation.chunk.as_deref().and_then(page_of);
+                }
+                Some(citation)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Page number encoded in a chunk id ("p2c1" → 2)
+fn page_of(chunk: &str) -> Option<usize> {
+    chunk.strip_prefix('p')?.split('c').next()?.parse().ok()
+}
+
+/// Incremental chunker: feed lines as they are read, collect finished chunks.
+/// Chunks are runs of whole paragraphs (separated by blank lines), restarting at every page break.
+#[derive(Debug, Default)]
+pub struct Chunker {
+    page: usize,
+    n: usize,
+    /// Bytes consumed so far
+    offset: usize,
+    chunk_start: usize,
+    chunk: String,
+    paragraph: String,
+    ready: Vec<(Chunk, String)>,
+}
+
+impl Chunker {
+    pub fn new() -> Self {
+        Self { page: 1, ..Default::default() }
+    }
+
+    /// Feed the next line (including its '\n', if any)
+    pub fn push_line(&mut self, line: &str) {
+        let mut parts = line.split(PAGE_BREAK);
+        if let Some(first) = parts.next() {
+            self.push_page_text(first);
+        }
+        for part in parts {
+            self.end_paragraph();
+            self.flush();
+            self.offset += PAGE_BREAK.len_utf8();
+            self.page += 1;
+            self.n = 0;
+            self.push_page_text(part);
+        }
+    }
+
+    /// Chunks completed so far (removed from the chunker)
+    pub fn take_ready(&mut self) -> Vec<(Chunk, String)> {
+        std::mem::take(&mut self.ready)
+    }
+
+    /// Flush the last chunk and return everything not yet taken
+    pub fn finish(mut self) -> Vec<(Chunk, String)> {
+        self.end_paragraph();
+        self.flush();
+        self.ready
+    }
+
+    fn push_page_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.paragraph.push_str(text);
+        if text.trim().is_empty() {
+            self.end_paragraph();
+        }
+    }
+
+    fn end_paragraph(&mut self) {
+        if self.paragraph.is_empty() {
+            return;
+        }
+        let paragraph = std::mem::take(&mut self.paragraph);
+        if paragraph.trim().is_empty() && self.chunk.is_empty() {
+            // Leading blank lines belong to no chunk
+            self.offset += paragraph.len();
+            return;
+        }
+        if !paragraph.trim().is_empty() && !self.chunk.is_empty() && self.chunk.len() + paragraph.len() > MAX_CHUNK_CHARS {
+            self.flush();
+        }
+        if self.chunk.is_empty() {
+            self.chunk_start = self.offset;
+        }
+        self.offset += paragraph.len();
+        self.chunk.push_str(&paragraph);
+    }
+
+    fn flush(&mut self) {
+        let text = std::mem::take(&mut self.chunk);
+        if text.trim().is_empty() {
+            return;
+        }
+        self.n += 1;
+        let chunk = Chunk {
+            id: format!("p{}c{}", self.page, self.n),
+            page: self.page,
+            start: self.chunk_start,
+            end: self.chunk_start + text.len(),
+        };
+        self.ready.push((chunk, text));
+    }
+}
+
+/// Split `text` into chunks of whole paragraphs, restarting at every page break
+pub fn chunk_document(text: &str) -> Vec<Chunk> {
+    let mut chunker = Chunker::new();
+    for line in text.split_inclusive('\n') {
+        chunker.push_line(line);
+    }
+    chunker.finish().into_iter().map(|(chunk, _)| chunk).collect()
+}
+
+/// Document text with a `[p1c1]` marker before each chunk, for the prompt
+pub fn render_chunks(text: &str, chunks: &[Chunk]) -> String {
+    let mut out = String::with_capacity(text.len() + chunks.len() * 8);
+    render_chunks_into(&mut out, text, chunks);
+    out
+}
+
+/// `render_chunks`, appended to `out`
+pub fn render_chunks_into(out: &mut String, text: &str, chunks: &[Chunk]) {
+    for chunk in chunks {
+        out.push('[');
+        out.push_str(&chunk.id);
+        out.push_str("]\n");
+        out.push_str(text[chunk.start..chunk.end].trim_end());
+        out.push('\n');
+    }
+}
@@ -1,23 +1,612 @@
-// SPDX-License-Identifier: GPL-3.0-or-later
-
-// Command Line Arguments
-
-use clap::Parser;
-
-#[derive(Parser, Debug)]
-#[command(
-    name = "doc-ai-server",
-    about = "Local AI-powered document Q&A server",
-    version,
-    author
-)]
-
-pub struct Args {
-    /// Port to listen on
-    #[arg(long, default_value_t = 8001)]
-    pub port: u16,
-
-    /// Ollama model name (e.g. llama3.2, phi3:mini)
-    #[arg(long, default_value = "llama3.2")]
-    pub model: String,
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Command Line Arguments
+
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::aggregate::{Field, GroupBy};
+use crate::ai::{OllamaApi, Strictness, DEFAULT_MODEL};
+use crate::bundle::OnConflict;
+use crate::config::Config;
+use crate::export::ExportFormat;
+use crate::lifecycle::InvoiceStatus;
+use crate::reconcile::DEFAULT_WINDOW_DAYS;
+use crate::retrieval::{EmbedBackend, RetrievalMode, DEFAULT_MAX_DOCS};
+use crate::service::ServiceKind;
+use crate::sync::Prefer;
+use crate::training::TrainingFormat;
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    name = "doc-ai-server",
+    about = "Local AI-powered document Q&A server",
+    version,
+    author
+)]
+pub struct Args {
+    /// What to do (defaults to `serve`)
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Port to listen on
+    #[arg(long, env = "DOC_AI_PORT", default_value_t = 8001)]
+    pub port: u16,
+
+    /// Address to listen on (127.0.0.1 by default; 0.0.0.0 in a container)
+    #[arg(long, env = "DOC_AI_ADDRESS")]
+    pub address: Option<std::net::IpAddr>,
+
+    /// Do not watch the config file, templates and schemas for changes while serving
+    #[arg(long, env = "DOC_AI_NO_RELOAD")]
+    pub no_reload: bool,
+
+    /// Seconds the requests in flight get to finish after SIGTERM or Ctrl-C before they are cut off
+    #[arg(long, env = "DOC_AI_SHUTDOWN_GRACE", default_value_t = 30)]
+    pub shutdown_grace: u32,
+
+    /// With a shared store: stop gracefully once another host saves a newer index, to be restarted on it
+    #[arg(long, env = "DOC_AI_RESTART_ON_INDEX")]
+    pub restart_on_index: bool,
+
+    /// Ollama model name (e.g. llama3.2, phi3:mini); defaults to `model` in the config file, then llama3.2
+    #[arg(long)]
+    pub model: Option<String>,
+
+    /// Ollama endpoint: `generate` (single prompt, the default) or `chat` (system/user messages)
+    #[arg(long, value_enum)]
+    pub api: Option<OllamaApi>,
+
+    /// Documents placed in the prompt per collection (capped at 20)
+    #[arg(long, env = "DOC_AI_TOP_K", default_value_t = DEFAULT_MAX_DOCS)]
+    pub top_k: usize,
+
+    /// How documents are ranked (overrides `mode` in [retrieval]): keyword, embedding, hybrid, bm25 or filename
+    #[arg(long, env = "DOC_AI_RETRIEVER", value_enum)]
+    pub retriever: Option<RetrievalMode>,
+
+    /// Old file selection: a question naming the document type ("invoice") gets every file of the collection
+    #[arg(long, env = "DOC_AI_LEGACY_MATCHING")]
+    pub legacy_matching: bool,
+
+    /// Anti-hallucination rules: `lax` (outside knowledge allowed), `normal` or `strict` (verbatim values, "don't know" when unsure);
+    /// defaults to each collection's setting
+    #[arg(long, env = "DOC_AI_STRICTNESS", value_enum)]
+    pub strictness: Option<Strictness>,
+
+    /// Split compound questions ("... in Q1 and how does it compare to Q4?") and answer each part separately
+    #[arg(long, env = "DOC_AI_DECOMPOSE")]
+    pub decompose: bool,
+
+    /// Agentic mode: the model searches/reads documents via tools instead of getting them all up front
+    #[arg(long, env = "DOC_AI_AGENT")]
+    pub agent: bool,
+
+    /// Longest answer in tokens (num_predict); answers cut off mid-JSON are continued
+    #[arg(long, env = "DOC_AI_MAX_ANSWER_TOKENS")]
+    pub max_answer_tokens: Option<i32>,
+
+    /// Answer each question N times at a small temperature and report how much the numbers disagree
+    #[arg(long, env = "DOC_AI_SAMPLES", default_value_t = 1)]
+    pub samples: usize,
+
+    /// Include the answer's provenance (retrieved docs, chunks, extracted values, checks) in every response
+    #[arg(long, env = "DOC_AI_EXPLAIN")]
+    pub explain: bool,
+
+    /// Answer schema (a name from [schemas] in the config file) the answers must follow
+    #[arg(long, env = "DOC_AI_SCHEMA")]
+    pub schema: Option<String>,
+
+    /// Answer every question in this document domain's mode (e.g. contracts: clauses with quotes and citations)
+    /// instead of the collection's own
+    #[arg(long, env = "DOC_AI_DOMAIN")]
+    pub domain: Option<String>,
+
+    /// Render verified amounts and ISO dates in answers for this locale (en-ZA, en-US, en-GB, de-DE, de-CH, fr-FR, nl-NL);
+    /// defaults to `locale` in the config file
+    #[arg(long)]
+    pub locale: Option<String>,
+
+    /// Output profile (a name from [output_profiles] in the config file) applied to every answer;
+    /// requests cannot choose another one
+    #[arg(long, env = "DOC_AI_OUTPUT_PROFILE")]
+    pub output_profile: Option<String>,
+
+    /// Let aggregation questions (totals, counts...) also use documents replaced by a correction
+    #[arg(long, env = "DOC_AI_INCLUDE_SUPERSEDED")]
+    pub include_superseded: bool,
+
+    /// Only documents in these accounts-payable statuses, in questions and reports
+    /// (comma-separated or repeated, e.g. approved,paid)
+    #[arg(long, value_enum, value_delimiter = ',', global = true)]
+    pub invoice_status: Vec<InvoiceStatus>,
+
+    /// Fail on documents that cannot be read (binary, not UTF-8, too large) instead of skipping them
+    #[arg(long, env = "DOC_AI_STRICT", global = true)]
+    pub strict: bool,
+
+    /// Wait for another `index` run to finish instead of failing at once
+    #[arg(long, env = "DOC_AI_WAIT", global = true)]
+    pub wait: bool,
+
+    /// Treat warnings (skipped files, truncated context, failed checks...) as errors
+    #[arg(long, env = "DOC_AI_DENY_WARNINGS", global = true)]
+    pub deny_warnings: bool,
+
+    /// Config file (defaults to ./doc-ai.toml when present)
+    #[arg(long, env = "DOC_AI_CONFIG", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Environment from the config file (`[profile.<name>]`: Ollama URL, model, collections, options)
+    #[arg(long, env = "DOC_AI_PROFILE", global = true)]
+    pub profile: Option<String>,
+
+    /// Collection used when a request does not name one
+    #[arg(long, global = true)]
+    pub collection: Option<String>,
+}
+
+impl Args {
+    /// Fill in what the command line left open from the config file (after its profile is applied)
+    pub fn apply_config(&mut self, config: &Config) {
+        self.model = self.model.take().or_else(|| config.model.clone());
+        self.api = self.api.or(config.api);
+        self.collection = self.collection.take().or_else(|| config.default_collection.clone());
+        self.locale = self.locale.take().or_else(|| config.locale.clone());
+    }
+
+    pub fn model(&self) -> &str {
+        self.model.as_deref().unwrap_or(DEFAULT_MODEL)
+    }
+
+    pub fn api(&self) -> OllamaApi {
+        self.api.unwrap_or_default()
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Run the HTTP server
+    Serve,
+    /// First-run setup: find Ollama, pick a model, create the data folders and
+    /// doc-ai.toml, add sample invoices and run a test question
+    Init {
+        /// Accept every default without asking
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Answer one question and print the JSON envelope.
+    /// Exit code: 0 answered, 1 error, 2 not found in the documents, 3 ambiguous
+    Ask {
+        question: String,
+        /// Index the documents changed since the last `index` before answering
+        #[arg(long)]
+        reindex_stale: bool,
+    },
+    /// Conversation about the documents: follow-up questions see the earlier answers.
+    /// `/pin <doc>` answers from chosen documents only; `/help` lists the commands
+    Chat,
+    /// Fetch remote collections (S3, HTTP) into the local cache and build the search index
+    Index {
+        /// Files read concurrently
+        #[arg(long, default_value_t = 4)]
+        jobs: usize,
+        /// Files queued between scanning, reading and indexing (bounds memory use)
+        #[arg(long, default_value_t = 64)]
+        queue: usize,
+        /// Save progress after this many files, so an interrupted run can resume
+        #[arg(long, default_value_t = 250)]
+        checkpoint_every: usize,
+        /// Discard the saved index and re-read every document
+        #[arg(long)]
+        fresh: bool,
+        /// Seconds allowed for reading one document before it counts as failed
+        #[arg(long, default_value_t = 60)]
+        timeout_secs: u64,
+        /// Quarantine (skip) a document after it failed this many runs in a row
+        #[arg(long, default_value_t = 3)]
+        max_failures: u32,
+        /// Try the quarantined documents again
+        #[arg(long)]
+        retry_quarantined: bool,
+        /// Compute the embeddings with Ollama or in this process (default: `[retrieval] embed_backend`)
+        #[arg(long, value_enum)]
+        embed_backend: Option<EmbedBackend>,
+    },
+    /// Score retrieval on labeled questions (precision and recall at --top-k, MRR);
+    /// see bench/retrieval.toml
+    BenchRetrieval {
+        /// TOML file of [[query]] entries: question, expected files, optional collection
+        file: PathBuf,
+        /// Retrieval modes to compare (repeatable); defaults to the configured one
+        #[arg(long, value_enum)]
+        mode: Vec<RetrievalMode>,
+        /// Print JSON instead of a summary
+        #[arg(long)]
+        json: bool,
+    },
+    /// Aggregate invoice amounts exactly, without the model (e.g. `agg --group-by vendor --sum total`)
+    Agg {
+        /// Group by vendor, month or currency (repeatable)
+        #[arg(long, value_enum)]
+        group_by: Vec<GroupBy>,
+        /// Sum of net, tax or total (repeatable)
+        #[arg(long, value_enum)]
+        sum: Vec<Field>,
+        #[arg(long, value_enum)]
+        avg: Vec<Field>,
+        #[arg(long, value_enum)]
+        min: Vec<Field>,
+        #[arg(long, value_enum)]
+        max: Vec<Field>,
+        /// Only vendors whose name contains this
+        #[arg(long)]
+        vendor: Option<String>,
+        /// Only this period: 2025, 2025-11 or 2025-Q1
+        #[arg(long)]
+        period: Option<String>,
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Monthly spend per vendor as a time series, from the invoice figures
+    Spend {
+        /// Amount to add up: net, tax or total
+        #[arg(long, value_enum, default_value_t = Field::Total)]
+        field: Field,
+        /// Only this period: 2025, 2025-11 or 2025-Q1
+        #[arg(long)]
+        period: Option<String>,
+        /// table (with sparklines), json or csv
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Export the invoices as bills for bookkeeping software
+    Export {
+        /// ledger, qif, iif or quickbooks-csv
+        #[arg(long, value_enum)]
+        format: ExportFormat,
+        /// Account mapping file (see accounts.toml.example); built-in account names otherwise
+        #[arg(long)]
+        accounts: Option<PathBuf>,
+        /// Only this period: 2025, 2025-11 or 2025-Q1
+        #[arg(long)]
+        period: Option<String>,
+        /// Write to this file instead of standard output
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Export the receipts (collections of the `receipts` domain) as the expense tool's CSV import
+    ExportExpenses {
+        /// Only this collection (name or alias) instead of every receipts collection
+        #[arg(long)]
+        collection: Option<String>,
+        /// Only this period: 2025, 2025-11 or 2025-Q1
+        #[arg(long)]
+        period: Option<String>,
+        /// Write to this file instead of standard output
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Match bank payments (statements in the `payments` collection) to invoices;
+    /// reports unpaid invoices and unmatched payments
+    Reconcile {
+        /// Days after the invoice date a payment matched on amount alone may be made
+        #[arg(long, default_value_t = DEFAULT_WINDOW_DAYS)]
+        window_days: i64,
+        /// Only invoices of this period: 2025, 2025-11 or 2025-Q1
+        #[arg(long)]
+        period: Option<String>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Run the [[rule]] validators over the invoices (all, or --collection) and list the
+    /// violations per invoice; exits with 1 when there are any
+    CheckRules {
+        /// Print JSON instead of the list
+        #[arg(long)]
+        json: bool,
+    },
+    /// Period close checklist over the invoices of one period; exits with 1 when a check fails
+    Close {
+        /// Accounting period: 2024-09, 2024-Q3 or 2024
+        #[arg(long)]
+        period: String,
+        /// Print JSON instead of the checklist
+        #[arg(long)]
+        json: bool,
+        /// Also write the report to this file, for the archive
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Save the index, embeddings, metadata, logs, the rest of .doc-ai, the remote
+    /// mirrors and the config file in one archive (e.g. backup-2025-11.tar.zst)
+    Backup {
+        archive: PathBuf,
+        /// Leave out the mirrors of remote collections (fetched again by `index`)
+        #[arg(long)]
+        no_cache: bool,
+    },
+    /// Check a `backup` archive and put its contents back (the store's documents are replaced)
+    RestoreBackup {
+        archive: PathBuf,
+        /// Only check the archive's integrity and version
+        #[arg(long)]
+        check: bool,
+        /// Replace an index or metadata that is already there
+        #[arg(long)]
+        force: bool,
+    },
+    /// Write the documents of --collection (or all collections) with their metadata, extracted
+    /// fields and embeddings to a portable bundle, for another machine or an auditor
+    ExportBundle {
+        bundle: PathBuf,
+        /// Leave out the embeddings (the other side computes them again)
+        #[arg(long)]
+        no_embeddings: bool,
+    },
+    /// Exchange new documents, extractions and annotations with another instance's server
+    /// (e.g. http://office:8000), both ways; --collection keeps it to one collection
+    Sync {
+        remote: String,
+        /// Key of the remote server, when it has tenants
+        #[arg(long, env = "DOC_AI_API_KEY")]
+        api_key: Option<String>,
+        /// Side whose verified extraction, status or rm/archive wins when they differ
+        #[arg(long, value_enum, default_value = "newer")]
+        prefer: Prefer,
+        /// Only list what would be pulled, pushed and resolved
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Merge an `export-bundle` bundle into the collections of the same name
+    ImportBundle {
+        bundle: PathBuf,
+        /// For a document whose name is taken by a different local one
+        #[arg(long, value_enum, default_value = "skip")]
+        on_conflict: OnConflict,
+        /// Only list what would be added, merged, overwritten, renamed or skipped
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check the audit log's hash chain; exits with 1 when an entry was changed, removed or inserted
+    VerifyAudit {
+        /// Print JSON instead of a summary
+        #[arg(long)]
+        json: bool,
+    },
+    /// Upgrade the index, metadata and embeddings written by an older doc-ai
+    /// (done on every start as well; this one backs up and migrates explicitly)
+    Migrate {
+        /// Only list what would be migrated
+        #[arg(long)]
+        check: bool,
+    },
+    /// Remove a document from the index (the file is kept; a tombstone stops re-indexing)
+    Rm {
+        /// File name (e.g. inv_001 or inv_001.txt) or path
+        doc: String,
+    },
+    /// Hide a document from retrieval but keep it on record
+    Archive {
+        /// File name (e.g. inv_001 or inv_001.txt) or path
+        doc: String,
+    },
+    /// Bring a removed or archived document back
+    Restore {
+        /// File name (e.g. inv_001 or inv_001.txt) or path
+        doc: String,
+    },
+    /// Find documents containing words or a phrase, with the matching lines (no model call)
+    Search {
+        /// Words or a phrase, e.g. "retention bond"
+        query: String,
+        /// Documents listed at most
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print a document: its text, state, tags, fields read from it, verified
+    /// extraction and the recent questions that used it
+    Show {
+        /// File name (e.g. inv_001 or inv_001.txt) or path
+        doc: String,
+        #[arg(long)]
+        json: bool,
+        /// Leave out the text
+        #[arg(long)]
+        no_text: bool,
+    },
+    /// Tag and annotate a document: `tag inv_001 +disputed -paid "sent to legal"`.
+    /// Tags filter /documents; notes are shown to the model with the document.
+    Tag {
+        /// File name (e.g. inv_001 or inv_001.txt) or path
+        doc: String,
+        /// +tag adds, -tag takes off, a bare word adds (takes off with --remove),
+        /// text with spaces is a note. Tags are stored in lower case.
+        #[arg(required_unless_present_any = ["note", "clear_notes"], allow_hyphen_values = true)]
+        tags: Vec<String>,
+        #[arg(long)]
+        remove: bool,
+        /// Add a note (may be repeated)
+        #[arg(long)]
+        note: Vec<String>,
+        /// Remove the document's notes first
+        #[arg(long)]
+        clear_notes: bool,
+    },
+    /// Move a document along received → extracted → approved → paid → archived,
+    /// or without a status, print its status and history
+    Status {
+        /// File name (e.g. inv_001 or inv_001.txt) or path
+        doc: String,
+        #[arg(value_enum)]
+        to: Option<InvoiceStatus>,
+        /// Allow a step outside the usual order (recorded as forced)
+        #[arg(long, requires = "to")]
+        force: bool,
+    },
+    /// Decide the received and extracted invoices by the [policy]: auto_approved,
+    /// needs_review or rejected; --apply moves them to approved, extracted or archived
+    Policy {
+        /// Change the statuses (otherwise only list the decisions)
+        #[arg(long)]
+        apply: bool,
+        /// Print JSON instead of one line per invoice
+        #[arg(long)]
+        json: bool,
+    },
+    /// Approve an extracted invoice as one person; over the [approval] threshold it
+    /// becomes approved once enough different people have approved it
+    Approve {
+        /// File name (e.g. inv_001 or inv_001.txt) or path
+        doc: String,
+        /// Approver's name
+        #[arg(long = "as", value_name = "NAME")]
+        by: String,
+    },
+    /// Record the corrected JSON extraction of a document (or forget it with --remove), for export-training
+    Verify {
+        /// File name (e.g. inv_001 or inv_001.txt) or path
+        doc: String,
+        /// JSON file with the corrected extraction; - reads standard input
+        #[arg(required_unless_present = "remove")]
+        file: Option<PathBuf>,
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Write the verified extractions as fine-tuning data (all collections, or --collection)
+    ExportTraining {
+        #[arg(long, value_enum, default_value = "openai")]
+        format: TrainingFormat,
+        /// Base model of the Modelfile; the configured model otherwise
+        #[arg(long)]
+        base: Option<String>,
+        /// Write to this file instead of standard output
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Bake a collection's prompt and options into an Ollama model (the collection of
+    /// --collection, invoices by default): prints the Modelfile, or registers it with --create
+    Modelfile {
+        /// Model to build on; the configured model otherwise
+        #[arg(long)]
+        base: Option<String>,
+        /// Verified extractions (see `verify`) added as example conversations
+        #[arg(long, default_value_t = 3)]
+        examples: usize,
+        /// Register the model with Ollama under this name (e.g. invoice-ai)
+        #[arg(long)]
+        create: Option<String>,
+        /// Write to this file instead of standard output
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// API keys of hosted model backends, kept in the OS keyring
+    Auth {
+        #[command(subcommand)]
+        action: AuthAction,
+    },
+    /// Prompt templates in use, with their versions and hashes
+    Prompts {
+        #[command(subcommand)]
+        action: PromptsAction,
+    },
+    /// Write a systemd unit (launchd agent on macOS) running `serve` with the current
+    /// settings from this directory; give the server flags before the command
+    InstallService {
+        /// Service name
+        #[arg(long, default_value = "doc-ai")]
+        name: String,
+        /// Service manager (defaults to this OS's)
+        #[arg(long, value_enum)]
+        kind: Option<ServiceKind>,
+        /// System-wide service running as the current user (needs root); a user service otherwise
+        #[arg(long)]
+        system: bool,
+        /// Write here instead of the service manager's folder
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Print the file instead of writing it
+        #[arg(long)]
+        print: bool,
+    },
+    /// Pull new documents into a collection
+    Intake {
+        #[command(subcommand)]
+        source: IntakeSource,
+    },
+}
+
+/// Output of the reporting commands
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum AuthAction {
+    /// Store a backend's API key (prompted for, not echoed)
+    Set {
+        /// Backend name, e.g. openai
+        backend: String,
+    },
+    /// Delete a backend's API key from the keyring
+    Remove {
+        backend: String,
+    },
+    /// Show where a backend's key would come from (keyring or environment)
+    Status {
+        backend: String,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum PromptsAction {
+    /// Template, version and hash per collection
+    List,
+    /// The full prompt of a collection, with {contents} and {query} as placeholders
+    Show {
+        collection: String,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum IntakeSource {
+    /// Save invoice attachments from the mailbox configured in [imap]
+    Imap {
+        /// List what would be saved without writing files or flagging messages
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Copy local files (or the files of folders) into the collection given with
+    /// --collection (invoices by default); content already there is a duplicate
+    Files {
+        paths: Vec<PathBuf>,
+    },
+    /// Poll the remote folders in [[connector]] (SFTP, Google Drive, Dropbox) and
+    /// save what is new there, until Ctrl-C
+    Connectors {
+        /// Only the connector with this name
+        #[arg(long)]
+        name: Option<String>,
+        /// Poll each connector once and stop
+        #[arg(long)]
+        once: bool,
+    },
+    /// Take document events from the queue in [queue], save, index and read the
+    /// documents and publish the results, until Ctrl-C
+    Queue {
+        /// Stop once the queue has no more events
+        #[arg(long)]
+        once: bool,
+    },
+    /// Add bank statements (CSV or OFX) to the `payments` collection
+    Statement {
+        files: Vec<PathBuf>,
+    },
 }
\ No newline at end of file
@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Typed client for a running `serve` instance (feature `client`), built on
+// the same request and response types the server uses, so Rust callers do
+// not keep their own copies of the JSON shapes. Envelopes are unwrapped: a
+// failure comes back as an `ErrorResponse` inside the anyhow error, which
+// callers can `downcast_ref` for its code. The server has no job endpoints,
+// so neither does the client.
+
+use anyhow::{Context, Result};
+use reqwest::{Client, Method, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::documents::{DocumentFilter, DocumentPage, Sort};
+use crate::{ApiResponse, ErrorResponse, IntakeResult, QueryRequest, VatReport};
+
+/// Envelope as received; `data` in the payload's own type
+#[derive(Deserialize)]
+struct Reply<T> {
+    success: bool,
+    data: Option<T>,
+    error: Option<ErrorResponse>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DocAiClient {
+    base_url: String,
+    http: Client,
+    api_key: Option<String>,
+}
+
+impl DocAiClient {
+    /// Client for the server at `base_url`, e.g. "http://localhost:8000"
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_http_client(base_url, Client::new())
+    }
+
+    /// With a configured reqwest client (timeouts, proxies, TLS roots...)
+    pub fn with_http_client(base_url: impl Into<String>, http: Client) -> Self {
+        Self { base_url: base_url.into().trim_end_matches('/').to_string(), http, api_key: None }
+    }
+
+    /// Key sent with every request, for a server with tenants
+    pub fn api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.http.request(method, format!("{}{}", self.base_url, path));
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    async fn unwrap<T: DeserializeOwned>(res: reqwest::Response) -> Result<T> {
+        let status = res.status();
+        let reply: Reply<T> = res.json().await.with_context(|| format!("Unexpected response from the server ({})", status))?;
+        match reply {
+            Reply { success: true, data: Some(data), .. } => Ok(data),
+            Reply { error: Some(err), .. } => Err(err.into()),
+            _ => anyhow::bail!("The server returned neither data nor an error ({})", status),
+        }
+    }
+
+    /// POST /query
+    pub async fn query(&self, request: &QueryRequest) -> Result<ApiResponse> {
+        let res = self.request(Method::POST, "/query").json(request).send().await.context("Cannot reach the doc-ai server")?;
+        Self::unwrap(res).await
+    }
+
+    /// POST /query with every other field left to the server's defaults
+    pub async fn ask(&self, question: &str, collection: Option<&str>) -> Result<ApiResponse> {
+        let request =
+            QueryRequest { query: question.to_string(), collection: collection.map(str::to_string), ..Default::default() };
+        self.query(&request).await
+    }
+
+    /// GET /documents; a filter without a status lists documents in every status
+    pub async fn documents(&self, filter: &DocumentFilter, sort: Sort, offset: usize, limit: usize) -> Result<DocumentPage> {
+        let mut params = vec![
+            ("status", filter.status.map_or("all", |s| s.as_str()).to_string()),
+            ("sort", sort.to_string()),
+            ("offset", offset.to_string()),
+            ("limit", limit.to_string()),
+        ];
+        for (name, value) in [
+            ("collection", &filter.collection),
+            ("vendor", &filter.vendor),
+            ("from", &filter.from),
+            ("to", &filter.to),
+            ("tag", &filter.tag),
+            ("q", &filter.text),
+        ] {
+            if let Some(value) = value {
+                params.push((name, value.clone()));
+            }
+        }
+        let res = self.request(Method::GET, "/documents").query(&params).send().await.context("Cannot reach the doc-ai server")?;
+        Self::unwrap(res).await
+    }
+
+    /// POST /documents: saved, duplicate (the same bytes are already there) or, for a `key` sent
+    /// before, updated
+    pub async fn upload(&self, name: &str, bytes: Vec<u8>, collection: Option<&str>, key: Option<&str>) -> Result<IntakeResult> {
+        let mut params = vec![("name", name)];
+        params.extend(collection.map(|c| ("collection", c)));
+        params.extend(key.map(|k| ("key", k)));
+        let request = self.request(Method::POST, "/documents").query(&params);
+        let request = request.header(reqwest::header::CONTENT_TYPE, "application/octet-stream").body(bytes);
+        let res = request.send().await.context("Cannot reach the doc-ai server")?;
+        Self::unwrap(res).await
+    }
+
+    /// GET /vat-check
+    pub async fn vat_check(&self) -> Result<Vec<VatReport>> {
+        let res = self.request(Method::GET, "/vat-check").send().await.context("Cannot reach the doc-ai server")?;
+        Self::unwrap(res).await
+    }
+}
@@ -0,0 +1,258 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Period close checklist: a configurable battery of checks over the invoices
+// of one accounting period (all extracted, sums consistent, no duplicates,
+// payments reconciled, extracted values found in the text, two-person rule
+// kept), reported as
+// pass/fail with the offending documents so the report can be archived.
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::aggregate::in_period;
+use crate::approval::{violations, ApprovalConfig};
+use crate::metadata;
+use crate::payments::{load_payments, payments_collection};
+use crate::reconcile::{reconcile, DEFAULT_WINDOW_DAYS};
+use crate::records::InvoiceRecord;
+use crate::versions::DocumentKind;
+use crate::{check_invoice, get_cached_content};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseCheck {
+    /// Every invoice has a number, vendor, date and total
+    Extracted,
+    /// Line items, net, VAT and total add up
+    Sums,
+    /// No invoice number (or vendor, date and total) appears twice
+    Duplicates,
+    /// Every payment of the period is matched to an invoice
+    Payments,
+    /// Every extracted value appears in the document text
+    Grounded,
+    /// Every approved invoice over the [approval] threshold has enough approvers
+    Approvals,
+}
+
+pub const ALL_CHECKS: [CloseCheck; 6] = [
+    CloseCheck::Extracted,
+    CloseCheck::Sums,
+    CloseCheck::Duplicates,
+    CloseCheck::Payments,
+    CloseCheck::Grounded,
+    CloseCheck::Approvals,
+];
+
+/// `[close]` section of the config file
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CloseConfig {
+    /// Checks to run, in order
+    pub checks: Vec<CloseCheck>,
+    /// Date window for matching payments on amount (see `reconcile`)
+    pub window_days: i64,
+}
+
+impl Default for CloseConfig {
+    fn default() -> Self {
+        Self { checks: ALL_CHECKS.to_vec(), window_days: DEFAULT_WINDOW_DAYS }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    /// The check could not run (e.g. no payments collection)
+    Skipped,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CheckResult {
+    pub check: CloseCheck,
+    pub status: CheckStatus,
+    /// One line per problem found
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CloseReport {
+    pub period: String,
+    /// Unix seconds
+    pub generated_at: u64,
+    pub invoices: usize,
+    pub passed: bool,
+    pub checks: Vec<CheckResult>,
+}
+
+fn result(check: CloseCheck, details: Vec<String>) -> CheckResult {
+    let status = if details.is_empty() { CheckStatus::Pass } else { CheckStatus::Fail };
+    CheckResult { check, status, details }
+}
+
+fn label(record: &InvoiceRecord) -> String {
+    format!("{}/{}", record.collection, record.file)
+}
+
+fn extracted(records: &[InvoiceRecord]) -> Vec<String> {
+    records
+        .iter()
+        .filter_map(|r| {
+            let missing: Vec<&str> = [
+                ("number", r.id.is_none()),
+                ("vendor", r.vendor.is_none()),
+                ("date", r.date.is_none()),
+                ("total", r.gross.is_none()),
+            ]
+            .into_iter()
+            .filter_map(|(name, absent)| absent.then_some(name))
+            .collect();
+            (!missing.is_empty()).then(|| format!("{}: no {}", label(r), missing.join(", ")))
+        })
+        .collect()
+}
+
+fn sums(records: &[InvoiceRecord]) -> Vec<String> {
+    let mut details = Vec::new();
+    for r in records {
+        let Ok(text) = get_cached_content(&r.path) else {
+            details.push(format!("{}: unreadable", label(r)));
+            continue;
+        };
+        let report = check_invoice(&r.file, &text);
+        details.extend(report.issues.iter().map(|i| format!("{}: {}", label(r), i.message)));
+    }
+    details
+}
+
+fn duplicates(records: &[InvoiceRecord]) -> Vec<String> {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for r in records.iter().filter(|r| r.kind != DocumentKind::CreditNote) {
+        let key = match (&r.id, &r.vendor, &r.date, r.gross) {
+            (Some(id), vendor, ..) => format!("number {} ({})", id, vendor.as_deref().unwrap_or("?")),
+            (None, Some(vendor), Some(date), Some(gross)) => format!("{} {} {}", vendor, date, gross),
+            _ => continue,
+        };
+        groups.entry(key).or_default().push(label(r));
+    }
+    groups
+        .into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .map(|(key, files)| format!("{}: {}", key, files.join(", ")))
+        .collect()
+}
+
+/// `records` are all invoices, so payments of earlier invoices are matched too
+fn payments(records: &[InvoiceRecord], period: &str, window_days: i64) -> CheckResult {
+    if payments_collection().is_err() {
+        return CheckResult {
+            check: CloseCheck::Payments,
+            status: CheckStatus::Skipped,
+            details: vec!["no payments collection configured".to_string()],
+        };
+    }
+    let all = match load_payments() {
+        Ok(p) => p,
+        Err(e) => return result(CloseCheck::Payments, vec![e.to_string()]),
+    };
+    let reconciliation = reconcile(records.to_vec(), all, window_days);
+    let details = reconciliation
+        .unmatched
+        .iter()
+        .filter(|p| p.date.as_deref().is_some_and(|d| in_period(d, period)))
+        .map(|p| format!("{}: {} {} not matched to an invoice", p.source, p.amount, p.text().trim()))
+        .collect();
+    result(CloseCheck::Payments, details)
+}
+
+/// True if `amount` is written in `text` (with or without thousands separators)
+fn amount_in_text(amount: Decimal, digits_only: &str) -> bool {
+    let plain: String = format!("{:.2}", amount.abs()).chars().filter(|c| c.is_ascii_digit()).collect();
+    digits_only.contains(&plain)
+}
+
+fn grounded(records: &[InvoiceRecord]) -> Vec<String> {
+    let mut details = Vec::new();
+    for r in records {
+        let Ok(text) = get_cached_content(&r.path) else { continue };
+        let digits: String = text.chars().filter(|c| c.is_ascii_digit()).collect();
+        let mut missing = Vec::new();
+        if r.id.as_deref().is_some_and(|id| !text.contains(id)) {
+            missing.push("number");
+        }
+        if r.vendor.as_deref().is_some_and(|v| !text.contains(v)) {
+            missing.push("vendor");
+        }
+        for (name, value) in [("net", r.net), ("tax", r.tax), ("total", r.gross)] {
+            if value.is_some_and(|v| !amount_in_text(v, &digits)) {
+                missing.push(name);
+            }
+        }
+        if !missing.is_empty() {
+            details.push(format!("{}: {} not found in the text", label(r), missing.join(", ")));
+        }
+    }
+    details
+}
+
+fn approvals(records: &[InvoiceRecord], approval: &ApprovalConfig) -> CheckResult {
+    if approval.threshold.is_none() {
+        return CheckResult {
+            check: CloseCheck::Approvals,
+            status: CheckStatus::Skipped,
+            details: vec!["no [approval] threshold configured".to_string()],
+        };
+    }
+    let details = violations(records, &metadata::current(), approval).iter().map(|v| v.to_string()).collect();
+    result(CloseCheck::Approvals, details)
+}
+
+/// Run the configured checks over the invoices dated in `period`.
+/// Undated invoices are included, since they may belong to it.
+pub fn close_period(all: Vec<InvoiceRecord>, period: &str, config: &CloseConfig, approval: &ApprovalConfig) -> CloseReport {
+    let records: Vec<InvoiceRecord> =
+        all.iter().filter(|r| r.date.as_deref().is_none_or(|d| in_period(d, period))).cloned().collect();
+    let checks: Vec<CheckResult> = config
+        .checks
+        .iter()
+        .map(|check| match check {
+            CloseCheck::Extracted => result(*check, extracted(&records)),
+            CloseCheck::Sums => result(*check, sums(&records)),
+            CloseCheck::Duplicates => result(*check, duplicates(&records)),
+            CloseCheck::Payments => payments(&all, period, config.window_days),
+            CloseCheck::Grounded => result(*check, grounded(&records)),
+            CloseCheck::Approvals => approvals(&records, approval),
+        })
+        .collect();
+
+    CloseReport {
+        period: period.to_string(),
+        generated_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+        invoices: records.len(),
+        passed: checks.iter().all(|c| c.status != CheckStatus::Fail),
+        checks,
+    }
+}
+
+impl CloseReport {
+    /// Plain-text checklist
+    pub fn to_table(&self) -> String {
+        let mut lines = vec![format!("Period {} close: {} invoice(s)", self.period, self.invoices)];
+        for check in &self.checks {
+            let mark = match check.status {
+                CheckStatus::Pass => "PASS",
+                CheckStatus::Fail => "FAIL",
+                CheckStatus::Skipped => "SKIP",
+            };
+            lines.push(format!("[{}] {:?}", mark, check.check));
+            lines.extend(check.details.iter().map(|d| format!("       {}", d)));
+        }
+        lines.push(if self.passed { "Result: PASS".to_string() } else { "Result: FAIL".to_string() });
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,280 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Named document collections.
+// The built-in categories are the default collections; the config file can
+// override them or add new ones (e.g. purchase-orders), each of a domain
+// (see domain.rs) that gives it its prompt, schema and checks.
+
+use anyhow::Context;
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use crate::ai::Strictness;
+use crate::config::{CollectionConfig, Config};
+use crate::domain::{category_domain, domains_from_config, Domain, Validator};
+use crate::storage::{cache_folder, DocumentSource};
+use crate::tenants::tenant_collections;
+use crate::{Category, ALL_CATEGORIES};
+
+#[derive(Debug, Clone)]
+pub struct Collection {
+    pub name: String,
+    pub display_name: String,
+    pub folder: PathBuf,
+    pub aliases: Vec<String>,
+    pub instruction: String,
+    /// Document domain the prompt settings came from
+    pub domain: Option<String>,
+    /// Answer schema of the domain
+    pub schema: Option<Value>,
+    /// Answer checks of the domain (VAT checks are `vat_check`)
+    pub validators: Vec<Validator>,
+    /// Custom prompt template (already loaded from disk)
+    pub template: Option<String>,
+    /// File the template was loaded from
+    pub template_file: Option<PathBuf>,
+    /// Semantic version given to the template in the config file
+    pub template_version: Option<String>,
+    pub vat_check: bool,
+    /// Grounding rules in the prompt (lax, normal or strict)
+    pub strictness: Strictness,
+    /// Where `folder` is filled from (local collections need no syncing)
+    pub source: DocumentSource,
+    /// Tenant owning this copy of the collection; its name is then "<tenant>/<name>"
+    pub tenant: Option<String>,
+}
+
+/// Collections in use, set at startup and replaced by a config reload.
+/// Replaced lists are leaked so references handed out earlier stay valid.
+static COLLECTIONS: RwLock<Option<&'static [Collection]>> = RwLock::new(None);
+
+impl Collection {
+    pub fn from_category(cat: &Category) -> Self {
+        Self {
+            name: cat.api_value().to_string(),
+            display_name: cat.display_name().to_string(),
+            folder: PathBuf::from(cat.folder_path()),
+            aliases: cat.aliases().iter().map(|a| a.to_string()).collect(),
+            instruction: cat.ai_instruction().to_string(),
+            domain: Some(category_domain(cat).to_string()),
+            schema: None,
+            validators: Vec::new(),
+            template: None,
+            template_file: None,
+            template_version: None,
+            vat_check: *cat == Category::Invoices,
+            strictness: Strictness::default(),
+            source: DocumentSource::Local,
+            tenant: None,
+        }
+    }
+
+    /// True if `s` is this collection's name or one of its aliases
+    pub fn matches(&self, s: &str) -> bool {
+        let lower = s.to_lowercase();
+        self.name == lower || self.aliases.contains(&lower)
+    }
+
+    /// The name without the tenant prefix
+    pub fn base_name(&self) -> &str {
+        match &self.tenant {
+            Some(tenant) => self.name.strip_prefix(tenant.as_str()).and_then(|n| n.strip_prefix('/')).unwrap_or(&self.name),
+            None => &self.name,
+        }
+    }
+
+    /// Take the prompt settings of a domain
+    pub fn use_domain(&mut self, domain: &Domain) {
+        self.domain = Some(domain.name.clone());
+        self.instruction = domain.instruction.clone();
+        self.schema = domain.schema.clone();
+        self.validators = domain.validators.iter().copied().filter(|v| *v != Validator::Vat).collect();
+        self.vat_check = domain.validators.contains(&Validator::Vat);
+    }
+
+    /// Apply the settings from a `[[collection]]` config entry
+    fn apply(&mut self, cfg: &CollectionConfig, domains: &[Domain]) -> anyhow::Result<()> {
+        // First, so the entry's own settings win over the domain's
+        if let Some(name) = cfg.domain.as_deref().map(str::to_lowercase) {
+            let Some(domain) = domains.iter().find(|d| d.name == name) else {
+                let known: Vec<&str> = domains.iter().map(|d| d.name.as_str()).collect();
+                anyhow::bail!("Unknown domain '{}' for collection '{}'. Valid values: {}", name, cfg.name, known.join(", "));
+            };
+            self.use_domain(domain);
+        }
+        if let Some(source) = &cfg.source {
+            self.source = DocumentSource::parse(source, &cfg.files, cfg.s3_endpoint.as_deref(), cfg.s3_region.as_deref());
+            match &self.source {
+                DocumentSource::Local => self.folder = PathBuf::from(source),
+                _ => self.folder = cache_folder(&self.name),
+            }
+        }
+        if let Some(folder) = &cfg.folder {
+            self.folder = folder.clone();
+        }
+        if let Some(display_name) = &cfg.display_name {
+            self.display_name = display_name.clone();
+        }
+        for alias in &cfg.aliases {
+            let alias = alias.to_lowercase();
+            if !self.aliases.contains(&alias) {
+                self.aliases.push(alias);
+            }
+        }
+        if let Some(instruction) = &cfg.instruction {
+            self.instruction = instruction.clone();
+        }
+        if let Some(path) = &cfg.template {
+            let text = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read prompt template: {}", path.display()))?;
+            self.template = Some(text);
+            self.template_file = Some(path.clone());
+        }
+        if let Some(version) = &cfg.template_version {
+            self.template_version = Some(version.clone());
+        }
+        if let Some(vat_check) = cfg.vat_check {
+            self.vat_check = vat_check;
+        }
+        if let Some(strictness) = cfg.strictness {
+            self.strictness = strictness;
+        }
+        Ok(())
+    }
+
+    /// Synthetic collection used when one query spans several collections
+    pub fn combined(parts: &[&Collection]) -> Self {
+        let mut instruction = String::from(
+            "You are answering a question that spans several document collections. \
+             Documents are grouped under '=== Collection: <name> ===' headers; keep track of which group each \
+             document belongs to, and cite sources as '<collection>/<file name>'.\nCollection roles:",
+        );
+        for c in parts {
+            instruction.push_str(&format!("\n- {} ({}): {}", c.display_name, c.name, c.instruction));
+        }
+
+        Self {
+            name: parts.iter().map(|c| c.name.as_str()).collect::<Vec<_>>().join("+"),
+            display_name: parts.iter().map(|c| c.display_name.as_str()).collect::<Vec<_>>().join(" + "),
+            folder: PathBuf::new(),
+            aliases: Vec::new(),
+            instruction,
+            // Several domains have no single schema; each part's checks still apply to its documents
+            domain: None,
+            schema: None,
+            validators: Vec::new(),
+            template: None,
+            template_file: None,
+            template_version: None,
+            vat_check: parts.iter().any(|c| c.vat_check),
+            // The strictest of the parts wins
+            strictness: parts.iter().map(|c| c.strictness).max().unwrap_or_default(),
+            source: DocumentSource::Local,
+            // A tenant's query only ever names that tenant's collections
+            tenant: parts.first().and_then(|c| c.tenant.clone()),
+        }
+    }
+
+    fn from_config(cfg: &CollectionConfig, domains: &[Domain]) -> anyhow::Result<Self> {
+        let name = cfg.name.to_lowercase();
+        let mut collection = Self {
+            display_name: cfg.name.clone(),
+            folder: PathBuf::from("data").join(&name),
+            aliases: Vec::new(),
+            instruction: format!(
+                "You are a precise document assistant for the '{}' collection. Extract and answer exactly as written.",
+                cfg.name
+            ),
+            domain: None,
+            schema: None,
+            validators: Vec::new(),
+            template: None,
+            template_file: None,
+            template_version: None,
+            vat_check: false,
+            strictness: Strictness::default(),
+            source: DocumentSource::Local,
+            tenant: None,
+            name,
+        };
+        collection.apply(cfg, domains)?;
+        Ok(collection)
+    }
+}
+
+/// Built-in collections merged with the ones defined in the config file,
+/// with folders under `data/` moved to `data_dir` when it is set, followed
+/// by each tenant's copies
+pub fn collections_from_config(config: &Config) -> anyhow::Result<Vec<Collection>> {
+    let domains = domains_from_config(config)?;
+    let mut collections: Vec<Collection> = ALL_CATEGORIES.iter().map(Collection::from_category).collect();
+    // [[domain]] entries changing a built-in domain reach the built-in collections too
+    for collection in &mut collections {
+        if let Some(domain) = domains.iter().find(|d| collection.domain.as_ref() == Some(&d.name)) {
+            collection.use_domain(domain);
+        }
+    }
+
+    for cfg in &config.collections {
+        if cfg.name.trim().is_empty() {
+            anyhow::bail!("Every [[collection]] in the config file needs a name");
+        }
+        match collections.iter_mut().find(|c| c.matches(&cfg.name)) {
+            Some(existing) => existing.apply(cfg, &domains)?,
+            None => collections.push(Collection::from_config(cfg, &domains)?),
+        }
+    }
+
+    if let Some(data_dir) = &config.data_dir {
+        for collection in &mut collections {
+            if let Ok(rest) = collection.folder.strip_prefix("data") {
+                collection.folder = data_dir.join(rest);
+            }
+        }
+    }
+
+    let per_tenant = tenant_collections(&collections, config)?;
+    collections.extend(per_tenant);
+
+    Ok(collections)
+}
+
+/// Install the collections for this process (first call wins)
+pub fn init_collections(collections: Vec<Collection>) {
+    let mut current = COLLECTIONS.write().unwrap();
+    if current.is_none() {
+        *current = Some(Vec::leak(collections));
+    }
+}
+
+/// Switch to a new set of collections (config reload); queries already running keep the old ones
+pub fn replace_collections(collections: Vec<Collection>) {
+    *COLLECTIONS.write().unwrap() = Some(Vec::leak(collections));
+}
+
+/// All collections; the built-in categories if `init_collections` was never called
+pub fn collections() -> &'static [Collection] {
+    if let Some(current) = *COLLECTIONS.read().unwrap() {
+        return current;
+    }
+    let mut current = COLLECTIONS.write().unwrap();
+    current.get_or_insert_with(|| Vec::leak(ALL_CATEGORIES.iter().map(Collection::from_category).collect()))
+}
+
+/// Look up a collection by name or alias
+pub fn find_collection(name: &str) -> Option<&'static Collection> {
+    collections().iter().find(|c| c.matches(name))
+}
+
+/// Returns a comma-separated (with 'or' before last) string of all collection names
+/// (tenants' copies left out: their names are the same without the prefix)
+pub fn all_collection_names_human() -> String {
+    let names: Vec<&str> = collections().iter().filter(|c| c.tenant.is_none()).map(|c| c.name.as_str()).collect();
+    match names.as_slice() {
+        [] => "none".to_string(),
+        [only] => only.to_string(),
+        [rest @ .., last] => format!("{}, or {}", rest.join(", "), last),
+    }
+}
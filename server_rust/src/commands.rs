@@ -0,0 +1,1268 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Subcommands other than `serve`
+
+use anyhow::{Context, Result};
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+
+use doc_ai_server::metadata::resolve_document;
+use doc_ai_server::*;
+
+/// Run one non-server command
+pub async fn run(command: &Command, args: &Args, file_config: &Config) -> Result<()> {
+    match command {
+        Command::Serve => Ok(()),
+        Command::Init { yes } => init(*yes, args).await,
+        Command::Ask { question, reindex_stale } => {
+            if *reindex_stale || file_config.retrieval.reindex_stale {
+                reindex_stale_documents(args, file_config).await?;
+            }
+            ask(question, args, file_config).await
+        }
+        Command::Chat => chat(args, file_config).await,
+        Command::Index {
+            jobs,
+            queue,
+            checkpoint_every,
+            fresh,
+            timeout_secs,
+            max_failures,
+            retry_quarantined,
+            embed_backend,
+        } => {
+            // Single writer: held until the index and the embeddings are saved
+            let _lock = lock_index(LockMode::Exclusive)?;
+            sync_collections(args.collection.as_deref()).await?;
+
+            let options = IngestOptions {
+                jobs: *jobs,
+                queue: *queue,
+                checkpoint_every: *checkpoint_every,
+                fresh: *fresh,
+                strict: args.strict,
+                timeout: std::time::Duration::from_secs(*timeout_secs),
+                max_failures: *max_failures,
+                retry_quarantined: *retry_quarantined,
+            };
+            let report = ingest::ingest(&options, |r| println!("… {} indexed, {} unchanged", r.indexed, r.unchanged)).await?;
+            println!(
+                "Index saved to {}: {} indexed, {} unchanged, {} removed",
+                store().describe(),
+                report.indexed,
+                report.unchanged,
+                report.removed
+            );
+            audit::record_or_warn(
+                &audit::local_actor(),
+                "index",
+                args.collection.as_deref(),
+                serde_json::json!({ "indexed": report.indexed, "unchanged": report.unchanged, "removed": report.removed }),
+            );
+            if !report.failed.is_empty() {
+                eprintln!("{} file(s) skipped (use --strict to fail instead):", report.failed.len());
+            }
+            for (file, reason) in &report.failed {
+                eprintln!("WARNING: skipped {}: {}", file, reason);
+            }
+            if !report.quarantined.is_empty() {
+                eprintln!(
+                    "{} file(s) quarantined after failing {} runs in a row (fix them, or use --retry-quarantined):",
+                    report.quarantined.len(),
+                    max_failures
+                );
+            }
+            for (file, reason) in &report.quarantined {
+                eprintln!("  {}: {}", file, reason);
+            }
+
+            once_cell::sync::Lazy::force(&indexer::INVERTED_INDEX);
+            println!("{} exact identifiers (document numbers, IBANs) indexed", indexer::IDENTIFIER_INDEX.values().map(|ids| ids.len()).sum::<usize>());
+
+            let retrieval = &file_config.retrieval;
+            if retrieval.mode.uses_embeddings() {
+                let mut embed_options = retrieval.embed_options();
+                if let Some(backend) = embed_backend {
+                    embed_options.backend = *backend;
+                }
+                println!("{}", update_embeddings(retrieval, embed_options).await?);
+            }
+
+            if !file_config.publishers.is_empty() {
+                let events: Vec<BusEvent> =
+                    report.changed.iter().filter_map(|p| bus::document_event(EventKind::Extracted, p, None)).collect();
+                bus::publish_or_warn(&file_config.publishers, &events).await;
+            }
+
+            if file_config.policy.on_index {
+                let mut received = invoice_records(args.collection.as_deref(), false);
+                received.retain(|r| r.status == InvoiceStatus::Received);
+                let results = apply_policy(&received, true, file_config)?;
+                let count = |d: Decision| results.iter().filter(|r| r.outcome.decision == d).count();
+                println!(
+                    "Policy: {} auto-approved, {} for review, {} rejected",
+                    count(Decision::AutoApproved),
+                    count(Decision::NeedsReview),
+                    count(Decision::Rejected)
+                );
+            }
+            Ok(())
+        }
+        Command::BenchRetrieval { file, mode, json } => {
+            let cases = bench::load_cases(file)?;
+            let mut retrieval = file_config.retrieval.clone();
+            retrieval.legacy_matching |= args.legacy_matching;
+            if let Some(mode) = args.retriever {
+                retrieval.mode = mode;
+            }
+            let modes = if mode.is_empty() { vec![retrieval.mode] } else { mode.clone() };
+            let collection = args.collection.as_deref().unwrap_or("invoices");
+
+            let mut reports = Vec::new();
+            for mode in modes {
+                let config = RetrievalConfig { mode, ..retrieval.clone() };
+                reports.push(bench_retrieval(&cases, collection, args.top_k, &config, &ConsoleEvents).await?);
+            }
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&reports)?);
+            } else {
+                for report in &reports {
+                    println!("{}", report.to_table());
+                }
+            }
+            Ok(())
+        }
+        Command::Agg { group_by, sum, avg, min, max, vendor, period, json } => {
+            let measures = [(Metric::Sum, sum), (Metric::Avg, avg), (Metric::Min, min), (Metric::Max, max)]
+                .into_iter()
+                .flat_map(|(metric, fields)| fields.iter().map(move |f| (metric, *f)))
+                .collect();
+            let aggregation =
+                Aggregation { group_by: group_by.clone(), measures, vendor: vendor.clone(), period: period.clone() };
+            let records = report_records(args, file_config);
+            let result = aggregate(&records, &aggregation);
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&result)?);
+            } else {
+                println!("{}", result.to_table());
+            }
+            Ok(())
+        }
+        Command::Spend { field, period, format } => {
+            let records = report_records(args, file_config);
+            let series = spend::spend_series(&records, *field, period.as_deref());
+            match format {
+                OutputFormat::Table => println!("{}", series.to_table()),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&series)?),
+                OutputFormat::Csv => print!("{}", series.to_csv()),
+            }
+            Ok(())
+        }
+        Command::Export { format, accounts, period, output } => {
+            let accounts = match accounts {
+                Some(path) => AccountMap::load(path)?,
+                None => AccountMap::default(),
+            };
+            let records: Vec<InvoiceRecord> = report_records(args, file_config)
+                .into_iter()
+                .filter(|r| period.as_deref().is_none_or(|p| r.date.as_deref().is_some_and(|d| aggregate::in_period(d, p))))
+                .collect();
+            let (text, skipped) = export::export(&records, *format, &accounts);
+            match output {
+                Some(path) => {
+                    std::fs::write(path, text)?;
+                    eprintln!("{} bill(s) written to {}", records.len() - skipped.len(), path.display());
+                }
+                None => print!("{}", text),
+            }
+            if !skipped.is_empty() {
+                eprintln!("WARNING: no date or total, not exported: {}", skipped.join(", "));
+            }
+            Ok(())
+        }
+        Command::ExportExpenses { collection, period, output } => {
+            let receipts = receipts(collection.as_deref(), period.as_deref(), &file_config.expenses);
+            let text = expenses_csv(&receipts);
+            match output {
+                Some(path) => {
+                    std::fs::write(path, text)?;
+                    eprintln!("{} receipt(s) written to {}", receipts.len(), path.display());
+                }
+                None => print!("{}", text),
+            }
+            let incomplete: Vec<&str> =
+                receipts.iter().filter(|r| r.date.is_none() || r.total.is_none()).map(|r| r.file.as_str()).collect();
+            if !incomplete.is_empty() {
+                eprintln!("WARNING: no date or total found, check before importing: {}", incomplete.join(", "));
+            }
+            Ok(())
+        }
+        Command::Reconcile { window_days, period, format } => {
+            let invoices: Vec<InvoiceRecord> = report_records(args, file_config)
+                .into_iter()
+                .filter(|r| period.as_deref().is_none_or(|p| r.date.as_deref().is_some_and(|d| aggregate::in_period(d, p))))
+                .collect();
+            let result = reconcile::reconcile(invoices, payments::load_payments()?, *window_days);
+            match format {
+                OutputFormat::Table => println!("{}", result.to_table()),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&result)?),
+                OutputFormat::Csv => print!("{}", result.to_csv()),
+            }
+            Ok(())
+        }
+        Command::CheckRules { json } => {
+            let rules = load_rules(&file_config.rules)?;
+            if rules.is_empty() {
+                anyhow::bail!("No [[rule]] in the config file");
+            }
+            let report = check_rules(&report_records(args, file_config), &rules, &metadata::current());
+            println!("{}", if *json { serde_json::to_string_pretty(&report)? } else { report.to_table() });
+            if !report.passed() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Close { period, json, output } => {
+            let records = report_records(args, file_config);
+            let report = close::close_period(records, period, &file_config.close, &file_config.approval);
+            let text = if *json { serde_json::to_string_pretty(&report)? } else { report.to_table() };
+            println!("{}", text);
+            if let Some(path) = output {
+                std::fs::write(path, format!("{}\n", text))?;
+                eprintln!("Report written to {}", path.display());
+            }
+            if !report.passed {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Backup { archive, no_cache } => {
+            // No `index` run halfway through its saves
+            let _lock = lock_index(LockMode::Shared)?;
+            let config_file = args.config.clone().unwrap_or_else(|| config::DEFAULT_CONFIG_FILE.into());
+            let summary = backup::backup(archive, Some(&config_file), !no_cache)?;
+            println!(
+                "{}: {} store document(s), {} other state file(s), {} mirrored file(s){}, {} bytes before compression",
+                archive.display(),
+                summary.documents,
+                summary.state_files,
+                summary.cached_files,
+                if summary.config { ", the config file" } else { "" },
+                summary.bytes
+            );
+            audited("backup", Some(archive), serde_json::json!({ "documents": summary.documents, "bytes": summary.bytes }));
+            Ok(())
+        }
+        Command::RestoreBackup { archive, check, force } => {
+            let checked = backup::read_backup(archive)?;
+            let manifest = &checked.manifest;
+            println!(
+                "{}: backup of {} by doc-ai {} at {}, {} file(s), all checksums match",
+                archive.display(),
+                manifest.store,
+                manifest.version,
+                manifest.created_at,
+                manifest.files.len()
+            );
+            if manifest.version != env!("CARGO_PKG_VERSION") {
+                eprintln!(
+                    "WARNING: written by doc-ai {}, this is {}; run `index` afterwards if answers look off",
+                    manifest.version,
+                    env!("CARGO_PKG_VERSION")
+                );
+            }
+            if *check {
+                return Ok(());
+            }
+            let _lock = lock_index(LockMode::Exclusive)?;
+            let existing: Vec<&str> =
+                checked.documents().into_iter().filter(|d| store().load(d).is_ok_and(|t| t.is_some())).collect();
+            if !existing.is_empty() && !force {
+                anyhow::bail!(
+                    "{} already holds {}; restoring replaces them (use --force)",
+                    store().describe(),
+                    existing.join(", ")
+                );
+            }
+            let config_file = args.config.clone().unwrap_or_else(|| config::DEFAULT_CONFIG_FILE.into());
+            let summary = checked.restore(&config_file)?;
+            println!(
+                "Restored {} into {}, {} other state file(s), {} mirrored file(s)",
+                summary.documents.join(", "),
+                store().describe(),
+                summary.state_files,
+                summary.cached_files
+            );
+            if let Some(path) = &summary.config {
+                println!("Config file written to {}", path.display());
+            }
+            let details = serde_json::json!({ "documents": summary.documents, "created_at": manifest.created_at });
+            audited("restore_backup", Some(archive), details);
+            Ok(())
+        }
+        Command::ExportBundle { bundle, no_embeddings } => {
+            let _lock = lock_index(LockMode::Shared)?;
+            let manifest = bundle::export_bundle(bundle, args.collection.as_deref(), !no_embeddings)?;
+            println!(
+                "{}: {} document(s) of {}{}",
+                bundle.display(),
+                manifest.documents,
+                manifest.collections.join(", "),
+                manifest.embedding_model.as_ref().map_or(String::new(), |m| format!(", with {} embeddings", m))
+            );
+            audited("export_bundle", Some(bundle), serde_json::json!({ "collections": manifest.collections, "documents": manifest.documents }));
+            Ok(())
+        }
+        Command::ImportBundle { bundle, on_conflict, dry_run } => {
+            let _lock = lock_index(LockMode::Exclusive)?;
+            let summary = bundle::import_bundle(bundle, *on_conflict, *dry_run)?;
+            let verb = if *dry_run { "would be " } else { "" };
+            for (label, names) in [("added", &summary.added), ("merged", &summary.merged), ("overwritten", &summary.overwritten)] {
+                if !names.is_empty() {
+                    println!("{} document(s) {}{}: {}", names.len(), verb, label, names.join(", "));
+                }
+            }
+            for (from, to) in &summary.renamed {
+                println!("{} {}imported as {}", from, verb, to);
+            }
+            if !summary.skipped.is_empty() {
+                println!("{} conflicting document(s) skipped (see --on-conflict): {}", summary.skipped.len(), summary.skipped.join(", "));
+            }
+            if let Some(why) = &summary.embeddings_ignored {
+                eprintln!("WARNING: the bundle's embeddings are not used: {}", why);
+            }
+            if !dry_run {
+                println!("{} embedding(s) taken over; run `index` to make the new documents searchable", summary.embeddings);
+                audited("import_bundle", Some(bundle), serde_json::to_value(&summary)?);
+            }
+            Ok(())
+        }
+        Command::Sync { remote, api_key, prefer, dry_run } => {
+            let _lock = lock_index(LockMode::Exclusive)?;
+            let report = sync::sync(
+                &sync::Remote::new(remote, api_key.clone()),
+                args.collection.as_deref(),
+                *prefer,
+                *dry_run,
+                &file_config.approval,
+            )
+            .await?;
+            let verb = if *dry_run { "would be " } else { "" };
+            for (label, names) in [("pulled", &report.pulled), ("pushed", &report.pushed)] {
+                if !names.is_empty() {
+                    println!("{} document(s) {}{}: {}", names.len(), verb, label, names.join(", "));
+                }
+            }
+            println!("{} document(s) with annotations {}brought level", report.annotations, verb);
+            for conflict in &report.conflicts {
+                println!("Conflict: {}", conflict);
+            }
+            if !report.diverged.is_empty() {
+                eprintln!("WARNING: different text on each side, left alone: {}", report.diverged.join(", "));
+            }
+            for (name, reason) in &report.refused {
+                eprintln!("WARNING: {} not taken: {}", name, reason);
+            }
+            if !dry_run {
+                if !report.pulled.is_empty() {
+                    println!("Run `index` to make the pulled documents searchable");
+                }
+                audited("sync", None, serde_json::json!({ "remote": remote, "report": report }));
+            }
+            Ok(())
+        }
+        Command::VerifyAudit { json } => {
+            let check = audit::verify()?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&check)?);
+            } else {
+                for problem in &check.problems {
+                    println!("BROKEN {}", problem);
+                }
+                match (&check.last_hash, check.problems.is_empty()) {
+                    (None, _) => println!("The audit log in {} is empty", store().describe()),
+                    (Some(hash), true) => println!("{} audit entries, chain intact; last hash {}", check.entries, hash),
+                    (Some(_), false) => println!("{} audit entries, chain BROKEN", check.entries),
+                }
+            }
+            if !check.problems.is_empty() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Migrate { check } => {
+            if *check {
+                let pending = migrate::pending()?;
+                if pending.is_empty() {
+                    println!("{} is up to date", store().describe());
+                }
+                for p in &pending {
+                    println!("{}: version {} → {}", p.document, p.from, p.to);
+                    for step in &p.steps {
+                        println!("  {}", step);
+                    }
+                }
+                return Ok(());
+            }
+            if !run_migrations()? {
+                println!("{} is up to date", store().describe());
+            }
+            Ok(())
+        }
+        Command::Auth { action } => auth(action),
+        Command::Prompts { action } => show_prompts(action),
+        Command::InstallService { name, kind, system, output, print } => {
+            install_service(name, kind.unwrap_or_else(ServiceKind::native), *system, output.as_deref(), *print, args)
+        }
+        Command::Intake { source } => run_intake(source, file_config, args.collection.as_deref()).await,
+        Command::Rm { doc } => hide(doc, args.collection.as_deref(), DocumentState::Deleted),
+        Command::Archive { doc } => hide(doc, args.collection.as_deref(), DocumentState::Archived),
+        Command::Restore { doc } => {
+            let path = resolve_document(doc, args.collection.as_deref())?;
+            let mut previous = None;
+            Metadata::update(|metadata| previous = metadata.restore(&path))?;
+            if let Some(state) = previous {
+                audited("restore", Some(&path), serde_json::json!({ "was": state }));
+            }
+            match previous {
+                Some(_) => println!("Restored {}", path.display()),
+                None => println!("{} was not removed or archived", path.display()),
+            }
+            Ok(())
+        }
+        Command::Tag { doc, tags, remove, note, clear_notes } => {
+            let path = resolve_document(doc, args.collection.as_deref())?;
+            let changes: Vec<Annotation> = tags
+                .iter()
+                .map(|t| Annotation::parse(t, *remove))
+                .chain(note.iter().map(|n| Annotation::Note(n.clone())))
+                .collect();
+            let mut entry = DocumentMeta::default();
+            Metadata::update(|metadata| {
+                if *clear_notes {
+                    metadata.clear_notes(&path);
+                }
+                entry = metadata.apply(&path, &changes);
+            })?;
+            audited("tag", Some(&path), serde_json::json!({ "changes": changes, "clear_notes": clear_notes }));
+            match entry.tags.is_empty() {
+                true => println!("{} has no tags", path.display()),
+                false => println!("{}: {}", path.display(), entry.tags.into_iter().collect::<Vec<_>>().join(", ")),
+            }
+            for note in &entry.notes {
+                println!("  note: {}", note.text);
+            }
+            Ok(())
+        }
+        Command::Search { query, limit, json } => {
+            if *json {
+                let hits = search::search(query, args.collection.as_deref(), None, *limit, &|m| m.to_string());
+                println!("{}", serde_json::to_string_pretty(&hits)?);
+                return Ok(());
+            }
+            // Bold yellow on a terminal, **match** when piped
+            let mark: &dyn Fn(&str) -> String = match std::io::stdout().is_terminal() {
+                true => &|m| format!("\x1b[1;33m{}\x1b[0m", m),
+                false => &|m| format!("**{}**", m),
+            };
+            let hits = search::search(query, args.collection.as_deref(), None, *limit, mark);
+            if hits.is_empty() {
+                println!("No document contains '{}'", query);
+            }
+            for hit in &hits {
+                println!("{} / {}{}", hit.collection, hit.file, if hit.phrase { "" } else { " (words only)" });
+                for snippet in &hit.snippets {
+                    println!("    {}", snippet);
+                }
+            }
+            Ok(())
+        }
+        Command::Show { doc, json, no_text } => show(doc, *json, *no_text, args),
+        Command::Status { doc, to, force } => {
+            let path = resolve_document(doc, args.collection.as_deref())?;
+            let Some(to) = to else {
+                let metadata = Metadata::load()?;
+                println!("{}: {}", path.display(), metadata.status(&path).as_str());
+                for change in metadata.get(&path).map(|m| m.status_history.as_slice()).unwrap_or_default() {
+                    println!("  {}", change);
+                }
+                return Ok(());
+            };
+            let approvers = if *to == InvoiceStatus::Approved { file_config.approval.required_for(&path) } else { 0 };
+            let mut result = Ok(InvoiceStatus::default());
+            Metadata::update(|metadata| result = metadata.set_status(&path, *to, *force, approvers))?;
+            let from = result.with_context(|| format!("{} not changed", path.display()))?;
+            audited("status", Some(&path), serde_json::json!({ "from": from, "to": to, "force": force }));
+            println!("{}: {} → {}", path.display(), from.as_str(), to.as_str());
+            Ok(())
+        }
+        Command::Policy { apply, json } => {
+            let records = invoice_records(args.collection.as_deref(), false);
+            let results = apply_policy(&records, *apply, file_config)?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&results)?);
+            } else {
+                results.iter().for_each(|r| println!("{}", r));
+                println!("{} invoice(s) decided{}", results.len(), if *apply { "" } else { " (dry run: --apply changes the statuses)" });
+            }
+            Ok(())
+        }
+        Command::Approve { doc, by } => {
+            let path = resolve_document(doc, args.collection.as_deref())?;
+            let approvers = file_config.approval.required_for(&path);
+            let mut result = Ok(Vec::new());
+            Metadata::update(|metadata| result = metadata.approve(&path, by, approvers))?;
+            let approvals = result.with_context(|| format!("{} not approved", path.display()))?;
+            let approved = approvals.len() >= approvers.max(1);
+            audit::record_or_warn(
+                by,
+                "approve",
+                Some(&path.display().to_string()),
+                serde_json::json!({ "approvals": approvals.len(), "required": approvers, "approved": approved }),
+            );
+            let names: Vec<&str> = approvals.iter().map(|a| a.by.as_str()).collect();
+            match approved {
+                true => println!("{}: approved by {}", path.display(), names.join(", ")),
+                false => println!(
+                    "{}: {} of {} approvals ({}); waiting for another approver",
+                    path.display(),
+                    approvals.len(),
+                    approvers,
+                    names.join(", ")
+                ),
+            }
+            Ok(())
+        }
+        Command::Verify { doc, file, remove } => {
+            let path = resolve_document(doc, args.collection.as_deref())?;
+            let answer = match (file, remove) {
+                (_, true) => None,
+                (Some(file), false) => {
+                    let text = match file.to_str() {
+                        Some("-") => std::io::read_to_string(std::io::stdin())?,
+                        _ => std::fs::read_to_string(file).with_context(|| format!("Cannot read {}", file.display()))?,
+                    };
+                    let value: serde_json::Value =
+                        serde_json::from_str(&text).with_context(|| format!("{} is not JSON", file.display()))?;
+                    Some(value)
+                }
+                (None, false) => anyhow::bail!("Give the JSON file of the corrected extraction, or --remove"),
+            };
+            let recorded = answer.is_some();
+            let mut previous = false;
+            Metadata::update(|metadata| previous = metadata.verify(&path, answer.clone()))?;
+            if recorded || previous {
+                audited("verify", Some(&path), serde_json::json!({ "removed": !recorded, "answer": answer }));
+            }
+            if let Some(event) = answer.clone().and_then(|a| bus::document_event(EventKind::Verified, &path, Some(a))) {
+                bus::publish_or_warn(&file_config.publishers, &[event]).await;
+            }
+            match (recorded, previous) {
+                (true, false) => println!("{}: verified extraction recorded", path.display()),
+                (true, true) => println!("{}: verified extraction replaced", path.display()),
+                (false, true) => println!("{}: verified extraction removed", path.display()),
+                (false, false) => println!("{} has no verified extraction", path.display()),
+            }
+            Ok(())
+        }
+        Command::ExportTraining { format, base, output } => {
+            let (examples, skipped) = training::training_examples(args.collection.as_deref())?;
+            if examples.is_empty() {
+                anyhow::bail!("No verified extractions to export; record them with `doc-ai-server verify <doc> <file.json>`");
+            }
+            let text = match format {
+                TrainingFormat::Openai => training::to_openai_jsonl(&examples),
+                TrainingFormat::Modelfile => training::to_modelfile(&examples, base.as_deref().unwrap_or(args.model()))?,
+            };
+            match output {
+                Some(path) => {
+                    std::fs::write(path, text)?;
+                    eprintln!("{} example(s) written to {}", examples.len(), path.display());
+                }
+                None => print!("{}", text),
+            }
+            if !skipped.is_empty() {
+                eprintln!("WARNING: cannot read, not exported: {}", skipped.join(", "));
+            }
+            Ok(())
+        }
+        Command::Modelfile { base, examples, create, output } => {
+            let name = args.collection.as_deref().unwrap_or("invoices");
+            let collection = find_collection(name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown collection '{}'; use {}", name, collections::all_collection_names_human()))?;
+            let options = ai::default_options().merged_with(file_config.options.clone().unwrap_or_default());
+            let (verified, _) = training::training_examples(Some(&collection.name))?;
+            let spec = ModelSpec::for_collection(collection, base.as_deref().unwrap_or(args.model()), &options, &verified, *examples);
+            let text = spec.to_modelfile();
+            match (output, create) {
+                (Some(path), _) => {
+                    std::fs::write(path, &text)?;
+                    eprintln!("Modelfile written to {}", path.display());
+                }
+                (None, Some(_)) => {}
+                (None, None) => print!("{}", text),
+            }
+            if let Some(model) = create {
+                spec.create(model).await?;
+                println!("Created {} from {}; use it with --model {}", model, spec.base, model);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Sample documents written by `init`, so there is something to ask about
+const SAMPLE_INVOICES: [(&str, &str); 2] = [
+    ("inv_001.txt", include_str!("../../data/invoices/inv_001.txt")),
+    ("inv_002.txt", include_str!("../../data/invoices/inv_002.txt")),
+];
+
+const SMOKE_TEST_QUESTION: &str = "What is the total due on invoice INV-2025-001?";
+
+// Ask on the terminal; empty input (or --yes) takes the default
+fn prompt(question: &str, default: &str, yes: bool) -> Result<String> {
+    if yes {
+        return Ok(default.to_string());
+    }
+    print!("{} [{}]: ", question, default);
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    let answer = line.trim();
+    Ok(if answer.is_empty() { default.to_string() } else { answer.to_string() })
+}
+
+fn confirm(question: &str, yes: bool) -> Result<bool> {
+    Ok(prompt(question, "Y/n", yes)?.to_lowercase().starts_with('y'))
+}
+
+// First-run wizard
+async fn init(yes: bool, args: &Args) -> Result<()> {
+    // 1. Ollama
+    let url = prompt("Ollama URL", ai::ollama_url(), yes)?;
+    let models = match ai::list_models(&url).await {
+        Ok(models) => {
+            println!("Ollama is running at {} with {} model(s)", url, models.len());
+            models
+        }
+        Err(e) => {
+            println!("{:#}", e);
+            println!("Install Ollama from https://ollama.com, start it with `ollama serve`, then run `init` again.");
+            Vec::new()
+        }
+    };
+
+    // 2. Model
+    for (i, name) in models.iter().enumerate() {
+        println!("  {}. {}", i + 1, name);
+    }
+    let suggested = models
+        .iter()
+        .find(|m| m.starts_with(args.model()))
+        .or(models.first())
+        .cloned()
+        .unwrap_or_else(|| args.model().to_string());
+    let choice = prompt("Model (name or number)", &suggested, yes)?;
+    let model = match choice.parse::<usize>() {
+        Ok(n) if (1..=models.len()).contains(&n) => models[n - 1].clone(),
+        _ => choice,
+    };
+    if !models.is_empty() && !models.iter().any(|m| *m == model || m.strip_suffix(":latest") == Some(model.as_str())) {
+        println!("'{}' is not installed yet: run `ollama pull {}`", model, model);
+    }
+
+    // 3. Data folders
+    for collection in collections().iter().filter(|c| !c.source.is_remote()) {
+        if !collection.folder.is_dir() {
+            std::fs::create_dir_all(&collection.folder)?;
+            println!("Created {}", collection.folder.display());
+        }
+    }
+
+    // 4. Config file
+    let config_path = args.config.clone().unwrap_or_else(|| config::DEFAULT_CONFIG_FILE.into());
+    if config_path.exists() {
+        println!("{} already exists; leaving it as it is", config_path.display());
+    } else {
+        let text = format!(
+            "# Written by `doc-ai-server init`; doc-ai.toml.example lists every setting.\n\
+             ollama_url = \"{}\"\nmodel = \"{}\"\n",
+            url, model
+        );
+        std::fs::write(&config_path, text)?;
+        println!("Wrote {}", config_path.display());
+    }
+
+    // 5. Sample invoices
+    let invoices = find_collection("invoices").map(|c| c.folder.clone()).unwrap_or_else(|| "data/invoices".into());
+    if confirm(&format!("Add sample invoices to {}?", invoices.display()), yes)? {
+        for (name, text) in SAMPLE_INVOICES {
+            let path = invoices.join(name);
+            if path.exists() {
+                println!("= {} already present", path.display());
+            } else {
+                std::fs::write(&path, text)?;
+                println!("+ {}", path.display());
+            }
+        }
+    }
+
+    // 6. Smoke test
+    if models.is_empty() || !confirm(&format!("Run a test question ({})?", SMOKE_TEST_QUESTION), yes)? {
+        println!("Setup done. Start the server with `doc-ai-server serve`.");
+        return Ok(());
+    }
+    ai::init_ollama_url(&url);
+    let query = Query::builder(SMOKE_TEST_QUESTION).model(model).collection("invoices").events(ConsoleEvents).build();
+    match query.run().await {
+        Ok(response) => {
+            println!("Status: {:?}", response.status);
+            println!("{}", serde_json::to_string_pretty(&response.answer)?);
+            println!("Setup done. Start the server with `doc-ai-server serve`.");
+        }
+        Err(err) => println!("The test question failed: {} ({})", err.message, err.code),
+    }
+    Ok(())
+}
+
+/// Index what changed since the last `index`, before this process loads the index, so the question sees it
+async fn reindex_stale_documents(args: &Args, file_config: &Config) -> Result<()> {
+    let _lock = lock_index(LockMode::Exclusive)?;
+    let manifest = ingest::IndexFile::load()?.map(|index| index.manifest()).unwrap_or_default();
+    let stale: usize = collections().iter().map(|c| freshness::stale(c, &manifest).len()).sum();
+    if stale == 0 {
+        return Ok(());
+    }
+    eprintln!("{} document(s) changed since the last index; indexing them first", stale);
+    let report = ingest::ingest(&IngestOptions { strict: args.strict, ..IngestOptions::default() }, |_| {}).await?;
+    eprintln!("Index updated: {} indexed, {} removed", report.indexed, report.removed);
+
+    let retrieval = &file_config.retrieval;
+    if retrieval.mode.uses_embeddings() {
+        eprintln!("{}", update_embeddings(retrieval, retrieval.embed_options()).await?);
+    }
+    Ok(())
+}
+
+// Embed the documents changed since the last `index`; returns the line to print
+#[cfg(feature = "embeddings")]
+async fn update_embeddings(retrieval: &RetrievalConfig, options: EmbedOptions) -> Result<String> {
+    let mut embeddings = EmbeddingIndex::load()?;
+    let updated = embeddings.update(&retrieval.embed_model, options).await;
+    // Keep what was embedded before a failure; a rerun does the rest
+    embeddings.save()?;
+    Ok(format!(
+        "Embeddings: {} updated, {} total ({}{})",
+        updated?,
+        embeddings.documents.len(),
+        retrieval.embed_model,
+        if embeddings.backend == EmbedBackend::Local { ", in-process" } else { "" }
+    ))
+}
+
+#[cfg(not(feature = "embeddings"))]
+async fn update_embeddings(_retrieval: &RetrievalConfig, _options: EmbedOptions) -> Result<String> {
+    anyhow::bail!("This build has no embeddings support (enable the `embeddings` feature)")
+}
+
+// Answer one question on the command line; the exit code tells scripts whether it was answered
+async fn ask(question: &str, args: &Args, file_config: &Config) -> Result<()> {
+    let req = QueryRequest { query: question.to_string(), ..Default::default() };
+    let (envelope, code) = match crate::build_query(&req, args, file_config) {
+        Ok(query) => {
+            // Progress goes to stderr, so stdout stays a single JSON document
+            let query = Query { events: Some(std::sync::Arc::new(ConsoleEvents)), ..query };
+            match query.run().await {
+                Ok(response) => {
+                    let code = response.status.exit_code();
+                    (Envelope::success(response), code)
+                }
+                Err(err) => (Envelope::failure(err), 1),
+            }
+        }
+        Err(err) => (Envelope::failure(err), 1),
+    };
+    println!("{}", serde_json::to_string_pretty(&envelope)?);
+    if code != 0 {
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+const CHAT_HELP: &str = "/pin <doc>    answer from this document (repeatable)\n\
+                         /unpin <doc>  stop using it\n\
+                         /pins         list the pinned documents\n\
+                         /history      earlier questions of this session\n\
+                         /reset        forget the conversation (pins stay)\n\
+                         /quit         leave";
+
+// Interactive multi-turn session on the command line (the same Session as /ws/chat)
+async fn chat(args: &Args, file_config: &Config) -> Result<()> {
+    let mut session = Session::new();
+    println!("Chat session {}. Ask a question, or /help for commands.", session.id);
+    let stdin = std::io::stdin();
+    loop {
+        print!("> ");
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim();
+        let (command, rest) = line.split_once(' ').map(|(c, r)| (c, r.trim())).unwrap_or((line, ""));
+        match command {
+            "" => continue,
+            "/quit" | "/exit" => break,
+            "/help" => println!("{}", CHAT_HELP),
+            "/pin" => match session.pin(rest, args.collection.as_deref()) {
+                Ok(name) => println!("Pinned {}", name),
+                Err(e) => println!("{:#}", e),
+            },
+            "/unpin" if session.unpin(rest) => println!("Unpinned {}", rest),
+            "/unpin" => println!("{} is not pinned", rest),
+            "/pins" if session.pinned.is_empty() => println!("Nothing pinned; documents are retrieved per question"),
+            "/pins" => println!("{}", session.pinned.join("\n")),
+            "/history" => {
+                for (i, turn) in session.history.iter().enumerate() {
+                    println!("{}. {}", i + 1, turn.question);
+                }
+            }
+            "/reset" => {
+                session.reset();
+                println!("Conversation cleared");
+            }
+            _ if command.starts_with('/') => println!("Unknown command {}; /help lists them", command),
+            _ => {
+                let req = QueryRequest { query: line.to_string(), ..Default::default() };
+                let query = match crate::build_query(&req, args, file_config) {
+                    Ok(query) => session.prepare(query),
+                    Err(err) => {
+                        println!("{}", err.message);
+                        continue;
+                    }
+                };
+                match query.run().await {
+                    Ok(response) => {
+                        println!("{}", serde_json::to_string_pretty(&response.answer)?);
+                        session.record(line, &response);
+                    }
+                    Err(err) => println!("{} ({})", err.message, err.code),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Mirror remote collections into their local folders (only `only` if given)
+pub async fn sync_collections(only: Option<&str>) -> Result<()> {
+    for collection in collections().iter().filter(|c| c.source.is_remote()) {
+        if only.is_some_and(|name| !collection.matches(name)) {
+            continue;
+        }
+        let report = collection
+            .source
+            .sync(&collection.folder)
+            .await
+            .map_err(|e| e.context(format!("Syncing collection '{}' failed", collection.name)))?;
+        println!(
+            "{}: {} downloaded, {} unchanged, {} removed",
+            collection.name,
+            report.downloaded.len(),
+            report.unchanged.len(),
+            report.removed.len()
+        );
+    }
+    Ok(())
+}
+
+// Pull documents from an intake source into a collection
+async fn run_intake(source: &IntakeSource, file_config: &Config, collection: Option<&str>) -> Result<()> {
+    match source {
+        #[cfg(feature = "imap")]
+        IntakeSource::Imap { dry_run } => {
+            let imap = file_config
+                .imap
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("No [imap] section in the config file"))?;
+            let name = collection.unwrap_or(&imap.collection);
+            let target = find_collection(name).ok_or_else(|| anyhow::anyhow!("Unknown collection '{}'", name))?;
+            let dry_run = *dry_run;
+
+            let report = tokio::task::spawn_blocking(move || mailbox::intake_imap(&imap, target, dry_run)).await??;
+            println!("{} matching message(s)", report.messages);
+            for (subject, outcome) in &report.attachments {
+                if let (Some(file), true, false) = (outcome.file(), outcome.is_new_content(), dry_run) {
+                    audited("intake", Some(&target.folder.join(file)), serde_json::json!({ "source": "imap", "subject": subject }));
+                }
+                match outcome {
+                    IngestOutcome::Saved(file) => println!("+ {} ({})", file, subject),
+                    IngestOutcome::Updated(file) => println!("~ {} updated ({})", file, subject),
+                    IngestOutcome::Duplicate(file) => println!("= {} already present ({})", file, subject),
+                    IngestOutcome::Skipped(reason) => println!("- skipped {} ({})", reason, subject),
+                }
+            }
+            Ok(())
+        }
+        #[cfg(not(feature = "imap"))]
+        IntakeSource::Imap { .. } => {
+            let _ = (file_config, collection);
+            anyhow::bail!("This build has no IMAP support (enable the `imap` feature)")
+        }
+        IntakeSource::Files { paths } => {
+            let name = collection.unwrap_or("invoices");
+            let target = find_collection(name).ok_or_else(|| anyhow::anyhow!("Unknown collection '{}'", name))?;
+            let mut files = Vec::new();
+            for path in paths {
+                match path.is_dir() {
+                    true => files.extend(std::fs::read_dir(path)?.flatten().map(|e| e.path()).filter(|p| p.is_file())),
+                    false => files.push(path.clone()),
+                }
+            }
+            files.sort();
+            let extensions = vec!["txt".to_string()];
+            for path in files {
+                let bytes = std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+                // The file's own path is its key: the same file changed is a new version of its document
+                let key = std::fs::canonicalize(&path).unwrap_or_else(|_| path.clone()).display().to_string();
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let outcome = intake::ingest_document(target, &name, Some(&key), &bytes, &extensions)?;
+                if let (Some(file), true) = (outcome.file(), outcome.is_new_content()) {
+                    audited("intake", Some(&target.folder.join(file)), serde_json::json!({ "source": key }));
+                }
+                match outcome {
+                    IngestOutcome::Saved(file) => println!("+ {} ({})", file, path.display()),
+                    IngestOutcome::Updated(file) => println!("~ {} updated ({})", file, path.display()),
+                    IngestOutcome::Duplicate(file) => println!("= {} already present ({})", file, path.display()),
+                    IngestOutcome::Skipped(reason) => println!("- skipped {}", reason),
+                }
+            }
+            Ok(())
+        }
+        IntakeSource::Connectors { name, once } => {
+            let connectors: Vec<ConnectorConfig> =
+                file_config.connectors.iter().filter(|c| name.as_ref().is_none_or(|n| c.name == *n)).cloned().collect();
+            if connectors.is_empty() {
+                anyhow::bail!("No [[connector]] {}in the config file", name.as_ref().map(|n| format!("'{}' ", n)).unwrap_or_default());
+            }
+            let mut failed = 0;
+            connectors::run(&connectors, collections(), *once, |connector, pulled| match pulled {
+                Ok(pulled) => {
+                    for Pulled { name, outcome } in pulled {
+                        if let (Some(file), true) = (outcome.file(), outcome.is_new_content()) {
+                            let target = find_collection(&connector.collection).map(|c| c.folder.join(file));
+                            audited("intake", target.as_deref(), serde_json::json!({ "source": connector.name, "remote": name }));
+                        }
+                        match outcome {
+                            IngestOutcome::Saved(file) => println!("+ {} ({}: {})", file, connector.name, name),
+                            IngestOutcome::Updated(file) => println!("~ {} updated ({}: {})", file, connector.name, name),
+                            IngestOutcome::Duplicate(file) => println!("= {} already taken in ({}: {})", file, connector.name, name),
+                            IngestOutcome::Skipped(reason) => println!("- skipped {} ({})", reason, connector.name),
+                        }
+                    }
+                }
+                Err(e) => {
+                    failed += 1;
+                    eprintln!("WARNING: connector '{}': {:#}", connector.name, e);
+                }
+            })
+            .await?;
+            if *once && failed > 0 {
+                anyhow::bail!("{} connector(s) could not be polled", failed);
+            }
+            Ok(())
+        }
+        IntakeSource::Queue { once } => {
+            let queue = file_config.queue.clone().ok_or_else(|| anyhow::anyhow!("No [queue] section in the config file"))?;
+            let consumer = queue::QueueConsumer {
+                collections: collections(),
+                default_collection: collection.unwrap_or(&queue.collection),
+                index: queue.index,
+                publishers: &file_config.publishers,
+            };
+            eprintln!("Consuming {} from {} ({:?}), results to {}; Ctrl-C stops", queue.input, queue.url, queue.backend, queue.output);
+            let handled = queue::consume(&queue, &consumer, *once).await?;
+            audited("intake", None, serde_json::json!({ "source": "queue", "input": queue.input, "events": handled }));
+            println!("{} event(s) handled", handled);
+            Ok(())
+        }
+        IntakeSource::Statement { files } => {
+            let target = payments::payments_collection()?;
+            let extensions: Vec<String> = payments::STATEMENT_EXTENSIONS.iter().map(|e| e.to_string()).collect();
+            for path in files {
+                let bytes = std::fs::read(path)?;
+                // Refuse files that do not parse, rather than finding out at reconcile time
+                let found = payments::parse_statement(path, &String::from_utf8_lossy(&bytes))?;
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let outcome = intake::ingest_bytes(target, &name, &bytes, &extensions)?;
+                if let (Some(file), true) = (outcome.file(), outcome.is_new_content()) {
+                    let source = path.display().to_string();
+                    audited("intake", Some(&target.folder.join(file)), serde_json::json!({ "source": source }));
+                }
+                match outcome {
+                    IngestOutcome::Saved(file) => println!("+ {} ({} payment(s))", file, found.len()),
+                    IngestOutcome::Updated(file) => println!("~ {} updated ({} payment(s))", file, found.len()),
+                    IngestOutcome::Duplicate(file) => println!("= {} already present", file),
+                    IngestOutcome::Skipped(reason) => println!("- skipped {}", reason),
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+// auth set / remove / status
+fn auth(action: &AuthAction) -> Result<()> {
+    match action {
+        AuthAction::Set { backend } => {
+            let key = secrets::prompt_api_key(backend)?;
+            secrets::set_api_key(backend, &key)?;
+            println!("Stored the {} API key in the OS keyring", backend);
+        }
+        AuthAction::Remove { backend } => match secrets::remove_api_key(backend)? {
+            true => println!("Removed the {} API key from the OS keyring", backend),
+            false => println!("No {} API key in the OS keyring", backend),
+        },
+        AuthAction::Status { backend } => match secrets::api_key(backend) {
+            Some((_, secrets::SecretSource::Keyring)) => println!("{}: key in the OS keyring", backend),
+            Some((_, secrets::SecretSource::Environment)) => {
+                println!("{}: key from {}", backend, secrets::env_var(backend))
+            }
+            None => println!("{}: no key (use `auth set {}` or set {})", backend, backend, secrets::env_var(backend)),
+        },
+    }
+    Ok(())
+}
+
+fn show_prompts(action: &PromptsAction) -> Result<()> {
+    match action {
+        PromptsAction::List => {
+            for collection in collections() {
+                let version = prompt_version(collection);
+                println!("{:<12} {:<12} {}  {}", collection.name, version.version, version.hash, version.template);
+            }
+        }
+        PromptsAction::Show { collection } => {
+            let collection = find_collection(collection).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown collection '{}'. Valid values: {}",
+                    collection,
+                    collections::all_collection_names_human()
+                )
+            })?;
+            let version = prompt_version(collection);
+            println!("# {} — template {}, version {}, hash {}", collection.name, version.template, version.version, version.hash);
+            if let Some(domain) = &collection.domain {
+                let schema = if collection.schema.is_some() { ", with an answer schema" } else { "" };
+                println!("# domain {}{}", domain, schema);
+            }
+            println!("{}", prompts::prompt_skeleton(collection));
+        }
+    }
+    Ok(())
+}
+
+fn install_service(
+    name: &str,
+    kind: ServiceKind,
+    system: bool,
+    output: Option<&std::path::Path>,
+    print: bool,
+    args: &Args,
+) -> Result<()> {
+    let spec = ServiceSpec::from_args(name, args, system)?;
+    let text = spec.render(kind);
+    if print {
+        print!("{}", text);
+        return Ok(());
+    }
+
+    let path = match output {
+        Some(p) => p.to_path_buf(),
+        None => service::service_path(kind, name, system)?,
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, text).map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+    println!("Wrote {}", path.display());
+    println!("Runs: {} {}", spec.executable.display(), spec.args.join(" "));
+    let secrets: Vec<String> = std::env::vars().map(|(n, _)| n).filter(|n| service::is_secret_variable(n)).collect();
+    if !secrets.is_empty() {
+        println!("Left out of the file: {} (set them in the service manager or use the keyring)", secrets.join(", "));
+    }
+    println!("Start it with:\n  {}", service::activation_hint(kind, name, &path, system));
+    Ok(())
+}
+
+// rm / archive: tombstone the document, leave the file alone
+fn hide(doc: &str, collection: Option<&str>, state: DocumentState) -> Result<()> {
+    let path = resolve_document(doc, collection)?;
+    let mut previous = None;
+    Metadata::update(|metadata| previous = metadata.hide(&path, state))?;
+    let action = match state {
+        DocumentState::Archived => "archive",
+        DocumentState::Deleted => "rm",
+    };
+    audited(action, Some(&path), serde_json::json!({ "was": previous }));
+
+    let verb = match state {
+        DocumentState::Archived => "Archived",
+        DocumentState::Deleted => "Removed",
+    };
+    match previous {
+        Some(p) if p != state => println!("{} {} (was {:?})", verb, path.display(), p),
+        _ => println!("{} {}", verb, path.display()),
+    }
+    println!("The file is kept on disk; `restore {}` brings it back into the index", doc);
+    Ok(())
+}
+
+/// Audit entry for a change made from the command line, by the OS user
+fn audited(action: &str, target: Option<&Path>, details: serde_json::Value) {
+    let target = target.map(|p| p.display().to_string());
+    audit::record_or_warn(&audit::local_actor(), action, target.as_deref(), details);
+}
+
+/// Decide `records` by the [policy] and, when `apply`, move them to the status of the decision
+fn apply_policy(records: &[InvoiceRecord], apply: bool, file_config: &Config) -> Result<Vec<policy::PolicyResult>> {
+    let results = policy::decide(records, &Metadata::load()?, &file_config.policy, &file_config.approval);
+    if !apply {
+        return Ok(results);
+    }
+    let mut failed = Vec::new();
+    Metadata::update(|metadata| {
+        failed.clear();
+        for (n, result) in results.iter().enumerate() {
+            if let Err(e) = policy::apply(metadata, &result.path, &result.outcome) {
+                failed.push((n, format!("{}/{}: {:#}", result.collection, result.file, e)));
+            }
+        }
+    })?;
+    for (_, result) in results.iter().enumerate().filter(|(n, _)| !failed.iter().any(|(f, _)| f == n)) {
+        audit::record_or_warn(
+            policy::POLICY_ACTOR,
+            "policy",
+            Some(&result.path.display().to_string()),
+            serde_json::json!({ "decision": result.outcome.decision, "reasons": result.outcome.reasons, "from": result.from }),
+        );
+    }
+    for (_, failure) in &failed {
+        eprintln!("WARNING: status not changed: {}", failure);
+    }
+    Ok(results)
+}
+
+/// Migrate the state written by an older doc-ai, if it was; true when anything was
+pub fn run_migrations() -> Result<bool> {
+    let report = migrate::migrate()?;
+    for p in &report.migrated {
+        eprintln!("Migrated the {} from format version {} to {}", p.document, p.from, p.to);
+    }
+    if let Some(backup) = &report.backup {
+        eprintln!("The state before the migration is in {} (`restore-backup` puts it back)", backup.display());
+        audited("migrate", Some(backup), serde_json::to_value(&report.migrated)?);
+    }
+    Ok(!report.migrated.is_empty())
+}
+
+/// Invoice records for the reports, as selected by --collection, --include-superseded and
+/// --invoice-status; invoices approved against the two-person rule are warned about
+fn report_records(args: &Args, file_config: &Config) -> Vec<InvoiceRecord> {
+    let mut records = invoice_records(args.collection.as_deref(), args.include_superseded);
+    records.retain(|r| lifecycle::selected(&args.invoice_status, r.status));
+    if file_config.approval.threshold.is_some() {
+        for violation in approval::violations(&records, &metadata::current(), &file_config.approval) {
+            eprintln!("WARNING: two-person rule not met: {}", violation);
+        }
+    }
+    records
+}
+
+/// Questions listed by `show`
+const SHOWN_QUERIES: usize = 10;
+
+// show: everything known about one document, for review
+fn show(doc: &str, json: bool, no_text: bool, args: &Args) -> Result<()> {
+    let path = resolve_document(doc, args.collection.as_deref())?;
+    let collection = collections()
+        .iter()
+        .find(|c| path.starts_with(&c.folder))
+        .ok_or_else(|| anyhow::anyhow!("{} is not in a collection folder", path.display()))?;
+    let metadata = Metadata::load()?;
+    let summary = documents::summarize(collection, &path, &metadata)
+        .ok_or_else(|| anyhow::anyhow!("Cannot read {}", path.display()))?;
+    let verified = metadata.get(&path).and_then(|m| m.verified.clone());
+    let history = metadata.get(&path).map(|m| m.status_history.clone()).unwrap_or_default();
+    let approvals = metadata.get(&path).map(|m| m.approvals.clone()).unwrap_or_default();
+    let queries = query_log::queries_for(collection, &summary.file)?;
+    let text = if no_text { None } else { Some(get_cached_content(&path)?) };
+
+    if json {
+        let detail = serde_json::json!({
+            "path": path.display().to_string(),
+            "document": summary,
+            "verified": verified,
+            "status_history": history,
+            "approvals": approvals,
+            "queries": queries,
+            "text": text,
+        });
+        println!("{}", serde_json::to_string_pretty(&detail)?);
+        return Ok(());
+    }
+
+    println!(
+        "{} ({}, {}, {})",
+        path.display(),
+        collection.display_name,
+        summary.status.as_str(),
+        summary.invoice_status.as_str()
+    );
+    for change in &history {
+        println!("  {}", change);
+    }
+    if !approvals.is_empty() {
+        println!("  Approved by: {}", approvals.iter().map(|a| a.by.as_str()).collect::<Vec<_>>().join(", "));
+    }
+    println!("  {} bytes, modified {}", summary.bytes, summary.modified);
+    if !summary.tags.is_empty() {
+        println!("  Tags: {}", summary.tags.iter().cloned().collect::<Vec<_>>().join(", "));
+    }
+    for note in &summary.notes {
+        println!("  Note: {}", note.text);
+    }
+    let fields = &summary.fields;
+    let read: Vec<String> = [
+        ("number", fields.id.clone()),
+        ("vendor", fields.vendor.clone()),
+        ("date", fields.date.clone()),
+        ("currency", fields.currency.clone()),
+        ("net", fields.net.map(|d| d.to_string())),
+        ("tax", fields.tax.map(|d| d.to_string())),
+        ("gross", fields.gross.map(|d| d.to_string())),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| Some(format!("{} {}", name, value?)))
+    .collect();
+    if !read.is_empty() {
+        println!("  Read from the text: {}", read.join(", "));
+    }
+    if let Some(verified) = &verified {
+        println!("  Verified extraction (at {}): {}", verified.at, verified.answer);
+    }
+    match queries.len() {
+        0 => println!("  No logged question used it"),
+        n => {
+            println!("  Used by {} logged question(s), newest first:", n);
+            for query in queries.iter().take(SHOWN_QUERIES) {
+                let how = if query.cited.contains(&summary.file) { "cited" } else { "in the prompt" };
+                println!("    [{}] {} ({})", query.at, query.question, how);
+            }
+        }
+    }
+    if let Some(text) = text {
+        println!("\n{}", text.trim_end());
+    }
+    Ok(())
+}
@@ -0,0 +1,302 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Config file (doc-ai.toml)
+
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ai::{OllamaApi, Strictness};
+use crate::approval::ApprovalConfig;
+use crate::bus::PublishConfig;
+use crate::close::CloseConfig;
+use crate::connectors::ConnectorConfig;
+use crate::domain::DomainConfig;
+use crate::encryption::EncryptionConfig;
+use crate::env_config::{apply_env, ENV_PREFIX};
+use crate::escalation::EscalationConfig;
+use crate::guardrail::GuardrailConfig;
+use crate::hosts::OllamaConfig;
+use crate::options::GenerationOptions;
+use crate::policy::PolicyConfig;
+use crate::postprocess::PostProcessConfig;
+use crate::queue::QueueConfig;
+use crate::quotas::QuotaConfig;
+use crate::receipts::ExpenseConfig;
+use crate::reader::ReadingConfig;
+use crate::redact::OutputProfile;
+use crate::retrieval::RetrievalConfig;
+use crate::routing::RoutingConfig;
+use crate::rules::RuleConfig;
+use crate::store::StoreConfig;
+
+/// Config file looked up in the working directory when `--config` is not given
+pub const DEFAULT_CONFIG_FILE: &str = "doc-ai.toml";
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Config {
+    /// Ollama server, e.g. http://gpu-box:11434 (default http://localhost:11434)
+    pub ollama_url: Option<String>,
+    /// Several Ollama hosts sharing the load, in place of `ollama_url`
+    pub ollama: OllamaConfig,
+    /// Model used unless --model is given
+    pub model: Option<String>,
+    /// Ollama endpoint unless --api is given
+    pub api: Option<OllamaApi>,
+    /// Collection used when neither the request nor --collection names one
+    pub default_collection: Option<String>,
+    /// Folder replacing `data/` in the collection folders (e.g. a mounted volume)
+    pub data_dir: Option<PathBuf>,
+    /// Generation options sent with every request
+    pub options: Option<GenerationOptions>,
+    /// Locale for amounts and dates in answers unless --locale is given
+    pub locale: Option<String>,
+    /// Named document collections; entries with a built-in name override it
+    #[serde(rename = "collection")]
+    pub collections: Vec<CollectionConfig>,
+    /// Document domains (persona, schema, checks) for the collections to pick from
+    #[serde(rename = "domain")]
+    pub domains: Vec<DomainConfig>,
+    /// Answer schemas selectable with --schema: name → JSON Schema file
+    pub schemas: BTreeMap<String, PathBuf>,
+    /// File size limit and non-UTF-8 handling
+    pub reading: ReadingConfig,
+    /// Keyword, embedding or hybrid retrieval
+    pub retrieval: RetrievalConfig,
+    /// Small or large model by the question's complexity
+    pub routing: RoutingConfig,
+    /// Larger model asked again when an answer fails a check
+    pub escalation: EscalationConfig,
+    /// Steps answers go through after the model, per command
+    pub postprocess: PostProcessConfig,
+    /// Mailbox polled by `intake imap`
+    pub imap: Option<ImapConfig>,
+    /// Validators of your own (`[[rule]]`), run by `check-rules`
+    #[serde(rename = "rule")]
+    pub rules: Vec<RuleConfig>,
+    /// Where extraction and verification events go (`[[publish]]`)
+    #[serde(rename = "publish")]
+    pub publishers: Vec<PublishConfig>,
+    /// Message queue read by `intake queue`
+    pub queue: Option<QueueConfig>,
+    /// Remote folders polled by `intake connectors` (`[[connector]]`)
+    #[serde(rename = "connector")]
+    pub connectors: Vec<ConnectorConfig>,
+    /// Checks run by `close`
+    pub close: CloseConfig,
+    /// Automatic approval, review or rejection of extracted invoices
+    pub policy: PolicyConfig,
+    /// Two-person rule for approving invoices over a threshold
+    pub approval: ApprovalConfig,
+    /// Expense categories of the receipts domain and `export-expenses`
+    pub expenses: ExpenseConfig,
+    /// Encryption of the index, metadata and mirrored documents
+    pub encryption: EncryptionConfig,
+    /// Where the index, metadata and embeddings are kept
+    pub store: StoreConfig,
+    /// Secret scanning of prompts sent to other machines
+    pub guardrail: GuardrailConfig,
+    /// Output profiles selectable with --output-profile: name → allowed answer fields
+    pub output_profiles: BTreeMap<String, OutputProfile>,
+    /// Environments selectable with --profile (`[profile.<name>]`)
+    pub profile: BTreeMap<String, EnvironmentProfile>,
+    /// Client organisations of a shared server, each with its own documents (`[[tenant]]`)
+    #[serde(rename = "tenant")]
+    pub tenants: Vec<TenantConfig>,
+    /// Limits for tenants without a quota of their own
+    pub quota: QuotaConfig,
+}
+
+/// One tenant: requests with its API key see only the collections under its `data_dir`
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TenantConfig {
+    /// Lower-case letters, digits, '-' and '_'
+    pub name: String,
+    /// Hex SHA-256 of the tenant's API key (`printf %s "$KEY" | sha256sum`); the key itself is not stored
+    pub key_sha256: String,
+    /// Replaces `data/` in the collection folders for this tenant, e.g. data/tenants/acme
+    pub data_dir: PathBuf,
+    /// Daily questions and tokens, and storage (`[tenant.quota]`; default `[quota]`)
+    pub quota: Option<QuotaConfig>,
+}
+
+/// Settings bundled for one environment (laptop, office GPU server...);
+/// the selected profile overrides the top-level settings
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct EnvironmentProfile {
+    pub ollama_url: Option<String>,
+    pub model: Option<String>,
+    pub api: Option<OllamaApi>,
+    pub default_collection: Option<String>,
+    /// Merged over the top-level options, key by key
+    pub options: Option<GenerationOptions>,
+    /// Applied after the top-level `[[collection]]` entries (same name: settings merged)
+    #[serde(rename = "collection")]
+    pub collections: Vec<CollectionConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct CollectionConfig {
+    pub name: String,
+    pub folder: Option<PathBuf>,
+    pub display_name: Option<String>,
+    pub aliases: Vec<String>,
+    /// Document domain ("invoices", "contracts" or a [[domain]]), for the prompt, schema and checks
+    pub domain: Option<String>,
+    /// System role for the prompt (default: the domain's)
+    pub instruction: Option<String>,
+    /// Path to a prompt template using {system_role}, {contents} and {query}
+    pub template: Option<PathBuf>,
+    /// Semantic version of the template, recorded with every answer; bump it on every edit
+    pub template_version: Option<String>,
+    /// Run the VAT/tax consistency checks on this collection's documents
+    pub vat_check: Option<bool>,
+    /// Grounding rules: "lax", "normal" or "strict" (`--strictness` and requests override it)
+    pub strictness: Option<Strictness>,
+    /// Where the documents live: a local path, an http(s):// base URL, or s3://bucket/prefix.
+    /// Remote documents are mirrored into `folder` (default data/.remote/<name>) by `index`.
+    pub source: Option<String>,
+    /// File names under an http(s) source; when empty, `<source>/index.json` lists them
+    pub files: Vec<String>,
+    /// S3-compatible endpoint, e.g. http://localhost:9000 for MinIO
+    pub s3_endpoint: Option<String>,
+    pub s3_region: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ImapConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    /// Prefer `password_env`; a password here is only for throwaway test mailboxes
+    pub password: Option<String>,
+    /// Environment variable holding the password
+    pub password_env: String,
+    pub mailbox: String,
+    /// Only messages from this sender (IMAP substring match)
+    pub from: Option<String>,
+    /// Only messages whose subject contains this
+    pub subject_contains: Option<String>,
+    /// Collection the attachments are saved into
+    pub collection: String,
+    /// Attachment extensions to keep
+    pub extensions: Vec<String>,
+    /// IMAP keyword set on processed messages
+    pub processed_flag: String,
+    /// Move processed messages to this folder
+    pub move_to: Option<String>,
+}
+
+impl Default for ImapConfig {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 993,
+            username: String::new(),
+            password: None,
+            password_env: "DOC_AI_IMAP_PASSWORD".to_string(),
+            mailbox: "INBOX".to_string(),
+            from: None,
+            subject_contains: Some("invoice".to_string()),
+            collection: "invoices".to_string(),
+            extensions: vec!["txt".to_string()],
+            processed_flag: "DocAiProcessed".to_string(),
+            move_to: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load the given config file, or `doc-ai.toml` if present, or fall back to defaults;
+    /// then apply the `DOC_AI_*` environment variables and the named `[profile.<name>]`
+    /// (where both set a key, the environment wins)
+    pub fn load(path: Option<&Path>, profile: Option<&str>) -> anyhow::Result<Self> {
+        let mut config = Self::load_file(path)?;
+        if let Some(name) = profile {
+            config.apply_profile(name)?;
+        }
+        Ok(config)
+    }
+
+    fn apply_profile(&mut self, name: &str) -> anyhow::Result<()> {
+        let profile = self.profile.get(name).cloned().ok_or_else(|| {
+            let known: Vec<&str> = self.profile.keys().map(String::as_str).collect();
+            anyhow::anyhow!(
+                "Unknown profile '{}'. Valid values: {}",
+                name,
+                if known.is_empty() { "none configured".to_string() } else { known.join(", ") }
+            )
+        })?;
+        self.ollama_url = profile.ollama_url.or(self.ollama_url.take());
+        self.model = profile.model.or(self.model.take());
+        self.api = profile.api.or(self.api);
+        self.default_collection = profile.default_collection.or(self.default_collection.take());
+        if let Some(options) = profile.options {
+            self.options = Some(match self.options.take() {
+                Some(base) => base.merged_with(options),
+                None => options,
+            });
+        }
+        self.collections.extend(profile.collections);
+        Ok(())
+    }
+
+    /// The config file, if any, with the `DOC_AI_*` environment variables applied over it
+    fn load_file(path: Option<&Path>) -> anyhow::Result<Self> {
+        let path = match path {
+            Some(p) => Some(p.to_path_buf()),
+            None => Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|p| p.exists()),
+        };
+
+        let mut table = match &path {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+                text.parse::<toml::Table>().with_context(|| format!("Invalid config file: {}", path.display()))?
+            }
+            None => toml::Table::new(),
+        };
+        let from_env = apply_env(&mut table, std::env::vars())?;
+
+        toml::Value::Table(table).try_into().with_context(|| match (&path, from_env) {
+            (Some(path), 0) => format!("Invalid config file: {}", path.display()),
+            (Some(path), _) => format!("Invalid config file {} with the {}* environment variables", path.display(), ENV_PREFIX),
+            (None, _) => format!("Invalid configuration in the {}* environment variables", ENV_PREFIX),
+        })
+    }
+
+    /// The named output profile
+    pub fn output_profile(&self, name: &str) -> anyhow::Result<OutputProfile> {
+        self.output_profiles.get(name).cloned().ok_or_else(|| {
+            let known: Vec<&str> = self.output_profiles.keys().map(String::as_str).collect();
+            anyhow::anyhow!(
+                "Unknown output profile '{}'. Valid values: {}",
+                name,
+                if known.is_empty() { "none configured".to_string() } else { known.join(", ") }
+            )
+        })
+    }
+
+    /// Load the named answer schema from its file
+    pub fn schema(&self, name: &str) -> anyhow::Result<Value> {
+        let path = self.schemas.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.schemas.keys().map(String::as_str).collect();
+            anyhow::anyhow!(
+                "Unknown schema '{}'. Valid values: {}",
+                name,
+                if known.is_empty() { "none configured".to_string() } else { known.join(", ") }
+            )
+        })?;
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read schema file: {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("Invalid JSON in schema file: {}", path.display()))
+    }
+}
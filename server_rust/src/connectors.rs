@@ -0,0 +1,514 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Intake connectors (`intake connectors`): poll a remote folder on a schedule
+// and save what is new there into a collection, for vendors that deliver to
+// an SFTP drop directory, a Google Drive folder or a Dropbox path rather than
+// by mail. Each `[[connector]]` is polled every `interval_secs`.
+//
+// A file is fetched when its remote version (SFTP size and mtime, Drive MD5,
+// Dropbox content hash) is one the connector has not seen, and taken in under
+// its remote id as the intake key: content the collection already has is a
+// duplicate, whatever the file is called, and a changed file updates the
+// document it was saved as. What each connector has seen is kept in the store
+// under `connectors`.
+//
+// SFTP needs the `sftp` feature (libssh2). Drive and Dropbox take an OAuth
+// access token from `secret_env`, or from `secret_file`, which is read on every
+// poll so that whatever refreshes the token can rewrite it.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::ingest::{ingest, IngestOptions};
+use crate::intake::{ingest_document, IngestOutcome};
+use crate::lock::{lock_index, LockMode};
+use crate::store::{self, store};
+use crate::Collection;
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectorKind {
+    #[default]
+    Sftp,
+    Gdrive,
+    Dropbox,
+}
+
+/// `[[connector]]` in doc-ai.toml
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ConnectorConfig {
+    /// Names the connector in messages and in its saved state
+    pub name: String,
+    pub kind: ConnectorKind,
+    /// SFTP directory, Drive folder id or Dropbox path
+    pub path: String,
+    /// Collection the files are saved into
+    pub collection: String,
+    pub extensions: Vec<String>,
+    /// Seconds between polls
+    pub interval_secs: u64,
+    /// Update the index after a poll that saved something
+    pub index: bool,
+    /// SFTP server
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    /// SFTP private key; the password (or the key's passphrase) comes from `secret_env`
+    pub key_file: Option<PathBuf>,
+    /// SFTP host key fingerprint as `ssh-keygen -lf` prints it (SHA256:...); when unset the
+    /// key must be in ~/.ssh/known_hosts
+    pub host_key_sha256: Option<String>,
+    /// Connect to an SFTP server whatever host key it shows (neither pinned nor known)
+    pub accept_any_host_key: bool,
+    /// Environment variable with the SFTP password or the Drive/Dropbox access token
+    pub secret_env: Option<String>,
+    /// File with the access token, read on every poll
+    pub secret_file: Option<PathBuf>,
+    /// Drive or Dropbox API base URL, for a proxy or a test server
+    pub api_url: Option<String>,
+}
+
+impl Default for ConnectorConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            kind: ConnectorKind::Sftp,
+            path: String::new(),
+            collection: "invoices".to_string(),
+            extensions: vec!["txt".to_string()],
+            interval_secs: 300,
+            index: true,
+            host: String::new(),
+            port: 22,
+            username: String::new(),
+            key_file: None,
+            host_key_sha256: None,
+            accept_any_host_key: false,
+            secret_env: None,
+            secret_file: None,
+            api_url: None,
+        }
+    }
+}
+
+impl ConnectorConfig {
+    /// `secret_file` as it is now, else `secret_env`
+    fn secret(&self) -> Result<Option<String>> {
+        if let Some(file) = &self.secret_file {
+            let text = std::fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?;
+            return Ok(Some(text.trim().to_string()));
+        }
+        match &self.secret_env {
+            Some(name) => Ok(Some(std::env::var(name).with_context(|| format!("{} is not set", name))?)),
+            None => Ok(None),
+        }
+    }
+
+    fn token(&self) -> Result<String> {
+        self.secret()?
+            .ok_or_else(|| anyhow::anyhow!("Connector '{}' needs an access token (secret_env or secret_file)", self.name))
+    }
+
+    fn wants(&self, name: &str) -> bool {
+        let extension = Path::new(name).extension().and_then(|e| e.to_str()).unwrap_or_default();
+        self.extensions.iter().any(|e| e.eq_ignore_ascii_case(extension))
+    }
+}
+
+/// A file in the remote folder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteFile {
+    /// Stable across renames where the service has ids (Drive, Dropbox), else the path
+    pub id: String,
+    pub name: String,
+    /// Changes when the content does
+    pub version: String,
+}
+
+pub trait Connector: Send + Sync {
+    /// The files in the remote folder (not its subfolders)
+    fn list(&self) -> impl Future<Output = Result<Vec<RemoteFile>>> + Send;
+
+    fn fetch(&self, file: &RemoteFile) -> impl Future<Output = Result<Vec<u8>>> + Send;
+}
+
+/// What one connector has taken in so far
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct ConnectorState {
+    /// Remote id → the version last fetched
+    pub seen: BTreeMap<String, String>,
+    /// Unix time of the last poll
+    pub polled: Option<u64>,
+}
+
+/// One remote file fetched in a poll
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Pulled {
+    /// Remote name
+    pub name: String,
+    pub outcome: IngestOutcome,
+}
+
+/// Fetch the files that are new or changed since `state` and save them into `collection`.
+/// A file that cannot be fetched is reported as skipped and tried again next time.
+pub async fn poll<C: Connector>(
+    connector: &C,
+    config: &ConnectorConfig,
+    collection: &Collection,
+    state: &mut ConnectorState,
+) -> Result<Vec<Pulled>> {
+    let mut pulled = Vec::new();
+    for file in connector.list().await? {
+        if !config.wants(&file.name) || state.seen.get(&file.id) == Some(&file.version) {
+            continue;
+        }
+        let bytes = match connector.fetch(&file).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let outcome = IngestOutcome::Skipped(format!("'{}' could not be fetched: {:#}", file.name, e));
+                pulled.push(Pulled { name: file.name, outcome });
+                continue;
+            }
+        };
+        let key = format!("{}:{}", config.name, file.id);
+        let outcome = ingest_document(collection, &file.name, Some(&key), &bytes, &config.extensions)?;
+        state.seen.insert(file.id, file.version);
+        pulled.push(Pulled { name: file.name, outcome });
+    }
+    state.polled = Some(crate::metadata::now());
+    Ok(pulled)
+}
+
+/// Poll one configured connector, with its state from the store
+pub async fn poll_configured(config: &ConnectorConfig, collection: &Collection) -> Result<Vec<Pulled>> {
+    let mut states = load_states()?;
+    let mut state = states.remove(&config.name).unwrap_or_default();
+    let pulled = match config.kind {
+        ConnectorKind::Gdrive => poll(&DriveConnector::new(config)?, config, collection, &mut state).await?,
+        ConnectorKind::Dropbox => poll(&DropboxConnector::new(config)?, config, collection, &mut state).await?,
+        #[cfg(feature = "sftp")]
+        ConnectorKind::Sftp => poll(&sftp::SftpConnector::new(config), config, collection, &mut state).await?,
+        #[cfg(not(feature = "sftp"))]
+        ConnectorKind::Sftp => anyhow::bail!("This build has no SFTP support (enable the `sftp` feature)"),
+    };
+    let name = config.name.clone();
+    store().update(store::CONNECTORS, &mut |text| {
+        let mut states: BTreeMap<String, ConnectorState> = match text {
+            Some(text) => serde_json::from_str(&text)?,
+            None => BTreeMap::new(),
+        };
+        states.insert(name.clone(), state.clone());
+        Ok(serde_json::to_string_pretty(&states)?)
+    })?;
+    Ok(pulled)
+}
+
+/// Saved state of every connector, by name
+pub fn load_states() -> Result<BTreeMap<String, ConnectorState>> {
+    match store().load(store::CONNECTORS)? {
+        Some(text) => serde_json::from_str(&text).context("The saved connector state is not valid"),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+/// Poll each connector when due until Ctrl-C, or with `once` each one time.
+/// `report` gets every poll's outcome; a connector that fails is reported and polled again later.
+pub async fn run(
+    connectors: &[ConnectorConfig],
+    collections: &[Collection],
+    once: bool,
+    mut report: impl FnMut(&ConnectorConfig, &Result<Vec<Pulled>>),
+) -> Result<()> {
+    for config in connectors {
+        anyhow::ensure!(!config.name.is_empty() && !config.path.is_empty(), "Every [[connector]] needs a name and a path");
+        anyhow::ensure!(
+            collections.iter().any(|c| c.matches(&config.collection)),
+            "Connector '{}': unknown collection '{}'",
+            config.name,
+            config.collection
+        );
+    }
+    let mut due: Vec<Instant> = vec![Instant::now(); connectors.len()];
+    loop {
+        for (config, due) in connectors.iter().zip(due.iter_mut()) {
+            if *due > Instant::now() {
+                continue;
+            }
+            let collection = collections.iter().find(|c| c.matches(&config.collection)).expect("checked above");
+            let pulled = poll_configured(config, collection).await;
+            let saved = pulled.as_ref().is_ok_and(|p| p.iter().any(|p| p.outcome.is_new_content()));
+            if config.index && saved {
+                let _lock = lock_index(LockMode::Exclusive)?;
+                ingest(&IngestOptions::default(), |_| {}).await?;
+            }
+            report(config, &pulled);
+            *due = Instant::now() + Duration::from_secs(config.interval_secs.max(1));
+        }
+        if once || connectors.is_empty() {
+            return Ok(());
+        }
+        let next = due.iter().min().copied().unwrap_or_else(Instant::now);
+        tokio::select! {
+            _ = tokio::time::sleep_until(next.into()) => {}
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+    }
+}
+
+fn client() -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder().timeout(TIMEOUT).build()?)
+}
+
+/// A Google Drive folder, by id (Drive API v3)
+pub struct DriveConnector {
+    api: String,
+    folder: String,
+    token: String,
+    client: reqwest::Client,
+}
+
+impl DriveConnector {
+    pub fn new(config: &ConnectorConfig) -> Result<Self> {
+        Ok(Self {
+            api: config.api_url.clone().unwrap_or_else(|| "https://www.googleapis.com".to_string()),
+            folder: config.path.clone(),
+            token: config.token()?,
+            client: client()?,
+        })
+    }
+}
+
+const FIELDS: &str = "nextPageToken, files(id, name, mimeType, md5Checksum, modifiedTime)";
+
+impl Connector for DriveConnector {
+    async fn list(&self) -> Result<Vec<RemoteFile>> {
+        let query = format!("'{}' in parents and trashed = false", self.folder.replace('\'', "\\'"));
+        let mut files = Vec::new();
+        let mut page: Option<String> = None;
+        loop {
+            let mut request = self
+                .client
+                .get(format!("{}/drive/v3/files", self.api))
+                .bearer_auth(&self.token)
+                .query(&[("q", query.as_str()), ("fields", FIELDS)]);
+            if let Some(page) = &page {
+                request = request.query(&[("pageToken", page)]);
+            }
+            let listing: Value = request.send().await?.error_for_status()?.json().await?;
+            for file in listing["files"].as_array().into_iter().flatten() {
+                // Google Docs and folders have no bytes to download
+                if file["mimeType"].as_str().is_some_and(|m| m.starts_with("application/vnd.google-apps")) {
+                    continue;
+                }
+                let (Some(id), Some(name)) = (file["id"].as_str(), file["name"].as_str()) else {
+                    continue;
+                };
+                let version = file["md5Checksum"].as_str().or(file["modifiedTime"].as_str()).unwrap_or_default();
+                files.push(RemoteFile { id: id.to_string(), name: name.to_string(), version: version.to_string() });
+            }
+            match listing["nextPageToken"].as_str() {
+                Some(next) => page = Some(next.to_string()),
+                None => return Ok(files),
+            }
+        }
+    }
+
+    async fn fetch(&self, file: &RemoteFile) -> Result<Vec<u8>> {
+        let url = format!("{}/drive/v3/files/{}", self.api, file.id);
+        let response = self.client.get(url).bearer_auth(&self.token).query(&[("alt", "media")]).send().await?;
+        Ok(response.error_for_status()?.bytes().await?.to_vec())
+    }
+}
+
+/// A Dropbox folder, by path (API v2)
+pub struct DropboxConnector {
+    api: String,
+    content: String,
+    path: String,
+    token: String,
+    client: reqwest::Client,
+}
+
+impl DropboxConnector {
+    pub fn new(config: &ConnectorConfig) -> Result<Self> {
+        // Dropbox's root folder is "", not "/"
+        let path = match config.path.trim_end_matches('/') {
+            "" => String::new(),
+            path if path.starts_with('/') => path.to_string(),
+            path => format!("/{}", path),
+        };
+        Ok(Self {
+            api: config.api_url.clone().unwrap_or_else(|| "https://api.dropboxapi.com".to_string()),
+            content: config.api_url.clone().unwrap_or_else(|| "https://content.dropboxapi.com".to_string()),
+            path,
+            token: config.token()?,
+            client: client()?,
+        })
+    }
+
+    async fn call(&self, endpoint: &str, body: Value) -> Result<Value> {
+        let url = format!("{}/2/files/{}", self.api, endpoint);
+        let response = self.client.post(url).bearer_auth(&self.token).json(&body).send().await?;
+        Ok(response.error_for_status()?.json().await?)
+    }
+}
+
+impl Connector for DropboxConnector {
+    async fn list(&self) -> Result<Vec<RemoteFile>> {
+        let mut files = Vec::new();
+        let mut listing = self.call("list_folder", json!({ "path": self.path, "recursive": false })).await?;
+        loop {
+            for entry in listing["entries"].as_array().into_iter().flatten() {
+                if entry[".tag"] != "file" {
+                    continue;
+                }
+                let (Some(id), Some(name)) = (entry["id"].as_str(), entry["name"].as_str()) else {
+                    continue;
+                };
+                let version = entry["content_hash"].as_str().or(entry["rev"].as_str()).unwrap_or_default();
+                files.push(RemoteFile { id: id.to_string(), name: name.to_string(), version: version.to_string() });
+            }
+            if listing["has_more"] != true {
+                return Ok(files);
+            }
+            listing = self.call("list_folder/continue", json!({ "cursor": listing["cursor"].clone() })).await?;
+        }
+    }
+
+    async fn fetch(&self, file: &RemoteFile) -> Result<Vec<u8>> {
+        // The argument travels in a header; ids are ASCII where names need not be
+        let argument = json!({ "path": file.id }).to_string();
+        let response = self
+            .client
+            .post(format!("{}/2/files/download", self.content))
+            .bearer_auth(&self.token)
+            .header("Dropbox-API-Arg", argument)
+            .send()
+            .await?;
+        Ok(response.error_for_status()?.bytes().await?.to_vec())
+    }
+}
+
+#[cfg(feature = "sftp")]
+mod sftp {
+    use anyhow::{Context, Result};
+    use base64::Engine as _;
+    use std::io::Read;
+    use std::net::TcpStream;
+    use std::path::PathBuf;
+
+    use super::{Connector, ConnectorConfig, RemoteFile};
+
+    /// A directory on an SFTP server (blocking libssh2, one session per call)
+    pub struct SftpConnector {
+        config: ConnectorConfig,
+    }
+
+    impl SftpConnector {
+        pub fn new(config: &ConnectorConfig) -> Self {
+            Self { config: config.clone() }
+        }
+    }
+
+    fn open(config: &ConnectorConfig) -> Result<ssh2::Sftp> {
+        let tcp = TcpStream::connect((config.host.as_str(), config.port))
+            .with_context(|| format!("Cannot connect to {}:{}", config.host, config.port))?;
+        let mut session = ssh2::Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.handshake()?;
+        check_host_key(&session, config)?;
+        let secret = config.secret()?;
+        match &config.key_file {
+            Some(key) => session.userauth_pubkey_file(&config.username, None, key, secret.as_deref())?,
+            None => session.userauth_password(
+                &config.username,
+                secret.as_deref().context("SFTP needs a key_file or a password in secret_env")?,
+            )?,
+        }
+        Ok(session.sftp()?)
+    }
+
+    /// The server's host key must be the pinned one, or else the one ~/.ssh/known_hosts has
+    /// for it; any key only with `accept_any_host_key`
+    fn check_host_key(session: &ssh2::Session, config: &ConnectorConfig) -> Result<()> {
+        let hash = session.host_key_hash(ssh2::HashType::Sha256).context("The server sent no host key")?;
+        let found = format!("SHA256:{}", base64::engine::general_purpose::STANDARD_NO_PAD.encode(hash));
+        if let Some(expected) = &config.host_key_sha256 {
+            anyhow::ensure!(
+                found == *expected,
+                "Host key of {} is {}, not the configured {}",
+                config.host,
+                found,
+                expected
+            );
+            return Ok(());
+        }
+        if config.accept_any_host_key {
+            return Ok(());
+        }
+
+        let (key, _) = session.host_key().context("The server sent no host key")?;
+        let file = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".ssh/known_hosts"));
+        let mut known_hosts = session.known_hosts()?;
+        if let Some(file) = file.as_ref().filter(|f| f.exists()) {
+            known_hosts
+                .read_file(file, ssh2::KnownHostFileKind::OpenSSH)
+                .with_context(|| format!("Cannot read {}", file.display()))?;
+        }
+        match known_hosts.check_port(&config.host, config.port, key) {
+            ssh2::CheckResult::Match => Ok(()),
+            ssh2::CheckResult::Mismatch => anyhow::bail!(
+                "Host key of {} ({}) does not match the one in ~/.ssh/known_hosts",
+                config.host,
+                found
+            ),
+            _ => anyhow::bail!(
+                "Host key of {} ({}) is not in ~/.ssh/known_hosts; pin it with host_key_sha256, \
+                 or set accept_any_host_key = true to connect anyway",
+                config.host,
+                found
+            ),
+        }
+    }
+
+    impl Connector for SftpConnector {
+        async fn list(&self) -> Result<Vec<RemoteFile>> {
+            let config = self.config.clone();
+            tokio::task::spawn_blocking(move || {
+                let sftp = open(&config)?;
+                let mut files = Vec::new();
+                for (path, stat) in sftp.readdir(std::path::Path::new(&config.path))? {
+                    let Some(name) = path.file_name().map(|n| n.to_string_lossy().into_owned()) else {
+                        continue;
+                    };
+                    if stat.is_file() {
+                        let version = format!("{}:{}", stat.size.unwrap_or_default(), stat.mtime.unwrap_or_default());
+                        files.push(RemoteFile { id: path.to_string_lossy().into_owned(), name, version });
+                    }
+                }
+                Ok(files)
+            })
+            .await?
+        }
+
+        async fn fetch(&self, file: &RemoteFile) -> Result<Vec<u8>> {
+            let config = self.config.clone();
+            let path = PathBuf::from(&file.id);
+            tokio::task::spawn_blocking(move || {
+                let mut bytes = Vec::new();
+                open(&config)?.open(&path)?.read_to_end(&mut bytes)?;
+                Ok(bytes)
+            })
+            .await?
+        }
+    }
+}
@@ -71,17 +71,14 @@ impl Category {
     /// Try to parse a string (from API request) into a Category
     pub fn from_api_value(s: &str) -> Option<Self> {
         let lower = s.to_lowercase();
-        for variant in [
+        [
             Category::Invoices,
             Category::EmploymentContracts,
             Category::CustomerSupport,
             Category::KnowledgeBase,
-        ] {
-            if variant.aliases().iter().any(|&a| a == lower) {
-                return Some(variant);
-            }
-        }
-        None
+        ]
+        .into_iter()
+        .find(|variant| variant.aliases().contains(&lower.as_str()))
     }
 
     /// Returns a comma-separated (with 'or' before last) string of all valid api values
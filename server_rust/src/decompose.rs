@@ -0,0 +1,154 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Rule-based question decomposition. "Total for ACME in Q1 and how does it
+// compare to Q4?" needs documents for both quarters, so compound questions
+// are split at sentence boundaries and at "and" followed by a new question,
+// each part is answered on its own, and the answers are composed afterwards.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::ai::AnswerStatus;
+use crate::ApiResponse;
+
+/// More parts than this are left as one question (probably a list, not several questions)
+pub const MAX_PARTS: usize = 4;
+
+/// "; " and "? " between questions, and "and"/"also" before a question word (captured:
+/// it starts the next part)
+static SPLIT_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)\?\s+|;\s*|,?\s+(?:and|also|as well as)\s+(?:(?:also|then)\s+)?(how|what|which|who|whom|when|where|why|is|are|was|were|does|do|did|has|have|can|compare)\b",
+    )
+    .unwrap()
+});
+
+/// A part that leans on the previous one ("how does it compare...")
+static FOLLOW_UP_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\b(?:it|its|that|this|those|these|they|them|compare[sd]?|same|difference)\b").unwrap());
+
+/// Sub-questions of `question`; a single element when it is not compound.
+/// Parts referring back to the first one get it attached as context.
+pub fn split_question(question: &str) -> Vec<String> {
+    let question = question.trim();
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for caps in SPLIT_RE.captures_iter(question) {
+        let whole = caps.get(0).unwrap();
+        pieces.push(&question[start..whole.start()]);
+        start = caps.get(1).map_or(whole.end(), |word| word.start());
+    }
+    pieces.push(&question[start..]);
+
+    let parts: Vec<&str> = pieces
+        .into_iter()
+        .map(|p| p.trim().trim_end_matches('?').trim())
+        .filter(|p| p.split_whitespace().count() >= 2)
+        .collect();
+    if parts.len() < 2 || parts.len() > MAX_PARTS {
+        return vec![question.to_string()];
+    }
+
+    let first = parts[0];
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            if i > 0 && FOLLOW_UP_RE.is_match(part) {
+                format!("{}? (Follow-up to: {}?)", part, first)
+            } else {
+                format!("{}?", part)
+            }
+        })
+        .collect()
+}
+
+/// Overall status: the first part that was not answered decides
+fn combined_status(responses: &[ApiResponse]) -> AnswerStatus {
+    responses
+        .iter()
+        .map(|r| r.status)
+        .find(|s| *s != AnswerStatus::Answered)
+        .unwrap_or(AnswerStatus::Answered)
+}
+
+/// One response from the answers to the parts, each part keeping its own sources
+pub fn compose(parts: &[String], responses: Vec<ApiResponse>) -> ApiResponse {
+    let status = combined_status(&responses);
+    let mut used_files: Vec<String> = Vec::new();
+    let mut verification = Vec::new();
+    let mut schema_errors: Option<Vec<String>> = None;
+    let mut skipped_files = Vec::new();
+    let mut redacted = Vec::new();
+    let mut warnings = Vec::new();
+    let mut elapsed_ms = 0;
+    let mut model = None;
+    let mut prompt = None;
+    let mut answers = Vec::new();
+
+    for (i, (question, response)) in parts.iter().zip(responses).enumerate() {
+        for file in &response.used_files {
+            if !used_files.contains(file) {
+                used_files.push(file.clone());
+            }
+        }
+        verification.extend(response.verification.into_iter().flatten());
+        if let Some(errors) = response.schema_errors {
+            schema_errors
+                .get_or_insert_with(Vec::new)
+                .extend(errors.into_iter().map(|e| format!("part {}: {}", i + 1, e)));
+        }
+        skipped_files.extend(response.skipped_files);
+        redacted.extend(response.redacted.into_iter().map(|path| format!("part {}: {}", i + 1, path)));
+        warnings.extend(response.warnings.into_iter().map(|mut w| {
+            w.message = format!("part {}: {}", i + 1, w.message);
+            w
+        }));
+        elapsed_ms += response.elapsed_ms.unwrap_or_default();
+        model = model.or(response.model);
+        prompt = prompt.or(response.prompt);
+
+        let mut part = json!({
+            "question": question,
+            "status": response.status,
+            "answer": response.answer,
+            "used_files": response.used_files,
+        });
+        if let Some(provenance) = response.provenance {
+            part["provenance"] = provenance;
+        }
+        if let Some(aggregation) = response.aggregation {
+            part["aggregation"] = serde_json::to_value(aggregation).unwrap_or_default();
+        }
+        if let Some(escalation) = response.escalation {
+            part["escalation"] = serde_json::to_value(escalation).unwrap_or_default();
+        }
+        answers.push(part);
+    }
+
+    let mut seen = Vec::new();
+    verification.retain(|r| {
+        let first = !seen.contains(&r.file);
+        seen.push(r.file.clone());
+        first
+    });
+    ApiResponse {
+        answer: json!({ "status": status, "parts": Value::Array(answers) }),
+        status,
+        used_files,
+        verification: (!verification.is_empty()).then_some(verification),
+        consistency: None,
+        aggregation: None,
+        provenance: None,
+        schema_errors,
+        skipped_files,
+        redacted,
+        warnings,
+        model,
+        escalation: None,
+        prompt,
+        elapsed_ms: Some(elapsed_ms),
+        error: None,
+    }
+}
@@ -0,0 +1,216 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Document ids: the string a document's path is known by in the index, the
+// metadata and the embeddings. An id names exactly one path and turns back
+// into it, whatever the file is called:
+//
+//   valid UTF-8   the path itself, with `\` written as `/` on Windows (where
+//                 `/` cannot be part of a name), so ids read the same on
+//                 every platform and match those saved before ids existed
+//   otherwise     a NUL (which no path contains) followed by the path with
+//                 `%` and each byte (Unix) or unpaired surrogate (Windows)
+//                 that is not text escaped as %XX or %uXXXX
+//
+// Scanners name files after vendors ("Société Générale.txt", "株式会社.txt")
+// and a copy off an old Windows share can hold Latin-1 names; before, such a
+// path was keyed lossily, could not be found again and dropped out of
+// retrieval. `long_path` is for the paths handed to code outside std, which
+// does not lift the 260 character limit of Windows by itself.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+const MARKER: char = '\0';
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DocId(String);
+
+impl DocId {
+    pub fn new(path: &Path) -> Self {
+        Self(encode(path))
+    }
+
+    /// An id as saved, e.g. a key of the index
+    pub fn from_key(key: impl Into<String>) -> Self {
+        Self(key.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+
+    /// The path the id was made from
+    pub fn to_path(&self) -> PathBuf {
+        match self.0.strip_prefix(MARKER) {
+            Some(escaped) => decode(escaped),
+            None => plain(&self.0),
+        }
+    }
+}
+
+impl From<&Path> for DocId {
+    fn from(path: &Path) -> Self {
+        Self::new(path)
+    }
+}
+
+/// The path, for people: names that are not text show replacement characters
+impl fmt::Display for DocId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_path().display())
+    }
+}
+
+/// The key `path` is saved under
+pub fn doc_key(path: &Path) -> String {
+    DocId::new(path).into_string()
+}
+
+/// The path saved under `key`
+pub fn doc_path(key: &str) -> PathBuf {
+    DocId::from_key(key).to_path()
+}
+
+/// Whether `path` names a text document: a `.txt` extension in any case, as
+/// Windows scanners write INV_0042.TXT
+pub fn is_text_file(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("txt"))
+}
+
+/// `path` as Windows accepts it past 260 characters: absolute with the \\?\
+/// (or \\?\UNC\ for a share) prefix. Shorter paths, paths with the prefix and
+/// other platforms are left as they are; std's own file functions add it
+/// themselves, this is for the ones that do not (SQLite)
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    #[cfg(windows)]
+    {
+        use std::ffi::OsString;
+        use std::os::windows::ffi::{OsStrExt, OsStringExt};
+        const MAX_PATH: usize = 260;
+        let wide: Vec<u16> = path.as_os_str().encode_wide().collect();
+        if wide.len() < MAX_PATH || wide.starts_with(&[92, 92, 63, 92]) {
+            return Cow::Borrowed(path);
+        }
+        let Ok(absolute) = std::path::absolute(path) else { return Cow::Borrowed(path) };
+        let absolute: Vec<u16> = absolute.as_os_str().encode_wide().collect();
+        let long: Vec<u16> = match absolute.strip_prefix(&[92, 92]) {
+            Some(share) => r"\\?\UNC\".encode_utf16().chain(share.iter().copied()).collect(),
+            None => r"\\?\".encode_utf16().chain(absolute.iter().copied()).collect(),
+        };
+        Cow::Owned(PathBuf::from(OsString::from_wide(&long)))
+    }
+    #[cfg(not(windows))]
+    Cow::Borrowed(path)
+}
+
+#[cfg(unix)]
+fn encode(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    let bytes = path.as_os_str().as_bytes();
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return text.to_string();
+    }
+    let mut key = String::from(MARKER);
+    for chunk in bytes.utf8_chunks() {
+        key.push_str(&chunk.valid().replace('%', "%25"));
+        for byte in chunk.invalid() {
+            key.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    key
+}
+
+#[cfg(unix)]
+fn decode(escaped: &str) -> PathBuf {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut rest = escaped.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match (byte, tail.get(..2).and_then(|h| std::str::from_utf8(h).ok()).map(|h| u8::from_str_radix(h, 16))) {
+            (b'%', Some(Ok(value))) => {
+                bytes.push(value);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+#[cfg(unix)]
+fn plain(key: &str) -> PathBuf {
+    PathBuf::from(key)
+}
+
+#[cfg(windows)]
+fn encode(path: &Path) -> String {
+    use std::os::windows::ffi::OsStrExt;
+    if let Some(text) = path.to_str() {
+        return text.replace('\\', "/");
+    }
+    let mut key = String::from(MARKER);
+    for unit in char::decode_utf16(path.as_os_str().encode_wide()) {
+        match unit {
+            Ok('\\') => key.push('/'),
+            Ok('%') => key.push_str("%25"),
+            Ok(c) => key.push(c),
+            Err(e) => key.push_str(&format!("%u{:04X}", e.unpaired_surrogate())),
+        }
+    }
+    key
+}
+
+#[cfg(windows)]
+fn decode(escaped: &str) -> PathBuf {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+    let mut wide: Vec<u16> = Vec::with_capacity(escaped.len());
+    let mut rest = escaped;
+    while let Some(c) = rest.chars().next() {
+        let surrogate = rest.strip_prefix("%u").and_then(|r| r.get(..4)).and_then(|h| u16::from_str_radix(h, 16).ok());
+        let percent = rest.strip_prefix('%').and_then(|r| r.get(..2)).and_then(|h| u8::from_str_radix(h, 16).ok());
+        if let Some(unit) = surrogate {
+            wide.push(unit);
+            rest = &rest[6..];
+        } else if let Some(byte) = percent {
+            wide.push(byte as u16);
+            rest = &rest[3..];
+        } else {
+            let unit = if c == '/' { '\\' } else { c };
+            wide.extend(unit.encode_utf16(&mut [0; 2]).iter());
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    PathBuf::from(OsString::from_wide(&wide))
+}
+
+#[cfg(windows)]
+fn plain(key: &str) -> PathBuf {
+    PathBuf::from(key.replace('/', "\\"))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn encode(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn decode(escaped: &str) -> PathBuf {
+    PathBuf::from(escaped)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn plain(key: &str) -> PathBuf {
+    PathBuf::from(key)
+}
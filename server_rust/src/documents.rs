@@ -0,0 +1,329 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// The documents list behind `GET /documents`: every document of every
+// collection with its state, tags and the fields read from its text (vendor,
+// date, amounts), filtered, sorted and cut into pages. The fields are
+// extracted once per file version and cached, so paging through thousands of
+// invoices does not re-read them; states, tags and invoice statuses come from the metadata
+// file as it is on disk now, not as it was at startup.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::docid::is_text_file;
+use crate::fold::{contains_folded, same_folded};
+use crate::lifecycle::{self, InvoiceStatus};
+use crate::metadata::{DocumentState, Metadata, Note};
+use crate::records::InvoiceRecord;
+use crate::tenants::Tenant;
+use crate::versions::VERSION_GRAPH;
+use crate::{collections, get_cached_content, Collection};
+
+/// Page size when none is asked for
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// Largest page served
+pub const MAX_PAGE_SIZE: usize = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentStatus {
+    Active,
+    /// Replaced by a correction; still retrieved, left out of totals
+    Superseded,
+    Archived,
+    Deleted,
+}
+
+impl DocumentStatus {
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s.to_lowercase().as_str() {
+            "active" => DocumentStatus::Active,
+            "superseded" => DocumentStatus::Superseded,
+            "archived" => DocumentStatus::Archived,
+            "deleted" => DocumentStatus::Deleted,
+            _ => anyhow::bail!("Unknown status '{}'. Valid values: active, superseded, archived, deleted, all", s),
+        })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentStatus::Active => "active",
+            DocumentStatus::Superseded => "superseded",
+            DocumentStatus::Archived => "archived",
+            DocumentStatus::Deleted => "deleted",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DocumentSummary {
+    pub collection: String,
+    pub file: String,
+    pub status: DocumentStatus,
+    /// Accounts-payable status
+    pub invoice_status: InvoiceStatus,
+    pub tags: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<Note>,
+    pub bytes: u64,
+    /// Unix time of the last change on disk
+    pub modified: u64,
+    #[serde(flatten)]
+    pub fields: ExtractedFields,
+}
+
+/// What `InvoiceRecord` reads from the text
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ExtractedFields {
+    pub id: Option<String>,
+    pub vendor: Option<String>,
+    /// ISO date (YYYY-MM-DD)
+    pub date: Option<String>,
+    pub currency: Option<String>,
+    pub net: Option<Decimal>,
+    pub tax: Option<Decimal>,
+    pub gross: Option<Decimal>,
+}
+
+impl ExtractedFields {
+    fn matches_text(&self, needle: &str) -> bool {
+        [&self.id, &self.vendor, &self.date, &self.currency]
+            .into_iter()
+            .flatten()
+            .chain(self.gross.map(|g| g.to_string()).as_ref())
+            .any(|v| contains_folded(v, needle))
+    }
+}
+
+/// Size, modification time and the fields extracted at that point
+type CachedFields = (u64, u64, ExtractedFields);
+
+/// Extracted fields by path, valid while size and modification time are unchanged
+static FIELD_CACHE: Lazy<Mutex<HashMap<PathBuf, CachedFields>>> = Lazy::new(Default::default);
+
+fn fields(collection: &str, path: &Path, bytes: u64, modified: u64) -> ExtractedFields {
+    if let Some((b, m, fields)) = FIELD_CACHE.lock().unwrap().get(path)
+        && (*b, *m) == (bytes, modified)
+    {
+        return fields.clone();
+    }
+    let fields = match get_cached_content(path) {
+        Ok(text) => {
+            let record = InvoiceRecord::from_text(collection, path, &text);
+            ExtractedFields {
+                id: record.id,
+                vendor: record.vendor,
+                date: record.date,
+                currency: record.currency,
+                net: record.net,
+                tax: record.tax,
+                gross: record.gross,
+            }
+        }
+        Err(_) => ExtractedFields::default(),
+    };
+    FIELD_CACHE.lock().unwrap().insert(path.to_path_buf(), (bytes, modified, fields.clone()));
+    fields
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DocumentFilter {
+    pub collection: Option<String>,
+    /// Substring of the vendor name, any case
+    pub vendor: Option<String>,
+    /// Dates from this one on (YYYY-MM-DD, or a prefix such as 2025-03)
+    pub from: Option<String>,
+    /// Dates up to this one, inclusive (YYYY-MM-DD, or a prefix)
+    pub to: Option<String>,
+    pub tag: Option<String>,
+    /// None for every status
+    pub status: Option<DocumentStatus>,
+    /// Accounts-payable statuses; empty for all
+    pub invoice_status: Vec<InvoiceStatus>,
+    /// Text searched in the number, vendor, date, currency, total and notes
+    pub text: Option<String>,
+    /// Only this tenant's collections (without one, only collections no tenant owns)
+    pub tenant: Option<Tenant>,
+}
+
+impl DocumentFilter {
+    fn accepts(&self, doc: &DocumentSummary) -> bool {
+        let date = doc.fields.date.as_deref();
+        self.status.is_none_or(|s| s == doc.status)
+            && lifecycle::selected(&self.invoice_status, doc.invoice_status)
+            && self.vendor.as_ref().is_none_or(|v| {
+                doc.fields.vendor.as_ref().is_some_and(|dv| contains_folded(dv, v))
+            })
+            && self.from.as_deref().is_none_or(|from| date.is_some_and(|d| d >= from))
+            // "2025-03" as the end includes all of March
+            && self.to.as_deref().is_none_or(|to| date.is_some_and(|d| d <= to || d.starts_with(to)))
+            && self.tag.as_ref().is_none_or(|t| doc.tags.iter().any(|tag| same_folded(tag, t)))
+            && self.text.as_ref().is_none_or(|t| {
+                contains_folded(&doc.file, t)
+                    || doc.fields.matches_text(t)
+                    || doc.notes.iter().any(|n| contains_folded(&n.text, t))
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    #[default]
+    File,
+    Date,
+    Vendor,
+    Gross,
+    Modified,
+    Collection,
+}
+
+/// Sort order such as "date" or "-gross" (descending)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Sort {
+    pub fn parse(s: &str) -> Result<Self> {
+        let (descending, name) = match s.strip_prefix('-') {
+            Some(name) => (true, name),
+            None => (false, s),
+        };
+        let key = match name {
+            "file" => SortKey::File,
+            "date" => SortKey::Date,
+            "vendor" => SortKey::Vendor,
+            "gross" | "total" => SortKey::Gross,
+            "modified" => SortKey::Modified,
+            "collection" => SortKey::Collection,
+            _ => anyhow::bail!(
+                "Unknown sort '{}'. Valid values: file, date, vendor, gross, modified, collection (prefix - for descending)",
+                s
+            ),
+        };
+        Ok(Self { key, descending })
+    }
+
+    fn apply(&self, docs: &mut [DocumentSummary]) {
+        let directed = |o: Ordering| if self.descending { o.reverse() } else { o };
+        docs.sort_by(|a, b| {
+            let (fa, fb) = (&a.fields, &b.fields);
+            let order = match self.key {
+                SortKey::File => directed(a.file.cmp(&b.file)),
+                SortKey::Collection => directed(a.collection.cmp(&b.collection)),
+                SortKey::Modified => directed(a.modified.cmp(&b.modified)),
+                SortKey::Date => present_first(fa.date.as_ref(), fb.date.as_ref(), directed),
+                SortKey::Vendor => present_first(
+                    fa.vendor.as_ref().map(|v| v.to_lowercase()),
+                    fb.vendor.as_ref().map(|v| v.to_lowercase()),
+                    directed,
+                ),
+                SortKey::Gross => present_first(fa.gross, fb.gross, directed),
+            };
+            order.then_with(|| a.collection.cmp(&b.collection)).then_with(|| a.file.cmp(&b.file))
+        });
+    }
+}
+
+/// Documents without the field go last, whichever the direction
+fn present_first<T: Ord>(a: Option<T>, b: Option<T>, directed: impl Fn(Ordering) -> Ordering) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => directed(a.cmp(&b)),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    }
+}
+
+impl fmt::Display for Sort {
+    /// The form `parse` reads
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.key {
+            SortKey::File => "file",
+            SortKey::Date => "date",
+            SortKey::Vendor => "vendor",
+            SortKey::Gross => "gross",
+            SortKey::Modified => "modified",
+            SortKey::Collection => "collection",
+        };
+        write!(f, "{}{}", if self.descending { "-" } else { "" }, name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DocumentPage {
+    /// Documents matching the filter, on all pages
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub documents: Vec<DocumentSummary>,
+}
+
+fn unix(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// State, tags and extracted fields of one document of `collection`; None if it cannot be found
+pub fn summarize(collection: &Collection, path: &Path, metadata: &Metadata) -> Option<DocumentSummary> {
+    let stat = fs::metadata(path).ok()?;
+    let meta = metadata.get(path);
+    let status = match meta.and_then(|m| m.tombstone.as_ref()).map(|t| t.state) {
+        Some(DocumentState::Archived) => DocumentStatus::Archived,
+        Some(DocumentState::Deleted) => DocumentStatus::Deleted,
+        None if VERSION_GRAPH.is_superseded(path) => DocumentStatus::Superseded,
+        None => DocumentStatus::Active,
+    };
+    let modified = stat.modified().map(unix).unwrap_or_default();
+    Some(DocumentSummary {
+        collection: collection.base_name().to_string(),
+        file: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        status,
+        invoice_status: metadata.status(path),
+        tags: meta.map(|m| m.tags.clone()).unwrap_or_default(),
+        notes: meta.map(|m| m.notes.clone()).unwrap_or_default(),
+        bytes: stat.len(),
+        modified,
+        fields: fields(&collection.name, path, stat.len(), modified),
+    })
+}
+
+/// One page of the documents matching `filter`, in `sort` order
+pub fn list_documents(filter: &DocumentFilter, sort: Sort, offset: usize, limit: usize) -> Result<DocumentPage> {
+    let metadata = Metadata::load()?;
+    let mut docs = Vec::new();
+    for collection in collections() {
+        if collection.tenant != filter.tenant.as_ref().map(|t| t.name.clone()) {
+            continue;
+        }
+        if filter.collection.as_ref().is_some_and(|name| !collection.matches(name)) {
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(&collection.folder) else { continue };
+        for path in entries.flatten().map(|e| e.path()) {
+            if !is_text_file(&path) {
+                continue;
+            }
+            docs.extend(summarize(collection, &path, &metadata));
+        }
+    }
+
+    docs.retain(|d| filter.accepts(d));
+    sort.apply(&mut docs);
+    let limit = limit.clamp(1, MAX_PAGE_SIZE);
+    let total = docs.len();
+    let documents = docs.into_iter().skip(offset).take(limit).collect();
+    Ok(DocumentPage { total, offset, limit, documents })
+}
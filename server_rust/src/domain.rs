@@ -0,0 +1,288 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Document domains: what kind of documents a collection holds, and with it
+// the persona of the prompt, the answer schema and the checks run on the
+// answers. The built-in collections each have one (invoices, employment,
+// support, knowledge); `[[domain]]` entries in the config file add more
+// (receipts, delivery notes...) or change a built-in one, and a collection
+// picks its domain with `domain = "..."`. The collection's own `instruction`
+// and `vat_check` still win over its domain's.
+//
+// `receipts` extracts merchant, date, total and VAT and picks an expense
+// category from the `[expenses]` taxonomy (see receipts.rs).
+//
+// `contracts` is the clause mode: parties, term, renewal, termination notice
+// and liability cap, each clause quoted and cited down to the chunk, and the
+// quote checked against the cited chunk. A query can switch to it (or any
+// other domain) with --domain, whatever its collection's domain is.
+
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::chunking::{chunk_document, Citation};
+use crate::config::Config;
+use crate::data::{Category, ALL_CATEGORIES};
+use crate::receipts::{ExpenseConfig, RECEIPTS_DOMAIN};
+use crate::records::parse_date;
+use crate::warnings::{Warning, WarningCode};
+
+/// A check run on the documents or the answers of a domain's collections
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Validator {
+    /// Invoice tax consistency (net + VAT = gross); sets the collection's `vat_check`
+    Vat,
+    /// Answer fields named "*date" must be dates
+    Dates,
+    /// Answer fields for amounts (total, subtotal, amount, vat, tax) must be numbers
+    Amounts,
+    /// Clause quotes must appear in the chunk they cite
+    Clauses,
+}
+
+/// `[[domain]]` entry of the config file
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct DomainConfig {
+    pub name: String,
+    /// Persona and extraction instructions for the prompt
+    pub instruction: Option<String>,
+    /// JSON Schema file the answers must follow (a query's --schema wins)
+    pub schema: Option<PathBuf>,
+    pub validators: Option<Vec<Validator>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Domain {
+    pub name: String,
+    pub instruction: String,
+    pub schema: Option<Value>,
+    pub validators: Vec<Validator>,
+}
+
+/// Name of a built-in collection's domain; the employment contracts are not
+/// in the clause mode unless asked for
+pub(crate) fn category_domain(cat: &Category) -> &'static str {
+    match cat {
+        Category::EmploymentContracts => "employment",
+        _ => cat.api_value(),
+    }
+}
+
+const CLAUSE_INSTRUCTION: &str = "You are a contract analyst. Find the clauses that set the parties, the term, \
+renewal, the termination notice and the liability cap. For each clause give a short summary, the exact wording \
+as \"quote\" (copied verbatim from one chunk) and the chunk it is in as \"citation\". Use null for a clause \
+the contract does not have; never infer one.";
+
+fn clause_schema() -> Value {
+    let clause = json!({
+        "type": ["object", "null"],
+        "required": ["summary", "quote", "citation"],
+        "properties": {
+            "summary": {"type": "string"},
+            "quote": {"type": "string"},
+            "citation": {
+                "type": "object",
+                "required": ["file", "chunk"],
+                "properties": {
+                    "file": {"type": "string"},
+                    "page": {"type": "integer"},
+                    "chunk": {"type": "string"}
+                }
+            }
+        }
+    });
+    json!({
+        "type": "object",
+        "required": ["parties", "term", "renewal", "termination_notice", "liability_cap"],
+        "properties": {
+            "parties": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["name"],
+                    "properties": {"name": {"type": "string"}, "role": {"type": "string"}}
+                }
+            },
+            "term": clause,
+            "renewal": clause,
+            "termination_notice": clause,
+            "liability_cap": clause
+        }
+    })
+}
+
+impl Domain {
+    fn from_category(cat: &Category) -> Self {
+        let validators = match cat {
+            Category::Invoices => vec![Validator::Vat, Validator::Dates, Validator::Amounts],
+            Category::EmploymentContracts => vec![Validator::Dates],
+            Category::CustomerSupport | Category::KnowledgeBase => Vec::new(),
+        };
+        Self {
+            name: category_domain(cat).to_string(),
+            instruction: cat.ai_instruction().to_string(),
+            schema: None,
+            validators,
+        }
+    }
+
+    fn contracts() -> Self {
+        Self {
+            name: "contracts".to_string(),
+            instruction: CLAUSE_INSTRUCTION.to_string(),
+            schema: Some(clause_schema()),
+            validators: vec![Validator::Dates, Validator::Clauses],
+        }
+    }
+
+    fn receipts(expenses: &ExpenseConfig) -> Self {
+        Self {
+            name: RECEIPTS_DOMAIN.to_string(),
+            instruction: expenses.instruction(),
+            schema: Some(expenses.schema()),
+            validators: vec![Validator::Dates, Validator::Amounts],
+        }
+    }
+
+    fn apply(&mut self, cfg: &DomainConfig) -> anyhow::Result<()> {
+        if let Some(instruction) = &cfg.instruction {
+            self.instruction = instruction.clone();
+        }
+        if let Some(path) = &cfg.schema {
+            let text = fs::read_to_string(path).with_context(|| format!("Failed to read the schema of domain '{}': {}", self.name, path.display()))?;
+            let schema = serde_json::from_str(&text).with_context(|| format!("{} is not valid JSON", path.display()))?;
+            self.schema = Some(schema);
+        }
+        if let Some(validators) = &cfg.validators {
+            self.validators = validators.clone();
+        }
+        Ok(())
+    }
+}
+
+/// Built-in domains merged with the `[[domain]]` entries
+pub fn domains_from_config(config: &Config) -> anyhow::Result<Vec<Domain>> {
+    let mut domains: Vec<Domain> = ALL_CATEGORIES.iter().map(Domain::from_category).collect();
+    domains.push(Domain::contracts());
+    domains.push(Domain::receipts(&config.expenses));
+    for cfg in &config.domains {
+        let name = cfg.name.trim().to_lowercase();
+        if name.is_empty() {
+            anyhow::bail!("Every [[domain]] in the config file needs a name");
+        }
+        match domains.iter_mut().find(|d| d.name == name) {
+            Some(existing) => existing.apply(cfg)?,
+            None => {
+                let mut domain = Domain {
+                    instruction: format!("You are a precise assistant for {} documents. Extract and answer exactly as written.", name),
+                    name,
+                    schema: None,
+                    validators: Vec::new(),
+                };
+                domain.apply(cfg)?;
+                domains.push(domain);
+            }
+        }
+    }
+    Ok(domains)
+}
+
+/// A domain by name, as configured
+pub fn find_domain(config: &Config, name: &str) -> anyhow::Result<Domain> {
+    let name = name.trim().to_lowercase();
+    let domains = domains_from_config(config)?;
+    let known: Vec<String> = domains.iter().map(|d| d.name.clone()).collect();
+    domains
+        .into_iter()
+        .find(|d| d.name == name)
+        .ok_or_else(|| anyhow::anyhow!("Unknown domain '{}'. Valid values: {}", name, known.join(", ")))
+}
+
+fn amount(value: &Value) -> bool {
+    match value {
+        Value::Number(_) | Value::Null => true,
+        Value::String(s) => {
+            let digits: String = s.chars().filter(|c| c.is_ascii_digit() || matches!(c, '.' | '-')).collect();
+            !digits.is_empty() && digits.parse::<f64>().is_ok()
+        }
+        _ => false,
+    }
+}
+
+fn check(validators: &[Validator], key: &str, value: &Value, path: &str, warnings: &mut Vec<Warning>) {
+    match value {
+        Value::Object(fields) => {
+            for (k, v) in fields {
+                check(validators, k, v, &format!("{}.{}", path, k), warnings);
+            }
+            return;
+        }
+        Value::Array(items) => {
+            for (i, v) in items.iter().enumerate() {
+                check(validators, key, v, &format!("{}[{}]", path, i), warnings);
+            }
+            return;
+        }
+        _ => {}
+    }
+    let key = key.to_lowercase();
+    if validators.contains(&Validator::Dates) && key.ends_with("date")
+        && let Some(text) = value.as_str().filter(|t| parse_date(t).is_none())
+    {
+        warnings.push(Warning::new(WarningCode::InvalidField, format!("{}: '{}' is not a date", path, text)));
+    }
+    let is_amount = ["total", "subtotal", "amount", "vat", "tax"].iter().any(|k| key == *k || key.ends_with(&format!("_{}", k)));
+    if validators.contains(&Validator::Amounts) && is_amount && !amount(value) {
+        warnings.push(Warning::new(WarningCode::InvalidField, format!("{}: {} is not an amount", path, value)));
+    }
+}
+
+/// Warnings about answer fields that fail the domain's checks
+pub fn check_answer(validators: &[Validator], answer: &Value) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    if validators.iter().any(|v| matches!(v, Validator::Dates | Validator::Amounts)) {
+        check(validators, "", answer, "$", &mut warnings);
+    }
+    warnings
+}
+
+fn normalized(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Warnings about clauses whose quote is not in the chunk they cite; `documents`
+/// are the (file name, text) pairs the answer was given
+pub fn check_clauses(answer: &Value, documents: &[(String, String)]) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let Some(fields) = answer.as_object() else {
+        return warnings;
+    };
+    for (name, clause) in fields.iter().filter(|(_, v)| v.get("quote").is_some()) {
+        let quote = normalized(clause["quote"].as_str().unwrap_or_default());
+        let ungrounded = |reason: String| Warning::new(WarningCode::UngroundedClause, format!("{}: {}", name, reason));
+        let Some(citation) = clause.get("citation").and_then(Citation::from_value) else {
+            warnings.push(ungrounded("no citation".to_string()));
+            continue;
+        };
+        let Some((_, text)) = documents.iter().find(|(file, _)| *file == citation.file) else {
+            warnings.push(ungrounded(format!("cites '{}', which was not retrieved", citation.file)));
+            continue;
+        };
+        let chunks = chunk_document(text);
+        let cited: Vec<_> = match &citation.chunk {
+            Some(id) => chunks.iter().filter(|c| c.id == *id).collect(),
+            None => chunks.iter().collect(),
+        };
+        if cited.is_empty() {
+            let id = citation.chunk.unwrap_or_default();
+            warnings.push(ungrounded(format!("chunk {} is not in the document", id)).file(&citation.file));
+        } else if !quote.is_empty() && !cited.iter().any(|c| normalized(&text[c.start..c.end]).contains(&quote)) {
+            warnings.push(ungrounded("the quote is not in the cited chunk".to_string()).file(&citation.file));
+        }
+    }
+    warnings
+}
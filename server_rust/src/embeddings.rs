@@ -0,0 +1,492 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Vector retrieval: document embeddings from Ollama's /api/embed, or computed
+// in this process with fastembed (`--embed-backend local`, feature
+// `local-embeddings`), stored in the store ("embeddings") and refreshed by
+// `index` when a document changes. Questions are embedded by the backend and
+// model the index was made with.
+//
+// Documents are embedded chunk by chunk (see chunking.rs) and a document is as
+// similar to a question as its best chunk. Refreshing is a pipeline like
+// ingest.rs: a blocking reader chunks the changed documents into batches of
+// `embed_batch` inputs on a bounded channel, `embed_concurrency` workers send
+// one batch per request, and finished documents are collected as their last
+// chunk comes back. The reader waits while the workers are behind, so memory
+// stays bounded however big the corpus.
+//
+// Vectors are stored as int8 with a scale each (see `int8`), and big indexes
+// get approximate nearest-neighbour lists (ann.rs) so a query only scores the
+// chunks near it.
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+
+use crate::ann::{self, Ivf};
+use crate::chunking::Chunker;
+use crate::docid::{doc_key, doc_path};
+use crate::hosts;
+use crate::indexer::INVERTED_INDEX;
+use crate::lock::{lock_index, LockMode};
+use crate::store::{self, store};
+use crate::{get_cached_content, Collection};
+
+pub use crate::retrieval::{EmbedBackend, EmbedOptions, DEFAULT_EMBED_MODEL};
+
+/// Characters of a chunk sent for embedding (longer chunks are cut)
+const MAX_EMBED_CHARS: usize = 8_000;
+
+/// Fewest documents of a collection the ANN lists must find before a query trusts them over a full scan
+const MIN_ANN_RESULTS: usize = 20;
+
+/// Vectors on disk: "i8:<scale>:<base64>", one signed byte per component times the scale, a
+/// quarter of their size as f32 and a tenth of JSON numbers. Arrays of numbers (as written
+/// before) are still read.
+pub(crate) mod int8 {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine as _;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn encode(vector: &[f32]) -> String {
+        let max = vector.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+        let scale = if max > 0.0 { max / 127.0 } else { 1.0 };
+        let bytes: Vec<u8> = vector.iter().map(|x| (x / scale).round().clamp(-127.0, 127.0) as i8 as u8).collect();
+        format!("i8:{}:{}", scale, STANDARD.encode(bytes))
+    }
+
+    pub fn decode(text: &str) -> Result<Vec<f32>, String> {
+        let (scale, data) = text.strip_prefix("i8:").and_then(|t| t.split_once(':')).ok_or("not an i8 vector")?;
+        let scale: f32 = scale.parse().map_err(|_| format!("invalid vector scale '{}'", scale))?;
+        let bytes = STANDARD.decode(data).map_err(|e| format!("invalid vector: {}", e))?;
+        Ok(bytes.into_iter().map(|b| b as i8 as f32 * scale).collect())
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Plain(Vec<f32>),
+        Packed(String),
+    }
+
+    impl Stored {
+        fn into_vector<E: Error>(self) -> Result<Vec<f32>, E> {
+            match self {
+                Stored::Plain(vector) => Ok(vector),
+                Stored::Packed(text) => decode(&text).map_err(E::custom),
+            }
+        }
+    }
+
+    pub fn serialize<S: Serializer>(vector: &[f32], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode(vector))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<f32>, D::Error> {
+        Stored::deserialize(deserializer)?.into_vector()
+    }
+
+    pub fn serialize_all<S: Serializer>(vectors: &[Vec<f32>], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(vectors.iter().map(|v| encode(v)))
+    }
+
+    pub fn deserialize_all<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Vec<f32>>, D::Error> {
+        Vec::<Stored>::deserialize(deserializer)?.into_iter().map(Stored::into_vector).collect()
+    }
+}
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChunkVector {
+    /// Chunk id ("p1c2")
+    pub id: String,
+    #[serde(with = "int8")]
+    pub vector: Vec<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EmbeddedDoc {
+    /// SHA-256 of the text the vectors were computed from
+    pub hash: String,
+    /// Mean of the (normalised) chunk vectors
+    #[serde(with = "int8")]
+    pub vector: Vec<f32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<ChunkVector>,
+}
+
+impl EmbeddedDoc {
+    fn from_chunks(hash: String, chunks: Vec<ChunkVector>) -> Self {
+        let mut vector = vec![0.0; chunks.first().map_or(0, |c| c.vector.len())];
+        for chunk in &chunks {
+            let norm = chunk.vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                for (sum, x) in vector.iter_mut().zip(&chunk.vector) {
+                    *sum += x / norm;
+                }
+            }
+        }
+        Self { hash, vector, chunks }
+    }
+
+    /// Cosine similarity of the best chunk to `query` (the document vector for embeddings made before chunking)
+    pub fn similarity(&self, query: &[f32]) -> f32 {
+        if self.chunks.is_empty() {
+            return cosine(query, &self.vector);
+        }
+        self.chunks.iter().map(|c| cosine(query, &c.vector)).fold(f32::MIN, f32::max)
+    }
+
+    /// Vectors of the chunks, or the document vector for embeddings made before chunking
+    fn vectors(&self) -> Vec<&[f32]> {
+        if self.chunks.is_empty() {
+            Some(self.vector.as_slice()).filter(|v| !v.is_empty()).into_iter().collect()
+        } else {
+            self.chunks.iter().map(|c| c.vector.as_slice()).collect()
+        }
+    }
+}
+
+/// One chunk on its way to the model
+struct Piece {
+    key: String,
+    hash: String,
+    /// Position of the chunk in its document, and the document's number of chunks
+    n: usize,
+    of: usize,
+    id: String,
+    text: String,
+}
+
+/// Read and chunk every document of `paths` whose text is not the one in `known` (blocking), sending
+/// the chunks in batches. Returns the documents without any text, which need no request.
+fn read_batches(
+    paths: Vec<PathBuf>,
+    known: HashMap<String, String>,
+    batch: usize,
+    tx: mpsc::Sender<Vec<Piece>>,
+) -> Vec<(String, String)> {
+    let mut empty = Vec::new();
+    let mut pieces = Vec::with_capacity(batch);
+    for path in paths {
+        let Ok(text) = get_cached_content(&path) else { continue };
+        let key = doc_key(&path);
+        let h = hash(&text);
+        if known.get(&key) == Some(&h) {
+            continue;
+        }
+        let mut chunker = Chunker::new();
+        for line in text.split_inclusive('\n') {
+            chunker.push_line(line);
+        }
+        let chunks = chunker.finish();
+        if chunks.is_empty() {
+            empty.push((key, h));
+            continue;
+        }
+        let of = chunks.len();
+        for (n, (chunk, text)) in chunks.into_iter().enumerate() {
+            pieces.push(Piece { key: key.clone(), hash: h.clone(), n, of, id: chunk.id, text: embed_text(&text) });
+            // Blocks while the workers are behind: this is the backpressure
+            if pieces.len() == batch && tx.blocking_send(std::mem::replace(&mut pieces, Vec::with_capacity(batch))).is_err() {
+                return empty;
+            }
+        }
+    }
+    if !pieces.is_empty() {
+        let _ = tx.blocking_send(pieces);
+    }
+    empty
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct EmbeddingIndex {
+    pub backend: EmbedBackend,
+    pub model: String,
+    /// Keyed by document path, '/'-separated
+    pub documents: BTreeMap<String, EmbeddedDoc>,
+    /// Nearest-neighbour lists over the vectors of `documents`, dropped whenever these change
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ann: Option<Ivf>,
+}
+
+/// Embeddings as stored on disk when first needed
+pub static EMBEDDING_INDEX: Lazy<EmbeddingIndex> = Lazy::new(|| {
+    lock_index(LockMode::Shared).and_then(|_lock| EmbeddingIndex::load()).unwrap_or_else(|e| {
+        eprintln!("WARNING: {:#}; vector retrieval disabled", e);
+        EmbeddingIndex::default()
+    })
+});
+
+/// Embed several texts in one request
+pub async fn embed(model: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+    let (res, _lease) = hosts::post(&hosts::client(), model, "/api/embed", &EmbedRequest { model, input: inputs }).await?;
+
+    let status = res.status();
+    if !status.is_success() {
+        let text = res.text().await.unwrap_or_default();
+        anyhow::bail!("Ollama embedding error {}: {}", status, text);
+    }
+
+    let parsed: EmbedResponse = res.json().await.context("Invalid Ollama embedding response")?;
+    if parsed.embeddings.len() != inputs.len() {
+        anyhow::bail!("Ollama returned {} embeddings for {} inputs", parsed.embeddings.len(), inputs.len());
+    }
+    Ok(parsed.embeddings)
+}
+
+/// Embed several texts with `backend`
+pub async fn embed_with(backend: EmbedBackend, model: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+    match backend {
+        EmbedBackend::Ollama => embed(model, inputs).await,
+        #[cfg(feature = "local-embeddings")]
+        EmbedBackend::Local => local::embed(model, inputs).await,
+        #[cfg(not(feature = "local-embeddings"))]
+        EmbedBackend::Local => anyhow::bail!("Local embeddings need doc-ai-server built with the `local-embeddings` feature"),
+    }
+}
+
+#[cfg(feature = "local-embeddings")]
+mod local {
+    use anyhow::{Context, Result};
+    use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+    use once_cell::sync::OnceCell;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    use crate::store::STATE_DIR;
+
+    /// Models by the names `embed_model` may use (Ollama's, where it has the model)
+    const MODELS: &[(&str, EmbeddingModel)] = &[
+        ("nomic-embed-text", EmbeddingModel::NomicEmbedTextV15),
+        ("all-minilm", EmbeddingModel::AllMiniLML6V2),
+        ("bge-small-en-v1.5", EmbeddingModel::BGESmallENV15),
+        ("bge-base-en-v1.5", EmbeddingModel::BGEBaseENV15),
+        ("multilingual-e5-small", EmbeddingModel::MultilingualE5Small),
+    ];
+
+    /// The model loaded, with its name; one per process
+    static LOADED: OnceCell<(String, Mutex<TextEmbedding>)> = OnceCell::new();
+
+    /// Load `name` on first use, downloading it into .doc-ai/models the first time
+    fn model(name: &str) -> Result<&'static Mutex<TextEmbedding>> {
+        let (loaded, model) = LOADED.get_or_try_init(|| -> Result<_> {
+            let Some((_, kind)) = MODELS.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) else {
+                let names: Vec<&str> = MODELS.iter().map(|(n, _)| *n).collect();
+                anyhow::bail!("Unknown local embedding model '{}'. Valid values: {}", name, names.join(", "));
+            };
+            let options = InitOptions::new(kind.clone())
+                .with_cache_dir(PathBuf::from(STATE_DIR).join("models"))
+                .with_show_download_progress(false);
+            let model = TextEmbedding::try_new(options).with_context(|| format!("Cannot load the local embedding model '{}'", name))?;
+            Ok((name.to_string(), Mutex::new(model)))
+        })?;
+        if !loaded.eq_ignore_ascii_case(name) {
+            anyhow::bail!("The local embedding model '{}' is already loaded; use one model per process", loaded);
+        }
+        Ok(model)
+    }
+
+    pub async fn embed(name: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        let (name, inputs) = (name.to_string(), inputs.to_vec());
+        // CPU-bound: off the async workers
+        tokio::task::spawn_blocking(move || {
+            let model = model(&name)?;
+            model.lock().unwrap_or_else(|e| e.into_inner()).embed(inputs, None).context("Local embedding failed")
+        })
+        .await?
+    }
+}
+
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denom = norm(a) * norm(b);
+    if denom == 0.0 { 0.0 } else { dot / denom }
+}
+
+fn hash(text: &str) -> String {
+    Sha256::digest(text.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn embed_text(text: &str) -> String {
+    text.chars().take(MAX_EMBED_CHARS).collect()
+}
+
+impl EmbeddingIndex {
+    pub fn load() -> Result<Self> {
+        match store().load(store::EMBEDDINGS)? {
+            Some(text) => serde_json::from_str(&text).with_context(|| format!("Invalid embeddings in {}", store().describe())),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        store().save(store::EMBEDDINGS, &serde_json::to_string(self)?)
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&EmbeddedDoc> {
+        self.documents.get(&doc_key(path))
+    }
+
+    /// Embed every indexed document that is new or changed, and forget the ones that left the
+    /// index; returns how many were embedded
+    pub async fn update(&mut self, model: &str, options: EmbedOptions) -> Result<usize> {
+        let mut paths: Vec<PathBuf> = INVERTED_INDEX.values().flatten().cloned().collect();
+        paths.sort();
+        paths.dedup();
+
+        let live: HashSet<String> = paths.iter().map(|p| doc_key(p)).collect();
+        let before = self.documents.len();
+        self.documents.retain(|k, _| live.contains(k));
+        if self.documents.len() != before {
+            self.ann = None;
+        }
+        let embedded = self.embed_files(model, paths, options).await?;
+        if !options.ann {
+            self.ann = None;
+        } else if self.ann.is_none() {
+            self.build_ann();
+        }
+        Ok(embedded)
+    }
+
+    /// (Re)build the nearest-neighbour lists; none for fewer than `ann::MIN_VECTORS` vectors
+    pub fn build_ann(&mut self) {
+        let vectors: Vec<(ann::Entry, &[f32])> = self
+            .documents
+            .values()
+            .enumerate()
+            .flat_map(|(d, doc)| doc.vectors().into_iter().enumerate().map(move |(c, v)| ((d as u32, c as u32), v)))
+            .collect();
+        self.ann = Ivf::build(&vectors);
+    }
+
+    /// Documents of the collection with a vector in the lists nearest to `query`; `None` without
+    /// lists, or when they hold too few of the collection's documents (a full scan is then due)
+    pub fn nearest(&self, query: &[f32], collection: &Collection) -> Option<Vec<PathBuf>> {
+        let ann = self.ann.as_ref()?;
+        let docs: Vec<(&String, &EmbeddedDoc)> = self.documents.iter().collect();
+        if ann.vectors != docs.iter().map(|(_, d)| d.vectors().len()).sum::<usize>() {
+            return None;
+        }
+        let mut found: Vec<u32> = ann.candidates(query, ann::PROBE).into_iter().map(|(d, _)| d).collect();
+        found.sort_unstable();
+        found.dedup();
+        let paths: Vec<PathBuf> = found
+            .into_iter()
+            .filter_map(|d| docs.get(d as usize))
+            .map(|(k, _)| doc_path(k))
+            .filter(|p| p.starts_with(&collection.folder))
+            .collect();
+        (paths.len() >= MIN_ANN_RESULTS).then_some(paths)
+    }
+
+    /// Embed those of `paths` that are new or changed; returns how many were embedded. On an error
+    /// the documents finished before it are kept, so a rerun only does the rest.
+    pub async fn embed_files(&mut self, model: &str, paths: Vec<PathBuf>, options: EmbedOptions) -> Result<usize> {
+        if self.model != model || self.backend != options.backend {
+            // Vectors from different models are not comparable
+            self.documents.clear();
+            self.ann = None;
+            self.model = model.to_string();
+            self.backend = options.backend;
+        }
+
+        let known: HashMap<String, String> = self.documents.iter().map(|(k, d)| (k.clone(), d.hash.clone())).collect();
+        let batch = options.batch.max(1);
+        let concurrency = options.concurrency.max(1);
+        let (batch_tx, batch_rx) = mpsc::channel::<Vec<Piece>>(concurrency);
+        let (done_tx, mut done_rx) = mpsc::channel::<Result<(Vec<Piece>, Vec<Vec<f32>>)>>(concurrency);
+
+        let reader = tokio::task::spawn_blocking(move || read_batches(paths, known, batch, batch_tx));
+
+        let batch_rx = Arc::new(Mutex::new(batch_rx));
+        for _ in 0..concurrency {
+            let batch_rx = Arc::clone(&batch_rx);
+            let done_tx = done_tx.clone();
+            let model = model.to_string();
+            let backend = options.backend;
+            tokio::spawn(async move {
+                loop {
+                    let Some(mut pieces) = batch_rx.lock().await.recv().await else { break };
+                    let inputs: Vec<String> = pieces.iter_mut().map(|p| std::mem::take(&mut p.text)).collect();
+                    let embedded = embed_with(backend, &model, &inputs).await.map(|vectors| (pieces, vectors));
+                    if done_tx.send(embedded).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(done_tx);
+
+        // Chunks come back in any order; a document is done with its last one
+        let mut partial: HashMap<String, Vec<(usize, ChunkVector)>> = HashMap::new();
+        let mut embedded = 0;
+        while let Some(done) = done_rx.recv().await {
+            // Dropping the receiver stops the workers, and with them the reader
+            let (pieces, vectors) = done?;
+            for (piece, vector) in pieces.into_iter().zip(vectors) {
+                let chunks = partial.entry(piece.key.clone()).or_default();
+                chunks.push((piece.n, ChunkVector { id: piece.id, vector }));
+                if chunks.len() == piece.of {
+                    let mut chunks = partial.remove(&piece.key).unwrap_or_default();
+                    chunks.sort_by_key(|(n, _)| *n);
+                    let chunks = chunks.into_iter().map(|(_, c)| c).collect();
+                    self.documents.insert(piece.key, EmbeddedDoc::from_chunks(piece.hash, chunks));
+                    self.ann = None;
+                    embedded += 1;
+                }
+            }
+        }
+
+        for (key, h) in reader.await? {
+            self.documents.insert(key, EmbeddedDoc { hash: h, vector: Vec::new(), chunks: Vec::new() });
+            self.ann = None;
+            embedded += 1;
+        }
+        Ok(embedded)
+    }
+
+    /// Similarity of every embedded document in the collection to `query`, best first
+    pub async fn rank(&self, query: &str, collection: &Collection) -> Result<Vec<(PathBuf, f32)>> {
+        if self.documents.is_empty() {
+            anyhow::bail!("No embeddings yet; run `index` with vector or hybrid retrieval configured");
+        }
+        let query_vector = embed_with(self.backend, &self.model, &[query.to_string()]).await?.swap_remove(0);
+
+        let mut scored: Vec<(PathBuf, f32)> = match self.nearest(&query_vector, collection) {
+            Some(paths) => paths
+                .into_iter()
+                .filter_map(|p| {
+                    let score = self.get(&p)?.similarity(&query_vector);
+                    Some((p, score))
+                })
+                .collect(),
+            None => self
+                .documents
+                .iter()
+                .map(|(k, doc)| (doc_path(k), doc.similarity(&query_vector)))
+                .filter(|(path, _)| path.starts_with(&collection.folder))
+                .collect(),
+        };
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(scored)
+    }
+}
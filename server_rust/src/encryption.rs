@@ -0,0 +1,200 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Optional encryption at rest for what doc-ai writes itself: the search
+// index, embeddings, document metadata and the local mirror of remote
+// collections. Files are sealed with ChaCha20-Poly1305 under a key kept in
+// the OS keyring, or derived (Argon2) from a passphrase in the environment.
+// Loading is transparent: sealed files start with a magic line and are
+// decrypted, plain files are read as they are (and sealed on their next save).
+
+#[cfg(feature = "encryption")]
+use anyhow::Context;
+use anyhow::Result;
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// First bytes of every sealed file
+const MAGIC: &[u8] = b"DOC-AI-SEALED-1\n";
+
+#[cfg(feature = "encryption")]
+const NONCE_BYTES: usize = 12;
+
+/// Salt for the passphrase key derivation, created on first use
+#[cfg(feature = "encryption")]
+const SALT_FILE: &str = ".doc-ai/salt";
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    /// A random key stored in the OS keyring (created on first use)
+    #[default]
+    Keyring,
+    /// Derived from the passphrase in `passphrase_env`
+    Passphrase,
+}
+
+/// `[encryption]` section of the config file
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    pub enabled: bool,
+    pub key: KeySource,
+    /// Environment variable holding the passphrase
+    pub passphrase_env: String,
+}
+
+impl Default for EncryptionConfig {
+    fn default() -> Self {
+        Self { enabled: false, key: KeySource::Keyring, passphrase_env: "DOC_AI_PASSPHRASE".to_string() }
+    }
+}
+
+/// Key for this process, set by `init_encryption` when encryption is enabled
+static KEY: OnceCell<[u8; 32]> = OnceCell::new();
+
+/// Load or create the key (first call wins); does nothing when encryption is off
+pub fn init_encryption(config: &EncryptionConfig) -> Result<()> {
+    if !config.enabled || KEY.get().is_some() {
+        return Ok(());
+    }
+    #[cfg(feature = "encryption")]
+    {
+        let key = match config.key {
+            KeySource::Keyring => keyring_key()?,
+            KeySource::Passphrase => passphrase_key(&config.passphrase_env)?,
+        };
+        let _ = KEY.set(key);
+        Ok(())
+    }
+    #[cfg(not(feature = "encryption"))]
+    anyhow::bail!("[encryption] is enabled but this build has no encryption support (enable the `encryption` feature)")
+}
+
+#[cfg(feature = "encryption")]
+fn keyring_key() -> Result<[u8; 32]> {
+    use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+
+    let entry = keyring::Entry::new(crate::secrets::KEYRING_SERVICE, "cache-encryption-key").context("OS keyring unavailable")?;
+    match entry.get_password() {
+        Ok(stored) => {
+            let bytes: Option<Vec<u8>> = (0..stored.len())
+                .step_by(2)
+                .map(|i| stored.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+                .collect();
+            bytes
+                .and_then(|b| b.try_into().ok())
+                .ok_or_else(|| anyhow::anyhow!("The encryption key in the keyring is damaged"))
+        }
+        Err(keyring::Error::NoEntry) => {
+            let mut key = [0u8; 32];
+            OsRng.fill_bytes(&mut key);
+            let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+            entry.set_password(&hex).context("Failed to store the encryption key in the keyring")?;
+            eprintln!("Created a new encryption key in the OS keyring");
+            Ok(key)
+        }
+        Err(e) => Err(e).context("Failed to read the encryption key from the keyring"),
+    }
+}
+
+#[cfg(feature = "encryption")]
+fn passphrase_key(env: &str) -> Result<[u8; 32]> {
+    use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+
+    let passphrase = std::env::var(env).with_context(|| format!("Encryption passphrase not set: {}", env))?;
+    let salt = match fs::read(SALT_FILE) {
+        Ok(salt) => salt,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let mut salt = vec![0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+            if let Some(dir) = Path::new(SALT_FILE).parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(SALT_FILE, &salt).with_context(|| format!("Failed to write {}", SALT_FILE))?;
+            salt
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", SALT_FILE)),
+    };
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+pub fn is_sealed(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Encrypt `bytes` when encryption is enabled; otherwise return them unchanged
+pub fn seal(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let Some(key) = KEY.get() else { return Ok(bytes) };
+    #[cfg(feature = "encryption")]
+    {
+        use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+        use chacha20poly1305::{ChaCha20Poly1305, Key};
+
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = cipher.encrypt(&nonce, bytes.as_slice()).map_err(|_| anyhow::anyhow!("Encryption failed"))?;
+        let mut out = Vec::with_capacity(MAGIC.len() + NONCE_BYTES + sealed.len());
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&sealed);
+        Ok(out)
+    }
+    #[cfg(not(feature = "encryption"))]
+    {
+        let _ = key;
+        Ok(bytes)
+    }
+}
+
+/// Decrypt sealed bytes; plain bytes are returned unchanged
+pub fn unseal(path: &Path, bytes: Vec<u8>) -> Result<Vec<u8>> {
+    if !is_sealed(&bytes) {
+        return Ok(bytes);
+    }
+    let Some(key) = KEY.get() else {
+        anyhow::bail!("{} is encrypted; enable [encryption] in the config file to read it", path.display())
+    };
+    #[cfg(feature = "encryption")]
+    {
+        use chacha20poly1305::aead::{Aead, KeyInit};
+        use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+        let body = &bytes[MAGIC.len()..];
+        if body.len() < NONCE_BYTES {
+            anyhow::bail!("{} is truncated", path.display());
+        }
+        let (nonce, sealed) = body.split_at(NONCE_BYTES);
+        ChaCha20Poly1305::new(Key::from_slice(key))
+            .decrypt(Nonce::from_slice(nonce), sealed)
+            .map_err(|_| anyhow::anyhow!("Cannot decrypt {}: wrong key or damaged file", path.display()))
+    }
+    #[cfg(not(feature = "encryption"))]
+    {
+        let _ = key;
+        anyhow::bail!("{} is encrypted but this build has no encryption support", path.display())
+    }
+}
+
+/// `fs::read`, decrypting sealed files (errors other than I/O come back as `InvalidData`)
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    let bytes = fs::read(path)?;
+    unseal(path, bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:#}", e)))
+}
+
+/// `fs::read_to_string`, decrypting sealed files
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    String::from_utf8(read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// `fs::write`, sealing the contents when encryption is enabled
+pub fn write(path: &Path, contents: impl Into<Vec<u8>>) -> io::Result<()> {
+    let sealed = seal(contents.into()).map_err(|e| io::Error::other(format!("{:#}", e)))?;
+    fs::write(path, sealed)
+}
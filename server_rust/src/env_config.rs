@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Configuration from `DOC_AI_*` environment variables, for containers that
+// should not need a config file baked into the image. Every config file key
+// has a variable: the part after the prefix, upper-cased, with `__` between
+// section and key:
+//
+//   DOC_AI_MODEL=llama3.2                     model = "llama3.2"
+//   DOC_AI_OPTIONS__TEMPERATURE=0.1           [options] temperature = 0.1
+//   DOC_AI_RETRIEVAL__MODE=hybrid             [retrieval] mode = "hybrid"
+//   DOC_AI_SCHEMAS__INVOICE=/schemas/inv.json [schemas] invoice = "..."
+//   DOC_AI_COLLECTION__PURCHASE_ORDERS__FOLDER=/data/po
+//                                             [[collection]] name = "purchase-orders", folder = "/data/po"
+//
+// Values are read as TOML when they parse as a number, boolean or array,
+// otherwise as a string. Precedence, highest first: command-line flags,
+// environment variables, the selected `[profile.<name>]`, the config file,
+// built-in defaults.
+
+use anyhow::Context;
+use toml::{Table, Value};
+
+pub const ENV_PREFIX: &str = "DOC_AI_";
+
+/// Top-level config file keys; other `DOC_AI_*` variables (flags, secrets) are not config
+const CONFIG_KEYS: &[&str] = &[
+    "ollama_url",
+    "model",
+    "api",
+    "default_collection",
+    "data_dir",
+    "options",
+    "locale",
+    "collection",
+    "schemas",
+    "reading",
+    "retrieval",
+    "imap",
+    "close",
+    "encryption",
+    "output_profiles",
+];
+
+/// Keys a `[profile.<name>]` can set, which the environment must override there too
+const PROFILE_KEYS: &[&str] = &["ollama_url", "model", "api", "default_collection", "options"];
+
+fn parse_value(raw: &str) -> Value {
+    match format!("v = {}", raw).parse::<Table>().ok().and_then(|mut t| t.remove("v")) {
+        Some(v @ (Value::Integer(_) | Value::Float(_) | Value::Boolean(_) | Value::Array(_))) => v,
+        _ => Value::String(raw.to_string()),
+    }
+}
+
+/// Collection names use dashes, which variable names cannot
+fn same_collection(name: &str, env_name: &str) -> bool {
+    name.to_lowercase().replace('-', "_") == env_name
+}
+
+/// Set `path` in `table`, creating the sections on the way
+fn set(table: &mut Table, path: &[&str], value: Value) -> anyhow::Result<()> {
+    let (key, sections) = path.split_last().context("empty key")?;
+    let mut table = table;
+    for section in sections {
+        table = table
+            .entry(section.to_string())
+            .or_insert_with(|| Value::Table(Table::new()))
+            .as_table_mut()
+            .with_context(|| format!("'{}' is not a section", section))?;
+    }
+    table.insert(key.to_string(), value);
+    Ok(())
+}
+
+/// Set `path` in the `[[collection]]` entry named `name`, appending it if `append` and missing
+fn set_collection(table: &mut Table, name: &str, path: &[&str], value: Value, append: bool) -> anyhow::Result<()> {
+    let entries = table.entry("collection").or_insert_with(|| Value::Array(Vec::new()));
+    let entries = entries.as_array_mut().context("'collection' is not a list of [[collection]] entries")?;
+    let mut found = false;
+    for entry in entries.iter_mut().filter_map(Value::as_table_mut) {
+        if entry.get("name").and_then(Value::as_str).is_some_and(|n| same_collection(n, name)) {
+            set(entry, path, value.clone())?;
+            found = true;
+        }
+    }
+    if !found && append {
+        let mut entry = Table::new();
+        entry.insert("name".to_string(), Value::String(name.replace('_', "-")));
+        set(&mut entry, path, value)?;
+        entries.push(Value::Table(entry));
+    }
+    Ok(())
+}
+
+/// Set one variable's value at `path`, in the top level and in the profiles that set it
+fn apply_one(table: &mut Table, path: &[&str], value: Value) -> anyhow::Result<()> {
+    let profiles: Vec<String> =
+        table.get("profile").and_then(Value::as_table).map(|p| p.keys().cloned().collect()).unwrap_or_default();
+    match path {
+        ["collection", collection, rest @ ..] if !rest.is_empty() => {
+            set_collection(table, collection, rest, value.clone(), true)?;
+            for profile in &profiles {
+                set_collection(profile_table(table, profile)?, collection, rest, value.clone(), false)?;
+            }
+        }
+        ["collection", ..] => anyhow::bail!("expected DOC_AI_COLLECTION__<NAME>__<KEY>"),
+        _ => {
+            set(table, path, value.clone())?;
+            if PROFILE_KEYS.contains(&path[0]) {
+                for profile in &profiles {
+                    let section = profile_table(table, profile)?;
+                    // A key the profile does not set falls through to the top level anyway
+                    if section.contains_key(path[0]) {
+                        set(section, path, value.clone())?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Apply the `DOC_AI_*` variables among `vars` to a parsed config file. Returns how many applied.
+pub fn apply_env(table: &mut Table, vars: impl IntoIterator<Item = (String, String)>) -> anyhow::Result<usize> {
+    let mut applied = 0;
+    for (name, raw) in vars {
+        let Some(key) = name.strip_prefix(ENV_PREFIX) else { continue };
+        let key = key.to_lowercase();
+        let path: Vec<&str> = key.split("__").collect();
+        if !CONFIG_KEYS.contains(&path[0]) {
+            continue;
+        }
+        apply_one(table, &path, parse_value(&raw)).with_context(|| format!("Invalid environment variable {}", name))?;
+        applied += 1;
+    }
+    Ok(applied)
+}
+
+fn profile_table<'a>(table: &'a mut Table, profile: &str) -> anyhow::Result<&'a mut Table> {
+    table
+        .get_mut("profile")
+        .and_then(Value::as_table_mut)
+        .and_then(|p| p.get_mut(profile))
+        .and_then(Value::as_table_mut)
+        .with_context(|| format!("[profile.{}] is not a section", profile))
+}
@@ -0,0 +1,264 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Cheap-first answering (`[escalation]`): the query's model, meant to be the
+// small fast one, answers first. Its answer is checked: every figure, date
+// and identifier must appear in the documents given to it, net plus tax must
+// make the gross, the answer must follow the schema and be JSON at all. If a
+// check fails, the larger `model` answers the same prompt; its answer wins,
+// and fields it left out are taken from the first answer unless they failed
+// a check. Most questions cost a small-model call; the hard ones get the large
+// model as well.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Smaller differences are rounding
+const SUM_TOLERANCE: f64 = 0.011;
+
+/// Numbers and strings with fewer digits are too common to check
+const MIN_CHECKED_DIGITS: usize = 3;
+
+/// Longer strings are prose, not values
+const MAX_CHECKED_CHARS: usize = 40;
+
+const NET_KEYS: [&str; 5] = ["net", "subtotal", "net_amount", "amount_excl_vat", "total_excl_vat"];
+const TAX_KEYS: [&str; 4] = ["tax", "vat", "tax_amount", "vat_amount"];
+const GROSS_KEYS: [&str; 6] = ["gross", "total", "total_due", "gross_amount", "amount_due", "total_incl_vat"];
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    /// A figure, date or identifier of the answer is not in the documents
+    Ungrounded,
+    /// Net plus tax is not the gross
+    SumMismatch,
+    /// The answer breaks the requested schema
+    Schema,
+    /// The answer is not JSON
+    Unparsed,
+}
+
+impl Check {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Check::Ungrounded => "ungrounded",
+            Check::SumMismatch => "sum_mismatch",
+            Check::Schema => "schema",
+            Check::Unparsed => "unparsed",
+        }
+    }
+}
+
+/// `[escalation]` section of the config file
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct EscalationConfig {
+    /// Larger model that answers when the first answer fails a check; none turns escalation off
+    pub model: Option<String>,
+    pub checks: Vec<Check>,
+}
+
+impl Default for EscalationConfig {
+    fn default() -> Self {
+        Self { model: None, checks: vec![Check::Ungrounded, Check::SumMismatch, Check::Schema, Check::Unparsed] }
+    }
+}
+
+/// A check the first answer failed, and where
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub check: Check,
+    /// Field of the answer ("total_due", "items[2].amount"); empty for the whole answer
+    pub path: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path.as_str() {
+            "" => write!(f, "{}", self.check.as_str()),
+            path => write!(f, "{} ({})", self.check.as_str(), path),
+        }
+    }
+}
+
+/// Reported with an escalated answer
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Escalation {
+    /// Model of the first answer
+    pub from: String,
+    /// Model of the answer given
+    pub to: String,
+    /// Checks the first answer failed
+    pub reasons: Vec<String>,
+}
+
+/// The checks of `config` that `answer` fails; `contents` is what the model was shown
+pub fn failed_checks(
+    config: &EscalationConfig,
+    answer: &Value,
+    contents: &str,
+    schema_errors: Option<&Vec<String>>,
+) -> Vec<Failure> {
+    let mut failures = Vec::new();
+    let unparsed = answer.as_object().is_some_and(|o| o.len() == 1 && o.contains_key("raw"));
+    for check in &config.checks {
+        match check {
+            Check::Unparsed if unparsed => failures.push(Failure { check: Check::Unparsed, path: String::new() }),
+            Check::Schema if schema_errors.is_some_and(|e| !e.is_empty()) => {
+                failures.push(Failure { check: Check::Schema, path: String::new() })
+            }
+            Check::Ungrounded if !unparsed => {
+                let lines: Vec<&str> = contents.lines().collect();
+                let mut leaves = Vec::new();
+                collect_leaves(answer, String::new(), &mut leaves);
+                for (path, value) in leaves {
+                    if !grounded(value, &lines) {
+                        failures.push(Failure { check: Check::Ungrounded, path });
+                    }
+                }
+            }
+            Check::SumMismatch => sum_mismatches(answer, String::new(), &mut failures),
+            _ => {}
+        }
+    }
+    failures
+}
+
+/// Values of the answer with their paths, leaving out citations and the status
+fn collect_leaves<'a>(value: &'a Value, path: String, out: &mut Vec<(String, &'a Value)>) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map {
+                if path.is_empty() && (key == "sources" || key == "status") {
+                    continue;
+                }
+                let sub = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                collect_leaves(v, sub, out);
+            }
+        }
+        Value::Array(items) => {
+            for (i, v) in items.iter().enumerate() {
+                collect_leaves(v, format!("{}[{}]", path, i), out);
+            }
+        }
+        _ => out.push((path, value)),
+    }
+}
+
+fn digits(text: &str) -> String {
+    text.chars().filter(char::is_ascii_digit).collect()
+}
+
+/// Digits of a number, without the leading zeros of "0.15"
+fn number_digits(text: &str) -> String {
+    digits(text).trim_start_matches('0').to_string()
+}
+
+/// Whether the value appears in one of the lines, in whatever format the document uses
+fn grounded(value: &Value, lines: &[&str]) -> bool {
+    let forms: Vec<String> = match value {
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default().abs();
+            vec![number_digits(&n.to_string()), number_digits(&format!("{:.2}", n))]
+        }
+        Value::String(s) if s.chars().count() <= MAX_CHECKED_CHARS => {
+            if lines.iter().any(|line| line.to_lowercase().contains(&s.to_lowercase())) {
+                return true;
+            }
+            if let Some(found) = iso_date_in(s, lines) {
+                return found;
+            }
+            vec![digits(s)]
+        }
+        _ => return true,
+    };
+    if forms.iter().all(|f| f.len() < MIN_CHECKED_DIGITS) {
+        return true;
+    }
+    lines.iter().any(|line| {
+        let line = digits(line);
+        forms.iter().any(|f| !f.is_empty() && line.contains(f.as_str()))
+    })
+}
+
+/// For an ISO date (2025-03-01): whether some line has it as 01/03/2025,
+/// 03/01/2025 or "1 March 2025"; None for anything else
+fn iso_date_in(s: &str, lines: &[&str]) -> Option<bool> {
+    let mut parts = s.trim().splitn(3, '-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 || digits(s).len() != 8 {
+        return None;
+    }
+    let month_no: usize = month.parse().ok()?;
+    let day_no: u32 = day.parse().ok()?;
+    let name = MONTHS.get(month_no.checked_sub(1)?)?;
+    let numeric = [format!("{}{}{}", day, month, year), format!("{}{}{}", month, day, year)];
+    Some(lines.iter().any(|line| {
+        let lower = line.to_lowercase();
+        numeric.iter().any(|n| digits(line).contains(n.as_str()))
+            || (lower.contains(name) && lower.contains(year) && lower.contains(&day_no.to_string()))
+    }))
+}
+
+/// A number from a JSON number or a string such as "R 1 234,50"
+fn amount(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => {
+            let mut kept: String = s.chars().filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '-')).collect();
+            kept = match (kept.contains('.'), kept.contains(',')) {
+                (true, true) => kept.replace(',', ""),
+                (false, true) => kept.replace(',', "."),
+                _ => kept,
+            };
+            kept.parse().ok()
+        }
+        _ => None,
+    }
+}
+
+fn field<'a>(map: &'a Map<String, Value>, keys: &[&str]) -> Option<(&'a str, f64)> {
+    map.iter().find(|(k, _)| keys.contains(&k.to_lowercase().as_str())).and_then(|(k, v)| Some((k.as_str(), amount(v)?)))
+}
+
+/// Objects whose net, tax and gross amounts do not add up
+fn sum_mismatches(value: &Value, path: String, out: &mut Vec<Failure>) {
+    match value {
+        Value::Object(map) => {
+            if let (Some((_, net)), Some((_, tax)), Some((gross_key, gross))) =
+                (field(map, &NET_KEYS), field(map, &TAX_KEYS), field(map, &GROSS_KEYS))
+                && (net + tax - gross).abs() > SUM_TOLERANCE
+            {
+                let at = if path.is_empty() { gross_key.to_string() } else { format!("{}.{}", path, gross_key) };
+                out.push(Failure { check: Check::SumMismatch, path: at });
+            }
+            for (key, v) in map {
+                sum_mismatches(v, if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) }, out);
+            }
+        }
+        Value::Array(items) => {
+            for (i, v) in items.iter().enumerate() {
+                sum_mismatches(v, format!("{}[{}]", path, i), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The larger model's answer, completed with the first answer's top-level
+/// fields it lacks, except those that failed a check
+pub fn merge(mut primary: Value, first: &Value, failures: &[Failure]) -> Value {
+    let (Some(target), Some(source)) = (primary.as_object_mut(), first.as_object()) else { return primary };
+    let top_level = |path: &str| path.split(['.', '[']).next().unwrap_or_default().to_string();
+    for (key, value) in source {
+        let failed = failures.iter().any(|f| top_level(&f.path) == *key);
+        if !failed && !target.contains_key(key) {
+            target.insert(key.clone(), value.clone());
+        }
+    }
+    primary
+}
@@ -0,0 +1,218 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Progress events of a query, for applications embedding the library that
+// want their own progress display. Implement `EventSink` (every method has
+// an empty default) and pass it with `QueryBuilder::events`; the command
+// line's progress output is the `ConsoleEvents` implementation. A sink that
+// asks for token deltas makes the model's answer stream in as it is generated,
+// along with the answer's fields as each one is completed (`on_partial_answer`);
+// `ChannelEvents` turns all of it into `QueryEvent` values on a channel. What
+// the query works around (a skipped file, a fallback) arrives as `on_warning`,
+// other notes along the way as `on_progress`. A query
+// with an output profile sends its events through `Profiled`: partial answers
+// are redacted like the final one, and token deltas are not sent at all.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::redact::OutputProfile;
+
+pub trait EventSink: Send + Sync {
+    /// Retrieval starts looking through a collection
+    fn on_scan_start(&self, _collection: &str) {}
+
+    /// A document was read and goes into the prompt
+    fn on_document_loaded(&self, _file: &str, _bytes: usize) {}
+
+    /// The prompt is complete; `tokens` is an estimate
+    fn on_prompt_built(&self, _documents: usize, _tokens: usize) {}
+
+    /// The model generated `n` tokens (once per model call)
+    fn on_tokens(&self, _n: usize) {}
+
+    /// Stream the answer: `on_token_delta` is called for every piece the model generates
+    fn wants_token_deltas(&self) -> bool {
+        false
+    }
+
+    /// Text generated since the last delta (raw model output, usually JSON being written)
+    fn on_token_delta(&self, _text: &str) {}
+
+    /// The answer's fields completed so far, each time one is added (streamed answers only)
+    fn on_partial_answer(&self, _answer: &Value) {}
+
+    /// A step of the query worth showing that has no event of its own
+    fn on_progress(&self, _message: &str) {}
+
+    /// Something went wrong that the query works around (a skipped file, a fallback)
+    fn on_warning(&self, _message: &str) {}
+
+    /// The query finished, answered or not
+    fn on_done(&self, _elapsed_ms: u64, _ok: bool) {}
+}
+
+impl fmt::Debug for dyn EventSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventSink")
+    }
+}
+
+/// Ignores every event (the default)
+pub struct NoEvents;
+
+impl EventSink for NoEvents {}
+
+/// Progress lines on standard error, leaving standard output to the answer
+pub struct ConsoleEvents;
+
+impl EventSink for ConsoleEvents {
+    fn on_scan_start(&self, collection: &str) {
+        eprintln!("Searching {}...", collection);
+    }
+
+    fn on_document_loaded(&self, file: &str, bytes: usize) {
+        eprintln!("  {} ({} bytes)", file, bytes);
+    }
+
+    fn on_prompt_built(&self, documents: usize, tokens: usize) {
+        eprintln!("Prompt: {} document(s), about {} tokens", documents, tokens);
+    }
+
+    fn on_tokens(&self, n: usize) {
+        eprintln!("Model: {} tokens", n);
+    }
+
+    fn on_progress(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+
+    fn on_warning(&self, message: &str) {
+        eprintln!("WARNING: {}", message);
+    }
+
+    fn on_done(&self, elapsed_ms: u64, ok: bool) {
+        eprintln!("{} in {} ms", if ok { "Done" } else { "Failed" }, elapsed_ms);
+    }
+}
+
+/// One event of a query, as sent by `ChannelEvents`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QueryEvent {
+    ScanStart { collection: String },
+    DocumentLoaded { file: String, bytes: usize },
+    PromptBuilt { documents: usize, tokens: usize },
+    Tokens { n: usize },
+    TokenDelta { text: String },
+    PartialAnswer {
+        #[cfg_attr(feature = "openapi", schema(value_type = Object))]
+        answer: Value,
+    },
+    Progress { message: String },
+    Warning { message: String },
+    Done { elapsed_ms: u64, ok: bool },
+}
+
+/// Sends every event, token deltas included, to a channel; events after the
+/// receiver is gone are dropped
+pub struct ChannelEvents(pub UnboundedSender<QueryEvent>);
+
+impl ChannelEvents {
+    fn send(&self, event: QueryEvent) {
+        let _ = self.0.send(event);
+    }
+}
+
+impl EventSink for ChannelEvents {
+    fn on_scan_start(&self, collection: &str) {
+        self.send(QueryEvent::ScanStart { collection: collection.to_string() });
+    }
+
+    fn on_document_loaded(&self, file: &str, bytes: usize) {
+        self.send(QueryEvent::DocumentLoaded { file: file.to_string(), bytes });
+    }
+
+    fn on_prompt_built(&self, documents: usize, tokens: usize) {
+        self.send(QueryEvent::PromptBuilt { documents, tokens });
+    }
+
+    fn on_tokens(&self, n: usize) {
+        self.send(QueryEvent::Tokens { n });
+    }
+
+    fn wants_token_deltas(&self) -> bool {
+        true
+    }
+
+    fn on_token_delta(&self, text: &str) {
+        self.send(QueryEvent::TokenDelta { text: text.to_string() });
+    }
+
+    fn on_partial_answer(&self, answer: &Value) {
+        self.send(QueryEvent::PartialAnswer { answer: answer.clone() });
+    }
+
+    fn on_progress(&self, message: &str) {
+        self.send(QueryEvent::Progress { message: message.to_string() });
+    }
+
+    fn on_warning(&self, message: &str) {
+        self.send(QueryEvent::Warning { message: message.to_string() });
+    }
+
+    fn on_done(&self, elapsed_ms: u64, ok: bool) {
+        self.send(QueryEvent::Done { elapsed_ms, ok });
+    }
+}
+
+/// The events of a query with an output profile, passed on to `inner`: partial
+/// answers are filtered through the profile, and token deltas, raw model text
+/// that cannot be, are dropped
+pub struct Profiled<'a> {
+    pub inner: &'a dyn EventSink,
+    pub profile: &'a OutputProfile,
+}
+
+impl EventSink for Profiled<'_> {
+    fn on_scan_start(&self, collection: &str) {
+        self.inner.on_scan_start(collection);
+    }
+
+    fn on_document_loaded(&self, file: &str, bytes: usize) {
+        self.inner.on_document_loaded(file, bytes);
+    }
+
+    fn on_prompt_built(&self, documents: usize, tokens: usize) {
+        self.inner.on_prompt_built(documents, tokens);
+    }
+
+    fn on_tokens(&self, n: usize) {
+        self.inner.on_tokens(n);
+    }
+
+    /// Still streamed when `inner` asks for it, for the partial answers
+    fn wants_token_deltas(&self) -> bool {
+        self.inner.wants_token_deltas()
+    }
+
+    fn on_partial_answer(&self, answer: &Value) {
+        let mut answer = answer.clone();
+        self.profile.apply_to_answer(&mut answer);
+        self.inner.on_partial_answer(&answer);
+    }
+
+    fn on_progress(&self, message: &str) {
+        self.inner.on_progress(message);
+    }
+
+    fn on_warning(&self, message: &str) {
+        self.inner.on_warning(message);
+    }
+
+    fn on_done(&self, elapsed_ms: u64, ok: bool) {
+        self.inner.on_done(elapsed_ms, ok);
+    }
+}
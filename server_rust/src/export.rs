@@ -0,0 +1,202 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Bookkeeping exports of the invoice records: ledger-cli journal entries,
+// QIF, and QuickBooks IIF or CSV. Each invoice becomes a bill: net to the
+// vendor's expense account, VAT to the input VAT account, gross owed to the
+// payables account. Accounts come from a mapping file (see accounts.toml.example).
+
+use anyhow::Context;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::fold::contains_folded;
+use crate::records::InvoiceRecord;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// ledger-cli / hledger journal
+    Ledger,
+    /// Quicken Interchange Format (GnuCash, Quicken)
+    Qif,
+    /// QuickBooks Desktop IIF
+    Iif,
+    /// QuickBooks Online bill import CSV
+    QuickbooksCsv,
+}
+
+/// Account mapping file
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AccountMap {
+    /// Expense account for vendors not listed under [vendors]
+    pub expense: String,
+    /// Input VAT (recoverable tax)
+    pub vat: String,
+    /// Accounts payable
+    pub payable: String,
+    /// Vendor name (or part of it, case-insensitive) → expense account
+    pub vendors: BTreeMap<String, String>,
+}
+
+impl Default for AccountMap {
+    fn default() -> Self {
+        Self {
+            expense: "Expenses:Purchases".to_string(),
+            vat: "Assets:VAT Input".to_string(),
+            payable: "Liabilities:Accounts Payable".to_string(),
+            vendors: BTreeMap::new(),
+        }
+    }
+}
+
+impl AccountMap {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read account mapping: {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid account mapping: {}", path.display()))
+    }
+
+    /// Expense account for a vendor: exact name first, then a contained name
+    pub fn expense_for(&self, vendor: &str) -> &str {
+        if let Some(account) = self.vendors.get(vendor) {
+            return account;
+        }
+        self.vendors
+            .iter()
+            .find(|(name, _)| contains_folded(vendor, name))
+            .map(|(_, account)| account.as_str())
+            .unwrap_or(&self.expense)
+    }
+}
+
+/// One bill ready for export
+struct Bill<'a> {
+    record: &'a InvoiceRecord,
+    date: &'a str,
+    vendor: &'a str,
+    reference: String,
+    /// Expense account and amount, then VAT (if any); both signed like the record
+    lines: Vec<(&'a str, Decimal)>,
+    gross: Decimal,
+}
+
+fn bill<'a>(record: &'a InvoiceRecord, accounts: &'a AccountMap) -> Option<Bill<'a>> {
+    let date = record.date.as_deref()?;
+    let gross = record.gross?;
+    let vendor = record.vendor.as_deref().unwrap_or("Unknown vendor");
+    let expense = accounts.expense_for(vendor);
+    let lines = match (record.net, record.tax) {
+        (Some(net), Some(tax)) => vec![(expense, net), (accounts.vat.as_str(), tax)],
+        (None, Some(tax)) => vec![(expense, gross - tax), (accounts.vat.as_str(), tax)],
+        (Some(net), None) => vec![(expense, net), (accounts.vat.as_str(), gross - net)],
+        (None, None) => vec![(expense, gross)],
+    };
+    let lines = lines.into_iter().filter(|(_, amount)| !amount.is_zero()).collect();
+    let reference = record.id.clone().unwrap_or_else(|| record.file.clone());
+    Some(Bill { record, date, vendor, reference, lines, gross })
+}
+
+/// MM/DD/YYYY, as QIF and IIF expect
+fn us_date(iso: &str) -> String {
+    format!("{}/{}/{}", &iso[5..7], &iso[8..10], &iso[..4])
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) { format!("\"{}\"", s.replace('"', "\"\"")) } else { s.to_string() }
+}
+
+fn ledger(bills: &[Bill], accounts: &AccountMap) -> String {
+    let mut out = String::new();
+    for b in bills {
+        let commodity = b.record.currency.as_deref().unwrap_or("");
+        let amount = |d: Decimal| if commodity.is_empty() { d.to_string() } else { format!("{} {}", commodity, d) };
+        out.push_str(&format!("{} * {}  ; {}\n", b.date.replace('-', "/"), b.vendor, b.reference));
+        out.push_str(&format!("    ; source: {}/{}\n", b.record.collection, b.record.file));
+        for (account, value) in &b.lines {
+            out.push_str(&format!("    {:<40}  {}\n", account, amount(*value)));
+        }
+        out.push_str(&format!("    {:<40}  {}\n\n", accounts.payable, amount(-b.gross)));
+    }
+    out
+}
+
+fn qif(bills: &[Bill], accounts: &AccountMap) -> String {
+    let mut out = format!("!Account\nN{}\nTOth L\n^\n!Type:Oth L\n", accounts.payable);
+    for b in bills {
+        out.push_str(&format!("D{}\nT{}\nP{}\nN{}\n", us_date(b.date), -b.gross, b.vendor, b.reference));
+        if let [(account, _)] = b.lines.as_slice() {
+            out.push_str(&format!("L{}\n", account));
+        } else {
+            for (account, value) in &b.lines {
+                out.push_str(&format!("S{}\n${}\n", account, -*value));
+            }
+        }
+        out.push_str("^\n");
+    }
+    out
+}
+
+/// QuickBooks Desktop bills; colons in account names are read as sub-accounts
+fn iif(bills: &[Bill], accounts: &AccountMap) -> String {
+    let mut out = String::from(
+        "!TRNS\tTRNSTYPE\tDATE\tACCNT\tNAME\tAMOUNT\tDOCNUM\tMEMO\n\
+         !SPL\tTRNSTYPE\tDATE\tACCNT\tNAME\tAMOUNT\tDOCNUM\tMEMO\n\
+         !ENDTRNS\n",
+    );
+    for b in bills {
+        let date = us_date(b.date);
+        let memo = b.record.file.as_str();
+        out.push_str(&format!(
+            "TRNS\tBILL\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            date, accounts.payable, b.vendor, -b.gross, b.reference, memo
+        ));
+        for (account, value) in &b.lines {
+            out.push_str(&format!("SPL\tBILL\t{}\t{}\t{}\t{}\t{}\t{}\n", date, account, b.vendor, value, b.reference, memo));
+        }
+        out.push_str("ENDTRNS\n");
+    }
+    out
+}
+
+fn quickbooks_csv(bills: &[Bill]) -> String {
+    let mut out = String::from("Bill No,Supplier,Bill Date,Account,Line Amount,Line Tax Amount,Currency\n");
+    for b in bills {
+        let (account, net) = b.lines.first().copied().unwrap_or(("", b.gross));
+        let tax = b.lines.get(1).map(|(_, t)| *t).unwrap_or_default();
+        out.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            csv_field(&b.reference),
+            csv_field(b.vendor),
+            b.date,
+            csv_field(account),
+            net,
+            tax,
+            b.record.currency.as_deref().unwrap_or_default()
+        ));
+    }
+    out
+}
+
+/// Export the records; the second value lists the records skipped for lack of a date or total
+pub fn export(records: &[InvoiceRecord], format: ExportFormat, accounts: &AccountMap) -> (String, Vec<String>) {
+    let mut bills = Vec::new();
+    let mut skipped = Vec::new();
+    for record in records {
+        match bill(record, accounts) {
+            Some(b) => bills.push(b),
+            None => skipped.push(record.file.clone()),
+        }
+    }
+    bills.sort_by(|a, b| a.date.cmp(b.date).then_with(|| a.reference.cmp(&b.reference)));
+
+    let text = match format {
+        ExportFormat::Ledger => ledger(&bills, accounts),
+        ExportFormat::Qif => qif(&bills, accounts),
+        ExportFormat::Iif => iif(&bills, accounts),
+        ExportFormat::QuickbooksCsv => quickbooks_csv(&bills),
+    };
+    (text, skipped)
+}
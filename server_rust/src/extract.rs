@@ -0,0 +1,72 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Typed extraction (feature `extract`): any struct that derives `Deserialize`
+// and `JsonSchema` can be the target of a query, so library users are not
+// limited to invoices.
+//
+//   #[derive(Deserialize, JsonSchema)]
+//   #[schemars(crate = "doc_ai_server::extract::schemars")]
+//   struct Timesheet { employee: String, week: String, hours: f64 }
+//
+//   let sheet: Extracted<Timesheet> = Query::builder("Hours of ts_042?")
+//       .extract::<Timesheet>()
+//       .build()
+//       .run_as()
+//       .await?;
+//
+// The schema is generated from the type (nested types inlined, since the
+// answer check does not follow $ref), shown to the model like a --schema file
+// and checked against the answer before it is deserialized.
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+pub use schemars;
+pub use schemars::JsonSchema;
+
+use crate::pipeline::{Query, QueryBuilder};
+use crate::ApiResponse;
+
+/// A type the model can be asked to fill in
+pub trait Extractable: DeserializeOwned + JsonSchema {
+    /// JSON schema of the type, as given to the model
+    fn extraction_schema() -> Value {
+        let generator = schemars::r#gen::SchemaSettings::draft07()
+            .with(|s| {
+                s.inline_subschemas = true;
+                s.meta_schema = None;
+            })
+            .into_generator();
+        serde_json::to_value(generator.into_root_schema_for::<Self>()).unwrap_or_default()
+    }
+}
+
+impl<T: DeserializeOwned + JsonSchema> Extractable for T {}
+
+/// The typed answer with the response it came in (sources, warnings, provenance...)
+pub struct Extracted<T> {
+    pub value: T,
+    pub response: ApiResponse,
+}
+
+impl QueryBuilder {
+    /// Ask for an answer of type `T`: its schema goes into the prompt and the answer check
+    pub fn extract<T: Extractable>(self) -> Self {
+        self.schema(T::extraction_schema())
+    }
+}
+
+impl Query {
+    /// Run the query and read the answer as `T`; an error when it breaks the
+    /// schema or does not deserialize
+    pub async fn run_as<T: Extractable>(&self) -> Result<Extracted<T>> {
+        let response = self.run().await?;
+        if let Some(errors) = response.schema_errors.as_ref().filter(|e| !e.is_empty()) {
+            anyhow::bail!("The answer does not follow the schema: {}", errors.join("; "));
+        }
+        let value = serde_json::from_value(response.answer.clone())
+            .map_err(|e| anyhow::anyhow!("The answer is not a {}: {}", std::any::type_name::<T>(), e))?;
+        Ok(Extracted { value, response })
+    }
+}
@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Text folding for matching names: file names, vendors, tags and notes are
+// compared in a form that ignores how they were written. Folding applies
+// compatibility normalization (NFKC: "ﬁ" is "fi", full-width "ＡＢＣ" is
+// "abc"), full case folding ("STRAẞE" is "strasse") and strips diacritics
+// ("Société" is "societe"). A name with an umlaut has a second form, spelled
+// the German way ("Müller" is both "muller" and "mueller"), so a question
+// about Müller finds mueller_inv_003.txt, and one about Mueller finds
+// Müller_inv_003.txt. Two names match when any of their forms do.
+
+use std::collections::HashSet;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+const DIAERESIS: char = '\u{0308}';
+
+/// `text` case-folded, compatibility-normalized and without diacritics
+pub fn fold(text: &str) -> String {
+    folded(text, false)
+}
+
+/// The forms `text` is matched in: `fold`, and the German spelling (ä → ae,
+/// ö → oe, ü → ue, ø → oe) when there is an umlaut
+pub fn fold_forms(text: &str) -> Vec<String> {
+    let plain = folded(text, false);
+    let german = folded(text, true);
+    if german == plain { vec![plain] } else { vec![plain, german] }
+}
+
+/// Whether `haystack` contains `needle`, folded
+pub fn contains_folded(haystack: &str, needle: &str) -> bool {
+    let needles = fold_forms(needle);
+    fold_forms(haystack).iter().any(|h| needles.iter().any(|n| h.contains(n.as_str())))
+}
+
+/// Whether `a` and `b` are the same, folded
+pub fn same_folded(a: &str, b: &str) -> bool {
+    let b = fold_forms(b);
+    fold_forms(a).iter().any(|a| b.contains(a))
+}
+
+/// Words of every form of `text` ("mueller_inv_003" has "mueller", "inv" and "003")
+pub fn folded_words(text: &str) -> HashSet<String> {
+    fold_forms(text)
+        .iter()
+        .flat_map(|form| {
+            form.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(str::to_string).collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn folded(text: &str, german: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut previous = None;
+    // NFKD splits the marks off their letters; lower-casing can add marks of its own (İ → i̇)
+    for c in text.nfkd().flat_map(char::to_lowercase) {
+        if is_combining_mark(c) {
+            if german && c == DIAERESIS && matches!(previous, Some('a' | 'o' | 'u')) {
+                out.push('e');
+                previous = None;
+            }
+            continue;
+        }
+        match c {
+            'ß' => out.push_str("ss"),
+            'æ' => out.push_str("ae"),
+            'œ' => out.push_str("oe"),
+            'ø' if german => out.push_str("oe"),
+            'ø' => out.push('o'),
+            'ł' => out.push('l'),
+            'đ' | 'ð' => out.push('d'),
+            'þ' => out.push_str("th"),
+            'ı' => out.push('i'),
+            'ς' => out.push('σ'),
+            c => out.push(c),
+        }
+        previous = Some(c);
+    }
+    out
+}
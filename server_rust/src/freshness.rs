@@ -0,0 +1,127 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Stale-index detection: a collection's documents as they are on disk against
+// the manifest of the index in use, the size and modification time of every
+// document it was built from (see ingest.rs). A question warns
+// (`stale_index`) when documents were added, changed or removed since, so an
+// answer never rests on an outdated index without saying so. `ask
+// --reindex-stale` (or `[retrieval] reindex_stale = true`) indexes the
+// changed documents before answering instead. A running server only warns:
+// it loads the index once, so it sees a new one after a restart.
+
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::docid::{doc_key, doc_path, is_text_file};
+use crate::metadata::is_hidden;
+use crate::warnings::{Warning, WarningCode};
+use crate::Collection;
+
+/// Document id → (size, modification time in Unix seconds)
+pub type Manifest = HashMap<String, (u64, u64)>;
+
+/// Manifest of the index this process loaded
+static IN_USE: OnceCell<Manifest> = OnceCell::new();
+
+/// Documents that differ from the manifest, by file name
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Staleness {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl Staleness {
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.added.len() + self.changed.len() + self.removed.len()
+    }
+
+    /// "1 changed, 2 added: inv_003.txt, inv_008.txt, inv_009.txt"
+    pub fn summary(&self) -> String {
+        let counts: Vec<String> = [("changed", &self.changed), ("added", &self.added), ("removed", &self.removed)]
+            .iter()
+            .filter(|(_, files)| !files.is_empty())
+            .map(|(what, files)| format!("{} {}", files.len(), what))
+            .collect();
+        let mut names: Vec<&str> = self.changed.iter().chain(&self.added).chain(&self.removed).map(String::as_str).collect();
+        let more = names.len().saturating_sub(5);
+        names.truncate(5);
+        let mut summary = format!("{}: {}", counts.join(", "), names.join(", "));
+        if more > 0 {
+            summary.push_str(&format!(" and {} more", more));
+        }
+        summary
+    }
+
+    /// A warning when anything changed
+    pub fn warning(&self, collection: &str) -> Option<Warning> {
+        (!self.is_empty()).then(|| {
+            Warning::new(
+                WarningCode::StaleIndex,
+                format!(
+                    "The index of '{}' is out of date ({}); run `index` or ask with --reindex-stale",
+                    collection,
+                    self.summary()
+                ),
+            )
+        })
+    }
+}
+
+/// Size and modification time of a file
+pub fn stat(path: &Path) -> Option<(u64, u64)> {
+    let meta = fs::metadata(path).ok().filter(|m| m.is_file())?;
+    let modified = meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_secs());
+    Some((meta.len(), modified))
+}
+
+/// Remember the manifest of the index this process answers from (first call wins)
+pub fn record(manifest: Manifest) {
+    let _ = IN_USE.set(manifest);
+}
+
+/// The collection's documents that differ from `manifest`
+pub fn stale(collection: &Collection, manifest: &Manifest) -> Staleness {
+    let mut staleness = Staleness::default();
+    let mut seen = HashSet::new();
+    if let Ok(entries) = fs::read_dir(&collection.folder) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !is_text_file(&path) || is_hidden(&path) {
+                continue;
+            }
+            let Some(now) = stat(&path) else { continue };
+            let key = doc_key(&path);
+            let name = entry.file_name().to_string_lossy().into_owned();
+            match manifest.get(&key) {
+                None => staleness.added.push(name),
+                Some(indexed) if *indexed != now => staleness.changed.push(name),
+                Some(_) => {}
+            }
+            seen.insert(key);
+        }
+    }
+    for key in manifest.keys().filter(|k| !seen.contains(*k)) {
+        let path = doc_path(key);
+        if path.parent() == Some(collection.folder.as_path()) && !is_hidden(&path) {
+            staleness.removed.push(path.file_name().unwrap_or_default().to_string_lossy().into_owned());
+        }
+    }
+    for files in [&mut staleness.added, &mut staleness.changed, &mut staleness.removed] {
+        files.sort();
+    }
+    staleness
+}
+
+/// What changed in the collection since the index in use was built (nothing before one is loaded)
+pub fn check(collection: &Collection) -> Staleness {
+    IN_USE.get().map(|manifest| stale(collection, manifest)).unwrap_or_default()
+}
@@ -0,0 +1,180 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Secret scanning of outgoing prompts (`[guardrail]`). A key file or `.env`
+// that ended up in a collection folder would otherwise be sent to the model
+// with the documents. Before a request leaves for a host that is not this
+// machine, its prompt text is scanned for private keys, cloud and API tokens
+// and `password = ...` assignments; the request is refused with the kinds and
+// lines found ("block", the default), or goes out with them replaced by
+// [REDACTED ...] ("redact"). Loopback hosts are only scanned with `scan_local`.
+
+use anyhow::Result;
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretAction {
+    /// Refuse to send the prompt
+    #[default]
+    Block,
+    /// Send it with the secrets replaced
+    Redact,
+    Off,
+}
+
+/// `[guardrail]` section of the config file
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct GuardrailConfig {
+    pub secrets: SecretAction,
+    /// Scan prompts for Ollama on this machine too
+    pub scan_local: bool,
+}
+
+static SECRET_PATTERNS: Lazy<Vec<(&'static str, Regex)>> = Lazy::new(|| {
+    [
+        ("private key", r"-----BEGIN [A-Z ]*PRIVATE KEY( BLOCK)?-----[\s\S]*?(-----END [A-Z ]*PRIVATE KEY( BLOCK)?-----|$)"),
+        ("AWS access key", r"\b(AKIA|ASIA)[0-9A-Z]{16}\b"),
+        ("GitHub token", r"\b(gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{60,})\b"),
+        ("OpenAI key", r"\bsk-(proj-)?[A-Za-z0-9_-]{20,}\b"),
+        ("Slack token", r"\bxox[abposr]-[A-Za-z0-9-]{10,}\b"),
+        ("Google API key", r"\bAIza[0-9A-Za-z_-]{35}\b"),
+        ("Stripe key", r"\b[rs]k_live_[0-9A-Za-z]{20,}\b"),
+        ("JSON web token", r"\beyJ[A-Za-z0-9_-]{10,}\.eyJ[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}\b"),
+        (
+            "password or secret",
+            r#"(?i)\b[a-z_]*(password|passwd|secret|api[_-]?key|access[_-]?token|auth[_-]?token)[a-z_]*["']?\s*[:=]\s*["']?[^\s"',;]{8,}"#,
+        ),
+    ]
+    .into_iter()
+    .map(|(kind, pattern)| (kind, Regex::new(pattern).unwrap()))
+    .collect()
+});
+
+/// A secret found in a prompt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretHit {
+    pub kind: &'static str,
+    /// 1-based line of the prompt text
+    pub line: usize,
+}
+
+/// Secrets in `text`, in order
+pub fn scan(text: &str) -> Vec<SecretHit> {
+    let mut hits: Vec<(usize, SecretHit)> = Vec::new();
+    for (kind, re) in SECRET_PATTERNS.iter() {
+        for m in re.find_iter(text) {
+            let line = text[..m.start()].matches('\n').count() + 1;
+            hits.push((m.start(), SecretHit { kind, line }));
+        }
+    }
+    hits.sort_by_key(|(start, _)| *start);
+    hits.into_iter().map(|(_, hit)| hit).collect()
+}
+
+/// `text` with every secret replaced by `[REDACTED <kind>]`
+pub fn redact_secrets(text: &str) -> String {
+    SECRET_PATTERNS
+        .iter()
+        .fold(text.to_string(), |text, (kind, re)| re.replace_all(&text, format!("[REDACTED {}]", kind)).into_owned())
+}
+
+static GUARDRAIL: OnceCell<GuardrailConfig> = OnceCell::new();
+
+/// Install the guardrail settings (first call wins)
+pub fn init_guardrail(config: GuardrailConfig) {
+    let _ = GUARDRAIL.set(config);
+}
+
+fn guardrail() -> &'static GuardrailConfig {
+    GUARDRAIL.get_or_init(GuardrailConfig::default)
+}
+
+/// Whether `url` points at this machine
+pub fn is_local(url: &str) -> bool {
+    reqwest::Url::parse(url).ok().and_then(|u| u.host_str().map(str::to_string)).is_some_and(|host| {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        host.eq_ignore_ascii_case("localhost")
+            || host.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+    })
+}
+
+/// Prompt text of an Ollama request: the prompt, chat messages and embedding input
+fn prompt_texts(body: &mut Value) -> Vec<&mut String> {
+    let Value::Object(map) = body else { return Vec::new() };
+    let mut texts = Vec::new();
+    for (key, value) in map.iter_mut() {
+        match (key.as_str(), value) {
+            ("prompt" | "system" | "input", Value::String(s)) => texts.push(s),
+            ("input", Value::Array(items)) => texts.extend(items.iter_mut().filter_map(|v| match v {
+                Value::String(s) => Some(s),
+                _ => None,
+            })),
+            ("messages", Value::Array(messages)) => texts.extend(messages.iter_mut().filter_map(|m| match m.get_mut("content") {
+                Some(Value::String(s)) => Some(s),
+                _ => None,
+            })),
+            _ => {}
+        }
+    }
+    texts
+}
+
+/// Refusal of a prompt that carries secrets
+#[derive(Debug)]
+pub struct SecretsFound {
+    pub url: String,
+    pub hits: Vec<SecretHit>,
+}
+
+impl fmt::Display for SecretsFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = self.hits.iter().map(|h| format!("{} (line {})", h.kind, h.line)).collect::<Vec<_>>().join(", ");
+        write!(
+            f,
+            "Prompt not sent to {}: it contains what looks like a secret: {}. Remove the file holding it from the \
+             collection folder, or set [guardrail] secrets = \"redact\"",
+            self.url, list
+        )
+    }
+}
+
+impl std::error::Error for SecretsFound {}
+
+/// Check a request before it is sent to `url`: an error when it carries a
+/// secret and the action is "block", the secrets replaced for "redact"
+pub fn check_outgoing(url: &str, body: &mut Value) -> Result<()> {
+    let config = guardrail();
+    if config.secrets == SecretAction::Off || (is_local(url) && !config.scan_local) {
+        return Ok(());
+    }
+    let mut found = Vec::new();
+    for text in prompt_texts(body) {
+        let hits = scan(text);
+        if hits.is_empty() {
+            continue;
+        }
+        if config.secrets == SecretAction::Redact {
+            *text = redact_secrets(text);
+        }
+        found.extend(hits);
+    }
+    if found.is_empty() {
+        return Ok(());
+    }
+    if config.secrets == SecretAction::Block {
+        return Err(SecretsFound { url: url.to_string(), hits: found }.into());
+    }
+    let list = found.iter().map(|h| format!("{} (line {})", h.kind, h.line)).collect::<Vec<_>>().join(", ");
+    eprintln!("WARNING: redacted from the prompt for {}: {}", url, list);
+    Ok(())
+}
+
+/// Error code of a failed model call: "secret_in_prompt" when the guardrail refused it
+pub fn error_code(e: &anyhow::Error) -> &'static str {
+    if e.is::<SecretsFound>() { "secret_in_prompt" } else { "ollama_error" }
+}
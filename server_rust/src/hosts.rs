@@ -0,0 +1,289 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Several Ollama hosts sharing the model calls (`[[ollama.host]]`). Each call
+// goes to a healthy host that has the model (hosts may list the models they
+// serve, e.g. the big one only on the big GPU), chosen round-robin or by the
+// fewest calls in flight. A host that cannot be reached, or answers 503, is
+// marked down and the call moves on to the next one; while serving, hosts are
+// checked every `health_check_secs` and come back once they answer. Without
+// `[[ollama.host]]` the single `ollama_url` is the only host.
+//
+// When no host could take the call (unreachable, connection reset, or 503
+// while a model loads), the hosts are tried again `retries` times, waiting
+// `retry_delay_ms` and twice as long each round. A host that takes longer than
+// `timeout_secs` to start answering fails the call without a retry: the model
+// may still be working on it, and a second copy would only queue behind it.
+
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::ai::{list_models, ollama_url};
+use crate::guardrail;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Balance {
+    RoundRobin,
+    #[default]
+    LeastInFlight,
+}
+
+/// `[[ollama.host]]`
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct HostConfig {
+    pub url: String,
+    /// Models this host serves; empty for any
+    pub models: Vec<String>,
+}
+
+/// `[ollama]` section of the config file
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct OllamaConfig {
+    #[serde(rename = "host")]
+    pub hosts: Vec<HostConfig>,
+    pub strategy: Balance,
+    pub health_check_secs: u64,
+    /// Rounds over the hosts after the first when none could take the call
+    pub retries: u32,
+    /// Wait before the first retry; doubled for each later one
+    pub retry_delay_ms: u64,
+    pub connect_timeout_secs: u64,
+    /// Longest wait for a host to start answering, and between two pieces of a streamed answer
+    pub timeout_secs: u64,
+}
+
+impl Default for OllamaConfig {
+    fn default() -> Self {
+        Self {
+            hosts: Vec::new(),
+            strategy: Balance::default(),
+            health_check_secs: 10,
+            retries: 2,
+            retry_delay_ms: 500,
+            connect_timeout_secs: 10,
+            timeout_secs: 300,
+        }
+    }
+}
+
+struct Host {
+    url: String,
+    models: Vec<String>,
+    in_flight: AtomicUsize,
+    healthy: AtomicBool,
+}
+
+impl Host {
+    fn new(url: &str, models: Vec<String>) -> Self {
+        Host {
+            url: url.trim_end_matches('/').to_string(),
+            models,
+            in_flight: AtomicUsize::new(0),
+            healthy: AtomicBool::new(true),
+        }
+    }
+
+    fn serves(&self, model: &str) -> bool {
+        // "llama3.2" and "llama3.2:latest" are the same model
+        let base = |name: &str| name.split(':').next().unwrap_or_default().to_string();
+        self.models.is_empty() || self.models.iter().any(|m| m == model || base(m) == base(model))
+    }
+}
+
+struct Pool {
+    hosts: Vec<Host>,
+    strategy: Balance,
+    interval: Duration,
+    next: AtomicUsize,
+    retries: u32,
+    retry_delay: Duration,
+    connect_timeout: Duration,
+    timeout: Duration,
+}
+
+impl Pool {
+    fn new(hosts: Vec<Host>, config: &OllamaConfig) -> Self {
+        Pool {
+            hosts,
+            strategy: config.strategy,
+            interval: Duration::from_secs(config.health_check_secs.max(1)),
+            next: AtomicUsize::new(0),
+            retries: config.retries,
+            retry_delay: Duration::from_millis(config.retry_delay_ms),
+            connect_timeout: Duration::from_secs(config.connect_timeout_secs.max(1)),
+            timeout: Duration::from_secs(config.timeout_secs.max(1)),
+        }
+    }
+}
+
+static POOL: OnceCell<Pool> = OnceCell::new();
+
+/// `[ollama]` as installed, for the single-host pool made on first use
+static CONFIG: OnceCell<OllamaConfig> = OnceCell::new();
+
+/// Install the hosts and retry settings of `[ollama]` (first call wins); without
+/// hosts, `ollama_url` is used
+pub fn init_hosts(config: &OllamaConfig) -> Result<()> {
+    let _ = CONFIG.set(config.clone());
+    if config.hosts.is_empty() {
+        // The pool is made on first use, once `ollama_url` is final
+        return Ok(());
+    }
+    let mut hosts = Vec::new();
+    for host in &config.hosts {
+        if !host.url.starts_with("http://") && !host.url.starts_with("https://") {
+            anyhow::bail!("[[ollama.host]] url '{}' must start with http:// or https://", host.url);
+        }
+        hosts.push(Host::new(&host.url, host.models.clone()));
+    }
+    let _ = POOL.set(Pool::new(hosts, config));
+    Ok(())
+}
+
+fn pool() -> &'static Pool {
+    POOL.get_or_init(|| {
+        let config = CONFIG.get().cloned().unwrap_or_default();
+        Pool::new(vec![Host::new(ollama_url(), Vec::new())], &config)
+    })
+}
+
+/// HTTP client for model calls, with the `[ollama]` connect timeout
+pub fn client() -> Client {
+    Client::builder().connect_timeout(pool().connect_timeout).build().unwrap_or_default()
+}
+
+/// Longest wait for the next piece of an answer (`timeout_secs`)
+pub fn timeout() -> Duration {
+    pool().timeout
+}
+
+/// The hosts in use, for messages
+pub fn describe() -> String {
+    pool().hosts.iter().map(|h| h.url.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+/// URL of every host that serves `model`, for calls that must reach all of them
+pub fn urls(model: &str) -> Vec<&'static str> {
+    pool().hosts.iter().filter(|h| h.serves(model)).map(|h| h.url.as_str()).collect()
+}
+
+/// Hosts to try for `model`, best first: healthy ones by the strategy, then
+/// the ones marked down (one of them may be back)
+fn candidates(model: &str) -> Vec<&'static Host> {
+    let pool = pool();
+    let mut serving: Vec<&Host> = pool.hosts.iter().filter(|h| h.serves(model)).collect();
+    if serving.is_empty() {
+        serving = pool.hosts.iter().collect();
+    }
+    match pool.strategy {
+        Balance::RoundRobin => {
+            let start = pool.next.fetch_add(1, Ordering::Relaxed) % serving.len();
+            serving.rotate_left(start);
+        }
+        Balance::LeastInFlight => serving.sort_by_key(|h| h.in_flight.load(Ordering::Relaxed)),
+    }
+    // Stable: keeps the strategy's order within each group
+    serving.sort_by_key(|h| !h.healthy.load(Ordering::Relaxed));
+    serving
+}
+
+/// A call in flight on a host; the count drops when the answer has been read
+pub struct Lease(&'static Host);
+
+impl Lease {
+    fn take(host: &'static Host) -> Self {
+        host.in_flight.fetch_add(1, Ordering::Relaxed);
+        Lease(host)
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// POST `body` to `path` (e.g. "/api/chat") on a host for `model`, failing over
+/// to the next host when one cannot be reached or answers 503, and retrying
+/// when none could; the last 503 is returned as is. Keep the lease until the
+/// response has been read.
+pub async fn post<T: Serialize + ?Sized>(client: &Client, model: &str, path: &str, body: &T) -> Result<(Response, Lease)> {
+    let pool = pool();
+    let body = serde_json::to_value(body)?;
+    let mut last_error = None;
+    let mut tried = 0;
+    for round in 0..=pool.retries {
+        if round > 0 {
+            let delay = pool.retry_delay * 2u32.saturating_pow(round - 1);
+            eprintln!("WARNING: Ollama is unavailable; retrying in {} ms ({}/{})", delay.as_millis(), round, pool.retries);
+            tokio::time::sleep(delay).await;
+        }
+        let hosts = candidates(model);
+        tried = hosts.len();
+        for (i, &host) in hosts.iter().enumerate() {
+            let last = round == pool.retries && i + 1 == hosts.len();
+            let mut outgoing = body.clone();
+            guardrail::check_outgoing(&host.url, &mut outgoing)?;
+            let lease = Lease::take(host);
+            let send = client.post(format!("{}{}", host.url, path)).json(&outgoing).send();
+            let Ok(sent) = tokio::time::timeout(pool.timeout, send).await else {
+                anyhow::bail!("Ollama at {} did not answer within {} s (timeout_secs)", host.url, pool.timeout.as_secs());
+            };
+            match sent {
+                Ok(res) if res.status() == StatusCode::SERVICE_UNAVAILABLE && !last => {
+                    if i + 1 < hosts.len() {
+                        eprintln!("WARNING: Ollama at {} is unavailable; trying the next host", host.url);
+                    }
+                    host.healthy.store(false, Ordering::Relaxed);
+                }
+                Ok(res) => {
+                    host.healthy.store(true, Ordering::Relaxed);
+                    return Ok((res, lease));
+                }
+                // Refused, timed out connecting, or reset before an answer
+                Err(e) if e.is_connect() || e.is_timeout() || e.is_request() => {
+                    if i + 1 < hosts.len() {
+                        eprintln!("WARNING: cannot reach Ollama at {}; trying the next host", host.url);
+                    }
+                    host.healthy.store(false, Ordering::Relaxed);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e).with_context(|| format!("Cannot reach Ollama at {}", host.url)),
+            }
+        }
+    }
+    match last_error {
+        Some(e) => Err(e).context(match tried {
+            1 => "Cannot reach Ollama".to_string(),
+            n => format!("Cannot reach any of the {} Ollama hosts", n),
+        }),
+        None => anyhow::bail!("Every Ollama host is unavailable"),
+    }
+}
+
+/// Check every host now and then, so a host marked down comes back (and one
+/// that went down is skipped before a call has to find out); runs while serving
+pub async fn watch_health() {
+    let pool = pool();
+    if pool.hosts.len() < 2 {
+        return;
+    }
+    loop {
+        for host in &pool.hosts {
+            let up = list_models(&host.url).await.is_ok();
+            if up != host.healthy.swap(up, Ordering::Relaxed) {
+                match up {
+                    true => eprintln!("Ollama at {} is back", host.url),
+                    false => eprintln!("WARNING: Ollama at {} is down", host.url),
+                }
+            }
+        }
+        tokio::time::sleep(pool.interval).await;
+    }
+}
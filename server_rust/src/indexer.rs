@@ -4,46 +4,185 @@ use once_cell::sync::Lazy;
 use regex::Regex;
 use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use crate::ALL_CATEGORIES;
+use crate::collections;
+use crate::docid::{doc_key, is_text_file};
+use crate::freshness::{self, Manifest};
 use crate::get_cached_content;
+use crate::ingest::load_inverted_index;
+use crate::metadata::is_hidden;
+use crate::versions::DocumentVersion;
+
+static WORD_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b\w+\b").unwrap());
+
+/// Distinct lower-case words of a text
+pub fn words(text: &str) -> HashSet<String> {
+    let lower_text = text.to_lowercase();
+    WORD_RE.find_iter(&lower_text).map(|m| m.as_str().to_string()).collect()
+}
+
+/// Occurrences of each lower-case word of a text
+pub fn word_counts(text: &str) -> HashMap<String, usize> {
+    let lower_text = text.to_lowercase();
+    let mut counts = HashMap::new();
+    for m in WORD_RE.find_iter(&lower_text) {
+        *counts.entry(m.as_str().to_string()).or_default() += 1;
+    }
+    counts
+}
 
 // Uses cache
-pub static INVERTED_INDEX: Lazy<HashMap<String, Vec<String>>> = Lazy::new(|| {
-    let mut index: HashMap<String, Vec<String>> = HashMap::new();
-    let word_re = Regex::new(r"\b\w+\b").unwrap();
+pub static INVERTED_INDEX: Lazy<HashMap<String, Vec<PathBuf>>> = Lazy::new(|| {
+    // Saved by `index` (see ingest.rs): no need to read every document again
+    if let Some(index) = load_inverted_index() {
+        eprintln!("✅ Inverted index loaded from {} with {} unique words.", crate::store::store().describe(), index.len());
+        return index;
+    }
+
+    let mut index: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    let mut manifest = Manifest::new();
 
-    for category in ALL_CATEGORIES {
-        let dir = Path::new(category.folder_path());
+    for collection in collections() {
+        let dir = &collection.folder;
         if !dir.exists() { continue; }
 
         if let Ok(entries) = fs::read_dir(dir) {
             for entry in entries.flatten() {
                 let path = entry.path();
-                if path.extension().and_then(|e| e.to_str()) == Some("txt") {
+                // Removed/archived documents stay out, however often the folder is re-scanned
+                if is_text_file(&path) && !is_hidden(&path) {
                     // ← Use the cache here (so files are loaded only once)
-                    if let Ok(text) = get_cached_content(&path) {
-                        let lower_text = text.to_lowercase();
-                        for cap in word_re.captures_iter(&lower_text) {
-                            if let Some(m) = cap.get(0) {
-                                let word = m.as_str().to_string();
-                                let fname = path.file_name().unwrap().to_string_lossy().to_string();
-                                index.entry(word).or_default().push(fname);
+                    let stat = freshness::stat(&path);
+                    match get_cached_content(&path) {
+                        Ok(text) => {
+                            if let Some(stat) = stat {
+                                manifest.insert(doc_key(&path), stat);
+                            }
+                            for word in words(&text) {
+                                index.entry(word).or_default().push(path.clone());
                             }
                         }
+                        Err(e) => eprintln!("WARNING: skipping {}: {:#}", path.display(), e),
                     }
                 }
             }
         }
     }
 
-    // Deduplicate filename lists
+    // Deduplicate file lists
     for files in index.values_mut() {
         let set: HashSet<_> = files.drain(..).collect();
         *files = set.into_iter().collect();
     }
 
-    println!("✅ Inverted index built with {} unique words. All files cached.", index.len());
+    freshness::record(manifest);
+    eprintln!("✅ Inverted index built with {} unique words. All files cached.", index.len());
+    index
+});
+
+/// Word (or identifier) → documents containing it
+pub type Postings = HashMap<String, Vec<PathBuf>>;
+
+/// Tenant key of a document: the tenant owning the collection it is in (None
+/// for the collections no tenant owns); no key at all outside every collection
+pub fn tenant_key(path: &Path) -> Option<Option<String>> {
+    collections()
+        .iter()
+        .find(|c| !c.folder.as_os_str().is_empty() && path.starts_with(&c.folder))
+        .map(|c| c.tenant.clone())
+}
+
+/// The inverted index split by tenant key: a lookup for one tenant never meets
+/// another tenant's documents, nor counts them in document frequencies
+pub static TENANT_INDEXES: Lazy<HashMap<Option<String>, Postings>> = Lazy::new(|| {
+    let mut keys: HashMap<&PathBuf, Option<Option<String>>> = HashMap::new();
+    let mut indexes: HashMap<Option<String>, Postings> = HashMap::new();
+    for (word, paths) in INVERTED_INDEX.iter() {
+        for path in paths {
+            if let Some(key) = keys.entry(path).or_insert_with(|| tenant_key(path)) {
+                indexes.entry(key.clone()).or_default().entry(word.clone()).or_default().push(path.clone());
+            }
+        }
+    }
+    indexes
+});
+
+static NO_DOCUMENTS: Lazy<Postings> = Lazy::new(HashMap::new);
+
+/// Word → documents of `tenant` (None: of the collections no tenant owns)
+pub fn tenant_index(tenant: Option<&str>) -> &'static Postings {
+    TENANT_INDEXES.get(&tenant.map(str::to_string)).unwrap_or(&NO_DOCUMENTS)
+}
+
+/// Document numbers such as INV-2025-001, PO-2025-4410 or C-77
+static DOC_NUMBER_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b[A-Z]{1,5}-\d{2,}(?:[-/][A-Z0-9]+)*\b").unwrap());
+
+/// IBANs, printed with or without spaces every four characters
+static IBAN_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b[A-Z]{2}\d{2}(?: ?[A-Z0-9]{4}){2,7}(?: ?[A-Z0-9]{1,4})?\b").unwrap());
+
+/// Any token with a digit in it, as typed in a question
+static TOKEN_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"[A-Z0-9][A-Z0-9/\-]*\d[A-Z0-9/\-]*").unwrap());
+
+/// Canonical form of an identifier: upper case, no spaces, no trailing punctuation
+pub fn normalize_identifier(id: &str) -> String {
+    id.chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .trim_end_matches(['.', ',', '-', '/'])
+        .to_uppercase()
+}
+
+/// Identifiers (document numbers, IBANs) appearing in a document
+pub fn document_identifiers(text: &str) -> Vec<String> {
+    let mut ids: Vec<String> = DOC_NUMBER_RE
+        .find_iter(text)
+        .chain(IBAN_RE.find_iter(text))
+        .map(|m| normalize_identifier(m.as_str()))
+        .filter(|id| !id.is_empty())
+        .collect();
+    // Numbers without letters, e.g. "Invoice No: 2025/4567"
+    if let Some(id) = DocumentVersion::from_text(Path::new(""), text).id {
+        ids.push(id);
+    }
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+/// Candidate identifiers in a question (only those present in the index matter)
+pub fn query_identifiers(query: &str) -> Vec<String> {
+    let upper = query.to_uppercase();
+    TOKEN_RE
+        .find_iter(&upper)
+        .chain(IBAN_RE.find_iter(&upper))
+        .map(|m| normalize_identifier(m.as_str()))
+        .collect()
+}
+
+/// Exact identifier → documents containing it, by tenant key as `TENANT_INDEXES`
+pub static IDENTIFIER_INDEX: Lazy<HashMap<Option<String>, Postings>> = Lazy::new(|| {
+    TENANT_INDEXES.iter().map(|(tenant, words)| (tenant.clone(), identifiers(words))).collect()
+});
+
+/// Identifier → documents of `tenant` (None: of the collections no tenant owns)
+pub fn identifier_index(tenant: Option<&str>) -> &'static Postings {
+    IDENTIFIER_INDEX.get(&tenant.map(str::to_string)).unwrap_or(&NO_DOCUMENTS)
+}
+
+fn identifiers(words: &Postings) -> Postings {
+    let mut index = Postings::new();
+    let mut paths: Vec<&PathBuf> = words.values().flatten().collect();
+    paths.sort();
+    paths.dedup();
+
+    for path in paths {
+        if let Ok(text) = get_cached_content(path) {
+            for id in document_identifiers(&text) {
+                index.entry(id).or_default().push(path.clone());
+            }
+        }
+    }
     index
-});
\ No newline at end of file
+}
@@ -0,0 +1,367 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Batch ingestion for large folders (thousands of documents).
+// Files are streamed through bounded queues (scanner → readers → index
+// writer), so memory use depends on the queue sizes, not on the corpus.
+// Progress is checkpointed to the store ("index") every few hundred files;
+// an interrupted run resumes where it stopped, and unchanged files
+// (same size and modification time) are never read again.
+// A document that takes longer than the timeout to read counts as failed;
+// one that fails in several runs in a row is quarantined (skipped) until it
+// changes on disk or `--retry-quarantined` is given.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::sync::{mpsc, Mutex};
+
+use crate::collections;
+use crate::docid::{doc_key, doc_path, is_text_file};
+use crate::freshness::{self, Manifest};
+use crate::indexer::words;
+use crate::lock::{lock_index, LockMode};
+use crate::metadata::is_hidden;
+use crate::reader::stream_chunks;
+use crate::store::{self, store};
+
+/// Bumped when the on-disk layout changes, with a migration in migrate.rs
+pub const INDEX_VERSION: u32 = 1;
+
+#[derive(Debug, Clone)]
+pub struct IngestOptions {
+    /// Files read concurrently
+    pub jobs: usize,
+    /// Capacity of each queue between the stages (the backpressure bound)
+    pub queue: usize,
+    /// Save the index after this many new or changed files
+    pub checkpoint_every: usize,
+    /// Ignore the existing index and start over
+    pub fresh: bool,
+    /// Stop at the first unreadable file instead of skipping it
+    pub strict: bool,
+    /// Time allowed for reading one document
+    pub timeout: Duration,
+    /// Failed runs in a row after which a document is quarantined
+    pub max_failures: u32,
+    /// Try the quarantined documents again
+    pub retry_quarantined: bool,
+}
+
+impl Default for IngestOptions {
+    fn default() -> Self {
+        Self {
+            jobs: 4,
+            queue: 64,
+            checkpoint_every: 250,
+            fresh: false,
+            strict: false,
+            timeout: Duration::from_secs(60),
+            max_failures: 3,
+            retry_quarantined: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexedFile {
+    pub path: String,
+    pub size: u64,
+    /// Modification time, Unix seconds
+    pub modified: u64,
+}
+
+/// A document that failed to load, as it was on disk when it failed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FailedFile {
+    pub size: u64,
+    pub modified: u64,
+    /// Failed runs in a row
+    pub count: u32,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct IndexFile {
+    pub version: u32,
+    pub next_id: u32,
+    pub files: BTreeMap<u32, IndexedFile>,
+    /// word → ids of the files containing it
+    pub postings: BTreeMap<String, Vec<u32>>,
+    /// path → last failure, for the documents that did not load
+    pub failures: BTreeMap<String, FailedFile>,
+}
+
+/// Outcome of an ingestion run
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct IngestReport {
+    pub indexed: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    /// Files indexed in this run, new or changed since the last one
+    pub changed: Vec<PathBuf>,
+    /// Files that could not be read and were skipped, with the reason
+    pub failed: Vec<(String, String)>,
+    /// Files skipped after failing `max_failures` runs in a row, with the last reason
+    pub quarantined: Vec<(String, String)>,
+}
+
+struct Candidate {
+    path: PathBuf,
+    size: u64,
+    modified: u64,
+}
+
+enum Loaded {
+    Words(Candidate, HashSet<String>),
+    Failed(Candidate, String),
+}
+
+impl IndexFile {
+    pub fn load() -> Result<Option<Self>> {
+        let Some(text) = store().load(store::INDEX)? else { return Ok(None) };
+        let index: Self = serde_json::from_str(&text).with_context(|| format!("Invalid index in {}", store().describe()))?;
+        if index.version != INDEX_VERSION {
+            anyhow::bail!(
+                "The index in {} has format version {}, expected {}; run `migrate` (or `index --fresh`)",
+                store().describe(),
+                index.version,
+                INDEX_VERSION
+            );
+        }
+        Ok(Some(index))
+    }
+
+    /// Saved whole (atomically in every store), so an interruption mid-save keeps the previous checkpoint
+    pub fn save(&self) -> Result<()> {
+        store().save(store::INDEX, &serde_json::to_string(self)?)
+    }
+
+    fn remove_files(&mut self, remove: &HashSet<u32>) {
+        self.files.retain(|id, _| !remove.contains(id));
+        self.postings.retain(|_, ids| {
+            ids.retain(|i| !remove.contains(i));
+            !ids.is_empty()
+        });
+    }
+
+    /// Size and modification time of every document the index was built from (failed ones included)
+    pub fn manifest(&self) -> Manifest {
+        let files = self.files.values().map(|f| (f.path.clone(), (f.size, f.modified)));
+        files.chain(self.failures.iter().map(|(path, f)| (path.clone(), (f.size, f.modified)))).collect()
+    }
+
+    /// The in-memory form used for retrieval (word → paths), hidden documents left out
+    pub fn to_inverted_index(&self) -> HashMap<String, Vec<PathBuf>> {
+        let paths: HashMap<u32, PathBuf> = self
+            .files
+            .iter()
+            .map(|(id, f)| (*id, doc_path(&f.path)))
+            .filter(|(_, p)| !is_hidden(p))
+            .collect();
+        self.postings
+            .iter()
+            .map(|(word, ids)| (word.clone(), ids.iter().filter_map(|id| paths.get(id).cloned()).collect::<Vec<_>>()))
+            .filter(|(_, files)| !files.is_empty())
+            .collect()
+    }
+}
+
+/// The index saved by the last `index` run, if any
+pub fn load_inverted_index() -> Option<HashMap<String, Vec<PathBuf>>> {
+    match lock_index(LockMode::Shared).and_then(|_lock| IndexFile::load()) {
+        Ok(index) => index.map(|i| {
+            freshness::record(i.manifest());
+            i.to_inverted_index()
+        }),
+        Err(e) => {
+            eprintln!("WARNING: {:#}; rebuilding the index in memory", e);
+            None
+        }
+    }
+}
+
+/// Walk every collection folder (blocking), queueing new or changed documents
+/// that are not quarantined. Returns every document seen, so vanished ones can
+/// be dropped afterwards, and the number left alone.
+fn scan(
+    known: HashMap<String, (u64, u64)>,
+    quarantined: HashMap<String, (u64, u64)>,
+    tx: mpsc::Sender<Candidate>,
+) -> (HashSet<String>, usize) {
+    let mut seen = HashSet::new();
+    let mut unchanged = 0;
+
+    for collection in collections() {
+        let Ok(entries) = fs::read_dir(&collection.folder) else { continue };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !is_text_file(&path) || is_hidden(&path) {
+                continue;
+            }
+            let Ok(meta) = entry.metadata() else { continue };
+            let size = meta.len();
+            let modified = meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+
+            let k = doc_key(&path);
+            let is_unchanged = known.get(&k) == Some(&(size, modified));
+            let is_quarantined = quarantined.get(&k) == Some(&(size, modified));
+            seen.insert(k);
+            if is_quarantined {
+                continue;
+            }
+            if is_unchanged {
+                unchanged += 1;
+                continue;
+            }
+            // Blocks while the readers are behind: this is the backpressure
+            if tx.blocking_send(Candidate { path, size, modified }).is_err() {
+                return (seen, unchanged);
+            }
+        }
+    }
+    (seen, unchanged)
+}
+
+/// Build or update the persistent index. `progress` is called after every checkpoint.
+pub async fn ingest(options: &IngestOptions, progress: impl Fn(&IngestReport)) -> Result<IngestReport> {
+    let mut index = if options.fresh { None } else { IndexFile::load()? }.unwrap_or_default();
+    index.version = INDEX_VERSION;
+
+    let mut ids: HashMap<String, u32> = index.files.iter().map(|(id, f)| (f.path.clone(), *id)).collect();
+    let known: HashMap<String, (u64, u64)> =
+        index.files.values().map(|f| (f.path.clone(), (f.size, f.modified))).collect();
+    let max_failures = options.max_failures.max(1);
+    if options.retry_quarantined {
+        index.failures.clear();
+    }
+    let quarantined: HashMap<String, (u64, u64)> = index
+        .failures
+        .iter()
+        .filter(|(_, f)| f.count >= max_failures)
+        .map(|(path, f)| (path.clone(), (f.size, f.modified)))
+        .collect();
+
+    let queue = options.queue.max(1);
+    let (path_tx, path_rx) = mpsc::channel::<Candidate>(queue);
+    let (loaded_tx, mut loaded_rx) = mpsc::channel::<Loaded>(queue);
+
+    let scanner = tokio::task::spawn_blocking(move || scan(known, quarantined, path_tx));
+
+    let path_rx = Arc::new(Mutex::new(path_rx));
+    for _ in 0..options.jobs.max(1) {
+        let path_rx = Arc::clone(&path_rx);
+        let loaded_tx = loaded_tx.clone();
+        let timeout = options.timeout;
+        tokio::spawn(async move {
+            loop {
+                let Some(candidate) = path_rx.lock().await.recv().await else { break };
+                let path = candidate.path.clone();
+                let reading = tokio::task::spawn_blocking(move || -> Result<HashSet<String>> {
+                    let mut found = HashSet::new();
+                    for chunk in stream_chunks(&path)? {
+                        found.extend(words(&chunk?.1));
+                    }
+                    Ok(found)
+                });
+                // A blocking read cannot be cancelled: on timeout it is abandoned, and the job moves on
+                let loaded = match tokio::time::timeout(timeout, reading).await {
+                    Ok(Ok(Ok(found))) => Loaded::Words(candidate, found),
+                    Ok(Ok(Err(e))) => Loaded::Failed(candidate, format!("{:#}", e)),
+                    Ok(Err(e)) => Loaded::Failed(candidate, e.to_string()),
+                    Err(_) => Loaded::Failed(candidate, format!("timed out after {} s", timeout.as_secs())),
+                };
+                if loaded_tx.send(loaded).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(loaded_tx);
+
+    let mut report = IngestReport::default();
+    let mut since_checkpoint = 0;
+
+    while let Some(loaded) = loaded_rx.recv().await {
+        let (candidate, words) = match loaded {
+            Loaded::Words(c, w) => (c, w),
+            Loaded::Failed(c, reason) if options.strict => {
+                // Keep what was indexed so far; a rerun resumes from here
+                index.save()?;
+                anyhow::bail!("Cannot index {}: {}", c.path.display(), reason);
+            }
+            Loaded::Failed(c, reason) => {
+                let failure = index.failures.entry(doc_key(&c.path)).or_insert(FailedFile {
+                    size: c.size,
+                    modified: c.modified,
+                    count: 0,
+                    reason: String::new(),
+                });
+                // A document changed since its last failure starts counting again
+                if (failure.size, failure.modified) != (c.size, c.modified) {
+                    *failure = FailedFile { size: c.size, modified: c.modified, count: 0, reason: String::new() };
+                }
+                failure.count += 1;
+                failure.reason = reason.clone();
+                report.failed.push((c.path.display().to_string(), reason));
+                continue;
+            }
+        };
+
+        let k = doc_key(&candidate.path);
+        index.failures.remove(&k);
+        let id = match ids.get(&k) {
+            Some(&id) => {
+                index.remove_files(&HashSet::from([id]));
+                id
+            }
+            None => {
+                let id = index.next_id;
+                index.next_id += 1;
+                ids.insert(k.clone(), id);
+                id
+            }
+        };
+        index.files.insert(id, IndexedFile { path: k, size: candidate.size, modified: candidate.modified });
+        for word in words {
+            index.postings.entry(word).or_default().push(id);
+        }
+
+        report.changed.push(candidate.path.clone());
+        report.indexed += 1;
+        since_checkpoint += 1;
+        if since_checkpoint >= options.checkpoint_every {
+            index.save()?;
+            since_checkpoint = 0;
+            progress(&report);
+        }
+    }
+
+    let (seen, unchanged) = scanner.await.context("Folder scan failed")?;
+    report.unchanged = unchanged;
+
+    // Documents deleted from disk (or removed/archived) leave the index
+    let vanished: HashSet<u32> = index.files.iter().filter(|(_, f)| !seen.contains(&f.path)).map(|(id, _)| *id).collect();
+    index.remove_files(&vanished);
+    report.removed = vanished.len();
+    index.failures.retain(|path, _| seen.contains(path));
+    report.quarantined = index
+        .failures
+        .iter()
+        .filter(|(_, f)| f.count >= max_failures)
+        .map(|(path, f)| (path.clone(), f.reason.clone()))
+        .collect();
+
+    index.save()?;
+    progress(&report);
+    Ok(report)
+}
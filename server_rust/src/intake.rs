@@ -0,0 +1,237 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Document intake: writing incoming documents (mail attachments, uploads, ...)
+// into a collection's folder so the next index run picks them up.
+//
+// Intake is idempotent whichever way a document comes in (files, upload,
+// mail, queue, connectors): bytes whose SHA-256 matches a document already in
+// the collection are that document, under whatever name they arrive. A sender
+// that knows its documents by a key of its own (a Drive file id, a path) can
+// pass it: new bytes under a key seen before replace the document saved for
+// it, as its updated version. Without a key, a name that is taken by other
+// content gets a numbered name. The checksums and keys are kept in the store
+// (`intake`); files put into a folder by hand are hashed when intake next
+// looks at that folder.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::docid::{doc_key, doc_path};
+use crate::quotas;
+use crate::storage::sha256;
+use crate::store::{self, store};
+use crate::Collection;
+
+/// What happened to one incoming document
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum IngestOutcome {
+    /// Written under this file name
+    Saved(String),
+    /// Identical content already present under this file name
+    Duplicate(String),
+    /// New content for the key's document, written over this file
+    Updated(String),
+    /// Not ingested, with the reason
+    Skipped(String),
+}
+
+impl IngestOutcome {
+    /// The file the document is in, unless it was skipped
+    pub fn file(&self) -> Option<&str> {
+        match self {
+            IngestOutcome::Saved(file) | IngestOutcome::Duplicate(file) | IngestOutcome::Updated(file) => Some(file),
+            IngestOutcome::Skipped(_) => None,
+        }
+    }
+
+    /// Whether the collection changed (saved or updated)
+    pub fn is_new_content(&self) -> bool {
+        matches!(self, IngestOutcome::Saved(_) | IngestOutcome::Updated(_))
+    }
+}
+
+/// Answer of POST /documents
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct IntakeResult {
+    pub collection: String,
+    pub outcome: IngestOutcome,
+    /// SHA-256 of the bytes received, hex
+    pub sha256: String,
+}
+
+/// A document's checksum, with the size and modification time it was taken at
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub size: u64,
+    pub modified: u64,
+    pub sha256: String,
+}
+
+/// The `intake` store document
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct IntakeRegistry {
+    /// Document id → checksum
+    pub files: BTreeMap<String, Checksum>,
+    /// Sender's key → document id
+    pub keys: BTreeMap<String, String>,
+}
+
+fn stat(path: &Path) -> Option<(u64, u64)> {
+    let meta = fs::metadata(path).ok().filter(|m| m.is_file())?;
+    let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    Some((meta.len(), modified))
+}
+
+impl IntakeRegistry {
+    pub fn load() -> Result<Self> {
+        match store().load(store::INTAKE)? {
+            Some(text) => serde_json::from_str(&text).with_context(|| format!("Invalid intake registry in {}", store().describe())),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Bring the checksums of `folder` up to date: files changed on disk are hashed again, vanished ones dropped
+    pub fn refresh(&mut self, folder: &Path) {
+        self.files.retain(|id, _| {
+            let path = doc_path(id);
+            path.parent() != Some(folder) || path.is_file()
+        });
+        let Ok(entries) = fs::read_dir(folder) else { return };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.file_name().is_some_and(|n| n.to_string_lossy().starts_with('.')) {
+                continue;
+            }
+            let Some((size, modified)) = stat(&path) else { continue };
+            let id = doc_key(&path);
+            if self.files.get(&id).is_some_and(|c| (c.size, c.modified) == (size, modified)) {
+                continue;
+            }
+            match fs::read(&path) {
+                Ok(bytes) => {
+                    self.files.insert(id, Checksum { size, modified, sha256: sha256(&bytes) });
+                }
+                Err(_) => {
+                    self.files.remove(&id);
+                }
+            }
+        }
+    }
+
+    /// The file in `folder` with this content, the one called `name` first
+    pub fn find(&self, folder: &Path, sha: &str, name: &str) -> Option<String> {
+        let mut found: Vec<String> = self
+            .files
+            .iter()
+            .filter(|(_, c)| c.sha256 == sha)
+            .map(|(id, _)| doc_path(id))
+            .filter(|p| p.parent() == Some(folder))
+            .filter_map(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+            .collect();
+        found.sort_by_key(|f| f.as_str() != name);
+        found.into_iter().next()
+    }
+
+    fn record(&mut self, path: &Path, sha: &str) {
+        if let Some((size, modified)) = stat(path) {
+            self.files.insert(doc_key(path), Checksum { size, modified, sha256: sha.to_string() });
+        }
+    }
+}
+
+/// Keep only characters that are safe in a file name on every platform
+pub fn sanitize_file_name(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = base
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+        .collect();
+    cleaned.trim_start_matches('.').to_string()
+}
+
+/// Write `bytes` into the collection folder as `name`: a duplicate when the collection already has
+/// the same content, a numbered name when `name` is taken by other content
+pub fn ingest_bytes(collection: &Collection, name: &str, bytes: &[u8], extensions: &[String]) -> Result<IngestOutcome> {
+    ingest_document(collection, name, None, bytes, extensions)
+}
+
+/// `ingest_bytes` for a document the sender knows by `key`: new content under a key
+/// seen before is written over the key's document
+pub fn ingest_document(
+    collection: &Collection,
+    name: &str,
+    key: Option<&str>,
+    bytes: &[u8],
+    extensions: &[String],
+) -> Result<IngestOutcome> {
+    let name = sanitize_file_name(name);
+    let extension = Path::new(&name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    if name.is_empty() || !extensions.iter().any(|e| e.eq_ignore_ascii_case(&extension)) {
+        return Ok(IngestOutcome::Skipped(format!("'{}' is not one of: {}", name, extensions.join(", "))));
+    }
+
+    if let Some(tenant) = &collection.tenant
+        && !quotas::storage_allows(tenant, bytes.len() as u64)
+    {
+        return Ok(IngestOutcome::Skipped(format!("'{}' would take tenant '{}' past its storage quota", name, tenant)));
+    }
+
+    fs::create_dir_all(&collection.folder)
+        .with_context(|| format!("Failed to create folder: {}", collection.folder.display()))?;
+
+    // A key is only looked up within its collection
+    let key = key.map(|k| format!("{}:{}", collection.name, k));
+    let sha = sha256(bytes);
+    let mut outcome = None;
+    store().update(store::INTAKE, &mut |text| {
+        let mut registry: IntakeRegistry = match text {
+            Some(text) => serde_json::from_str(&text).context("Invalid intake registry")?,
+            None => IntakeRegistry::default(),
+        };
+        registry.refresh(&collection.folder);
+        let keyed = key.as_ref().and_then(|k| registry.keys.get(k)).map(|id| doc_path(id));
+        let placed = if let Some(existing) = registry.find(&collection.folder, &sha, &name) {
+            IngestOutcome::Duplicate(existing)
+        } else if let Some(path) = keyed.filter(|p| p.parent() == Some(collection.folder.as_path()) && p.is_file()) {
+            fs::write(&path, bytes).with_context(|| format!("Failed to write {}", path.display()))?;
+            registry.record(&path, &sha);
+            IngestOutcome::Updated(path.file_name().unwrap_or_default().to_string_lossy().into_owned())
+        } else {
+            let stem = name.strip_suffix(&format!(".{}", extension)).unwrap_or(&name).to_string();
+            let mut candidate = name.clone();
+            let mut n = 0;
+            while collection.folder.join(&candidate).exists() {
+                n += 1;
+                candidate = format!("{}-{}.{}", stem, n, extension);
+            }
+            let path = collection.folder.join(&candidate);
+            fs::write(&path, bytes).with_context(|| format!("Failed to write {}", path.display()))?;
+            registry.record(&path, &sha);
+            IngestOutcome::Saved(candidate)
+        };
+        if let (Some(key), Some(file)) = (&key, placed.file()) {
+            registry.keys.insert(key.clone(), doc_key(&collection.folder.join(file)));
+        }
+        outcome = Some(placed);
+        Ok(serde_json::to_string(&registry)?)
+    })?;
+    Ok(outcome.expect("set by the update"))
+}
+
+/// `ingest_document` with the checksum of what was received, as POST /documents answers
+pub fn receive(collection: &Collection, name: &str, key: Option<&str>, bytes: &[u8], extensions: &[String]) -> Result<IntakeResult> {
+    let outcome = ingest_document(collection, name, key, bytes, extensions)?;
+    Ok(IntakeResult { collection: collection.name.clone(), outcome, sha256: sha256(bytes) })
+}
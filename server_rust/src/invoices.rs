@@ -0,0 +1,125 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Typed read access to the extracted invoice data for Rust programs
+// (dashboards, scripts), without the model and without parsing JSON:
+//
+//   for invoice in invoices().by_vendor("acme").in_period("2025-Q3") {
+//       println!("{} {:?}", invoice.file, invoice.gross);
+//   }
+//
+// The records are the ones the reports use (records.rs), read from the
+// documents of the invoice collections when iterated; removed, archived and
+// superseded documents are left out as they are there.
+
+use rust_decimal::Decimal;
+
+use crate::aggregate::in_period;
+use crate::fold::contains_folded;
+use crate::lifecycle::{selected, InvoiceStatus};
+use crate::records::{collection_records, InvoiceRecord};
+use crate::versions::DocumentKind;
+use crate::{collections, Collection};
+
+/// One invoice (or credit note) as extracted
+pub type Invoice = InvoiceRecord;
+
+/// A selection of invoices, narrowed step by step; iterate it for the records
+#[derive(Debug, Clone, Default)]
+pub struct Invoices {
+    collection: Option<String>,
+    vendor: Option<String>,
+    period: Option<String>,
+    statuses: Vec<InvoiceStatus>,
+    kind: Option<DocumentKind>,
+    include_superseded: bool,
+}
+
+/// Every invoice of the collections with VAT checks
+pub fn invoices() -> Invoices {
+    Invoices::default()
+}
+
+impl Invoices {
+    /// Only this collection (name or alias), whether it has VAT checks or not
+    pub fn in_collection(mut self, name: &str) -> Self {
+        self.collection = Some(name.to_string());
+        self
+    }
+
+    /// Only vendors whose name contains this (ignoring case and accents: "Müller" is "Mueller")
+    pub fn by_vendor(mut self, vendor: &str) -> Self {
+        self.vendor = Some(vendor.to_string());
+        self
+    }
+
+    /// Only invoices dated in the period ("2025", "2025-11" or "2025-Q3")
+    pub fn in_period(mut self, period: &str) -> Self {
+        self.period = Some(period.to_string());
+        self
+    }
+
+    /// Only invoices in one of these statuses
+    pub fn with_status(mut self, statuses: &[InvoiceStatus]) -> Self {
+        self.statuses = statuses.to_vec();
+        self
+    }
+
+    /// Only originals, corrections or credit notes
+    pub fn of_kind(mut self, kind: DocumentKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Keep documents replaced by a correction or credit note
+    pub fn include_superseded(mut self) -> Self {
+        self.include_superseded = true;
+        self
+    }
+
+    fn collections(&self) -> impl Iterator<Item = &'static Collection> + '_ {
+        collections().iter().filter(|c| match &self.collection {
+            Some(name) => c.matches(name),
+            None => c.vat_check,
+        })
+    }
+
+    /// Whether a record passes every filter but the collection
+    pub fn accepts(&self, invoice: &Invoice) -> bool {
+        let vendor = invoice.vendor.as_deref().unwrap_or_default();
+        self.vendor.as_ref().is_none_or(|v| contains_folded(vendor, v))
+            && self.period.as_ref().is_none_or(|p| invoice.date.as_deref().is_some_and(|d| in_period(d, p)))
+            && selected(&self.statuses, invoice.status)
+            && self.kind.is_none_or(|k| invoice.kind == k)
+    }
+
+    /// The selected invoices, one collection read at a time
+    pub fn iter(&self) -> impl Iterator<Item = Invoice> + '_ {
+        self.collections().flat_map(|c| collection_records(c, self.include_superseded)).filter(|r| self.accepts(r))
+    }
+
+    pub fn count(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Sum of the gross amounts (credit notes count negative); invoices without one are left out
+    pub fn total_gross(&self) -> Decimal {
+        self.iter().filter_map(|r| r.gross).sum()
+    }
+
+    /// Distinct vendor names, sorted
+    pub fn vendors(&self) -> Vec<String> {
+        let mut vendors: Vec<String> = self.iter().filter_map(|r| r.vendor).collect();
+        vendors.sort();
+        vendors.dedup();
+        vendors
+    }
+}
+
+impl IntoIterator for Invoices {
+    type Item = Invoice;
+    type IntoIter = std::vec::IntoIter<Invoice>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter().collect::<Vec<_>>().into_iter()
+    }
+}
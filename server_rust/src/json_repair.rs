@@ -0,0 +1,299 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Repair of almost-JSON as produced by language models: markdown fences and
+// prose around the object, trailing commas, unquoted or single-quoted keys,
+// Python literals, comments, raw newlines in strings, and output cut off
+// before the closing braces. Independent of the rest of the crate.
+
+use serde_json::Value;
+
+/// The text inside the first markdown code fence, or the text itself
+pub fn strip_fences(text: &str) -> &str {
+    let Some(start) = text.find("```") else { return text.trim() };
+    let after = &text[start + 3..];
+    // Skip the info string ("json", "JSON5"...) up to the end of the fence line
+    let body = match after.find('\n') {
+        Some(newline) if after[..newline].trim().chars().all(|c| c.is_ascii_alphanumeric()) => &after[newline + 1..],
+        _ => after,
+    };
+    match body.find("```") {
+        Some(end) => body[..end].trim(),
+        None => body.trim(),
+    }
+}
+
+/// From the first `{` or `[` to its matching closer (or to the end, when it is never closed)
+pub fn extract_json(text: &str) -> &str {
+    let Some(start) = text.find(['{', '[']) else { return text };
+    match closer(&text[start..]) {
+        Some(end) => &text[start..start + end + 1],
+        None => &text[start..],
+    }
+}
+
+/// Whether the text stops inside its first object or array, as output cut off
+/// at the token limit does
+pub fn is_cut_off(text: &str) -> bool {
+    let text = strip_fences(text);
+    text.find(['{', '[']).is_some_and(|start| closer(&text[start..]).is_none())
+}
+
+/// Byte index of the closer matching the `{` or `[` that starts `text`
+fn closer(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if let Some(q) = quote {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                c if c == q => quote = None,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Read a string literal starting at `chars[start]` (either quote) and write
+/// it double-quoted; returns the index after it. Unterminated strings are closed.
+fn read_string(chars: &[char], start: usize, out: &mut String) -> usize {
+    let quote = chars[start];
+    out.push('"');
+    let mut i = start + 1;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' if i + 1 < chars.len() => {
+                let next = chars[i + 1];
+                if next == '\'' {
+                    out.push('\'');
+                } else {
+                    out.push('\\');
+                    out.push(next);
+                }
+                i += 2;
+                continue;
+            }
+            c if c == quote => {
+                out.push('"');
+                return i + 1;
+            }
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+        i += 1;
+    }
+    out.push('"');
+    i
+}
+
+/// Drop a trailing comma (and whitespace) from the output
+fn trim_trailing_comma(out: &mut String) {
+    let trimmed = out.trim_end().len();
+    if out[..trimmed].ends_with(',') {
+        out.truncate(trimmed - 1);
+    }
+}
+
+fn last_significant(out: &str) -> Option<char> {
+    out.trim_end().chars().next_back()
+}
+
+fn next_significant(chars: &[char], from: usize) -> Option<char> {
+    chars[from..].iter().copied().find(|c| !c.is_whitespace())
+}
+
+/// Rewrite almost-JSON into JSON. Valid JSON comes back unchanged (apart from
+/// the surrounding text); anything beyond repair still comes back, for the
+/// caller's parser to reject.
+pub fn repair(text: &str) -> String {
+    let body = extract_json(strip_fences(text));
+    if !body.starts_with(['{', '[']) {
+        return body.to_string();
+    }
+    let chars: Vec<char> = body.chars().collect();
+    let mut out = String::with_capacity(chars.len() + 8);
+    let mut closers: Vec<char> = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '"' | '\'' => {
+                i = read_string(&chars, i, &mut out);
+                continue;
+            }
+            '/' if chars.get(i + 1) == Some(&'/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i += 2;
+                continue;
+            }
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '{' => {
+                closers.push('}');
+                out.push(c);
+            }
+            '[' => {
+                closers.push(']');
+                out.push(c);
+            }
+            '}' | ']' => {
+                // A closer for an outer level closes the inner ones too; a stray one is dropped
+                if closers.contains(&c) {
+                    while let Some(closer) = closers.pop() {
+                        trim_trailing_comma(&mut out);
+                        if last_significant(&out) == Some(':') {
+                            out.push_str("null");
+                        }
+                        out.push(closer);
+                        if closer == c {
+                            break;
+                        }
+                    }
+                }
+            }
+            ',' => {
+                // No empty elements: "[1,,2]", "{,"
+                if !matches!(last_significant(&out), Some(',') | Some('[') | Some('{') | None) {
+                    out.push(',');
+                }
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '+' => {
+                // Read the whole number, so an exponent is not taken for a bare word
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || matches!(chars[i], '.' | 'e' | 'E' | '+' | '-')) {
+                    i += 1;
+                }
+                let number: String = chars[start..i].iter().collect();
+                out.push_str(number.strip_prefix('+').unwrap_or(&number));
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' || c == '$' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '$' | '-' | '.')) {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                if next_significant(&chars, i) == Some(':') {
+                    out.push_str(&serde_json::to_string(&word).unwrap_or_default());
+                } else {
+                    match word.as_str() {
+                        "true" | "True" | "TRUE" => out.push_str("true"),
+                        "false" | "False" | "FALSE" => out.push_str("false"),
+                        "null" | "None" | "NULL" | "undefined" | "NaN" | "Infinity" => out.push_str("null"),
+                        _ => out.push_str(&serde_json::to_string(&word).unwrap_or_default()),
+                    }
+                }
+                continue;
+            }
+            c => out.push(c),
+        }
+        i += 1;
+    }
+
+    // Output cut off: close what is still open
+    trim_trailing_comma(&mut out);
+    if last_significant(&out) == Some(':') {
+        out.push_str("null");
+    }
+    while let Some(closer) = closers.pop() {
+        trim_trailing_comma(&mut out);
+        out.push(closer);
+    }
+    out
+}
+
+/// Parse model output as JSON, repairing it when it is not valid as it is
+pub fn parse_lenient(text: &str) -> Option<Value> {
+    serde_json::from_str(text.trim()).ok().or_else(|| serde_json::from_str(&repair(text)).ok())
+}
+
+/// Incremental parsing of JSON as it is streamed in. After each piece, the
+/// value made of the fields and items completed so far: a string or number
+/// still being written is left out, open objects and arrays are closed.
+#[derive(Debug, Default)]
+pub struct PartialJson {
+    text: String,
+    depth: usize,
+    quote: Option<char>,
+    escaped: bool,
+    /// Length of the text up to which every value is complete
+    complete: usize,
+    last: Option<Value>,
+}
+
+impl PartialJson {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a piece of the text; returns the value so far when it has gained a field or item
+    pub fn push(&mut self, piece: &str) -> Option<Value> {
+        let offset = self.text.len();
+        let before = self.complete;
+        self.text.push_str(piece);
+        for (i, c) in piece.char_indices() {
+            if let Some(q) = self.quote {
+                match c {
+                    _ if self.escaped => self.escaped = false,
+                    '\\' => self.escaped = true,
+                    c if c == q => self.quote = None,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' | '\'' if self.depth > 0 => self.quote = Some(c),
+                '{' | '[' => self.depth += 1,
+                // The value before the comma is complete; the comma is dropped by `repair`
+                ',' if self.depth > 0 => self.complete = offset + i,
+                '}' | ']' if self.depth > 0 => {
+                    self.depth -= 1;
+                    self.complete = offset + i + 1;
+                }
+                _ => {}
+            }
+        }
+        if self.complete == before {
+            return None;
+        }
+        let value = parse_lenient(&self.text[..self.complete])?;
+        let empty = value.as_object().is_some_and(|o| o.is_empty()) || value.as_array().is_some_and(|a| a.is_empty());
+        if empty || self.last.as_ref() == Some(&value) {
+            return None;
+        }
+        self.last = Some(value.clone());
+        Some(value)
+    }
+}
@@ -1,22 +1,280 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 pub mod ai;
-pub use ai::query_ollama;
+pub use ai::{Answer, AnswerStatus, ChatMessage, OllamaApi, Strictness, DEFAULT_TEMPERATURE};
+#[cfg(feature = "async")]
+pub use ai::{query_ollama, query_ollama_chat};
+
+#[cfg(feature = "minimal")]
+pub mod blocking;
+#[cfg(feature = "minimal")]
+pub use blocking::BlockingClient;
+
+pub mod calc;
 
 pub mod cache;
 pub use cache::get_cached_content;
 
-pub mod cla;
-pub use cla::Args;
-pub use clap::Parser;
+pub mod chunking;
+pub use chunking::{Chunk, Citation};
 
 pub mod data;
 pub use data::{Category, ALL_CATEGORIES};
 
-pub mod indexer;
+pub mod docid;
+pub use docid::DocId;
+
+pub mod encryption;
+pub use encryption::EncryptionConfig;
+
+pub mod env_config;
+
+pub mod escalation;
+pub use escalation::{Escalation, EscalationConfig};
+
+pub mod fold;
+
+pub mod json_repair;
+pub use json_repair::{parse_lenient, repair};
+
+pub mod locale;
+pub use locale::Locale;
+
+pub mod options;
+pub use options::GenerationOptions;
+
+pub mod provenance;
+pub use provenance::{ExplainFormat, Provenance};
+
+pub mod reader;
+pub use reader::{read_document, stream_chunks, NonUtf8, ReadingConfig};
+
+pub mod sampling;
+pub use sampling::{summarize, Consistency, SAMPLING_TEMPERATURE};
+
+pub mod schema;
+
+pub mod secrets;
+pub use secrets::api_key;
+
+pub mod vat;
+pub use vat::{check_invoice, VatReport};
+
+pub mod warnings;
+pub use warnings::{Warning, WarningCode};
+
+// Everything else reaches Ollama, the store or remote collections through
+// tokio and reqwest, so the `minimal` build (`--no-default-features
+// --features minimal`) leaves it out
+macro_rules! cfg_async {
+    ($($item:item)*) => {
+        $( #[cfg(feature = "async")] $item )*
+    };
+}
+
+cfg_async! {
+    pub mod aggregate;
+    pub use aggregate::{aggregate, Aggregation, AggregationResult, Field, GroupBy, Metric};
+
+    pub mod agent;
+    pub use agent::{run_agent, AgentResult};
+
+    #[cfg(feature = "embeddings")]
+    pub mod ann;
+    #[cfg(feature = "embeddings")]
+    pub use ann::Ivf;
+
+    pub mod approval;
+    pub use approval::{ApprovalConfig, ApprovalViolation};
+
+    pub mod audit;
+    pub use audit::{AuditCheck, AuditEntry};
+
+    pub mod backup;
+    pub use backup::{BackupSummary, Manifest, RestoreSummary};
+
+    pub mod bench;
+    pub use bench::{bench_retrieval, BenchReport};
+
+    pub mod bundle;
+    pub use bundle::{BundleManifest, ImportSummary, OnConflict};
+
+    pub mod bus;
+    pub use bus::{BusEvent, EventKind, PublishConfig};
+
+    pub mod cla;
+    pub use cla::{Args, AuthAction, Command, IntakeSource, OutputFormat, PromptsAction};
+    pub use clap::Parser;
+
+    #[cfg(feature = "client")]
+    pub mod client;
+    #[cfg(feature = "client")]
+    pub use client::DocAiClient;
+
+    pub mod close;
+    pub use close::{close_period, CloseConfig, CloseReport};
+
+    pub mod collections;
+    pub use collections::{collections, find_collection, Collection};
+
+    pub mod connectors;
+    pub use connectors::{Connector, ConnectorConfig, ConnectorState, Pulled, RemoteFile};
+
+    pub mod config;
+    pub use config::Config;
+
+    pub mod decompose;
+    pub use decompose::split_question;
+
+    pub mod documents;
+    pub use documents::{list_documents, DocumentFilter, DocumentPage, DocumentSummary, Sort};
+
+    pub mod domain;
+    pub use domain::{check_clauses, find_domain, Domain, DomainConfig, Validator};
+
+    #[cfg(feature = "embeddings")]
+    pub mod embeddings;
+    #[cfg(feature = "embeddings")]
+    pub use embeddings::EmbeddingIndex;
+
+    pub mod events;
+    pub use events::{ChannelEvents, ConsoleEvents, EventSink, Profiled, QueryEvent};
+
+    pub mod export;
+    pub use export::{AccountMap, ExportFormat};
+
+    #[cfg(feature = "extract")]
+    pub mod extract;
+    #[cfg(feature = "extract")]
+    pub use extract::{Extractable, Extracted, JsonSchema};
+
+    pub mod freshness;
+    pub use freshness::Staleness;
+
+    pub mod guardrail;
+    pub use guardrail::{GuardrailConfig, SecretAction};
+
+    pub mod hosts;
+    pub use hosts::{Balance, HostConfig, OllamaConfig};
+
+    pub mod indexer;
+
+    pub mod ingest;
+    pub use ingest::{IngestOptions, IngestReport};
+
+    pub mod intake;
+    pub use intake::{IngestOutcome, IntakeRegistry, IntakeResult};
+
+    pub mod invoices;
+    pub use invoices::{invoices, Invoice, Invoices};
+
+    pub mod lifecycle;
+    pub use lifecycle::{InvoiceStatus, StatusChange};
+
+    pub mod lock;
+    pub use lock::{lock_index, LockMode};
+
+    #[cfg(feature = "imap")]
+    pub mod mailbox;
+
+    pub mod metadata;
+    pub use metadata::{Annotation, DocumentMeta, DocumentState, Metadata, Note};
+
+    pub mod migrate;
+    pub use migrate::{MigrationReport, Pending};
+
+    pub mod modelfile;
+    pub use modelfile::ModelSpec;
+
+    pub mod payments;
+    pub use payments::{load_payments, Payment};
+
+    pub mod pipeline;
+    pub use pipeline::{Query, QueryBuilder};
+
+    pub mod policy;
+    pub use policy::{Decision, PolicyConfig, PolicyOutcome};
+
+    pub mod postprocess;
+    pub use postprocess::{PostContext, PostProcessConfig, PostProcessor};
+
+    pub mod prompts;
+    pub use prompts::{prompt_version, PromptVersion};
+
+    pub mod query_log;
+    pub use query_log::LoggedQuery;
+
+    pub mod quotas;
+    pub use quotas::{Metered, QuotaConfig, QuotaStatus};
+    #[cfg(feature = "server")]
+    pub use quotas::{QueryQuota, QuotaHeaders};
+
+    pub mod queue;
+    pub use queue::{DocumentEvent, MemoryQueue, MessageQueue, QueueConfig, QueueConsumer};
+
+    pub mod receipts;
+    pub use receipts::{expenses_csv, receipts, ExpenseCategory, ExpenseConfig, Receipt};
+
+    pub mod reconcile;
+    pub use reconcile::{reconcile, Reconciliation};
+
+    pub mod records;
+    pub use records::{day_number, invoice_records, InvoiceRecord};
+
+    pub mod redact;
+    pub use redact::{AmountView, OutputProfile};
+
+    pub mod reload;
+    pub use reload::LiveConfig;
+
+    pub mod replica;
+    #[cfg(feature = "server")]
+    pub use replica::IndexFollower;
+
+    pub mod retrieval;
+    pub use retrieval::{find_relevant_files, rank_files, retrieve, EmbedBackend, EmbedOptions, Fusion, RetrievalConfig, RetrievalMode};
+
+    pub mod routing;
+    pub use routing::{Complexity, RoutingConfig};
+
+    pub mod rules;
+    pub use rules::{check_rules, load_rules, Rule, RuleConfig, RulesReport};
+
+    pub mod scoring;
+    pub use scoring::{rank_with, Bm25Scorer, Combined, FilenameScorer, RelevanceScorer};
+    #[cfg(feature = "embeddings")]
+    pub use scoring::EmbeddingScorer;
+
+    pub mod search;
+    pub use search::SearchHit;
+
+    pub mod service;
+    pub use service::{ServiceKind, ServiceSpec};
+
+    pub mod session;
+    pub use session::{Session, SessionStore, Turn};
+
+    pub mod spend;
+    pub use spend::{spend_series, SpendSeries};
+
+    pub mod storage;
+    pub use storage::{DocumentSource, SyncReport};
+
+    pub mod store;
+    pub use store::{store, Store, StoreConfig};
+
+    pub mod sync;
+    pub use sync::{Prefer, SyncManifest, SyncOutcome, SyncPush};
+
+    pub mod tenants;
+    pub use tenants::{Tenancy, Tenant};
+
+    pub mod training;
+    pub use training::{TrainingExample, TrainingFormat};
 
-pub mod retrieval;
-pub use retrieval::find_relevant_files;
+    pub mod types;
+    pub use types::{ErrorResponse, ApiResponse, QueryRequest, Envelope, SkippedFile, AnnotateRequest};
 
-pub mod types;
-pub use types::{ErrorResponse, ApiResponse, QueryRequest, Envelope};
\ No newline at end of file
+    pub mod versions;
+    pub use versions::{DocumentKind, DocumentVersion, VersionGraph};
+}
@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Accounts-payable status of a document: received → extracted → approved →
+// paid → archived. Every document starts out received; `status inv_001
+// approved` moves it on, one allowed step at a time (see `next`), and the
+// steps are kept in the document metadata. `--invoice-status` (and
+// `invoice_status` in requests) keeps queries, reports and the documents list
+// to documents in the given statuses.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::docid::is_text_file;
+use crate::metadata::Metadata;
+
+#[derive(
+    Serialize, Deserialize, clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum InvoiceStatus {
+    /// In a collection folder, nothing done yet
+    #[default]
+    Received,
+    /// Fields read and checked
+    Extracted,
+    Approved,
+    Paid,
+    /// Done with; kept for the record
+    Archived,
+}
+
+pub const ALL_STATUSES: [InvoiceStatus; 5] =
+    [InvoiceStatus::Received, InvoiceStatus::Extracted, InvoiceStatus::Approved, InvoiceStatus::Paid, InvoiceStatus::Archived];
+
+impl InvoiceStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvoiceStatus::Received => "received",
+            InvoiceStatus::Extracted => "extracted",
+            InvoiceStatus::Approved => "approved",
+            InvoiceStatus::Paid => "paid",
+            InvoiceStatus::Archived => "archived",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        let s = s.trim().to_lowercase();
+        match ALL_STATUSES.into_iter().find(|status| status.as_str() == s) {
+            Some(status) => Ok(status),
+            None => anyhow::bail!(
+                "Unknown invoice status '{}'. Valid values: {}",
+                s,
+                ALL_STATUSES.map(|s| s.as_str()).join(", ")
+            ),
+        }
+    }
+
+    /// Comma-separated statuses ("approved,paid"); empty for all
+    pub fn parse_list(s: &str) -> Result<Vec<Self>> {
+        s.split(',').filter(|s| !s.trim().is_empty()).map(Self::parse).collect()
+    }
+
+    /// Statuses a document may move to from this one: forward one step, back
+    /// one step to redo the extraction or withdraw an approval, and to archived
+    /// from anything not yet approved (duplicates, documents that are no invoice)
+    pub fn next(&self) -> &'static [InvoiceStatus] {
+        use InvoiceStatus::*;
+        match self {
+            Received => &[Extracted, Archived],
+            Extracted => &[Approved, Received, Archived],
+            Approved => &[Paid, Extracted],
+            Paid => &[Archived],
+            Archived => &[],
+        }
+    }
+
+    /// Error unless a document may move from this status to `to`
+    pub fn check_transition(&self, to: InvoiceStatus) -> Result<()> {
+        if *self == to {
+            anyhow::bail!("Already {}", to.as_str());
+        }
+        if !self.next().contains(&to) {
+            let allowed = match self.next() {
+                [] => "nowhere".to_string(),
+                next => next.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(" or "),
+            };
+            anyhow::bail!(
+                "Cannot go from {} to {}: a {} document can only go to {} (--force overrides this)",
+                self.as_str(),
+                to.as_str(),
+                self.as_str(),
+                allowed
+            );
+        }
+        Ok(())
+    }
+}
+
+/// One status change of a document
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct StatusChange {
+    pub from: InvoiceStatus,
+    pub to: InvoiceStatus,
+    /// Unix time of the change
+    pub at: u64,
+    /// Made with --force, outside the allowed steps
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub forced: bool,
+    /// Approver whose `approve` made the change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub by: Option<String>,
+}
+
+impl fmt::Display for StatusChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {} → {}", self.at, self.from.as_str(), self.to.as_str())?;
+        if let Some(by) = &self.by {
+            write!(f, " by {}", by)?;
+        }
+        if self.forced {
+            write!(f, " (forced)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `status` passes a status filter (an empty one passes everything)
+pub fn selected(statuses: &[InvoiceStatus], status: InvoiceStatus) -> bool {
+    statuses.is_empty() || statuses.contains(&status)
+}
+
+/// Documents of `folder` a status filter leaves out
+pub fn excluded_in(folder: &Path, metadata: &Metadata, statuses: &[InvoiceStatus]) -> usize {
+    if statuses.is_empty() {
+        return 0;
+    }
+    let Ok(entries) = fs::read_dir(folder) else { return 0 };
+    entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| is_text_file(p))
+        .filter(|p| !selected(statuses, metadata.status(p)))
+        .count()
+}
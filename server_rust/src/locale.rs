@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Output locale: amounts in the answer that match figures read from the
+// documents (the invoice checks) are re-rendered from those exact values in
+// the locale's format, e.g. "R 12 345,67" for en-ZA, however the model wrote
+// them. ISO dates (2025-11-01) in the answer are rendered the same way.
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rust_decimal::Decimal;
+use serde_json::Value;
+use std::str::FromStr;
+
+use crate::vat::{parse_amount, VatReport};
+
+static ISO_DATE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\d{4})-(\d{2})-(\d{2})$").unwrap());
+
+/// Number and date conventions of one locale
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    pub tag: &'static str,
+    /// Thousands separator
+    pub group: &'static str,
+    pub decimal: &'static str,
+    /// Currency symbol used when the model gave none
+    pub symbol: &'static str,
+    /// {symbol} and {amount}
+    pub amount_pattern: &'static str,
+    /// {y}, {m} and {d}
+    pub date_pattern: &'static str,
+}
+
+const LOCALES: &[Locale] = &[
+    Locale { tag: "en-ZA", group: " ", decimal: ",", symbol: "R", amount_pattern: "{symbol} {amount}", date_pattern: "{y}/{m}/{d}" },
+    Locale { tag: "en-US", group: ",", decimal: ".", symbol: "$", amount_pattern: "{symbol}{amount}", date_pattern: "{m}/{d}/{y}" },
+    Locale { tag: "en-GB", group: ",", decimal: ".", symbol: "£", amount_pattern: "{symbol}{amount}", date_pattern: "{d}/{m}/{y}" },
+    Locale { tag: "de-DE", group: ".", decimal: ",", symbol: "€", amount_pattern: "{amount} {symbol}", date_pattern: "{d}.{m}.{y}" },
+    Locale { tag: "de-CH", group: "'", decimal: ".", symbol: "CHF", amount_pattern: "{symbol} {amount}", date_pattern: "{d}.{m}.{y}" },
+    Locale { tag: "fr-FR", group: " ", decimal: ",", symbol: "€", amount_pattern: "{amount} {symbol}", date_pattern: "{d}/{m}/{y}" },
+    Locale { tag: "nl-NL", group: ".", decimal: ",", symbol: "€", amount_pattern: "{symbol} {amount}", date_pattern: "{d}-{m}-{y}" },
+];
+
+/// Look up a locale by tag ("en-ZA", "en_za"...)
+pub fn locale(tag: &str) -> Result<&'static Locale> {
+    let wanted = tag.replace('_', "-");
+    LOCALES.iter().find(|l| l.tag.eq_ignore_ascii_case(&wanted)).ok_or_else(|| {
+        let valid: Vec<&str> = LOCALES.iter().map(|l| l.tag).collect();
+        anyhow!("Unknown locale '{}'. Valid values: {}", tag, valid.join(", "))
+    })
+}
+
+/// Every amount the invoice checks read from the documents
+pub fn verified_amounts(reports: &[VatReport]) -> Vec<Decimal> {
+    let mut amounts: Vec<Decimal> = reports
+        .iter()
+        .flat_map(|r| {
+            let figures = &r.figures;
+            [figures.net, figures.tax, figures.gross]
+                .into_iter()
+                .flatten()
+                .chain(figures.line_items.iter().flat_map(|i| [i.unit_price, i.total]))
+        })
+        .map(|a| a.round_dp(2))
+        .collect();
+    amounts.sort();
+    amounts.dedup();
+    amounts
+}
+
+/// Currency symbol and amount of a string that is nothing but an amount ("R8,866.50", "$ 1 200.00")
+fn split_amount(s: &str) -> Option<(Option<&str>, Decimal)> {
+    let s = s.trim();
+    let digits_at = s.find(|c: char| c.is_ascii_digit())?;
+    let (symbol, number) = s.split_at(digits_at);
+    let symbol = symbol.trim().trim_end_matches('-').trim();
+    if symbol.chars().count() > 3 || symbol.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    if !number.chars().all(|c| c.is_ascii_digit() || " ,.".contains(c)) {
+        return None;
+    }
+    let amount = parse_amount(s)?;
+    Some(((!symbol.is_empty()).then_some(symbol), amount))
+}
+
+impl Locale {
+    /// An amount with two decimals, grouped, with the currency symbol
+    pub fn format_amount(&self, amount: Decimal, symbol: Option<&str>) -> String {
+        let fixed = format!("{:.2}", amount.round_dp(2).abs());
+        let (whole, cents) = fixed.split_once('.').unwrap_or((&fixed, "00"));
+        let mut grouped = String::new();
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                grouped.push_str(self.group);
+            }
+            grouped.push(digit);
+        }
+        let sign = if amount.is_sign_negative() && !amount.is_zero() { "-" } else { "" };
+        let number = format!("{}{}{}{}", sign, grouped, self.decimal, cents);
+        self.amount_pattern.replace("{symbol}", symbol.unwrap_or(self.symbol)).replace("{amount}", &number)
+    }
+
+    /// 2025-11-01 in the locale's order; other text is returned as it is
+    pub fn format_date(&self, date: &str) -> Option<String> {
+        let caps = ISO_DATE_RE.captures(date.trim())?;
+        Some(self.date_pattern.replace("{y}", &caps[1]).replace("{m}", &caps[2]).replace("{d}", &caps[3]))
+    }
+
+    /// Re-render the amounts in `answer` that equal a verified amount, and ISO dates
+    pub fn render(&self, answer: &mut Value, verified: &[Decimal]) {
+        match answer {
+            Value::Object(map) => map.values_mut().for_each(|v| self.render(v, verified)),
+            Value::Array(items) => items.iter_mut().for_each(|v| self.render(v, verified)),
+            Value::Number(n) => {
+                if let Some(amount) = Decimal::from_str(&n.to_string()).ok().filter(|a| verified.contains(&a.round_dp(2))) {
+                    *answer = Value::String(self.format_amount(amount, None));
+                }
+            }
+            Value::String(s) => {
+                if let Some(date) = self.format_date(s) {
+                    *answer = Value::String(date);
+                } else if let Some((symbol, amount)) = split_amount(s).filter(|(_, a)| verified.contains(&a.round_dp(2))) {
+                    *answer = Value::String(self.format_amount(amount, symbol));
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,161 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Advisory locking of the index files under .doc-ai: one writer (`index`)
+// at a time, any number of readers while nobody writes. A second writer, or
+// a reader during a write, fails at once unless `--wait` was given, in which
+// case it blocks until the lock is free. The OS releases the lock when the
+// holder exits, so a crashed run never leaves a stale lock behind.
+// A store shared between hosts (Postgres) keeps the lock itself, as an
+// advisory lock that likewise ends with the holder's connection.
+
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::store::store;
+
+pub const LOCK_FILE: &str = ".doc-ai/index.lock";
+
+/// Block instead of failing when the lock is taken, set by `init_locking`
+static WAIT: OnceCell<bool> = OnceCell::new();
+
+/// This process holds the write lock (its own reads need no lock)
+static WRITING: AtomicBool = AtomicBool::new(false);
+
+/// Wait for the lock instead of failing (first call wins)
+pub fn init_locking(wait: bool) {
+    let _ = WAIT.set(wait);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Reading; shared with other readers
+    Shared,
+    /// Writing; nobody else may read or write
+    Exclusive,
+}
+
+/// Held lock, released when dropped
+#[derive(Debug)]
+pub struct IndexLock {
+    file: Option<File>,
+    mode: LockMode,
+    /// Held in the store rather than in the lock file
+    in_store: bool,
+}
+
+impl Drop for IndexLock {
+    fn drop(&mut self) {
+        if let Some(file) = &self.file
+            && self.mode == LockMode::Exclusive
+        {
+            // Forget our process id before the OS releases the lock
+            let _ = file.set_len(0);
+            WRITING.store(false, Ordering::SeqCst);
+        }
+        if self.in_store {
+            if let Err(e) = store().unlock_index(self.mode) {
+                eprintln!("WARNING: index lock not released: {:#}", e);
+            }
+            if self.mode == LockMode::Exclusive {
+                WRITING.store(false, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+fn attempt(result: Result<(), fs::TryLockError>) -> io::Result<bool> {
+    match result {
+        Ok(()) => Ok(true),
+        Err(fs::TryLockError::WouldBlock) => Ok(false),
+        Err(fs::TryLockError::Error(e)) => Err(e),
+    }
+}
+
+/// Who is writing, from the process id the writer leaves in the lock file
+fn holder(file: &mut File) -> String {
+    let mut pid = String::new();
+    let _ = file.rewind().and_then(|_| file.read_to_string(&mut pid));
+    match pid.trim() {
+        "" => "another process".to_string(),
+        pid => format!("process {}", pid),
+    }
+}
+
+fn busy(mode: LockMode, holder: &str) -> anyhow::Error {
+    match mode {
+        LockMode::Shared => anyhow::anyhow!("The index is being updated by {}; try again later or use --wait", holder),
+        LockMode::Exclusive => {
+            anyhow::anyhow!("The index is in use (written by {} or being read); try again later or use --wait", holder)
+        }
+    }
+}
+
+/// The lock kept by the store; polled while waiting, so the store stays usable meanwhile
+fn lock_in_store(mode: LockMode, mut locked: bool) -> Result<IndexLock> {
+    if !locked {
+        if !WAIT.get().copied().unwrap_or(false) {
+            return Err(busy(mode, "another server"));
+        }
+        eprintln!("Waiting for the index lock (held by another server)...");
+        while !locked {
+            std::thread::sleep(Duration::from_secs(1));
+            locked = store().try_lock_index(mode)?.unwrap_or(true);
+        }
+    }
+    if mode == LockMode::Exclusive {
+        WRITING.store(true, Ordering::SeqCst);
+    }
+    Ok(IndexLock { file: None, mode, in_store: true })
+}
+
+/// Take the index lock, waiting for it or failing at once depending on `--wait`
+pub fn lock_index(mode: LockMode) -> Result<IndexLock> {
+    if mode == LockMode::Shared && WRITING.load(Ordering::SeqCst) {
+        return Ok(IndexLock { file: None, mode, in_store: false });
+    }
+    if let Some(locked) = store().try_lock_index(mode)? {
+        return lock_in_store(mode, locked);
+    }
+    if let Some(dir) = Path::new(LOCK_FILE).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Not truncated on open: the file holds the writer's process id
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(LOCK_FILE)
+        .with_context(|| format!("Failed to open {}", LOCK_FILE))?;
+
+    let try_lock = match mode {
+        LockMode::Shared => file.try_lock_shared(),
+        LockMode::Exclusive => file.try_lock(),
+    };
+    if !attempt(try_lock).with_context(|| format!("Failed to lock {}", LOCK_FILE))? {
+        let holder = holder(&mut file);
+        if !WAIT.get().copied().unwrap_or(false) {
+            return Err(busy(mode, &holder));
+        }
+        eprintln!("Waiting for the index lock ({})...", holder);
+        match mode {
+            LockMode::Shared => file.lock_shared(),
+            LockMode::Exclusive => file.lock(),
+        }
+        .with_context(|| format!("Failed to lock {}", LOCK_FILE))?;
+    }
+
+    if mode == LockMode::Exclusive {
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+        file.flush()?;
+        WRITING.store(true, Ordering::SeqCst);
+    }
+    Ok(IndexLock { file: Some(file), mode, in_store: false })
+}
@@ -0,0 +1,111 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// IMAP intake: pull matching messages from a mailbox, save their attachments
+// into a collection, and flag the messages so they are not processed twice.
+
+use anyhow::{Context, Result};
+use mailparse::{DispositionType, MailHeaderMap, ParsedMail};
+use serde::Serialize;
+
+use crate::config::ImapConfig;
+use crate::intake::{ingest_bytes, IngestOutcome};
+use crate::Collection;
+
+/// Summary of one intake run
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct IntakeReport {
+    /// Messages that matched the filters
+    pub messages: usize,
+    /// (message subject, attachment outcome)
+    pub attachments: Vec<(String, IngestOutcome)>,
+}
+
+/// Quote a value for an IMAP SEARCH command
+fn quoted(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// SEARCH criteria: not yet processed, plus the configured sender/subject filters
+fn search_criteria(cfg: &ImapConfig) -> String {
+    let mut criteria = vec![format!("UNKEYWORD {}", cfg.processed_flag)];
+    if let Some(from) = &cfg.from {
+        criteria.push(format!("FROM {}", quoted(from)));
+    }
+    if let Some(subject) = &cfg.subject_contains {
+        criteria.push(format!("SUBJECT {}", quoted(subject)));
+    }
+    criteria.join(" ")
+}
+
+/// Attachments (and inline parts with a file name) of a message, recursively
+fn attachments(part: &ParsedMail, out: &mut Vec<(String, Vec<u8>)>) -> Result<()> {
+    let disposition = part.get_content_disposition();
+    let name = disposition
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"))
+        .cloned();
+
+    if let Some(name) = name
+        && (disposition.disposition == DispositionType::Attachment || part.subparts.is_empty())
+    {
+        out.push((name, part.get_body_raw()?));
+    }
+    for sub in &part.subparts {
+        attachments(sub, out)?;
+    }
+    Ok(())
+}
+
+/// Run one intake pass against the configured mailbox (blocking)
+pub fn intake_imap(cfg: &ImapConfig, collection: &Collection, dry_run: bool) -> Result<IntakeReport> {
+    let password = match (&cfg.password, std::env::var(&cfg.password_env)) {
+        (_, Ok(p)) => p,
+        (Some(p), _) => p.clone(),
+        (None, Err(_)) => anyhow::bail!("No IMAP password: set {} or `password` in [imap]", cfg.password_env),
+    };
+
+    let tls = native_tls::TlsConnector::builder().build()?;
+    let client = imap::connect((cfg.host.as_str(), cfg.port), &cfg.host, &tls)
+        .with_context(|| format!("Cannot connect to {}:{}", cfg.host, cfg.port))?;
+    let mut session = client
+        .login(&cfg.username, &password)
+        .map_err(|(e, _)| e)
+        .with_context(|| format!("IMAP login failed for {}", cfg.username))?;
+
+    session.select(&cfg.mailbox).with_context(|| format!("Cannot open mailbox '{}'", cfg.mailbox))?;
+
+    let mut uids: Vec<u32> = session.uid_search(search_criteria(cfg))?.into_iter().collect();
+    uids.sort_unstable();
+
+    let mut report = IntakeReport { messages: uids.len(), ..Default::default() };
+    for uid in uids {
+        let fetched = session.uid_fetch(uid.to_string(), "RFC822")?;
+        let Some(body) = fetched.iter().next().and_then(|m| m.body()) else {
+            continue;
+        };
+        let mail = mailparse::parse_mail(body).with_context(|| format!("Cannot parse message {}", uid))?;
+        let subject = mail.headers.get_first_value("Subject").unwrap_or_default();
+
+        let mut files = Vec::new();
+        attachments(&mail, &mut files)?;
+        for (name, bytes) in files {
+            let outcome = if dry_run {
+                IngestOutcome::Skipped(format!("dry run: {}", name))
+            } else {
+                ingest_bytes(collection, &name, &bytes, &cfg.extensions)?
+            };
+            report.attachments.push((subject.clone(), outcome));
+        }
+
+        if !dry_run {
+            session.uid_store(uid.to_string(), format!("+FLAGS ({})", cfg.processed_flag))?;
+            if let Some(folder) = &cfg.move_to {
+                session.uid_mv(uid.to_string(), folder)?;
+            }
+        }
+    }
+
+    session.logout()?;
+    Ok(report)
+}
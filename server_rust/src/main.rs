@@ -3,19 +3,28 @@
 #[macro_use]
 extern crate rocket;
 
+use rocket::data::{Data, ToByteUnit};
 use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
 use rocket::http::{Header, Status};
 use rocket::Request;
 use rocket::Response;   // Somehow different from response...
+use rocket::response::stream::{Event, EventStream};
 use rocket::response::{self, Responder};
 use rocket::serde::json::Json;
 use rocket::State;
-use serde_json::{json, Value};
+use serde_json::Value;
 use std::sync::Arc;
 
 use doc_ai_server::*;
 
+mod chat_socket;
+mod commands;
+mod openapi;
+mod shutdown;
+
 // CORS fairing
+#[allow(clippy::upper_case_acronyms)]
 struct CORS;
 
 #[rocket::async_trait]
@@ -30,7 +39,7 @@ impl Fairing for CORS {
     async fn on_response<'r>(&self, _req: &'r Request<'_>, res: &mut Response<'r>) {
         res.set_header(Header::new("Access-Control-Allow-Origin", "*"));
         res.set_header(Header::new("Access-Control-Allow-Methods", "POST, GET, OPTIONS"));
-        res.set_header(Header::new("Access-Control-Allow-Headers", "Content-Type"));
+        res.set_header(Header::new("Access-Control-Allow-Headers", "Content-Type, Authorization, X-API-Key"));
     }
 }
 
@@ -42,7 +51,7 @@ impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for CorsResponder<R> {
         let mut res = self.0.respond_to(request)?;
         res.set_header(Header::new("Access-Control-Allow-Origin", "*")); // or specific origin like "http://localhost:your-mvc-port"
         res.set_header(Header::new("Access-Control-Allow-Methods", "POST, OPTIONS"));
-        res.set_header(Header::new("Access-Control-Allow-Headers", "Content-Type"));
+        res.set_header(Header::new("Access-Control-Allow-Headers", "Content-Type, Authorization, X-API-Key"));
         Ok(res)
     }
 }
@@ -53,117 +62,757 @@ fn options_handler() -> CorsResponder<Status> {
     CorsResponder(Status::Ok)
 }
 
+// Query for a request, with the command-line settings as defaults
+fn build_query(req: &QueryRequest, state: &Args, file_config: &Config) -> Result<Query, ErrorResponse> {
+    let mut retrieval = file_config.retrieval.clone();
+    retrieval.legacy_matching |= state.legacy_matching;
+    if let Some(mode) = state.retriever {
+        retrieval.mode = mode;
+    }
+
+    let mut builder = Query::builder(req.query.clone())
+        .model(state.model())
+        .max_docs(req.top_k.unwrap_or(state.top_k))
+        .retrieval(retrieval)
+        .api(state.api())
+        .agent(req.agent.unwrap_or(state.agent))
+        .samples(req.samples.unwrap_or(state.samples))
+        .include_superseded(req.include_superseded.unwrap_or(state.include_superseded))
+        .strict(req.strict.unwrap_or(state.strict))
+        .deny_warnings(req.deny_warnings.unwrap_or(state.deny_warnings))
+        .decompose(req.decompose.unwrap_or(state.decompose));
+
+    match req.collections.as_deref() {
+        Some(names) if !names.is_empty() => {
+            for name in names {
+                builder = builder.collection(name.clone());
+            }
+        }
+        _ => {
+            let name = req
+                .collection
+                .as_deref()
+                .or(req.category.as_deref())
+                .or(state.collection.as_deref())
+                .unwrap_or_default();
+            builder = builder.collection(name);
+        }
+    }
+
+    if let Some(name) = req.domain.as_deref().or(state.domain.as_deref()) {
+        match find_domain(file_config, name) {
+            Ok(domain) => builder = builder.domain(domain),
+            Err(e) => {
+                return Err(ErrorResponse {
+                    error: true,
+                    code: "invalid_domain".to_string(),
+                    message: format!("{:#}", e),
+                    category: None,
+                    query: Some(req.query.clone()),
+                });
+            }
+        }
+    }
+
+    if let Some(name) = req.schema.as_deref().or(state.schema.as_deref()) {
+        match file_config.schema(name) {
+            Ok(schema) => builder = builder.schema(schema),
+            Err(e) => {
+                return Err(ErrorResponse {
+                    error: true,
+                    code: "invalid_schema".to_string(),
+                    message: format!("{:#}", e),
+                    category: None,
+                    query: Some(req.query.clone()),
+                });
+            }
+        }
+    }
+
+    if let Some(options) = &file_config.options {
+        builder = builder.options(options.clone());
+    }
+    if let Some(tokens) = req.max_answer_tokens.or(state.max_answer_tokens) {
+        builder = builder.max_answer_tokens(tokens);
+    }
+    match req.invoice_status.as_deref().map(InvoiceStatus::parse_list) {
+        None => builder = builder.invoice_statuses(state.invoice_status.clone()),
+        Some(Ok(statuses)) => builder = builder.invoice_statuses(statuses),
+        Some(Err(e)) => {
+            return Err(ErrorResponse {
+                error: true,
+                code: "invalid_status".to_string(),
+                message: format!("{:#}", e),
+                category: None,
+                query: Some(req.query.clone()),
+            });
+        }
+    }
+    if file_config.routing.is_enabled() {
+        builder = builder.routing(file_config.routing.clone());
+    }
+    if file_config.escalation.model.is_some() {
+        builder = builder.escalation(file_config.escalation.clone());
+    }
+    // [postprocess] may give questions on the command line, in chat and to the server their own steps
+    let command = match state.command {
+        Some(Command::Ask { .. }) => "ask",
+        Some(Command::Chat) => "chat",
+        _ => "serve",
+    };
+    match file_config.postprocess.chain(command, &[]) {
+        Ok(chain) => builder = builder.post_processors(chain),
+        Err(e) => {
+            return Err(ErrorResponse {
+                error: true,
+                code: "invalid_postprocess".to_string(),
+                message: format!("{:#}", e),
+                category: None,
+                query: Some(req.query.clone()),
+            });
+        }
+    }
+
+    // The server's output profile wins, so a client cannot widen its own view
+    if let Some(name) = state.output_profile.as_deref().or(req.output_profile.as_deref()) {
+        match file_config.output_profile(name) {
+            Ok(profile) => builder = builder.profile(profile),
+            Err(e) => {
+                return Err(ErrorResponse {
+                    error: true,
+                    code: "invalid_profile".to_string(),
+                    message: format!("{:#}", e),
+                    category: None,
+                    query: Some(req.query.clone()),
+                });
+            }
+        }
+    }
+
+    if let Some(tag) = req.locale.as_deref().or(state.locale.as_deref()) {
+        match locale::locale(tag) {
+            Ok(locale) => builder = builder.locale(locale.clone()),
+            Err(e) => {
+                return Err(ErrorResponse {
+                    error: true,
+                    code: "invalid_locale".to_string(),
+                    message: format!("{:#}", e),
+                    category: None,
+                    query: Some(req.query.clone()),
+                });
+            }
+        }
+    }
+
+    if let Some(strictness) = req.strictness.or(state.strictness) {
+        builder = builder.strictness(strictness);
+    }
+
+    if req.explain.unwrap_or(state.explain) {
+        builder = builder.explain(match req.explain_format.as_deref() {
+            Some("dot") => ExplainFormat::Dot,
+            _ => ExplainFormat::Json,
+        });
+    }
+
+    Ok(builder.build())
+}
+
 // Main query handler
+#[utoipa::path(
+    post,
+    path = "/query",
+    request_body = QueryRequest,
+    responses((status = 200, description = "The answer, or why there is none", body = openapi::QueryEnvelope))
+)]
 #[post("/query", format = "json", data = "<req>")]
 async fn query(
     req: Json<QueryRequest>,
-    state: &State<Arc<Args>>,
+    live: &State<Arc<LiveConfig>>,
+    tenancy: Tenancy,
+    _quota: QueryQuota,
 ) -> CorsResponder<Json<Value>> {
-    let category_str = req.category.as_deref().unwrap_or_default();
-    let category = match Category::from_api_value(category_str) {
-        Some(cat) => cat,
-        None => {
-            let err = ErrorResponse {
-                error: true,
-                code: "invalid_category".to_string(),
-                message: format!(
-                    "Unknown category '{}'. Valid values: {}",
-                    category_str,
-                    Category::all_api_values_human()
-                ),
-                category: Some(category_str.to_string()),
-                query: Some(req.query.clone()),
-            };
-            return CorsResponder(Envelope::failure(err).into());
-        }
+    // Settings as of now; a reload during the query does not affect it
+    let (state, file_config) = live.get();
+    let mut req = req.into_inner();
+    if let Some(tenant) = &tenancy.0 {
+        tenant.scope_request(&mut req, state.collection.as_deref());
+    }
+    let query = match build_query(&req, &state, &file_config) {
+        Ok(q) => q,
+        Err(err) => return CorsResponder(Envelope::failure(err).into()),
     };
+    let metered = Metered::new(query.events.clone());
+    let query = Query { events: Some(metered.clone()), ..query };
+
+    let in_flight = shutdown::InFlight::start();
+    let result = query.run().await;
+    in_flight.finish();
+    if let Some(tenant) = tenancy.name() {
+        quotas::record(tenant, metered.tokens());
+    }
+    match result {
+        Ok(api_resp) => CorsResponder(Envelope::success(api_resp).into()),
+        Err(err) => CorsResponder(Envelope::failure(err).into()),
+    }
+}
 
-    let relevant_files = find_relevant_files(&req.query, &category);
+/// Fields of `QueryRequest` that are numbers or booleans rather than text
+const NON_TEXT_PARAMS: &[&str] = &[
+    "top_k",
+    "max_answer_tokens",
+    "samples",
+    "decompose",
+    "agent",
+    "explain",
+    "include_superseded",
+    "strict",
+    "deny_warnings",
+];
 
-    if relevant_files.is_empty() {
-        let err = ErrorResponse {
-            error: true,
-            code: "no_matches".to_string(),
-            message: format!("No relevant documents found in '{}' category", category.display_name()),
-            category: Some(category.api_value().to_string()),
-            query: Some(req.query.clone()),
+/// QueryRequest from URL parameters (`?query=...&collection=invoices&top_k=3`);
+/// `collections` may be repeated or comma-separated
+fn request_from_params(uri: &Origin<'_>) -> Result<QueryRequest, ErrorResponse> {
+    let mut fields = serde_json::Map::new();
+    let mut collections = Vec::new();
+    for (key, value) in uri.query().into_iter().flat_map(|q| q.segments()) {
+        if key == "api_key" {
+            continue;
+        }
+        if key == "collections" {
+            collections.extend(value.split(',').filter(|c| !c.is_empty()).map(|c| Value::String(c.to_string())));
+            continue;
+        }
+        let value = match serde_json::from_str::<Value>(value) {
+            Ok(parsed) if NON_TEXT_PARAMS.contains(&key) => parsed,
+            _ => Value::String(value.to_string()),
         };
-        return CorsResponder(Envelope::failure(err).into());
+        fields.insert(key.to_string(), value);
+    }
+    if !collections.is_empty() {
+        fields.insert("collections".to_string(), Value::Array(collections));
     }
+    serde_json::from_value(Value::Object(fields)).map_err(|e| ErrorResponse {
+        error: true,
+        code: "invalid_request".to_string(),
+        message: e.to_string(),
+        category: None,
+        query: None,
+    })
+}
 
-    let mut contents = String::new();
-    let mut file_names = Vec::new();
+// Same as /query, as Server-Sent Events: `progress` events while documents are
+// gathered (scan_start, document_loaded, prompt_built, tokens, done), `delta`
+// events with the answer as the model writes it (not under an output profile,
+// where `partial` answers are redacted instead), then one `answer` event with
+// the envelope /query would have returned
+#[utoipa::path(
+    get,
+    path = "/query/stream",
+    params(
+        ("query" = String, Query, description = "The question; any other /query field may be given as a parameter too"),
+        ("collection" = Option<String>, Query),
+        ("collections" = Option<String>, Query, description = "Repeated or comma-separated"),
+        ("api_key" = Option<String>, Query, description = "Tenant API key, when the server has tenants"),
+    ),
+    responses((
+        status = 200,
+        description = "Server-Sent Events: `progress` and `delta` events carrying a QueryEvent, then one `answer` event carrying a QueryEnvelope",
+        content_type = "text/event-stream",
+        body = String
+    ))
+)]
+#[get("/query/stream")]
+fn query_stream(uri: &Origin<'_>, live: &State<Arc<LiveConfig>>, tenancy: Tenancy, _quota: QueryQuota) -> EventStream![] {
+    let (state, file_config) = live.get();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let request = request_from_params(uri).map(|mut req| {
+        if let Some(tenant) = &tenancy.0 {
+            tenant.scope_request(&mut req, state.collection.as_deref());
+        }
+        req
+    });
+    let mut profiled = false;
+    let task = request.and_then(|req| build_query(&req, &state, &file_config)).map(|query| {
+        profiled = query.profile.is_some();
+        let metered = Metered::new(Some(Arc::new(ChannelEvents(tx))));
+        let query = Query { events: Some(metered.clone()), ..query };
+        let tenant = tenancy.name().map(str::to_string);
+        tokio::spawn(async move {
+            let in_flight = shutdown::InFlight::start();
+            let result = query.run().await;
+            in_flight.finish();
+            if let Some(tenant) = tenant {
+                quotas::record(&tenant, metered.tokens());
+            }
+            result
+        })
+    });
 
-    for path in relevant_files {
-        let text = match get_cached_content(&path) {
-            Ok(t) => t,
-            Err(e) => {
-                let err = ErrorResponse {
-                    error: true,
-                    code: "internal_server_error".to_string(),
-                    message: e.to_string(),
-                    category: Some(category.api_value().to_string()),
-                    query: Some(req.query.clone()),
+    EventStream! {
+        match task {
+            Err(err) => yield Event::json(&Envelope::failure(err)).event("answer"),
+            Ok(task) => {
+                while let Some(event) = rx.recv().await {
+                    let kind = match event {
+                        // Raw model output shows fields the output profile hides
+                        QueryEvent::TokenDelta { .. } if profiled => continue,
+                        QueryEvent::TokenDelta { .. } => "delta",
+                        QueryEvent::PartialAnswer { .. } => "partial",
+                        _ => "progress",
+                    };
+                    yield Event::json(&event).event(kind);
+                }
+                let envelope = match task.await {
+                    Ok(Ok(api_resp)) => Envelope::success(api_resp),
+                    Ok(Err(err)) => Envelope::failure(err),
+                    Err(e) => Envelope::failure(ErrorResponse {
+                        error: true,
+                        code: "internal_server_error".to_string(),
+                        message: e.to_string(),
+                        category: None,
+                        query: None,
+                    }),
                 };
-                return CorsResponder(Envelope::failure(err).into());
+                yield Event::json(&envelope).event("answer");
             }
-        };
+        }
+    }
+}
 
-        let fname = path.file_name().unwrap().to_string_lossy().to_string();
-        contents.push_str(&format!("\n--- {} ---\n{}\n", fname, text));
-        file_names.push(fname);
+// Documents with their state, tags and extracted fields, a page at a time:
+// ?collection=&vendor=&from=&to=&tag=&status=&invoice_status=&q=&sort=-date&offset=0&limit=50
+#[utoipa::path(
+    get,
+    path = "/documents",
+    params(
+        ("collection" = Option<String>, Query),
+        ("vendor" = Option<String>, Query, description = "Substring of the vendor name, any case"),
+        ("from" = Option<String>, Query, description = "First date, YYYY-MM-DD or a prefix such as 2025-03"),
+        ("to" = Option<String>, Query, description = "Last date, inclusive; YYYY-MM-DD or a prefix"),
+        ("tag" = Option<String>, Query),
+        ("status" = Option<String>, Query, description = "active (default), superseded, archived, deleted or all"),
+        ("invoice_status" = Option<String>, Query, description = "received, extracted, approved, paid or archived; comma-separated for several"),
+        ("q" = Option<String>, Query, description = "Text searched in the file name, number, vendor, date, currency, total and notes"),
+        ("sort" = Option<String>, Query, description = "file, date, vendor, gross, modified or collection; prefix - for descending"),
+        ("offset" = Option<usize>, Query),
+        ("limit" = Option<usize>, Query, description = "Page size, 50 by default, at most 500"),
+    ),
+    responses((status = 200, description = "One page of documents", body = openapi::DocumentsEnvelope))
+)]
+#[allow(clippy::too_many_arguments)]
+#[get("/documents?<collection>&<vendor>&<from>&<to>&<tag>&<status>&<invoice_status>&<q>&<sort>&<offset>&<limit>")]
+fn document_list(
+    collection: Option<String>,
+    vendor: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    tag: Option<String>,
+    status: Option<String>,
+    invoice_status: Option<String>,
+    q: Option<String>,
+    sort: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    tenancy: Tenancy,
+) -> CorsResponder<Json<Value>> {
+    let invalid = |code: &str, e: anyhow::Error| {
+        let err = ErrorResponse { error: true, code: code.to_string(), message: format!("{:#}", e), category: None, query: None };
+        CorsResponder(Envelope::failure(err).into())
+    };
+    // Active documents unless asked otherwise; "all" for every status
+    let status = match status.as_deref() {
+        None => Some(documents::DocumentStatus::Active),
+        Some("all") => None,
+        Some(s) => match documents::DocumentStatus::parse(s) {
+            Ok(status) => Some(status),
+            Err(e) => return invalid("invalid_status", e),
+        },
+    };
+    let invoice_status = match InvoiceStatus::parse_list(invoice_status.as_deref().unwrap_or_default()) {
+        Ok(statuses) => statuses,
+        Err(e) => return invalid("invalid_status", e),
+    };
+    let sort = match sort.as_deref().map(Sort::parse).transpose() {
+        Ok(sort) => sort.unwrap_or_default(),
+        Err(e) => return invalid("invalid_sort", e),
+    };
+    let collection = collection.map(|name| tenancy.scope(&name));
+    if let Some(name) = &collection
+        && find_collection(name).is_none_or(|c| !tenancy.allows(c))
+    {
+        let e = anyhow::anyhow!("Unknown collection '{}'. Valid values: {}", name, collections::all_collection_names_human());
+        return invalid("invalid_category", e);
     }
 
-    match query_ollama(&state.model.clone(), contents, &req.query, &category).await {
-        Ok(raw_json) => {
-            let parsed: Value = serde_json::from_str(&raw_json)
-                .unwrap_or_else(|_| json!({"raw": raw_json}));
+    let filter = DocumentFilter { collection, vendor, from, to, tag, status, invoice_status, text: q, tenant: tenancy.0 };
+    match list_documents(&filter, sort, offset.unwrap_or(0), limit.unwrap_or(documents::DEFAULT_PAGE_SIZE)) {
+        Ok(page) => CorsResponder(Envelope::success(page).into()),
+        Err(e) => invalid("internal_server_error", e),
+    }
+}
 
-            let api_resp = ApiResponse {
-                answer: parsed,
-                used_files: file_names,
-                error: None,
-            };
+// Idempotent upload, the document's bytes as the body:
+// POST /documents?name=inv_042.txt&key=erp-4711 answers saved, duplicate (the
+// collection already has these bytes) or updated (new bytes for a key sent
+// before), with the SHA-256 received; the next index run reads the document
+#[utoipa::path(
+    post,
+    path = "/documents",
+    params(
+        ("name" = String, Query, description = "File name to save the document as"),
+        ("collection" = Option<String>, Query, description = "Collection (the server's --collection, else invoices)"),
+        ("key" = Option<String>, Query, description = "Your id for the document; new bytes under it update the document"),
+    ),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses((status = 200, description = "What intake did with the document", body = openapi::IntakeEnvelope))
+)]
+#[post("/documents?<name>&<collection>&<key>", data = "<body>")]
+async fn upload(
+    name: String,
+    collection: Option<String>,
+    key: Option<String>,
+    body: Data<'_>,
+    live: &State<Arc<LiveConfig>>,
+    tenancy: Tenancy,
+) -> CorsResponder<Json<Value>> {
+    let invalid = |code: &str, e: anyhow::Error| {
+        let err = ErrorResponse { error: true, code: code.to_string(), message: format!("{:#}", e), category: None, query: None };
+        CorsResponder(Envelope::failure(err).into())
+    };
+    let limit = reader::reading().max_file_bytes;
+    let bytes = match body.open(limit.bytes()).into_bytes().await {
+        Ok(bytes) if bytes.is_complete() => bytes.into_inner(),
+        Ok(_) => return invalid("document_too_large", anyhow::anyhow!("The document is over {} bytes", limit)),
+        Err(e) => return invalid("invalid_request", e.into()),
+    };
+    let (state, _) = live.get();
+    let wanted = tenancy.scope(collection.as_deref().or(state.collection.as_deref()).unwrap_or("invoices"));
+    let Some(target) = collections().iter().find(|c| tenancy.allows(c) && c.matches(&wanted)) else {
+        return invalid("unknown_collection", anyhow::anyhow!("Unknown collection '{}'", wanted));
+    };
+    let extensions = vec!["txt".to_string()];
+    let from_key = key.clone();
+    let received =
+        tokio::task::spawn_blocking(move || intake::receive(target, &name, from_key.as_deref(), &bytes, &extensions)).await;
+    match received.map_err(anyhow::Error::from).and_then(|r| r) {
+        Ok(result) => {
+            if let (Some(file), true) = (result.outcome.file(), result.outcome.is_new_content()) {
+                let target = target.folder.join(file).display().to_string();
+                let details = serde_json::json!({ "source": "upload", "key": key, "sha256": result.sha256 });
+                audit::record_or_warn(tenancy.name().unwrap_or("api"), "intake", Some(&target), details);
+            }
+            CorsResponder(Envelope::success(result).into())
+        }
+        Err(e) => invalid("internal_server_error", e),
+    }
+}
 
-            CorsResponder(Envelope::success(api_resp).into())
+// Tags and notes of one document:
+// {"doc": "inv_001", "tags": ["+disputed", "-paid"], "note": "sent to legal"}
+#[utoipa::path(
+    post,
+    path = "/documents/annotate",
+    request_body = AnnotateRequest,
+    responses((status = 200, description = "The document afterwards", body = openapi::DocumentEnvelope))
+)]
+#[post("/documents/annotate", format = "json", data = "<req>")]
+fn annotate(req: Json<AnnotateRequest>, tenancy: Tenancy) -> CorsResponder<Json<Value>> {
+    let invalid = |code: &str, e: anyhow::Error| {
+        let err = ErrorResponse { error: true, code: code.to_string(), message: format!("{:#}", e), category: None, query: None };
+        CorsResponder(Envelope::failure(err).into())
+    };
+    // A file name, not a path: nothing outside the collection folders
+    if req.doc.contains(['/', '\\']) || req.doc.contains("..") {
+        return invalid("invalid_document", anyhow::anyhow!("'{}' is not a file name", req.doc));
+    }
+    let collection = req.collection.as_deref().map(|name| tenancy.scope(name));
+    let visible = collections().iter().filter(|c| tenancy.allows(c) && collection.as_ref().is_none_or(|n| c.matches(n)));
+    let path = match metadata::find_document(&req.doc, visible) {
+        Ok(path) => path,
+        Err(e) => return invalid("unknown_document", e),
+    };
+    let Some(owner) = collections().iter().find(|c| path.starts_with(&c.folder)) else {
+        return invalid("unknown_document", anyhow::anyhow!("No document named '{}'", req.doc));
+    };
+    let mut changes: Vec<Annotation> = req.tags.iter().map(|t| Annotation::parse(t, false)).collect();
+    changes.extend(req.note.iter().map(|n| Annotation::Note(n.clone())));
+    let updated = Metadata::update(|metadata| {
+        if req.clear_notes {
+            metadata.clear_notes(&path);
         }
-        Err(e) => {
-            let err = ErrorResponse {
-                error: true,
-                code: "ollama_error".to_string(),
-                message: e.to_string(),
-                category: Some(category.api_value().to_string()),
-                query: Some(req.query.clone()),
-            };
-            CorsResponder(Envelope::failure(err).into())
+        metadata.apply(&path, &changes);
+    });
+    if updated.is_ok() {
+        let target = path.display().to_string();
+        let details = serde_json::json!({ "changes": changes, "clear_notes": req.clear_notes });
+        audit::record_or_warn(tenancy.name().unwrap_or("api"), "tag", Some(&target), details);
+    }
+    match updated.map(|metadata| documents::summarize(owner, &path, &metadata)) {
+        Ok(Some(summary)) => CorsResponder(Envelope::success(summary).into()),
+        Ok(None) => invalid("unknown_document", anyhow::anyhow!("Cannot read {}", path.display())),
+        Err(e) => invalid("internal_server_error", e),
+    }
+}
+
+// Instance-to-instance sync (`sync <remote-url>`, see sync.rs), within the tenant's collections
+#[get("/sync")]
+fn sync_manifest(tenancy: Tenancy) -> CorsResponder<Json<Value>> {
+    match sync::manifest(&sync::shared(tenancy.name())) {
+        Ok(manifest) => CorsResponder(Envelope::success(manifest).into()),
+        Err(e) => sync_failure(e),
+    }
+}
+
+#[post("/sync/fetch", format = "json", data = "<names>")]
+fn sync_fetch(names: Json<Vec<String>>, tenancy: Tenancy) -> CorsResponder<Json<Value>> {
+    CorsResponder(Envelope::success(sync::texts(&sync::shared(tenancy.name()), &names)).into())
+}
+
+#[post("/sync", format = "json", data = "<push>")]
+fn sync_push(push: Json<SyncPush>, live: &State<Arc<LiveConfig>>, tenancy: Tenancy) -> CorsResponder<Json<Value>> {
+    let (_, file_config) = live.get();
+    match sync::apply(&sync::shared(tenancy.name()), &push, &file_config.approval, tenancy.name().unwrap_or("api")) {
+        Ok(applied) => CorsResponder(Envelope::success(applied).into()),
+        Err(e) => sync_failure(e),
+    }
+}
+
+fn sync_failure(e: anyhow::Error) -> CorsResponder<Json<Value>> {
+    let err = ErrorResponse { error: true, code: "sync_failed".to_string(), message: format!("{:#}", e), category: None, query: None };
+    CorsResponder(Envelope::failure(err).into())
+}
+
+// Tax consistency report for every invoice, computed without the model
+#[utoipa::path(
+    get,
+    path = "/vat-check",
+    params(("invoice_status" = Option<String>, Query, description = "Only invoices in these statuses (comma-separated)")),
+    responses((status = 200, description = "A report per invoice", body = openapi::VatCheckEnvelope))
+)]
+#[get("/vat-check?<invoice_status>")]
+fn vat_check(invoice_status: Option<String>, tenancy: Tenancy) -> CorsResponder<Json<Value>> {
+    let failure = |code: &str, message: String, category: Option<String>| {
+        let err = ErrorResponse { error: true, code: code.to_string(), message, category, query: None };
+        CorsResponder(Envelope::failure(err).into())
+    };
+    let statuses = match InvoiceStatus::parse_list(invoice_status.as_deref().unwrap_or_default()) {
+        Ok(statuses) => statuses,
+        Err(e) => return failure("invalid_status", format!("{:#}", e), None),
+    };
+    // As it is now, so status changes made while serving count
+    let current = match statuses.is_empty() {
+        true => Metadata::default(),
+        false => match Metadata::load() {
+            Ok(metadata) => metadata,
+            Err(e) => return failure("internal_server_error", format!("{:#}", e), None),
+        },
+    };
+    let mut paths = Vec::new();
+    for collection in collections().iter().filter(|c| c.vat_check && tenancy.allows(c)) {
+        match std::fs::read_dir(&collection.folder) {
+            Ok(entries) => paths.extend(
+                entries
+                    .flatten()
+                    .map(|e| e.path())
+                    .filter(|p| docid::is_text_file(p))
+                    .filter(|p| !metadata::is_hidden(p))
+                    .filter(|p| lifecycle::selected(&statuses, current.status(p))),
+            ),
+            Err(e) => return failure("internal_server_error", e.to_string(), Some(collection.base_name().to_string())),
+        }
+    }
+    paths.sort();
+
+    let mut reports = Vec::new();
+    for path in paths {
+        let fname = path.file_name().unwrap().to_string_lossy().to_string();
+        match get_cached_content(&path) {
+            Ok(text) => reports.push(check_invoice(&fname, &text)),
+            Err(e) => eprintln!("Skipping {}: {}", fname, e),
         }
     }
+
+    CorsResponder(Envelope::success(reports).into())
+}
+
+// A tenant over its daily questions or tokens (X-Quota-* headers tell which)
+#[catch(429)]
+fn quota_exceeded() -> CorsResponder<Json<Value>> {
+    let err = ErrorResponse {
+        error: true,
+        code: "quota_exceeded".to_string(),
+        message: "Daily quota used up; see the X-Quota-* headers and Retry-After".to_string(),
+        category: None,
+        query: None,
+    };
+    CorsResponder(Envelope::failure(err).into())
+}
+
+// With tenants configured: a request without a valid API key
+#[catch(401)]
+fn unauthorized() -> CorsResponder<Json<Value>> {
+    let err = ErrorResponse {
+        error: true,
+        code: "unauthorized".to_string(),
+        message: "Missing or unknown API key (send it as `Authorization: Bearer <key>` or `X-API-Key`)".to_string(),
+        category: None,
+        query: None,
+    };
+    CorsResponder(Envelope::failure(err).into())
+}
+
+fn build_rocket(config: &Args, live: Arc<LiveConfig>) -> rocket::Rocket<rocket::Build> {
+    let mut figment = rocket::Config::figment()
+        .merge(("port", config.port))
+        .merge(("shutdown.grace", config.shutdown_grace))
+        .merge(("shutdown.mercy", shutdown::SHUTDOWN_MERCY_SECS));
+    if let Some(address) = config.address {
+        figment = figment.merge(("address", address));
+    }
+    rocket::build()
+        .configure(figment)
+        .attach(CORS)
+        .attach(shutdown::Drain)
+        .attach(QuotaHeaders)
+        .attach(IndexFollower { restart: config.restart_on_index })
+        .mount(
+            "/",
+            routes![
+                query,
+                query_stream,
+                chat_socket::ws_chat,
+                document_list,
+                upload,
+                annotate,
+                sync_manifest,
+                sync_fetch,
+                sync_push,
+                options_handler,
+                vat_check,
+                openapi::openapi_json
+            ],
+        )
+        .mount("/", openapi::swagger_ui())
+        .register("/", catchers![unauthorized, quota_exceeded])
+        .manage(live)
+        .manage(Arc::new(SessionStore::default()))
 }
 
 // Startup validation
-#[launch]
-fn rocket() -> _ {
-    let config = Args::parse();
+#[rocket::main]
+async fn main() {
+    let mut config = Args::parse();
+    // Reloads start again from the command line as given
+    let cli = config.clone();
+
+    let file_config = match Config::load(config.config.as_deref(), config.profile.as_deref()) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("ERROR: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    config.apply_config(&file_config);
+    if let Some(url) = &file_config.ollama_url {
+        ai::init_ollama_url(url);
+    }
+    if let Err(e) = hosts::init_hosts(&file_config.ollama) {
+        eprintln!("ERROR: {:#}", e);
+        std::process::exit(1);
+    }
+    reader::init_reading(file_config.reading.clone());
+    guardrail::init_guardrail(file_config.guardrail.clone());
+    lock::init_locking(config.wait);
+    if let Err(e) = encryption::init_encryption(&file_config.encryption) {
+        eprintln!("ERROR: {:#}", e);
+        std::process::exit(1);
+    }
+    if let Err(e) = store::init_store(&file_config.store) {
+        eprintln!("ERROR: {:#}", e);
+        std::process::exit(1);
+    }
+    // A config edited since the last run goes into the audit log
+    let config_file = config.config.clone().unwrap_or_else(|| doc_ai_server::config::DEFAULT_CONFIG_FILE.into());
+    audit::record_config(&audit::local_actor(), &config_file);
+    // Old formats are upgraded before anything reads them (after a restore-backup, on the next start)
+    if !matches!(config.command, Some(Command::Migrate { .. } | Command::RestoreBackup { .. }))
+        && let Err(e) = commands::run_migrations()
+    {
+        eprintln!("ERROR: {:#}", e);
+        std::process::exit(1);
+    }
+    match collections::collections_from_config(&file_config) {
+        Ok(c) => collections::init_collections(c),
+        Err(e) => {
+            eprintln!("ERROR: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+    quotas::set_quotas(&file_config);
+
+    if let Some(name) = &config.collection
+        && find_collection(name).is_none()
+    {
+        eprintln!(
+            "ERROR: Unknown collection '{}'. Valid values: {}",
+            name,
+            collections::all_collection_names_human()
+        );
+        std::process::exit(1);
+    }
+
+    if let Some(command) = config.command.as_ref().filter(|c| !matches!(c, Command::Serve)) {
+        if let Err(e) = commands::run(command, &config, &file_config).await {
+            eprintln!("ERROR: {:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(name) = &config.schema
+        && let Err(e) = file_config.schema(name)
+    {
+        eprintln!("ERROR: {:#}", e);
+        std::process::exit(1);
+    }
+
+    // Serving from a stale mirror beats not serving at all
+    if let Err(e) = commands::sync_collections(None).await {
+        eprintln!("WARNING: {:#}; using the cached copies", e);
+    }
 
     // Validate folders
-    for cat in ALL_CATEGORIES {
-        let path = std::path::Path::new(cat.folder_path());
-        if !path.exists() || !path.is_dir() {
-            eprintln!("ERROR: Required data folder missing: {}", cat.folder_path());
+    for collection in collections() {
+        if !collection.folder.is_dir() {
+            eprintln!("ERROR: Required data folder missing: {}", collection.folder.display());
             std::process::exit(1);
         }
     }
 
     println!("All data folders found. Starting server on port {}", config.port);
-    println!("Using Ollama model: {}", config.model);
-    println!("Supported categories:");
-    for cat in ALL_CATEGORIES {
-        println!("- {} ({}) → {}", cat.display_name(), cat.api_value(), cat.folder_path());
+    println!("Using Ollama model: {} (/api/{}) at {}", config.model(), config.api().endpoint(), hosts::describe());
+    println!("Supported collections:");
+    for collection in collections() {
+        println!("- {} ({}) → {}", collection.display_name, collection.name, collection.folder.display());
     }
 
-    rocket::build()
-        .configure(rocket::Config::figment().merge(("port", config.port)))
-        .attach(CORS)
-        .mount("/", routes![query, options_handler])
-        .manage(Arc::new(config))
-}
\ No newline at end of file
+    let live = Arc::new(LiveConfig::new(cli, file_config));
+    if !config.no_reload {
+        println!("Watching the config file, templates and schemas for changes");
+        tokio::spawn(reload::watch(live.clone()));
+    }
+    tokio::spawn(hosts::watch_health());
+
+    if let Err(e) = build_rocket(&config, live).launch().await {
+        eprintln!("ERROR: {}", e);
+        std::process::exit(1);
+    }
+    match shutdown::cut_off() {
+        0 => println!("Server stopped"),
+        n => eprintln!("WARNING: server stopped with {} request(s) cut off at the shutdown deadline", n),
+    }
+    if replica::restarting() {
+        std::process::exit(replica::RESTART_EXIT_CODE);
+    }
+}
@@ -0,0 +1,405 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Per-document metadata kept next to the data (the "metadata" document of the store).
+// Source files are never modified; removing or archiving a document leaves a
+// tombstone here, so re-indexing the folder does not bring it back.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{collections, Collection};
+use crate::approval::{check_approvals, Approval};
+use crate::docid::{doc_key, doc_path};
+use crate::lifecycle::{InvoiceStatus, StatusChange};
+use crate::policy::PolicyOutcome;
+use crate::quotas::Usage;
+use crate::store::{self, store};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentState {
+    /// Hidden from retrieval, kept for the record
+    Archived,
+    /// Removed from the index (the file itself stays on disk)
+    Deleted,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Tombstone {
+    pub state: DocumentState,
+    /// Unix time of the rm/archive
+    pub at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct DocumentMeta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tombstone: Option<Tombstone>,
+    /// Free-form labels set with `tag`, for filtering the documents list
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    /// Free-text notes added with `tag`, shown to the model with the document
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<Note>,
+    /// Corrected extraction set with `verify`, for `export-training`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verified: Option<Verified>,
+    /// Accounts-payable status set with `status` (received when not set)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<InvoiceStatus>,
+    /// Status changes, oldest first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub status_history: Vec<StatusChange>,
+    /// Approvals given with `approve` since the document was last extracted
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub approvals: Vec<Approval>,
+    /// Last decision of the [policy], with its reasons
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy: Option<PolicyOutcome>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Note {
+    pub text: String,
+    /// Unix time the note was added
+    pub at: u64,
+}
+
+/// One change asked of `tag` or POST /documents/annotate
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Annotation {
+    AddTag(String),
+    RemoveTag(String),
+    Note(String),
+}
+
+impl Annotation {
+    /// "+disputed" adds a tag, "-paid" takes one off, text with spaces is a note
+    /// and a bare word is a tag, added (or with `remove`, taken off)
+    pub fn parse(arg: &str, remove: bool) -> Self {
+        let arg = arg.trim();
+        if arg.contains(char::is_whitespace) {
+            return Annotation::Note(arg.to_string());
+        }
+        match (arg.strip_prefix('+'), arg.strip_prefix('-')) {
+            (Some(tag), _) => Annotation::AddTag(tag.to_string()),
+            (_, Some(tag)) => Annotation::RemoveTag(tag.to_string()),
+            _ if remove => Annotation::RemoveTag(arg.to_string()),
+            _ => Annotation::AddTag(arg.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Verified {
+    pub answer: Value,
+    /// Unix time of the `verify`
+    pub at: u64,
+}
+
+impl DocumentMeta {
+    /// Take in another instance's entry for the same document: tags and notes
+    /// are combined, everything else is only taken where this one has none
+    pub fn merge(&mut self, other: &DocumentMeta) {
+        self.tags.extend(other.tags.iter().cloned());
+        for note in &other.notes {
+            if !self.notes.iter().any(|n| n.text == note.text) {
+                self.notes.push(note.clone());
+            }
+        }
+        self.notes.sort_by_key(|n| n.at);
+        if self.tombstone.is_none() {
+            self.tombstone = other.tombstone.clone();
+        }
+        if self.verified.is_none() {
+            self.verified = other.verified.clone();
+        }
+        if self.status.is_none() && self.status_history.is_empty() {
+            self.status = other.status;
+            self.status_history = other.status_history.clone();
+            self.approvals = other.approvals.clone();
+            self.policy = other.policy.clone();
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Metadata {
+    /// Keyed by document path, '/'-separated
+    pub documents: BTreeMap<String, DocumentMeta>,
+    /// Today's questions and tokens per tenant, for the quotas
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub usage: BTreeMap<String, Usage>,
+}
+
+/// How long `current` trusts what it read before reading the store again
+const SNAPSHOT_AGE: Duration = Duration::from_secs(1);
+
+/// Metadata as this process last read or saved it, and when
+static SNAPSHOT: RwLock<Option<(Instant, Arc<Metadata>)>> = RwLock::new(None);
+
+/// The metadata as it is now: what this process saved last, or read from the store
+/// at most `SNAPSHOT_AGE` ago, so removals and status changes made by another process
+/// (or a server sharing the store) show within a second
+pub fn current() -> Arc<Metadata> {
+    if let Some((at, metadata)) = SNAPSHOT.read().unwrap().as_ref()
+        && at.elapsed() < SNAPSHOT_AGE
+    {
+        return metadata.clone();
+    }
+    let metadata = Metadata::load().unwrap_or_else(|e| {
+        eprintln!("WARNING: {:#}; ignoring document metadata", e);
+        Metadata::default()
+    });
+    remember(metadata)
+}
+
+fn remember(metadata: Metadata) -> Arc<Metadata> {
+    let metadata = Arc::new(metadata);
+    *SNAPSHOT.write().unwrap() = Some((Instant::now(), metadata.clone()));
+    metadata
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+impl Metadata {
+    pub fn load() -> Result<Self> {
+        Self::parse(store().load(store::METADATA)?)
+    }
+
+    fn parse(text: Option<String>) -> Result<Self> {
+        match text {
+            Some(text) => serde_json::from_str(&text).with_context(|| format!("Invalid metadata in {}", store().describe())),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        store().save(store::METADATA, &serde_json::to_string_pretty(self)?)?;
+        remember(self.clone());
+        Ok(())
+    }
+
+    /// Load, change and save in one step, so a change saved meanwhile by another
+    /// process (or server sharing the store) is not lost; returns what was saved
+    pub fn update(mut change: impl FnMut(&mut Metadata) + Send) -> Result<Self> {
+        let mut saved = Self::default();
+        store().update(store::METADATA, &mut |text| {
+            let mut metadata = Self::parse(text)?;
+            change(&mut metadata);
+            let text = serde_json::to_string_pretty(&metadata)?;
+            saved = metadata;
+            Ok(text)
+        })?;
+        remember(saved.clone());
+        Ok(saved)
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&DocumentMeta> {
+        self.documents.get(&doc_key(path))
+    }
+
+    pub fn entry(&mut self, path: &Path) -> &mut DocumentMeta {
+        self.documents.entry(doc_key(path)).or_default()
+    }
+
+    /// Hide a document from retrieval; returns the previous state
+    pub fn hide(&mut self, path: &Path, state: DocumentState) -> Option<DocumentState> {
+        let previous = self.entry(path).tombstone.replace(Tombstone { state, at: now() });
+        previous.map(|t| t.state)
+    }
+
+    /// Undo `hide`; returns the state the document was in
+    pub fn restore(&mut self, path: &Path) -> Option<DocumentState> {
+        let k = doc_key(path);
+        let previous = self.documents.get_mut(&k)?.tombstone.take();
+        self.prune(&k);
+        previous.map(|t| t.state)
+    }
+
+    pub fn is_hidden(&self, path: &Path) -> bool {
+        self.get(path).is_some_and(|m| m.tombstone.is_some())
+    }
+
+    /// Add (or with `remove`, take away) tags; returns the document's tags afterwards
+    pub fn tag(&mut self, path: &Path, tags: &[String], remove: bool) -> BTreeSet<String> {
+        let entry = self.entry(path);
+        for tag in tags.iter().map(|t| t.trim().to_lowercase()).filter(|t| !t.is_empty()) {
+            if remove {
+                entry.tags.remove(&tag);
+            } else {
+                entry.tags.insert(tag);
+            }
+        }
+        let tags = entry.tags.clone();
+        self.prune(&doc_key(path));
+        tags
+    }
+
+    /// Add a note; returns the document's notes afterwards
+    pub fn annotate(&mut self, path: &Path, text: &str) -> Vec<Note> {
+        let entry = self.entry(path);
+        let text = text.trim();
+        if !text.is_empty() && !entry.notes.iter().any(|n| n.text == text) {
+            entry.notes.push(Note { text: text.to_string(), at: now() });
+        }
+        let notes = entry.notes.clone();
+        self.prune(&doc_key(path));
+        notes
+    }
+
+    /// Apply tag and note changes; returns the document's entry afterwards
+    pub fn apply(&mut self, path: &Path, changes: &[Annotation]) -> DocumentMeta {
+        for change in changes {
+            match change {
+                Annotation::AddTag(tag) => {
+                    self.tag(path, std::slice::from_ref(tag), false);
+                }
+                Annotation::RemoveTag(tag) => {
+                    self.tag(path, std::slice::from_ref(tag), true);
+                }
+                Annotation::Note(text) => {
+                    self.annotate(path, text);
+                }
+            }
+        }
+        self.get(path).cloned().unwrap_or_default()
+    }
+
+    /// Remove every note of a document; returns how many there were
+    pub fn clear_notes(&mut self, path: &Path) -> usize {
+        let k = doc_key(path);
+        let removed = self.documents.get_mut(&k).map_or(0, |m| std::mem::take(&mut m.notes).len());
+        self.prune(&k);
+        removed
+    }
+
+    /// Record the corrected extraction of a document, or with None forget it;
+    /// returns whether one was recorded before
+    pub fn verify(&mut self, path: &Path, answer: Option<Value>) -> bool {
+        let previous = std::mem::replace(&mut self.entry(path).verified, answer.map(|answer| Verified { answer, at: now() }));
+        self.prune(&doc_key(path));
+        previous.is_some()
+    }
+
+    /// Accounts-payable status of a document
+    pub fn status(&self, path: &Path) -> InvoiceStatus {
+        self.get(path).and_then(|m| m.status).unwrap_or_default()
+    }
+
+    /// Move a document to another status, only along the allowed steps (and to
+    /// approved only with `approvers` approvals) unless `force`; returns the
+    /// status it had. Going back to received or extracted drops the approvals.
+    pub fn set_status(&mut self, path: &Path, to: InvoiceStatus, force: bool, approvers: usize) -> Result<InvoiceStatus> {
+        let from = self.status(path);
+        let approvals = self.get(path).map(|m| m.approvals.as_slice()).unwrap_or_default();
+        let allowed = from.check_transition(to).and_then(|_| match to {
+            InvoiceStatus::Approved => check_approvals(approvals, approvers),
+            _ => Ok(()),
+        });
+        let forced = allowed.is_err();
+        if !force {
+            allowed?;
+        }
+        let entry = self.entry(path);
+        entry.status = Some(to);
+        entry.status_history.push(StatusChange { from, to, at: now(), forced, by: None });
+        if matches!(to, InvoiceStatus::Received | InvoiceStatus::Extracted) {
+            entry.approvals.clear();
+        }
+        Ok(from)
+    }
+
+    /// Record `by`'s approval of an extracted document and approve it once it
+    /// has `approvers` different approvals (at least one); returns the approvals
+    pub fn approve(&mut self, path: &Path, by: &str, approvers: usize) -> Result<Vec<Approval>> {
+        let by = by.trim();
+        if by.is_empty() {
+            anyhow::bail!("Give the approver's name with --as");
+        }
+        let from = self.status(path);
+        from.check_transition(InvoiceStatus::Approved)?;
+        let entry = self.entry(path);
+        if entry.approvals.iter().any(|a| a.by.eq_ignore_ascii_case(by)) {
+            anyhow::bail!("{} has already approved it; another person must approve", by);
+        }
+        entry.approvals.push(Approval { by: by.to_string(), at: now() });
+        if entry.approvals.len() >= approvers.max(1) {
+            entry.status = Some(InvoiceStatus::Approved);
+            entry.status_history.push(StatusChange {
+                from,
+                to: InvoiceStatus::Approved,
+                at: now(),
+                forced: false,
+                by: Some(by.to_string()),
+            });
+        }
+        Ok(entry.approvals.clone())
+    }
+
+    /// Drop entries with nothing left in them
+    fn prune(&mut self, k: &str) {
+        if self.documents.get(k).is_some_and(|m| {
+            m.tombstone.is_none()
+                && m.tags.is_empty()
+                && m.notes.is_empty()
+                && m.verified.is_none()
+                && m.status.is_none()
+                && m.status_history.is_empty()
+                && m.approvals.is_empty()
+                && m.policy.is_none()
+        }) {
+            self.documents.remove(k);
+        }
+    }
+}
+
+/// True if the document was removed or archived (see `current`)
+pub fn is_hidden(path: &Path) -> bool {
+    current().is_hidden(path)
+}
+
+/// Accounts-payable status of a document (see `current`)
+pub fn invoice_status(path: &Path) -> InvoiceStatus {
+    current().status(path)
+}
+
+/// Find a document by path or by file name (with or without ".txt"), optionally within one collection
+pub fn resolve_document(doc: &str, collection: Option<&str>) -> Result<PathBuf> {
+    let direct = doc_path(doc);
+    if direct.is_file() {
+        return Ok(direct);
+    }
+
+    find_document(doc, collections().iter().filter(|c| collection.is_none_or(|name| c.matches(name))))
+}
+
+/// Find a document by file name (with or without ".txt") in these collections
+pub fn find_document<'a>(doc: &str, collections: impl Iterator<Item = &'a Collection>) -> Result<PathBuf> {
+    let names = [doc.to_string(), format!("{}.txt", doc)];
+    let matches: Vec<PathBuf> = collections
+        .flat_map(|c| names.iter().map(|n| c.folder.join(n)).collect::<Vec<_>>())
+        .filter(|p| p.is_file())
+        .collect();
+
+    match matches.as_slice() {
+        [] => anyhow::bail!("No document named '{}'", doc),
+        [one] => Ok(one.clone()),
+        many => anyhow::bail!(
+            "'{}' is ambiguous ({}); use --collection or give the path",
+            doc,
+            many.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
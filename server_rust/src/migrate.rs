@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Upgrades of the store's JSON documents (index, metadata, embeddings) from
+// the format an older doc-ai wrote. The "schema" document records the format
+// version of each; on start, documents behind the version this build writes
+// go through the registered migrations in order, after a backup of the whole
+// state to .doc-ai/pre-migration-<time>.tar.zst, so an upgrade no longer means
+// deleting .doc-ai and indexing again. A document written by a newer doc-ai is
+// refused rather than misread. The query and audit logs are append-only and
+// never rewritten (that would break the audit hash chain).
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::backup;
+#[cfg(feature = "embeddings")]
+use crate::embeddings::EmbeddingIndex;
+use crate::ingest::INDEX_VERSION;
+use crate::lock::{lock_index, LockMode};
+use crate::metadata::now;
+use crate::store::{self, store, STATE_DIR};
+
+/// Format versions this build writes
+pub const CURRENT: [(&str, u32); 3] = [(store::INDEX, INDEX_VERSION), (store::METADATA, 1), (store::EMBEDDINGS, 2)];
+
+/// One step up of one document's format
+pub struct Migration {
+    pub document: &'static str,
+    /// Version the document is at afterwards (from `to - 1`)
+    pub to: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut Value) -> Result<()>,
+}
+
+/// Every migration, oldest first
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        document: store::INDEX,
+        to: 1,
+        description: "record the format version in the index",
+        apply: stamp_index,
+    },
+    Migration {
+        document: store::EMBEDDINGS,
+        to: 2,
+        description: "store the embedding vectors as int8",
+        apply: quantize_embeddings,
+    },
+];
+
+/// Indexes saved before `version` was written read as version 0; the layout is otherwise the same
+fn stamp_index(index: &mut Value) -> Result<()> {
+    let fields = index.as_object_mut().context("The index is not a JSON object")?;
+    fields.insert("version".to_string(), Value::from(1));
+    Ok(())
+}
+
+/// Vectors were arrays of numbers; reading accepts those, writing packs them
+#[cfg(feature = "embeddings")]
+fn quantize_embeddings(embeddings: &mut Value) -> Result<()> {
+    let index: EmbeddingIndex = serde_json::from_value(embeddings.take()).context("Invalid embeddings")?;
+    *embeddings = serde_json::to_value(index)?;
+    Ok(())
+}
+
+/// Left as they are: they still read, and are packed when a build with embeddings saves them
+#[cfg(not(feature = "embeddings"))]
+fn quantize_embeddings(_embeddings: &mut Value) -> Result<()> {
+    Ok(())
+}
+
+/// A document behind the current format
+#[derive(Serialize, Debug, Clone)]
+pub struct Pending {
+    pub document: String,
+    pub from: u32,
+    pub to: u32,
+    /// Descriptions of the migrations to apply, in order
+    pub steps: Vec<String>,
+}
+
+/// What `migrate` did
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct MigrationReport {
+    pub migrated: Vec<Pending>,
+    /// State as it was before, when anything was migrated
+    pub backup: Option<PathBuf>,
+}
+
+fn recorded() -> Result<Option<BTreeMap<String, u32>>> {
+    match store().load(store::SCHEMA)? {
+        Some(text) => Ok(Some(serde_json::from_str(&text).context("The schema document is not valid")?)),
+        None => Ok(None),
+    }
+}
+
+/// Version of a document without a recorded one: what the index says of
+/// itself, 1 for the others (their format when versions were introduced)
+fn unrecorded_version(document: &str) -> Result<Option<u32>> {
+    let Some(text) = store().load(document)? else { return Ok(None) };
+    if document != store::INDEX {
+        return Ok(Some(1));
+    }
+    let index: Value = serde_json::from_str(&text).with_context(|| format!("Invalid index in {}", store().describe()))?;
+    Ok(Some(index.get("version").and_then(Value::as_u64).unwrap_or(0) as u32))
+}
+
+/// Documents behind the current format; an error for one written by a newer doc-ai
+pub fn pending() -> Result<Vec<Pending>> {
+    let recorded = recorded()?.unwrap_or_default();
+    let mut found = Vec::new();
+    for (document, current) in CURRENT {
+        let version = match recorded.get(document) {
+            Some(v) => Some(*v),
+            None => unrecorded_version(document)?,
+        };
+        let Some(version) = version else { continue };
+        if version > current {
+            anyhow::bail!(
+                "The {} in {} has format version {}, this doc-ai reads up to {}; upgrade doc-ai",
+                document,
+                store().describe(),
+                version,
+                current
+            );
+        }
+        if version < current {
+            let steps = MIGRATIONS
+                .iter()
+                .filter(|m| m.document == document && m.to > version && m.to <= current)
+                .map(|m| format!("{} → {}: {}", m.to - 1, m.to, m.description))
+                .collect();
+            found.push(Pending { document: document.to_string(), from: version, to: current, steps });
+        }
+    }
+    Ok(found)
+}
+
+/// Bring every document up to the current format, after a backup
+pub fn migrate() -> Result<MigrationReport> {
+    let _lock = lock_index(LockMode::Exclusive)?;
+    let pending = pending()?;
+    let versions: BTreeMap<String, u32> = CURRENT.iter().map(|(d, v)| (d.to_string(), *v)).collect();
+    if pending.is_empty() {
+        // Documents written from now on are at the current version
+        if recorded()?.as_ref() != Some(&versions) {
+            store().save(store::SCHEMA, &serde_json::to_string(&versions)?)?;
+        }
+        return Ok(MigrationReport::default());
+    }
+
+    let archive = PathBuf::from(STATE_DIR).join(format!("pre-migration-{}.tar.zst", now()));
+    backup::backup(&archive, None, false).context("Cannot back up the state before migrating it")?;
+    for p in &pending {
+        let text = store().load(&p.document)?.context("Document removed while migrating")?;
+        let mut value: Value = serde_json::from_str(&text).with_context(|| format!("Invalid {} in {}", p.document, store().describe()))?;
+        for m in MIGRATIONS.iter().filter(|m| m.document == p.document && m.to > p.from && m.to <= p.to) {
+            (m.apply)(&mut value).with_context(|| {
+                format!("Cannot migrate the {} to version {}; the state before is in {}", p.document, m.to, archive.display())
+            })?;
+        }
+        store().save(&p.document, &serde_json::to_string(&value)?)?;
+    }
+    store().save(store::SCHEMA, &serde_json::to_string(&versions)?)?;
+    Ok(MigrationReport { migrated: pending, backup: Some(archive) })
+}
@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// A collection's prompt baked into an Ollama model (`doc-ai-server modelfile`):
+// the collection's instruction and grounding rules as SYSTEM, the configured
+// options as PARAMETER lines and verified extractions as example MESSAGE pairs.
+// The Modelfile is printed (for `ollama create`), or the model is registered
+// directly with `/api/create` (`--create invoice-ai`); then `--model invoice-ai`
+// answers in the collection's way without a template on every call.
+
+use anyhow::{Context, Result};
+use reqwest::Client;
+use serde_json::{json, Map, Value};
+
+use crate::ai::ChatMessage;
+use crate::{guardrail, hosts};
+use crate::options::GenerationOptions;
+use crate::training::TrainingExample;
+use crate::Collection;
+
+/// Options a Modelfile can set (the rest are load-time settings of the server)
+const MODELFILE_PARAMETERS: [&str; 14] = [
+    "num_ctx",
+    "num_keep",
+    "num_predict",
+    "seed",
+    "temperature",
+    "top_k",
+    "top_p",
+    "min_p",
+    "typical_p",
+    "repeat_last_n",
+    "repeat_penalty",
+    "mirostat",
+    "mirostat_tau",
+    "mirostat_eta",
+];
+
+/// Everything `FROM`, `PARAMETER`, `SYSTEM` and `MESSAGE` say
+#[derive(Debug, Clone)]
+pub struct ModelSpec {
+    pub base: String,
+    pub system: String,
+    pub parameters: Map<String, Value>,
+    /// Example conversation, user and assistant turns
+    pub messages: Vec<ChatMessage>,
+    /// Comment lines at the top of the Modelfile
+    pub comments: Vec<String>,
+}
+
+impl ModelSpec {
+    /// The model for `collection`, with up to `max_examples` of its verified extractions as examples
+    pub fn for_collection(
+        collection: &Collection,
+        base: &str,
+        options: &GenerationOptions,
+        examples: &[TrainingExample],
+        max_examples: usize,
+    ) -> Self {
+        let mut parameters = Map::new();
+        if let Ok(Value::Object(all)) = serde_json::to_value(options) {
+            for (key, value) in all {
+                if MODELFILE_PARAMETERS.contains(&key.as_str()) || key == "stop" {
+                    parameters.insert(key, value);
+                }
+            }
+        }
+        let examples: Vec<&TrainingExample> = examples.iter().filter(|e| e.collection == collection.name).take(max_examples).collect();
+        let mut comments = vec![format!("{} model made by doc-ai-server modelfile", collection.display_name)];
+        comments.extend(examples.iter().map(|e| format!("example: {}", e.file)));
+        Self {
+            base: base.to_string(),
+            system: format!("{}\n\n{}", collection.instruction, collection.strictness.rules()),
+            parameters,
+            messages: examples.iter().flat_map(|e| e.messages.iter().skip(1).cloned()).collect(),
+            comments,
+        }
+    }
+
+    pub fn to_modelfile(&self) -> String {
+        let mut out: String = self.comments.iter().map(|c| format!("# {}\n", c)).collect();
+        out.push_str(&format!("FROM {}\n", self.base));
+        for (key, value) in &self.parameters {
+            match value {
+                // One line per stop sequence
+                Value::Array(items) => {
+                    for item in items {
+                        out.push_str(&format!("PARAMETER {} {}\n", key, item));
+                    }
+                }
+                value => out.push_str(&format!("PARAMETER {} {}\n", key, value)),
+            }
+        }
+        out.push_str(&format!("SYSTEM {}\n", quoted(&self.system)));
+        for message in &self.messages {
+            out.push_str(&format!("MESSAGE {} {}\n", message.role, quoted(&message.content)));
+        }
+        out
+    }
+
+    /// Register the model as `name` with Ollama (on every host of `[ollama]` that serves the base model)
+    pub async fn create(&self, name: &str) -> Result<()> {
+        let messages: Vec<Value> = self.messages.iter().map(|m| json!({"role": m.role, "content": m.content})).collect();
+        let body = json!({
+            "model": name,
+            "from": self.base,
+            "system": self.system,
+            "parameters": self.parameters,
+            "messages": messages,
+            "stream": false,
+        });
+        for url in hosts::urls(&self.base) {
+            let mut outgoing = body.clone();
+            guardrail::check_outgoing(url, &mut outgoing)?;
+            let res = Client::new()
+                .post(format!("{}/api/create", url))
+                .json(&outgoing)
+                .send()
+                .await
+                .with_context(|| format!("Cannot reach Ollama at {}", url))?;
+            if !res.status().is_success() {
+                let status = res.status();
+                let text = res.text().await.unwrap_or_default();
+                anyhow::bail!("Ollama at {} could not create '{}' ({}): {}", url, name, status, text.trim());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Modelfile strings are triple-quoted; keep the content from closing them
+fn quoted(text: &str) -> String {
+    format!("\"\"\"{}\"\"\"", text.replace("\"\"\"", "\" \" \""))
+}
@@ -0,0 +1,89 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// OpenAPI 3 description of the server, served at /openapi.json so clients
+// can be generated from it. The request and response types derive their
+// schemas; the handlers carry `#[utoipa::path]` next to their route. Every
+// JSON response is an `Envelope`, described here once per payload type so
+// generated clients get a typed `data`. Built with the `swagger-ui` feature,
+// the server also serves a browsable UI at /swagger-ui/.
+
+use rocket::serde::json::Json;
+use serde::Serialize;
+use utoipa::{OpenApi, ToSchema};
+
+use doc_ai_server::*;
+
+/// Envelope of POST /query and of the `answer` event of /query/stream
+#[derive(Serialize, ToSchema)]
+pub struct QueryEnvelope {
+    pub success: bool,
+    pub data: Option<ApiResponse>,
+    pub error: Option<ErrorResponse>,
+}
+
+/// Envelope of GET /documents
+#[derive(Serialize, ToSchema)]
+pub struct DocumentsEnvelope {
+    pub success: bool,
+    pub data: Option<DocumentPage>,
+    pub error: Option<ErrorResponse>,
+}
+
+/// Envelope of POST /documents/annotate
+#[derive(Serialize, ToSchema)]
+pub struct DocumentEnvelope {
+    pub success: bool,
+    pub data: Option<DocumentSummary>,
+    pub error: Option<ErrorResponse>,
+}
+
+/// Envelope of POST /documents
+#[derive(Serialize, ToSchema)]
+pub struct IntakeEnvelope {
+    pub success: bool,
+    pub data: Option<IntakeResult>,
+    pub error: Option<ErrorResponse>,
+}
+
+/// Envelope of GET /vat-check
+#[derive(Serialize, ToSchema)]
+pub struct VatCheckEnvelope {
+    pub success: bool,
+    pub data: Option<Vec<VatReport>>,
+    pub error: Option<ErrorResponse>,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "doc-ai server", description = "Questions answered from local documents by a local model"),
+    paths(
+        crate::query,
+        crate::query_stream,
+        crate::chat_socket::ws_chat,
+        crate::document_list,
+        crate::upload,
+        crate::annotate,
+        crate::vat_check,
+        openapi_json
+    ),
+    components(schemas(QueryEvent))
+)]
+pub struct ApiDoc;
+
+#[utoipa::path(get, path = "/openapi.json", responses((status = 200, description = "This specification")))]
+#[get("/openapi.json")]
+pub fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// /swagger-ui/, reading the specification from /openapi.json
+#[cfg(feature = "swagger-ui")]
+pub fn swagger_ui() -> Vec<rocket::Route> {
+    use utoipa_swagger_ui::{Config, SwaggerUi};
+    SwaggerUi::new("/swagger-ui/<_..>").config(Config::from("/openapi.json")).into()
+}
+
+#[cfg(not(feature = "swagger-ui"))]
+pub fn swagger_ui() -> Vec<rocket::Route> {
+    Vec::new()
+}
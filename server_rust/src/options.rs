@@ -0,0 +1,268 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Typed Ollama generation options.
+// Replaces the untyped `serde_json::Value`, where a typo such as `temprature`
+// was silently ignored by Ollama. Unset options are left out of the request.
+
+use serde::{Deserialize, Serialize};
+
+/// Ollama's documented `options` keys (see the Modelfile PARAMETER docs)
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct GenerationOptions {
+    /// Number of prompt tokens kept when the context is shifted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_keep: Option<i32>,
+    /// Random seed, for reproducible sampling
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i32>,
+    /// Maximum number of tokens to generate (-1 = unlimited)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<i32>,
+    /// Sample only from the k most likely tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    /// Nucleus sampling threshold
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Minimum token probability relative to the most likely token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_p: Option<f32>,
+    /// Locally typical sampling threshold
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typical_p: Option<f32>,
+    /// How far back to look when penalising repetition
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_last_n: Option<i32>,
+    /// Sampling temperature (0.0 = deterministic)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Penalty for repeated tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+    /// Penalty for tokens already present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// Penalty proportional to token frequency
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    /// Mirostat sampling (0 = off, 1 or 2)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirostat: Option<u8>,
+    /// Mirostat target entropy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirostat_tau: Option<f32>,
+    /// Mirostat learning rate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirostat_eta: Option<f32>,
+    /// Whether newlines count towards the repeat penalty
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub penalize_newline: Option<bool>,
+    /// Stop sequences
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    /// Enable NUMA support
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub numa: Option<bool>,
+    /// Context window size in tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
+    /// Prompt processing batch size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_batch: Option<u32>,
+    /// Number of layers offloaded to the GPU
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_gpu: Option<i32>,
+    /// GPU used for small tensors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub main_gpu: Option<u32>,
+    /// Reduce VRAM usage
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub low_vram: Option<bool>,
+    /// Load only the vocabulary
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vocab_only: Option<bool>,
+    /// Memory-map the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_mmap: Option<bool>,
+    /// Lock the model in RAM
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_mlock: Option<bool>,
+    /// Number of CPU threads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_thread: Option<u32>,
+}
+
+impl GenerationOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn num_keep(mut self, num_keep: i32) -> Self {
+        self.num_keep = Some(num_keep);
+        self
+    }
+
+    pub fn seed(mut self, seed: i32) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn num_predict(mut self, num_predict: i32) -> Self {
+        self.num_predict = Some(num_predict);
+        self
+    }
+
+    pub fn top_k(mut self, top_k: u32) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+
+    pub fn top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    pub fn min_p(mut self, min_p: f32) -> Self {
+        self.min_p = Some(min_p);
+        self
+    }
+
+    pub fn typical_p(mut self, typical_p: f32) -> Self {
+        self.typical_p = Some(typical_p);
+        self
+    }
+
+    pub fn repeat_last_n(mut self, repeat_last_n: i32) -> Self {
+        self.repeat_last_n = Some(repeat_last_n);
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn repeat_penalty(mut self, repeat_penalty: f32) -> Self {
+        self.repeat_penalty = Some(repeat_penalty);
+        self
+    }
+
+    pub fn presence_penalty(mut self, presence_penalty: f32) -> Self {
+        self.presence_penalty = Some(presence_penalty);
+        self
+    }
+
+    pub fn frequency_penalty(mut self, frequency_penalty: f32) -> Self {
+        self.frequency_penalty = Some(frequency_penalty);
+        self
+    }
+
+    pub fn mirostat(mut self, mirostat: u8) -> Self {
+        self.mirostat = Some(mirostat);
+        self
+    }
+
+    pub fn mirostat_tau(mut self, mirostat_tau: f32) -> Self {
+        self.mirostat_tau = Some(mirostat_tau);
+        self
+    }
+
+    pub fn mirostat_eta(mut self, mirostat_eta: f32) -> Self {
+        self.mirostat_eta = Some(mirostat_eta);
+        self
+    }
+
+    pub fn penalize_newline(mut self, penalize_newline: bool) -> Self {
+        self.penalize_newline = Some(penalize_newline);
+        self
+    }
+
+    pub fn stop(mut self, stop: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.stop = Some(stop.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn numa(mut self, numa: bool) -> Self {
+        self.numa = Some(numa);
+        self
+    }
+
+    pub fn num_ctx(mut self, num_ctx: u32) -> Self {
+        self.num_ctx = Some(num_ctx);
+        self
+    }
+
+    pub fn num_batch(mut self, num_batch: u32) -> Self {
+        self.num_batch = Some(num_batch);
+        self
+    }
+
+    pub fn num_gpu(mut self, num_gpu: i32) -> Self {
+        self.num_gpu = Some(num_gpu);
+        self
+    }
+
+    pub fn main_gpu(mut self, main_gpu: u32) -> Self {
+        self.main_gpu = Some(main_gpu);
+        self
+    }
+
+    pub fn low_vram(mut self, low_vram: bool) -> Self {
+        self.low_vram = Some(low_vram);
+        self
+    }
+
+    pub fn vocab_only(mut self, vocab_only: bool) -> Self {
+        self.vocab_only = Some(vocab_only);
+        self
+    }
+
+    pub fn use_mmap(mut self, use_mmap: bool) -> Self {
+        self.use_mmap = Some(use_mmap);
+        self
+    }
+
+    pub fn use_mlock(mut self, use_mlock: bool) -> Self {
+        self.use_mlock = Some(use_mlock);
+        self
+    }
+
+    pub fn num_thread(mut self, num_thread: u32) -> Self {
+        self.num_thread = Some(num_thread);
+        self
+    }
+
+    /// Options explicitly set in `other` win over the ones in `self`
+    pub fn merged_with(self, other: GenerationOptions) -> Self {
+        Self {
+            num_keep: other.num_keep.or(self.num_keep),
+            seed: other.seed.or(self.seed),
+            num_predict: other.num_predict.or(self.num_predict),
+            top_k: other.top_k.or(self.top_k),
+            top_p: other.top_p.or(self.top_p),
+            min_p: other.min_p.or(self.min_p),
+            typical_p: other.typical_p.or(self.typical_p),
+            repeat_last_n: other.repeat_last_n.or(self.repeat_last_n),
+            temperature: other.temperature.or(self.temperature),
+            repeat_penalty: other.repeat_penalty.or(self.repeat_penalty),
+            presence_penalty: other.presence_penalty.or(self.presence_penalty),
+            frequency_penalty: other.frequency_penalty.or(self.frequency_penalty),
+            mirostat: other.mirostat.or(self.mirostat),
+            mirostat_tau: other.mirostat_tau.or(self.mirostat_tau),
+            mirostat_eta: other.mirostat_eta.or(self.mirostat_eta),
+            penalize_newline: other.penalize_newline.or(self.penalize_newline),
+            stop: other.stop.or(self.stop),
+            numa: other.numa.or(self.numa),
+            num_ctx: other.num_ctx.or(self.num_ctx),
+            num_batch: other.num_batch.or(self.num_batch),
+            num_gpu: other.num_gpu.or(self.num_gpu),
+            main_gpu: other.main_gpu.or(self.main_gpu),
+            low_vram: other.low_vram.or(self.low_vram),
+            vocab_only: other.vocab_only.or(self.vocab_only),
+            use_mmap: other.use_mmap.or(self.use_mmap),
+            use_mlock: other.use_mlock.or(self.use_mlock),
+            num_thread: other.num_thread.or(self.num_thread),
+        }
+    }
+}
@@ -0,0 +1,228 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Bank statements: CSV exports and OFX files kept in the `payments`
+// collection, read into one payment per transaction. Only money going out
+// is a payment (of a supplier invoice); incoming transactions are ignored.
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::records::parse_date;
+use crate::{find_collection, Collection};
+
+/// Collection the statements are stored in
+pub const PAYMENTS_COLLECTION: &str = "payments";
+
+/// File types accepted as statements
+pub const STATEMENT_EXTENSIONS: [&str; 3] = ["csv", "ofx", "qfx"];
+
+static OFX_TRANSACTION_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)<STMTTRN>").unwrap());
+
+static OFX_CURRENCY_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)<CURDEF>\s*([A-Z]{3})").unwrap());
+
+/// DD/MM/YYYY, DD.MM.YYYY or YYYY/MM/DD
+static NUMERIC_DATE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\d{1,2})[/.](\d{1,2})[/.](\d{4})$|^(\d{4})/(\d{1,2})/(\d{1,2})$").unwrap());
+
+/// One outgoing bank transaction
+#[derive(Serialize, Debug, Clone)]
+pub struct Payment {
+    /// Statement file and transaction number in it ("march.csv#4")
+    pub source: String,
+    /// ISO date
+    pub date: Option<String>,
+    /// Amount paid (positive)
+    pub amount: Decimal,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// Payee or description as printed on the statement
+    pub payee: String,
+    /// Payment reference, where the statement has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+}
+
+impl Payment {
+    /// Payee and reference together, for finding invoice numbers
+    pub fn text(&self) -> String {
+        format!("{} {}", self.payee, self.reference.as_deref().unwrap_or_default())
+    }
+}
+
+/// The configured `payments` collection
+pub fn payments_collection() -> Result<&'static Collection> {
+    find_collection(PAYMENTS_COLLECTION).ok_or_else(|| {
+        anyhow::anyhow!("No '{}' collection: add a [[collection]] named payments to the config file", PAYMENTS_COLLECTION)
+    })
+}
+
+/// "-1,234.50", "(1 234.50)", "R 1234,50" → signed decimal
+fn parse_signed(s: &str) -> Option<Decimal> {
+    let s = s.trim();
+    let negative = s.starts_with('-') || s.ends_with('-') || (s.starts_with('(') && s.ends_with(')'));
+    let mut digits: String = s.chars().filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',')).collect();
+    if digits.contains('.') {
+        digits.retain(|c| c != ',');
+    } else if let Some(pos) = digits.rfind(',') {
+        // A lone comma two digits from the end is a decimal comma
+        if digits.len() - pos == 3 {
+            digits.replace_range(pos..=pos, ".");
+        }
+        digits.retain(|c| c != ',');
+    }
+    let value = Decimal::from_str(&digits).ok()?;
+    Some(if negative { -value } else { value })
+}
+
+/// ISO, "15 Nov 2025", DD/MM/YYYY or the OFX form YYYYMMDD[hhmmss]
+fn statement_date(s: &str) -> Option<String> {
+    let s = s.trim();
+    if s.len() >= 8 && s[..8].chars().all(|c| c.is_ascii_digit()) {
+        return Some(format!("{}-{}-{}", &s[..4], &s[4..6], &s[6..8]));
+    }
+    if let Some(c) = NUMERIC_DATE_RE.captures(s) {
+        let (year, month, day) = match c.get(3) {
+            Some(year) => (year.as_str(), &c[2], &c[1]),
+            None => (c.get(4)?.as_str(), &c[5], &c[6]),
+        };
+        return Some(format!("{}-{:0>2}-{:0>2}", year, month, day));
+    }
+    parse_date(s)
+}
+
+/// Split one CSV line, honouring quoted fields
+fn csv_fields(line: &str, separator: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+/// Column of the first header matching one of `names`
+fn column(header: &[String], names: &[&str]) -> Option<usize> {
+    names.iter().find_map(|name| header.iter().position(|h| h.to_lowercase() == *name))
+}
+
+/// CSV export from online banking: a header row naming the date, amount (or
+/// debit/credit) and description columns. Comma, semicolon or tab separated.
+fn parse_csv(file: &str, text: &str) -> Result<Vec<Payment>> {
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let first = lines.next().unwrap_or_default();
+    let separator = [';', '\t', ','].into_iter().max_by_key(|s| first.matches(*s).count()).unwrap_or(',');
+    let header = csv_fields(first, separator);
+
+    let date = column(&header, &["date", "transaction date", "posting date", "booking date", "value date"])
+        .with_context(|| format!("{}: no date column in the header", file))?;
+    let amount = column(&header, &["amount", "transaction amount"]);
+    let debit = column(&header, &["debit", "withdrawal", "withdrawals", "paid out", "money out"]);
+    let payee = column(&header, &["payee", "beneficiary", "name", "description", "details", "narrative", "memo"]);
+    let reference = column(&header, &["reference", "ref", "payment reference", "memo"]).filter(|r| Some(*r) != payee);
+    let currency = column(&header, &["currency", "ccy"]);
+    if amount.is_none() && debit.is_none() {
+        anyhow::bail!("{}: no amount or debit column in the header", file);
+    }
+
+    let mut payments = Vec::new();
+    for (n, line) in lines.enumerate() {
+        let row = csv_fields(line, separator);
+        let cell = |i: Option<usize>| i.and_then(|i| row.get(i)).map(String::as_str).filter(|s| !s.is_empty());
+        // Outgoing: a negative amount, or anything in the debit column
+        let paid = match (cell(debit).and_then(parse_signed), cell(amount).and_then(parse_signed)) {
+            (Some(d), _) if !d.is_zero() => d.abs(),
+            (_, Some(a)) if a < Decimal::ZERO => -a,
+            _ => continue,
+        };
+        payments.push(Payment {
+            source: format!("{}#{}", file, n + 1),
+            date: cell(Some(date)).and_then(statement_date),
+            amount: paid,
+            currency: cell(currency).map(str::to_uppercase),
+            payee: cell(payee).unwrap_or_default().to_string(),
+            reference: cell(reference).map(str::to_string),
+        });
+    }
+    Ok(payments)
+}
+
+/// Value of an OFX element; SGML-style OFX (1.x) leaves elements unclosed
+fn ofx_value(block: &str, tag: &str) -> Option<String> {
+    let start = block.to_ascii_uppercase().find(&format!("<{}>", tag))? + tag.len() + 2;
+    let value = block[start..].split(['<', '\n', '\r']).next()?.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+fn parse_ofx(file: &str, text: &str) -> Vec<Payment> {
+    let currency = OFX_CURRENCY_RE.captures(text).map(|c| c[1].to_uppercase());
+    OFX_TRANSACTION_RE
+        .split(text)
+        .skip(1)
+        .enumerate()
+        .filter_map(|(n, block)| {
+            let amount = parse_signed(&ofx_value(block, "TRNAMT")?)?;
+            if amount >= Decimal::ZERO {
+                return None;
+            }
+            Some(Payment {
+                source: format!("{}#{}", file, n + 1),
+                date: ofx_value(block, "DTPOSTED").and_then(|d| statement_date(&d)),
+                amount: -amount,
+                currency: currency.clone(),
+                payee: ofx_value(block, "NAME").or_else(|| ofx_value(block, "PAYEE")).unwrap_or_default(),
+                reference: ofx_value(block, "MEMO").or_else(|| ofx_value(block, "CHECKNUM")),
+            })
+        })
+        .collect()
+}
+
+/// Outgoing payments in one statement; the format follows the extension
+pub fn parse_statement(path: &Path, text: &str) -> Result<Vec<Payment>> {
+    let file = path.file_name().unwrap_or_default().to_string_lossy();
+    match path.extension().and_then(|e| e.to_str()).map(str::to_lowercase).as_deref() {
+        Some("csv") => parse_csv(&file, text),
+        Some("ofx") | Some("qfx") => Ok(parse_ofx(&file, text)),
+        _ => anyhow::bail!("{}: not a CSV or OFX statement", file),
+    }
+}
+
+/// Every payment in the statements of the `payments` collection, by date
+pub fn load_payments() -> Result<Vec<Payment>> {
+    let collection = payments_collection()?;
+    let Ok(entries) = fs::read_dir(&collection.folder) else { return Ok(Vec::new()) };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| STATEMENT_EXTENSIONS.iter().any(|s| s.eq_ignore_ascii_case(e)))
+        })
+        .collect();
+    paths.sort();
+
+    let mut payments = Vec::new();
+    for path in paths {
+        let bytes = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        payments.extend(parse_statement(&path, &String::from_utf8_lossy(&bytes))?);
+    }
+    payments.sort_by(|a, b| a.date.cmp(&b.date));
+    Ok(payments)
+}
@@ -0,0 +1,759 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Query pipeline: collections → retrieval → prompt → model → post-processing (postprocess.rs).
+// Build a `Query` with `Query::builder(question)` and execute it with `run()`.
+
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::aggregate::{aggregate, plan, AggregationResult};
+use crate::agent::{is_tools_unsupported, run_agent};
+use crate::ai::{
+    default_options, push_document, query_ollama, query_ollama_chat, AnswerStatus, OllamaApi, Strictness, DEFAULT_MODEL,
+};
+use crate::chunking::chunk_document;
+use crate::collections::all_collection_names_human;
+use crate::decompose::{compose, split_question};
+use crate::domain::Domain;
+use crate::escalation::{failed_checks, merge, Escalation, EscalationConfig};
+use crate::events::{EventSink, NoEvents, Profiled};
+use crate::freshness;
+use crate::guardrail;
+use crate::indexer::INVERTED_INDEX;
+use crate::json_repair::parse_lenient;
+use crate::locale::{verified_amounts, Locale};
+use crate::lifecycle::{self, InvoiceStatus};
+use crate::metadata::Metadata;
+use crate::postprocess::{default_chain, run_chain, PostContext, PostProcessor};
+use crate::prompts::prompt_version;
+use crate::provenance::{ExplainFormat, Provenance};
+use crate::query_log;
+use crate::redact::OutputProfile;
+use crate::records::collection_records;
+use crate::retrieval::{retrieve, RetrievalConfig, DEFAULT_MAX_DOCS, MAX_TOP_K};
+use crate::routing::RoutingConfig;
+use crate::schema::validate;
+use crate::scoring::{rank_with, RelevanceScorer};
+use crate::sampling::{summarize, Consistency, MAX_SAMPLES, SAMPLING_TEMPERATURE};
+use crate::session::{history_block, Turn};
+use crate::versions::{is_aggregation, VERSION_GRAPH};
+use crate::warnings::{estimate_tokens, Warning, WarningCode, DEFAULT_NUM_CTX};
+use crate::{
+    check_invoice, find_collection, get_cached_content, ApiResponse, Collection, ErrorResponse, GenerationOptions,
+    SkippedFile,
+};
+
+/// A fully specified question, ready to run
+#[derive(Debug, Clone)]
+pub struct Query {
+    pub question: String,
+    pub model: String,
+    pub api: OllamaApi,
+    /// Overrides for the default generation options
+    pub options: GenerationOptions,
+    pub max_docs: usize,
+    pub retrieval: RetrievalConfig,
+    /// Custom relevance scoring, replacing the configured retrieval mode
+    pub scorer: Option<Arc<dyn RelevanceScorer>>,
+    /// Prompt template overriding the collection's ({system_role}, {rules}, {contents}, {query})
+    pub template: Option<String>,
+    /// JSON schema the answer must follow
+    pub schema: Option<Value>,
+    /// Domain whose prompt, schema and checks replace the collection's
+    pub domain: Option<Domain>,
+    /// Overrides the collection's grounding rules
+    pub strictness: Option<Strictness>,
+    /// Collection names or aliases; several means a cross-collection query
+    pub collections: Vec<String>,
+    pub agent: bool,
+    pub samples: usize,
+    pub explain: Option<ExplainFormat>,
+    /// Keep superseded documents (originals replaced by a correction) in aggregation questions
+    pub include_superseded: bool,
+    /// Only documents in these accounts-payable statuses (all when empty)
+    pub invoice_statuses: Vec<InvoiceStatus>,
+    /// Fail on a document that cannot be read instead of skipping it
+    pub strict: bool,
+    /// Split compound questions and answer each part separately
+    pub decompose: bool,
+    /// Role-based view: answer fields outside its allowlist are removed
+    pub profile: Option<OutputProfile>,
+    /// Fail instead of answering with warnings
+    pub deny_warnings: bool,
+    /// Render verified amounts and dates in the answer for this locale
+    pub locale: Option<Locale>,
+    /// Progress events (none by default)
+    pub events: Option<Arc<dyn EventSink>>,
+    /// Earlier turns of a chat session, shown to the model before the documents
+    pub history: Vec<Turn>,
+    /// Pick the model by the question's complexity (`model` when no rule matches)
+    pub routing: Option<RoutingConfig>,
+    /// Ask a larger model again when the answer fails a check
+    pub escalation: Option<EscalationConfig>,
+    /// Steps the answer goes through before it is returned (see postprocess.rs)
+    pub post_processors: Vec<Arc<dyn PostProcessor>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryBuilder {
+    query: Query,
+}
+
+impl Query {
+    pub fn builder(question: impl Into<String>) -> QueryBuilder {
+        QueryBuilder {
+            query: Query {
+                question: question.into(),
+                model: DEFAULT_MODEL.to_string(),
+                api: OllamaApi::default(),
+                options: GenerationOptions::default(),
+                max_docs: DEFAULT_MAX_DOCS,
+                retrieval: RetrievalConfig::default(),
+                scorer: None,
+                template: None,
+                schema: None,
+                domain: None,
+                strictness: None,
+                collections: Vec::new(),
+                agent: false,
+                samples: 1,
+                explain: None,
+                include_superseded: false,
+                invoice_statuses: Vec::new(),
+                strict: false,
+                decompose: false,
+                profile: None,
+                deny_warnings: false,
+                locale: None,
+                events: None,
+                history: Vec::new(),
+                routing: None,
+                escalation: None,
+                post_processors: default_chain(),
+            },
+        }
+    }
+}
+
+impl QueryBuilder {
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.query.model = model.into();
+        self
+    }
+
+    pub fn api(mut self, api: OllamaApi) -> Self {
+        self.query.api = api;
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.query.options.temperature = Some(temperature);
+        self
+    }
+
+    /// Generation options; the ones set here override the defaults
+    pub fn options(mut self, options: GenerationOptions) -> Self {
+        self.query.options = options;
+        self
+    }
+
+    /// Longest answer in tokens (`num_predict`); a JSON answer cut off there is continued
+    pub fn max_answer_tokens(mut self, tokens: i32) -> Self {
+        self.query.options.num_predict = Some(tokens);
+        self
+    }
+
+    /// Maximum number of documents retrieved per collection (at most `MAX_TOP_K`)
+    pub fn max_docs(mut self, max_docs: usize) -> Self {
+        self.query.max_docs = max_docs.clamp(1, MAX_TOP_K);
+        self
+    }
+
+    /// Keyword, embedding or hybrid retrieval settings
+    pub fn retrieval(mut self, retrieval: RetrievalConfig) -> Self {
+        self.query.retrieval = retrieval;
+        self
+    }
+
+    /// Rank documents with `scorer` instead of the retrieval settings
+    pub fn scorer(mut self, scorer: impl RelevanceScorer + 'static) -> Self {
+        self.query.scorer = Some(Arc::new(scorer));
+        self
+    }
+
+    pub fn template(mut self, template: impl Into<String>) -> Self {
+        self.query.template = Some(template.into());
+        self
+    }
+
+    pub fn schema(mut self, schema: Value) -> Self {
+        self.query.schema = Some(schema);
+        self
+    }
+
+    /// Answer in this domain's mode (e.g. the clause mode of `contracts`); a schema set here still wins
+    pub fn domain(mut self, domain: Domain) -> Self {
+        self.query.domain = Some(domain);
+        self
+    }
+
+    /// How forceful the anti-hallucination rules are (default: the collection's setting)
+    pub fn strictness(mut self, strictness: Strictness) -> Self {
+        self.query.strictness = Some(strictness);
+        self
+    }
+
+    /// Add a collection to query (call several times for a cross-collection query)
+    pub fn collection(mut self, name: impl Into<String>) -> Self {
+        self.query.collections.push(name.into());
+        self
+    }
+
+    pub fn agent(mut self, agent: bool) -> Self {
+        self.query.agent = agent;
+        self
+    }
+
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.query.samples = samples.clamp(1, MAX_SAMPLES);
+        self
+    }
+
+    pub fn explain(mut self, format: ExplainFormat) -> Self {
+        self.query.explain = Some(format);
+        self
+    }
+
+    pub fn include_superseded(mut self, include: bool) -> Self {
+        self.query.include_superseded = include;
+        self
+    }
+
+    pub fn invoice_statuses(mut self, statuses: Vec<InvoiceStatus>) -> Self {
+        self.query.invoice_statuses = statuses;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.query.strict = strict;
+        self
+    }
+
+    pub fn decompose(mut self, decompose: bool) -> Self {
+        self.query.decompose = decompose;
+        self
+    }
+
+    pub fn profile(mut self, profile: OutputProfile) -> Self {
+        self.query.profile = Some(profile);
+        self
+    }
+
+    /// Report progress to `sink`
+    pub fn events(mut self, sink: impl EventSink + 'static) -> Self {
+        self.query.events = Some(Arc::new(sink));
+        self
+    }
+
+    pub fn locale(mut self, locale: Locale) -> Self {
+        self.query.locale = Some(locale);
+        self
+    }
+
+    pub fn deny_warnings(mut self, deny: bool) -> Self {
+        self.query.deny_warnings = deny;
+        self
+    }
+
+    /// Earlier questions and answers the new one may refer to
+    pub fn history(mut self, turns: Vec<Turn>) -> Self {
+        self.query.history = turns;
+        self
+    }
+
+    pub fn routing(mut self, routing: RoutingConfig) -> Self {
+        self.query.routing = Some(routing);
+        self
+    }
+
+    pub fn escalation(mut self, escalation: EscalationConfig) -> Self {
+        self.query.escalation = Some(escalation);
+        self
+    }
+
+    /// Replace the post-processing steps (json-repair → schema-validate → citation-verify →
+    /// arithmetic-check → redact by default). The output profile is applied even if `chain`
+    /// has no `redact` step.
+    pub fn post_processors(mut self, chain: Vec<Arc<dyn PostProcessor>>) -> Self {
+        self.query.post_processors = chain;
+        self
+    }
+
+    /// Run `step` just before the step named `before` ("redact", say), or last if there is none
+    pub fn post_processor_before(mut self, before: &str, step: impl PostProcessor + 'static) -> Self {
+        let chain = &mut self.query.post_processors;
+        let at = chain.iter().position(|s| s.name() == before).unwrap_or(chain.len());
+        chain.insert(at, Arc::new(step));
+        self
+    }
+
+    pub fn build(self) -> Query {
+        self.query
+    }
+}
+
+fn failure(code: &str, message: impl Into<String>, category: &str, query: &str) -> ErrorResponse {
+    ErrorResponse {
+        error: true,
+        code: code.to_string(),
+        message: message.into(),
+        category: Some(category.to_string()),
+        query: Some(query.to_string()),
+    }
+}
+
+impl Query {
+    /// Resolve the requested collections (deduplicated, in request order)
+    fn resolve_collections(&self) -> Result<Vec<&'static Collection>, ErrorResponse> {
+        let mut selected: Vec<&'static Collection> = Vec::new();
+        for name in &self.collections {
+            match find_collection(name) {
+                Some(c) if !selected.iter().any(|s| s.name == c.name) => selected.push(c),
+                Some(_) => {}
+                None => {
+                    return Err(failure(
+                        "invalid_category",
+                        format!("Unknown collection '{}'. Valid values: {}", name, all_collection_names_human()),
+                        name,
+                        &self.question,
+                    ))
+                }
+            }
+        }
+        if selected.is_empty() {
+            return Err(failure(
+                "invalid_category",
+                format!("No collection given. Valid values: {}", all_collection_names_human()),
+                "",
+                &self.question,
+            ));
+        }
+        Ok(selected)
+    }
+
+    /// The collection whose prompt settings are used, with this query's overrides applied
+    fn effective_collection(&self, selected: &[&Collection]) -> Collection {
+        let mut collection = match selected {
+            [single] => (*single).clone(),
+            _ => Collection::combined(selected),
+        };
+        if let Some(domain) = &self.domain {
+            collection.use_domain(domain);
+        }
+        if let Some(template) = &self.template {
+            collection.template = Some(template.clone());
+        }
+        if let Some(strictness) = self.strictness {
+            collection.strictness = strictness;
+        }
+        if let Some(schema) = &self.schema {
+            collection.schema = Some(schema.clone());
+        }
+        if let Some(schema) = &collection.schema {
+            collection.instruction = format!(
+                "{}\n\nThe JSON answer must follow this JSON schema:\n{}",
+                collection.instruction,
+                serde_json::to_string_pretty(schema).unwrap_or_default()
+            );
+        }
+        collection
+    }
+
+    fn events(&self) -> &dyn EventSink {
+        self.events.as_deref().unwrap_or(&NoEvents)
+    }
+
+    /// Execute the pipeline
+    pub async fn run(&self) -> Result<ApiResponse, ErrorResponse> {
+        let started = Instant::now();
+        let routed = match &self.routing {
+            Some(routing) => {
+                let model = routing.route(&self.question, &self.collections, &self.model).await;
+                Some(Query { model, routing: None, ..self.clone() })
+            }
+            None => None,
+        };
+        let result = routed.as_ref().unwrap_or(self).run_parts().await;
+        if let Ok(response) = &result {
+            query_log::record(&self.question, &self.collections, response);
+        }
+        self.events().on_done(started.elapsed().as_millis() as u64, result.is_ok());
+        result
+    }
+
+    async fn run_parts(&self) -> Result<ApiResponse, ErrorResponse> {
+        let parts = if self.decompose { split_question(&self.question) } else { Vec::new() };
+        if parts.len() < 2 {
+            return self.run_single().await.and_then(|r| self.check_warnings(r));
+        }
+
+        let mut responses = Vec::new();
+        for part in &parts {
+            let sub = Query { question: part.clone(), decompose: false, ..self.clone() };
+            responses.push(sub.run_single().await?);
+        }
+        self.check_warnings(compose(&parts, responses))
+    }
+
+    /// With `deny_warnings`, an answer with warnings is an error
+    fn check_warnings(&self, response: ApiResponse) -> Result<ApiResponse, ErrorResponse> {
+        if !self.deny_warnings || response.warnings.is_empty() {
+            return Ok(response);
+        }
+        let messages: Vec<String> = response
+            .warnings
+            .iter()
+            .map(|w| match &w.file {
+                Some(file) => format!("{} ({}): {}", w.code.as_str(), file, w.message),
+                None => format!("{}: {}", w.code.as_str(), w.message),
+            })
+            .collect();
+        Err(failure(
+            "warnings_denied",
+            format!("Answer has warnings: {}", messages.join("; ")),
+            &self.collections.join(","),
+            &self.question,
+        ))
+    }
+
+    /// Run the post-processing steps, then render the locale, if any
+    fn post_process(
+        &self,
+        mut response: ApiResponse,
+        collection: &Collection,
+        documents: &[(String, String)],
+    ) -> Result<ApiResponse, ErrorResponse> {
+        // The profile drops the verification, and rounded amounts must stay rounded
+        let verified = verified_amounts(response.verification.as_deref().unwrap_or_default());
+        let context = PostContext { question: &self.question, collection, documents, profile: self.profile.as_ref() };
+        run_chain(&self.post_processors, &mut response, &context)
+            .map_err(|e| failure("post_processing_failed", format!("{:#}", e), &collection.name, &self.question))?;
+        if let Some(locale) = &self.locale {
+            locale.render(&mut response.answer, &verified);
+        }
+        Ok(response)
+    }
+
+    /// Answer the question as a whole
+    async fn run_single(&self) -> Result<ApiResponse, ErrorResponse> {
+        let selected = self.resolve_collections()?;
+        let collection = self.effective_collection(&selected);
+        let mut warnings = Vec::new();
+
+        if self.agent {
+            match self.run_agent(&selected, &collection).await {
+                Some(result) => return result,
+                None => {
+                    self.events().on_warning(&format!("Model '{}' cannot call tools; answering without them", self.model));
+                    warnings.push(Warning::new(
+                        WarningCode::ToolsUnsupported,
+                        format!("Model '{}' cannot call tools; answered without them", self.model),
+                    ));
+                }
+            }
+        }
+
+        let multi = selected.len() > 1;
+        let mut provenance = Provenance::new(&self.question);
+        // Citations and clause quotes are checked against the documents' text
+        let mut documents = Vec::new();
+
+        let mut contents = String::new();
+        let mut file_names = Vec::new();
+        let mut reports = Vec::new();
+        let mut skipped_files = Vec::new();
+
+        // Totals must not count an invoice and its correction twice
+        let latest_only = !self.include_superseded && is_aggregation(&self.question);
+        // As it is now: notes and statuses changed while serving count from the next question on
+        let metadata = Metadata::load().unwrap_or_else(|e| {
+            self.events().on_warning(&format!("{:#}; documents are shown without their notes and taken as received", e));
+            Metadata::default()
+        });
+
+        // An answer from an index older than the documents says so
+        if self.retrieval.stale_check {
+            once_cell::sync::Lazy::force(&INVERTED_INDEX);
+            for part in &selected {
+                if let Some(warning) = freshness::check(part).warning(&part.name) {
+                    self.events().on_warning(&warning.message);
+                    warnings.push(warning);
+                }
+            }
+        }
+
+        for part in &selected {
+            self.events().on_scan_start(&part.name);
+            let mut extra = if latest_only { VERSION_GRAPH.superseded_in(&part.folder) } else { 0 };
+            extra += lifecycle::excluded_in(&part.folder, &metadata, &self.invoice_statuses);
+            let ranked = match &self.scorer {
+                Some(scorer) => rank_with(scorer.as_ref(), &self.question, part, self.max_docs + extra),
+                None => retrieve(&self.question, part, self.max_docs + extra, &self.retrieval, self.events()).await,
+            };
+            let relevant_files: Vec<_> = ranked
+                .into_iter()
+                .filter(|(path, _)| !latest_only || !VERSION_GRAPH.is_superseded(path))
+                .filter(|(path, _)| lifecycle::selected(&self.invoice_statuses, metadata.status(path)))
+                .take(self.max_docs)
+                .collect();
+            if relevant_files.is_empty() {
+                continue;
+            }
+            if multi {
+                contents.push_str("\n=== Collection: ");
+                contents.push_str(&part.name);
+                contents.push_str(" ===\n");
+            }
+
+            for (path, score) in relevant_files {
+                let mut fname = path.file_name().unwrap().to_string_lossy().to_string();
+                if multi {
+                    fname = format!("{}/{}", part.name, fname);
+                }
+
+                // One bad file must not sink the whole question
+                let text = match get_cached_content(&path) {
+                    Ok(text) => text,
+                    Err(e) if self.strict => {
+                        return Err(failure("unreadable_document", format!("{:#}", e), &part.name, &self.question))
+                    }
+                    Err(e) => {
+                        self.events().on_warning(&format!("skipping {}: {:#}", fname, e));
+                        warnings.push(Warning::new(WarningCode::SkippedFile, format!("{:#}", e)).file(&fname));
+                        skipped_files.push(SkippedFile { file: fname, reason: format!("{:#}", e) });
+                        continue;
+                    }
+                };
+                self.events().on_document_loaded(&fname, text.len());
+                if text.contains('\u{FFFD}') {
+                    warnings.push(
+                        Warning::new(WarningCode::LossyDecoding, "Not valid UTF-8; invalid bytes were replaced")
+                            .file(&fname),
+                    );
+                }
+                if part.vat_check {
+                    reports.push(check_invoice(&fname, &text));
+                }
+                let chunks = chunk_document(&text);
+                if self.explain.is_some() {
+                    provenance.add_document(&part.name, &fname, score, &chunks);
+                }
+                documents.push((fname.clone(), text.clone()));
+                let notes: Vec<&str> =
+                    metadata.get(&path).map(|m| m.notes.iter().map(|n| n.text.as_str()).collect()).unwrap_or_default();
+                let note = VERSION_GRAPH.note(&path);
+                push_document(&mut contents, &fname, note.as_deref(), &notes, &text, &chunks);
+                file_names.push(fname);
+            }
+        }
+
+        // Totals over the invoice collections are computed exactly, not left to the model
+        let aggregation = self.aggregation(&selected, latest_only, &metadata);
+        // Put in front of the documents in place, without building the whole prompt again
+        let mut preamble = String::new();
+        if !self.history.is_empty() {
+            preamble.push_str(&history_block(&self.history));
+            preamble.push('\n');
+        }
+        if let Some(result) = &aggregation {
+            preamble.push_str(
+                "Computed from the extracted figures of all matching documents (exact; use these numbers instead of adding up yourself):\n",
+            );
+            preamble.push_str(&result.to_table());
+            preamble.push('\n');
+        }
+        contents.insert_str(0, &preamble);
+
+        if file_names.is_empty() {
+            return Err(failure(
+                "no_matches",
+                format!("No relevant documents found in '{}' collection", collection.display_name),
+                &collection.name,
+                &self.question,
+            ));
+        }
+
+        let samples = self.samples.clamp(1, MAX_SAMPLES);
+        let mut options = default_options();
+        if samples > 1 {
+            // Identical samples would tell us nothing
+            options.temperature = Some(SAMPLING_TEMPERATURE);
+        }
+        let options = options.merged_with(self.options.clone());
+
+        // Ollama silently drops the start of a prompt that does not fit
+        let num_ctx = options.num_ctx.unwrap_or(DEFAULT_NUM_CTX) as usize;
+        let tokens = estimate_tokens(&contents) + estimate_tokens(&collection.instruction) + estimate_tokens(&self.question);
+        self.events().on_prompt_built(file_names.len(), tokens);
+        if tokens > num_ctx {
+            warnings.push(Warning::new(
+                WarningCode::ContextTruncated,
+                format!("The prompt is about {} tokens but the context window is {}; raise num_ctx or lower --top-k", tokens, num_ctx),
+            ));
+        }
+
+        let (mut answer, mut consistency, mut elapsed_ms) =
+            self.ask(&self.model, &contents, &collection, &options, samples).await?;
+        let schema_errors = collection.schema.as_ref().map(|schema| validate(&answer, schema));
+        let mut model = self.model.clone();
+
+        // Cheap first: a larger model answers again if the first answer fails a check
+        let mut escalation = None;
+        let larger = self.escalation.as_ref().and_then(|e| Some((e, e.model.as_ref().filter(|m| **m != self.model)?)));
+        if let Some((config, larger)) = larger {
+            let failures = failed_checks(config, &answer, &contents, schema_errors.as_ref());
+            if !failures.is_empty() {
+                let reasons: Vec<String> = failures.iter().map(|f| f.to_string()).collect();
+                let (second, _, ms) = self.ask(larger, &contents, &collection, &options, 1).await?;
+                answer = merge(second, &answer, &failures);
+                consistency = None;
+                elapsed_ms += ms;
+                escalation = Some(Escalation { from: model, to: larger.clone(), reasons });
+                model = larger.clone();
+            }
+        }
+
+        let provenance = self.explain.map(|format| {
+            provenance.add_verification(&reports);
+            provenance.set_answer(&answer, &documents);
+            provenance.render(format)
+        });
+
+        let response = ApiResponse {
+            status: AnswerStatus::of(&answer),
+            answer,
+            used_files: file_names,
+            verification: (!reports.is_empty()).then_some(reports),
+            consistency,
+            aggregation,
+            provenance,
+            schema_errors: None,
+            skipped_files,
+            redacted: Vec::new(),
+            warnings,
+            model: Some(model),
+            escalation,
+            prompt: Some(prompt_version(&collection)),
+            elapsed_ms: Some(elapsed_ms),
+            error: None,
+        };
+        self.post_process(response, &collection, &documents)
+    }
+
+    /// Ask `model` `samples` times: the (majority) answer, its consistency and the model time
+    async fn ask(
+        &self,
+        model: &str,
+        contents: &str,
+        collection: &Collection,
+        options: &GenerationOptions,
+        samples: usize,
+    ) -> Result<(Value, Option<Consistency>, u64), ErrorResponse> {
+        let mut answers: Vec<Value> = Vec::new();
+        let mut elapsed_ms = 0;
+        // What streams out while the model writes must not show more than the answer will
+        let profiled;
+        let events = match &self.profile {
+            Some(profile) => {
+                profiled = Profiled { inner: self.events(), profile };
+                &profiled as &dyn EventSink
+            }
+            None => self.events(),
+        };
+        for _ in 0..samples {
+            let answer = match self.api {
+                OllamaApi::Generate => query_ollama(model, contents, &self.question, collection, options, events).await,
+                OllamaApi::Chat => query_ollama_chat(model, contents, &self.question, collection, options, events).await,
+            }
+            .map_err(|e| failure(guardrail::error_code(&e), e.to_string(), &collection.name, &self.question))?;
+
+            elapsed_ms += answer.elapsed_ms;
+            self.events().on_tokens(answer.tokens.unwrap_or_else(|| estimate_tokens(&answer.raw)));
+            answers.push(answer.value());
+        }
+
+        Ok(match summarize(&answers) {
+            Some((majority, stats)) if samples > 1 => (majority, Some(stats), elapsed_ms),
+            _ => (answers.swap_remove(0), None, elapsed_ms),
+        })
+    }
+
+    /// Exact aggregation for an aggregation question over invoice collections
+    fn aggregation(&self, selected: &[&Collection], latest_only: bool, metadata: &Metadata) -> Option<AggregationResult> {
+        let records: Vec<_> = selected
+            .iter()
+            .filter(|c| c.vat_check)
+            .flat_map(|c| collection_records(c, !latest_only))
+            .filter(|r| lifecycle::selected(&self.invoice_statuses, metadata.status(&r.path)))
+            .collect();
+        if records.is_empty() {
+            return None;
+        }
+        let mut vendors: Vec<String> = records.iter().filter_map(|r| r.vendor.clone()).collect();
+        vendors.sort();
+        vendors.dedup();
+        let aggregation = plan(&self.question, &vendors)?;
+        Some(aggregate(&records, &aggregation))
+    }
+
+    /// Agentic mode: the model searches and reads documents through tools.
+    /// None if the model has no tool support (the caller then uses the normal
+    /// pipeline, where arithmetic is verified after the fact instead).
+    async fn run_agent(
+        &self,
+        selected: &[&Collection],
+        collection: &Collection,
+    ) -> Option<Result<ApiResponse, ErrorResponse>> {
+        let started = Instant::now();
+        match run_agent(&self.model, &self.question, selected, &collection.instruction, collection.strictness).await {
+            Ok(result) => {
+                let parsed: Value = parse_lenient(&result.answer).unwrap_or_else(|| json!({"raw": result.answer}));
+
+                let mut reports = Vec::new();
+                let mut documents = Vec::new();
+                for fname in &result.used_files {
+                    let Some(part) = selected.iter().find(|c| c.folder.join(fname).is_file()) else { continue };
+                    let Ok(text) = get_cached_content(&part.folder.join(fname)) else { continue };
+                    if part.vat_check {
+                        reports.push(check_invoice(fname, &text));
+                    }
+                    documents.push((fname.clone(), text));
+                }
+
+                self.events().on_progress(&format!("Agent answered in {} step(s)", result.steps));
+                let response = ApiResponse {
+                    status: AnswerStatus::of(&parsed),
+                    answer: parsed,
+                    used_files: result.used_files,
+                    verification: (!reports.is_empty()).then_some(reports),
+                    consistency: None,
+                    aggregation: None,
+                    provenance: None,
+                    schema_errors: None,
+                    skipped_files: Vec::new(),
+                    redacted: Vec::new(),
+                    warnings: Vec::new(),
+                    model: Some(self.model.clone()),
+                    escalation: None,
+                    prompt: Some(prompt_version(collection)),
+                    elapsed_ms: Some(started.elapsed().as_millis() as u64),
+                    error: None,
+                };
+                Some(self.post_process(response, collection, &documents))
+            }
+            Err(e) if is_tools_unsupported(&e) => None,
+            Err(e) => Some(Err(failure(guardrail::error_code(&e), e.to_string(), &collection.name, &self.question))),
+        }
+    }
+}
@@ -0,0 +1,288 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Approval policy (`[policy]`): declarative limits evaluated over the fields
+// read from each received or extracted invoice, deciding it
+//
+//   auto_approved  within the auto-approval limit, nothing missing or wrong  → approved
+//   needs_review   over the limit, fields missing, sums off, two-person rule  → extracted
+//   rejected       a currency not allowed, or over the rejection limit        → archived
+//
+// Limits can be set per vendor and per collection (`[[policy.limit]]`); a
+// vendor's limit beats its collection's, which beats the `[policy]` defaults.
+// Without an auto-approval limit nothing is auto-approved. The decision and
+// its reasons are kept in the document metadata next to the status history,
+// where the changes are recorded as made by "policy".
+
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::approval::ApprovalConfig;
+use crate::check_invoice;
+use crate::fold::same_folded;
+use crate::get_cached_content;
+use crate::lifecycle::InvoiceStatus;
+use crate::metadata::{now, Metadata};
+use crate::records::InvoiceRecord;
+
+/// Who the status changes of a policy are recorded as made by
+pub const POLICY_ACTOR: &str = "policy";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    AutoApproved,
+    NeedsReview,
+    Rejected,
+}
+
+impl Decision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Decision::AutoApproved => "auto_approved",
+            Decision::NeedsReview => "needs_review",
+            Decision::Rejected => "rejected",
+        }
+    }
+
+    /// The status the decision moves an invoice to
+    pub fn status(&self) -> InvoiceStatus {
+        match self {
+            Decision::AutoApproved => InvoiceStatus::Approved,
+            Decision::NeedsReview => InvoiceStatus::Extracted,
+            Decision::Rejected => InvoiceStatus::Archived,
+        }
+    }
+}
+
+/// Fields an invoice must have to be decided without review
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RequiredField {
+    Number,
+    Vendor,
+    Date,
+    Currency,
+    Net,
+    Tax,
+    Total,
+}
+
+impl RequiredField {
+    fn present(&self, record: &InvoiceRecord) -> bool {
+        match self {
+            RequiredField::Number => record.id.is_some(),
+            RequiredField::Vendor => record.vendor.is_some(),
+            RequiredField::Date => record.date.is_some(),
+            RequiredField::Currency => record.currency.is_some(),
+            RequiredField::Net => record.net.is_some(),
+            RequiredField::Tax => record.tax.is_some(),
+            RequiredField::Total => record.gross.is_some(),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            RequiredField::Number => "number",
+            RequiredField::Vendor => "vendor",
+            RequiredField::Date => "date",
+            RequiredField::Currency => "currency",
+            RequiredField::Net => "net",
+            RequiredField::Tax => "tax",
+            RequiredField::Total => "total",
+        }
+    }
+}
+
+/// Limits for one vendor or one collection (`[[policy.limit]]`)
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PolicyLimit {
+    /// Vendor name, matched folded ("Müller" is "MUELLER")
+    pub vendor: Option<String>,
+    pub collection: Option<String>,
+    pub auto_approve_up_to: Option<Decimal>,
+    pub reject_over: Option<Decimal>,
+}
+
+/// `[policy]` section of the config file
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PolicyConfig {
+    /// Decide newly indexed invoices at the end of `index`
+    pub on_index: bool,
+    pub required_fields: Vec<RequiredField>,
+    /// Currency codes invoices may be in (any when empty)
+    pub currencies: Vec<String>,
+    /// Totals up to this are approved without review
+    pub auto_approve_up_to: Option<Decimal>,
+    /// Totals over this are rejected
+    pub reject_over: Option<Decimal>,
+    #[serde(rename = "limit")]
+    pub limits: Vec<PolicyLimit>,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            on_index: false,
+            required_fields: vec![RequiredField::Number, RequiredField::Vendor, RequiredField::Date, RequiredField::Total],
+            currencies: Vec::new(),
+            auto_approve_up_to: None,
+            reject_over: None,
+            limits: Vec::new(),
+        }
+    }
+}
+
+impl PolicyConfig {
+    /// (auto-approval limit, rejection limit) for the invoice: the vendor's,
+    /// else the collection's, else the defaults, each limit on its own
+    pub fn limits_for(&self, record: &InvoiceRecord) -> (Option<Decimal>, Option<Decimal>) {
+        let vendor = self.limits.iter().find(|l| {
+            l.vendor.as_deref().is_some_and(|v| record.vendor.as_deref().is_some_and(|rv| same_folded(v, rv)))
+        });
+        let collection =
+            self.limits.iter().find(|l| l.vendor.is_none() && l.collection.as_deref() == Some(record.collection.as_str()));
+        let pick = |get: fn(&PolicyLimit) -> Option<Decimal>, default: Option<Decimal>| {
+            vendor.and_then(get).or_else(|| collection.and_then(get)).or(default)
+        };
+        (pick(|l| l.auto_approve_up_to, self.auto_approve_up_to), pick(|l| l.reject_over, self.reject_over))
+    }
+}
+
+/// A decision with the reasons for it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PolicyOutcome {
+    pub decision: Decision,
+    /// Why it was not auto-approved (empty when it was)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
+    /// Unix time of the decision
+    pub at: u64,
+}
+
+/// Decide one invoice from its record and text
+pub fn evaluate(record: &InvoiceRecord, text: &str, config: &PolicyConfig, approval: &ApprovalConfig) -> PolicyOutcome {
+    let mut rejected = Vec::new();
+    let mut review = Vec::new();
+    let (auto_limit, reject_limit) = config.limits_for(record);
+
+    let missing: Vec<&str> =
+        config.required_fields.iter().filter(|f| !f.present(record)).map(|f| f.as_str()).collect();
+    if !missing.is_empty() {
+        review.push(format!("no {}", missing.join(", ")));
+    }
+    if !config.currencies.is_empty() {
+        match &record.currency {
+            Some(c) if !config.currencies.iter().any(|allowed| allowed.eq_ignore_ascii_case(c)) => {
+                rejected.push(format!("currency {} is not one of {}", c, config.currencies.join(", ")))
+            }
+            Some(_) => {}
+            None => review.push("currency unknown".to_string()),
+        }
+    }
+    // Missing figures are for `required_fields` to decide
+    let issues = check_invoice(&record.file, text).issues.into_iter().filter(|i| !i.code.ends_with("_missing"));
+    review.extend(issues.map(|i| i.message));
+
+    match record.gross.map(|g| g.abs()) {
+        Some(total) if reject_limit.is_some_and(|limit| total > limit) => {
+            rejected.push(format!("total {} is over the rejection limit {}", total, reject_limit.unwrap_or_default()))
+        }
+        Some(total) => match auto_limit {
+            Some(limit) if total > limit => review.push(format!("total {} is over the auto-approval limit {}", total, limit)),
+            Some(_) => {}
+            None => review.push("no auto-approval limit for this invoice".to_string()),
+        },
+        None if !config.required_fields.contains(&RequiredField::Total) => review.push("total unknown".to_string()),
+        None => {}
+    }
+    if approval.required(record.gross) > 0 {
+        review.push("over the [approval] threshold: approvers must `approve` it".to_string());
+    }
+
+    let (decision, reasons) = if !rejected.is_empty() {
+        (Decision::Rejected, rejected)
+    } else if !review.is_empty() {
+        (Decision::NeedsReview, review)
+    } else {
+        (Decision::AutoApproved, Vec::new())
+    };
+    PolicyOutcome { decision, reasons, at: now() }
+}
+
+/// The decision for one invoice
+#[derive(Serialize, Debug, Clone)]
+pub struct PolicyResult {
+    pub collection: String,
+    pub file: String,
+    #[serde(skip)]
+    pub path: PathBuf,
+    /// Status before the decision
+    pub from: InvoiceStatus,
+    #[serde(flatten)]
+    pub outcome: PolicyOutcome,
+}
+
+impl fmt::Display for PolicyResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}: {}", self.collection, self.file, self.outcome.decision.as_str())?;
+        if !self.outcome.reasons.is_empty() {
+            write!(f, " ({})", self.outcome.reasons.join("; "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Decide the records that are received or extracted; invoices further
+/// along (approved, paid, archived) were decided by a person already
+pub fn decide(
+    records: &[InvoiceRecord],
+    metadata: &Metadata,
+    config: &PolicyConfig,
+    approval: &ApprovalConfig,
+) -> Vec<PolicyResult> {
+    records
+        .iter()
+        .filter_map(|r| {
+            let from = metadata.status(&r.path);
+            if !matches!(from, InvoiceStatus::Received | InvoiceStatus::Extracted) {
+                return None;
+            }
+            let text = get_cached_content(&r.path).ok()?;
+            Some(PolicyResult {
+                collection: r.collection.clone(),
+                file: r.file.clone(),
+                path: r.path.clone(),
+                from,
+                outcome: evaluate(r, &text, config, approval),
+            })
+        })
+        .collect()
+}
+
+/// Move the document to the status of the decision (approved by way of
+/// extracted) and keep the decision; returns the status it ends up in
+pub fn apply(metadata: &mut Metadata, path: &Path, outcome: &PolicyOutcome) -> Result<InvoiceStatus> {
+    let from = metadata.status(path);
+    let to = outcome.decision.status();
+    let steps: &[InvoiceStatus] = match (from, to) {
+        (from, to) if from == to => &[],
+        (InvoiceStatus::Received, InvoiceStatus::Approved) => &[InvoiceStatus::Extracted, InvoiceStatus::Approved],
+        _ => std::slice::from_ref(&to),
+    };
+    for step in steps {
+        // No approvals needed: `evaluate` leaves invoices over the [approval] threshold for review
+        metadata.set_status(path, *step, false, 0)?;
+    }
+    let entry = metadata.entry(path);
+    let changes = entry.status_history.len();
+    for change in &mut entry.status_history[changes - steps.len()..] {
+        change.by = Some(POLICY_ACTOR.to_string());
+    }
+    entry.policy = Some(outcome.clone());
+    Ok(to)
+}
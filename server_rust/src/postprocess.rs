@@ -0,0 +1,227 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// What happens to an answer between the model and the caller, as a chain of
+// `PostProcessor` steps over the response. The built-in steps, in their
+// default order:
+//
+//   json-repair       answers left as text are parsed leniently once more
+//   schema-validate   the answer against the requested schema (`schema_errors`)
+//   citation-verify   cited files and chunks were given to the model; clause
+//                     quotes are in the chunk they cite
+//   arithmetic-check  the invoice checks and the domain's field checks
+//   redact            the output profile's allowlist
+//
+// `[postprocess]` in doc-ai.toml sets the chain, for every command or per
+// command (ask, chat, serve); a library caller can put its own steps in the
+// chain (say, a GL-code mapper before `redact`) with `QueryBuilder::post_processors`.
+// A chain that leaves `redact` out still ends with it when the query has an
+// output profile, so no configuration can turn a profile off.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::ai::AnswerStatus;
+use crate::chunking::{chunk_document, Citation};
+use crate::domain::{check_answer, check_clauses, Validator};
+use crate::json_repair::parse_lenient;
+use crate::redact::OutputProfile;
+use crate::schema::validate;
+use crate::warnings::{schema_warning, unparsed_warning, verification_warnings, Warning, WarningCode};
+use crate::{ApiResponse, Collection};
+
+/// The built-in steps, in the order they run by default
+pub const DEFAULT_STEPS: &[&str] = &["json-repair", "schema-validate", "citation-verify", "arithmetic-check", "redact"];
+
+/// What a step can see besides the response
+pub struct PostContext<'a> {
+    pub question: &'a str,
+    /// The collection as queried: schema, validators and instructions included
+    pub collection: &'a Collection,
+    /// (file name, text) of the documents the model was given
+    pub documents: &'a [(String, String)],
+    pub profile: Option<&'a OutputProfile>,
+}
+
+pub trait PostProcessor: Send + Sync {
+    /// Name used in `[postprocess]` and in errors
+    fn name(&self) -> &str;
+
+    /// Change the response in place; an error fails the question
+    fn process(&self, response: &mut ApiResponse, context: &PostContext<'_>) -> Result<()>;
+}
+
+impl fmt::Debug for dyn PostProcessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Run the steps in order, then `redact` if the chain has no such step and the query has a profile
+pub fn run_chain(chain: &[Arc<dyn PostProcessor>], response: &mut ApiResponse, context: &PostContext<'_>) -> Result<()> {
+    for step in chain {
+        step.process(response, context).with_context(|| format!("Post-processing step '{}' failed", step.name()))?;
+    }
+    if context.profile.is_some() && !chain.iter().any(|step| step.name() == "redact") {
+        Redact.process(response, context)?;
+    }
+    Ok(())
+}
+
+/// Parses answers that are still text (`{"raw": ...}` or a JSON string) leniently:
+/// the model's own answers are parsed as they arrive, this is for answers from the
+/// agent or earlier steps. Warns when there is still no JSON.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonRepair;
+
+impl PostProcessor for JsonRepair {
+    fn name(&self) -> &str {
+        "json-repair"
+    }
+
+    fn process(&self, response: &mut ApiResponse, _context: &PostContext<'_>) -> Result<()> {
+        let text = match &response.answer {
+            Value::String(text) => Some(text.as_str()),
+            Value::Object(o) if o.len() == 1 => o.get("raw").and_then(Value::as_str),
+            _ => None,
+        };
+        if let Some(parsed) = text.and_then(parse_lenient).filter(|v| v.is_object()) {
+            response.status = AnswerStatus::of(&parsed);
+            response.answer = parsed;
+        }
+        response.warnings.extend(unparsed_warning(&response.answer));
+        Ok(())
+    }
+}
+
+/// Checks the answer against the collection's (or the query's) schema
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SchemaValidate;
+
+impl PostProcessor for SchemaValidate {
+    fn name(&self) -> &str {
+        "schema-validate"
+    }
+
+    fn process(&self, response: &mut ApiResponse, context: &PostContext<'_>) -> Result<()> {
+        response.schema_errors = context.collection.schema.as_ref().map(|schema| validate(&response.answer, schema));
+        response.warnings.extend(response.schema_errors.as_deref().and_then(schema_warning));
+        Ok(())
+    }
+}
+
+/// Checks that the answer's sources are documents (and chunks) the model was given,
+/// and, for clause answers, that each quote is in the chunk it cites
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CitationVerify;
+
+impl PostProcessor for CitationVerify {
+    fn name(&self) -> &str {
+        "citation-verify"
+    }
+
+    fn process(&self, response: &mut ApiResponse, context: &PostContext<'_>) -> Result<()> {
+        let sources = response.answer.get("sources").and_then(Value::as_array).cloned().unwrap_or_default();
+        for citation in sources.iter().filter_map(Citation::from_value) {
+            let unknown = |reason: String| Warning::new(WarningCode::UnknownSource, reason).file(&citation.file);
+            let Some((_, text)) = context.documents.iter().find(|(file, _)| *file == citation.file) else {
+                response.warnings.push(unknown(format!("The answer cites '{}', which was not retrieved", citation.file)));
+                continue;
+            };
+            if let Some(id) = &citation.chunk
+                && !chunk_document(text).iter().any(|c| c.id == *id)
+            {
+                response.warnings.push(unknown(format!("The answer cites chunk {}, which is not in the document", id)));
+            }
+        }
+        if context.collection.validators.contains(&Validator::Clauses) {
+            response.warnings.extend(check_clauses(&response.answer, context.documents));
+        }
+        Ok(())
+    }
+}
+
+/// Warns about documents failing the invoice checks (net + VAT = gross, line
+/// items adding up...) and answer fields failing the domain's checks
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ArithmeticCheck;
+
+impl PostProcessor for ArithmeticCheck {
+    fn name(&self) -> &str {
+        "arithmetic-check"
+    }
+
+    fn process(&self, response: &mut ApiResponse, context: &PostContext<'_>) -> Result<()> {
+        response.warnings.extend(verification_warnings(response.verification.as_deref().unwrap_or_default()));
+        response.warnings.extend(check_answer(&context.collection.validators, &response.answer));
+        Ok(())
+    }
+}
+
+/// Applies the output profile, if the query has one
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Redact;
+
+impl PostProcessor for Redact {
+    fn name(&self) -> &str {
+        "redact"
+    }
+
+    fn process(&self, response: &mut ApiResponse, context: &PostContext<'_>) -> Result<()> {
+        if let Some(profile) = context.profile {
+            response.redacted = profile.apply(response);
+        }
+        Ok(())
+    }
+}
+
+/// The built-in step of this name
+pub fn builtin(name: &str) -> Option<Arc<dyn PostProcessor>> {
+    Some(match name {
+        "json-repair" => Arc::new(JsonRepair),
+        "schema-validate" => Arc::new(SchemaValidate),
+        "citation-verify" => Arc::new(CitationVerify),
+        "arithmetic-check" => Arc::new(ArithmeticCheck),
+        "redact" => Arc::new(Redact),
+        _ => return None,
+    })
+}
+
+/// json-repair → schema-validate → citation-verify → arithmetic-check → redact
+pub fn default_chain() -> Vec<Arc<dyn PostProcessor>> {
+    DEFAULT_STEPS.iter().filter_map(|name| builtin(name)).collect()
+}
+
+/// `[postprocess]`: the steps answers go through, by name
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PostProcessConfig {
+    /// For every command without its own list (the default chain when empty)
+    pub steps: Vec<String>,
+    /// Per command: "ask", "chat" or "serve" (HTTP and WebSocket queries)
+    pub commands: BTreeMap<String, Vec<String>>,
+}
+
+impl PostProcessConfig {
+    /// The chain for `command`; names that are not built in are looked up in `custom`
+    pub fn chain(&self, command: &str, custom: &[Arc<dyn PostProcessor>]) -> Result<Vec<Arc<dyn PostProcessor>>> {
+        let names = match self.commands.get(command) {
+            Some(names) => names,
+            None if self.steps.is_empty() => return Ok(default_chain()),
+            None => &self.steps,
+        };
+        names
+            .iter()
+            .map(|name| {
+                builtin(name).or_else(|| custom.iter().find(|s| s.name() == name).cloned()).ok_or_else(|| {
+                    let mut known: Vec<&str> = DEFAULT_STEPS.to_vec();
+                    known.extend(custom.iter().map(|s| s.name()));
+                    anyhow::anyhow!("Unknown post-processing step '{}' in [postprocess]. Valid values: {}", name, known.join(", "))
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,49 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Prompt versions: every answer records which prompt produced it, as a
+// semantic version (the built-in prompt's, or `template_version` from the
+// config file for a custom template) plus a hash of the prompt as sent, with
+// placeholders for the documents and the question. When answers change, the
+// model, the prompt and the data can then be told apart.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::ai::build_prompt;
+use crate::Collection;
+
+/// Version of the built-in prompt (DEFAULT_TEMPLATE, the rules and the
+/// collection instructions); bump it with every change to them
+pub const BUILTIN_PROMPT_VERSION: &str = "1.0.0";
+
+/// Version given to custom templates without `template_version`
+pub const UNVERSIONED: &str = "unversioned";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PromptVersion {
+    /// "default" for the built-in template, otherwise the template file
+    pub template: String,
+    pub version: String,
+    /// First 12 hex digits of the SHA-256 of `prompt_skeleton`
+    pub hash: String,
+}
+
+/// The prompt with {contents} and {query} left as placeholders
+pub fn prompt_skeleton(collection: &Collection) -> String {
+    build_prompt(collection, "{contents}", "{query}")
+}
+
+/// Name, version and hash of the prompt a collection's questions are asked with
+pub fn prompt_version(collection: &Collection) -> PromptVersion {
+    let digest = Sha256::digest(prompt_skeleton(collection).as_bytes());
+    let hash: String = digest.iter().take(6).map(|b| format!("{:02x}", b)).collect();
+    match &collection.template_file {
+        Some(path) => PromptVersion {
+            template: path.display().to_string(),
+            version: collection.template_version.clone().unwrap_or_else(|| UNVERSIONED.to_string()),
+            hash,
+        },
+        None => PromptVersion { template: "default".to_string(), version: BUILTIN_PROMPT_VERSION.to_string(), hash },
+    }
+}
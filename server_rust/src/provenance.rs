@@ -0,0 +1,188 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Answer provenance for --explain:
+// query → retrieved docs (with scores) → chunks used → extracted values
+// → verification results → final answer.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::chunking::Chunk;
+use crate::VatReport;
+
+/// How --explain output is rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExplainFormat {
+    Json,
+    /// Graphviz DOT, returned as a string
+    Dot,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RetrievedDoc {
+    pub collection: String,
+    pub file: String,
+    pub score: f32,
+}
+
+/// Part of a document placed in the prompt
+#[derive(Serialize, Debug, Clone)]
+pub struct ChunkRef {
+    pub file: String,
+    pub id: String,
+    pub page: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A leaf value of the answer and whether it appears verbatim in the documents used
+#[derive(Serialize, Debug, Clone)]
+pub struct ExtractedValue {
+    pub path: String,
+    pub value: Value,
+    pub grounded_in: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct VerificationResult {
+    pub file: String,
+    pub consistent: bool,
+    pub issues: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Provenance {
+    pub query: String,
+    pub retrieved: Vec<RetrievedDoc>,
+    pub chunks: Vec<ChunkRef>,
+    pub extracted: Vec<ExtractedValue>,
+    pub verification: Vec<VerificationResult>,
+    pub answer: Value,
+}
+
+impl Provenance {
+    pub fn new(query: &str) -> Self {
+        Self { query: query.to_string(), ..Default::default() }
+    }
+
+    pub fn add_document(&mut self, collection: &str, file: &str, score: f32, chunks: &[Chunk]) {
+        self.retrieved.push(RetrievedDoc {
+            collection: collection.to_string(),
+            file: file.to_string(),
+            score,
+        });
+        self.chunks.extend(chunks.iter().map(|c| ChunkRef {
+            file: file.to_string(),
+            id: c.id.clone(),
+            page: c.page,
+            start: c.start,
+            end: c.end,
+        }));
+    }
+
+    pub fn add_verification(&mut self, reports: &[VatReport]) {
+        self.verification.extend(reports.iter().map(|r| VerificationResult {
+            file: r.file.clone(),
+            consistent: r.consistent,
+            issues: r.issues.iter().map(|i| i.message.clone()).collect(),
+        }));
+    }
+
+    /// Record the final answer; `documents` are (file, text) pairs used for grounding
+    pub fn set_answer(&mut self, answer: &Value, documents: &[(String, String)]) {
+        let mut leaves = Vec::new();
+        collect_leaves(answer, String::new(), &mut leaves);
+
+        self.extracted = leaves
+            .into_iter()
+            .map(|(path, value)| {
+                let needle = match &value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                let grounded_in = documents
+                    .iter()
+                    .filter(|(_, text)| !needle.is_empty() && text.contains(needle.as_str()))
+                    .map(|(file, _)| file.clone())
+                    .collect();
+                ExtractedValue { path, value, grounded_in }
+            })
+            .collect();
+        self.answer = answer.clone();
+    }
+
+    pub fn render(&self, format: ExplainFormat) -> Value {
+        match format {
+            ExplainFormat::Json => serde_json::to_value(self).unwrap_or_default(),
+            ExplainFormat::Dot => Value::String(self.to_dot()),
+        }
+    }
+
+    /// Graphviz rendering of the same structure
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph provenance {\n  rankdir=LR;\n  node [shape=box, fontname=\"Helvetica\"];\n");
+        dot.push_str(&format!("  query [label=\"{}\", shape=ellipse];\n", escape(&self.query)));
+        dot.push_str("  answer [label=\"answer\", shape=doubleoctagon];\n");
+
+        for (i, doc) in self.retrieved.iter().enumerate() {
+            dot.push_str(&format!(
+                "  doc{} [label=\"{}/{}\\nscore {}\"];\n  query -> doc{};\n",
+                i, escape(&doc.collection), escape(&doc.file), doc.score, i
+            ));
+        }
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if let Some(d) = self.retrieved.iter().position(|doc| doc.file == chunk.file) {
+                dot.push_str(&format!(
+                    "  chunk{} [label=\"{} {} (page {})\", shape=note];\n  doc{} -> chunk{};\n",
+                    i, escape(&chunk.file), escape(&chunk.id), chunk.page, d, i
+                ));
+            }
+        }
+        for (i, value) in self.extracted.iter().enumerate() {
+            dot.push_str(&format!(
+                "  value{} [label=\"{} = {}\", shape=plaintext];\n  value{} -> answer;\n",
+                i, escape(&value.path), escape(&value.value.to_string()), i
+            ));
+            for file in &value.grounded_in {
+                if let Some(c) = self.chunks.iter().position(|chunk| chunk.file == *file) {
+                    dot.push_str(&format!("  chunk{} -> value{};\n", c, i));
+                }
+            }
+        }
+        for (i, check) in self.verification.iter().enumerate() {
+            let color = if check.consistent { "green" } else { "red" };
+            dot.push_str(&format!(
+                "  check{} [label=\"VAT check {}\", color={}];\n  check{} -> answer;\n",
+                i, escape(&check.file), color, i
+            ));
+            if let Some(c) = self.chunks.iter().position(|chunk| chunk.file == check.file) {
+                dot.push_str(&format!("  chunk{} -> check{};\n", c, i));
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn collect_leaves(value: &Value, path: String, out: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map {
+                let child = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                collect_leaves(v, child, out);
+            }
+        }
+        Value::Array(items) => {
+            for (i, v) in items.iter().enumerate() {
+                collect_leaves(v, format!("{}[{}]", path, i), out);
+            }
+        }
+        Value::Null => {}
+        leaf => out.push((path, leaf.clone())),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,98 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Recent answered questions with the documents they used (the "queries"
+// document of the store), so `show` can tell which questions cited a document.
+// Each entry carries the tenant that asked, and is only shown for its documents.
+// Only the last MAX_LOGGED answers are kept; logging is best effort and never
+// fails a query.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::chunking::Citation;
+use crate::collections::find_collection;
+use crate::metadata::now;
+use crate::store::{self, store};
+use crate::{ApiResponse, Collection};
+
+/// Answers kept in the log
+pub const MAX_LOGGED: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoggedQuery {
+    /// Unix time of the answer
+    pub at: u64,
+    pub question: String,
+    /// Collections as named in the query
+    pub collections: Vec<String>,
+    /// Files the answer cites in its "sources"
+    #[serde(default)]
+    pub cited: Vec<String>,
+    /// Files given to the model
+    #[serde(default)]
+    pub used: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Tenant that asked (None: no tenant), so `show` never lists another tenant's questions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+impl LoggedQuery {
+    /// Whether the query went to `collection` (or to the default collection, not named)
+    /// of the tenant owning it
+    fn covers(&self, collection: &Collection) -> bool {
+        if self.tenant != collection.tenant {
+            return false;
+        }
+        let named: Vec<&Collection> = self.collections.iter().filter_map(|c| find_collection(c)).collect();
+        named.is_empty() || named.iter().any(|c| c.name == collection.name)
+    }
+}
+
+fn parse(text: Option<String>) -> Result<Vec<LoggedQuery>> {
+    Ok(match text {
+        Some(text) => serde_json::from_str(&text)?,
+        None => Vec::new(),
+    })
+}
+
+/// Add an answer to the log
+pub fn record(question: &str, collections: &[String], response: &ApiResponse) {
+    let mut cited: Vec<String> = match response.answer.get("sources").and_then(|s| s.as_array()) {
+        Some(items) => items.iter().filter_map(Citation::from_value).map(|c| c.file).collect(),
+        None => Vec::new(),
+    };
+    cited.dedup();
+    if cited.is_empty() && response.used_files.is_empty() {
+        return;
+    }
+    let entry = LoggedQuery {
+        at: now(),
+        question: question.to_string(),
+        collections: collections.to_vec(),
+        cited,
+        used: response.used_files.clone(),
+        model: response.model.clone(),
+        // Tenants' queries name their own copies of the collections
+        tenant: collections.iter().filter_map(|c| find_collection(c)).find_map(|c| c.tenant.clone()),
+    };
+    let result = store().update(store::QUERIES, &mut |text| {
+        let mut log = parse(text).unwrap_or_default();
+        log.push(entry.clone());
+        let excess = log.len().saturating_sub(MAX_LOGGED);
+        log.drain(..excess);
+        Ok(serde_json::to_string(&log)?)
+    });
+    if let Err(e) = result {
+        eprintln!("WARNING: {:#}; the answer was not logged", e);
+    }
+}
+
+/// Logged answers that cited (or were given) `file` of `collection`, newest first
+pub fn queries_for(collection: &Collection, file: &str) -> Result<Vec<LoggedQuery>> {
+    let mut log = parse(store().load(store::QUERIES)?)?;
+    log.retain(|q| q.covers(collection) && (q.cited.iter().any(|f| f == file) || q.used.iter().any(|f| f == file)));
+    log.reverse();
+    Ok(log)
+}
@@ -0,0 +1,506 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Queue intake (`intake queue`): documents arrive as events on a message
+// queue, are saved into their collection, indexed and read, and the result
+// goes out on another topic, so doc-ai can sit in an event-driven pipeline.
+//
+// An event is a JSON object naming the document by URL or carrying it:
+//
+//   {"id": "evt-42", "collection": "invoices", "name": "inv_042.txt", "url": "https://..."}
+//   {"id": "evt-43", "name": "inv_043.txt", "content": "<base64>"}
+//   {"id": "evt-44", "name": "inv_044.txt", "text": "Invoice INV-44 ..."}
+//
+// An event may carry a `key`, the sender's own id for the document; content
+// sent again under the same key updates the document saved for it.
+//
+// Every event gets one result on the output topic, with its `id`, what
+// intake did with it (saved, updated, duplicate, skipped), the fields read
+// from the document and its checks, or an `error`. An event is acknowledged
+// once its result is published, so one interrupted mid-batch is delivered
+// again (at least once; intake makes a second delivery a duplicate).
+//
+// New documents also go out as `extracted` events to the `[[publish]]`
+// targets (see `bus`).
+//
+// Backends, each behind its feature: Redis Streams (`redis`; consumer
+// groups, XACK), Kafka (`kafka`; offsets committed per event) and NATS
+// (`nats`; core subjects with a queue group, at most once).
+
+use anyhow::{Context, Result};
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::bus::{publish_or_warn, BusEvent, EventKind, PublishConfig};
+use crate::docid::doc_key;
+use crate::ingest::{ingest, IngestOptions};
+use crate::intake::{ingest_document, IngestOutcome};
+use crate::lock::{lock_index, LockMode};
+use crate::metadata::now;
+use crate::records::InvoiceRecord;
+use crate::vat::{check_invoice, VatReport};
+use crate::Collection;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueBackend {
+    #[default]
+    Redis,
+    Kafka,
+    Nats,
+}
+
+/// `[queue]` section of the config file
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct QueueConfig {
+    pub backend: QueueBackend,
+    /// redis://host:6379, Kafka bootstrap servers (host:9092,...) or nats://host:4222
+    pub url: String,
+    /// Stream, topic or subject the document events come from
+    pub input: String,
+    /// Stream, topic or subject the results go to
+    pub output: String,
+    /// Consumer group (Kafka group id, Redis group, NATS queue group)
+    pub group: String,
+    /// Collection for events that name none
+    pub collection: String,
+    /// Events taken at a time; the index is updated once per batch
+    pub batch: usize,
+    /// How long to wait for events before looking again
+    pub wait_ms: u64,
+    /// Update the index after each batch with new documents
+    pub index: bool,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            backend: QueueBackend::Redis,
+            url: "redis://127.0.0.1:6379".to_string(),
+            input: "doc-ai.documents".to_string(),
+            output: "doc-ai.results".to_string(),
+            group: "doc-ai".to_string(),
+            collection: "invoices".to_string(),
+            batch: 20,
+            wait_ms: 5000,
+            index: true,
+        }
+    }
+}
+
+/// What a backend needs to acknowledge a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Receipt {
+    /// Nothing to acknowledge (NATS core, the in-memory queue)
+    None,
+    /// Redis stream entry id
+    Redis(String),
+    Kafka { topic: String, partition: i32, offset: i64 },
+}
+
+/// One message taken from the queue
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    pub payload: Vec<u8>,
+    pub receipt: Receipt,
+}
+
+pub trait MessageQueue: Send {
+    /// Up to `max` messages, waiting up to `wait` for the first; empty when none came
+    fn receive(&mut self, max: usize, wait: Duration) -> impl Future<Output = Result<Vec<Delivery>>> + Send;
+
+    /// Done with the message: it is not delivered again
+    fn ack(&mut self, delivery: &Delivery) -> impl Future<Output = Result<()>> + Send;
+
+    /// Send a result to the output topic
+    fn publish(&mut self, payload: Vec<u8>) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// A queue in memory, for tests and for feeding the consumer from code
+#[derive(Debug, Default)]
+pub struct MemoryQueue {
+    pub incoming: VecDeque<Vec<u8>>,
+    pub published: Vec<Vec<u8>>,
+    pub acked: usize,
+}
+
+impl MessageQueue for MemoryQueue {
+    async fn receive(&mut self, max: usize, _wait: Duration) -> Result<Vec<Delivery>> {
+        let n = max.min(self.incoming.len());
+        Ok(self.incoming.drain(..n).map(|payload| Delivery { payload, receipt: Receipt::None }).collect())
+    }
+
+    async fn ack(&mut self, _delivery: &Delivery) -> Result<()> {
+        self.acked += 1;
+        Ok(())
+    }
+
+    async fn publish(&mut self, payload: Vec<u8>) -> Result<()> {
+        self.published.push(payload);
+        Ok(())
+    }
+}
+
+/// A document event
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct DocumentEvent {
+    /// Echoed in the result, for the producer to match them up
+    pub id: Option<String>,
+    /// Collection name or alias (`[queue] collection` when not given)
+    pub collection: Option<String>,
+    /// File name to save the document as (default: the last part of the URL)
+    pub name: Option<String>,
+    /// The sender's id for the document: new content under a key sent before updates its document
+    pub key: Option<String>,
+    /// Where to download the document from
+    pub url: Option<String>,
+    /// The document itself, base64
+    pub content: Option<String>,
+    /// The document itself, as text
+    pub text: Option<String>,
+}
+
+/// The result published for one event
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct DocumentResult {
+    pub id: Option<String>,
+    pub collection: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<IngestOutcome>,
+    /// Fields read from the document
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record: Option<InvoiceRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification: Option<VatReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Where the events go and what happens after
+pub struct QueueConsumer<'a> {
+    pub collections: &'a [Collection],
+    /// For events that name no collection
+    pub default_collection: &'a str,
+    /// Update the index after a batch with new documents
+    pub index: bool,
+    /// Where `extracted` events for the new documents go
+    pub publishers: &'a [PublishConfig],
+}
+
+/// Intake takes what the reader reads
+const EXTENSIONS: &[&str] = &["txt"];
+
+impl QueueConsumer<'_> {
+    fn collection(&self, event: &DocumentEvent) -> Result<&Collection> {
+        let name = event.collection.as_deref().unwrap_or(self.default_collection);
+        self.collections.iter().find(|c| c.matches(name)).ok_or_else(|| anyhow::anyhow!("Unknown collection '{}'", name))
+    }
+
+    /// The document's name and bytes
+    async fn document(&self, event: &DocumentEvent) -> Result<(String, Vec<u8>)> {
+        let from_url = event.url.as_deref().and_then(|u| u.split(['?', '#']).next()?.rsplit('/').next());
+        let name = event
+            .name
+            .clone()
+            .or_else(|| from_url.filter(|n| !n.is_empty()).map(str::to_string))
+            .ok_or_else(|| anyhow::anyhow!("The event has no name and no URL to take one from"))?;
+        let bytes = match (&event.text, &event.content, &event.url) {
+            (Some(text), _, _) => text.clone().into_bytes(),
+            (None, Some(content), _) => {
+                base64::engine::general_purpose::STANDARD.decode(content.trim()).context("content is not base64")?
+            }
+            (None, None, Some(url)) => {
+                let response = reqwest::get(url).await?.error_for_status()?;
+                response.bytes().await.with_context(|| format!("Failed to download {}", url))?.to_vec()
+            }
+            (None, None, None) => anyhow::bail!("The event has no url, content or text"),
+        };
+        Ok((name, bytes))
+    }
+
+    /// Save the event's document; the result is complete but for the fields
+    async fn intake(&self, payload: &[u8]) -> (DocumentResult, Option<std::path::PathBuf>) {
+        let mut result = DocumentResult::default();
+        let saved = async {
+            let event: DocumentEvent = serde_json::from_slice(payload).context("The event is not a JSON document event")?;
+            result.id = event.id.clone();
+            let collection = self.collection(&event)?;
+            result.collection = Some(collection.name.clone());
+            let (name, bytes) = self.document(&event).await?;
+            let extensions: Vec<String> = EXTENSIONS.iter().map(|e| e.to_string()).collect();
+            let outcome = ingest_document(collection, &name, event.key.as_deref(), &bytes, &extensions)?;
+            let path = outcome.file().map(|file| collection.folder.join(file));
+            result.outcome = Some(outcome);
+            anyhow::Ok(path)
+        }
+        .await;
+        match saved {
+            Ok(path) => (result, path),
+            Err(e) => {
+                result.error = Some(format!("{:#}", e));
+                (result, None)
+            }
+        }
+    }
+
+    /// Take one batch: save, index, read, publish and acknowledge; returns the events handled
+    pub async fn consume_batch<Q: MessageQueue>(&self, queue: &mut Q, batch: usize, wait: Duration) -> Result<usize> {
+        let deliveries = queue.receive(batch, wait).await?;
+        let mut taken = Vec::with_capacity(deliveries.len());
+        for delivery in &deliveries {
+            taken.push(self.intake(&delivery.payload).await);
+        }
+
+        let saved = taken.iter().any(|(r, _)| r.outcome.as_ref().is_some_and(IngestOutcome::is_new_content));
+        if self.index && saved {
+            let _lock = lock_index(LockMode::Exclusive)?;
+            ingest(&IngestOptions::default(), |_| {}).await?;
+        }
+
+        let mut events = Vec::new();
+        for (delivery, (mut result, path)) in deliveries.iter().zip(taken) {
+            if let Some(path) = path {
+                match std::fs::read(&path) {
+                    Ok(bytes) => {
+                        let text = String::from_utf8_lossy(&bytes);
+                        let collection = result.collection.as_deref().unwrap_or_default();
+                        let record = InvoiceRecord::from_text(collection, &path, &text);
+                        let verification = check_invoice(&record.file, &text);
+                        if result.outcome.as_ref().is_some_and(IngestOutcome::is_new_content) && !self.publishers.is_empty() {
+                            events.push(BusEvent {
+                                event: EventKind::Extracted,
+                                collection: collection.to_string(),
+                                file: record.file.clone(),
+                                document: doc_key(&path),
+                                record: record.clone(),
+                                verification: verification.clone(),
+                                extraction: None,
+                                at: now(),
+                            });
+                        }
+                        result.record = Some(record);
+                        result.verification = Some(verification);
+                    }
+                    Err(e) => result.error = Some(format!("Failed to read {}: {}", path.display(), e)),
+                }
+            }
+            queue.publish(serde_json::to_vec(&result)?).await?;
+            queue.ack(delivery).await?;
+        }
+        publish_or_warn(self.publishers, &events).await;
+        Ok(deliveries.len())
+    }
+
+    /// Consume until Ctrl-C, or with `once` until the queue has nothing more
+    pub async fn run<Q: MessageQueue>(&self, queue: &mut Q, config: &QueueConfig, once: bool) -> Result<usize> {
+        let wait = Duration::from_millis(config.wait_ms);
+        let mut handled = 0;
+        loop {
+            tokio::select! {
+                n = self.consume_batch(queue, config.batch.max(1), wait) => {
+                    let n = n?;
+                    handled += n;
+                    if once && n == 0 {
+                        return Ok(handled);
+                    }
+                }
+                _ = tokio::signal::ctrl_c() => return Ok(handled),
+            }
+        }
+    }
+}
+
+/// Connect to the configured backend and consume from it
+pub async fn consume(config: &QueueConfig, consumer: &QueueConsumer<'_>, once: bool) -> Result<usize> {
+    match config.backend {
+        #[cfg(feature = "redis")]
+        QueueBackend::Redis => consumer.run(&mut redis_queue::RedisQueue::connect(config).await?, config, once).await,
+        #[cfg(feature = "kafka")]
+        QueueBackend::Kafka => consumer.run(&mut kafka_queue::KafkaQueue::connect(config)?, config, once).await,
+        #[cfg(feature = "nats")]
+        QueueBackend::Nats => consumer.run(&mut nats_queue::NatsQueue::connect(config).await?, config, once).await,
+        #[allow(unreachable_patterns)]
+        backend => {
+            let _ = (consumer, once);
+            let feature = format!("{:?}", backend).to_lowercase();
+            anyhow::bail!("This build has no {} queue support (enable the `{}` feature)", feature, feature)
+        }
+    }
+}
+
+#[cfg(feature = "redis")]
+pub mod redis_queue {
+    use super::*;
+    use redis::aio::MultiplexedConnection;
+    use redis::streams::{StreamReadOptions, StreamReadReply};
+    use redis::AsyncCommands;
+
+    /// A Redis stream read through a consumer group; entries carry the event in `payload`
+    pub struct RedisQueue {
+        connection: MultiplexedConnection,
+        config: QueueConfig,
+        consumer: String,
+    }
+
+    impl RedisQueue {
+        pub async fn connect(config: &QueueConfig) -> Result<Self> {
+            let client = redis::Client::open(config.url.as_str())?;
+            let mut connection = client.get_multiplexed_async_connection().await?;
+            // BUSYGROUP when it exists already
+            let _: redis::RedisResult<()> = redis::cmd("XGROUP")
+                .arg("CREATE")
+                .arg(&config.input)
+                .arg(&config.group)
+                .arg("0")
+                .arg("MKSTREAM")
+                .query_async(&mut connection)
+                .await;
+            let consumer = format!("{}-{}", crate::audit::local_actor(), std::process::id());
+            Ok(Self { connection, config: config.clone(), consumer })
+        }
+    }
+
+    impl MessageQueue for RedisQueue {
+        async fn receive(&mut self, max: usize, wait: Duration) -> Result<Vec<Delivery>> {
+            let options =
+                StreamReadOptions::default().group(&self.config.group, &self.consumer).count(max).block(wait.as_millis() as usize);
+            let reply: StreamReadReply = self.connection.xread_options(&[&self.config.input], &[">"], &options).await?;
+            Ok(reply
+                .keys
+                .into_iter()
+                .flat_map(|key| key.ids)
+                .map(|entry| Delivery {
+                    payload: entry.get::<Vec<u8>>("payload").unwrap_or_default(),
+                    receipt: Receipt::Redis(entry.id),
+                })
+                .collect())
+        }
+
+        async fn ack(&mut self, delivery: &Delivery) -> Result<()> {
+            if let Receipt::Redis(id) = &delivery.receipt {
+                let _: i64 = self.connection.xack(&self.config.input, &self.config.group, &[id]).await?;
+            }
+            Ok(())
+        }
+
+        async fn publish(&mut self, payload: Vec<u8>) -> Result<()> {
+            let _: String = self.connection.xadd(&self.config.output, "*", &[("payload", payload)]).await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "kafka")]
+pub mod kafka_queue {
+    use super::*;
+    use rdkafka::consumer::{CommitMode, Consumer as _, StreamConsumer};
+    use rdkafka::producer::{FutureProducer, FutureRecord};
+    use rdkafka::{ClientConfig, Message, Offset, TopicPartitionList};
+
+    /// A Kafka topic read in a consumer group, offsets committed per handled event
+    pub struct KafkaQueue {
+        consumer: StreamConsumer,
+        producer: FutureProducer,
+        output: String,
+    }
+
+    impl KafkaQueue {
+        pub fn connect(config: &QueueConfig) -> Result<Self> {
+            let consumer: StreamConsumer = ClientConfig::new()
+                .set("bootstrap.servers", &config.url)
+                .set("group.id", &config.group)
+                .set("enable.auto.commit", "false")
+                .set("auto.offset.reset", "earliest")
+                .create()?;
+            consumer.subscribe(&[&config.input])?;
+            let producer: FutureProducer = ClientConfig::new().set("bootstrap.servers", &config.url).create()?;
+            Ok(Self { consumer, producer, output: config.output.clone() })
+        }
+    }
+
+    impl MessageQueue for KafkaQueue {
+        async fn receive(&mut self, max: usize, wait: Duration) -> Result<Vec<Delivery>> {
+            let mut deliveries = Vec::new();
+            // The first message may take `wait`; the rest of the batch only what is already there
+            let mut timeout = wait;
+            while deliveries.len() < max {
+                let Ok(message) = tokio::time::timeout(timeout, self.consumer.recv()).await else { break };
+                let message = message?;
+                deliveries.push(Delivery {
+                    payload: message.payload().unwrap_or_default().to_vec(),
+                    receipt: Receipt::Kafka {
+                        topic: message.topic().to_string(),
+                        partition: message.partition(),
+                        offset: message.offset(),
+                    },
+                });
+                timeout = Duration::from_millis(10);
+            }
+            Ok(deliveries)
+        }
+
+        async fn ack(&mut self, delivery: &Delivery) -> Result<()> {
+            if let Receipt::Kafka { topic, partition, offset } = &delivery.receipt {
+                let mut offsets = TopicPartitionList::new();
+                offsets.add_partition_offset(topic, *partition, Offset::Offset(offset + 1))?;
+                self.consumer.commit(&offsets, CommitMode::Async)?;
+            }
+            Ok(())
+        }
+
+        async fn publish(&mut self, payload: Vec<u8>) -> Result<()> {
+            let record = FutureRecord::<(), [u8]>::to(&self.output).payload(&payload);
+            self.producer.send(record, Duration::from_secs(10)).await.map_err(|(e, _)| e)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "nats")]
+pub mod nats_queue {
+    use super::*;
+    use futures::StreamExt;
+
+    /// A NATS subject read in a queue group. Core NATS does not redeliver:
+    /// events published while no consumer runs are lost.
+    pub struct NatsQueue {
+        client: async_nats::Client,
+        subscriber: async_nats::Subscriber,
+        output: String,
+    }
+
+    impl NatsQueue {
+        pub async fn connect(config: &QueueConfig) -> Result<Self> {
+            let client = async_nats::connect(&config.url).await?;
+            let subscriber = client.queue_subscribe(config.input.clone(), config.group.clone()).await?;
+            Ok(Self { client, subscriber, output: config.output.clone() })
+        }
+    }
+
+    impl MessageQueue for NatsQueue {
+        async fn receive(&mut self, max: usize, wait: Duration) -> Result<Vec<Delivery>> {
+            let mut deliveries = Vec::new();
+            let mut timeout = wait;
+            while deliveries.len() < max {
+                let Ok(Some(message)) = tokio::time::timeout(timeout, self.subscriber.next()).await else { break };
+                deliveries.push(Delivery { payload: message.payload.to_vec(), receipt: Receipt::None });
+                timeout = Duration::from_millis(10);
+            }
+            Ok(deliveries)
+        }
+
+        async fn ack(&mut self, _delivery: &Delivery) -> Result<()> {
+            Ok(())
+        }
+
+        async fn publish(&mut self, payload: Vec<u8>) -> Result<()> {
+            self.client.publish(self.output.clone(), payload.into()).await?;
+            self.client.flush().await?;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,364 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Soft quotas per tenant: questions and model tokens per day (UTC), and
+// megabytes of documents. A request is refused with 429 once a limit is
+// reached; the one that crosses it still runs, hence "soft". Daily usage is
+// kept in the document metadata of the store so a restart does not
+// reset it; servers sharing a store add to the same counts, and read them
+// back every few seconds. Storage is the size of the tenant's collection folders, measured
+// at most once a minute; intake into a tenant over its storage limit skips
+// the document. Every response to a tenant carries its quota status in
+// X-Quota-* headers.
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::collections;
+use crate::config::Config;
+use crate::events::EventSink;
+use crate::metadata::{now, Metadata};
+use crate::store::store;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// How long a measured storage size is reused
+const STORAGE_TTL: Duration = Duration::from_secs(60);
+
+/// How long usage read from a shared store is reused
+const USAGE_TTL: Duration = Duration::from_secs(5);
+
+/// `[quota]` (default for every tenant) or `[tenant.quota]`; unset limits are unlimited
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct QuotaConfig {
+    pub queries_per_day: Option<u64>,
+    /// Prompt and answer tokens (estimated where the model does not report them)
+    pub tokens_per_day: Option<u64>,
+    pub storage_mb: Option<u64>,
+}
+
+/// One tenant's use on one day
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Days since 1970-01-01 (UTC)
+    pub day: u64,
+    pub queries: u64,
+    pub tokens: u64,
+}
+
+/// Limits in force, by tenant (set at startup and on reload)
+static LIMITS: Lazy<RwLock<BTreeMap<String, QuotaConfig>>> = Lazy::new(Default::default);
+
+/// Usage as last saved, by tenant; loaded from the metadata store when first needed
+static USAGE: Lazy<Mutex<BTreeMap<String, Usage>>> = Lazy::new(|| {
+    Mutex::new(Metadata::load().map(|m| m.usage).unwrap_or_default())
+});
+
+static USAGE_READ: Lazy<Mutex<Instant>> = Lazy::new(|| Mutex::new(Instant::now()));
+
+static STORAGE: Lazy<Mutex<HashMap<String, (Instant, u64)>>> = Lazy::new(Default::default);
+
+/// Take the limits from the config: a tenant's own `quota`, else the top-level `[quota]`
+pub fn set_quotas(config: &Config) {
+    let limits = config
+        .tenants
+        .iter()
+        .map(|t| (t.name.clone(), t.quota.clone().unwrap_or_else(|| config.quota.clone())))
+        .collect();
+    *LIMITS.write().unwrap() = limits;
+}
+
+fn today() -> u64 {
+    now() / SECONDS_PER_DAY
+}
+
+/// Count one question and its tokens against the tenant's day
+pub fn record(tenant: &str, tokens: u64) {
+    let add = |usage: &mut BTreeMap<String, Usage>| {
+        let entry = usage.entry(tenant.to_string()).or_default();
+        if entry.day != today() {
+            *entry = Usage { day: today(), ..Usage::default() };
+        }
+        entry.queries += 1;
+        entry.tokens += tokens;
+    };
+
+    // Added to the stored counts, which may include other servers' questions
+    match Metadata::update(|metadata| add(&mut metadata.usage)) {
+        Ok(metadata) => *USAGE.lock().unwrap() = metadata.usage,
+        Err(e) => {
+            add(&mut USAGE.lock().unwrap());
+            eprintln!("WARNING: usage not saved: {:#}", e);
+        }
+    }
+}
+
+/// The tenant's use today, re-read now and then if other servers share the store
+fn usage_today(tenant: &str) -> Usage {
+    if store().shared() {
+        let mut read = USAGE_READ.lock().unwrap();
+        if read.elapsed() >= USAGE_TTL {
+            if let Ok(metadata) = Metadata::load() {
+                *USAGE.lock().unwrap() = metadata.usage;
+            }
+            *read = Instant::now();
+        }
+    }
+    USAGE.lock().unwrap().get(tenant).filter(|u| u.day == today()).cloned().unwrap_or_default()
+}
+
+/// Bytes of documents in the tenant's collection folders
+fn storage_bytes(tenant: &str) -> u64 {
+    if let Some((at, bytes)) = STORAGE.lock().unwrap().get(tenant)
+        && at.elapsed() < STORAGE_TTL
+    {
+        return *bytes;
+    }
+    let bytes = collections().iter().filter(|c| c.tenant.as_deref() == Some(tenant)).map(|c| folder_size(&c.folder)).sum();
+    STORAGE.lock().unwrap().insert(tenant.to_string(), (Instant::now(), bytes));
+    bytes
+}
+
+fn folder_size(folder: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(folder) else { return 0 };
+    entries
+        .flatten()
+        .map(|e| match e.file_type() {
+            Ok(t) if t.is_dir() => folder_size(&e.path()),
+            Ok(_) => e.metadata().map(|m| m.len()).unwrap_or_default(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// False if writing `extra` more bytes would take the tenant past its storage limit
+pub fn storage_allows(tenant: &str, extra: u64) -> bool {
+    let limit = LIMITS.read().unwrap().get(tenant).and_then(|q| q.storage_mb);
+    limit.is_none_or(|mb| storage_bytes(tenant) + extra <= mb * 1024 * 1024)
+}
+
+/// Used and allowed amounts of one resource
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allowance {
+    pub used: u64,
+    pub limit: Option<u64>,
+}
+
+impl Allowance {
+    fn exhausted(&self) -> bool {
+        self.limit.is_some_and(|limit| self.used >= limit)
+    }
+
+    fn header(&self) -> String {
+        match self.limit {
+            Some(limit) => format!("{}/{}", self.used, limit),
+            None => self.used.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct QuotaStatus {
+    pub tenant: String,
+    pub queries: Allowance,
+    pub tokens: Allowance,
+    pub storage_mb: Allowance,
+    /// Unix time the daily counts start again
+    pub reset_at: u64,
+}
+
+impl QuotaStatus {
+    pub fn of(tenant: &str) -> Self {
+        let limits = LIMITS.read().unwrap().get(tenant).cloned().unwrap_or_default();
+        let usage = usage_today(tenant);
+        Self {
+            tenant: tenant.to_string(),
+            queries: Allowance { used: usage.queries, limit: limits.queries_per_day },
+            tokens: Allowance { used: usage.tokens, limit: limits.tokens_per_day },
+            storage_mb: Allowance { used: storage_bytes(tenant).div_ceil(1024 * 1024), limit: limits.storage_mb },
+            reset_at: (today() + 1) * SECONDS_PER_DAY,
+        }
+    }
+
+    /// Why no more questions are accepted today, if that is so
+    pub fn refusal(&self) -> Option<String> {
+        if self.queries.exhausted() {
+            Some(format!("Tenant '{}' has used its {} questions for today", self.tenant, self.queries.header()))
+        } else if self.tokens.exhausted() {
+            Some(format!("Tenant '{}' has used its {} tokens for today", self.tenant, self.tokens.header()))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+pub use guard::{QueryQuota, QuotaHeaders};
+
+/// Request guard and response headers of the server
+#[cfg(feature = "server")]
+mod guard {
+    use rocket::fairing::{Fairing, Info, Kind};
+    use rocket::http::{Header, Status};
+    use rocket::request::{FromRequest, Outcome, Request};
+    use rocket::Response;
+
+    use super::QuotaStatus;
+    use crate::metadata::now;
+    use crate::tenants::Tenancy;
+
+    impl QuotaStatus {
+        /// X-Quota-* headers of the status, with Retry-After once refused
+        pub fn headers(&self) -> Vec<Header<'static>> {
+            let mut headers = vec![
+                Header::new("X-Quota-Queries", self.queries.header()),
+                Header::new("X-Quota-Tokens", self.tokens.header()),
+                Header::new("X-Quota-Storage-MB", self.storage_mb.header()),
+                Header::new("X-Quota-Reset", self.reset_at.to_string()),
+            ];
+            if self.refusal().is_some() {
+                headers.push(Header::new("Retry-After", self.reset_at.saturating_sub(now()).to_string()));
+            }
+            headers
+        }
+    }
+
+    /// Admits a question under the tenant's daily quota (429 otherwise); always
+    /// admits when no tenants are configured
+    pub struct QueryQuota(pub Option<QuotaStatus>);
+
+    /// The status seen by the request, for `QuotaHeaders`
+    struct SeenQuota(Option<QuotaStatus>);
+
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for QueryQuota {
+        type Error = String;
+
+        async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+            let tenancy = match req.guard::<Tenancy>().await {
+                Outcome::Success(tenancy) => tenancy,
+                Outcome::Error(e) => return Outcome::Error(e),
+                Outcome::Forward(status) => return Outcome::Forward(status),
+            };
+            let Some(tenant) = tenancy.name() else { return Outcome::Success(QueryQuota(None)) };
+            let status = QuotaStatus::of(tenant);
+            req.local_cache(|| SeenQuota(Some(status.clone())));
+            match status.refusal() {
+                Some(reason) => Outcome::Error((Status::TooManyRequests, reason)),
+                None => Outcome::Success(QueryQuota(Some(status))),
+            }
+        }
+    }
+
+    /// Adds the X-Quota-* headers to every response to a tenant: the status the
+    /// query guard saw, or else the tenant's current one
+    pub struct QuotaHeaders;
+
+    #[rocket::async_trait]
+    impl Fairing for QuotaHeaders {
+        fn info(&self) -> Info {
+            Info { name: "Quota status headers", kind: Kind::Response }
+        }
+
+        async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+            let status = match &req.local_cache(|| SeenQuota(None)).0 {
+                Some(status) => status.clone(),
+                None => match req.guard::<Tenancy>().await {
+                    Outcome::Success(tenancy) => match tenancy.name() {
+                        Some(tenant) => QuotaStatus::of(tenant),
+                        None => return,
+                    },
+                    _ => return,
+                },
+            };
+            for header in status.headers() {
+                res.set_header(header);
+            }
+        }
+    }
+}
+
+/// Passes every event on and counts the tokens of the prompts and answers
+pub struct Metered {
+    pub inner: Option<Arc<dyn EventSink>>,
+    tokens: AtomicU64,
+}
+
+impl Metered {
+    pub fn new(inner: Option<Arc<dyn EventSink>>) -> Arc<Self> {
+        Arc::new(Self { inner, tokens: AtomicU64::new(0) })
+    }
+
+    pub fn tokens(&self) -> u64 {
+        self.tokens.load(Ordering::Relaxed)
+    }
+}
+
+impl EventSink for Metered {
+    fn on_scan_start(&self, collection: &str) {
+        if let Some(inner) = &self.inner {
+            inner.on_scan_start(collection);
+        }
+    }
+
+    fn on_document_loaded(&self, file: &str, bytes: usize) {
+        if let Some(inner) = &self.inner {
+            inner.on_document_loaded(file, bytes);
+        }
+    }
+
+    fn on_prompt_built(&self, documents: usize, tokens: usize) {
+        self.tokens.fetch_add(tokens as u64, Ordering::Relaxed);
+        if let Some(inner) = &self.inner {
+            inner.on_prompt_built(documents, tokens);
+        }
+    }
+
+    fn on_tokens(&self, n: usize) {
+        self.tokens.fetch_add(n as u64, Ordering::Relaxed);
+        if let Some(inner) = &self.inner {
+            inner.on_tokens(n);
+        }
+    }
+
+    fn wants_token_deltas(&self) -> bool {
+        self.inner.as_ref().is_some_and(|inner| inner.wants_token_deltas())
+    }
+
+    fn on_token_delta(&self, text: &str) {
+        if let Some(inner) = &self.inner {
+            inner.on_token_delta(text);
+        }
+    }
+
+    fn on_partial_answer(&self, answer: &Value) {
+        if let Some(inner) = &self.inner {
+            inner.on_partial_answer(answer);
+        }
+    }
+
+    fn on_progress(&self, message: &str) {
+        if let Some(inner) = &self.inner {
+            inner.on_progress(message);
+        }
+    }
+
+    fn on_warning(&self, message: &str) {
+        if let Some(inner) = &self.inner {
+            inner.on_warning(message);
+        }
+    }
+
+    fn on_done(&self, elapsed_ms: u64, ok: bool) {
+        if let Some(inner) = &self.inner {
+            inner.on_done(elapsed_ms, ok);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Reading documents from disk: a per-file size limit, a policy for files
+// that are not valid UTF-8, and a streaming reader that feeds the chunker
+// line by line instead of loading the whole file. Binary files (a NUL byte
+// near the start) are always refused. Encrypted files (the mirror of remote
+// collections, see encryption.rs) are decrypted first.
+
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Cursor};
+use std::path::{Path, PathBuf};
+
+use crate::chunking::{Chunk, Chunker};
+use crate::encryption;
+
+/// What to do with bytes that are not valid UTF-8
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NonUtf8 {
+    /// Replace invalid sequences with U+FFFD and warn
+    #[default]
+    Lossy,
+    /// Refuse the file
+    Skip,
+}
+
+/// `[reading]` section of the config file
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct ReadingConfig {
+    /// Larger files are not read at all
+    pub max_file_bytes: u64,
+    pub non_utf8: NonUtf8,
+}
+
+impl Default for ReadingConfig {
+    fn default() -> Self {
+        Self { max_file_bytes: 20 * 1024 * 1024, non_utf8: NonUtf8::Lossy }
+    }
+}
+
+/// Reading settings for this process, set once at startup
+static READING: OnceCell<ReadingConfig> = OnceCell::new();
+
+/// Install the reading settings (first call wins)
+pub fn init_reading(config: ReadingConfig) {
+    let _ = READING.set(config);
+}
+
+pub fn reading() -> &'static ReadingConfig {
+    READING.get_or_init(ReadingConfig::default)
+}
+
+fn check_size(path: &Path) -> Result<()> {
+    let size = fs::metadata(path)
+        .with_context(|| format!("Failed to read file: {}", path.display()))?
+        .len();
+    let limit = reading().max_file_bytes;
+    if size > limit {
+        anyhow::bail!("{} is {} bytes, over the {} byte limit", path.display(), size, limit);
+    }
+    Ok(())
+}
+
+/// Bytes inspected for a NUL when telling text from binary
+const BINARY_SNIFF_BYTES: usize = 8_000;
+
+fn check_text(path: &Path, head: &[u8]) -> Result<()> {
+    if head.iter().take(BINARY_SNIFF_BYTES).any(|&b| b == 0) {
+        anyhow::bail!("{} looks like a binary file", path.display());
+    }
+    Ok(())
+}
+
+fn decode(path: &Path, bytes: Vec<u8>) -> Result<String> {
+    check_text(path, &bytes)?;
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        Err(e) => match reading().non_utf8 {
+            NonUtf8::Skip => anyhow::bail!("{} is not valid UTF-8", path.display()),
+            NonUtf8::Lossy => {
+                eprintln!("WARNING: {} is not valid UTF-8; invalid bytes replaced", path.display());
+                Ok(String::from_utf8_lossy(e.as_bytes()).into_owned())
+            }
+        },
+    }
+}
+
+/// Whole document as text, within the size limit and UTF-8 policy
+pub fn read_document(path: &Path) -> Result<String> {
+    check_size(path)?;
+    let bytes = encryption::read(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+    decode(path, bytes)
+}
+
+/// Chunks of a document, read line by line (memory use is about one chunk)
+pub struct ChunkStream {
+    path: PathBuf,
+    reader: Box<dyn BufRead + Send>,
+    chunker: Option<Chunker>,
+    ready: VecDeque<(Chunk, String)>,
+    line: Vec<u8>,
+    warned: bool,
+}
+
+impl Iterator for ChunkStream {
+    type Item = Result<(Chunk, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(chunk) = self.ready.pop_front() {
+                return Some(Ok(chunk));
+            }
+            let chunker = self.chunker.as_mut()?;
+
+            self.line.clear();
+            match self.reader.read_until(b'\n', &mut self.line) {
+                Ok(0) => {
+                    let chunker = self.chunker.take()?;
+                    self.ready.extend(chunker.finish());
+                }
+                Ok(_) => {
+                    // Lines end at '\n', which never occurs inside a multi-byte character
+                    let line = match std::str::from_utf8(&self.line) {
+                        Ok(line) => line.to_string(),
+                        Err(_) if reading().non_utf8 == NonUtf8::Skip => {
+                            self.chunker = None;
+                            return Some(Err(anyhow::anyhow!("{} is not valid UTF-8", self.path.display())));
+                        }
+                        Err(_) => {
+                            if !self.warned {
+                                eprintln!("WARNING: {} is not valid UTF-8; invalid bytes replaced", self.path.display());
+                                self.warned = true;
+                            }
+                            String::from_utf8_lossy(&self.line).into_owned()
+                        }
+                    };
+                    chunker.push_line(&line);
+                    self.ready.extend(chunker.take_ready());
+                }
+                Err(e) => {
+                    self.chunker = None;
+                    return Some(Err(anyhow::Error::new(e).context(format!("Failed to read file: {}", self.path.display()))));
+                }
+            }
+        }
+    }
+}
+
+/// Stream a document's chunks, within the size limit and UTF-8 policy
+pub fn stream_chunks(path: &Path) -> Result<ChunkStream> {
+    check_size(path)?;
+    let file = File::open(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+    let mut reader = BufReader::with_capacity(BINARY_SNIFF_BYTES.max(8 * 1024), file);
+    let head = reader.fill_buf().with_context(|| format!("Failed to read file: {}", path.display()))?;
+    // An encrypted file has to be decrypted as a whole
+    let reader: Box<dyn BufRead + Send> = if encryption::is_sealed(head) {
+        let bytes = encryption::read(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+        check_text(path, &bytes)?;
+        Box::new(Cursor::new(bytes))
+    } else {
+        check_text(path, head)?;
+        Box::new(reader)
+    };
+    Ok(ChunkStream {
+        path: path.to_path_buf(),
+        reader,
+        chunker: Some(Chunker::new()),
+        ready: VecDeque::new(),
+        line: Vec::new(),
+        warned: false,
+    })
+}
@@ -0,0 +1,191 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Receipts and expenses: the `receipts` domain asks the model for merchant,
+// date, total, VAT and an expense category from the `[expenses]` taxonomy;
+// `export-expenses` reads the same fields straight from the text (as the
+// invoice reports do) and writes them in the expense tool's CSV import format:
+//
+//   Date,Merchant,Category,Amount,VAT,Currency,Receipt
+//
+// Categories are picked by keyword: the first category with a keyword in the
+// merchant name, then in the whole receipt; `default_category` otherwise.
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::aggregate::in_period;
+use crate::records::{collection_records, InvoiceRecord};
+use crate::{collections, get_cached_content};
+
+pub const RECEIPTS_DOMAIN: &str = "receipts";
+
+/// `[expenses]` section of the config file
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ExpenseConfig {
+    /// Category → keywords (case-insensitive) of the merchants and items it covers, in order of precedence
+    pub categories: Vec<ExpenseCategory>,
+    /// Category of receipts no keyword matches
+    pub default_category: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ExpenseCategory {
+    pub name: String,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+}
+
+impl Default for ExpenseConfig {
+    fn default() -> Self {
+        let category = |name: &str, keywords: &[&str]| ExpenseCategory {
+            name: name.to_string(),
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+        };
+        Self {
+            categories: vec![
+                category("Travel", &["airline", "airways", "flight", "uber", "bolt", "taxi", "train", "parking", "toll"]),
+                category("Fuel", &["fuel", "petrol", "diesel", "engen", "shell", "caltex", "sasol"]),
+                category("Accommodation", &["hotel", "lodge", "guest house", "airbnb", "inn"]),
+                category("Meals", &["restaurant", "cafe", "coffee", "bistro", "grill", "pizza", "meal", "lunch", "dinner"]),
+                category("Office supplies", &["stationery", "paper", "toner", "ink", "office"]),
+                category("Software", &["software", "subscription", "licence", "license", "saas"]),
+            ],
+            default_category: "Other".to_string(),
+        }
+    }
+}
+
+impl ExpenseConfig {
+    /// Every category name, the default one last
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.categories.iter().map(|c| c.name.clone()).collect();
+        if !names.contains(&self.default_category) {
+            names.push(self.default_category.clone());
+        }
+        names
+    }
+
+    /// Category for a receipt by its merchant and text
+    pub fn classify(&self, merchant: Option<&str>, text: &str) -> &str {
+        let matches = |haystack: &str| {
+            let haystack = haystack.to_lowercase();
+            self.categories.iter().find(|c| c.keywords.iter().any(|k| haystack.contains(&k.to_lowercase())))
+        };
+        merchant
+            .and_then(matches)
+            .or_else(|| matches(text))
+            .map(|c| c.name.as_str())
+            .unwrap_or(&self.default_category)
+    }
+
+    /// Prompt persona of the `receipts` domain
+    pub fn instruction(&self) -> String {
+        format!(
+            "You are a precise expense clerk reading receipts. Extract the merchant, the date, the total paid, the VAT \
+             included and the currency exactly as printed, and pick the expense category from this list only: {}. \
+             Use \"{}\" when none fits.",
+            self.names().join(", "),
+            self.default_category
+        )
+    }
+
+    /// Answer schema of the `receipts` domain; the category must be one of the taxonomy
+    pub fn schema(&self) -> Value {
+        let receipt = json!({
+            "type": "object",
+            "required": ["merchant", "date", "total", "category"],
+            "properties": {
+                "merchant": {"type": "string"},
+                "date": {"type": "string"},
+                "total": {"type": ["number", "null"]},
+                "vat": {"type": ["number", "null"]},
+                "currency": {"type": ["string", "null"]},
+                "category": {"type": "string", "enum": self.names()},
+                "file": {"type": "string"}
+            }
+        });
+        json!({
+            "type": "object",
+            "required": ["receipts"],
+            "properties": {"receipts": {"type": "array", "items": receipt}}
+        })
+    }
+}
+
+/// One receipt as exported
+#[derive(Serialize, Debug, Clone)]
+pub struct Receipt {
+    pub collection: String,
+    pub file: String,
+    pub merchant: Option<String>,
+    /// ISO date (YYYY-MM-DD)
+    pub date: Option<String>,
+    pub total: Option<Decimal>,
+    pub vat: Option<Decimal>,
+    pub currency: Option<String>,
+    pub category: String,
+}
+
+impl Receipt {
+    fn from_record(record: InvoiceRecord, text: &str, expenses: &ExpenseConfig) -> Self {
+        // Receipts rarely say "From:"; the merchant is printed on top
+        let merchant = record.vendor.clone().or_else(|| {
+            text.lines().map(str::trim).find(|l| !l.is_empty()).map(|l| l.trim_matches('*').trim().to_string())
+        });
+        let category = expenses.classify(merchant.as_deref(), text).to_string();
+        Self {
+            collection: record.collection,
+            file: record.file,
+            merchant,
+            date: record.date,
+            total: record.gross,
+            vat: record.tax,
+            currency: record.currency,
+            category,
+        }
+    }
+}
+
+/// Receipts of the named collection, or of every collection of the `receipts` domain
+pub fn receipts(collection: Option<&str>, period: Option<&str>, expenses: &ExpenseConfig) -> Vec<Receipt> {
+    collections()
+        .iter()
+        .filter(|c| match collection {
+            Some(name) => c.matches(name),
+            None => c.domain.as_deref() == Some(RECEIPTS_DOMAIN),
+        })
+        .flat_map(|c| collection_records(c, false))
+        .filter(|r| period.is_none_or(|p| r.date.as_deref().is_some_and(|d| in_period(d, p))))
+        .filter_map(|r| {
+            let text = get_cached_content(&r.path).ok()?;
+            Some(Receipt::from_record(r, &text, expenses))
+        })
+        .collect()
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) { format!("\"{}\"", s.replace('"', "\"\"")) } else { s.to_string() }
+}
+
+/// The expense tool's CSV import: one line per receipt, amounts with two decimals
+pub fn expenses_csv(receipts: &[Receipt]) -> String {
+    let mut out = String::from("Date,Merchant,Category,Amount,VAT,Currency,Receipt\n");
+    let amount = |d: Option<Decimal>| d.map(|d| format!("{:.2}", d)).unwrap_or_default();
+    for r in receipts {
+        let fields = [
+            r.date.clone().unwrap_or_default(),
+            r.merchant.clone().unwrap_or_default(),
+            r.category.clone(),
+            amount(r.total),
+            amount(r.vat),
+            r.currency.clone().unwrap_or_default(),
+            format!("{}/{}", r.collection, r.file),
+        ];
+        let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
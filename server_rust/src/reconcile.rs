@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Payment reconciliation: bank payments are matched to invoices, first by an
+// invoice number in the payment reference (same amount), then by amount alone
+// within a date window after the invoice date. What is left over is reported
+// as unpaid invoices and unmatched payments.
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::payments::Payment;
+use crate::records::{day_number, InvoiceRecord};
+
+/// Default number of days after the invoice date a payment may be made
+pub const DEFAULT_WINDOW_DAYS: i64 = 60;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchRule {
+    /// The invoice number appears in the payment reference or payee
+    Reference,
+    /// Same amount, paid within the date window
+    Amount,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PaymentMatch {
+    pub invoice: InvoiceRecord,
+    pub payment: Payment,
+    pub rule: MatchRule,
+    /// Days from the invoice date to the payment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub days: Option<i64>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Reconciliation {
+    pub matched: Vec<PaymentMatch>,
+    pub unpaid: Vec<InvoiceRecord>,
+    pub unmatched: Vec<Payment>,
+}
+
+/// Letters and digits only, upper case ("inv-2025/001" → "INV2025001")
+fn normalize(s: &str) -> String {
+    s.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_uppercase).collect()
+}
+
+fn same_currency(invoice: &InvoiceRecord, payment: &Payment) -> bool {
+    match (&invoice.currency, &payment.currency) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+    }
+}
+
+fn days_between(invoice: &InvoiceRecord, payment: &Payment) -> Option<i64> {
+    Some(day_number(payment.date.as_deref()?)? - day_number(invoice.date.as_deref()?)?)
+}
+
+/// Match `payments` to `invoices`; credit notes and invoices without a total are not payable
+pub fn reconcile(invoices: Vec<InvoiceRecord>, payments: Vec<Payment>, window_days: i64) -> Reconciliation {
+    let mut open: Vec<Option<InvoiceRecord>> =
+        invoices.into_iter().filter(|r| r.gross.is_some_and(|g| g > Decimal::ZERO)).map(Some).collect();
+    let mut result = Reconciliation::default();
+    let mut remaining = Vec::new();
+
+    // Invoice number in the reference, same amount
+    for payment in payments {
+        let text = normalize(&payment.text());
+        let found = open.iter().position(|slot| {
+            slot.as_ref().is_some_and(|r| {
+                let id = r.id.as_deref().map(normalize).unwrap_or_default();
+                id.len() >= 3 && text.contains(&id) && r.gross == Some(payment.amount) && same_currency(r, &payment)
+            })
+        });
+        match found.and_then(|i| open[i].take()) {
+            Some(invoice) => {
+                let days = days_between(&invoice, &payment);
+                result.matched.push(PaymentMatch { invoice, payment, rule: MatchRule::Reference, days });
+            }
+            None => remaining.push(payment),
+        }
+    }
+
+    // Same amount within the window; the invoice closest before the payment wins
+    for payment in remaining {
+        let found = open
+            .iter()
+            .enumerate()
+            .filter_map(|(i, slot)| {
+                let r = slot.as_ref()?;
+                let days = days_between(r, &payment)?;
+                let fits = r.gross == Some(payment.amount) && same_currency(r, &payment) && (0..=window_days).contains(&days);
+                fits.then_some((i, days))
+            })
+            .min_by_key(|(_, days)| *days);
+        match found {
+            Some((i, days)) => {
+                let invoice = open[i].take().unwrap();
+                result.matched.push(PaymentMatch { invoice, payment, rule: MatchRule::Amount, days: Some(days) });
+            }
+            None => result.unmatched.push(payment),
+        }
+    }
+
+    result.unpaid = open.into_iter().flatten().collect();
+    result
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) { format!("\"{}\"", s.replace('"', "\"\"")) } else { s.to_string() }
+}
+
+impl Reconciliation {
+    /// Terminal report: matches, then unpaid invoices, then unmatched payments
+    pub fn to_table(&self) -> String {
+        let mut lines = vec![format!("Matched ({})", self.matched.len())];
+        for m in &self.matched {
+            lines.push(format!(
+                "  {}\t{}\t{}\t← {} {} ({:?}{})",
+                m.invoice.id.as_deref().unwrap_or(&m.invoice.file),
+                m.invoice.vendor.as_deref().unwrap_or_default(),
+                m.payment.amount,
+                m.payment.date.as_deref().unwrap_or("?"),
+                m.payment.source,
+                m.rule,
+                m.days.map(|d| format!(", {} days", d)).unwrap_or_default()
+            ));
+        }
+        lines.push(format!("Unpaid invoices ({})", self.unpaid.len()));
+        for r in &self.unpaid {
+            lines.push(format!(
+                "  {}\t{}\t{}\t{} {}",
+                r.id.as_deref().unwrap_or(&r.file),
+                r.vendor.as_deref().unwrap_or_default(),
+                r.date.as_deref().unwrap_or("?"),
+                r.gross.unwrap_or_default(),
+                r.currency.as_deref().unwrap_or_default()
+            ));
+        }
+        lines.push(format!("Unmatched payments ({})", self.unmatched.len()));
+        for p in &self.unmatched {
+            lines.push(format!(
+                "  {}\t{}\t{}\t{}",
+                p.source,
+                p.date.as_deref().unwrap_or("?"),
+                p.amount,
+                p.text().trim()
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// status,invoice,invoice_id,vendor,invoice_date,amount,currency,payment,payment_date,payee,rule
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("status,invoice,invoice_id,vendor,invoice_date,amount,currency,payment,payment_date,payee,rule\n");
+        let mut row = |status: &str, invoice: Option<&InvoiceRecord>, payment: Option<&Payment>, rule: Option<MatchRule>| {
+            let amount = invoice.and_then(|r| r.gross).or(payment.map(|p| p.amount)).unwrap_or_default();
+            let currency = invoice.and_then(|r| r.currency.clone()).or(payment.and_then(|p| p.currency.clone()));
+            let cells = [
+                status.to_string(),
+                invoice.map(|r| format!("{}/{}", r.collection, r.file)).unwrap_or_default(),
+                invoice.and_then(|r| r.id.clone()).unwrap_or_default(),
+                invoice.and_then(|r| r.vendor.clone()).unwrap_or_default(),
+                invoice.and_then(|r| r.date.clone()).unwrap_or_default(),
+                amount.to_string(),
+                currency.unwrap_or_default(),
+                payment.map(|p| p.source.clone()).unwrap_or_default(),
+                payment.and_then(|p| p.date.clone()).unwrap_or_default(),
+                payment.map(|p| p.payee.clone()).unwrap_or_default(),
+                rule.map(|r| format!("{:?}", r).to_lowercase()).unwrap_or_default(),
+            ];
+            out.push_str(&cells.iter().map(|c| csv_field(c)).collect::<Vec<_>>().join(","));
+            out.push('\n');
+        };
+        for m in &self.matched {
+            row("matched", Some(&m.invoice), Some(&m.payment), Some(m.rule));
+        }
+        for r in &self.unpaid {
+            row("unpaid", Some(r), None, None);
+        }
+        for p in &self.unmatched {
+            row("unmatched", None, Some(p), None);
+        }
+        out
+    }
+}
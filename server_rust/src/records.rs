@@ -0,0 +1,166 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Structured invoice records read directly from the text (no model involved):
+// number, vendor, date, currency and amounts. Aggregation, spend reports,
+// exports and reconciliation all work from these records.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::docid::is_text_file;
+use crate::lifecycle::InvoiceStatus;
+use crate::metadata::{invoice_status, is_hidden};
+use crate::vat::extract_figures;
+use crate::versions::{DocumentKind, DocumentVersion, VERSION_GRAPH};
+use crate::{collections, get_cached_content, Collection};
+
+static VENDOR_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?im)^\s*(?:from|supplier|vendor|seller|billed\s+by)\s*:\s*(?P<name>[^,\n]+)").unwrap());
+
+static DATE_LINE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?im)^\s*(?:invoice\s+)?(?:date|issued|dated)(?:\s+issued)?\s*:?\s*(?P<date>.+)$").unwrap());
+
+// ASCII digits only: `\d` would also take other scripts' digits, which do not parse as numbers
+static ISO_DATE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b([0-9]{4})-([0-9]{2})-([0-9]{2})\b").unwrap());
+
+static DMY_DATE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b([0-9]{1,2})(?:st|nd|rd|th)?\s+(jan|feb|mar|apr|may|jun|jul|aug|sep|oct|nov|dec)[a-z]*\.?\s+([0-9]{4})\b")
+        .unwrap()
+});
+
+static CURRENCY_LINE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?im)^\s*currency\s*:\s*([A-Z]{3})\b").unwrap());
+
+static CURRENCY_CODE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b(ZAR|USD|EUR|GBP|CHF|AUD|CAD|NZD|JPY)\b").unwrap());
+
+/// Currency symbol directly before an amount ("R8,866.50", "$ 120.00")
+static CURRENCY_SYMBOL_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|[\s(])(R|\$|€|£)\s?\d").unwrap());
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+/// One invoice (or credit note) as structured data
+#[derive(Serialize, Debug, Clone)]
+pub struct InvoiceRecord {
+    pub collection: String,
+    pub file: String,
+    #[serde(skip)]
+    pub path: PathBuf,
+    pub id: Option<String>,
+    pub kind: DocumentKind,
+    pub vendor: Option<String>,
+    /// ISO date (YYYY-MM-DD)
+    pub date: Option<String>,
+    /// ISO 4217 code
+    pub currency: Option<String>,
+    /// Amounts; negative for credit notes
+    pub net: Option<Decimal>,
+    pub tax: Option<Decimal>,
+    pub gross: Option<Decimal>,
+    /// Accounts-payable status (see `metadata::current`)
+    pub status: InvoiceStatus,
+}
+
+/// First date in `text` as YYYY-MM-DD (ISO or "15 November 2025")
+pub fn parse_date(text: &str) -> Option<String> {
+    if let Some(c) = ISO_DATE_RE.captures(text) {
+        return Some(format!("{}-{}-{}", &c[1], &c[2], &c[3]));
+    }
+    let c = DMY_DATE_RE.captures(text)?;
+    let month = MONTHS.iter().position(|m| c[2].to_lowercase() == *m)? + 1;
+    Some(format!("{}-{:02}-{:02}", &c[3], month, c[1].parse::<u32>().ok()?))
+}
+
+fn currency(text: &str) -> Option<String> {
+    if let Some(c) = CURRENCY_LINE_RE.captures(text).or_else(|| CURRENCY_CODE_RE.captures(text)) {
+        return Some(c[1].to_string());
+    }
+    let symbol = CURRENCY_SYMBOL_RE.captures(text)?;
+    let code = match &symbol[1] {
+        "R" => "ZAR",
+        "$" => "USD",
+        "€" => "EUR",
+        "£" => "GBP",
+        _ => return None,
+    };
+    Some(code.to_string())
+}
+
+impl InvoiceRecord {
+    pub fn from_text(collection: &str, path: &Path, text: &str) -> Self {
+        let version = DocumentVersion::from_text(path, text);
+        let figures = extract_figures(text);
+        let sign = |d: Decimal| if version.kind == DocumentKind::CreditNote { -d.abs() } else { d };
+        let date = DATE_LINE_RE
+            .captures_iter(text)
+            .find_map(|c| parse_date(&c["date"]))
+            .or_else(|| parse_date(text));
+
+        Self {
+            collection: collection.to_string(),
+            file: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            path: path.to_path_buf(),
+            id: version.id,
+            kind: version.kind,
+            vendor: VENDOR_RE.captures(text).map(|c| c["name"].trim().to_string()),
+            date,
+            currency: currency(text),
+            net: figures.net.map(sign),
+            tax: figures.tax.map(sign),
+            gross: figures.gross.map(sign),
+            status: invoice_status(path),
+        }
+    }
+
+    /// YYYY-MM
+    pub fn month(&self) -> Option<&str> {
+        self.date.as_deref().map(|d| &d[..7])
+    }
+}
+
+/// Records of every document in the collection, in file name order
+pub fn collection_records(collection: &Collection, include_superseded: bool) -> Vec<InvoiceRecord> {
+    let Ok(entries) = fs::read_dir(&collection.folder) else { return Vec::new() };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| is_text_file(p) && !is_hidden(p))
+        .filter(|p| include_superseded || !VERSION_GRAPH.is_superseded(p))
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .filter_map(|p| Some(InvoiceRecord::from_text(&collection.name, p, &get_cached_content(p).ok()?)))
+        .collect()
+}
+
+/// Invoice records of the named collection, or of every collection with VAT checks
+pub fn invoice_records(collection: Option<&str>, include_superseded: bool) -> Vec<InvoiceRecord> {
+    collections()
+        .iter()
+        .filter(|c| match collection {
+            Some(name) => c.matches(name),
+            None => c.vat_check,
+        })
+        .flat_map(|c| collection_records(c, include_superseded))
+        .collect()
+}
+
+/// Days since 1970-01-01 for an ISO date, to measure distances between dates
+pub fn day_number(date: &str) -> Option<i64> {
+    let year: i64 = date.get(..4)?.parse().ok()?;
+    let month: i64 = date.get(5..7)?.parse().ok()?;
+    let day: i64 = date.get(8..10)?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // Days-from-civil: March-based years put the leap day at the end
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146_097 + day_of_era - 719_468)
+}
@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Output profiles: role-based views of the answers. A profile lists the
+// answer fields it may see; everything else is removed from the structured
+// answer after the model has run, so no prompt wording can leak it. Amounts
+// in allowed fields can be rounded or hidden, and the sections that repeat
+// document values (verification, aggregation, provenance...) are dropped.
+
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::ApiResponse;
+
+/// How amounts in allowed fields are shown
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AmountView {
+    #[default]
+    Full,
+    /// One significant digit (8866.50 → 9000)
+    Rounded,
+    /// Removed like a field outside the allowlist
+    Hidden,
+}
+
+/// `[profiles.<name>]` in the config file
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct OutputProfile {
+    /// Answer fields shown, as dotted paths ("vendor", "line_items.description");
+    /// a path allows everything below it. Array elements need no index.
+    pub allow: Vec<String>,
+    pub amounts: AmountView,
+}
+
+fn allowed(allow: &[String], path: &str) -> bool {
+    path == "status"
+        || allow.iter().any(|a| {
+            a == "*" || path == a || path.starts_with(&format!("{}.", a)) || a.starts_with(&format!("{}.", path))
+        })
+}
+
+/// Below an allowed path itself (not just on the way to one)
+fn fully_allowed(allow: &[String], path: &str) -> bool {
+    path == "status" || allow.iter().any(|a| a == "*" || path == a || path.starts_with(&format!("{}.", a)))
+}
+
+fn is_amount(value: &Value) -> bool {
+    match value {
+        Value::Number(_) => true,
+        Value::String(s) => {
+            let digits = s.chars().filter(char::is_ascii_digit).count();
+            digits > 0 && s.chars().all(|c| c.is_ascii_digit() || " .,-+()$€£R".contains(c)) && s.contains(['.', ','])
+        }
+        _ => false,
+    }
+}
+
+fn round_amount(value: &Value) -> Value {
+    let number = match value {
+        Value::Number(n) => n.as_f64().and_then(Decimal::from_f64),
+        Value::String(s) => crate::vat::parse_amount(s),
+        _ => None,
+    };
+    let Some(number) = number.filter(|n| !n.is_zero()) else { return value.clone() };
+    let magnitude = number.abs().to_f64().unwrap_or_default().log10().floor() as i32;
+    let rounded = (number.to_f64().unwrap_or_default() / 10f64.powi(magnitude)).round() * 10f64.powi(magnitude);
+    Value::String(format!("≈{}", rounded))
+}
+
+impl OutputProfile {
+    fn filter(&self, value: &mut Value, path: &str, removed: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                let fields = std::mem::take(map);
+                let mut kept = Map::new();
+                for (key, mut child) in fields {
+                    let child_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                    if !allowed(&self.allow, &child_path) {
+                        removed.push(child_path);
+                        continue;
+                    }
+                    self.filter(&mut child, &child_path, removed);
+                    if self.amounts == AmountView::Hidden && is_amount(&child) {
+                        removed.push(child_path);
+                        continue;
+                    }
+                    kept.insert(key, child);
+                }
+                *map = kept;
+            }
+            Value::Array(items) => {
+                for item in items.iter_mut() {
+                    self.filter(item, path, removed);
+                }
+                if self.amounts == AmountView::Hidden {
+                    items.retain(|item| !is_amount(item));
+                }
+            }
+            _ if !fully_allowed(&self.allow, path) => {
+                *value = Value::Null;
+                removed.push(path.to_string());
+            }
+            _ if self.amounts == AmountView::Rounded && is_amount(value) => *value = round_amount(value),
+            _ => {}
+        }
+    }
+
+    /// Remove the answer fields the profile may not see. Returns their paths.
+    pub fn apply_to_answer(&self, answer: &mut Value) -> Vec<String> {
+        let mut removed = Vec::new();
+        self.filter(answer, "", &mut removed);
+        removed.sort();
+        removed.dedup();
+        removed
+    }
+
+    /// Remove everything the profile may not see. Returns the removed answer paths.
+    pub fn apply(&self, response: &mut ApiResponse) -> Vec<String> {
+        let removed = self.apply_to_answer(&mut response.answer);
+
+        response.verification = None;
+        response.consistency = None;
+        response.aggregation = None;
+        response.provenance = None;
+        removed
+    }
+}
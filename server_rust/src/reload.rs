@@ -0,0 +1,152 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Hot reload for `serve`: the config file, prompt templates and schema files
+// are polled for changes and re-applied without a restart, so in-flight
+// requests and the model's keep-alive survive. A new config is validated in
+// full (file parses, templates and schemas load, named profiles exist) before
+// it replaces the current one; on any error the running config stays.
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use crate::collections::{collections, collections_from_config, find_collection, replace_collections};
+use crate::config::{Config, DEFAULT_CONFIG_FILE};
+use crate::domain::find_domain;
+use crate::audit;
+use crate::locale::locale;
+use crate::quotas;
+use crate::Args;
+
+/// How often the watched files are checked
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Effective command-line settings and config, replaced as a whole on reload
+pub struct LiveConfig {
+    /// The command line as given, before the config file's defaults were applied
+    cli: Args,
+    current: RwLock<(Arc<Args>, Arc<Config>)>,
+}
+
+impl LiveConfig {
+    pub fn new(cli: Args, config: Config) -> Self {
+        let mut args = cli.clone();
+        args.apply_config(&config);
+        Self { cli, current: RwLock::new((Arc::new(args), Arc::new(config))) }
+    }
+
+    /// The settings in force now
+    pub fn get(&self) -> (Arc<Args>, Arc<Config>) {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Files whose changes trigger a reload
+    pub fn watched_files(&self) -> Vec<PathBuf> {
+        let (_, config) = self.get();
+        let mut files: Vec<PathBuf> = self.cli.config.iter().cloned().collect();
+        if files.is_empty() {
+            files.push(PathBuf::from(DEFAULT_CONFIG_FILE));
+        }
+        let profile_collections = config.profile.values().flat_map(|p| p.collections.iter());
+        files.extend(config.collections.iter().chain(profile_collections).filter_map(|c| c.template.clone()));
+        files.extend(config.schemas.values().cloned());
+        files
+    }
+
+    /// Load and check the config again and switch to it. Returns the changed
+    /// settings that only take effect after a restart; on error nothing changes.
+    pub fn reload(&self) -> Result<Vec<String>> {
+        let config = Config::load(self.cli.config.as_deref(), self.cli.profile.as_deref())?;
+        let new_collections = collections_from_config(&config)?;
+        for name in config.schemas.keys() {
+            config.schema(name)?;
+        }
+        let mut args = self.cli.clone();
+        args.apply_config(&config);
+        if let Some(name) = &args.schema {
+            config.schema(name)?;
+        }
+        if let Some(name) = &args.domain {
+            find_domain(&config, name)?;
+        }
+        if let Some(name) = &args.output_profile {
+            config.output_profile(name)?;
+        }
+        if let Some(tag) = &args.locale {
+            locale(tag)?;
+        }
+        if let Some(name) = &args.collection
+            && !new_collections.iter().any(|c| c.matches(name))
+        {
+            anyhow::bail!("Unknown collection '{}'", name);
+        }
+
+        let (_, old) = self.get();
+        let mut restart = Vec::new();
+        if config.ollama_url != old.ollama_url {
+            restart.push("ollama_url".to_string());
+        }
+        if config.ollama != old.ollama {
+            restart.push("[ollama]".to_string());
+        }
+        if format!("{:?}", config.encryption) != format!("{:?}", old.encryption) {
+            restart.push("[encryption]".to_string());
+        }
+        if format!("{:?}", config.reading) != format!("{:?}", old.reading) {
+            restart.push("[reading]".to_string());
+        }
+        if config.store != old.store {
+            restart.push("[store]".to_string());
+        }
+        if config.guardrail != old.guardrail {
+            restart.push("[guardrail]".to_string());
+        }
+        // The search index covers the folders the server started with
+        for collection in &new_collections {
+            if find_collection(&collection.name).is_none_or(|c| c.folder != collection.folder) {
+                restart.push(format!("folder of collection '{}'", collection.name));
+            }
+        }
+
+        replace_collections(new_collections);
+        quotas::set_quotas(&config);
+        *self.current.write().unwrap() = (Arc::new(args), Arc::new(config));
+        let file = self.cli.config.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE));
+        audit::record_config(&audit::local_actor(), &file);
+        Ok(restart)
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Poll the watched files and reload on every change (runs until the server stops)
+pub async fn watch(live: Arc<LiveConfig>) {
+    let stamps = |files: &[PathBuf]| -> Vec<(PathBuf, Option<SystemTime>)> {
+        files.iter().map(|f| (f.clone(), modified(f))).collect()
+    };
+    let mut last = stamps(&live.watched_files());
+    loop {
+        tokio::time::sleep(RELOAD_INTERVAL).await;
+        let now = stamps(&live.watched_files());
+        if now == last {
+            continue;
+        }
+        match live.reload() {
+            Ok(restart) => {
+                println!("Configuration reloaded ({} collections)", collections().len());
+                if !restart.is_empty() {
+                    eprintln!("WARNING: changes to {} take effect after a restart", restart.join(", "));
+                }
+                // The reloaded config may name other templates or schemas
+                last = stamps(&live.watched_files());
+            }
+            Err(e) => {
+                eprintln!("ERROR: config reload failed, keeping the current configuration: {:#}", e);
+                last = now;
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Several `serve` processes, on as many hosts, answering from one shared
+// store (`[store] backend = "postgres"`) behind a load balancer. A server
+// keeps nothing between requests that another could need, except chat
+// sessions (route a session to one server, e.g. by cookie or source address).
+// Writes are serialized in the store: `index` holds the index lock as a
+// Postgres advisory lock, so one run at a time writes, wherever it runs;
+// tags, removals and quota usage are read, changed and saved in one
+// transaction. Each server loads the index when it starts; the one saved
+// later by `index` is noticed here and, with --restart-on-index, the server
+// stops gracefully and exits with RESTART_EXIT_CODE, for its service manager
+// (Restart=on-failure, a container restart policy) to start it on the new one.
+// Document paths in the index must mean the same on every host (a shared
+// mount, or the same mirror of a remote collection).
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(feature = "server")]
+pub use follower::IndexFollower;
+
+/// Exit code of a server stopped for a newer index (EX_TEMPFAIL)
+pub const RESTART_EXIT_CODE: i32 = 75;
+
+static RESTARTING: AtomicBool = AtomicBool::new(false);
+
+/// The server was stopped to be restarted on a newer index
+pub fn restarting() -> bool {
+    RESTARTING.load(Ordering::SeqCst)
+}
+
+#[cfg(feature = "server")]
+mod follower {
+    use rocket::fairing::{Fairing, Info, Kind};
+    use rocket::{Orbit, Rocket};
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use super::RESTARTING;
+    use crate::lock::LockMode;
+    use crate::store::{self, store};
+
+    /// How often the store is asked for a newer index
+    const POLL: Duration = Duration::from_secs(30);
+
+    /// An `index` run is under way (its checkpoints are not worth a restart)
+    fn being_written() -> bool {
+        match store().try_lock_index(LockMode::Shared) {
+            Ok(Some(true)) => {
+                let _ = store().unlock_index(LockMode::Shared);
+                false
+            }
+            Ok(Some(false)) => true,
+            _ => false,
+        }
+    }
+
+    /// Watches a shared store for an index newer than the one being served
+    pub struct IndexFollower {
+        /// Stop the server (gracefully) when there is one
+        pub restart: bool,
+    }
+
+    #[rocket::async_trait]
+    impl Fairing for IndexFollower {
+        fn info(&self) -> Info {
+            Info { name: "Follow the shared index", kind: Kind::Liftoff }
+        }
+
+        async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+            if !store().shared() {
+                return;
+            }
+            let loaded = store().updated(store::INDEX).ok().flatten();
+            let shutdown = rocket.shutdown();
+            let restart = self.restart;
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(POLL).await;
+                    match store().updated(store::INDEX) {
+                        Ok(saved) if saved != loaded && !being_written() => {
+                            if restart {
+                                println!("A newer index was saved in {}; restarting on it", store().describe());
+                                RESTARTING.store(true, Ordering::SeqCst);
+                                shutdown.notify();
+                            } else {
+                                let from = store().describe();
+                                println!("A newer index was saved in {}; restart the server to answer from it", from);
+                            }
+                            return;
+                        }
+                        Ok(_) => {}
+                        Err(e) => eprintln!("WARNING: cannot check the shared index: {:#}", e),
+                    }
+                }
+            });
+        }
+    }
+}
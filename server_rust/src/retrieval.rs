@@ -1,62 +1,359 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::collections::HashSet;
-use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
 
-use crate::Category;
-use crate::indexer::INVERTED_INDEX;
+use crate::Collection;
+use crate::docid::is_text_file;
+#[cfg(feature = "embeddings")]
+use crate::embeddings::EMBEDDING_INDEX;
+use crate::events::EventSink;
+use crate::indexer::{identifier_index, query_identifiers, tenant_index, words};
+use crate::metadata::is_hidden;
+use crate::scoring::{rank_with, Bm25Scorer, FilenameScorer};
 
-pub fn find_relevant_files(query: &str, category: &Category) -> Vec<PathBuf> {
-    let base_dir = category.folder_path();
+/// Documents placed in the prompt per collection unless a query asks otherwise
+pub const DEFAULT_MAX_DOCS: usize = 4;
 
-    let lower_query = query.to_lowercase();
-    let query_words: HashSet<String> = lower_query
-        .split_whitespace()
-        .filter(|w| w.len() > 2)
-        .map(|w| w.to_string())
+/// Hard limit on documents per collection, whatever `--top-k` or the request asks for
+pub const MAX_TOP_K: usize = 20;
+
+/// Embedding model used unless the config names another
+pub const DEFAULT_EMBED_MODEL: &str = "nomic-embed-text";
+
+/// Where embeddings are computed
+#[derive(Serialize, Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbedBackend {
+    /// Ollama's /api/embed
+    #[default]
+    Ollama,
+    /// In this process with fastembed (ONNX), no Ollama needed
+    Local,
+}
+
+/// How `EmbeddingIndex::update` sends chunks to the model and indexes the vectors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbedOptions {
+    pub backend: EmbedBackend,
+    /// Inputs per /api/embed request
+    pub batch: usize,
+    /// Requests in flight at once
+    pub concurrency: usize,
+    /// Build approximate nearest-neighbour lists (for big indexes)
+    pub ann: bool,
+}
+
+impl Default for EmbedOptions {
+    fn default() -> Self {
+        Self { backend: EmbedBackend::Ollama, batch: 32, concurrency: 4, ann: true }
+    }
+}
+
+/// How documents are found for a question
+#[derive(Serialize, Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RetrievalMode {
+    /// Inverted-index word matches (no model needed)
+    #[default]
+    Keyword,
+    /// Cosine similarity of embeddings
+    Embedding,
+    /// Both, fused: exact ids from keywords, paraphrases from embeddings
+    Hybrid,
+    /// Okapi BM25 over the inverted index (see scoring.rs)
+    Bm25,
+    /// Query words in the file name
+    Filename,
+}
+
+impl RetrievalMode {
+    /// True if `index` must compute embeddings for this mode
+    pub fn uses_embeddings(self) -> bool {
+        matches!(self, Self::Embedding | Self::Hybrid)
+    }
+}
+
+/// How keyword and embedding rankings are combined in hybrid mode
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Fusion {
+    /// Reciprocal rank fusion: sum of weight / (rrf_k + rank)
+    #[default]
+    Rrf,
+    /// Weighted sum of scores normalised to 0..1
+    Weighted,
+}
+
+/// `[retrieval]` section of the config file
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RetrievalConfig {
+    pub mode: RetrievalMode,
+    pub fusion: Fusion,
+    pub keyword_weight: f32,
+    pub embedding_weight: f32,
+    pub rrf_k: f32,
+    pub embed_model: String,
+    /// Ollama, or `local` (in-process, feature `local-embeddings`)
+    pub embed_backend: EmbedBackend,
+    /// Chunks per embedding request, and requests in flight at once, when `index` embeds
+    pub embed_batch: usize,
+    pub embed_concurrency: usize,
+    /// Approximate nearest-neighbour lists for big indexes (a full scan otherwise)
+    pub ann: bool,
+    /// Warn when documents changed on disk after the index was built (a folder listing per question)
+    pub stale_check: bool,
+    /// `ask`: index the changed documents before answering, as with --reindex-stale
+    pub reindex_stale: bool,
+    /// Old file selection: a question naming the document type ("invoice") gets every file
+    pub legacy_matching: bool,
+}
+
+impl Default for RetrievalConfig {
+    fn default() -> Self {
+        Self {
+            mode: RetrievalMode::Keyword,
+            fusion: Fusion::Rrf,
+            keyword_weight: 1.0,
+            embedding_weight: 1.0,
+            rrf_k: 60.0,
+            embed_model: DEFAULT_EMBED_MODEL.to_string(),
+            embed_backend: EmbedBackend::Ollama,
+            embed_batch: EmbedOptions::default().batch,
+            embed_concurrency: EmbedOptions::default().concurrency,
+            ann: EmbedOptions::default().ann,
+            stale_check: true,
+            reindex_stale: false,
+            legacy_matching: false,
+        }
+    }
+}
+
+impl RetrievalConfig {
+    pub fn embed_options(&self) -> EmbedOptions {
+        EmbedOptions {
+            backend: self.embed_backend,
+            batch: self.embed_batch,
+            concurrency: self.embed_concurrency,
+            ann: self.ann,
+        }
+    }
+}
+
+pub fn find_relevant_files(query: &str, collection: &Collection) -> Vec<PathBuf> {
+    rank_files(query, collection).into_iter().map(|(path, _)| path).collect()
+}
+
+/// Top matching files with their scores (number of matching query words)
+pub fn rank_files(query: &str, collection: &Collection) -> Vec<(PathBuf, usize)> {
+    rank_files_top(query, collection, DEFAULT_MAX_DOCS)
+}
+
+/// Score given to documents selected by an exact identifier
+pub const EXACT_MATCH_SCORE: usize = usize::MAX;
+
+/// Documents in the collection containing an identifier (invoice/PO number, IBAN) named in the query
+pub fn identifier_matches(query: &str, collection: &Collection) -> Vec<PathBuf> {
+    let index = identifier_index(collection.tenant.as_deref());
+    let mut paths: Vec<PathBuf> = query_identifiers(query)
+        .iter()
+        .filter_map(|id| index.get(id))
+        .flatten()
+        .filter(|p| p.starts_with(&collection.folder))
+        .cloned()
+        .collect();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Like `rank_files`, returning at most `max_results` files.
+/// Exact identifiers skip ranking: the documents containing them are selected directly.
+pub fn rank_files_top(query: &str, collection: &Collection, max_results: usize) -> Vec<(PathBuf, usize)> {
+    let exact = identifier_matches(query, collection);
+    if !exact.is_empty() {
+        return exact
+            .into_iter()
+            .take(max_results)
+            .filter(|path| path.exists())
+            .map(|path| (path, EXACT_MATCH_SCORE))
+            .collect();
+    }
+
+    keyword_scores(query, collection)
+        .into_iter()
+        .take(max_results)
+        .filter(|(path, _)| path.exists())
+        .collect()
+}
+
+/// Documents in the collection folder (removed and archived ones left out)
+pub(crate) fn collection_documents(collection: &Collection) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(&collection.folder) else { return Vec::new() };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| is_text_file(p) && !is_hidden(p))
         .collect();
+    paths.sort();
+    paths
+}
 
+/// Every file in the collection matching at least one query word, best first.
+/// The score is the number of distinct query words found in the document; words
+/// found in every document of the collection ("invoice" among invoices) don't count
+/// unless nothing else matches.
+fn keyword_scores(query: &str, collection: &Collection) -> Vec<(PathBuf, usize)> {
+    let base_dir = &collection.folder;
+
+    let query_words: Vec<String> = words(query).into_iter().filter(|w| w.len() > 2).collect();
     if query_words.is_empty() {
         return vec![];
     }
 
-    // Collect candidates + count matches
-    let mut scored_files: Vec<(String, usize)> = Vec::new();
+    // Documents of this collection containing each query word
+    let index = tenant_index(collection.tenant.as_deref());
+    let matches: Vec<Vec<&PathBuf>> = query_words
+        .iter()
+        .filter_map(|w| index.get(w))
+        .map(|paths| paths.iter().filter(|p| p.starts_with(base_dir)).collect::<Vec<_>>())
+        .filter(|paths| !paths.is_empty())
+        .collect();
 
-    for (word, filenames) in INVERTED_INDEX.iter() {
-        if query_words.contains(word) {
-            for fname in filenames {
-                // Find or insert with score
-                if let Some((_, score)) = scored_files.iter_mut().find(|(f, _)| f == fname) {
-                    *score += 1;
-                } else {
-                    scored_files.push((fname.clone(), 1));
-                }
-            }
-        }
+    let size = collection_documents(collection).len();
+    let discriminating: Vec<&Vec<&PathBuf>> = matches.iter().filter(|paths| paths.len() < size).collect();
+    let counted = if discriminating.is_empty() { matches.iter().collect() } else { discriminating };
+
+    let mut scores: HashMap<&PathBuf, usize> = HashMap::new();
+    for path in counted.into_iter().flatten().copied() {
+        *scores.entry(path).or_default() += 1;
     }
 
     // Sort: highest score first, then stable by filename
+    let mut scored_files: Vec<(PathBuf, usize)> = scores.into_iter().map(|(p, s)| (p.clone(), s)).collect();
     scored_files.sort_by(|a, b| {
         b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0))
     });
+    scored_files
+}
 
-    // Take top N
-    let max_results = 4;
-    let top_fnames: Vec<String> = scored_files
+/// The old selection (`--legacy-matching`): a question mentioning the collection's
+/// document type ("invoice", "contracts", an alias) selects every file, in name order.
+/// None when the question names no type (keyword ranking is used instead).
+fn legacy_matches(query: &str, collection: &Collection, max_results: usize) -> Option<Vec<(PathBuf, f32)>> {
+    let names: Vec<String> = [&collection.name, &collection.display_name]
         .into_iter()
-        .take(max_results)
-        .map(|(f, score)| {
-            println!("Selected: {} (score: {})", f, score); // debug
-            f
-        })
+        .chain(&collection.aliases)
+        .map(|n| n.to_lowercase())
         .collect();
+    let lower_query = query.to_lowercase();
+    let named = names.iter().any(|n| lower_query.contains(n.as_str()) || lower_query.contains(n.trim_end_matches('s')));
+    if !named {
+        return None;
+    }
+    Some(collection_documents(collection).into_iter().take(max_results).map(|p| (p, 1.0)).collect())
+}
 
-    top_fnames
-        .into_iter()
-        .filter_map(|fname| {
-            let path = Path::new(base_dir).join(&fname);
-            path.exists().then_some(path.to_path_buf())
-        })
-        .collect()
-}
\ No newline at end of file
+/// Top `max_results` files for `query` using the configured retrieval mode.
+/// Embedding failures (Ollama down, no embeddings yet) fall back to keyword ranking,
+/// with a warning to `events`.
+pub async fn retrieve(
+    query: &str,
+    collection: &Collection,
+    max_results: usize,
+    config: &RetrievalConfig,
+    events: &dyn EventSink,
+) -> Vec<(PathBuf, f32)> {
+    let keyword = || -> Vec<(PathBuf, f32)> {
+        rank_files_top(query, collection, max_results)
+            .into_iter()
+            .map(|(p, s)| (p, s as f32))
+            .collect()
+    };
+    if config.legacy_matching && identifier_matches(query, collection).is_empty()
+        && let Some(all) = legacy_matches(query, collection, max_results)
+    {
+        return all;
+    }
+    match config.mode {
+        RetrievalMode::Bm25 => return rank_with(&Bm25Scorer::default(), query, collection, max_results),
+        RetrievalMode::Filename => return rank_with(&FilenameScorer, query, collection, max_results),
+        _ => {}
+    }
+    if config.mode == RetrievalMode::Keyword || !identifier_matches(query, collection).is_empty() {
+        return keyword();
+    }
+
+    match vector_ranking(query, collection, max_results, config).await {
+        Ok(ranked) => ranked,
+        Err(e) => {
+            events.on_warning(&format!("Vector retrieval unavailable ({:#}); using keyword matching", e));
+            keyword()
+        }
+    }
+}
+
+/// Embedding (or hybrid) ranking of the collection for `query`, best first
+#[cfg(feature = "embeddings")]
+async fn vector_ranking(
+    query: &str,
+    collection: &Collection,
+    max_results: usize,
+    config: &RetrievalConfig,
+) -> anyhow::Result<Vec<(PathBuf, f32)>> {
+    let vector = EMBEDDING_INDEX.rank(query, collection).await?;
+    let mut ranked = match config.mode {
+        RetrievalMode::Hybrid => {
+            let lexical: Vec<(PathBuf, f32)> = keyword_scores(query, collection)
+                .into_iter()
+                .map(|(p, s)| (p, s as f32))
+                .collect();
+            fuse(&lexical, &vector, config)
+        }
+        _ => vector,
+    };
+    ranked.truncate(max_results);
+    ranked.retain(|(path, _)| path.exists());
+    Ok(ranked)
+}
+
+#[cfg(not(feature = "embeddings"))]
+async fn vector_ranking(
+    _query: &str,
+    _collection: &Collection,
+    _max_results: usize,
+    _config: &RetrievalConfig,
+) -> anyhow::Result<Vec<(PathBuf, f32)>> {
+    anyhow::bail!("not in this build; enable the `embeddings` feature")
+}
+
+/// Combine two rankings (each sorted best first) into one
+pub fn fuse(keyword: &[(PathBuf, f32)], vector: &[(PathBuf, f32)], config: &RetrievalConfig) -> Vec<(PathBuf, f32)> {
+    let mut combined: HashMap<PathBuf, f32> = HashMap::new();
+
+    for (ranking, weight) in [(keyword, config.keyword_weight), (vector, config.embedding_weight)] {
+        match config.fusion {
+            Fusion::Rrf => {
+                for (rank, (path, _)) in ranking.iter().enumerate() {
+                    *combined.entry(path.clone()).or_default() += weight / (config.rrf_k + rank as f32 + 1.0);
+                }
+            }
+            Fusion::Weighted => {
+                let (min, max) = ranking
+                    .iter()
+                    .fold((f32::MAX, f32::MIN), |(lo, hi), (_, s)| (lo.min(*s), hi.max(*s)));
+                for (path, score) in ranking {
+                    let normalised = if max > min { (score - min) / (max - min) } else { 1.0 };
+                    *combined.entry(path.clone()).or_default() += weight * normalised;
+                }
+            }
+        }
+    }
+
+    let mut fused: Vec<(PathBuf, f32)> = combined.into_iter().collect();
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    fused
+}
@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Model routing (`[routing]`): simple lookups go to a small, fast model and
+// questions that need reasoning over several documents to a larger one. The
+// question is classified first, by heuristics (several collections, several
+// questions in one, comparison or explanation words, length) or by asking a
+// tiny model; then the first `[[routing.rule]]` matching the class (and the
+// collection, if the rule names one) picks the model. Without a matching rule
+// the configured model answers.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::ai::{OllamaRequest, OllamaResponse};
+use crate::collections::find_collection;
+use crate::decompose::split_question;
+use crate::hosts;
+use crate::json_repair::parse_lenient;
+
+/// Questions longer than this count as complex
+const COMPLEX_WORDS: usize = 30;
+
+static REASONING_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"(?i)\b(compare[ds]?|comparison|differ(s|ence|ences)?|why|explain|trends?|versus|vs\.?|across|between",
+        r"|summari[sz]e|analy[sz]e|reconcile|overall)\b"
+    ))
+    .unwrap()
+});
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Complexity {
+    /// One fact from one document
+    Simple,
+    /// Reasoning over several documents or steps
+    Complex,
+}
+
+impl Complexity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Complexity::Simple => "simple",
+            Complexity::Complex => "complex",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Classifier {
+    #[default]
+    Heuristic,
+    /// Ask `classifier_model`; the heuristics decide when it cannot be reached
+    Model,
+}
+
+/// `[[routing.rule]]`
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RoutingRule {
+    /// Class of question the rule applies to; any when not given
+    pub complexity: Option<Complexity>,
+    /// Collection the rule applies to; any when not given
+    pub collection: Option<String>,
+    pub model: String,
+}
+
+/// `[routing]` section of the config file
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct RoutingConfig {
+    pub classifier: Classifier,
+    /// Small model for `classifier = "model"`
+    pub classifier_model: Option<String>,
+    #[serde(rename = "rule")]
+    pub rules: Vec<RoutingRule>,
+}
+
+/// Classify by the shape of the question alone
+pub fn classify(question: &str, collections: usize) -> Complexity {
+    let complex = collections > 1
+        || split_question(question).len() > 1
+        || REASONING_RE.is_match(question)
+        || question.split_whitespace().count() > COMPLEX_WORDS;
+    if complex { Complexity::Complex } else { Complexity::Simple }
+}
+
+/// Ask a small model for the class; None if it gives no usable answer
+async fn classify_with_model(model: &str, question: &str) -> Option<Complexity> {
+    let prompt = format!(
+        "Classify this question for a document assistant. Reply with JSON only: {{\"complexity\": \"simple\"}} if it looks up \
+         one fact in one document, {{\"complexity\": \"complex\"}} if it needs comparing, adding up or reasoning over several \
+         documents or steps.\n\nQuestion: {}",
+        question
+    );
+    let request = OllamaRequest { model: model.to_string(), prompt, stream: false, format: "json".to_string(), options: None };
+    let (res, _lease) = hosts::post(&hosts::client(), model, "/api/generate", &request).await.ok()?;
+    let reply: OllamaResponse = res.json().await.ok()?;
+    let value: Value = parse_lenient(&reply.response)?;
+    serde_json::from_value(value.get("complexity")?.clone()).ok()
+}
+
+impl RoutingConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    pub async fn complexity(&self, question: &str, collections: usize) -> Complexity {
+        if let (Classifier::Model, Some(model)) = (self.classifier, &self.classifier_model) {
+            match classify_with_model(model, question).await {
+                Some(complexity) => return complexity,
+                None => eprintln!("WARNING: classifier model '{}' gave no answer; using the heuristics", model),
+            }
+        }
+        classify(question, collections)
+    }
+
+    /// The model for this question: the first matching rule's, else `default`
+    pub async fn route(&self, question: &str, collections: &[String], default: &str) -> String {
+        let complexity = self.complexity(question, collections.len()).await;
+        let rule = self.rules.iter().find(|rule| {
+            rule.complexity.is_none_or(|c| c == complexity)
+                && rule.collection.as_deref().is_none_or(|name| {
+                    // A tenant's copy of a collection counts as the collection
+                    collections.iter().filter_map(|c| find_collection(c)).any(|c| c.base_name().eq_ignore_ascii_case(name))
+                })
+        });
+        let model = rule.map_or(default, |rule| rule.model.as_str());
+        eprintln!("Routed a {} question to {}", complexity.as_str(), model); // debug
+        model.to_string()
+    }
+}
@@ -0,0 +1,300 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Business rules of your own, as scripts: a `[[rule]]` is a Rhai script
+// (`rhai` feature) or a WebAssembly module (`wasm` feature) run once per
+// invoice over what was extracted from it. Rules say what the built-in checks
+// cannot, e.g. "PO numbers match ^PO-\d{6}$" or "ACME invoices reference
+// contract C-77". `check-rules` reports the violations per invoice.
+//
+// Each rule sees the invoice as JSON: the record fields (collection, file, id,
+// kind, vendor, date, currency, net, tax, gross as numbers, status), the
+// document numbers found in the text (`identifiers`), the tags, the corrected
+// extraction from `verify` (`extraction`, or null) and the `text` itself.
+//
+// A Rhai script gets it as `invoice` and returns nothing or true (passed),
+// false (failed), a message, or an array of messages. `matches(text, pattern)`
+// tests a regular expression:
+//
+//     let po = invoice.identifiers.filter(|id| id.starts_with("PO-"));
+//     po.filter(|id| !matches(id, "^PO-\\d{6}$")).map(|id| `${id} is not PO- and six digits`)
+//
+// A WebAssembly module exports `memory`, `alloc(len: i32) -> i32` and
+// `validate(ptr: i32, len: i32) -> i64`: the invoice JSON is written to the
+// memory `alloc` returns, and `validate` returns where its answer is (pointer
+// in the high 32 bits, length in the low ones), a JSON array of messages.
+
+use anyhow::{Context, Result};
+use rust_decimal::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+use crate::get_cached_content;
+use crate::indexer::document_identifiers;
+use crate::metadata::Metadata;
+use crate::records::InvoiceRecord;
+
+/// `[[rule]]` in doc-ai.toml
+#[derive(Deserialize, Debug, Clone)]
+pub struct RuleConfig {
+    /// Shown with each violation
+    pub name: String,
+    /// A Rhai script (.rhai) or a WebAssembly module (.wasm)
+    pub path: PathBuf,
+    /// Collections the rule applies to (every collection when empty)
+    #[serde(default)]
+    pub collections: Vec<String>,
+}
+
+/// A validator over one invoice
+pub trait Rule: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Messages for what the invoice breaks; empty when it passes.
+    /// An error means the rule itself could not run.
+    fn check(&self, invoice: &Value) -> Result<Vec<String>>;
+}
+
+/// A loaded rule with the collections it is for
+pub struct LoadedRule {
+    pub rule: Box<dyn Rule>,
+    pub collections: Vec<String>,
+}
+
+impl LoadedRule {
+    pub fn applies_to(&self, collection: &str) -> bool {
+        self.collections.is_empty() || self.collections.iter().any(|c| c.eq_ignore_ascii_case(collection))
+    }
+}
+
+/// Load every `[[rule]]`, by file extension
+pub fn load_rules(configs: &[RuleConfig]) -> Result<Vec<LoadedRule>> {
+    configs
+        .iter()
+        .map(|config| {
+            let rule = load_rule(&config.name, &config.path)
+                .with_context(|| format!("Failed to load rule '{}' from {}", config.name, config.path.display()))?;
+            Ok(LoadedRule { rule, collections: config.collections.clone() })
+        })
+        .collect()
+}
+
+#[cfg_attr(not(any(feature = "rhai", feature = "wasm")), allow(unused_variables))]
+fn load_rule(name: &str, path: &Path) -> Result<Box<dyn Rule>> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+    match extension.as_str() {
+        #[cfg(feature = "rhai")]
+        "rhai" => Ok(Box::new(RhaiRule::new(name, &std::fs::read_to_string(path)?)?)),
+        #[cfg(not(feature = "rhai"))]
+        "rhai" => anyhow::bail!("Rhai rules need doc-ai-server built with the `rhai` feature"),
+        #[cfg(feature = "wasm")]
+        "wasm" | "wat" => Ok(Box::new(WasmRule::new(name, &std::fs::read(path)?)?)),
+        #[cfg(not(feature = "wasm"))]
+        "wasm" | "wat" => anyhow::bail!("WebAssembly rules need doc-ai-server built with the `wasm` feature"),
+        _ => anyhow::bail!("Unknown rule type '.{}'. Valid values: .rhai, .wasm", extension),
+    }
+}
+
+/// What a rule sees of an invoice
+pub fn rule_input(record: &InvoiceRecord, text: &str, metadata: &Metadata) -> Value {
+    let mut input = serde_json::to_value(record).unwrap_or_else(|_| json!({}));
+    // Numbers, not the decimal strings of the record, so scripts can compare them
+    for (field, amount) in [("net", record.net), ("tax", record.tax), ("gross", record.gross)] {
+        input[field] = json!(amount.and_then(|a| a.to_f64()));
+    }
+    let meta = metadata.get(&record.path);
+    input["identifiers"] = json!(document_identifiers(text));
+    input["tags"] = json!(meta.map(|m| m.tags.iter().collect::<Vec<_>>()).unwrap_or_default());
+    input["extraction"] = meta.and_then(|m| m.verified.as_ref()).map(|v| v.answer.clone()).unwrap_or(Value::Null);
+    input["text"] = json!(text);
+    input
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub rule: String,
+    pub message: String,
+}
+
+/// The violations of one invoice
+#[derive(Serialize, Debug, Clone)]
+pub struct InvoiceViolations {
+    pub collection: String,
+    pub file: String,
+    pub id: Option<String>,
+    pub violations: Vec<Violation>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RulesReport {
+    pub invoices: usize,
+    pub rules: usize,
+    /// Invoices with at least one violation, in record order
+    pub failed: Vec<InvoiceViolations>,
+}
+
+/// Run the rules over the records. A rule that cannot run on an invoice is
+/// reported as a violation of it, so a broken script does not pass silently.
+pub fn check_rules(records: &[InvoiceRecord], rules: &[LoadedRule], metadata: &Metadata) -> RulesReport {
+    let mut report = RulesReport { invoices: records.len(), rules: rules.len(), failed: Vec::new() };
+    for record in records {
+        let applicable: Vec<&LoadedRule> = rules.iter().filter(|r| r.applies_to(&record.collection)).collect();
+        if applicable.is_empty() {
+            continue;
+        }
+        let text = get_cached_content(&record.path).unwrap_or_default();
+        let input = rule_input(record, &text, metadata);
+        let violations: Vec<Violation> = applicable
+            .iter()
+            .flat_map(|loaded| {
+                let rule = loaded.rule.name().to_string();
+                let messages = loaded.rule.check(&input).unwrap_or_else(|e| vec![format!("rule failed: {:#}", e)]);
+                messages.into_iter().map(move |message| Violation { rule: rule.clone(), message })
+            })
+            .collect();
+        if !violations.is_empty() {
+            report.failed.push(InvoiceViolations {
+                collection: record.collection.clone(),
+                file: record.file.clone(),
+                id: record.id.clone(),
+                violations,
+            });
+        }
+    }
+    report
+}
+
+impl RulesReport {
+    pub fn passed(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// One block per failing invoice
+    pub fn to_table(&self) -> String {
+        let mut lines = Vec::new();
+        for invoice in &self.failed {
+            let id = invoice.id.as_deref().map(|id| format!(" ({})", id)).unwrap_or_default();
+            lines.push(format!("{}/{}{}", invoice.collection, invoice.file, id));
+            lines.extend(invoice.violations.iter().map(|v| format!("    [{}] {}", v.rule, v.message)));
+        }
+        lines.push(format!(
+            "{} rule(s) over {} invoice(s): {} with violations",
+            self.rules,
+            self.invoices,
+            self.failed.len()
+        ));
+        lines.join("\n")
+    }
+}
+
+#[cfg(feature = "rhai")]
+pub use rhai_rules::RhaiRule;
+
+#[cfg(feature = "rhai")]
+mod rhai_rules {
+    use super::*;
+    use regex::Regex;
+    use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+
+    /// Upper bound on the work one script does per invoice, against endless loops
+    const MAX_OPERATIONS: u64 = 1_000_000;
+
+    pub struct RhaiRule {
+        name: String,
+        engine: Engine,
+        ast: AST,
+    }
+
+    impl RhaiRule {
+        pub fn new(name: &str, script: &str) -> Result<Self> {
+            let mut engine = Engine::new();
+            engine.set_max_operations(MAX_OPERATIONS);
+            engine.register_fn("matches", |text: &str, pattern: &str| -> Result<bool, Box<EvalAltResult>> {
+                let re = Regex::new(pattern).map_err(|e| e.to_string())?;
+                Ok(re.is_match(text))
+            });
+            let ast = engine.compile(script).map_err(|e| anyhow::anyhow!("{}", e))?;
+            Ok(Self { name: name.to_string(), engine, ast })
+        }
+    }
+
+    impl Rule for RhaiRule {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn check(&self, invoice: &Value) -> Result<Vec<String>> {
+            let mut scope = Scope::new();
+            let invoice: Dynamic = rhai::serde::to_dynamic(invoice).map_err(|e| anyhow::anyhow!("{}", e))?;
+            scope.push("invoice", invoice);
+            let result: Dynamic =
+                self.engine.eval_ast_with_scope(&mut scope, &self.ast).map_err(|e| anyhow::anyhow!("{}", e))?;
+            Ok(messages(&self.name, result))
+        }
+    }
+
+    fn messages(name: &str, result: Dynamic) -> Vec<String> {
+        if result.is_unit() {
+            Vec::new()
+        } else if let Ok(passed) = result.as_bool() {
+            if passed { Vec::new() } else { vec![format!("{} failed", name)] }
+        } else if result.is_array() {
+            result.into_array().unwrap_or_default().into_iter().flat_map(|m| messages(name, m)).collect()
+        } else {
+            vec![result.to_string()]
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+pub use wasm_rules::WasmRule;
+
+#[cfg(feature = "wasm")]
+mod wasm_rules {
+    use super::*;
+    use wasmtime::{Config, Engine, Instance, Module, Store};
+
+    /// Fuel per invoice, against endless loops
+    const FUEL: u64 = 100_000_000;
+
+    pub struct WasmRule {
+        name: String,
+        engine: Engine,
+        module: Module,
+    }
+
+    impl WasmRule {
+        /// `bytes` is a compiled module or its text format
+        pub fn new(name: &str, bytes: &[u8]) -> Result<Self> {
+            let engine = Engine::new(Config::new().consume_fuel(true))?;
+            let module = Module::new(&engine, bytes)?;
+            Ok(Self { name: name.to_string(), engine, module })
+        }
+    }
+
+    impl Rule for WasmRule {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn check(&self, invoice: &Value) -> Result<Vec<String>> {
+            // A fresh instance per invoice: nothing carries over between them
+            let mut store = Store::new(&self.engine, ());
+            store.set_fuel(FUEL)?;
+            let instance = Instance::new(&mut store, &self.module, &[])?;
+            let memory = instance.get_memory(&mut store, "memory").context("The module exports no 'memory'")?;
+            let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+            let validate = instance.get_typed_func::<(i32, i32), i64>(&mut store, "validate")?;
+
+            let input = serde_json::to_vec(invoice)?;
+            let len = i32::try_from(input.len()).context("Invoice too large for the module")?;
+            let ptr = alloc.call(&mut store, len)?;
+            memory.write(&mut store, ptr as u32 as usize, &input)?;
+            let packed = validate.call(&mut store, (ptr, len))? as u64;
+
+            let mut output = vec![0; (packed & 0xffff_ffff) as usize];
+            memory.read(&store, (packed >> 32) as usize, &mut output)?;
+            serde_json::from_slice(&output).context("The module's answer is not a JSON array of messages")
+        }
+    }
+}
@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Self-consistency sampling: the same prompt is answered several times at a
+// small nonzero temperature. If the model really read a total from the
+// documents, every sample agrees; if it is guessing, the numbers scatter.
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::vat::parse_amount;
+
+/// Temperature used for each sample (0.0 would make all samples identical)
+pub const SAMPLING_TEMPERATURE: f32 = 0.3;
+
+/// Upper bound on samples per question (each one is a full model call)
+pub const MAX_SAMPLES: usize = 20;
+
+/// Spread of one numeric field across the samples
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FieldStats {
+    /// None if the two middle values are too large to average
+    pub median: Option<Decimal>,
+    pub min: Decimal,
+    pub max: Decimal,
+    /// (max - min) / |median|; 0 means every sample agreed. None if the samples
+    /// differ but the spread cannot be computed (a zero median, amounts too
+    /// large), which counts as disagreement
+    pub relative_spread: Option<Decimal>,
+    /// Number of samples that contained this field
+    pub present_in: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Consistency {
+    pub samples: usize,
+    /// Share of samples identical to the returned answer
+    pub agreement: f64,
+    /// True if any numeric field differs between samples
+    pub suspect: bool,
+    pub fields: BTreeMap<String, FieldStats>,
+}
+
+/// Collect every numeric leaf (numbers and money strings) keyed by its JSON path
+fn numeric_fields(value: &Value, path: String, out: &mut BTreeMap<String, Vec<Decimal>>) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map {
+                let child = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                numeric_fields(v, child, out);
+            }
+        }
+        Value::Array(items) => {
+            for (i, v) in items.iter().enumerate() {
+                numeric_fields(v, format!("{}[{}]", path, i), out);
+            }
+        }
+        Value::Number(n) => {
+            if let Ok(d) = n.to_string().parse::<Decimal>() {
+                out.entry(path).or_default().push(d);
+            }
+        }
+        Value::String(s) => {
+            if let Some(d) = parse_amount(s) {
+                out.entry(path).or_default().push(d);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn median(sorted: &[Decimal]) -> Option<Decimal> {
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        sorted[mid - 1].checked_add(sorted[mid])?.checked_div(Decimal::TWO)
+    } else {
+        Some(sorted[mid])
+    }
+}
+
+fn relative_spread(min: Decimal, max: Decimal, median: Option<Decimal>) -> Option<Decimal> {
+    let range = max.checked_sub(min)?;
+    if range.is_zero() {
+        return Some(Decimal::ZERO);
+    }
+    let median = median.filter(|m| !m.is_zero())?;
+    Some(range.checked_div(median.abs())?.round_dp(4))
+}
+
+/// Pick the majority answer and measure how much the numeric fields disagree
+pub fn summarize(answers: &[Value]) -> Option<(Value, Consistency)> {
+    if answers.is_empty() {
+        return None;
+    }
+
+    // Majority vote over the whole answer (first one wins ties)
+    let mut counts: Vec<(&Value, usize)> = Vec::new();
+    for answer in answers {
+        match counts.iter_mut().find(|(v, _)| *v == answer) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((answer, 1)),
+        }
+    }
+    let (majority, votes) = counts
+        .iter()
+        .fold(counts[0], |best, &c| if c.1 > best.1 { c } else { best });
+
+    let mut values: BTreeMap<String, Vec<Decimal>> = BTreeMap::new();
+    for answer in answers {
+        numeric_fields(answer, String::new(), &mut values);
+    }
+
+    let mut fields = BTreeMap::new();
+    for (path, mut v) in values {
+        v.sort();
+        let median = median(&v);
+        let (min, max) = (v[0], v[v.len() - 1]);
+        let relative_spread = relative_spread(min, max, median);
+        fields.insert(path, FieldStats { median, min, max, relative_spread, present_in: v.len() });
+    }
+
+    let consistency = Consistency {
+        samples: answers.len(),
+        agreement: votes as f64 / answers.len() as f64,
+        suspect: fields.values().any(|f| f.relative_spread != Some(Decimal::ZERO) || f.present_in != answers.len()),
+        fields,
+    };
+    Some((majority.clone(), consistency))
+}
@@ -0,0 +1,240 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Pluggable relevance scoring. A `RelevanceScorer` rates one document for a
+// query; `rank_with` applies it to a collection. The built-in scorers match
+// file names, rank by BM25, or compare embeddings (feature `embeddings`), and `Combined` mixes any
+// of them with weights. Pass your own with `QueryBuilder::scorer`.
+
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[cfg(feature = "embeddings")]
+use crate::embeddings::{embed_with, EMBEDDING_INDEX};
+use crate::fold::{contains_folded, fold_forms, folded_words};
+use crate::indexer::{tenant_index, tenant_key, word_counts, words, TENANT_INDEXES};
+use crate::retrieval::{collection_documents, identifier_matches, EXACT_MATCH_SCORE};
+use crate::{get_cached_content, Collection};
+
+pub trait RelevanceScorer: Send + Sync {
+    /// Short name, shown in logs
+    fn name(&self) -> &str;
+
+    /// How relevant `doc` is to `query`; 0 means not at all
+    fn score(&self, query: &str, doc: &Path) -> f32;
+
+    /// Documents worth scoring (by default every document of the collection)
+    fn candidates(&self, _query: &str, collection: &Collection) -> Vec<PathBuf> {
+        collection_documents(collection)
+    }
+}
+
+impl fmt::Debug for dyn RelevanceScorer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Query words that take part in scoring
+fn query_terms(query: &str) -> HashSet<String> {
+    words(query).into_iter().filter(|w| w.len() > 2).collect()
+}
+
+/// Matches query words against the file name ("march" finds `invoice_march.txt`)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FilenameScorer;
+
+impl RelevanceScorer for FilenameScorer {
+    fn name(&self) -> &str {
+        "filename"
+    }
+
+    /// Share of the query words found in the file name; 1 if the query names the file.
+    /// Compared folded, so "Müller" finds `mueller_inv_003.txt` and "Mueller" `Müller_inv_003.txt`
+    fn score(&self, query: &str, doc: &Path) -> f32 {
+        let Some(stem) = doc.file_stem().map(|s| s.to_string_lossy().into_owned()) else { return 0.0 };
+        if contains_folded(query, &stem) {
+            return 1.0;
+        }
+        let terms = query_terms(query);
+        if terms.is_empty() {
+            return 0.0;
+        }
+        let name_words = folded_words(&stem);
+        terms.iter().filter(|t| fold_forms(t).iter().any(|f| name_words.contains(f))).count() as f32 / terms.len() as f32
+    }
+}
+
+/// Document count and average length (bytes) over the indexed documents of one tenant key
+struct CorpusStats {
+    docs: f32,
+    avg_len: f32,
+}
+
+const NO_CORPUS: CorpusStats = CorpusStats { docs: 1.0, avg_len: 1.0 };
+
+static CORPUS_STATS: Lazy<HashMap<Option<String>, CorpusStats>> = Lazy::new(|| {
+    TENANT_INDEXES
+        .iter()
+        .map(|(tenant, index)| {
+            let paths: HashSet<&PathBuf> = index.values().flatten().collect();
+            let total: u64 = paths.iter().filter_map(|p| fs::metadata(p).ok()).map(|m| m.len()).sum();
+            let docs = paths.len().max(1) as f32;
+            (tenant.clone(), CorpusStats { docs, avg_len: (total as f32 / docs).max(1.0) })
+        })
+        .collect()
+});
+
+/// Okapi BM25 over the inverted index: rare words count more than common ones,
+/// repeated words count with diminishing returns, long documents are not favoured
+#[derive(Debug, Clone, Copy)]
+pub struct Bm25Scorer {
+    pub k1: f32,
+    pub b: f32,
+}
+
+impl Default for Bm25Scorer {
+    fn default() -> Self {
+        Self { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl RelevanceScorer for Bm25Scorer {
+    fn name(&self) -> &str {
+        "bm25"
+    }
+
+    fn score(&self, query: &str, doc: &Path) -> f32 {
+        let Ok(text) = get_cached_content(doc) else { return 0.0 };
+        let counts = word_counts(&text);
+        // Frequencies among the documents of the same tenant only
+        let tenant = tenant_key(doc).flatten();
+        let index = tenant_index(tenant.as_deref());
+        let corpus = CORPUS_STATS.get(&tenant).unwrap_or(&NO_CORPUS);
+        let len_norm = 1.0 - self.b + self.b * text.len() as f32 / corpus.avg_len;
+
+        query_terms(query)
+            .iter()
+            .filter_map(|term| Some((counts.get(term)?, index.get(term)?.len() as f32)))
+            .map(|(&tf, df)| {
+                let tf = tf as f32;
+                let idf = (1.0 + (corpus.docs - df + 0.5) / (df + 0.5)).ln();
+                idf * tf * (self.k1 + 1.0) / (tf + self.k1 * len_norm)
+            })
+            .sum()
+    }
+
+    /// Only documents containing a query word can score
+    fn candidates(&self, query: &str, collection: &Collection) -> Vec<PathBuf> {
+        let index = tenant_index(collection.tenant.as_deref());
+        let mut paths: Vec<PathBuf> = query_terms(query)
+            .iter()
+            .filter_map(|t| index.get(t))
+            .flatten()
+            .filter(|p| p.starts_with(&collection.folder))
+            .cloned()
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+}
+
+/// Cosine similarity to the query's embedding, from the vectors saved by `index`.
+/// Built for one query (embedding it is a model call); `score` ignores its query argument.
+#[cfg(feature = "embeddings")]
+#[derive(Debug, Clone)]
+pub struct EmbeddingScorer {
+    query_vector: Vec<f32>,
+}
+
+#[cfg(feature = "embeddings")]
+impl EmbeddingScorer {
+    pub async fn new(query: &str) -> anyhow::Result<Self> {
+        if EMBEDDING_INDEX.documents.is_empty() {
+            anyhow::bail!("No embeddings yet; run `index` with vector or hybrid retrieval configured");
+        }
+        let query_vector =
+            embed_with(EMBEDDING_INDEX.backend, &EMBEDDING_INDEX.model, &[query.to_string()]).await?.swap_remove(0);
+        Ok(Self { query_vector })
+    }
+}
+
+#[cfg(feature = "embeddings")]
+impl RelevanceScorer for EmbeddingScorer {
+    fn name(&self) -> &str {
+        "embedding"
+    }
+
+    fn score(&self, _query: &str, doc: &Path) -> f32 {
+        EMBEDDING_INDEX.get(doc).map_or(0.0, |d| d.similarity(&self.query_vector).max(0.0))
+    }
+
+    /// The documents near the query in the nearest-neighbour lists, when `index` built them
+    fn candidates(&self, _query: &str, collection: &Collection) -> Vec<PathBuf> {
+        EMBEDDING_INDEX.nearest(&self.query_vector, collection).unwrap_or_else(|| collection_documents(collection))
+    }
+}
+
+/// Weighted sum of other scorers
+#[derive(Debug, Clone, Default)]
+pub struct Combined {
+    pub parts: Vec<(Arc<dyn RelevanceScorer>, f32)>,
+}
+
+impl Combined {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, scorer: impl RelevanceScorer + 'static, weight: f32) -> Self {
+        self.parts.push((Arc::new(scorer), weight));
+        self
+    }
+}
+
+impl RelevanceScorer for Combined {
+    fn name(&self) -> &str {
+        "combined"
+    }
+
+    fn score(&self, query: &str, doc: &Path) -> f32 {
+        self.parts.iter().map(|(scorer, weight)| weight * scorer.score(query, doc)).sum()
+    }
+
+    fn candidates(&self, query: &str, collection: &Collection) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.parts.iter().flat_map(|(s, _)| s.candidates(query, collection)).collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+}
+
+/// Top `max_results` documents of the collection by `scorer`, best first.
+/// Exact identifiers (document numbers, IBANs) still select their documents directly.
+pub fn rank_with(
+    scorer: &dyn RelevanceScorer,
+    query: &str,
+    collection: &Collection,
+    max_results: usize,
+) -> Vec<(PathBuf, f32)> {
+    let exact = identifier_matches(query, collection);
+    if !exact.is_empty() {
+        return exact.into_iter().take(max_results).map(|p| (p, EXACT_MATCH_SCORE as f32)).collect();
+    }
+
+    let mut scored: HashMap<PathBuf, f32> = HashMap::new();
+    for path in scorer.candidates(query, collection) {
+        let score = scorer.score(query, &path);
+        if score > 0.0 {
+            scored.insert(path, score);
+        }
+    }
+    let mut ranked: Vec<(PathBuf, f32)> = scored.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(max_results);
+    ranked
+}
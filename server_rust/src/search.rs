@@ -0,0 +1,161 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Full-text search without the model (`doc-ai-server search "retention bond"`):
+// the inverted index finds the documents containing the words, BM25 ranks
+// them, documents containing the whole phrase come first and exact
+// identifiers (invoice numbers, IBANs) select their documents directly. Each
+// hit comes with the lines that match, the matches marked.
+
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::indexer::words;
+use crate::metadata::is_hidden;
+use crate::retrieval::identifier_matches;
+use crate::scoring::{Bm25Scorer, RelevanceScorer};
+use crate::tenants::Tenant;
+use crate::{collections, get_cached_content, Collection};
+
+/// Matching lines shown per document
+pub const SNIPPETS_PER_HIT: usize = 3;
+
+/// Characters of a matching line shown around the first match
+const SNIPPET_CHARS: usize = 160;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SearchHit {
+    pub collection: String,
+    pub file: String,
+    pub path: PathBuf,
+    pub score: f32,
+    /// The document contains the query as a phrase
+    pub phrase: bool,
+    /// Matching lines, matches marked by `mark`
+    pub snippets: Vec<String>,
+}
+
+/// Words of the query that are searched for (as in retrieval: longer than two letters)
+fn terms(query: &str) -> HashSet<String> {
+    words(query).into_iter().filter(|w| w.len() > 2).collect()
+}
+
+/// Documents matching `query`, best first, in `collection` or in all collections of
+/// `tenant` (without one, those no tenant owns); `mark` wraps each match in a snippet
+pub fn search(
+    query: &str,
+    collection: Option<&str>,
+    tenant: Option<&Tenant>,
+    limit: usize,
+    mark: &dyn Fn(&str) -> String,
+) -> Vec<SearchHit> {
+    let phrase = query.trim().trim_matches('"').to_lowercase();
+    let terms = terms(&phrase);
+    let scorer = Bm25Scorer::default();
+    // Collection names are read within the tenant
+    let collection = collection.map(|name| match tenant {
+        Some(tenant) => tenant.scope(name),
+        None => name.to_string(),
+    });
+    let mut hits = Vec::new();
+    for c in collections().iter().filter(|c| c.tenant.as_deref() == tenant.map(|t| t.name.as_str())) {
+        if collection.as_deref().is_some_and(|name| !c.matches(name)) {
+            continue;
+        }
+        let exact = identifier_matches(query, c);
+        let candidates = if exact.is_empty() { scorer.candidates(&phrase, c) } else { exact.clone() };
+        for path in candidates.into_iter().filter(|p| !is_hidden(p)) {
+            let Ok(text) = get_cached_content(&path) else { continue };
+            let score = if exact.contains(&path) { f32::MAX } else { scorer.score(&phrase, &path) };
+            if score <= 0.0 {
+                continue;
+            }
+            hits.push(hit(c, &path, &text, &phrase, &terms, score, mark));
+        }
+    }
+    hits.sort_by(|a, b| b.phrase.cmp(&a.phrase).then(b.score.total_cmp(&a.score)).then_with(|| a.path.cmp(&b.path)));
+    hits.truncate(limit);
+    hits
+}
+
+fn hit(
+    collection: &Collection,
+    path: &Path,
+    text: &str,
+    phrase: &str,
+    terms: &HashSet<String>,
+    score: f32,
+    mark: &dyn Fn(&str) -> String,
+) -> SearchHit {
+    let lower = text.to_lowercase();
+    let has_phrase = !phrase.is_empty() && lower.contains(phrase);
+    // Lines with the phrase first, then lines with the most query words
+    let mut lines: Vec<(usize, &str)> = text
+        .lines()
+        .map(|line| {
+            let line_lower = line.to_lowercase();
+            let found = match has_phrase && line_lower.contains(phrase) {
+                true => usize::MAX,
+                false => words(&line_lower).intersection(terms).count(),
+            };
+            (found, line)
+        })
+        .filter(|(found, _)| *found > 0)
+        .collect();
+    lines.sort_by_key(|(found, _)| std::cmp::Reverse(*found));
+    let needles: Vec<&str> = match has_phrase {
+        true => vec![phrase],
+        false => terms.iter().map(String::as_str).collect(),
+    };
+    SearchHit {
+        collection: collection.base_name().to_string(),
+        file: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        path: path.to_path_buf(),
+        score,
+        phrase: has_phrase,
+        snippets: lines.into_iter().take(SNIPPETS_PER_HIT).map(|(_, line)| snippet(line.trim(), &needles, mark)).collect(),
+    }
+}
+
+/// The line (cut to about SNIPPET_CHARS around the first match) with every match marked
+fn snippet(line: &str, needles: &[&str], mark: &dyn Fn(&str) -> String) -> String {
+    let lower = line.to_lowercase();
+    // Lower-casing can change byte lengths; mark nothing rather than split a character
+    if lower.len() != line.len() {
+        return line.chars().take(SNIPPET_CHARS).collect();
+    }
+    let mut matches: Vec<(usize, usize)> = needles
+        .iter()
+        .filter(|n| !n.is_empty())
+        .flat_map(|n| lower.match_indices(*n).map(move |(i, _)| (i, i + n.len())))
+        .collect();
+    matches.sort();
+    let first = matches.first().map_or(0, |m| m.0);
+    let mut start = first.saturating_sub(SNIPPET_CHARS / 3);
+    while !line.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (start + SNIPPET_CHARS).min(line.len());
+    while !line.is_char_boundary(end) {
+        end += 1;
+    }
+
+    let mut out = String::new();
+    if start > 0 {
+        out.push('…');
+    }
+    let mut at = start;
+    for (from, to) in matches {
+        if from < at || to > end {
+            continue;
+        }
+        out.push_str(&line[at..from]);
+        out.push_str(&mark(&line[from..to]));
+        at = to;
+    }
+    out.push_str(&line[at..end]);
+    if end < line.len() {
+        out.push('…');
+    }
+    out
+}
@@ -0,0 +1,87 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// API keys for hosted model backends, kept in the OS keyring (`auth set
+// openai`) rather than in the config file. The environment variable
+// DOC_AI_<BACKEND>_API_KEY still works, for CI and builds without keyring
+// support; the keyring wins when both are set.
+
+use anyhow::Result;
+#[cfg(feature = "keyring")]
+use anyhow::Context;
+
+/// Keyring service name doc-ai's secrets are stored under
+pub const KEYRING_SERVICE: &str = "doc-ai";
+
+/// Where a backend's key was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretSource {
+    Keyring,
+    Environment,
+}
+
+/// Environment variable for a backend's key ("openai" → DOC_AI_OPENAI_API_KEY)
+pub fn env_var(backend: &str) -> String {
+    let name: String = backend.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect();
+    format!("DOC_AI_{}_API_KEY", name)
+}
+
+#[cfg(feature = "keyring")]
+fn entry(backend: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, &format!("{}-api-key", backend.to_lowercase())).context("OS keyring unavailable")
+}
+
+/// Store a backend's key in the keyring
+pub fn set_api_key(backend: &str, key: &str) -> Result<()> {
+    #[cfg(feature = "keyring")]
+    {
+        entry(backend)?.set_password(key.trim()).with_context(|| format!("Failed to store the {} key", backend))
+    }
+    #[cfg(not(feature = "keyring"))]
+    {
+        let _ = key;
+        anyhow::bail!("This build has no keyring support (enable the `keyring` feature); set {} instead", env_var(backend))
+    }
+}
+
+/// Delete a backend's key from the keyring; false if there was none
+pub fn remove_api_key(backend: &str) -> Result<bool> {
+    #[cfg(feature = "keyring")]
+    {
+        match entry(backend)?.delete_credential() {
+            Ok(()) => Ok(true),
+            Err(keyring::Error::NoEntry) => Ok(false),
+            Err(e) => Err(e).with_context(|| format!("Failed to remove the {} key", backend)),
+        }
+    }
+    #[cfg(not(feature = "keyring"))]
+    {
+        let _ = backend;
+        anyhow::bail!("This build has no keyring support (enable the `keyring` feature)")
+    }
+}
+
+/// A backend's key and where it came from: the keyring first, then the environment
+pub fn api_key(backend: &str) -> Option<(String, SecretSource)> {
+    #[cfg(feature = "keyring")]
+    if let Ok(key) = entry(backend).and_then(|e| Ok(e.get_password()?)) {
+        return Some((key, SecretSource::Keyring));
+    }
+    std::env::var(env_var(backend))
+        .ok()
+        .filter(|k| !k.trim().is_empty())
+        .map(|k| (k.trim().to_string(), SecretSource::Environment))
+}
+
+/// Read a key from the terminal without echoing it (or from a pipe)
+pub fn prompt_api_key(backend: &str) -> Result<String> {
+    #[cfg(feature = "keyring")]
+    {
+        let key = rpassword::prompt_password(format!("API key for {}: ", backend))?.trim().to_string();
+        if key.is_empty() {
+            anyhow::bail!("No key entered");
+        }
+        Ok(key)
+    }
+    #[cfg(not(feature = "keyring"))]
+    anyhow::bail!("This build has no keyring support (enable the `keyring` feature); set {} instead", env_var(backend))
+}
@@ -0,0 +1,283 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// `install-service`: a systemd unit (Linux) or launchd agent (macOS) that
+// runs `serve` with the current settings, from the current directory (the
+// collection folders are relative to it). The systemd unit is sandboxed:
+// read-only file system except .doc-ai and the remote mirrors, no new privileges, no
+// capabilities, network limited to IP and Unix sockets. Secrets are left out
+// of the file; set them with `systemctl edit` or in the keyring.
+
+use anyhow::{Context, Result};
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::config::DEFAULT_CONFIG_FILE;
+use crate::env_config::ENV_PREFIX;
+use crate::Args;
+
+/// Seconds systemd waits on top of the shutdown grace period before killing the server
+const STOP_MARGIN_SECS: u32 = 10;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceKind {
+    Systemd,
+    Launchd,
+}
+
+impl ServiceKind {
+    /// The service manager of this OS
+    pub fn native() -> Self {
+        if cfg!(target_os = "macos") { ServiceKind::Launchd } else { ServiceKind::Systemd }
+    }
+}
+
+/// What the service runs
+#[derive(Debug, Clone)]
+pub struct ServiceSpec {
+    pub name: String,
+    pub executable: PathBuf,
+    pub working_dir: PathBuf,
+    /// Arguments after the executable, starting with `serve`
+    pub args: Vec<String>,
+    /// `DOC_AI_*` variables to set
+    pub env: Vec<(String, String)>,
+    /// Seconds `serve` gives requests in flight on shutdown
+    pub shutdown_grace: u32,
+    /// Account a system-wide service runs as (user services run as their owner)
+    pub user: Option<String>,
+}
+
+/// `DOC_AI_*` variables holding secrets, which are not written into a service file
+pub fn is_secret_variable(name: &str) -> bool {
+    name.starts_with(ENV_PREFIX) && ["_API_KEY", "_PASSWORD", "_PASSPHRASE", "_SECRET", "_TOKEN"].iter().any(|s| name.ends_with(s))
+}
+
+impl ServiceSpec {
+    /// `serve` with the settings given on the command line and in `DOC_AI_*` variables;
+    /// a `system` service runs as the current user
+    pub fn from_args(name: &str, args: &Args, system: bool) -> Result<Self> {
+        let working_dir = env::current_dir()?;
+        let executable = env::current_exe().context("Cannot find the doc-ai-server executable")?;
+
+        let mut serve = vec!["serve".to_string()];
+        let mut flag = |name: &str, value: Option<String>| {
+            if let Some(value) = value {
+                serve.push(format!("--{}", name));
+                serve.push(value);
+            }
+        };
+        // Absolute, so the service does not depend on the directory it was installed from
+        let config = args.config.clone().or_else(|| Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|p| p.exists()));
+        flag("config", config.map(|p| working_dir.join(p).display().to_string()));
+        flag("profile", args.profile.clone());
+        flag("port", Some(args.port.to_string()));
+        flag("address", args.address.map(|a| a.to_string()));
+        flag("model", args.model.clone());
+        flag("collection", args.collection.clone());
+        flag("locale", args.locale.clone());
+        flag("schema", args.schema.clone());
+        flag("domain", args.domain.clone());
+        flag("output-profile", args.output_profile.clone());
+        flag("top-k", Some(args.top_k.to_string()));
+        flag("max-answer-tokens", args.max_answer_tokens.map(|n| n.to_string()));
+        flag(
+            "invoice-status",
+            Some(args.invoice_status.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(",")).filter(|s| !s.is_empty()),
+        );
+        flag("shutdown-grace", Some(args.shutdown_grace.to_string()));
+        for (set, name) in [
+            (args.no_reload, "--no-reload"),
+            (args.restart_on_index, "--restart-on-index"),
+            (args.strict, "--strict"),
+            (args.deny_warnings, "--deny-warnings"),
+            (args.explain, "--explain"),
+            (args.decompose, "--decompose"),
+            (args.agent, "--agent"),
+        ] {
+            if set {
+                serve.push(name.to_string());
+            }
+        }
+
+        // Flags above already carry what the command line got from the environment
+        let variables = env::vars()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX) && !is_secret_variable(name))
+            .filter(|(name, _)| !is_flag_variable(name))
+            .collect();
+
+        Ok(Self {
+            name: name.to_string(),
+            executable,
+            working_dir,
+            args: serve,
+            env: variables,
+            shutdown_grace: args.shutdown_grace,
+            user: if system { Some(env::var("USER").context("USER is not set")?) } else { None },
+        })
+    }
+
+    pub fn render(&self, kind: ServiceKind) -> String {
+        match kind {
+            ServiceKind::Systemd => self.systemd_unit(),
+            ServiceKind::Launchd => self.launchd_plist(),
+        }
+    }
+
+    pub fn systemd_unit(&self) -> String {
+        let quote = |s: &str| {
+            if s.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+                format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+            } else {
+                s.to_string()
+            }
+        };
+        let exec: Vec<String> =
+            std::iter::once(self.executable.display().to_string()).chain(self.args.iter().cloned()).map(|a| quote(&a)).collect();
+        let writable = [self.working_dir.join(".doc-ai"), self.working_dir.join("data/.remote")];
+
+        let mut unit = format!(
+            "[Unit]\n\
+             Description=doc-ai document Q&A server ({name})\n\
+             After=network-online.target\n\
+             Wants=network-online.target\n\
+             \n\
+             [Service]\n\
+             Type=simple\n\
+             {user}\
+             WorkingDirectory={dir}\n\
+             ExecStart={exec}\n\
+             Restart=on-failure\n\
+             RestartSec=5\n\
+             KillSignal=SIGTERM\n\
+             TimeoutStopSec={stop}\n",
+            name = self.name,
+            user = self.user.as_ref().map(|u| format!("User={}\n", u)).unwrap_or_default(),
+            dir = quote(&self.working_dir.display().to_string()),
+            exec = exec.join(" "),
+            stop = self.shutdown_grace + STOP_MARGIN_SECS,
+        );
+        for (name, value) in &self.env {
+            unit.push_str(&format!("Environment={}\n", quote(&format!("{}={}", name, value))));
+        }
+        unit.push_str(&format!(
+            "\n\
+             # Sandboxing: the server reads the documents and writes only the index lock\n\
+             # and the mirrors of remote collections\n\
+             NoNewPrivileges=yes\n\
+             ProtectSystem=strict\n\
+             ProtectHome=read-only\n\
+             ReadWritePaths={writable}\n\
+             PrivateTmp=yes\n\
+             PrivateDevices=yes\n\
+             ProtectKernelTunables=yes\n\
+             ProtectKernelModules=yes\n\
+             ProtectKernelLogs=yes\n\
+             ProtectControlGroups=yes\n\
+             ProtectClock=yes\n\
+             ProtectHostname=yes\n\
+             RestrictAddressFamilies=AF_INET AF_INET6 AF_UNIX\n\
+             RestrictNamespaces=yes\n\
+             RestrictRealtime=yes\n\
+             RestrictSUIDSGID=yes\n\
+             LockPersonality=yes\n\
+             MemoryDenyWriteExecute=yes\n\
+             SystemCallArchitectures=native\n\
+             SystemCallFilter=@system-service\n\
+             CapabilityBoundingSet=\n\
+             UMask=0077\n\
+             \n\
+             [Install]\n\
+             WantedBy={target}\n",
+            writable = writable.iter().map(|p| format!("-{}", quote(&p.display().to_string()))).collect::<Vec<_>>().join(" "),
+            target = if self.user.is_some() { "multi-user.target" } else { "default.target" },
+        ));
+        unit
+    }
+
+    pub fn launchd_plist(&self) -> String {
+        let xml = |s: &str| s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+        let mut args = format!("        <string>{}</string>\n", xml(&self.executable.display().to_string()));
+        for arg in &self.args {
+            args.push_str(&format!("        <string>{}</string>\n", xml(arg)));
+        }
+        let mut env = String::new();
+        for (name, value) in &self.env {
+            env.push_str(&format!("        <key>{}</key>\n        <string>{}</string>\n", xml(name), xml(value)));
+        }
+        let log = self.working_dir.join(".doc-ai").join("server.log");
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+             <plist version=\"1.0\">\n\
+             <dict>\n\
+             \x20   <key>Label</key>\n\
+             \x20   <string>{label}</string>\n\
+             \x20   <key>ProgramArguments</key>\n\
+             \x20   <array>\n\
+             {args}\
+             \x20   </array>\n\
+             \x20   <key>EnvironmentVariables</key>\n\
+             \x20   <dict>\n\
+             {env}\
+             \x20   </dict>\n\
+             \x20   <key>WorkingDirectory</key>\n\
+             \x20   <string>{dir}</string>\n\
+             \x20   <key>RunAtLoad</key>\n\
+             \x20   <true/>\n\
+             \x20   <key>KeepAlive</key>\n\
+             \x20   <dict>\n\
+             \x20       <key>SuccessfulExit</key>\n\
+             \x20       <false/>\n\
+             \x20   </dict>\n\
+             \x20   <key>ExitTimeOut</key>\n\
+             \x20   <integer>{stop}</integer>\n\
+             \x20   <key>ProcessType</key>\n\
+             \x20   <string>Background</string>\n\
+             \x20   <key>StandardOutPath</key>\n\
+             \x20   <string>{log}</string>\n\
+             \x20   <key>StandardErrorPath</key>\n\
+             \x20   <string>{log}</string>\n\
+             </dict>\n\
+             </plist>\n",
+            label = xml(&launchd_label(&self.name)),
+            dir = xml(&self.working_dir.display().to_string()),
+            stop = self.shutdown_grace + STOP_MARGIN_SECS,
+            log = xml(&log.display().to_string()),
+        )
+    }
+}
+
+/// True if a flag reads this `DOC_AI_*` variable (rather than the config)
+fn is_flag_variable(name: &str) -> bool {
+    use clap::CommandFactory;
+    Args::command().get_arguments().any(|a| a.get_env().is_some_and(|e| e == name))
+}
+
+pub fn launchd_label(name: &str) -> String {
+    format!("org.doc-ai.{}", name)
+}
+
+/// Where the service file goes: the user's own services, or the system's with `system`
+pub fn service_path(kind: ServiceKind, name: &str, system: bool) -> Result<PathBuf> {
+    let home = || env::var_os("HOME").map(PathBuf::from).context("HOME is not set");
+    Ok(match (kind, system) {
+        (ServiceKind::Systemd, true) => Path::new("/etc/systemd/system").join(format!("{}.service", name)),
+        (ServiceKind::Systemd, false) => home()?.join(".config/systemd/user").join(format!("{}.service", name)),
+        (ServiceKind::Launchd, true) => Path::new("/Library/LaunchDaemons").join(format!("{}.plist", launchd_label(name))),
+        (ServiceKind::Launchd, false) => home()?.join("Library/LaunchAgents").join(format!("{}.plist", launchd_label(name))),
+    })
+}
+
+/// Commands that load and start the service once the file is written
+pub fn activation_hint(kind: ServiceKind, name: &str, path: &Path, system: bool) -> String {
+    match (kind, system) {
+        (ServiceKind::Systemd, true) => format!("sudo systemctl daemon-reload && sudo systemctl enable --now {}", name),
+        (ServiceKind::Systemd, false) => format!(
+            "systemctl --user daemon-reload && systemctl --user enable --now {}\n\
+             (run `loginctl enable-linger $USER` to keep it running after logout)",
+            name
+        ),
+        (ServiceKind::Launchd, true) => format!("sudo launchctl bootstrap system {}", path.display()),
+        (ServiceKind::Launchd, false) => format!("launchctl bootstrap gui/$(id -u) {}", path.display()),
+    }
+}
@@ -0,0 +1,171 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Multi-turn chat sessions, shared by the `chat` command and the /ws/chat
+// endpoint. A session keeps the recent questions and answers, which are shown
+// to the model so follow-ups ("and the one before that?") make sense, and an
+// optional set of pinned documents that every question is answered from
+// instead of the retrieved ones. Retrieval itself only sees the new question.
+
+use anyhow::Result;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::metadata::resolve_document;
+use crate::retrieval::collection_documents;
+use crate::scoring::RelevanceScorer;
+use crate::tenants::Tenant;
+use crate::{ApiResponse, Collection, Query};
+
+/// Earlier turns shown to the model with a new question
+pub const MAX_HISTORY_TURNS: usize = 6;
+
+/// Sessions kept by the server; the least recently used one is dropped beyond this
+pub const MAX_SESSIONS: usize = 256;
+
+/// One question and its answer
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Turn {
+    pub question: String,
+    pub answer: Value,
+    pub used_files: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Session {
+    pub id: String,
+    pub history: Vec<Turn>,
+    /// File names every question is answered from, when not empty
+    pub pinned: Vec<String>,
+    /// Tenant that opened the session (server with `[[tenant]]`s only)
+    #[serde(default)]
+    pub tenant: Option<Tenant>,
+}
+
+fn new_session_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or_default();
+    let seed = format!("{}-{}-{}", nanos, std::process::id(), COUNTER.fetch_add(1, Ordering::SeqCst));
+    Sha256::digest(seed.as_bytes()).iter().take(8).map(|b| format!("{:02x}", b)).collect()
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self { id: new_session_id(), history: Vec::new(), pinned: Vec::new(), tenant: None }
+    }
+
+    /// Pin a document (file name, name without .txt, or path); returns its file name
+    pub fn pin(&mut self, doc: &str, collection: Option<&str>) -> Result<String> {
+        let path = match &self.tenant {
+            Some(tenant) => tenant.resolve_document(doc, collection)?,
+            None => resolve_document(doc, collection)?,
+        };
+        let name = file_name(&path);
+        if !self.pinned.contains(&name) {
+            self.pinned.push(name.clone());
+        }
+        Ok(name)
+    }
+
+    /// Returns false if the document was not pinned
+    pub fn unpin(&mut self, doc: &str) -> bool {
+        let before = self.pinned.len();
+        self.pinned.retain(|p| p != doc && p.strip_suffix(".txt") != Some(doc));
+        self.pinned.len() != before
+    }
+
+    /// Forget the conversation (pins stay)
+    pub fn reset(&mut self) {
+        self.history.clear();
+    }
+
+    /// The query with this session's recent turns and pinned documents
+    pub fn prepare(&self, query: Query) -> Query {
+        let start = self.history.len().saturating_sub(MAX_HISTORY_TURNS);
+        let scorer: Option<Arc<dyn RelevanceScorer>> =
+            (!self.pinned.is_empty()).then(|| Arc::new(PinnedScorer { files: self.pinned.clone() }) as _);
+        Query { history: self.history[start..].to_vec(), scorer: scorer.or(query.scorer.clone()), ..query }
+    }
+
+    pub fn record(&mut self, question: &str, response: &ApiResponse) {
+        self.history.push(Turn {
+            question: question.to_string(),
+            answer: response.answer.clone(),
+            used_files: response.used_files.clone(),
+        });
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+/// Earlier turns as placed before the documents in the prompt
+pub fn history_block(turns: &[Turn]) -> String {
+    let mut block = String::from(
+        "Earlier in this conversation (for resolving follow-up questions; the documents below remain the only source of facts):\n",
+    );
+    for turn in turns {
+        block.push_str(&format!("Q: {}\nA: {}\n", turn.question, turn.answer));
+    }
+    block
+}
+
+/// Offers only the pinned documents, all equally relevant
+#[derive(Debug, Clone)]
+pub struct PinnedScorer {
+    pub files: Vec<String>,
+}
+
+impl RelevanceScorer for PinnedScorer {
+    fn name(&self) -> &str {
+        "pinned"
+    }
+
+    fn score(&self, _query: &str, _doc: &Path) -> f32 {
+        1.0
+    }
+
+    fn candidates(&self, _query: &str, collection: &Collection) -> Vec<PathBuf> {
+        collection_documents(collection).into_iter().filter(|p| self.files.contains(&file_name(p))).collect()
+    }
+}
+
+/// Sessions of the server, by id
+pub struct SessionStore(Mutex<LruCache<String, Session>>);
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        Self(Mutex::new(LruCache::new(NonZeroUsize::new(MAX_SESSIONS).unwrap())))
+    }
+}
+
+impl SessionStore {
+    /// The session with this id, or a new one (with a new id) if unknown, not given
+    /// or opened by another tenant
+    pub fn open(&self, id: Option<&str>, tenant: Option<&Tenant>) -> Session {
+        let mut sessions = self.0.lock().unwrap();
+        let existing = id.and_then(|id| sessions.get(id).cloned()).filter(|s| s.tenant.as_ref() == tenant);
+        existing.unwrap_or_else(|| {
+            let session = Session { tenant: tenant.cloned(), ..Session::new() };
+            sessions.put(session.id.clone(), session.clone());
+            session
+        })
+    }
+
+    pub fn save(&self, session: &Session) {
+        self.0.lock().unwrap().put(session.id.clone(), session.clone());
+    }
+}
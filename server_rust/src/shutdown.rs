@@ -0,0 +1,76 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Graceful shutdown of `serve`: on SIGTERM or SIGINT (Ctrl-C) Rocket stops
+// accepting connections and gives the requests in flight `--shutdown-grace`
+// seconds to finish, then a few more to close their connections, before it
+// cuts them off. The server keeps no state of its own between requests (the
+// index is written by `index`, not by queries), so nothing needs persisting;
+// we count the queries in flight to report what was drained and what was cut off.
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Orbit, Rocket};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Seconds for closing connections after the grace period, before they are dropped
+pub const SHUTDOWN_MERCY_SECS: u32 = 5;
+
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+static CUT_OFF: AtomicUsize = AtomicUsize::new(0);
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Queries being answered right now
+pub fn in_flight() -> usize {
+    IN_FLIGHT.load(Ordering::SeqCst)
+}
+
+/// Queries dropped unfinished during the shutdown (past the deadline)
+pub fn cut_off() -> usize {
+    CUT_OFF.load(Ordering::SeqCst)
+}
+
+/// Counts a query as in flight until dropped; dropped without `finish`, it was cut off
+pub struct InFlight {
+    finished: bool,
+}
+
+impl InFlight {
+    pub fn start() -> Self {
+        IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+        InFlight { finished: false }
+    }
+
+    pub fn finish(mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+        if !self.finished && SHUTTING_DOWN.load(Ordering::SeqCst) {
+            CUT_OFF.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Logs the start of the shutdown and what is left to drain
+pub struct Drain;
+
+#[rocket::async_trait]
+impl Fairing for Drain {
+    fn info(&self) -> Info {
+        Info {
+            name: "Drain requests on shutdown",
+            kind: Kind::Shutdown,
+        }
+    }
+
+    async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
+        SHUTTING_DOWN.store(true, Ordering::SeqCst);
+        let grace = rocket.config().shutdown.grace;
+        match in_flight() {
+            0 => println!("Shutting down: no requests in flight"),
+            n => println!("Shutting down: waiting up to {} s for {} request(s) in flight", grace, n),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Spend analysis: monthly spend per vendor as a time series, computed from
+// the invoice records (no model involved). Rendered as JSON, CSV, or a
+// terminal table with a sparkline per vendor.
+
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::aggregate::{in_period, Field};
+use crate::records::InvoiceRecord;
+
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+#[derive(Serialize, Debug, Clone)]
+pub struct VendorSeries {
+    pub vendor: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// One value per month of `SpendSeries::months` (zero when nothing was invoiced)
+    pub values: Vec<Decimal>,
+    pub total: Decimal,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SpendSeries {
+    /// Every month from the first to the last invoice, as YYYY-MM
+    pub months: Vec<String>,
+    pub vendors: Vec<VendorSeries>,
+    /// Records left out for lack of a date or amount
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub undated: Vec<String>,
+}
+
+/// Months from `first` to `last` inclusive (both YYYY-MM)
+fn month_range(first: &str, last: &str) -> Vec<String> {
+    let parse = |m: &str| -> Option<(i32, u32)> { Some((m.get(..4)?.parse().ok()?, m.get(5..7)?.parse().ok()?)) };
+    let (Some((mut year, mut month)), Some(end)) = (parse(first), parse(last)) else { return Vec::new() };
+    let mut months = Vec::new();
+    while (year, month) <= end {
+        months.push(format!("{}-{:02}", year, month));
+        month += 1;
+        if month > 12 {
+            month = 1;
+            year += 1;
+        }
+    }
+    months
+}
+
+/// Monthly spend per vendor (and currency: amounts in different currencies are never added)
+pub fn spend_series(records: &[InvoiceRecord], field: Field, period: Option<&str>) -> SpendSeries {
+    let mut sums: BTreeMap<(String, Option<String>), BTreeMap<String, Decimal>> = BTreeMap::new();
+    let mut undated = Vec::new();
+
+    for record in records {
+        let amount = match field {
+            Field::Net => record.net,
+            Field::Tax => record.tax,
+            Field::Total => record.gross,
+        };
+        let (Some(month), Some(amount)) = (record.month(), amount) else {
+            undated.push(record.file.clone());
+            continue;
+        };
+        if period.is_some_and(|p| !record.date.as_deref().is_some_and(|d| in_period(d, p))) {
+            continue;
+        }
+        let vendor = record.vendor.clone().unwrap_or_else(|| "(unknown)".to_string());
+        *sums.entry((vendor, record.currency.clone())).or_default().entry(month.to_string()).or_default() += amount;
+    }
+
+    let first = sums.values().filter_map(|m| m.keys().next()).min().cloned();
+    let last = sums.values().filter_map(|m| m.keys().next_back()).max().cloned();
+    let months = match (first, last) {
+        (Some(first), Some(last)) => month_range(&first, &last),
+        _ => Vec::new(),
+    };
+
+    let vendors = sums
+        .into_iter()
+        .map(|((vendor, currency), by_month)| {
+            let values: Vec<Decimal> = months.iter().map(|m| by_month.get(m).copied().unwrap_or_default()).collect();
+            let total = values.iter().sum();
+            VendorSeries { vendor, currency, values, total }
+        })
+        .collect();
+
+    SpendSeries { months, vendors, undated }
+}
+
+/// One block character per value, scaled between zero and the largest value
+pub fn sparkline(values: &[Decimal]) -> String {
+    let max = values.iter().copied().max().unwrap_or_default();
+    values
+        .iter()
+        .map(|v| {
+            if max <= Decimal::ZERO || *v <= Decimal::ZERO {
+                return if v.is_zero() { ' ' } else { SPARK_LEVELS[0] };
+            }
+            let level = (*v * Decimal::from(SPARK_LEVELS.len() - 1) / max).round();
+            SPARK_LEVELS[level.to_usize().unwrap_or_default().min(SPARK_LEVELS.len() - 1)]
+        })
+        .collect()
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) { format!("\"{}\"", s.replace('"', "\"\"")) } else { s.to_string() }
+}
+
+impl SpendSeries {
+    /// vendor,currency,<month>...,total
+    pub fn to_csv(&self) -> String {
+        let mut out = format!("vendor,currency,{},total\n", self.months.join(","));
+        for series in &self.vendors {
+            let values: Vec<String> = series.values.iter().map(|v| v.to_string()).collect();
+            out.push_str(&format!(
+                "{},{},{},{}\n",
+                csv_field(&series.vendor),
+                series.currency.as_deref().unwrap_or_default(),
+                values.join(","),
+                series.total
+            ));
+        }
+        out
+    }
+
+    /// Terminal view: one line per vendor with its sparkline and total
+    pub fn to_table(&self) -> String {
+        let Some((first, last)) = self.months.first().zip(self.months.last()) else {
+            return "No dated invoices".to_string();
+        };
+        let width = self.vendors.iter().map(|s| s.vendor.chars().count()).max().unwrap_or_default();
+        let mut lines = vec![format!("{:width$}  {} … {}", "", first, last, width = width)];
+        for series in &self.vendors {
+            lines.push(format!(
+                "{:width$}  {}  {} {}",
+                series.vendor,
+                sparkline(&series.values),
+                series.total,
+                series.currency.as_deref().unwrap_or_default(),
+                width = width
+            ));
+        }
+        if !self.undated.is_empty() {
+            lines.push(format!("(no date or amount: {})", self.undated.join(", ")));
+        }
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,369 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Document storage: collections can live on the local filesystem, behind
+// plain HTTP(S) URLs, or in an S3-compatible bucket (AWS S3, MinIO).
+// Remote documents are mirrored into the collection's local folder, with
+// ETags remembered so unchanged files are not downloaded again.
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::docid::is_text_file;
+use crate::encryption;
+
+/// ETags of mirrored files, stored next to them
+const ETAG_FILE: &str = ".etags.json";
+
+/// Where a collection's documents come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DocumentSource {
+    /// Files already in the collection folder
+    Local,
+    /// `base` + each file name; the names come from the config or from `<base>index.json`
+    Http { base: String, files: Vec<String> },
+    /// `s3://bucket/prefix`, signed with the usual AWS_* environment variables
+    S3 { bucket: String, prefix: String, endpoint: String, region: String },
+}
+
+/// Outcome of mirroring one collection
+#[derive(Debug, Clone, Default)]
+pub struct SyncReport {
+    pub downloaded: Vec<String>,
+    pub unchanged: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+struct RemoteObject {
+    name: String,
+    url: String,
+    etag: Option<String>,
+}
+
+impl DocumentSource {
+    /// Parse a `source` setting: a local path, an http(s) URL, or an s3:// URL
+    pub fn parse(source: &str, files: &[String], endpoint: Option<&str>, region: Option<&str>) -> Self {
+        if let Some(rest) = source.strip_prefix("s3://") {
+            let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+            let region = region
+                .map(str::to_string)
+                .or_else(|| std::env::var("AWS_REGION").ok())
+                .unwrap_or_else(|| "us-east-1".to_string());
+            let endpoint = endpoint
+                .map(|e| e.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region));
+            DocumentSource::S3 { bucket: bucket.to_string(), prefix: prefix.to_string(), endpoint, region }
+        } else if source.starts_with("http://") || source.starts_with("https://") {
+            let base = if source.ends_with('/') { source.to_string() } else { format!("{}/", source) };
+            DocumentSource::Http { base, files: files.to_vec() }
+        } else {
+            DocumentSource::Local
+        }
+    }
+
+    pub fn is_remote(&self) -> bool {
+        *self != DocumentSource::Local
+    }
+
+    /// Mirror the remote documents into `folder`, skipping files whose ETag is unchanged
+    pub async fn sync(&self, folder: &Path) -> Result<SyncReport> {
+        let mut report = SyncReport::default();
+        if !self.is_remote() {
+            return Ok(report);
+        }
+
+        fs::create_dir_all(folder)
+            .with_context(|| format!("Failed to create cache folder: {}", folder.display()))?;
+        let etag_path = folder.join(ETAG_FILE);
+        let mut etags: HashMap<String, String> = encryption::read_to_string(&etag_path)
+            .ok()
+            .and_then(|t| serde_json::from_str(&t).ok())
+            .unwrap_or_default();
+
+        let client = Client::new();
+        let objects = self.list(&client).await?;
+
+        for object in &objects {
+            let local = folder.join(&object.name);
+            let known = etags.get(&object.name).filter(|_| local.exists());
+            if let (Some(known), Some(remote)) = (known, &object.etag)
+                && known == remote
+            {
+                report.unchanged.push(object.name.clone());
+                continue;
+            }
+
+            let mut request = self.authorize(client.get(&object.url), &object.url, "")?;
+            if let Some(known) = known {
+                request = request.header("If-None-Match", known.as_str());
+            }
+            let res = request.send().await.with_context(|| format!("Cannot fetch {}", object.url))?;
+
+            if res.status() == StatusCode::NOT_MODIFIED {
+                report.unchanged.push(object.name.clone());
+                continue;
+            }
+            if !res.status().is_success() {
+                anyhow::bail!("Fetching {} failed: {}", object.url, res.status());
+            }
+
+            let etag = res
+                .headers()
+                .get("etag")
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+                .or_else(|| object.etag.clone());
+            let bytes = res.bytes().await.with_context(|| format!("Cannot read {}", object.url))?;
+            encryption::write(&local, bytes.to_vec()).with_context(|| format!("Failed to write {}", local.display()))?;
+
+            match etag {
+                Some(etag) => etags.insert(object.name.clone(), etag),
+                None => etags.remove(&object.name),
+            };
+            report.downloaded.push(object.name.clone());
+        }
+
+        // Files deleted remotely disappear from the mirror too
+        let names: Vec<&str> = objects.iter().map(|o| o.name.as_str()).collect();
+        let stale: Vec<String> = etags.keys().filter(|k| !names.contains(&k.as_str())).cloned().collect();
+        for name in stale {
+            let _ = fs::remove_file(folder.join(&name));
+            etags.remove(&name);
+            report.removed.push(name);
+        }
+
+        encryption::write(&etag_path, serde_json::to_string_pretty(&etags)?)
+            .with_context(|| format!("Failed to write {}", etag_path.display()))?;
+        Ok(report)
+    }
+
+    async fn list(&self, client: &Client) -> Result<Vec<RemoteObject>> {
+        match self {
+            DocumentSource::Local => Ok(Vec::new()),
+            DocumentSource::Http { base, files } => {
+                let names = if files.is_empty() {
+                    let url = format!("{}index.json", base);
+                    let res = client.get(&url).send().await.with_context(|| format!("Cannot fetch {}", url))?;
+                    if !res.status().is_success() {
+                        anyhow::bail!("Fetching {} failed: {}", url, res.status());
+                    }
+                    res.json::<Vec<String>>().await.with_context(|| format!("{} is not a JSON array of file names", url))?
+                } else {
+                    files.clone()
+                };
+
+                Ok(names
+                    .into_iter()
+                    .filter_map(|name| {
+                        let local = local_name(&name)?;
+                        Some(RemoteObject { url: format!("{}{}", base, name), name: local, etag: None })
+                    })
+                    .collect())
+            }
+            DocumentSource::S3 { bucket, prefix, endpoint, .. } => {
+                static CONTENTS_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<Contents>(.*?)</Contents>").unwrap());
+                static KEY_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<Key>(.*?)</Key>").unwrap());
+                static ETAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<ETag>(.*?)</ETag>").unwrap());
+                static TOKEN_RE: Lazy<Regex> =
+                    Lazy::new(|| Regex::new(r"<NextContinuationToken>(.*?)</NextContinuationToken>").unwrap());
+
+                let mut objects = Vec::new();
+                let mut token: Option<String> = None;
+                loop {
+                    let mut params = vec![("list-type", "2".to_string()), ("prefix", prefix.clone())];
+                    if let Some(t) = &token {
+                        params.push(("continuation-token", t.clone()));
+                    }
+                    params.sort();
+                    let query = params
+                        .iter()
+                        .map(|(k, v)| format!("{}={}", uri_encode(k, true), uri_encode(v, true)))
+                        .collect::<Vec<_>>()
+                        .join("&");
+
+                    let url = format!("{}/{}?{}", endpoint, bucket, query);
+                    let res = self
+                        .authorize(client.get(&url), &url, &query)?
+                        .send()
+                        .await
+                        .with_context(|| format!("Cannot list s3://{}/{}", bucket, prefix))?;
+                    let status = res.status();
+                    let body = res.text().await.unwrap_or_default();
+                    if !status.is_success() {
+                        anyhow::bail!("Listing s3://{}/{} failed: {} {}", bucket, prefix, status, body);
+                    }
+
+                    for caps in CONTENTS_RE.captures_iter(&body) {
+                        let Some(key) = KEY_RE.captures(&caps[1]).map(|k| xml_unescape(&k[1])) else {
+                            continue;
+                        };
+                        let Some(name) = local_name(&key) else { continue };
+                        objects.push(RemoteObject {
+                            name,
+                            url: format!("{}/{}/{}", endpoint, bucket, uri_encode(&key, false)),
+                            etag: ETAG_RE.captures(&caps[1]).map(|e| xml_unescape(&e[1])),
+                        });
+                    }
+
+                    token = TOKEN_RE.captures(&body).map(|t| xml_unescape(&t[1]));
+                    if token.is_none() {
+                        break;
+                    }
+                }
+                Ok(objects)
+            }
+        }
+    }
+
+    /// Add AWS Signature V4 headers for S3 (when credentials are set); other sources pass through
+    fn authorize(&self, request: RequestBuilder, url: &str, query: &str) -> Result<RequestBuilder> {
+        let DocumentSource::S3 { region, .. } = self else {
+            return Ok(request);
+        };
+        let (Ok(access_key), Ok(secret_key)) =
+            (std::env::var("AWS_ACCESS_KEY_ID"), std::env::var("AWS_SECRET_ACCESS_KEY"))
+        else {
+            // Anonymous access (public bucket / MinIO with anonymous read)
+            return Ok(request);
+        };
+        let session_token = std::env::var("AWS_SESSION_TOKEN").ok();
+
+        let parsed = reqwest::Url::parse(url).context("Invalid S3 URL")?;
+        let host = match parsed.port() {
+            Some(port) => format!("{}:{}", parsed.host_str().unwrap_or_default(), port),
+            None => parsed.host_str().unwrap_or_default().to_string(),
+        };
+        let (date, timestamp) = amz_timestamp(SystemTime::now());
+        let payload_hash = "UNSIGNED-PAYLOAD";
+
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", payload_hash.to_string()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+        if let Some(token) = &session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed_headers = headers.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(";");
+        let canonical_headers: String = headers.iter().map(|(k, v)| format!("{}:{}\n", k, v.trim())).collect();
+
+        let canonical_request = format!(
+            "GET\n{}\n{}\n{}\n{}\n{}",
+            parsed.path(),
+            query,
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let mut key = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), date.as_bytes())?;
+        for part in [region.as_str(), "s3", "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes())?;
+        }
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes())?);
+
+        let mut request = request
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", timestamp)
+            .header(
+                "Authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                    access_key, scope, signed_headers, signature
+                ),
+            );
+        if let Some(token) = session_token {
+            request = request.header("x-amz-security-token", token);
+        }
+        Ok(request)
+    }
+}
+
+/// Local file name for a remote key: its last segment, text documents only
+fn local_name(key: &str) -> Option<String> {
+    let name = key.rsplit('/').next()?;
+    (!name.is_empty() && !name.starts_with('.') && is_text_file(Path::new(name)))
+        .then(|| name.to_string())
+}
+
+/// Default mirror folder for a remote collection
+pub fn cache_folder(collection: &str) -> PathBuf {
+    PathBuf::from("data/.remote").join(collection)
+}
+
+#[cfg(feature = "signing")]
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    use hmac::{Hmac, Mac};
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+#[cfg(not(feature = "signing"))]
+pub(crate) fn hmac_sha256(_key: &[u8], _data: &[u8]) -> Result<Vec<u8>> {
+    anyhow::bail!("This build cannot sign requests (enable the `signing` feature)")
+}
+
+/// Lowercase hex SHA-256 of `bytes`
+pub(crate) fn sha256(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// RFC 3986 encoding as required by SigV4 (optionally keeping '/')
+fn uri_encode(s: &str, encode_slash: bool) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(b as char),
+            b'/' if !encode_slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+fn xml_unescape(s: &str) -> String {
+    s.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// ("YYYYMMDD", "YYYYMMDDTHHMMSSZ") in UTC
+fn amz_timestamp(now: SystemTime) -> (String, String) {
+    let secs = now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (H. Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let time = format!("{}T{:02}{:02}{:02}Z", date, rem / 3_600, rem % 3_600 / 60, rem % 60);
+    (date, time)
+}
@@ -0,0 +1,450 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Where doc-ai keeps its own state (document metadata, the search index,
+// embeddings): each is one JSON document under a fixed name, loaded and saved
+// whole through the `Store` trait. Backends, chosen by `[store] backend`:
+//
+//   sqlite    .doc-ai/state.db (default; feature `sqlite`, part of `app`)
+//   files     .doc-ai/<name>.json, as before the store existed
+//   postgres  one table in a shared database (feature `postgres`), so several
+//             servers can work from the same state (see replica.rs)
+//
+// Encryption at rest applies to every backend. On first use the SQLite and
+// Postgres stores import whatever .doc-ai/*.json files they do not hold yet.
+// A shared store also holds the index lock, which a lock file could only
+// enforce on one host.
+
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use crate::encryption;
+use crate::lock::LockMode;
+
+/// Folder of the file store, and of the SQLite database by default
+pub const STATE_DIR: &str = ".doc-ai";
+
+/// Names of the state documents
+pub const METADATA: &str = "metadata";
+pub const INDEX: &str = "index";
+pub const EMBEDDINGS: &str = "embeddings";
+pub const QUERIES: &str = "queries";
+pub const AUDIT: &str = "audit";
+/// What each intake connector has taken in
+pub const CONNECTORS: &str = "connectors";
+/// Checksums and sender keys of the documents taken in
+pub const INTAKE: &str = "intake";
+/// Format version of each of the others (see migrate.rs)
+pub const SCHEMA: &str = "schema";
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+const ALL_DOCUMENTS: [&str; 3] = [METADATA, INDEX, EMBEDDINGS];
+
+pub trait Store: Send + Sync {
+    /// Where the state lives, for messages
+    fn describe(&self) -> String;
+
+    /// The document saved under `name`, or None if there is none yet
+    fn load(&self, name: &str) -> Result<Option<String>>;
+
+    /// Replace the document under `name` as a whole
+    fn save(&self, name: &str, text: &str) -> Result<()>;
+
+    /// Load, change and save the document under `name`, with no other save in
+    /// between (the file store only guarantees that within one process)
+    fn update(&self, name: &str, change: &mut (dyn FnMut(Option<String>) -> Result<String> + Send)) -> Result<()> {
+        let _one_at_a_time = UPDATING.lock().unwrap();
+        let text = change(self.load(name)?)?;
+        self.save(name, &text)
+    }
+
+    /// When the document under `name` was last saved (Unix time)
+    fn updated(&self, name: &str) -> Result<Option<u64>>;
+
+    /// Whether servers on other hosts may be using the same state
+    fn shared(&self) -> bool {
+        false
+    }
+
+    /// Try the index lock kept in the store itself; None when the store has
+    /// none and the lock file applies
+    fn try_lock_index(&self, _mode: LockMode) -> Result<Option<bool>> {
+        Ok(None)
+    }
+
+    fn unlock_index(&self, _mode: LockMode) -> Result<()> {
+        Ok(())
+    }
+}
+
+static UPDATING: Mutex<()> = Mutex::new(());
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StoreBackend {
+    Files,
+    Sqlite,
+    Postgres,
+}
+
+impl Default for StoreBackend {
+    fn default() -> Self {
+        if cfg!(feature = "sqlite") { StoreBackend::Sqlite } else { StoreBackend::Files }
+    }
+}
+
+/// `[store]` section of the config file
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct StoreConfig {
+    pub backend: StoreBackend,
+    /// SQLite database file (default .doc-ai/state.db)
+    pub path: Option<PathBuf>,
+    /// Postgres connection string; better set as DOC_AI_STORE__URL than written here
+    pub url: Option<String>,
+}
+
+static STORE: OnceCell<Box<dyn Store>> = OnceCell::new();
+
+/// Open the configured store (first call wins)
+pub fn init_store(config: &StoreConfig) -> Result<()> {
+    if STORE.get().is_some() {
+        return Ok(());
+    }
+    let store: Box<dyn Store> = match config.backend {
+        StoreBackend::Files => Box::new(FileStore::new(STATE_DIR)),
+        StoreBackend::Sqlite => open_sqlite(config.path.clone().unwrap_or_else(|| Path::new(STATE_DIR).join("state.db")))?,
+        StoreBackend::Postgres => {
+            open_postgres(config.url.as_deref().context("[store] backend = \"postgres\" needs a url (or DOC_AI_STORE__URL)")?)?
+        }
+    };
+    let _ = STORE.set(store);
+    Ok(())
+}
+
+/// The store of this process; the file store if `init_store` was never called
+pub fn store() -> &'static dyn Store {
+    STORE.get_or_init(|| Box::new(FileStore::new(STATE_DIR))).as_ref()
+}
+
+/// One JSON file per document, written atomically (temp file + rename)
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", name))
+    }
+}
+
+impl Store for FileStore {
+    fn describe(&self) -> String {
+        self.dir.display().to_string()
+    }
+
+    fn load(&self, name: &str) -> Result<Option<String>> {
+        let path = self.path(name);
+        match encryption::read_to_string(&path) {
+            Ok(text) => Ok(Some(text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    fn save(&self, name: &str, text: &str) -> Result<()> {
+        fs::create_dir_all(&self.dir).with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let path = self.path(name);
+        let tmp = path.with_extension("json.tmp");
+        encryption::write(&tmp, text).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn updated(&self, name: &str) -> Result<Option<u64>> {
+        match fs::metadata(self.path(name)).and_then(|m| m.modified()) {
+            Ok(time) => Ok(Some(time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", self.path(name).display())),
+        }
+    }
+}
+
+/// Documents of the file store not yet in a database store, as stored (sealed or plain)
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn files_to_import(has: impl Fn(&str) -> Result<bool>) -> Result<Vec<(&'static str, Vec<u8>)>> {
+    let files = FileStore::new(STATE_DIR);
+    let mut found = Vec::new();
+    for name in ALL_DOCUMENTS {
+        if let Ok(bytes) = fs::read(files.path(name))
+            && !has(name)?
+        {
+            found.push((name, bytes));
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn unseal_text(label: &str, bytes: Vec<u8>) -> Result<String> {
+    let bytes = encryption::unseal(Path::new(label), bytes)?;
+    String::from_utf8(bytes).with_context(|| format!("{} is not UTF-8", label))
+}
+
+#[cfg(feature = "sqlite")]
+fn open_sqlite(path: PathBuf) -> Result<Box<dyn Store>> {
+    Ok(Box::new(sqlite::SqliteStore::open(path)?))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_sqlite(_path: PathBuf) -> Result<Box<dyn Store>> {
+    anyhow::bail!("[store] backend = \"sqlite\" needs a build with `--features sqlite`")
+}
+
+#[cfg(feature = "postgres")]
+pub use postgres_store::PostgresStore;
+
+#[cfg(feature = "postgres")]
+fn open_postgres(url: &str) -> Result<Box<dyn Store>> {
+    Ok(Box::new(PostgresStore::connect(url)?))
+}
+
+#[cfg(not(feature = "postgres"))]
+fn open_postgres(_url: &str) -> Result<Box<dyn Store>> {
+    anyhow::bail!("[store] backend = \"postgres\" needs a build with `--features postgres`")
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use anyhow::{Context, Result};
+    use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    use super::{files_to_import, unseal_text, Store};
+    use crate::docid::long_path;
+    use crate::encryption;
+    use crate::metadata::now;
+
+    pub struct SqliteStore {
+        path: PathBuf,
+        db: Mutex<Connection>,
+    }
+
+    impl SqliteStore {
+        pub fn open(path: PathBuf) -> Result<Self> {
+            if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+            }
+            let db = Connection::open(long_path(&path)).with_context(|| format!("Cannot open {}", path.display()))?;
+            // WAL: readers (serve) are not blocked while `index` writes
+            db.pragma_update(None, "journal_mode", "WAL")?;
+            db.busy_timeout(std::time::Duration::from_secs(30))?;
+            db.execute(
+                "CREATE TABLE IF NOT EXISTS state (name TEXT PRIMARY KEY, body BLOB NOT NULL, updated INTEGER NOT NULL)",
+                [],
+            )?;
+            let has = |name: &str| -> Result<bool> {
+                Ok(db.query_row("SELECT 1 FROM state WHERE name = ?1", [name], |_| Ok(())).optional()?.is_some())
+            };
+            for (name, bytes) in files_to_import(has)? {
+                db.execute("INSERT INTO state (name, body, updated) VALUES (?1, ?2, ?3)", params![name, bytes, now() as i64])?;
+                eprintln!("Imported .doc-ai/{}.json into {}", name, path.display());
+            }
+            Ok(Self { path, db: Mutex::new(db) })
+        }
+    }
+
+    impl Store for SqliteStore {
+        fn describe(&self) -> String {
+            self.path.display().to_string()
+        }
+
+        fn load(&self, name: &str) -> Result<Option<String>> {
+            let db = self.db.lock().unwrap();
+            let body: Option<Vec<u8>> =
+                db.query_row("SELECT body FROM state WHERE name = ?1", [name], |row| row.get(0)).optional()?;
+            body.map(|bytes| unseal_text(&format!("{}:{}", self.path.display(), name), bytes)).transpose()
+        }
+
+        fn save(&self, name: &str, text: &str) -> Result<()> {
+            let sealed = encryption::seal(text.as_bytes().to_vec())?;
+            self.db.lock().unwrap().execute(
+                "INSERT INTO state (name, body, updated) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (name) DO UPDATE SET body = excluded.body, updated = excluded.updated",
+                params![name, sealed, now() as i64],
+            )?;
+            Ok(())
+        }
+
+        fn update(&self, name: &str, change: &mut (dyn FnMut(Option<String>) -> Result<String> + Send)) -> Result<()> {
+            let mut db = self.db.lock().unwrap();
+            // IMMEDIATE: the write lock is taken before reading, so other processes wait
+            let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let body: Option<Vec<u8>> =
+                tx.query_row("SELECT body FROM state WHERE name = ?1", [name], |row| row.get(0)).optional()?;
+            let text = body.map(|bytes| unseal_text(&format!("{}:{}", self.path.display(), name), bytes)).transpose()?;
+            let sealed = encryption::seal(change(text)?.into_bytes())?;
+            tx.execute(
+                "INSERT INTO state (name, body, updated) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (name) DO UPDATE SET body = excluded.body, updated = excluded.updated",
+                params![name, sealed, now() as i64],
+            )?;
+            tx.commit()?;
+            Ok(())
+        }
+
+        fn updated(&self, name: &str) -> Result<Option<u64>> {
+            let db = self.db.lock().unwrap();
+            let updated: Option<i64> =
+                db.query_row("SELECT updated FROM state WHERE name = ?1", [name], |row| row.get(0)).optional()?;
+            Ok(updated.map(|t| t as u64))
+        }
+    }
+}
+
+#[cfg(feature = "postgres")]
+mod postgres_store {
+    use anyhow::{Context, Result};
+    use postgres::{Client, NoTls};
+    use sha2::{Digest, Sha256};
+    use std::sync::Mutex;
+
+    use super::{files_to_import, unseal_text, Store};
+    use crate::encryption;
+    use crate::lock::LockMode;
+    use crate::metadata::now;
+
+    /// Advisory lock key for a name, the same on every server
+    fn lock_key(name: &str) -> i64 {
+        let digest = Sha256::digest(format!("doc-ai:{}", name).as_bytes());
+        i64::from_be_bytes(digest[..8].try_into().unwrap())
+    }
+
+    /// The synchronous client runs its own runtime, so every call is made on a
+    /// thread of its own rather than inside the server's
+    pub struct PostgresStore {
+        host: String,
+        db: Mutex<Client>,
+    }
+
+    fn off_runtime<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+        std::thread::scope(|s| s.spawn(f).join().expect("store thread panicked"))
+    }
+
+    impl PostgresStore {
+        pub fn connect(url: &str) -> Result<Self> {
+            let mut db = off_runtime(|| Client::connect(url, NoTls)).context("Cannot connect to the Postgres store")?;
+            off_runtime(|| -> Result<()> {
+                db.batch_execute(
+                    "CREATE TABLE IF NOT EXISTS doc_ai_state (name TEXT PRIMARY KEY, body BYTEA NOT NULL, updated BIGINT NOT NULL)",
+                )?;
+                let mut existing = Vec::new();
+                for row in db.query("SELECT name FROM doc_ai_state", &[])? {
+                    existing.push(row.get::<_, String>(0));
+                }
+                for (name, bytes) in files_to_import(|name| Ok(existing.iter().any(|e| e == name)))? {
+                    db.execute(
+                        "INSERT INTO doc_ai_state (name, body, updated) VALUES ($1, $2, $3) ON CONFLICT (name) DO NOTHING",
+                        &[&name, &bytes, &(now() as i64)],
+                    )?;
+                    eprintln!("Imported .doc-ai/{}.json into the Postgres store", name);
+                }
+                Ok(())
+            })?;
+            // The host only, so messages never show a password
+            let host = url.rsplit('@').next().unwrap_or_default().split(['/', '?']).next().unwrap_or_default().to_string();
+            Ok(Self { host, db: Mutex::new(db) })
+        }
+    }
+
+    impl Store for PostgresStore {
+        fn describe(&self) -> String {
+            format!("postgres://{}", self.host)
+        }
+
+        fn load(&self, name: &str) -> Result<Option<String>> {
+            // The guard stays here; the store thread gets the client itself
+            let mut guard = self.db.lock().unwrap();
+            let db = &mut *guard;
+            let row = off_runtime(|| db.query_opt("SELECT body FROM doc_ai_state WHERE name = $1", &[&name]))?;
+            row.map(|row| unseal_text(&format!("{}:{}", self.describe(), name), row.get(0))).transpose()
+        }
+
+        fn save(&self, name: &str, text: &str) -> Result<()> {
+            let sealed = encryption::seal(text.as_bytes().to_vec())?;
+            let mut guard = self.db.lock().unwrap();
+            let db = &mut *guard;
+            off_runtime(|| {
+                db.execute(
+                    "INSERT INTO doc_ai_state (name, body, updated) VALUES ($1, $2, $3)
+                     ON CONFLICT (name) DO UPDATE SET body = EXCLUDED.body, updated = EXCLUDED.updated",
+                    &[&name, &sealed, &(now() as i64)],
+                )
+            })?;
+            Ok(())
+        }
+
+        fn update(&self, name: &str, change: &mut (dyn FnMut(Option<String>) -> Result<String> + Send)) -> Result<()> {
+            let label = format!("{}:{}", self.describe(), name);
+            let mut guard = self.db.lock().unwrap();
+            let db = &mut *guard;
+            off_runtime(|| -> Result<()> {
+                let mut tx = db.transaction()?;
+                // Held until commit; also covers a document that does not exist yet
+                tx.execute("SELECT pg_advisory_xact_lock($1)", &[&lock_key(name)])?;
+                let row = tx.query_opt("SELECT body FROM doc_ai_state WHERE name = $1", &[&name])?;
+                let text = row.map(|row| unseal_text(&label, row.get(0))).transpose()?;
+                let sealed = encryption::seal(change(text)?.into_bytes())?;
+                tx.execute(
+                    "INSERT INTO doc_ai_state (name, body, updated) VALUES ($1, $2, $3)
+                     ON CONFLICT (name) DO UPDATE SET body = EXCLUDED.body, updated = EXCLUDED.updated",
+                    &[&name, &sealed, &(now() as i64)],
+                )?;
+                tx.commit()?;
+                Ok(())
+            })
+        }
+
+        fn updated(&self, name: &str) -> Result<Option<u64>> {
+            let mut guard = self.db.lock().unwrap();
+            let db = &mut *guard;
+            let row = off_runtime(|| db.query_opt("SELECT updated FROM doc_ai_state WHERE name = $1", &[&name]))?;
+            Ok(row.map(|row| row.get::<_, i64>(0) as u64))
+        }
+
+        fn shared(&self) -> bool {
+            true
+        }
+
+        fn try_lock_index(&self, mode: LockMode) -> Result<Option<bool>> {
+            let sql = match mode {
+                LockMode::Shared => "SELECT pg_try_advisory_lock_shared($1)",
+                LockMode::Exclusive => "SELECT pg_try_advisory_lock($1)",
+            };
+            let mut guard = self.db.lock().unwrap();
+            let db = &mut *guard;
+            let row = off_runtime(|| db.query_one(sql, &[&lock_key("index.lock")]))?;
+            Ok(Some(row.get(0)))
+        }
+
+        fn unlock_index(&self, mode: LockMode) -> Result<()> {
+            let sql = match mode {
+                LockMode::Shared => "SELECT pg_advisory_unlock_shared($1)",
+                LockMode::Exclusive => "SELECT pg_advisory_unlock($1)",
+            };
+            let mut guard = self.db.lock().unwrap();
+            let db = &mut *guard;
+            off_runtime(|| db.execute(sql, &[&lock_key("index.lock")]))?;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,454 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// `sync <remote-url>`: two instances (a laptop and the office server) brought
+// level. Each side lists its documents as "<collection>/<file>" with the
+// SHA-256 of the text and its metadata (GET /sync); only documents the other
+// side lacks are sent (POST /sync/fetch to pull, POST /sync to push), so
+// nothing already there crosses the network again. A document whose text
+// differs between the two is reported and left alone on both sides.
+//
+// Metadata of documents on both sides (tags, notes, verified extractions,
+// status and approvals) is resolved on the calling side and written to both:
+// tags and notes are combined; a verified extraction, status or tombstone set
+// differently on each side goes to the newer change (`--prefer` picks a side
+// instead). A tag removed on one side only comes back from the other. Each
+// side makes the status changes it receives itself, through the same steps
+// and approvals as `status` and `approve`, and refuses those that fail them;
+// every document a sync changes gets an audit entry.
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+use crate::approval::ApprovalConfig;
+use crate::audit;
+use crate::docid::{doc_key, is_text_file};
+use crate::lifecycle::InvoiceStatus;
+use crate::metadata::{DocumentMeta, Metadata};
+use crate::storage::{sha256, DocumentSource};
+use crate::{collections, get_cached_content, Collection};
+
+/// One document as listed by GET /sync
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncedDoc {
+    /// SHA-256 of the text
+    pub sha256: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<DocumentMeta>,
+}
+
+/// Every document of one side, by "<collection>/<file>"
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SyncManifest {
+    pub documents: BTreeMap<String, SyncedDoc>,
+}
+
+/// Body of POST /sync
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SyncPush {
+    /// New documents: name → text
+    #[serde(default)]
+    pub documents: BTreeMap<String, String>,
+    /// Resolved metadata to keep: name → entry
+    #[serde(default)]
+    pub metadata: BTreeMap<String, DocumentMeta>,
+}
+
+/// What one side took in
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Applied {
+    pub written: Vec<String>,
+    pub metadata: usize,
+    /// Names that were not taken, with the reason
+    pub refused: Vec<(String, String)>,
+}
+
+/// Which side wins a metadata conflict
+#[derive(clap::ValueEnum, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Prefer {
+    /// The more recent change
+    #[default]
+    Newer,
+    Local,
+    Remote,
+}
+
+/// The collections a side shares, by the name the other side knows them by:
+/// those of `tenant` (the others without one), local ones only, since mirrors
+/// are filled from their own source
+pub fn shared(tenant: Option<&str>) -> Vec<(String, &'static Collection)> {
+    collections()
+        .iter()
+        .filter(|c| c.tenant.as_deref() == tenant && matches!(c.source, DocumentSource::Local))
+        .map(|c| {
+            let name = match tenant {
+                Some(t) => c.name.strip_prefix(&format!("{}/", t)).unwrap_or(&c.name).to_string(),
+                None => c.name.clone(),
+            };
+            (name, c)
+        })
+        .collect()
+}
+
+/// Local path of a shared document name; None for a collection not shared or a name that is not a file name
+fn locate(shared: &[(String, &Collection)], name: &str) -> Option<PathBuf> {
+    let (collection, file) = name.rsplit_once('/')?;
+    if file.is_empty() || file.contains(['\\']) || file.starts_with('.') || !file.ends_with(".txt") {
+        return None;
+    }
+    shared.iter().find(|(n, _)| n == collection).map(|(_, c)| c.folder.join(file))
+}
+
+/// The documents of the shared collections, with their hashes and metadata
+pub fn manifest(shared: &[(String, &Collection)]) -> Result<SyncManifest> {
+    let metadata = Metadata::load()?;
+    let mut documents = BTreeMap::new();
+    for (name, collection) in shared {
+        let Ok(entries) = fs::read_dir(&collection.folder) else { continue };
+        for path in entries.flatten().map(|e| e.path()) {
+            if !is_text_file(&path) {
+                continue;
+            }
+            let Some(file) = path.file_name().map(|f| f.to_string_lossy().to_string()) else { continue };
+            let Ok(text) = get_cached_content(&path) else { continue };
+            let doc = SyncedDoc { sha256: sha256(text.as_bytes()), meta: metadata.get(&path).cloned() };
+            documents.insert(format!("{}/{}", name, file), doc);
+        }
+    }
+    Ok(SyncManifest { documents })
+}
+
+/// Texts of the named documents (unknown names are left out)
+pub fn texts(shared: &[(String, &Collection)], names: &[String]) -> BTreeMap<String, String> {
+    names
+        .iter()
+        .filter_map(|name| Some((name.clone(), get_cached_content(&locate(shared, name)?).ok()?)))
+        .collect()
+}
+
+/// Write new documents (never over an existing one) and take in metadata
+/// entries; every document changed gets an audit entry by `actor`
+pub fn apply(shared: &[(String, &Collection)], push: &SyncPush, approval: &ApprovalConfig, actor: &str) -> Result<Applied> {
+    let mut applied = Applied::default();
+    for (name, text) in &push.documents {
+        let Some(path) = locate(shared, name) else {
+            applied.refused.push((name.clone(), "no such collection here".to_string()));
+            continue;
+        };
+        if path.exists() {
+            applied.refused.push((name.clone(), "a document of that name is already here".to_string()));
+            continue;
+        }
+        fs::write(&path, text).with_context(|| format!("Cannot write {}", path.display()))?;
+        applied.written.push(name.clone());
+    }
+    // Read before the store is locked for the update
+    let entries: Vec<(&String, PathBuf, &DocumentMeta, usize)> = push
+        .metadata
+        .iter()
+        .filter_map(|(name, meta)| {
+            let path = locate(shared, name)?;
+            let approvers = approval.required_for(&path);
+            Some((name, path, meta, approvers))
+        })
+        .collect();
+    let mut outcomes = Vec::new();
+    if !entries.is_empty() {
+        Metadata::update(|metadata| {
+            outcomes = entries.iter().map(|(_, path, meta, approvers)| take_in(metadata, path, meta, *approvers)).collect();
+        })?;
+    }
+    let mut changed: Vec<&String> = Vec::new();
+    for ((name, ..), outcome) in entries.iter().zip(outcomes) {
+        match outcome {
+            Ok(true) => changed.push(name),
+            Ok(false) => {}
+            Err(e) => applied.refused.push(((*name).clone(), format!("metadata not taken: {:#}", e))),
+        }
+    }
+    let touched: BTreeSet<&String> = applied.written.iter().chain(changed.iter().copied()).collect();
+    for name in touched {
+        let Some(path) = locate(shared, name) else { continue };
+        let details = json!({ "written": applied.written.contains(name), "metadata": changed.contains(&name) });
+        audit::record_or_warn(actor, "sync", Some(&path.display().to_string()), details);
+    }
+    applied.metadata = changed.len();
+    Ok(applied)
+}
+
+/// Take one document's pushed metadata into `metadata`; returns whether it changed.
+/// Tags and notes are combined, a verified extraction and rm/archive taken as
+/// sent. The status changes sent after the status the document has here are
+/// made again, step by step, through `set_status` and `approve`, so a push is
+/// held to the same steps and approvers as `status` and `approve` (never
+/// forced); the policy decision is not taken, being made here. On an error the
+/// entry is left as it was.
+fn take_in(metadata: &mut Metadata, path: &Path, pushed: &DocumentMeta, approvers: usize) -> Result<bool> {
+    let before = metadata.get(path).cloned();
+    if let Err(e) = take_in_steps(metadata, path, pushed, approvers) {
+        match before {
+            Some(entry) => *metadata.entry(path) = entry,
+            None => {
+                metadata.documents.remove(&doc_key(path));
+            }
+        }
+        return Err(e);
+    }
+    Ok(serde_json::to_value(&before)? != serde_json::to_value(metadata.get(path))?)
+}
+
+fn take_in_steps(metadata: &mut Metadata, path: &Path, pushed: &DocumentMeta, approvers: usize) -> Result<()> {
+    let entry = metadata.entry(path);
+    let tags_and_notes = DocumentMeta { tags: pushed.tags.clone(), notes: pushed.notes.clone(), ..DocumentMeta::default() };
+    entry.merge(&tags_and_notes);
+    if pushed.verified.is_some() {
+        entry.verified = pushed.verified.clone();
+    }
+    if pushed.tombstone.is_some() {
+        entry.tombstone = pushed.tombstone.clone();
+    }
+
+    let approve_all = |metadata: &mut Metadata| -> Result<()> {
+        for approval in &pushed.approvals {
+            let given = metadata.get(path).is_some_and(|m| m.approvals.iter().any(|a| a.by.eq_ignore_ascii_case(&approval.by)));
+            if !given && metadata.status(path) != InvoiceStatus::Approved {
+                metadata.approve(path, &approval.by, approvers)?;
+            }
+        }
+        Ok(())
+    };
+    // The sent steps after the status this document has here
+    let current = metadata.status(path);
+    let steps = match pushed.status_history.iter().rposition(|c| c.to == current) {
+        Some(i) => &pushed.status_history[i + 1..],
+        None if current == InvoiceStatus::Received || pushed.status_history.is_empty() => &pushed.status_history[..],
+        None => anyhow::bail!("it is {} here, a status the sent history never reached", current.as_str()),
+    };
+    for change in steps {
+        if change.to == InvoiceStatus::Approved {
+            approve_all(metadata)?;
+        }
+        if metadata.status(path) != change.to {
+            let required = if change.to == InvoiceStatus::Approved { approvers } else { 0 };
+            metadata.set_status(path, change.to, false, required)?;
+        }
+    }
+    // Approvals given since, waiting for another approver
+    if metadata.status(path) == InvoiceStatus::Extracted {
+        approve_all(metadata)?;
+    }
+    Ok(())
+}
+
+fn last_status_change(meta: &DocumentMeta) -> u64 {
+    meta.status_history.last().map_or(0, |c| c.at)
+}
+
+/// One document's metadata from both sides, resolved; the fields decided by
+/// `prefer` (or by the newer change) are named in `conflicts`
+pub fn resolve(local: &DocumentMeta, remote: &DocumentMeta, prefer: Prefer, conflicts: &mut Vec<String>) -> DocumentMeta {
+    let mut resolved = local.clone();
+    resolved.merge(remote);
+    let remote_wins = |field: &str, local_at: u64, remote_at: u64, conflicts: &mut Vec<String>| {
+        let wins = match prefer {
+            Prefer::Newer => remote_at > local_at,
+            Prefer::Local => false,
+            Prefer::Remote => true,
+        };
+        conflicts.push(format!("{} (kept the {} one)", field, if wins { "remote" } else { "local" }));
+        wins
+    };
+    if let (Some(l), Some(r)) = (&local.verified, &remote.verified)
+        && l.answer != r.answer && remote_wins("verified extraction", l.at, r.at, conflicts)
+    {
+        resolved.verified = remote.verified.clone();
+    }
+    if local.status_history != remote.status_history
+        && !local.status_history.is_empty()
+        && !remote.status_history.is_empty()
+        && remote_wins("status", last_status_change(local), last_status_change(remote), conflicts)
+    {
+        resolved.status = remote.status;
+        resolved.status_history = remote.status_history.clone();
+        resolved.approvals = remote.approvals.clone();
+        resolved.policy = remote.policy.clone();
+    }
+    if let (Some(l), Some(r)) = (&local.tombstone, &remote.tombstone)
+        && l.state != r.state && remote_wins("rm/archive", l.at, r.at, conflicts)
+    {
+        resolved.tombstone = remote.tombstone.clone();
+    }
+    resolved
+}
+
+/// Outcome of `sync`
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct SyncOutcome {
+    pub pulled: Vec<String>,
+    pub pushed: Vec<String>,
+    /// Documents whose metadata changed on either side
+    pub annotations: usize,
+    /// "<name>: <field> (kept the ... one)"
+    pub conflicts: Vec<String>,
+    /// Documents with different text on each side, left alone
+    pub diverged: Vec<String>,
+    /// Documents one side did not take, with the reason
+    pub refused: Vec<(String, String)>,
+}
+
+/// HTTP side of a sync: the remote instance's /sync endpoints
+pub struct Remote {
+    base_url: String,
+    api_key: Option<String>,
+    http: reqwest::Client,
+}
+
+impl Remote {
+    pub fn new(base_url: &str, api_key: Option<String>) -> Self {
+        Self { base_url: base_url.trim_end_matches('/').to_string(), api_key, http: reqwest::Client::new() }
+    }
+
+    async fn call<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<T> {
+        let request = match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        };
+        let res = request.send().await.with_context(|| format!("Cannot reach {}", self.base_url))?;
+        let status = res.status();
+        let reply: serde_json::Value =
+            res.json().await.with_context(|| format!("Unexpected response from {} ({}); is it a doc-ai server?", self.base_url, status))?;
+        if reply.get("success").and_then(|s| s.as_bool()) != Some(true) {
+            let message = reply.pointer("/error/message").and_then(|m| m.as_str()).unwrap_or("no error message");
+            anyhow::bail!("{} refused the sync ({}): {}", self.base_url, status, message);
+        }
+        serde_json::from_value(reply.get("data").cloned().unwrap_or_default()).context("Unexpected sync response")
+    }
+
+    pub async fn manifest(&self) -> Result<SyncManifest> {
+        self.call(self.http.get(format!("{}/sync", self.base_url))).await
+    }
+
+    pub async fn fetch(&self, names: &[String]) -> Result<BTreeMap<String, String>> {
+        self.call(self.http.post(format!("{}/sync/fetch", self.base_url)).json(names)).await
+    }
+
+    pub async fn push(&self, push: &SyncPush) -> Result<Applied> {
+        self.call(self.http.post(format!("{}/sync", self.base_url)).json(push)).await
+    }
+}
+
+/// Largest POST /sync body sent, well under the server's 1 MiB limit for JSON
+const BATCH_BYTES: usize = 512 * 1024;
+
+/// `push` split into bodies of about `BATCH_BYTES` (a larger document goes alone)
+fn batches(push: SyncPush) -> Result<Vec<SyncPush>> {
+    let mut batches = vec![SyncPush::default()];
+    let mut size = 0;
+    let mut add = |bytes: usize, batches: &mut Vec<SyncPush>| {
+        if size + bytes > BATCH_BYTES && size > 0 {
+            batches.push(SyncPush::default());
+            size = 0;
+        }
+        size += bytes;
+    };
+    for (name, text) in push.documents {
+        add(name.len() + text.len(), &mut batches);
+        batches.last_mut().unwrap().documents.insert(name, text);
+    }
+    for (name, meta) in push.metadata {
+        add(name.len() + serde_json::to_string(&meta)?.len(), &mut batches);
+        batches.last_mut().unwrap().metadata.insert(name, meta);
+    }
+    batches.retain(|b| !b.documents.is_empty() || !b.metadata.is_empty());
+    Ok(batches)
+}
+
+/// Bring the shared local collections and `remote` level; with `dry_run` only report what would move
+pub async fn sync(
+    remote: &Remote,
+    collection: Option<&str>,
+    prefer: Prefer,
+    dry_run: bool,
+    approval: &ApprovalConfig,
+) -> Result<SyncOutcome> {
+    let actor = audit::local_actor();
+    let shared: Vec<(String, &Collection)> =
+        shared(None).into_iter().filter(|(_, c)| collection.is_none_or(|name| c.matches(name))).collect();
+    let local = manifest(&shared)?;
+    let theirs = remote.manifest().await?;
+    let in_scope = |name: &str| locate(&shared, name).is_some();
+
+    let mut report = SyncOutcome::default();
+    let mut to_push = SyncPush::default();
+    let mut local_metadata = BTreeMap::new();
+    for (name, doc) in &local.documents {
+        match theirs.documents.get(name) {
+            None => report.pushed.push(name.clone()),
+            Some(other) if other.sha256 != doc.sha256 => report.diverged.push(name.clone()),
+            Some(other) => {
+                let mut conflicts = Vec::new();
+                let resolved = match (&doc.meta, &other.meta) {
+                    (Some(l), Some(r)) => resolve(l, r, prefer, &mut conflicts),
+                    (Some(l), None) => l.clone(),
+                    (None, Some(r)) => r.clone(),
+                    (None, None) => continue,
+                };
+                report.conflicts.extend(conflicts.into_iter().map(|c| format!("{}: {}", name, c)));
+                let json = |m: &Option<DocumentMeta>| m.as_ref().map(|m| serde_json::to_value(m).unwrap_or_default());
+                let resolved_json = Some(serde_json::to_value(&resolved)?);
+                if json(&other.meta) != resolved_json {
+                    to_push.metadata.insert(name.clone(), resolved.clone());
+                }
+                if json(&doc.meta) != resolved_json {
+                    local_metadata.insert(name.clone(), resolved);
+                }
+                if to_push.metadata.contains_key(name) || local_metadata.contains_key(name) {
+                    report.annotations += 1;
+                }
+            }
+        }
+    }
+    // The remote's documents of collections shared here, with their metadata as they are
+    let mut pulled_metadata = BTreeMap::new();
+    for (name, doc) in theirs.documents.iter().filter(|(n, _)| !local.documents.contains_key(*n)) {
+        if in_scope(name) {
+            report.pulled.push(name.clone());
+            if let Some(meta) = &doc.meta {
+                pulled_metadata.insert(name.clone(), meta.clone());
+            }
+        }
+    }
+    if dry_run {
+        return Ok(report);
+    }
+
+    if !report.pulled.is_empty() {
+        let texts = remote.fetch(&report.pulled).await?;
+        let pull = SyncPush { documents: texts, metadata: pulled_metadata };
+        let applied = apply(&shared, &pull, approval, &actor)?;
+        report.refused.extend(applied.refused);
+        report.pulled = applied.written;
+    }
+    for name in &report.pushed {
+        if let Some(path) = locate(&shared, name) {
+            to_push.documents.insert(name.clone(), get_cached_content(&path)?);
+            if let Some(meta) = local.documents.get(name).and_then(|d| d.meta.clone()) {
+                to_push.metadata.insert(name.clone(), meta);
+            }
+        }
+    }
+    report.pushed.clear();
+    for batch in batches(to_push)? {
+        let applied = remote.push(&batch).await?;
+        report.refused.extend(applied.refused);
+        report.pushed.extend(applied.written);
+    }
+    let applied = apply(&shared, &SyncPush { documents: BTreeMap::new(), metadata: local_metadata }, approval, &actor)?;
+    report.refused.extend(applied.refused);
+    Ok(report)
+}
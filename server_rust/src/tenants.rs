@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Tenants: one server shared by several client organisations. Each
+// `[[tenant]]` in the config file gets its own copy of every local collection,
+// named "<tenant>/<collection>" and kept under the tenant's `data_dir`, and an
+// API key (stored as its SHA-256 only). With tenants configured, every server
+// request must carry a key (`Authorization: Bearer`, `X-API-Key`, or `api_key=`
+// for EventSource and WebSocket clients, which cannot set headers); collection
+// names in the request are read within the key's tenant, so a tenant cannot
+// even name another one's documents. The word and identifier indexes (and the
+// BM25 statistics) are split by tenant key, so retrieval and search for one
+// tenant never consult another's documents; logged queries carry the tenant
+// that asked, and chat sessions belong to the tenant that opened them.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, TenantConfig};
+use crate::metadata::find_document;
+use crate::{collections, Collection, QueryRequest};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Tenant {
+    pub name: String,
+}
+
+/// Hex SHA-256 of an API key, as given in `key_sha256`
+pub fn key_hash(key: &str) -> String {
+    Sha256::digest(key.trim().as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The tenant whose key this is
+pub fn tenant_for_key(config: &Config, key: &str) -> Option<Tenant> {
+    let hash = key_hash(key);
+    config
+        .tenants
+        .iter()
+        .find(|t| t.key_sha256.eq_ignore_ascii_case(&hash))
+        .map(|t| Tenant { name: t.name.clone() })
+}
+
+impl Tenant {
+    /// The tenant's copy of a collection, by the name clients use
+    pub fn scope(&self, collection: &str) -> String {
+        format!("{}/{}", self.name, collection.to_lowercase())
+    }
+
+    pub fn owns(&self, collection: &Collection) -> bool {
+        collection.tenant.as_deref() == Some(self.name.as_str())
+    }
+
+    /// Point the request at this tenant's collections; `default` applies when it names none
+    pub fn scope_request(&self, request: &mut QueryRequest, default: Option<&str>) {
+        if let Some(names) = &mut request.collections {
+            *names = names.iter().map(|n| self.scope(n)).collect();
+        }
+        let name = request.collection.as_deref().or(request.category.as_deref()).or(default);
+        request.collection = name.map(|n| self.scope(n));
+        request.category = None;
+    }
+
+    /// A document of this tenant by file name (paths are not accepted)
+    pub fn resolve_document(&self, doc: &str, collection: Option<&str>) -> Result<PathBuf> {
+        if Path::new(doc).components().count() != 1 {
+            anyhow::bail!("No document named '{}'", doc);
+        }
+        let scoped = collection.map(|c| self.scope(c));
+        find_document(
+            doc,
+            collections().iter().filter(|c| self.owns(c) && scoped.as_deref().is_none_or(|name| c.matches(name))),
+        )
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Each tenant's copies of the local collections (remote ones are not offered to tenants)
+pub fn tenant_collections(base: &[Collection], config: &Config) -> Result<Vec<Collection>> {
+    let mut copies = Vec::new();
+    let data_dir = config.data_dir.clone().unwrap_or_else(|| PathBuf::from("data"));
+    for (i, tenant) in config.tenants.iter().enumerate() {
+        if !valid_name(&tenant.name) {
+            anyhow::bail!("Tenant name '{}' must be lower-case letters, digits, '-' or '_'", tenant.name);
+        }
+        if tenant.key_sha256.len() != 64 || !tenant.key_sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            anyhow::bail!("Tenant '{}': key_sha256 must be the 64 hex digits of a SHA-256", tenant.name);
+        }
+        if tenant.data_dir.as_os_str().is_empty() {
+            anyhow::bail!("Tenant '{}' needs a data_dir", tenant.name);
+        }
+        let clash = |o: &&TenantConfig| o.name == tenant.name || o.key_sha256.eq_ignore_ascii_case(&tenant.key_sha256);
+        if let Some(other) = config.tenants[..i].iter().find(clash) {
+            anyhow::bail!("Tenants '{}' and '{}' share a name or a key", other.name, tenant.name);
+        }
+
+        for collection in base.iter().filter(|c| !c.source.is_remote()) {
+            let rest = match collection.folder.strip_prefix(&data_dir) {
+                Ok(rest) => rest.to_path_buf(),
+                Err(_) => PathBuf::from(&collection.name),
+            };
+            let mut copy = collection.clone();
+            copy.name = format!("{}/{}", tenant.name, collection.name);
+            copy.aliases = collection.aliases.iter().map(|a| format!("{}/{}", tenant.name, a)).collect();
+            copy.folder = tenant.data_dir.join(rest);
+            copy.tenant = Some(tenant.name.clone());
+            copies.push(copy);
+        }
+    }
+
+    // No folder may sit inside another, or one tenant would see the other's files
+    let all: Vec<&Collection> = base.iter().chain(&copies).collect();
+    for (i, a) in all.iter().enumerate() {
+        for b in &all[i + 1..] {
+            if a.tenant != b.tenant && (a.folder.starts_with(&b.folder) || b.folder.starts_with(&a.folder)) {
+                anyhow::bail!(
+                    "Collections '{}' ({}) and '{}' ({}) overlap; give each tenant a data_dir of its own",
+                    a.name,
+                    a.folder.display(),
+                    b.name,
+                    b.folder.display()
+                );
+            }
+        }
+    }
+    Ok(copies)
+}
+
+/// The tenant a server request acts for: None when no tenants are configured,
+/// otherwise the owner of the request's API key (requests without a valid key get 401)
+pub struct Tenancy(pub Option<Tenant>);
+
+impl Tenancy {
+    pub fn name(&self) -> Option<&str> {
+        self.0.as_ref().map(|t| t.name.as_str())
+    }
+
+    /// Whether this request may see the collection
+    pub fn allows(&self, collection: &Collection) -> bool {
+        collection.tenant.as_deref() == self.name()
+    }
+
+    /// A collection name from the request, read within the tenant
+    pub fn scope(&self, collection: &str) -> String {
+        match &self.0 {
+            Some(tenant) => tenant.scope(collection),
+            None => collection.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+mod guard {
+    use rocket::http::Status;
+    use rocket::request::{FromRequest, Outcome, Request};
+    use std::sync::Arc;
+
+    use super::{tenant_for_key, Tenancy};
+    use crate::LiveConfig;
+
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for Tenancy {
+        type Error = String;
+
+        async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+            let Some(live) = req.rocket().state::<Arc<LiveConfig>>() else { return Outcome::Success(Tenancy(None)) };
+            let (_, config) = live.get();
+            if config.tenants.is_empty() {
+                return Outcome::Success(Tenancy(None));
+            }
+            let key = req
+                .headers()
+                .get_one("X-API-Key")
+                .or_else(|| req.headers().get_one("Authorization").and_then(|h| h.strip_prefix("Bearer ")))
+                .map(str::to_string)
+                .or_else(|| req.query_value::<String>("api_key").and_then(|v| v.ok()));
+            match key.as_deref().and_then(|k| tenant_for_key(&config, k)) {
+                Some(tenant) => Outcome::Success(Tenancy(Some(tenant))),
+                None => Outcome::Error((Status::Unauthorized, "Missing or unknown API key".to_string())),
+            }
+        }
+    }
+}
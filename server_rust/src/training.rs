@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Fine-tuning data from human corrections. `verify <doc> <file.json>` keeps
+// the corrected extraction of a document in its metadata; `export-training`
+// turns every verified document into one example, prompted exactly as the
+// pipeline prompts the model (collection instruction, grounding rules, the
+// document in chunks), with the corrected JSON as the answer. Two formats:
+//
+//   openai     JSONL, one {"messages": [system, user, assistant]} per line,
+//              for OpenAI fine-tuning and most LoRA trainers
+//   modelfile  an Ollama Modelfile with the examples as MESSAGE pairs; Ollama
+//              cannot train, so it also leaves a place for the ADAPTER that a
+//              trainer built from the JSONL
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::path::Path;
+
+use crate::ai::{build_messages, push_document, ChatMessage};
+use crate::chunking::chunk_document;
+use crate::docid::doc_path;
+use crate::metadata::Metadata;
+use crate::modelfile::ModelSpec;
+use crate::{collections, get_cached_content, Collection};
+
+/// The question of every example: the whole extraction
+pub const EXTRACTION_QUESTION: &str = "Extract the information in this document as JSON.";
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrainingFormat {
+    /// JSONL of chat messages (OpenAI fine-tuning format)
+    Openai,
+    /// Ollama Modelfile with the examples as MESSAGE pairs
+    Modelfile,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrainingExample {
+    pub collection: String,
+    pub file: String,
+    pub messages: Vec<ChatMessage>,
+}
+
+/// One example per verified document (in `collection` only, if given); the
+/// second value lists verified documents that could not be read
+pub fn training_examples(collection: Option<&str>) -> Result<(Vec<TrainingExample>, Vec<String>)> {
+    let metadata = Metadata::load()?;
+    let mut examples = Vec::new();
+    let mut skipped = Vec::new();
+    for (key, meta) in &metadata.documents {
+        let Some(verified) = &meta.verified else { continue };
+        let path = &doc_path(key);
+        let Some(owner) = collections().iter().find(|c| path.starts_with(&c.folder)) else { continue };
+        if collection.is_some_and(|name| !owner.matches(name)) {
+            continue;
+        }
+        match get_cached_content(path) {
+            Ok(text) => examples.push(example(owner, path, &text, &verified.answer)),
+            Err(e) => skipped.push(format!("{} ({:#})", key, e)),
+        }
+    }
+    Ok((examples, skipped))
+}
+
+fn example(collection: &Collection, path: &Path, text: &str, answer: &Value) -> TrainingExample {
+    let file = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let mut contents = String::new();
+    push_document(&mut contents, &file, None, &[], text, &chunk_document(text));
+    let mut messages = build_messages(collection, &contents, EXTRACTION_QUESTION);
+    messages.push(ChatMessage::assistant(answer.to_string()));
+    TrainingExample { collection: collection.name.clone(), file, messages }
+}
+
+/// One line per example
+pub fn to_openai_jsonl(examples: &[TrainingExample]) -> String {
+    examples
+        .iter()
+        .map(|e| {
+            let messages: Vec<Value> = e.messages.iter().map(|m| json!({"role": m.role, "content": m.content})).collect();
+            format!("{}\n", json!({ "messages": messages }))
+        })
+        .collect()
+}
+
+/// A Modelfile on `base`; the examples must share one system message (one collection)
+pub fn to_modelfile(examples: &[TrainingExample], base: &str) -> Result<String> {
+    let system = examples.first().and_then(|e| e.messages.first()).map(|m| m.content.clone()).unwrap_or_default();
+    if examples.iter().any(|e| e.messages.first().map(|m| &m.content) != Some(&system)) {
+        anyhow::bail!("A Modelfile has one system prompt; export one collection at a time (--collection)");
+    }
+    let mut comments = vec![
+        format!("{} verified extraction(s) exported by doc-ai-server export-training", examples.len()),
+        "Add `ADAPTER ./adapter.safetensors` below FROM for a LoRA adapter trained on the `--format openai` export".to_string(),
+    ];
+    comments.extend(examples.iter().map(|e| format!("example: {}/{}", e.collection, e.file)));
+    let spec = ModelSpec {
+        base: base.to_string(),
+        system,
+        parameters: [("temperature".to_string(), json!(0))].into_iter().collect(),
+        messages: examples.iter().flat_map(|e| e.messages.iter().skip(1).cloned()).collect(),
+        comments,
+    };
+    Ok(spec.to_modelfile())
+}
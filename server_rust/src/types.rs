@@ -1,10 +1,20 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+#[cfg(feature = "server")]
 use rocket::serde::json::Json;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-#[derive(Serialize)]
+use crate::aggregate::AggregationResult;
+use crate::ai::{AnswerStatus, Strictness};
+use crate::escalation::Escalation;
+use crate::prompts::PromptVersion;
+use crate::sampling::Consistency;
+use crate::warnings::Warning;
+use crate::VatReport;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct ErrorResponse {
     pub error: bool,
     pub code: String,
@@ -15,23 +25,153 @@ pub struct ErrorResponse {
     pub query: Option<String>,
 }
 
-#[derive(Serialize)]
+impl std::fmt::Display for ErrorResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for ErrorResponse {}
+
+/// A document left out because it could not be read
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SkippedFile {
+    pub file: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct ApiResponse {
     pub answer: serde_json::Value,
+    /// answered, not_found or ambiguous
+    pub status: AnswerStatus,
     pub used_files: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification: Option<Vec<VatReport>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<Object>))]
+    pub consistency: Option<Consistency>,
+    /// Exact figures computed for an aggregation question (also given to the model)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<Object>))]
+    pub aggregation: Option<AggregationResult>,
+    /// Provenance (JSON object, or a Graphviz DOT string) when explain is requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Value>,
+    /// Ways the answer breaks the requested schema (absent when no schema was given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema_errors: Option<Vec<String>>,
+    /// Retrieved documents that could not be read (binary, not UTF-8, too large...)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_files: Vec<SkippedFile>,
+    /// Answer fields removed by the output profile
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redacted: Vec<String>,
+    /// Problems that did not stop the answer, with machine-readable codes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Set when a larger model answered because the first answer failed a check
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escalation: Option<Escalation>,
+    /// Prompt template, its version and the hash of the prompt as sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<PromptVersion>,
+    /// Total model time, summed over all calls
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elapsed_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct QueryRequest {
     pub query: String,
     #[serde(default)]  // makes category optional, defaults to None
     pub category: Option<String>,
+    /// Collection name; takes precedence over `category`
+    #[serde(default)]
+    pub collection: Option<String>,
+    /// Several collections queried together; takes precedence over `collection`
+    #[serde(default)]
+    pub collections: Option<Vec<String>>,
+    /// Documents placed in the prompt per collection (overrides `--top-k`)
+    #[serde(default)]
+    pub top_k: Option<usize>,
+    /// "lax", "normal" or "strict" (overrides `--strictness`)
+    #[serde(default)]
+    pub strictness: Option<Strictness>,
+    /// Split compound questions into parts answered separately (overrides `--decompose`)
+    #[serde(default)]
+    pub decompose: Option<bool>,
+    /// Let the model fetch documents through tools (overrides `--agent`)
+    #[serde(default)]
+    pub agent: Option<bool>,
+    /// Longest answer in tokens (overrides `--max-answer-tokens` and `num_predict`)
+    #[serde(default)]
+    pub max_answer_tokens: Option<i32>,
+    /// Number of self-consistency samples (overrides `--samples`)
+    #[serde(default)]
+    pub samples: Option<usize>,
+    /// Include answer provenance (overrides `--explain`)
+    #[serde(default)]
+    pub explain: Option<bool>,
+    /// "json" (default) or "dot" for Graphviz
+    #[serde(default)]
+    pub explain_format: Option<String>,
+    /// Answer schema name from the config file (overrides `--schema`)
+    #[serde(default)]
+    pub schema: Option<String>,
+    /// Document domain whose prompt, schema and checks to use, e.g. "contracts" (overrides `--domain`)
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// Only documents in these statuses, e.g. "approved,paid" (overrides `--invoice-status`)
+    #[serde(default)]
+    pub invoice_status: Option<String>,
+    /// Use superseded documents in totals too (overrides `--include-superseded`)
+    #[serde(default)]
+    pub include_superseded: Option<bool>,
+    /// Fail on unreadable documents instead of skipping them (overrides `--strict`)
+    #[serde(default)]
+    pub strict: Option<bool>,
+    /// Output profile from the config file; ignored when the server runs with `--output-profile`
+    #[serde(default)]
+    pub output_profile: Option<String>,
+    /// Locale for amounts and dates in the answer, e.g. "en-ZA" (overrides `--locale`)
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Fail when the answer has warnings (overrides `--deny-warnings`)
+    #[serde(default)]
+    pub deny_warnings: Option<bool>,
+}
+
+/// Body of POST /documents/annotate
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AnnotateRequest {
+    /// File name, e.g. inv_001 or inv_001.txt
+    pub doc: String,
+    /// Needed when several collections have a file of that name
+    #[serde(default)]
+    pub collection: Option<String>,
+    /// "+disputed" adds a tag, "-paid" takes one off (a bare word adds)
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Free-text note, shown to the model with the document
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Remove the document's notes before adding `note`
+    #[serde(default)]
+    pub clear_notes: bool,
 }
 
 // Consistent response envelope
 #[derive(serde::Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Envelope {
     pub success: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -59,6 +199,7 @@ impl Envelope {
     }
 }
 
+#[cfg(feature = "server")]
 impl From<Envelope> for Json<Value> {
     fn from(envelope: Envelope) -> Self {
         let value = serde_json::to_value(envelope).expect("Envelope serialization failed");
@@ -0,0 +1,306 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// VAT / tax consistency checks.
+// Small models are unreliable at arithmetic, so the numbers on an invoice are
+// re-computed here from the source text instead of trusting the model.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Differences up to one cent are treated as rounding, not as errors
+const TOLERANCE: Decimal = Decimal::from_parts(1, 0, 0, false, 2);
+
+static AMOUNT_RE: Lazy<Regex> = Lazy::new(|| {
+    // ASCII digits only, so digits of other scripts are not dropped from the amount
+    Regex::new(r"(?:[A-Z]{1,3}|[$€£])?\s?([0-9]{1,3}(?:[ ,][0-9]{3})+|[0-9]+)\.([0-9]{2})\b").unwrap()
+});
+
+static LINE_ITEM_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^(?P<desc>\S.*?)\s{2,}(?P<qty>\d+(?:\.\d+)?)\s+(?P<unit>\S+\.\d{2})\s+(?P<total>\S+\.\d{2})\s*$",
+    )
+    .unwrap()
+});
+
+static RATE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(\d+(?:\.\d+)?)\s?%").unwrap());
+
+static PARENTHESES_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\([^)]*\)").unwrap());
+
+/// Labels of the figures, compared whole so "Total VAT" is not read as the total
+const TAX_LABELS: &[&str] = &["vat", "tax", "sales tax", "vat rate", "tax rate", "vat amount", "tax amount", "total vat", "total tax", "vat total"];
+const NET_LABELS: &[&str] = &["subtotal", "sub-total", "sub total", "net", "net total", "net amount", "total excl. vat"];
+const GROSS_LABELS: &[&str] = &["total", "grand total", "total due", "amount due", "balance due", "total incl. vat"];
+
+static VAT_NUMBER_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\bVAT\s*(?:Reg(?:istration)?\.?\s*)?(?:No\.?|Number|#)?\s*:?\s*([A-Z]{2}[\dA-Z]{8,12}|\d{9,12})\b")
+        .unwrap()
+});
+
+/// VAT number formats per country (prefix as printed on the invoice)
+static VAT_FORMATS: Lazy<Vec<(&'static str, Regex)>> = Lazy::new(|| {
+    [
+        ("ZA", r"^4\d{9}$"),
+        ("GB", r"^GB(\d{9}|\d{12}|GD\d{3}|HA\d{3})$"),
+        ("DE", r"^DE\d{9}$"),
+        ("FR", r"^FR[0-9A-Z]{2}\d{9}$"),
+        ("NL", r"^NL\d{9}B\d{2}$"),
+        ("BE", r"^BE[01]\d{9}$"),
+        ("IT", r"^IT\d{11}$"),
+        ("ES", r"^ES[0-9A-Z]\d{7}[0-9A-Z]$"),
+        ("IE", r"^IE\d{7}[A-W][A-I]?$"),
+        ("AT", r"^ATU\d{8}$"),
+    ]
+    .into_iter()
+    .map(|(country, pattern)| (country, Regex::new(pattern).unwrap()))
+    .collect()
+});
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LineItem {
+    pub description: String,
+    pub quantity: Decimal,
+    pub unit_price: Decimal,
+    pub total: Decimal,
+}
+
+/// Figures read directly from the invoice text (no model involved)
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct InvoiceFigures {
+    pub line_items: Vec<LineItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub net: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tax_rate: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tax: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gross: Option<Decimal>,
+    pub vat_numbers: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct VatIssue {
+    pub code: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub found: Option<Decimal>,
+}
+
+/// Result of checking a single invoice
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct VatReport {
+    pub file: String,
+    pub consistent: bool,
+    pub figures: InvoiceFigures,
+    pub issues: Vec<VatIssue>,
+}
+
+/// Parse a money string such as "R8,866.50", "$ 1 200.00" or "450.00"
+pub fn parse_amount(s: &str) -> Option<Decimal> {
+    let caps = AMOUNT_RE.captures(s)?;
+    let whole: String = caps[1].chars().filter(|c| c.is_ascii_digit()).collect();
+    Decimal::from_str(&format!("{}.{}", whole, &caps[2])).ok()
+}
+
+/// Last money amount on a line (labels like "VAT (15%):" precede the value)
+fn last_amount(line: &str) -> Option<Decimal> {
+    AMOUNT_RE
+        .find_iter(line)
+        .last()
+        .and_then(|m| parse_amount(m.as_str()))
+}
+
+/// The label in front of a figure, lowercased and without "(15%)"-style remarks:
+/// what precedes the colon, or else the first amount or rate
+fn label(line: &str) -> String {
+    let end = line.find(':').unwrap_or_else(|| {
+        let amount = AMOUNT_RE.find(line).map_or(line.len(), |m| m.start());
+        let rate = RATE_RE.find(line).map_or(line.len(), |m| m.start());
+        amount.min(rate)
+    });
+    let label = PARENTHESES_RE.replace_all(&line[..end], " ").to_lowercase();
+    label.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Extract line items, totals, tax rate and VAT numbers from invoice text
+pub fn extract_figures(text: &str) -> InvoiceFigures {
+    let mut figures = InvoiceFigures::default();
+
+    for raw in text.lines() {
+        let line = raw.trim();
+        let label = label(line);
+
+        if TAX_LABELS.contains(&label.as_str()) {
+            if let Some(rate) = RATE_RE.captures(line) {
+                figures.tax_rate = Decimal::from_str(&rate[1]).ok();
+            }
+            if let Some(amount) = last_amount(line) {
+                figures.tax = Some(amount);
+            }
+        } else if NET_LABELS.contains(&label.as_str()) {
+            figures.net = last_amount(line).or(figures.net);
+        } else if GROSS_LABELS.contains(&label.as_str()) {
+            figures.gross = last_amount(line).or(figures.gross);
+        } else if let Some(caps) = LINE_ITEM_RE.captures(line) {
+            let (Some(unit_price), Some(total)) = (parse_amount(&caps["unit"]), parse_amount(&caps["total"])) else {
+                continue;
+            };
+            let Ok(quantity) = Decimal::from_str(&caps["qty"]) else {
+                continue;
+            };
+            figures.line_items.push(LineItem {
+                description: caps["desc"].trim().to_string(),
+                quantity,
+                unit_price,
+                total,
+            });
+        }
+    }
+
+    for caps in VAT_NUMBER_RE.captures_iter(text) {
+        figures.vat_numbers.push(caps[1].to_uppercase());
+    }
+
+    figures
+}
+
+/// Validate a VAT number against the known per-country formats.
+/// Numbers without a country prefix are checked as South African (the demo's home market).
+pub fn validate_vat_number(number: &str) -> Result<&'static str, String> {
+    let cleaned: String = number
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_uppercase();
+
+    let country = if cleaned.len() > 2 && cleaned.chars().take(2).all(|c| c.is_ascii_alphabetic()) {
+        &cleaned[..2]
+    } else {
+        "ZA"
+    };
+
+    match VAT_FORMATS.iter().find(|(c, _)| *c == country) {
+        Some((c, re)) if re.is_match(&cleaned) => Ok(*c),
+        Some((c, _)) => Err(format!("'{}' is not a valid {} VAT number", number, c)),
+        None => Err(format!("No VAT number format known for country '{}'", country)),
+    }
+}
+
+fn issue(code: &str, message: String, expected: Option<Decimal>, found: Option<Decimal>) -> VatIssue {
+    VatIssue {
+        code: code.to_string(),
+        message,
+        expected,
+        found,
+    }
+}
+
+/// A figure too large to compute with, reported rather than panicking on
+fn overflow(what: &str) -> VatIssue {
+    issue("amount_overflow", format!("{} is too large to check", what), None, None)
+}
+
+fn differs(a: Decimal, b: Decimal) -> bool {
+    (a - b).abs() > TOLERANCE
+}
+
+/// Run all tax consistency rules over one invoice
+pub fn check_invoice(file: &str, text: &str) -> VatReport {
+    let figures = extract_figures(text);
+    let mut issues = Vec::new();
+
+    for item in &figures.line_items {
+        let Some(expected) = item.quantity.checked_mul(item.unit_price).map(|d| d.round_dp(2)) else {
+            issues.push(overflow(&format!("'{}': {} x {}", item.description, item.quantity, item.unit_price)));
+            continue;
+        };
+        if differs(expected, item.total) {
+            issues.push(issue(
+                "line_total_mismatch",
+                format!("'{}': {} x {} != {}", item.description, item.quantity, item.unit_price, item.total),
+                Some(expected),
+                Some(item.total),
+            ));
+        }
+    }
+
+    if let Some(net) = figures.net {
+        if !figures.line_items.is_empty() {
+            match figures.line_items.iter().try_fold(Decimal::ZERO, |sum, i| sum.checked_add(i.total)) {
+                None => issues.push(overflow("The sum of the line items")),
+                Some(sum) if differs(sum, net) => issues.push(issue(
+                    "net_mismatch",
+                    "Line items do not add up to the subtotal".to_string(),
+                    Some(sum),
+                    Some(net),
+                )),
+                Some(_) => {}
+            }
+        }
+
+        match (figures.tax_rate, figures.tax) {
+            (Some(rate), Some(tax)) => match net.checked_mul(rate) {
+                None => issues.push(overflow(&format!("VAT at {}% of {}", rate, net))),
+                Some(product) => {
+                    let expected = (product / Decimal::ONE_HUNDRED).round_dp(2);
+                    if differs(expected, tax) {
+                        issues.push(issue(
+                            "tax_mismatch",
+                            format!("VAT at {}% of {} should be {}", rate, net, expected),
+                            Some(expected),
+                            Some(tax),
+                        ));
+                    }
+                }
+            },
+            (None, Some(_)) => issues.push(issue(
+                "tax_rate_missing",
+                "VAT amount present but no rate stated".to_string(),
+                None,
+                None,
+            )),
+            _ => {}
+        }
+
+        if let (Some(tax), Some(gross)) = (figures.tax, figures.gross) {
+            match net.checked_add(tax) {
+                None => issues.push(overflow("net + tax")),
+                Some(sum) if differs(sum, gross) => issues.push(issue(
+                    "gross_mismatch",
+                    "net + tax != gross".to_string(),
+                    Some(sum),
+                    Some(gross),
+                )),
+                Some(_) => {}
+            }
+        }
+    } else {
+        issues.push(issue("net_missing", "No subtotal / net amount found".to_string(), None, None));
+    }
+
+    if figures.gross.is_none() {
+        issues.push(issue("gross_missing", "No total / gross amount found".to_string(), None, None));
+    }
+
+    for number in &figures.vat_numbers {
+        if let Err(message) = validate_vat_number(number) {
+            issues.push(issue("invalid_vat_number", message, None, None));
+        }
+    }
+
+    VatReport {
+        file: file.to_string(),
+        consistent: issues.is_empty(),
+        figures,
+        issues,
+    }
+}
@@ -0,0 +1,196 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Document versions: corrected invoices supersede the original, credit notes
+// are linked to the invoice they credit. Links are detected from references
+// in the text ("Replaces invoice INV-2025-001", "Credit note against ...").
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::docid::is_text_file;
+use crate::metadata::is_hidden;
+use crate::{collections, get_cached_content};
+
+static HEADER_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?im)^\s*(?P<kind>credit\s+note|(?:corrected|revised|replacement)\s+(?:tax\s+)?invoice|(?:tax\s+)?invoice|purchase\s+order)\s*(?:#|no\.?|number)?\s*:?\s*(?P<id>[A-Z0-9][A-Z0-9/\-]*\d[A-Z0-9/\-]*)",
+    )
+    .unwrap()
+});
+
+static SUPERSEDES_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)\b(?:replaces|supersedes|corrects|correction\s+(?:of|to)|cancels)\s+(?:(?:tax\s+)?invoice\s*)?(?:#|no\.?|number)?\s*:?\s*(?P<id>[A-Z0-9][A-Z0-9/\-]*\d[A-Z0-9/\-]*)",
+    )
+    .unwrap()
+});
+
+static CREDITS_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)\b(?:credit\s+note\s+(?:for|against|on)|original\s+invoice|against\s+invoice|in\s+respect\s+of\s+invoice)\s*(?:#|no\.?|number)?\s*:?\s*(?P<id>[A-Z0-9][A-Z0-9/\-]*\d[A-Z0-9/\-]*)",
+    )
+    .unwrap()
+});
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentKind {
+    Original,
+    Correction,
+    CreditNote,
+}
+
+/// Identity and references of one document, as read from its text
+#[derive(Serialize, Debug, Clone)]
+pub struct DocumentVersion {
+    pub path: PathBuf,
+    pub id: Option<String>,
+    pub kind: DocumentKind,
+    /// Document ids this one replaces
+    pub supersedes: Vec<String>,
+    /// Document ids this credit note applies to
+    pub credits: Vec<String>,
+}
+
+impl DocumentVersion {
+    pub fn from_text(path: &Path, text: &str) -> Self {
+        let header = HEADER_RE.captures(text);
+        let kind = match header.as_ref().map(|c| c["kind"].to_lowercase()) {
+            Some(k) if k.starts_with("credit") => DocumentKind::CreditNote,
+            Some(k) if k.starts_with("corrected") || k.starts_with("revised") || k.starts_with("replacement") => {
+                DocumentKind::Correction
+            }
+            _ => DocumentKind::Original,
+        };
+        let id = header.map(|c| normalize_id(&c["id"]));
+        let refs = |re: &Regex| -> Vec<String> {
+            let mut ids: Vec<String> = re
+                .captures_iter(text)
+                .map(|c| normalize_id(&c["id"]))
+                .filter(|r| Some(r) != id.as_ref())
+                .collect();
+            ids.sort();
+            ids.dedup();
+            ids
+        };
+
+        let mut supersedes = refs(&SUPERSEDES_RE);
+        let mut credits = refs(&CREDITS_RE);
+        if kind == DocumentKind::Correction {
+            // "Corrected invoice ... Original invoice: X" means it replaces X
+            supersedes.append(&mut credits);
+        }
+
+        Self { path: path.to_path_buf(), id, kind, supersedes, credits }
+    }
+}
+
+fn normalize_id(id: &str) -> String {
+    id.trim_end_matches(['.', ',', '-', '/']).to_uppercase()
+}
+
+/// Links between all indexed documents
+#[derive(Debug, Clone, Default)]
+pub struct VersionGraph {
+    pub documents: Vec<DocumentVersion>,
+    /// path → paths of the documents replacing it
+    superseded_by: HashMap<PathBuf, Vec<PathBuf>>,
+    /// path → paths of credit notes against it
+    credited_by: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl VersionGraph {
+    pub fn build(documents: Vec<DocumentVersion>) -> Self {
+        let mut superseded_by: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+        let mut credited_by: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+
+        for doc in &documents {
+            for (refs, links) in [(&doc.supersedes, &mut superseded_by), (&doc.credits, &mut credited_by)] {
+                for target in refs {
+                    // Only link within the same folder: ids are not unique across collections
+                    for other in documents.iter().filter(|o| {
+                        o.id.as_ref() == Some(target) && o.path != doc.path && o.path.parent() == doc.path.parent()
+                    }) {
+                        links.entry(other.path.clone()).or_default().push(doc.path.clone());
+                    }
+                }
+            }
+        }
+        Self { documents, superseded_by, credited_by }
+    }
+
+    pub fn is_superseded(&self, path: &Path) -> bool {
+        self.superseded_by.contains_key(path)
+    }
+
+    pub fn superseded_by(&self, path: &Path) -> &[PathBuf] {
+        self.superseded_by.get(path).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn credited_by(&self, path: &Path) -> &[PathBuf] {
+        self.credited_by.get(path).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&DocumentVersion> {
+        self.documents.iter().find(|d| d.path == path)
+    }
+
+    /// Number of superseded documents under `folder`
+    pub fn superseded_in(&self, folder: &Path) -> usize {
+        self.superseded_by.keys().filter(|p| p.starts_with(folder)).count()
+    }
+
+    /// Short note for the prompt describing how `path` relates to other documents
+    pub fn note(&self, path: &Path) -> Option<String> {
+        let name = |p: &PathBuf| p.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let mut notes = Vec::new();
+
+        if let Some(doc) = self.get(path) {
+            if !doc.supersedes.is_empty() {
+                notes.push(format!("replaces {}", doc.supersedes.join(", ")));
+            }
+            if !doc.credits.is_empty() {
+                notes.push(format!("credit note against {}", doc.credits.join(", ")));
+            }
+        }
+        let newer = self.superseded_by(path);
+        if !newer.is_empty() {
+            notes.push(format!("SUPERSEDED by {}", newer.iter().map(name).collect::<Vec<_>>().join(", ")));
+        }
+        let credits = self.credited_by(path);
+        if !credits.is_empty() {
+            notes.push(format!("credited by {}", credits.iter().map(name).collect::<Vec<_>>().join(", ")));
+        }
+
+        (!notes.is_empty()).then(|| notes.join("; "))
+    }
+}
+
+/// True for questions that add up or compare many documents (totals, counts, averages...)
+pub fn is_aggregation(question: &str) -> bool {
+    let lower = question.to_lowercase();
+    ["total", "sum", "all ", "how much", "how many", "spend", "spent", "average", "altogether", "combined", "outstanding"]
+        .iter()
+        .any(|w| lower.contains(w))
+}
+
+/// Version links across every collection, built once like the inverted index
+pub static VERSION_GRAPH: Lazy<VersionGraph> = Lazy::new(|| {
+    let mut documents = Vec::new();
+    for collection in collections() {
+        let Ok(entries) = fs::read_dir(&collection.folder) else { continue };
+        for path in entries.flatten().map(|e| e.path()) {
+            if !is_text_file(&path) || is_hidden(&path) {
+                continue;
+            }
+            if let Ok(text) = get_cached_content(&path) {
+                documents.push(DocumentVersion::from_text(&path, &text));
+            }
+        }
+    }
+    VersionGraph::build(documents)
+});
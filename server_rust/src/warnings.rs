@@ -0,0 +1,113 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Structured warnings: problems that did not stop the answer (a document left
+// out, a prompt longer than the context window, failed invoice checks...),
+// returned with the answer under a machine-readable code instead of only
+// going to the server log. With `--deny-warnings` any of them is an error.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::VatReport;
+
+/// Ollama's context size when `num_ctx` is not set
+pub const DEFAULT_NUM_CTX: u32 = 2048;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum WarningCode {
+    /// A retrieved document could not be read and was left out
+    SkippedFile,
+    /// A document is not valid UTF-8; the invalid bytes were replaced
+    LossyDecoding,
+    /// The prompt is probably longer than the context window, so the model did not see all of it
+    ContextTruncated,
+    /// The invoice checks found a problem (details under `verification`)
+    VerificationFailed,
+    /// The answer breaks the requested schema (details under `schema_errors`)
+    SchemaMismatch,
+    /// The model did not answer in JSON (its text is under `answer.raw`)
+    UnparsedAnswer,
+    /// Agent mode was asked for, but the model cannot call tools
+    ToolsUnsupported,
+    /// An answer field fails a check of the collection's domain (a date that is no date...)
+    InvalidField,
+    /// A clause's quote is not found in the chunk it cites
+    UngroundedClause,
+    /// The answer cites a document or chunk the model was not given
+    UnknownSource,
+    /// Documents changed on disk after the index in use was built
+    StaleIndex,
+}
+
+impl WarningCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WarningCode::SkippedFile => "skipped_file",
+            WarningCode::LossyDecoding => "lossy_decoding",
+            WarningCode::ContextTruncated => "context_truncated",
+            WarningCode::VerificationFailed => "verification_failed",
+            WarningCode::SchemaMismatch => "schema_mismatch",
+            WarningCode::UnparsedAnswer => "unparsed_answer",
+            WarningCode::ToolsUnsupported => "tools_unsupported",
+            WarningCode::InvalidField => "invalid_field",
+            WarningCode::UngroundedClause => "ungrounded_clause",
+            WarningCode::UnknownSource => "unknown_source",
+            WarningCode::StaleIndex => "stale_index",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Warning {
+    pub code: WarningCode,
+    pub message: String,
+    /// The document concerned, if there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+}
+
+impl Warning {
+    pub fn new(code: WarningCode, message: impl Into<String>) -> Self {
+        Self { code, message: message.into(), file: None }
+    }
+
+    pub fn file(mut self, file: impl Into<String>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+}
+
+/// Rough token count (about four characters per token for English text)
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// One warning per document failing the invoice checks
+pub fn verification_warnings(reports: &[VatReport]) -> Vec<Warning> {
+    reports
+        .iter()
+        .filter(|r| !r.issues.is_empty())
+        .map(|r| {
+            Warning::new(WarningCode::VerificationFailed, format!("{} problem(s) found by the invoice checks", r.issues.len()))
+                .file(&r.file)
+        })
+        .collect()
+}
+
+/// A warning if the answer breaks the schema
+pub fn schema_warning(errors: &[String]) -> Option<Warning> {
+    (!errors.is_empty()).then(|| {
+        Warning::new(WarningCode::SchemaMismatch, format!("The answer breaks the schema in {} place(s)", errors.len()))
+    })
+}
+
+/// A warning if the answer is not JSON
+pub fn unparsed_warning(answer: &Value) -> Option<Warning> {
+    answer
+        .as_object()
+        .is_some_and(|o| o.len() == 1 && o.contains_key("raw"))
+        .then(|| Warning::new(WarningCode::UnparsedAnswer, "The model did not answer in JSON"))
+}
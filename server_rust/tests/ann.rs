@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Stored vectors and nearest-neighbour lists, on made-up embeddings: vectors
+// are written as int8 and read back within rounding (arrays from older
+// versions still load), the lists find a chunk's document while scoring a
+// fraction of the vectors, and lists that no longer match are not used.
+
+use std::path::{Path, PathBuf};
+
+use doc_ai_server::ann::{MIN_VECTORS, PROBE};
+use doc_ai_server::docid::doc_key;
+use doc_ai_server::embeddings::{ChunkVector, EmbeddedDoc};
+use doc_ai_server::{Category, Collection, EmbeddingIndex};
+
+const DIM: usize = 16;
+
+/// Deterministic numbers in -1..1 (xorshift)
+struct Noise(u64);
+
+impl Noise {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % 20_001) as f32 / 10_000.0 - 1.0
+    }
+
+    fn vector(&mut self) -> Vec<f32> {
+        (0..DIM).map(|_| self.next()).collect()
+    }
+}
+
+fn collection() -> Collection {
+    let mut collection = Collection::from_category(&Category::Invoices);
+    collection.folder = std::env::temp_dir().join("doc-ai-ann").join("invoices");
+    collection
+}
+
+fn doc_path(collection: &Collection, n: usize) -> PathBuf {
+    collection.folder.join(format!("doc_{:04}.txt", n))
+}
+
+/// `docs` documents of ten chunks, each near one of forty topics
+fn index(collection: &Collection, docs: usize) -> EmbeddingIndex {
+    let mut noise = Noise(0x5eed);
+    let topics: Vec<Vec<f32>> = (0..40).map(|_| noise.vector()).collect();
+    let mut index = EmbeddingIndex { model: "test".to_string(), ..EmbeddingIndex::default() };
+    for n in 0..docs {
+        let chunks = (0..10)
+            .map(|c| {
+                let topic = &topics[(n * 7 + c * 3) % topics.len()];
+                let vector = topic.iter().zip(noise.vector()).map(|(t, e)| t + 0.05 * e).collect();
+                ChunkVector { id: format!("p1c{}", c + 1), vector }
+            })
+            .collect();
+        let doc = EmbeddedDoc { hash: format!("h{}", n), vector: vec![0.0; DIM], chunks };
+        index.documents.insert(doc_key(&doc_path(collection, n)), doc);
+    }
+    index
+}
+
+fn similarity(index: &EmbeddingIndex, path: &Path, query: &[f32]) -> f32 {
+    index.get(path).unwrap().similarity(query)
+}
+
+#[test]
+fn the_lists_find_the_nearest_documents() {
+    let invoices = collection();
+    let mut index = index(&invoices, 400);
+    index.build_ann();
+    let ann = index.ann.clone().expect("4000 vectors get lists");
+    assert_eq!((ann.vectors, ann.lists.iter().map(Vec::len).sum::<usize>()), (4000, 4000));
+
+    // A question close to one chunk of doc 123
+    let target = doc_path(&invoices, 123);
+    let mut noise = Noise(42);
+    let query: Vec<f32> =
+        index.get(&target).unwrap().chunks[4].vector.iter().zip(noise.vector()).map(|(x, e)| x + 0.01 * e).collect();
+
+    assert!(ann.candidates(&query, PROBE).len() < 4000 / 3);
+    let found = index.nearest(&query, &invoices).expect("enough documents near the query");
+    assert!(found.contains(&target));
+    // Whatever a full scan ranks first is among them
+    let best = (0..400)
+        .map(|n| doc_path(&invoices, n))
+        .max_by(|a, b| similarity(&index, a, &query).total_cmp(&similarity(&index, b, &query)))
+        .unwrap();
+    assert!(found.contains(&best));
+
+    // No documents of another collection
+    let mut receipts = collection();
+    receipts.folder = invoices.folder.with_file_name("receipts");
+    assert_eq!(index.nearest(&query, &receipts), None);
+}
+
+#[test]
+fn vectors_are_stored_as_int8() {
+    let invoices = collection();
+    let mut index = index(&invoices, 250);
+    index.build_ann();
+    let json = serde_json::to_string(&index).unwrap();
+    assert!(json.contains("\"i8:"));
+
+    let loaded: EmbeddingIndex = serde_json::from_str(&json).unwrap();
+    for (key, doc) in &index.documents {
+        for (a, b) in doc.chunks.iter().zip(&loaded.documents[key].chunks) {
+            let max = a.vector.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+            assert!(a.vector.iter().zip(&b.vector).all(|(x, y)| (x - y).abs() <= max / 254.0 + 1e-6));
+        }
+    }
+    assert_eq!(loaded.ann.as_ref().map(|a| &a.lists), index.ann.as_ref().map(|a| &a.lists));
+
+    // Arrays of numbers, as written before
+    let old = r#"{"model": "m", "documents": {"invoices/a.txt": {"hash": "h", "vector": [0.5, -1.0]}}}"#;
+    let old: EmbeddingIndex = serde_json::from_str(old).unwrap();
+    assert_eq!(old.documents["invoices/a.txt"].vector, [0.5, -1.0]);
+    assert!(old.ann.is_none());
+}
+
+#[test]
+fn lists_that_no_longer_match_are_not_used() {
+    let invoices = collection();
+    let mut index = index(&invoices, 400);
+    index.build_ann();
+    let query = index.get(&doc_path(&invoices, 7)).unwrap().chunks[0].vector.clone();
+    assert!(index.nearest(&query, &invoices).is_some());
+    index.documents.remove(&doc_key(&doc_path(&invoices, 7)));
+    assert_eq!(index.nearest(&query, &invoices), None);
+
+    // Small indexes are scanned
+    let mut small = self::index(&invoices, MIN_VECTORS / 10 - 1);
+    small.build_ann();
+    assert!(small.ann.is_none());
+}
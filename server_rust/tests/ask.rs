@@ -0,0 +1,57 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// `doc-ai-server ask` against a mock Ollama (wiremock): whatever the library
+// reports while answering (documents read, index loading, migrations)
+// goes to stderr, so stdout parses as the one JSON envelope scripts expect.
+
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const ANSWER: &str = r#"{"status": "answered", "answer": "R8,866.50", "sources": ["inv_001.txt"]}"#;
+
+/// A working directory with the sample invoice and a config pointing at `ollama`
+fn workspace(name: &str, ollama: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("doc-ai-ask-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    for folder in ["invoices", "employment-contracts", "customer-support", "knowledge-base"] {
+        std::fs::create_dir_all(dir.join("data").join(folder)).unwrap();
+    }
+    std::fs::copy("../data/invoices/inv_001.txt", dir.join("data/invoices/inv_001.txt")).unwrap();
+    std::fs::write(dir.join("doc-ai.toml"), format!("ollama_url = \"{}\"\nmodel = \"mock\"\n", ollama)).unwrap();
+    dir
+}
+
+async fn ask(dir: PathBuf, question: &str) -> Output {
+    let question = question.to_string();
+    tokio::task::spawn_blocking(move || {
+        Command::new(env!("CARGO_BIN_EXE_doc-ai-server"))
+            .current_dir(dir)
+            .args(["--collection", "invoices", "ask", &question])
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn ask_prints_only_the_envelope_on_stdout() {
+    let server = MockServer::start().await;
+    let reply = json!({"response": ANSWER, "message": {"role": "assistant", "content": ANSWER}, "done": true, "eval_count": 42});
+    Mock::given(method("POST")).and(path("/api/generate")).respond_with(ResponseTemplate::new(200).set_body_json(&reply)).mount(&server).await;
+    Mock::given(method("POST")).and(path("/api/chat")).respond_with(ResponseTemplate::new(200).set_body_json(&reply)).mount(&server).await;
+    Mock::given(method("GET")).and(path("/api/tags")).respond_with(ResponseTemplate::new(200).set_body_json(json!({"models": [{"name": "mock"}]}))).mount(&server).await;
+
+    let output = ask(workspace("envelope", &server.uri()), "What is the total due on INV-2025-001?").await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let envelope: Value = serde_json::from_str(&stdout)
+        .unwrap_or_else(|e| panic!("stdout is not one JSON document ({}):\n{}\nstderr:\n{}", e, stdout, stderr));
+    assert_eq!(envelope["success"], json!(true), "{}", stderr);
+    assert_eq!(envelope["data"]["status"], json!("answered"));
+    assert!(stderr.contains("Searching invoices"), "progress should be on stderr:\n{}", stderr);
+    assert!(output.status.success());
+}
@@ -0,0 +1,111 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// `[[publish]]` targets against a mock webhook (wiremock): events reach the
+// targets whose collections and event kinds they match, signed when the
+// target has a secret, and a target that fails is reported, not fatal.
+
+use serde_json::Value;
+use std::path::Path;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use doc_ai_server::bus::{publish, signature, SIGNATURE_HEADER};
+use doc_ai_server::{check_invoice, BusEvent, EventKind, InvoiceRecord, PublishConfig};
+
+const INVOICE: &str = "Invoice INV-9\nVendor: ACME\nSubtotal: 100.00\nVAT (15%): 15.00\nTotal Due: 115.00\n";
+
+fn event(kind: EventKind, collection: &str) -> BusEvent {
+    let path = Path::new("data").join(collection).join("inv_009.txt");
+    let record = InvoiceRecord::from_text(collection, &path, INVOICE);
+    BusEvent {
+        event: kind,
+        collection: collection.to_string(),
+        file: record.file.clone(),
+        document: path.display().to_string(),
+        verification: check_invoice(&record.file, INVOICE),
+        record,
+        extraction: None,
+        at: 1_764_000_000,
+    }
+}
+
+fn target(toml: &str) -> PublishConfig {
+    toml::from_str(toml).unwrap()
+}
+
+async fn webhook(route: &str, status: u16) -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("POST")).and(path(route)).respond_with(ResponseTemplate::new(status)).mount(&server).await;
+    server
+}
+
+async fn received(server: &MockServer) -> Vec<Value> {
+    server.received_requests().await.unwrap().iter().map(|r| serde_json::from_slice(&r.body).unwrap()).collect()
+}
+
+#[tokio::test]
+async fn targets_get_the_events_they_want() {
+    let erp = webhook("/erp", 200).await;
+    let alerts = webhook("/alerts", 200).await;
+    let targets = [
+        target(&format!("target = \"webhook\"\nurl = \"{}/erp\"\ncollections = [\"invoices\"]", erp.uri())),
+        target(&format!("target = \"webhook\"\nurl = \"{}/alerts\"\nevents = [\"verified\"]", alerts.uri())),
+    ];
+    let events = [
+        event(EventKind::Extracted, "invoices"),
+        event(EventKind::Extracted, "receipts"),
+        event(EventKind::Verified, "receipts"),
+    ];
+    assert!(publish(&targets, &events).await.is_empty());
+
+    let to_erp = received(&erp).await;
+    assert_eq!(to_erp.len(), 1);
+    assert_eq!(to_erp[0]["event"], "extracted");
+    assert_eq!(to_erp[0]["collection"], "invoices");
+    assert_eq!(to_erp[0]["record"]["id"], "INV-9");
+    assert_eq!(to_erp[0]["verification"]["consistent"], true);
+
+    let to_alerts = received(&alerts).await;
+    assert_eq!(to_alerts.len(), 1);
+    assert_eq!((to_alerts[0]["event"].as_str(), to_alerts[0]["collection"].as_str()), (Some("verified"), Some("receipts")));
+}
+
+#[tokio::test]
+async fn webhook_bodies_are_signed() {
+    let server = webhook("/signed", 200).await;
+    unsafe { std::env::set_var("DOC_AI_TEST_WEBHOOK_SECRET", "s3cret") };
+    let signed = target(&format!(
+        "target = \"webhook\"\nurl = \"{}/signed\"\nsecret_env = \"DOC_AI_TEST_WEBHOOK_SECRET\"",
+        server.uri()
+    ));
+    assert!(publish(&[signed], &[event(EventKind::Extracted, "invoices")]).await.is_empty());
+
+    let requests = server.received_requests().await.unwrap();
+    let header = requests[0].headers.get(SIGNATURE_HEADER).unwrap().to_str().unwrap().to_string();
+    assert_eq!(header, signature("s3cret", &requests[0].body).unwrap());
+    assert!(header.starts_with("sha256=") && header.len() == "sha256=".len() + 64, "{}", header);
+    assert_ne!(header, signature("other", &requests[0].body).unwrap());
+}
+
+#[tokio::test]
+async fn failing_targets_are_reported() {
+    let down = webhook("/down", 503).await;
+    let up = webhook("/up", 200).await;
+    let targets = [
+        target(&format!("target = \"webhook\"\nurl = \"{}/down\"", down.uri())),
+        target(&format!("target = \"webhook\"\nurl = \"{}/up\"", up.uri())),
+        target("target = \"webhook\"\nurl = \"http://127.0.0.1:9/x\"\nsecret_env = \"DOC_AI_TEST_NOT_SET\""),
+    ];
+    let failures = publish(&targets, &[event(EventKind::Extracted, "invoices")]).await;
+    assert_eq!(failures.len(), 2, "{:?}", failures);
+    assert!(failures[0].contains("503"), "{}", failures[0]);
+    assert!(failures[1].contains("DOC_AI_TEST_NOT_SET is not set"), "{}", failures[1]);
+    // The others still got theirs
+    assert_eq!(received(&up).await.len(), 1);
+}
+
+#[test]
+fn unknown_targets_and_events_are_config_errors() {
+    assert!(toml::from_str::<PublishConfig>("target = \"smtp\"\nurl = \"x\"").is_err());
+    assert!(toml::from_str::<PublishConfig>("target = \"webhook\"\nurl = \"x\"\nevents = [\"deleted\"]").is_err());
+}
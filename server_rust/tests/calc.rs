@@ -0,0 +1,35 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// The `calculate` tool's evaluator: exact decimal arithmetic, and errors
+// instead of a stack overflow for expressions nested too deeply.
+
+use rust_decimal::Decimal;
+
+use doc_ai_server::calc::{evaluate, MAX_DEPTH};
+
+#[test]
+fn invoice_arithmetic_is_exact() {
+    assert_eq!(evaluate("R7,710.00 * 15%"), Ok(Decimal::new(115650, 2)));
+    assert_eq!(evaluate("(6000 + 1710) * 1.15"), Ok(Decimal::new(88665, 1)));
+    assert_eq!(evaluate("-(-2)"), Ok(Decimal::TWO));
+    assert_eq!(evaluate("1 / 0"), Err("Division by zero".to_string()));
+}
+
+#[test]
+fn nesting_up_to_the_limit_is_evaluated() {
+    let depth = MAX_DEPTH - 1;
+    let expression = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+    assert_eq!(evaluate(&expression), Ok(Decimal::ONE));
+}
+
+#[test]
+fn deeper_nesting_is_an_error() {
+    for expression in [
+        format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000)),
+        format!("{}1", "-".repeat(100_000)),
+        format!("{}1", "(-".repeat(MAX_DEPTH)),
+    ] {
+        let error = evaluate(&expression).unwrap_err();
+        assert!(error.contains("nested more than"), "{}", error);
+    }
+}
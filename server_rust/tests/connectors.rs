@@ -0,0 +1,167 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Intake connectors against mock Google Drive and Dropbox APIs (wiremock):
+// new files are fetched and saved, files already seen are not fetched again,
+// changed ones are and update their document, and content the collection
+// already has is a duplicate whatever its name.
+
+use serde_json::{json, Value};
+use std::fs;
+use wiremock::matchers::{body_json, header, method, path, query_param};
+use wiremock::{Mock, MockBuilder, MockServer, ResponseTemplate};
+
+use doc_ai_server::connectors::{poll, DriveConnector, DropboxConnector};
+use doc_ai_server::{Category, Collection, ConnectorConfig, ConnectorState, IngestOutcome};
+
+const INVOICE: &str = "Invoice INV-7\nVendor: ACME\nTotal: 115.00\n";
+
+fn collection(name: &str) -> Collection {
+    let mut collection = Collection::from_category(&Category::Invoices);
+    collection.folder = std::env::temp_dir().join(format!("doc-ai-connectors-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&collection.folder);
+    collection
+}
+
+fn config(name: &str, kind: &str, path: &str, server: &MockServer) -> ConnectorConfig {
+    let token = std::env::temp_dir().join(format!("doc-ai-connectors-{}-{}.token", std::process::id(), name));
+    fs::write(&token, "test-token\n").unwrap();
+    let toml = format!(
+        "name = \"{name}\"\nkind = \"{kind}\"\npath = \"{path}\"\napi_url = \"{}\"\nsecret_file = \"{}\"",
+        server.uri(),
+        token.display().to_string().replace('\\', "/")
+    );
+    toml::from_str(&toml).unwrap()
+}
+
+/// A mock of an authorized call
+fn api(method_name: &str, route: &str) -> MockBuilder {
+    Mock::given(method(method_name)).and(path(route)).and(header("authorization", "Bearer test-token"))
+}
+
+fn json_response(body: Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(body)
+}
+
+fn outcomes(pulled: &[doc_ai_server::Pulled]) -> Vec<(&str, &IngestOutcome)> {
+    pulled.iter().map(|p| (p.name.as_str(), &p.outcome)).collect()
+}
+
+#[tokio::test]
+async fn drive_files_are_taken_in_once() {
+    let server = MockServer::start().await;
+    let listing = json!({"files": [
+        {"id": "1", "name": "inv_007.txt", "mimeType": "text/plain", "md5Checksum": "m1"},
+        {"id": "2", "name": "copy of inv_007.txt", "mimeType": "text/plain", "md5Checksum": "m1"},
+        {"id": "3", "name": "Notes", "mimeType": "application/vnd.google-apps.document"},
+        {"id": "4", "name": "scan.pdf", "mimeType": "application/pdf", "md5Checksum": "m4"},
+    ]});
+    api("GET", "/drive/v3/files")
+        .and(query_param("q", "'folder-1' in parents and trashed = false"))
+        .respond_with(json_response(listing))
+        .mount(&server)
+        .await;
+    for id in ["1", "2"] {
+        api("GET", &format!("/drive/v3/files/{}", id))
+            .and(query_param("alt", "media"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(INVOICE))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+
+    let config = config("drive", "gdrive", "folder-1", &server);
+    let target = collection("drive");
+    let drive = DriveConnector::new(&config).unwrap();
+    let mut state = ConnectorState::default();
+    let pulled = poll(&drive, &config, &target, &mut state).await.unwrap();
+    assert_eq!(
+        outcomes(&pulled),
+        [
+            ("inv_007.txt", &IngestOutcome::Saved("inv_007.txt".into())),
+            ("copy of inv_007.txt", &IngestOutcome::Duplicate("inv_007.txt".into())),
+        ]
+    );
+    assert_eq!(fs::read_to_string(target.folder.join("inv_007.txt")).unwrap(), INVOICE);
+    assert_eq!(state.seen.len(), 2);
+    assert_eq!(fs::read_dir(&target.folder).unwrap().count(), 1);
+
+    // Nothing changed: nothing is fetched (the mocks expect one download each)
+    assert!(poll(&drive, &config, &target, &mut state).await.unwrap().is_empty());
+    assert!(state.polled.is_some());
+}
+
+#[tokio::test]
+async fn dropbox_pages_and_changed_files() {
+    let server = MockServer::start().await;
+    let first = json!({"entries": [{".tag": "file", "id": "id:a", "name": "inv_1.txt", "content_hash": "h1"}],
+                       "cursor": "c1", "has_more": true});
+    let second = json!({"entries": [{".tag": "folder", "id": "id:f", "name": "archive"},
+                                    {".tag": "file", "id": "id:b", "name": "inv_2.txt", "content_hash": "h2"}],
+                        "cursor": "c2", "has_more": false});
+    api("POST", "/2/files/list_folder")
+        .and(body_json(json!({"path": "/vendors/acme", "recursive": false})))
+        .respond_with(json_response(first))
+        .mount(&server)
+        .await;
+    api("POST", "/2/files/list_folder/continue")
+        .and(body_json(json!({"cursor": "c1"})))
+        .respond_with(json_response(second))
+        .mount(&server)
+        .await;
+    for (id, text) in [("id:a", INVOICE.to_string()), ("id:b", INVOICE.replace("INV-7", "INV-8"))] {
+        api("POST", "/2/files/download")
+            .and(header("Dropbox-API-Arg", json!({"path": id}).to_string().as_str()))
+            .respond_with(ResponseTemplate::new(200).set_body_string(text))
+            .mount(&server)
+            .await;
+    }
+
+    let config = config("dropbox", "dropbox", "vendors/acme/", &server);
+    let target = collection("dropbox");
+    let dropbox = DropboxConnector::new(&config).unwrap();
+    let mut state = ConnectorState::default();
+    let pulled = poll(&dropbox, &config, &target, &mut state).await.unwrap();
+    let saved: Vec<&IngestOutcome> = pulled.iter().map(|p| &p.outcome).collect();
+    assert_eq!(saved, [&IngestOutcome::Saved("inv_1.txt".into()), &IngestOutcome::Saved("inv_2.txt".into())]);
+
+    // inv_2.txt changes: it is fetched again and updates its document
+    server.reset().await;
+    let changed = json!({"entries": [{".tag": "file", "id": "id:a", "name": "inv_1.txt", "content_hash": "h1"},
+                                     {".tag": "file", "id": "id:b", "name": "inv_2.txt", "content_hash": "h2b"}],
+                         "has_more": false});
+    api("POST", "/2/files/list_folder").respond_with(json_response(changed)).mount(&server).await;
+    api("POST", "/2/files/download")
+        .respond_with(ResponseTemplate::new(200).set_body_string(INVOICE.replace("115", "230")))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let pulled = poll(&dropbox, &config, &target, &mut state).await.unwrap();
+    assert_eq!(outcomes(&pulled), [("inv_2.txt", &IngestOutcome::Updated("inv_2.txt".into()))]);
+    assert!(fs::read_to_string(target.folder.join("inv_2.txt")).unwrap().contains("230"));
+    assert_eq!(state.seen["id:b"], "h2b");
+}
+
+#[tokio::test]
+async fn failed_downloads_are_tried_again() {
+    let server = MockServer::start().await;
+    let listing = json!({"files": [{"id": "9", "name": "inv_009.txt", "mimeType": "text/plain", "md5Checksum": "m9"}]});
+    api("GET", "/drive/v3/files").respond_with(json_response(listing)).mount(&server).await;
+    api("GET", "/drive/v3/files/9").respond_with(ResponseTemplate::new(503)).mount(&server).await;
+
+    let config = config("down", "gdrive", "folder-9", &server);
+    let target = collection("down");
+    let drive = DriveConnector::new(&config).unwrap();
+    let mut state = ConnectorState::default();
+    let pulled = poll(&drive, &config, &target, &mut state).await.unwrap();
+    assert!(matches!(&pulled[0].outcome, IngestOutcome::Skipped(reason) if reason.contains("503")), "{:?}", pulled);
+    assert!(state.seen.is_empty());
+    assert_eq!(poll(&drive, &config, &target, &mut state).await.unwrap().len(), 1);
+}
+
+#[test]
+fn tokens_are_required() {
+    let config: ConnectorConfig = toml::from_str("name = \"d\"\nkind = \"dropbox\"\npath = \"/in\"").unwrap();
+    let error = DropboxConnector::new(&config).err().unwrap().to_string();
+    assert!(error.contains("needs an access token"), "{}", error);
+    assert!(toml::from_str::<ConnectorConfig>("name = \"x\"\nkind = \"ftp\"").is_err());
+}
@@ -0,0 +1,182 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Chunk embedding against a mock Ollama `/api/embed` (wiremock): chunks of
+// several documents share requests of `batch` inputs, `concurrency` requests
+// are in flight at once, unchanged documents are not sent again, and a
+// document ranks by its best chunk. The local backend is chosen by config.
+//
+// The mock's vectors count two words in each input, [alpha, beta], and it
+// fails inputs with "gamma".
+
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+use doc_ai_server::hosts::{init_hosts, HostConfig, OllamaConfig};
+use doc_ai_server::{Category, Collection, EmbedBackend, EmbedOptions, EmbeddingIndex, RetrievalConfig};
+
+/// How long the mock takes to answer a request
+const DELAY: Duration = Duration::from_millis(300);
+
+/// Answers every input with its word counts, recording the inputs of each request
+struct Counting {
+    requests: Mutex<Vec<(String, usize)>>,
+}
+
+impl Counting {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        let inputs: Vec<&str> = body["input"].as_array().unwrap().iter().map(|i| i.as_str().unwrap()).collect();
+        self.requests.lock().unwrap().push((body["model"].as_str().unwrap().to_string(), inputs.len()));
+        if inputs.iter().any(|t| t.contains("gamma")) {
+            return ResponseTemplate::new(500).set_body_string("model crashed");
+        }
+        let count = |text: &str, word: &str| text.matches(word).count() as f32;
+        let embeddings: Vec<Vec<f32>> = inputs.iter().map(|t| vec![count(t, "alpha"), count(t, "beta")]).collect();
+        ResponseTemplate::new(200).set_body_json(json!({ "embeddings": embeddings })).set_delay(DELAY)
+    }
+}
+
+static MOCK: OnceCell<(MockServer, &'static Counting)> = OnceCell::const_new();
+
+/// The mock, with the hosts installed on first use
+async fn mock() -> &'static Counting {
+    let (_, counting) = MOCK
+        .get_or_init(|| async {
+            let server = MockServer::start().await;
+            let counting: &'static Counting = Box::leak(Box::new(Counting { requests: Mutex::new(Vec::new()) }));
+            Mock::given(method("POST")).and(path("/api/embed")).respond_with(move |r: &Request| counting.respond(r)).mount(&server).await;
+            let config = OllamaConfig {
+                hosts: vec![HostConfig { url: server.uri(), models: Vec::new() }],
+                retries: 0,
+                ..OllamaConfig::default()
+            };
+            init_hosts(&config).unwrap();
+            (server, counting)
+        })
+        .await;
+    counting
+}
+
+/// Sizes of the requests made for `model`
+fn requests(counting: &Counting, model: &str) -> Vec<usize> {
+    counting.requests.lock().unwrap().iter().filter(|(m, _)| m == model).map(|(_, n)| *n).collect()
+}
+
+fn collection(name: &str) -> Collection {
+    let mut collection = Collection::from_category(&Category::Invoices);
+    collection.folder = std::env::temp_dir().join(format!("doc-ai-embeddings-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&collection.folder);
+    fs::create_dir_all(&collection.folder).unwrap();
+    collection
+}
+
+/// A paragraph long enough to be a chunk of its own
+fn paragraph(word: &str) -> String {
+    format!("{}\n\n", format!("{} filler text ", word).repeat(60))
+}
+
+fn write(collection: &Collection, name: &str, paragraphs: &[&str]) -> PathBuf {
+    let path = collection.folder.join(name);
+    fs::write(&path, paragraphs.iter().map(|w| paragraph(w)).collect::<String>()).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn chunks_go_in_concurrent_batches() {
+    let counting = mock().await;
+    let docs = collection("batches");
+    let paths: Vec<PathBuf> =
+        (1..=4).map(|n| write(&docs, &format!("doc_{}.txt", n), &["alpha", "beta", "alpha", "beta", "alpha"])).collect();
+
+    let mut index = EmbeddingIndex::default();
+    let options = EmbedOptions { batch: 3, concurrency: 4, ann: false, ..EmbedOptions::default() };
+    let started = Instant::now();
+    assert_eq!(index.embed_files("batched", paths.clone(), options).await.unwrap(), 4);
+    let elapsed = started.elapsed();
+
+    // 20 chunks in requests of at most 3, four at a time: two rounds, not seven
+    let sizes = requests(counting, "batched");
+    assert_eq!((sizes.len(), sizes.iter().sum::<usize>()), (7, 20), "{:?}", sizes);
+    assert!(sizes.iter().all(|&n| n <= 3), "{:?}", sizes);
+    assert!(elapsed < DELAY * 5, "{:?}", elapsed);
+
+    let doc = index.get(&paths[0]).unwrap();
+    let ids: Vec<&str> = doc.chunks.iter().map(|c| c.id.as_str()).collect();
+    assert_eq!(ids, ["p1c1", "p1c2", "p1c3", "p1c4", "p1c5"]);
+    assert!(doc.chunks[1].vector[1] > 0.0 && doc.chunks[1].vector[0] == 0.0);
+
+    // Unchanged documents are not sent again; a new one is, alone
+    assert_eq!(index.embed_files("batched", paths.clone(), options).await.unwrap(), 0);
+    let mut paths = paths;
+    paths.push(write(&docs, "doc_5.txt", &["beta"]));
+    assert_eq!(index.embed_files("batched", paths, options).await.unwrap(), 1);
+    assert_eq!(requests(counting, "batched").len(), 8);
+}
+
+#[tokio::test]
+async fn documents_rank_by_their_best_chunk() {
+    mock().await;
+    let docs = collection("ranking");
+    // Mostly alpha, with one paragraph on beta
+    let mixed = write(&docs, "mixed.txt", &["alpha", "alpha", "alpha", "beta"]);
+    let half = write(&docs, "half.txt", &["alpha beta"]);
+    let empty = docs.folder.join("empty.txt");
+    fs::write(&empty, "\n\n").unwrap();
+
+    let mut index = EmbeddingIndex::default();
+    let options = EmbedOptions { batch: 2, concurrency: 2, ann: false, ..EmbedOptions::default() };
+    assert_eq!(index.embed_files("ranked", vec![mixed.clone(), half.clone(), empty.clone()], options).await.unwrap(), 3);
+    assert!(index.get(&empty).unwrap().chunks.is_empty());
+
+    let ranked = index.rank("beta", &docs).await.unwrap();
+    let order: Vec<&PathBuf> = ranked.iter().map(|(p, _)| p).collect();
+    assert_eq!(order, [&mixed, &half, &empty]);
+    assert!((ranked[0].1 - 1.0).abs() < 0.01, "{:?}", ranked);
+}
+
+#[tokio::test]
+async fn a_failed_request_keeps_what_was_done() {
+    let counting = mock().await;
+    let docs = collection("failing");
+    let good = write(&docs, "good.txt", &["alpha"]);
+    let missing = docs.folder.join("missing.txt");
+    let bad = write(&docs, "bad.txt", &["gamma"]);
+
+    let mut index = EmbeddingIndex::default();
+    let options = EmbedOptions { batch: 1, concurrency: 1, ann: false, ..EmbedOptions::default() };
+    let error = index.embed_files("partial", vec![good.clone(), missing, bad.clone()], options).await.unwrap_err();
+    assert!(error.to_string().contains("model crashed"), "{:#}", error);
+    // The unreadable document is left out, the one before the failure kept
+    assert!(index.get(&good).is_some() && index.get(&bad).is_none());
+    assert_eq!(requests(counting, "partial"), [1, 1]);
+
+    // Another model starts over
+    assert_eq!(index.embed_files("partial-2", vec![good.clone()], options).await.unwrap(), 1);
+    assert_eq!((index.model.as_str(), index.documents.len()), ("partial-2", 1));
+}
+
+#[test]
+fn the_backend_comes_from_the_config() {
+    let config: RetrievalConfig = toml::from_str("embed_backend = \"local\"\nembed_batch = 8").unwrap();
+    let options = config.embed_options();
+    assert_eq!((options.backend, options.batch, options.concurrency), (EmbedBackend::Local, 8, 4));
+    assert!(toml::from_str::<RetrievalConfig>("embed_backend = \"openai\"").is_err());
+}
+
+#[cfg(not(feature = "local-embeddings"))]
+#[tokio::test]
+async fn local_embeddings_need_the_feature() {
+    let docs = collection("local");
+    let path = write(&docs, "doc.txt", &["alpha"]);
+    let mut index = EmbeddingIndex::default();
+    let options = EmbedOptions { backend: EmbedBackend::Local, ..EmbedOptions::default() };
+    let error = index.embed_files("nomic-embed-text", vec![path], options).await.unwrap_err();
+    assert!(error.to_string().contains("`local-embeddings` feature"), "{:#}", error);
+    assert_eq!(index.backend, EmbedBackend::Local);
+}
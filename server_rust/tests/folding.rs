@@ -0,0 +1,78 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Matching names however they are written: case, accents, umlauts spelled
+// out, ligatures, full-width letters and composed or decomposed characters,
+// in the folding functions and in file name scoring.
+
+use std::path::Path;
+
+use doc_ai_server::fold::{contains_folded, fold, fold_forms, folded_words, same_folded};
+use doc_ai_server::scoring::{FilenameScorer, RelevanceScorer};
+
+#[test]
+fn folds_case_accents_and_compatibility_forms() {
+    assert_eq!(fold("Société Générale"), "societe generale");
+    assert_eq!(fold("MÜLLER"), "muller");
+    assert_eq!(fold("Straße"), "strasse");
+    assert_eq!(fold("STRAẞE"), "strasse");
+    assert_eq!(fold("ﬁnance"), "finance");
+    assert_eq!(fold("ＡＣＭＥ"), "acme");
+    assert_eq!(fold("Ærø Øl"), "aero ol");
+    assert_eq!(fold("Łódź"), "lodz");
+    assert_eq!(fold("İstanbul"), "istanbul");
+    assert_eq!(fold("ΟΔΟΣ"), fold("οδος"));
+    assert_eq!(fold("inv_003"), "inv_003");
+}
+
+#[test]
+fn composed_and_decomposed_fold_alike() {
+    assert_eq!(fold("M\u{00fc}ller"), fold("Mu\u{0308}ller"));
+    assert!(same_folded("M\u{00fc}ller", "Mu\u{0308}ller"));
+}
+
+#[test]
+fn umlauts_have_a_german_form() {
+    assert_eq!(fold_forms("Müller"), vec!["muller", "mueller"]);
+    assert_eq!(fold_forms("Sørensen"), vec!["sorensen", "soerensen"]);
+    assert_eq!(fold_forms("Mueller"), vec!["mueller"]);
+    // Only umlauts spell out: ë and ï are just stripped
+    assert_eq!(fold_forms("Citroën"), vec!["citroen"]);
+}
+
+#[test]
+fn names_match_in_either_spelling() {
+    for (a, b) in [("Müller", "mueller"), ("mueller", "MÜLLER"), ("Müller", "Muller"), ("Jürgen Köhler", "juergen koehler")] {
+        assert!(same_folded(a, b), "{} / {}", a, b);
+        assert!(contains_folded(&format!("Invoice from {} GmbH", a), b), "{} / {}", a, b);
+    }
+    assert!(!same_folded("Müller", "Miller"));
+    assert!(!contains_folded("Mueller GmbH", "Möller"));
+    assert!(contains_folded("anything", ""));
+}
+
+#[test]
+fn words_of_every_form() {
+    let words = folded_words("Müller_inv_003");
+    for word in ["muller", "mueller", "inv", "003"] {
+        assert!(words.contains(word), "{} in {:?}", word, words);
+    }
+}
+
+#[test]
+fn file_names_match_umlauts_both_ways() {
+    let scorer = FilenameScorer;
+    let score = |query: &str, name: &str| scorer.score(query, &Path::new("data/invoices").join(name));
+    assert_eq!(score("Invoices from Müller", "mueller_inv_003.txt"), score("Invoices from Mueller", "mueller_inv_003.txt"));
+    assert!(score("Invoices from Müller", "mueller_inv_003.txt") > 0.0);
+    assert!(score("Invoices from Mueller", "Müller_inv_003.txt") > 0.0);
+    assert!(score("Invoices from Muller", "Müller_inv_003.txt") > 0.0);
+    assert!(score("Société Générale invoices", "SOCIETE_GENERALE_2025.txt") > 0.0);
+    assert_eq!(score("Invoices from Müller", "schmidt_inv_004.txt"), 0.0);
+}
+
+#[test]
+fn naming_the_file_scores_one() {
+    let scorer = FilenameScorer;
+    assert_eq!(scorer.score("What is due on müller_inv_003?", Path::new("data/invoices/Mueller_Inv_003.txt")), 1.0);
+    assert_eq!(scorer.score("What is due on MUELLER_INV_003?", Path::new("data/invoices/Müller_inv_003.txt")), 1.0);
+}
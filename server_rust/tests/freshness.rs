@@ -0,0 +1,66 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Stale-index detection: a collection folder against the manifest of an
+// index (size and modification time per document). Documents added, changed
+// or removed since are found, the rest are not, and the warning names them.
+
+use std::fs;
+
+use doc_ai_server::docid::doc_key;
+use doc_ai_server::freshness::{stale, stat, Manifest};
+use doc_ai_server::warnings::WarningCode;
+use doc_ai_server::{Category, Collection, Staleness};
+
+fn collection(name: &str) -> Collection {
+    let mut collection = Collection::from_category(&Category::Invoices);
+    collection.folder = std::env::temp_dir().join(format!("doc-ai-freshness-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&collection.folder);
+    fs::create_dir_all(&collection.folder).unwrap();
+    collection
+}
+
+/// The manifest an index run would record for the folder as it is now
+fn indexed(collection: &Collection) -> Manifest {
+    fs::read_dir(&collection.folder)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .map(|p| (doc_key(&p), stat(&p).unwrap()))
+        .collect()
+}
+
+#[test]
+fn changes_since_the_index_are_found() {
+    let invoices = collection("changes");
+    for n in 1..=3 {
+        fs::write(invoices.folder.join(format!("inv_{}.txt", n)), format!("Invoice INV-{}\nTotal: 100.00\n", n)).unwrap();
+    }
+    let mut manifest = indexed(&invoices);
+    assert!(stale(&invoices, &manifest).is_empty());
+
+    fs::write(invoices.folder.join("inv_1.txt"), "Invoice INV-1\nTotal: 1100.00\n").unwrap();
+    fs::write(invoices.folder.join("inv_4.txt"), "Invoice INV-4\n").unwrap();
+    fs::remove_file(invoices.folder.join("inv_2.txt")).unwrap();
+    // Not documents
+    fs::write(invoices.folder.join("scan.png"), [0x89, b'P', b'N', b'G']).unwrap();
+    // Documents of other folders are not this collection's
+    manifest.insert(doc_key(&invoices.folder.with_file_name("elsewhere").join("x.txt")), (1, 1));
+
+    let staleness = stale(&invoices, &manifest);
+    assert_eq!(
+        staleness,
+        Staleness { added: vec!["inv_4.txt".into()], changed: vec!["inv_1.txt".into()], removed: vec!["inv_2.txt".into()] }
+    );
+    assert_eq!(staleness.summary(), "1 changed, 1 added, 1 removed: inv_1.txt, inv_4.txt, inv_2.txt");
+}
+
+#[test]
+fn the_warning_names_the_documents() {
+    assert!(Staleness::default().warning("invoices").is_none());
+
+    let many = Staleness { added: (1..=8).map(|n| format!("inv_{}.txt", n)).collect(), ..Staleness::default() };
+    let warning = many.warning("invoices").unwrap();
+    assert_eq!(warning.code, WarningCode::StaleIndex);
+    let names = "8 added: inv_1.txt, inv_2.txt, inv_3.txt, inv_4.txt, inv_5.txt and 3 more";
+    assert!(warning.message.contains(names), "{}", warning.message);
+    assert!(warning.message.contains("--reindex-stale"));
+}
@@ -0,0 +1,94 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Idempotent intake: the same bytes are one document whatever they are called
+// or however often they come, new bytes under a key seen before update the
+// key's document, and other content under a taken name gets a numbered name.
+
+use std::fs;
+
+use doc_ai_server::intake::{ingest_bytes, ingest_document, receive};
+use doc_ai_server::{Category, Collection, IngestOutcome};
+
+const INVOICE: &str = "Invoice INV-5\nVendor: ACME\nTotal: 115.00\n";
+
+fn collection(name: &str) -> Collection {
+    let mut collection = Collection::from_category(&Category::Invoices);
+    collection.folder = std::env::temp_dir().join(format!("doc-ai-intake-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&collection.folder);
+    collection
+}
+
+fn txt() -> Vec<String> {
+    vec!["txt".to_string()]
+}
+
+fn files(collection: &Collection) -> Vec<String> {
+    let mut names: Vec<String> =
+        fs::read_dir(&collection.folder).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn the_same_bytes_are_one_document() {
+    let target = collection("same");
+    let saved = |name: &str, text: &str| ingest_bytes(&target, name, text.as_bytes(), &txt()).unwrap();
+    assert_eq!(saved("inv_005.txt", INVOICE), IngestOutcome::Saved("inv_005.txt".into()));
+    assert_eq!(saved("inv_005.txt", INVOICE), IngestOutcome::Duplicate("inv_005.txt".into()));
+    // Another name (a forwarded mail, a renamed upload) is still the same document
+    assert_eq!(saved("Fwd_ invoice.txt", INVOICE), IngestOutcome::Duplicate("inv_005.txt".into()));
+    // Other content under a taken name is another document
+    let other = INVOICE.replace("INV-5", "INV-6");
+    assert_eq!(saved("inv_005.txt", &other), IngestOutcome::Saved("inv_005-1.txt".into()));
+    assert_eq!(saved("inv_006.txt", &other), IngestOutcome::Duplicate("inv_005-1.txt".into()));
+    assert_eq!(files(&target), ["inv_005-1.txt", "inv_005.txt"]);
+}
+
+#[test]
+fn files_put_there_by_hand_count() {
+    let target = collection("by-hand");
+    fs::create_dir_all(&target.folder).unwrap();
+    fs::write(target.folder.join("scan_0001.txt"), INVOICE).unwrap();
+    let outcome = ingest_bytes(&target, "inv_005.txt", INVOICE.as_bytes(), &txt()).unwrap();
+    assert_eq!(outcome, IngestOutcome::Duplicate("scan_0001.txt".into()));
+
+    // Edited on disk: the old content is no longer there
+    fs::write(target.folder.join("scan_0001.txt"), "Invoice INV-5 (corrected)\nVendor: ACME\nTotal: 120.00\n").unwrap();
+    let outcome = ingest_bytes(&target, "inv_005.txt", INVOICE.as_bytes(), &txt()).unwrap();
+    assert_eq!(outcome, IngestOutcome::Saved("inv_005.txt".into()));
+}
+
+#[test]
+fn new_bytes_under_a_key_update_its_document() {
+    let target = collection("keyed");
+    let take = |name: &str, key: &str, text: &str| ingest_document(&target, name, Some(key), text.as_bytes(), &txt()).unwrap();
+    assert_eq!(take("inv_005.txt", "erp-4711", INVOICE), IngestOutcome::Saved("inv_005.txt".into()));
+    let corrected = INVOICE.replace("115.00", "120.00");
+    // The sender may call it something else this time
+    assert_eq!(take("inv_005_v2.txt", "erp-4711", &corrected), IngestOutcome::Updated("inv_005.txt".into()));
+    assert_eq!(fs::read_to_string(target.folder.join("inv_005.txt")).unwrap(), corrected);
+    assert_eq!(take("inv_005.txt", "erp-4711", &corrected), IngestOutcome::Duplicate("inv_005.txt".into()));
+    // Another key is another document
+    let other = INVOICE.replace("INV-5", "INV-7");
+    assert_eq!(take("inv_005.txt", "erp-4712", &other), IngestOutcome::Saved("inv_005-1.txt".into()));
+    assert_eq!(files(&target), ["inv_005-1.txt", "inv_005.txt"]);
+
+    // Keys belong to their collection
+    let elsewhere = collection("keyed-elsewhere");
+    let outcome = ingest_document(&elsewhere, "x.txt", Some("erp-4711"), b"Invoice INV-9\n", &txt()).unwrap();
+    assert_eq!(outcome, IngestOutcome::Saved("x.txt".into()));
+}
+
+#[test]
+fn receipts_carry_the_checksum() {
+    let target = collection("receipt");
+    let result = receive(&target, "inv_005.txt", None, INVOICE.as_bytes(), &txt()).unwrap();
+    assert_eq!(result.collection, target.name);
+    assert_eq!(result.sha256.len(), 64);
+    let again = receive(&target, "copy.txt", None, INVOICE.as_bytes(), &txt()).unwrap();
+    assert_eq!((again.outcome, again.sha256), (IngestOutcome::Duplicate("inv_005.txt".into()), result.sha256));
+    let skipped = receive(&target, "scan.pdf", None, b"%PDF", &txt()).unwrap();
+    assert!(matches!(skipped.outcome, IngestOutcome::Skipped(_)));
+    let json = serde_json::to_value(IngestOutcome::Updated("inv_005.txt".into())).unwrap();
+    assert_eq!(json, serde_json::json!({"updated": "inv_005.txt"}));
+}
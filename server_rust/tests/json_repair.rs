@@ -0,0 +1,178 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Corpus of malformed model outputs, each with the JSON it should repair to.
+// Most are taken from real llama3.2 / mistral answers to the invoice prompts.
+
+use doc_ai_server::json_repair::{extract_json, is_cut_off, parse_lenient, repair, strip_fences, PartialJson};
+use serde_json::{json, Value};
+
+/// (model output, expected JSON)
+const CORPUS: &[(&str, &str)] = &[
+    // Already valid
+    (r#"{"total": 8866.5}"#, r#"{"total": 8866.5}"#),
+    (r#"[1, 2, 3]"#, r#"[1, 2, 3]"#),
+    (r#"{"a": {"b": [true, false, null]}}"#, r#"{"a": {"b": [true, false, null]}}"#),
+    (r#"{"note": "a } in a string, and a ] too"}"#, r#"{"note": "a } in a string, and a ] too"}"#),
+    (r#"{"amount": -1.5e3}"#, r#"{"amount": -1500.0}"#),
+    // Markdown fences
+    ("```json\n{\"total\": 100}\n```", r#"{"total": 100}"#),
+    ("```\n{\"total\": 100}\n```", r#"{"total": 100}"#),
+    ("```JSON\n{\"total\": 100}\n```\nLet me know if you need more.", r#"{"total": 100}"#),
+    ("Here is the answer:\n```json\n{\"vendor\": \"Acme\"}\n```", r#"{"vendor": "Acme"}"#),
+    ("```json\n{\"total\": 100}", r#"{"total": 100}"#),
+    ("```json {\"total\": 100} ```", r#"{"total": 100}"#),
+    // Prose around the object
+    ("Sure! {\"total\": 100}", r#"{"total": 100}"#),
+    ("{\"total\": 100}\n\nThe total is 100.", r#"{"total": 100}"#),
+    ("The answer is {\"a\": 1} and {\"b\": 2}", r#"{"a": 1}"#),
+    ("Result: [\"INV-1\", \"INV-2\"] (two invoices)", r#"["INV-1", "INV-2"]"#),
+    // Trailing commas
+    (r#"{"a": 1,}"#, r#"{"a": 1}"#),
+    (r#"{"a": 1, }"#, r#"{"a": 1}"#),
+    (r#"[1, 2, 3,]"#, r#"[1, 2, 3]"#),
+    ("{\"a\": [1, 2,\n],\n}", r#"{"a": [1, 2]}"#),
+    (r#"{"items": [{"x": 1,}, {"x": 2,},],}"#, r#"{"items": [{"x": 1}, {"x": 2}]}"#),
+    // Doubled and leading commas
+    (r#"[1,, 2]"#, r#"[1, 2]"#),
+    (r#"{, "a": 1}"#, r#"{"a": 1}"#),
+    (r#"{"a": 1,, "b": 2}"#, r#"{"a": 1, "b": 2}"#),
+    // Unquoted keys
+    (r#"{total: 100}"#, r#"{"total": 100}"#),
+    (r#"{invoice_number: "INV-1", vendor: "Acme"}"#, r#"{"invoice_number": "INV-1", "vendor": "Acme"}"#),
+    (r#"{a: {b: {c: 1}}}"#, r#"{"a": {"b": {"c": 1}}}"#),
+    (r#"{ total : 100 }"#, r#"{"total": 100}"#),
+    (r#"{$ref: 1}"#, r#"{"$ref": 1}"#),
+    (r#"{line_items: [{description: "Widget", qty: 2}]}"#, r#"{"line_items": [{"description": "Widget", "qty": 2}]}"#),
+    // Single quotes
+    (r#"{'total': 100}"#, r#"{"total": 100}"#),
+    (r#"{'vendor': 'Acme Ltd'}"#, r#"{"vendor": "Acme Ltd"}"#),
+    (r#"{'note': 'He said "hi"'}"#, r#"{"note": "He said \"hi\""}"#),
+    (r#"{'note': 'it\'s paid'}"#, r#"{"note": "it's paid"}"#),
+    (r#"['a', 'b']"#, r#"["a", "b"]"#),
+    // Python and JavaScript literals
+    (r#"{"paid": True, "void": False, "po": None}"#, r#"{"paid": true, "void": false, "po": null}"#),
+    (r#"{"x": undefined}"#, r#"{"x": null}"#),
+    (r#"{"x": NaN}"#, r#"{"x": null}"#),
+    (r#"{"x": TRUE}"#, r#"{"x": true}"#),
+    // Numbers
+    (r#"{"x": +5}"#, r#"{"x": 5}"#),
+    (r#"{"x": 1e2}"#, r#"{"x": 100.0}"#),
+    (r#"{"x": 2.5E-1}"#, r#"{"x": 0.25}"#),
+    // Bare words as values
+    (r#"{"currency": ZAR}"#, r#"{"currency": "ZAR"}"#),
+    (r#"{"status": not_found}"#, r#"{"status": "not_found"}"#),
+    // Comments
+    ("{\"total\": 100 // incl. VAT\n}", r#"{"total": 100}"#),
+    ("{/* the answer */ \"total\": 100}", r#"{"total": 100}"#),
+    ("{\n  \"total\": 100, # from page 2\n  \"vat\": 15\n}", r#"{"total": 100, "vat": 15}"#),
+    ("{\"url\": \"http://example.com//x\"}", r#"{"url": "http://example.com//x"}"#),
+    // Raw control characters in strings
+    ("{\"address\": \"1 Main Rd\nCape Town\"}", r#"{"address": "1 Main Rd\nCape Town"}"#),
+    ("{\"a\": \"tab\there\"}", r#"{"a": "tab\there"}"#),
+    // Cut off output
+    (r#"{"total": 100"#, r#"{"total": 100}"#),
+    (r#"{"total": 100,"#, r#"{"total": 100}"#),
+    (r#"{"total":"#, r#"{"total": null}"#),
+    (r#"{"vendor": "Acme"#, r#"{"vendor": "Acme"}"#),
+    (r#"{"items": [1, 2"#, r#"{"items": [1, 2]}"#),
+    (r#"{"items": [{"x": 1}, {"x": 2"#, r#"{"items": [{"x": 1}, {"x": 2}]}"#),
+    (r#"[{"a": [{"b": ["#, r#"[{"a": [{"b": []}]}]"#),
+    ("```json\n{\"total\": 100, \"sources\": [\"inv_001.txt\"", r#"{"total": 100, "sources": ["inv_001.txt"]}"#),
+    // Mismatched and stray closers
+    (r#"{"items": [1, 2}"#, r#"{"items": [1, 2]}"#),
+    (r#"{"a": {"b": 1]}"#, r#"{"a": {"b": 1}}"#),
+    (r#"{"a": 1}}"#, r#"{"a": 1}"#),
+    // Keys with no value before a closer
+    (r#"{"a": }"#, r#"{"a": null}"#),
+    // Several problems at once
+    (
+        "Here you go:\n```json\n{\n  invoice_number: 'INV-2025-001',\n  total: 8866.50,\n  paid: False,\n  sources: ['inv_001.txt',],\n}\n```",
+        r#"{"invoice_number": "INV-2025-001", "total": 8866.50, "paid": false, "sources": ["inv_001.txt"]}"#,
+    ),
+    (
+        "{'answer': 'R 1,234.50', 'status': 'answered', // from the total line\n 'sources': [{'file': 'inv_002.txt', page: 1}",
+        r#"{"answer": "R 1,234.50", "status": "answered", "sources": [{"file": "inv_002.txt", "page": 1}]}"#,
+    ),
+    (
+        "```json\n{\"status\": \"not_found\", \"reason\": \"No invoice mentions\nthat vendor\",}\n```",
+        r#"{"status": "not_found", "reason": "No invoice mentions\nthat vendor"}"#,
+    ),
+    (
+        "{\"line_items\": [\n  {\"description\": \"Consulting\", \"amount\": 5000.00},\n  {\"description\": \"Travel\", \"amount\": 1200.00},\n],\n\"total\": 6200.00,\n}",
+        r#"{"line_items": [{"description": "Consulting", "amount": 5000.00}, {"description": "Travel", "amount": 1200.00}], "total": 6200.00}"#,
+    ),
+    // Non-ASCII
+    (r#"{'vendor': 'Café Müller', 'total': 12}"#, r#"{"vendor": "Café Müller", "total": 12}"#),
+    (r#"{währung: "EUR"}"#, r#"{"währung": "EUR"}"#),
+];
+
+/// Outputs with nothing to recover
+const HOPELESS: &[&str] = &["", "I could not find that invoice.", "```\n```", "The total is R 100."];
+
+#[test]
+fn corpus_repairs_to_expected_json() {
+    let mut failures = Vec::new();
+    for (input, expected) in CORPUS {
+        let expected: Value = serde_json::from_str(expected).expect("corpus expectation is valid JSON");
+        let parsed = parse_lenient(input);
+        if parsed.as_ref() != Some(&expected) {
+            failures.push(format!("{:?}\n  repaired: {}\n  parsed:   {:?}", input, repair(input), parsed));
+        }
+    }
+    assert!(failures.is_empty(), "{} of {} failed:\n{}", failures.len(), CORPUS.len(), failures.join("\n"));
+}
+
+#[test]
+fn repair_is_idempotent() {
+    for (input, _) in CORPUS {
+        let once = repair(input);
+        assert_eq!(repair(&once), once, "input {:?}", input);
+    }
+}
+
+#[test]
+fn valid_json_is_parsed_as_is() {
+    let text = r##"{"a": "// not a comment", "b": "# nor this", "c": "it's"}"##;
+    assert_eq!(parse_lenient(text), Some(json!({"a": "// not a comment", "b": "# nor this", "c": "it's"})));
+}
+
+#[test]
+fn hopeless_output_is_rejected() {
+    for input in HOPELESS {
+        assert_eq!(parse_lenient(input), None, "input {:?}", input);
+    }
+}
+
+#[test]
+fn fences_and_prose_are_stripped() {
+    assert_eq!(strip_fences("```json\n{}\n```"), "{}");
+    assert_eq!(strip_fences("no fences"), "no fences");
+    assert_eq!(extract_json("Answer: {\"a\": {\"b\": 1}} done"), "{\"a\": {\"b\": 1}}");
+    assert_eq!(extract_json("{\"a\": \"}\"} done"), "{\"a\": \"}\"}");
+    assert_eq!(extract_json("no json"), "no json");
+}
+
+#[test]
+fn cut_off_output_is_detected() {
+    assert!(is_cut_off(r#"{"line_items": [{"description": "Consulting", "amount": 5000.00}, {"descr"#));
+    assert!(is_cut_off("```json\n{\"total\": \"R 1"));
+    assert!(!is_cut_off(r#"{"total": 100}"#));
+    assert!(!is_cut_off(r#"{"note": "a { in a string"}"#));
+    assert!(!is_cut_off("I could not find that invoice."));
+}
+
+#[test]
+fn partial_json_grows_field_by_field() {
+    let mut partial = PartialJson::new();
+    let pieces = ["{\"vendor\": \"Ac", "me\", \"total", "\": 8866.5, \"items\": [{\"amount\": 1", "00}, {\"amo", "unt\": 2}]}"];
+    let seen: Vec<Value> = pieces.iter().filter_map(|p| partial.push(p)).collect();
+    assert_eq!(
+        seen,
+        vec![
+            json!({"vendor": "Acme"}),
+            json!({"vendor": "Acme", "total": 8866.5}),
+            json!({"vendor": "Acme", "total": 8866.5, "items": [{"amount": 100}]}),
+            json!({"vendor": "Acme", "total": 8866.5, "items": [{"amount": 100}, {"amount": 2}]}),
+        ]
+    );
+}
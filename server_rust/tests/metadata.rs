@@ -0,0 +1,55 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Hidden documents and statuses as retrieval sees them (`metadata::is_hidden`,
+// `invoice_status`): a document archived or moved on by this process counts at
+// once, and one changed in the store by another process (a second server, the
+// CLI) within a second, without a restart.
+
+use std::fs;
+use std::path::Path;
+use std::thread::sleep;
+use std::time::Duration;
+
+use doc_ai_server::lifecycle::InvoiceStatus;
+use doc_ai_server::metadata::{invoice_status, is_hidden, DocumentState, Metadata};
+use doc_ai_server::store::{self, store};
+
+const INVOICE: &str = "data/invoices/inv_meta.txt";
+
+/// A working directory with one invoice, made current
+fn workspace() {
+    let dir = std::env::temp_dir().join(format!("doc-ai-metadata-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("data/invoices")).unwrap();
+    fs::write(dir.join(INVOICE), "Invoice INV-2025-950\nTotal: 40.00\n").unwrap();
+    std::env::set_current_dir(&dir).unwrap();
+}
+
+#[test]
+fn changes_count_without_a_restart() {
+    workspace();
+    let invoice = Path::new(INVOICE);
+    assert!(!is_hidden(invoice));
+    assert_eq!(invoice_status(invoice), InvoiceStatus::Received);
+
+    // Changed here
+    Metadata::update(|m| {
+        m.hide(invoice, DocumentState::Archived);
+    })
+    .unwrap();
+    assert!(is_hidden(invoice));
+    Metadata::update(|m| {
+        m.restore(invoice);
+        m.set_status(invoice, InvoiceStatus::Extracted, false, 1).unwrap();
+    })
+    .unwrap();
+    assert!(!is_hidden(invoice));
+    assert_eq!(invoice_status(invoice), InvoiceStatus::Extracted);
+
+    // Changed by another process, straight in the store
+    let mut theirs = Metadata::load().unwrap();
+    theirs.hide(invoice, DocumentState::Deleted);
+    store().save(store::METADATA, &serde_json::to_string(&theirs).unwrap()).unwrap();
+    sleep(Duration::from_millis(1100));
+    assert!(is_hidden(invoice));
+}
@@ -0,0 +1,394 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// The Ollama client against a mock Ollama (wiremock), end to end through
+// `query_ollama`/`query_ollama_chat`: answers plain and streamed, 503 while a
+// model loads, malformed JSON, slow and stalled answers, connection resets and
+// error statuses, and how each is retried or reported.
+//
+// The hosts are installed once per test binary, so every test uses its own
+// model name and its mocks only match that model. Two bare TCP hosts stand in
+// for what wiremock cannot do: one resets every connection, one starts a
+// streamed answer and then goes quiet.
+
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use doc_ai_server::ai::{default_options, query_ollama, query_ollama_chat, Answer};
+use doc_ai_server::events::{EventSink, NoEvents, Profiled};
+use doc_ai_server::hosts::{init_hosts, HostConfig, OllamaConfig};
+use doc_ai_server::{Category, Collection, OutputProfile};
+
+/// Models answered by the mock
+const MOCKED: &[&str] = &[
+    "ok",
+    "ok-stream",
+    "profiled",
+    "ok-chat",
+    "no-chat",
+    "not-pulled",
+    "loading",
+    "busy",
+    "broken-json",
+    "broken-stream",
+    "stream-error",
+    "slow",
+    "slow-ok",
+    "server-error",
+];
+
+const ANSWER: &str = r#"{"status": "answered", "answer": "R 8 866,50", "sources": ["inv_001.txt"]}"#;
+
+/// Retries and timeouts of the installed hosts
+const RETRIES: u32 = 2;
+const TIMEOUT_SECS: u64 = 1;
+
+static MOCK: OnceCell<MockServer> = OnceCell::const_new();
+
+/// Connections the resetting host has accepted
+static RESETS: AtomicUsize = AtomicUsize::new(0);
+
+/// A host that accepts connections on its own thread and hands each to `handle`
+fn bare_host(handle: fn(std::net::TcpStream)) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            std::thread::spawn(move || handle(stream));
+        }
+    });
+    url
+}
+
+/// Drop the connection without answering, the request unread (a reset)
+fn reset(stream: std::net::TcpStream) {
+    RESETS.fetch_add(1, Ordering::SeqCst);
+    drop(stream);
+}
+
+/// Send the first line of a streamed answer, then nothing
+fn stall(mut stream: std::net::TcpStream) {
+    let mut request = [0u8; 65536];
+    let _ = stream.read(&mut request);
+    let line = format!("{}\n", json!({"response": "{\"status\": ", "done": false}));
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nTransfer-Encoding: chunked\r\n\r\n";
+    let _ = write!(stream, "{}{:x}\r\n{}\r\n", head, line.len(), line);
+    let _ = stream.flush();
+    std::thread::sleep(Duration::from_secs(30));
+}
+
+/// The mock, with the hosts installed on first use
+async fn mock() -> &'static MockServer {
+    MOCK.get_or_init(|| async {
+        let server = MockServer::start().await;
+        let host = |url: String, models: &[&str]| HostConfig { url, models: models.iter().map(|m| m.to_string()).collect() };
+        let config = OllamaConfig {
+            hosts: vec![host(server.uri(), MOCKED), host(bare_host(reset), &["reset"]), host(bare_host(stall), &["stall"])],
+            retries: RETRIES,
+            retry_delay_ms: 10,
+            timeout_secs: TIMEOUT_SECS,
+            ..OllamaConfig::default()
+        };
+        init_hosts(&config).unwrap();
+        server
+    })
+    .await
+}
+
+/// POSTs of `model` to `endpoint`
+fn calls(endpoint: &str, model: &str) -> wiremock::MockBuilder {
+    Mock::given(method("POST")).and(path(endpoint)).and(body_partial_json(json!({"model": model})))
+}
+
+fn generated(text: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({"response": text, "done": true, "eval_count": 42}))
+}
+
+/// A streamed `/api/generate` reply: one line per piece, then the final line
+fn streamed(pieces: &[&str]) -> ResponseTemplate {
+    let mut body = String::new();
+    for piece in pieces {
+        body.push_str(&format!("{}\n", json!({"response": piece, "done": false})));
+    }
+    body.push_str(&format!("{}\n", json!({"response": "", "done": true, "eval_count": pieces.len()})));
+    ResponseTemplate::new(200).set_body_raw(body, "application/x-ndjson")
+}
+
+/// Records the streamed pieces
+#[derive(Default)]
+struct Deltas(Mutex<Vec<String>>);
+
+impl EventSink for Deltas {
+    fn wants_token_deltas(&self) -> bool {
+        true
+    }
+
+    fn on_token_delta(&self, text: &str) {
+        self.0.lock().unwrap().push(text.to_string());
+    }
+}
+
+/// Records the streamed pieces and partial answers
+#[derive(Default)]
+struct Streamed {
+    deltas: Mutex<Vec<String>>,
+    partials: Mutex<Vec<Value>>,
+}
+
+impl EventSink for Streamed {
+    fn wants_token_deltas(&self) -> bool {
+        true
+    }
+
+    fn on_token_delta(&self, text: &str) {
+        self.deltas.lock().unwrap().push(text.to_string());
+    }
+
+    fn on_partial_answer(&self, answer: &Value) {
+        self.partials.lock().unwrap().push(answer.clone());
+    }
+}
+
+async fn ask(model: &str, events: &dyn EventSink) -> anyhow::Result<Answer> {
+    let collection = Collection::from_category(&Category::Invoices);
+    let contents = "\n--- inv_001.txt ---\nTotal: R 8 866,50\n";
+    query_ollama(model, contents, "What is the total?", &collection, &default_options(), events).await
+}
+
+/// The error chain of a failed call, as printed
+async fn error_of(model: &str, events: &dyn EventSink) -> String {
+    format!("{:#}", ask(model, events).await.expect_err("the call should fail"))
+}
+
+fn answer_json() -> Value {
+    serde_json::from_str(ANSWER).unwrap()
+}
+
+#[tokio::test]
+async fn an_answer_is_parsed_with_its_token_count() {
+    let mock = mock().await;
+    let _guard = calls("/api/generate", "ok").respond_with(generated(ANSWER)).expect(1).mount_as_scoped(mock).await;
+
+    let answer = ask("ok", &NoEvents).await.unwrap();
+    assert_eq!(answer.json, Some(answer_json()));
+    assert_eq!(answer.sources, ["inv_001.txt"]);
+    assert_eq!(answer.tokens, Some(42));
+    assert_eq!(answer.model, "ok");
+}
+
+#[tokio::test]
+async fn a_streamed_answer_is_joined_from_its_pieces() {
+    let mock = mock().await;
+    let pieces = ["{\"status\": \"answered\", ", "\"answer\": \"R 8 866,50\", ", "\"sources\": [\"inv_001.txt\"]}"];
+    let _guard = calls("/api/generate", "ok-stream").respond_with(streamed(&pieces)).expect(1).mount_as_scoped(mock).await;
+
+    let events = Deltas::default();
+    let answer = ask("ok-stream", &events).await.unwrap();
+    assert_eq!(answer.json, Some(answer_json()));
+    assert_eq!(answer.tokens, Some(3));
+    assert_eq!(*events.0.lock().unwrap(), pieces);
+}
+
+#[tokio::test]
+async fn a_profile_redacts_what_streams_out() {
+    let mock = mock().await;
+    let pieces = ["{\"vendor\": \"ACME\", ", "\"iban\": \"DE89370400440532013000\", ", "\"status\": \"answered\"}"];
+    let _guard = calls("/api/generate", "profiled").respond_with(streamed(&pieces)).expect(1).mount_as_scoped(mock).await;
+
+    let events = Streamed::default();
+    let profile = OutputProfile { allow: vec!["vendor".to_string()], ..Default::default() };
+    ask("profiled", &Profiled { inner: &events, profile: &profile }).await.unwrap();
+    assert!(events.deltas.lock().unwrap().is_empty());
+    let partials = events.partials.lock().unwrap();
+    assert!(!partials.is_empty());
+    for partial in partials.iter() {
+        assert!(partial.get("iban").is_none(), "{}", partial);
+    }
+    assert_eq!(partials.last(), Some(&json!({"vendor": "ACME", "status": "answered"})));
+}
+
+#[tokio::test]
+async fn chat_answers_are_read_from_the_message() {
+    let mock = mock().await;
+    let reply = json!({"message": {"role": "assistant", "content": ANSWER}, "done": true, "eval_count": 5});
+    let _guard = calls("/api/chat", "ok-chat")
+        .respond_with(ResponseTemplate::new(200).set_body_json(reply))
+        .expect(1)
+        .mount_as_scoped(mock)
+        .await;
+
+    let collection = Collection::from_category(&Category::Invoices);
+    let answer = query_ollama_chat("ok-chat", "", "What is the total?", &collection, &default_options(), &NoEvents)
+        .await
+        .unwrap();
+    assert_eq!(answer.json, Some(answer_json()));
+    assert_eq!(answer.tokens, Some(5));
+}
+
+#[tokio::test]
+async fn chat_falls_back_to_generate_without_the_endpoint() {
+    let mock = mock().await;
+    let missing = ResponseTemplate::new(404).set_body_raw("404 page not found", "text/plain");
+    let _chat = calls("/api/chat", "no-chat").respond_with(missing).expect(1).mount_as_scoped(mock).await;
+    let _generate = calls("/api/generate", "no-chat").respond_with(generated(ANSWER)).expect(1).mount_as_scoped(mock).await;
+
+    let collection = Collection::from_category(&Category::Invoices);
+    let answer = query_ollama_chat("no-chat", "", "What is the total?", &collection, &default_options(), &NoEvents)
+        .await
+        .unwrap();
+    assert_eq!(answer.json, Some(answer_json()));
+}
+
+#[tokio::test]
+async fn a_model_missing_on_chat_is_reported_without_a_fallback() {
+    let mock = mock().await;
+    let not_pulled = ResponseTemplate::new(404).set_body_json(json!({"error": "model \"not-pulled\" not found, try pulling it first"}));
+    let _chat = calls("/api/chat", "not-pulled").respond_with(not_pulled).expect(1).mount_as_scoped(mock).await;
+    let _generate = calls("/api/generate", "not-pulled").respond_with(generated(ANSWER)).expect(0).mount_as_scoped(mock).await;
+
+    let collection = Collection::from_category(&Category::Invoices);
+    let error = query_ollama_chat("not-pulled", "", "What is the total?", &collection, &default_options(), &NoEvents)
+        .await
+        .unwrap_err();
+    assert!(format!("{:#}", error).contains("not found, try pulling it first"), "{:#}", error);
+}
+
+#[tokio::test]
+async fn a_loading_model_is_retried_until_it_answers() {
+    let mock = mock().await;
+    let loading = ResponseTemplate::new(503).set_body_json(json!({"error": "model is loading"}));
+    let _loading = calls("/api/generate", "loading")
+        .respond_with(loading)
+        .up_to_n_times(2)
+        .with_priority(1)
+        .expect(2)
+        .mount_as_scoped(mock)
+        .await;
+    let _ready = calls("/api/generate", "loading").respond_with(generated(ANSWER)).expect(1).mount_as_scoped(mock).await;
+
+    let answer = ask("loading", &NoEvents).await.unwrap();
+    assert_eq!(answer.json, Some(answer_json()));
+}
+
+#[tokio::test]
+async fn a_host_that_stays_unavailable_gives_its_503() {
+    let mock = mock().await;
+    let busy = ResponseTemplate::new(503).set_body_json(json!({"error": "server busy, please try again"}));
+    let _guard = calls("/api/generate", "busy")
+        .respond_with(busy)
+        .expect(RETRIES as u64 + 1)
+        .mount_as_scoped(mock)
+        .await;
+
+    let error = error_of("busy", &NoEvents).await;
+    assert!(error.contains("Ollama error 503"), "{}", error);
+    assert!(error.contains("server busy"), "{}", error);
+}
+
+#[tokio::test]
+async fn other_error_statuses_are_reported_without_a_retry() {
+    let mock = mock().await;
+    let _guard = calls("/api/generate", "server-error")
+        .respond_with(ResponseTemplate::new(500).set_body_string("boom"))
+        .expect(1)
+        .mount_as_scoped(mock)
+        .await;
+
+    let error = error_of("server-error", &NoEvents).await;
+    assert_eq!(error, "Ollama error 500 Internal Server Error: boom");
+}
+
+#[tokio::test]
+async fn malformed_json_is_an_invalid_response() {
+    let mock = mock().await;
+    let _guard = calls("/api/generate", "broken-json")
+        .respond_with(ResponseTemplate::new(200).set_body_string("<html>Bad gateway</html>"))
+        .expect(1)
+        .mount_as_scoped(mock)
+        .await;
+
+    let error = error_of("broken-json", &NoEvents).await;
+    assert!(error.starts_with("Invalid Ollama response"), "{}", error);
+}
+
+#[tokio::test]
+async fn a_malformed_streamed_line_is_an_invalid_response() {
+    let mock = mock().await;
+    let body = format!("{}\n{{\"response\": \"cut\n", json!({"response": "{", "done": false}));
+    let _guard = calls("/api/generate", "broken-stream")
+        .respond_with(ResponseTemplate::new(200).set_body_raw(body, "application/x-ndjson"))
+        .expect(1)
+        .mount_as_scoped(mock)
+        .await;
+
+    let events = Deltas::default();
+    let error = error_of("broken-stream", &events).await;
+    assert!(error.starts_with("Invalid Ollama response"), "{}", error);
+    assert_eq!(*events.0.lock().unwrap(), ["{"]);
+}
+
+#[tokio::test]
+async fn an_error_line_in_a_stream_is_reported() {
+    let mock = mock().await;
+    let body = format!("{}\n", json!({"error": "model requires more system memory"}));
+    let _guard = calls("/api/generate", "stream-error")
+        .respond_with(ResponseTemplate::new(200).set_body_raw(body, "application/x-ndjson"))
+        .expect(1)
+        .mount_as_scoped(mock)
+        .await;
+
+    let error = error_of("stream-error", &Deltas::default()).await;
+    assert_eq!(error, "Ollama error: model requires more system memory");
+}
+
+#[tokio::test]
+async fn a_slow_answer_within_the_timeout_arrives() {
+    let mock = mock().await;
+    let _guard = calls("/api/generate", "slow-ok")
+        .respond_with(generated(ANSWER).set_delay(Duration::from_millis(300)))
+        .expect(1)
+        .mount_as_scoped(mock)
+        .await;
+
+    assert_eq!(ask("slow-ok", &NoEvents).await.unwrap().json, Some(answer_json()));
+}
+
+#[tokio::test]
+async fn an_answer_slower_than_the_timeout_fails_without_a_retry() {
+    let mock = mock().await;
+    let _guard = calls("/api/generate", "slow")
+        .respond_with(generated(ANSWER).set_delay(Duration::from_secs(TIMEOUT_SECS + 2)))
+        .expect(1)
+        .mount_as_scoped(mock)
+        .await;
+
+    let error = error_of("slow", &NoEvents).await;
+    assert!(error.contains(&format!("did not answer within {} s", TIMEOUT_SECS)), "{}", error);
+}
+
+#[tokio::test]
+async fn a_stream_that_goes_quiet_times_out() {
+    mock().await;
+    let events = Deltas::default();
+    let started = std::time::Instant::now();
+
+    let error = error_of("stall", &events).await;
+    assert!(error.starts_with("Ollama stopped sending"), "{}", error);
+    assert_eq!(*events.0.lock().unwrap(), ["{\"status\": "]);
+    assert!(started.elapsed() < Duration::from_secs(TIMEOUT_SECS + 5));
+}
+
+#[tokio::test]
+async fn connection_resets_are_retried_then_reported() {
+    mock().await;
+
+    let error = error_of("reset", &NoEvents).await;
+    assert!(error.starts_with("Cannot reach Ollama"), "{}", error);
+    assert_eq!(RESETS.load(Ordering::SeqCst), RETRIES as usize + 1);
+}
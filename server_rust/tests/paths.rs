@@ -0,0 +1,207 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Document ids over exotic file names: vendor names in other scripts and with
+// diacritics, spaces, `%`, backslashes (a plain character on Unix), names
+// that are not valid UTF-8, and Windows long and UNC paths. Each id must turn
+// back into the exact path it was made from, and files written under such
+// names must be found and read through their ids.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use doc_ai_server::docid::{doc_key, doc_path, is_text_file, long_path, DocId};
+use doc_ai_server::reader::read_document;
+
+const NAMES: &[&str] = &[
+    "inv_0001.txt",
+    "Société Générale – facture 12.txt",
+    "Müller & Söhne GmbH.txt",
+    "株式会社サンプル 請求書.txt",
+    "Ελληνικά ΑΕ.txt",
+    "שלום בע״מ.txt",
+    "emoji 🧾 receipt.txt",
+    "100% discount.txt",
+    "%41 not an escape.txt",
+    "NFD Mu\u{0308}ller.txt",
+    " leading and trailing spaces .txt",
+];
+
+/// A fresh folder under the system temp folder
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("doc-ai-paths-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn unicode_names_round_trip() {
+    for name in NAMES {
+        let path = Path::new("data").join("invoices").join(name);
+        let id = DocId::new(&path);
+        assert_eq!(id.to_path(), path, "{}", name);
+        assert_eq!(doc_path(&doc_key(&path)), path, "{}", name);
+    }
+}
+
+#[test]
+fn text_names_keep_their_keys() {
+    // Ids of names that are text are the keys saved before ids existed
+    let path = Path::new("data/invoices/Müller & Söhne GmbH.txt");
+    assert_eq!(doc_key(path), "data/invoices/Müller & Söhne GmbH.txt");
+    assert_eq!(DocId::new(path).to_string(), path.display().to_string());
+}
+
+#[test]
+fn ids_serialize_as_plain_strings() {
+    let id = DocId::new(Path::new("data/invoices/株式会社.txt"));
+    let json = serde_json::to_string(&id).unwrap();
+    assert_eq!(json, "\"data/invoices/株式会社.txt\"");
+    assert_eq!(serde_json::from_str::<DocId>(&json).unwrap(), id);
+}
+
+#[test]
+fn different_paths_get_different_ids() {
+    let ids: std::collections::HashSet<String> =
+        NAMES.iter().map(|n| doc_key(&Path::new("data").join(n))).collect();
+    assert_eq!(ids.len(), NAMES.len());
+    // Composed and decomposed "ü" are different files to the file system
+    assert_ne!(doc_key(Path::new("Müller.txt")), doc_key(Path::new("Mu\u{0308}ller.txt")));
+}
+
+#[test]
+fn text_files_in_any_case() {
+    for name in ["a.txt", "INV_0042.TXT", "Scan.Txt", "株式会社.txt"] {
+        assert!(is_text_file(Path::new(name)), "{}", name);
+    }
+    for name in ["a.pdf", "txt", "a.txt.bak", ".txt.", "a.text"] {
+        assert!(!is_text_file(Path::new(name)), "{}", name);
+    }
+}
+
+#[test]
+fn files_with_exotic_names_are_found_and_read() {
+    let dir = scratch("read");
+    for (n, name) in NAMES.iter().enumerate() {
+        fs::write(dir.join(name), format!("Invoice {}\nVendor: {}\nTotal: 1.00 EUR\n", n, name)).unwrap();
+    }
+    let mut found: Vec<DocId> = fs::read_dir(&dir)
+        .unwrap()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| is_text_file(p))
+        .map(|p| DocId::new(&p))
+        .collect();
+    found.sort();
+    assert_eq!(found.len(), NAMES.len());
+    for id in &found {
+        let text = read_document(&id.to_path()).unwrap();
+        let name = id.to_path().file_name().unwrap().to_string_lossy().into_owned();
+        assert!(text.contains(&format!("Vendor: {}", name)), "{}", id);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    /// "Café.txt" as an old Windows share writes it, in Latin-1
+    fn latin1() -> &'static Path {
+        Path::new(OsStr::from_bytes(b"Caf\xe9 %41.txt"))
+    }
+
+    #[test]
+    fn names_that_are_not_utf8_round_trip() {
+        let path = Path::new("data/invoices").join(latin1());
+        let id = DocId::new(&path);
+        assert!(id.as_str().starts_with('\0'), "{:?}", id);
+        assert_eq!(id.to_path(), path);
+        // The lossy name they used to be keyed by no longer stands in for them
+        assert_ne!(id.as_str(), path.to_string_lossy());
+        assert_ne!(DocId::new(Path::new("data/invoices/Caf\u{fffd} %41.txt")).to_path(), path);
+    }
+
+    #[test]
+    fn backslashes_are_part_of_the_name() {
+        let path = Path::new(r"data/invoices/a\b.txt");
+        assert_eq!(doc_key(path), r"data/invoices/a\b.txt");
+        assert_eq!(doc_path(&doc_key(path)), path);
+    }
+
+    #[test]
+    fn files_not_named_in_utf8_are_read() {
+        let dir = scratch("latin1");
+        fs::write(dir.join(latin1()), "Invoice 7\nTotal: 12.50 EUR\n").unwrap();
+        let ids: Vec<DocId> = fs::read_dir(&dir).unwrap().flatten().map(|e| DocId::new(&e.path())).collect();
+        assert_eq!(ids.len(), 1);
+        assert!(read_document(&ids[0].to_path()).unwrap().contains("12.50 EUR"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn long_paths_are_left_alone() {
+        let path = PathBuf::from(format!("/data/{}/inv.txt", "x".repeat(300)));
+        assert_eq!(long_path(&path), path.as_path());
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::*;
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+
+    #[test]
+    fn keys_use_forward_slashes() {
+        let path = Path::new(r"C:\data\invoices\Müller.txt");
+        assert_eq!(doc_key(path), "C:/data/invoices/Müller.txt");
+        assert_eq!(doc_path(&doc_key(path)), path);
+    }
+
+    #[test]
+    fn unc_and_verbatim_paths_round_trip() {
+        let paths = [r"\\fileserver\scans\株式会社.txt", r"\\?\C:\data\inv.txt", r"\\?\UNC\fileserver\scans\a.txt"];
+        for path in paths {
+            assert_eq!(DocId::new(Path::new(path)).to_path(), Path::new(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn unpaired_surrogates_round_trip() {
+        let mut wide: Vec<u16> = r"C:\data\inv".encode_utf16().collect();
+        wide.push(0xD800);
+        wide.extend("%41.txt".encode_utf16());
+        let path = PathBuf::from(OsString::from_wide(&wide));
+        let id = DocId::new(&path);
+        assert!(id.as_str().starts_with('\0'), "{:?}", id);
+        assert_eq!(id.to_path(), path);
+    }
+
+    #[test]
+    fn long_paths_get_the_verbatim_prefix() {
+        let short = Path::new(r"C:\data\inv.txt");
+        assert_eq!(long_path(short), short);
+        let long = PathBuf::from(format!(r"C:\data\{}\inv.txt", "x".repeat(300)));
+        assert_eq!(long_path(&long).to_str().unwrap(), format!(r"\\?\{}", long.display()));
+        let share = PathBuf::from(format!(r"\\fileserver\scans\{}\inv.txt", "x".repeat(300)));
+        assert_eq!(long_path(&share).to_str().unwrap(), format!(r"\\?\UNC\{}", &share.to_str().unwrap()[2..]));
+    }
+
+    #[test]
+    fn files_deeper_than_max_path_are_read() {
+        let mut dir = scratch("long");
+        for n in 0..6 {
+            dir.push(format!("{}-{}", n, "ü".repeat(50)));
+        }
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("Société Générale.TXT");
+        fs::write(&path, "Total: 3.00 EUR\n").unwrap();
+        assert!(path.as_os_str().len() > 260);
+        let id = DocId::new(&path);
+        assert!(is_text_file(&id.to_path()));
+        assert!(read_document(&id.to_path()).unwrap().contains("3.00 EUR"));
+    }
+}
@@ -0,0 +1,117 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// `[policy]`: decisions from limits per vendor and collection, required
+// fields and allowed currencies, and the status changes they make.
+
+use rust_decimal::Decimal;
+use std::path::Path;
+
+use doc_ai_server::metadata::Metadata;
+use doc_ai_server::policy::{apply, evaluate, POLICY_ACTOR};
+use doc_ai_server::{ApprovalConfig, Decision, InvoiceRecord, InvoiceStatus, PolicyConfig};
+
+const CONFIG: &str = r#"
+    currencies = ["EUR", "ZAR"]
+    auto_approve_up_to = 1000
+    reject_over = 50000
+
+    [[limit]]
+    vendor = "Müller GmbH"
+    auto_approve_up_to = 5000
+
+    [[limit]]
+    collection = "receipts"
+    auto_approve_up_to = 200
+"#;
+
+fn invoice(collection: &str, vendor: &str, total: &str, currency: &str) -> (InvoiceRecord, String) {
+    let text = format!(
+        "Invoice INV-1\nVendor: {}\nDate: 2025-03-01\nCurrency: {}\nTotal: {}\n",
+        vendor, currency, total
+    );
+    (InvoiceRecord::from_text(collection, Path::new("data/invoices/inv_001.txt"), &text), text)
+}
+
+fn decide(config: &PolicyConfig, collection: &str, vendor: &str, total: &str, currency: &str) -> (Decision, Vec<String>) {
+    let (record, text) = invoice(collection, vendor, total, currency);
+    let outcome = evaluate(&record, &text, config, &ApprovalConfig::default());
+    (outcome.decision, outcome.reasons)
+}
+
+#[test]
+fn limits_decide() {
+    let config: PolicyConfig = toml::from_str(CONFIG).unwrap();
+    assert_eq!(decide(&config, "invoices", "ACME", "800.00", "EUR"), (Decision::AutoApproved, vec![]));
+    let (decision, reasons) = decide(&config, "invoices", "ACME", "1200.00", "EUR");
+    assert_eq!(decision, Decision::NeedsReview);
+    assert_eq!(reasons, ["total 1200.00 is over the auto-approval limit 1000"]);
+    assert_eq!(decide(&config, "invoices", "ACME", "60000.00", "EUR").0, Decision::Rejected);
+}
+
+#[test]
+fn vendor_limits_beat_collection_limits() {
+    let config: PolicyConfig = toml::from_str(CONFIG).unwrap();
+    // Matched folded, however the invoice spells the vendor
+    assert_eq!(decide(&config, "invoices", "MUELLER GMBH", "4000.00", "EUR").0, Decision::AutoApproved);
+    assert_eq!(decide(&config, "receipts", "Müller GmbH", "4000.00", "EUR").0, Decision::AutoApproved);
+    assert_eq!(decide(&config, "receipts", "ACME", "300.00", "EUR").0, Decision::NeedsReview);
+    // The vendor limit sets no rejection limit of its own: the default applies
+    assert_eq!(decide(&config, "invoices", "Müller GmbH", "60000.00", "EUR").0, Decision::Rejected);
+}
+
+#[test]
+fn currencies_and_required_fields() {
+    let config: PolicyConfig = toml::from_str(CONFIG).unwrap();
+    let (decision, reasons) = decide(&config, "invoices", "ACME", "100.00", "USD");
+    assert_eq!(decision, Decision::Rejected);
+    assert_eq!(reasons, ["currency USD is not one of EUR, ZAR"]);
+
+    let text = "Vendor: ACME\nTotal: 100.00 EUR\n";
+    let record = InvoiceRecord::from_text("invoices", Path::new("data/invoices/scan.txt"), text);
+    let outcome = evaluate(&record, text, &config, &ApprovalConfig::default());
+    assert_eq!(outcome.decision, Decision::NeedsReview);
+    assert_eq!(outcome.reasons, ["no number, date"]);
+
+    let unknown = toml::from_str::<PolicyConfig>("required_fields = [\"iban\"]").unwrap_err().to_string();
+    assert!(unknown.contains("iban"), "{}", unknown);
+}
+
+#[test]
+fn nothing_is_auto_approved_without_a_limit_or_over_the_approval_threshold() {
+    assert_eq!(decide(&PolicyConfig::default(), "invoices", "ACME", "10.00", "EUR").0, Decision::NeedsReview);
+
+    let config: PolicyConfig = toml::from_str(CONFIG).unwrap();
+    let approval = ApprovalConfig { threshold: Some(Decimal::new(500, 0)), approvers: 2 };
+    let (record, text) = invoice("invoices", "ACME", "800.00", "EUR");
+    assert_eq!(evaluate(&record, &text, &config, &approval).decision, Decision::NeedsReview);
+}
+
+#[test]
+fn decisions_drive_the_status() {
+    let config: PolicyConfig = toml::from_str(CONFIG).unwrap();
+    let approval = ApprovalConfig::default();
+    let mut metadata = Metadata::default();
+    let path = Path::new("data/invoices/inv_001.txt");
+
+    let (record, text) = invoice("invoices", "ACME", "800.00", "EUR");
+    let outcome = evaluate(&record, &text, &config, &approval);
+    assert_eq!(apply(&mut metadata, path, &outcome).unwrap(), InvoiceStatus::Approved);
+    assert_eq!(metadata.status(path), InvoiceStatus::Approved);
+    let meta = metadata.get(path).unwrap();
+    let steps: Vec<(InvoiceStatus, Option<&str>)> = meta.status_history.iter().map(|c| (c.to, c.by.as_deref())).collect();
+    assert_eq!(steps, [(InvoiceStatus::Extracted, Some(POLICY_ACTOR)), (InvoiceStatus::Approved, Some(POLICY_ACTOR))]);
+    assert_eq!(meta.policy.as_ref().unwrap().decision, Decision::AutoApproved);
+
+    let other = Path::new("data/invoices/inv_002.txt");
+    let (record, text) = invoice("invoices", "ACME", "100.00", "USD");
+    apply(&mut metadata, other, &evaluate(&record, &text, &config, &approval)).unwrap();
+    assert_eq!(metadata.status(other), InvoiceStatus::Archived);
+
+    // A review decision on an extracted invoice leaves its status as it is
+    let review = Path::new("data/invoices/inv_003.txt");
+    metadata.set_status(review, InvoiceStatus::Extracted, false, 0).unwrap();
+    let (record, text) = invoice("invoices", "ACME", "2000.00", "EUR");
+    apply(&mut metadata, review, &evaluate(&record, &text, &config, &approval)).unwrap();
+    assert_eq!(metadata.get(review).unwrap().status_history.len(), 1);
+    assert_eq!(metadata.get(review).unwrap().policy.as_ref().unwrap().decision, Decision::NeedsReview);
+}
@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// The Postgres store against a real database, named by
+// DOC_AI_TEST_POSTGRES_URL (e.g. postgres://postgres@localhost/postgres);
+// without it the tests only check that the backend builds, and pass.
+// Documents saved, loaded and changed in place come back as written; two
+// connections, as two servers sharing the store, exclude each other from the
+// index lock and lose no change when they update one document at once.
+
+use doc_ai_server::lock::LockMode;
+use doc_ai_server::store::PostgresStore;
+use doc_ai_server::Store;
+
+/// A connection to the test database, or None when there is none
+fn connect() -> Option<PostgresStore> {
+    let Ok(url) = std::env::var("DOC_AI_TEST_POSTGRES_URL") else {
+        eprintln!("DOC_AI_TEST_POSTGRES_URL not set; skipped");
+        return None;
+    };
+    Some(PostgresStore::connect(&url).unwrap())
+}
+
+/// A document name no other test run uses
+fn name(what: &str) -> String {
+    format!("test-{}-{}", std::process::id(), what)
+}
+
+#[test]
+fn documents_round_trip() {
+    let Some(store) = connect() else { return };
+    let doc = name("round-trip");
+    assert_eq!(store.load(&doc).unwrap(), None);
+    assert_eq!(store.updated(&doc).unwrap(), None);
+
+    store.save(&doc, r#"{"a": 1}"#).unwrap();
+    assert_eq!(store.load(&doc).unwrap().as_deref(), Some(r#"{"a": 1}"#));
+    assert!(store.updated(&doc).unwrap().is_some());
+
+    store
+        .update(&doc, &mut |text| Ok(text.unwrap_or_default().replace('1', "2")))
+        .unwrap();
+    assert_eq!(store.load(&doc).unwrap().as_deref(), Some(r#"{"a": 2}"#));
+    assert!(store.shared());
+    assert!(store.describe().starts_with("postgres://"));
+}
+
+#[test]
+fn the_index_lock_excludes_other_servers() {
+    let (Some(writer), Some(reader)) = (connect(), connect()) else { return };
+    assert_eq!(writer.try_lock_index(LockMode::Exclusive).unwrap(), Some(true));
+    assert_eq!(reader.try_lock_index(LockMode::Shared).unwrap(), Some(false));
+    assert_eq!(reader.try_lock_index(LockMode::Exclusive).unwrap(), Some(false));
+    writer.unlock_index(LockMode::Exclusive).unwrap();
+
+    // Readers share the lock, and keep a writer out until the last one is done
+    let other = connect().unwrap();
+    assert_eq!(reader.try_lock_index(LockMode::Shared).unwrap(), Some(true));
+    assert_eq!(other.try_lock_index(LockMode::Shared).unwrap(), Some(true));
+    assert_eq!(writer.try_lock_index(LockMode::Exclusive).unwrap(), Some(false));
+    reader.unlock_index(LockMode::Shared).unwrap();
+    other.unlock_index(LockMode::Shared).unwrap();
+    assert_eq!(writer.try_lock_index(LockMode::Exclusive).unwrap(), Some(true));
+    writer.unlock_index(LockMode::Exclusive).unwrap();
+}
+
+#[test]
+fn concurrent_updates_are_serialized() {
+    let (Some(first), Some(second)) = (connect(), connect()) else { return };
+    let doc = name("counter");
+    first.save(&doc, "0").unwrap();
+    std::thread::scope(|s| {
+        for store in [&first, &second] {
+            s.spawn(|| {
+                for _ in 0..25 {
+                    store.update(&doc, &mut |text| Ok((text.unwrap().parse::<u32>()? + 1).to_string())).unwrap();
+                }
+            });
+        }
+    });
+    assert_eq!(first.load(&doc).unwrap().as_deref(), Some("50"));
+}
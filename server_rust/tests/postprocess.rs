@@ -0,0 +1,197 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// The post-processing chain: each built-in step on a response as the model
+// step leaves it, a custom step (a GL-code mapper) put in before `redact`,
+// `[postprocess]` choosing the steps per command, and the output profile
+// applied even by a chain without `redact`.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use doc_ai_server::postprocess::{
+    default_chain, run_chain, ArithmeticCheck, CitationVerify, JsonRepair, PostContext, PostProcessConfig, PostProcessor,
+    Redact, SchemaValidate, DEFAULT_STEPS,
+};
+use doc_ai_server::warnings::WarningCode;
+use doc_ai_server::{ApiResponse, AnswerStatus, Category, Collection, OutputProfile, Query};
+
+const INVOICE: &str = "Invoice INV-7\nVendor: ACME\nNet: 100.00\nVAT: 15.00\nTotal: 115.00\n";
+
+fn response(answer: Value) -> ApiResponse {
+    serde_json::from_value(json!({"answer": answer, "status": "answered", "used_files": ["inv_007.txt"]})).unwrap()
+}
+
+fn documents() -> Vec<(String, String)> {
+    vec![("inv_007.txt".to_string(), INVOICE.to_string())]
+}
+
+fn codes(response: &ApiResponse) -> Vec<WarningCode> {
+    response.warnings.iter().map(|w| w.code).collect()
+}
+
+fn run(step: impl PostProcessor, response: &mut ApiResponse, collection: &Collection, profile: Option<&OutputProfile>) {
+    let documents = documents();
+    let context = PostContext { question: "What is the total of INV-7?", collection, documents: &documents, profile };
+    step.process(response, &context).unwrap();
+}
+
+/// Adds the GL account to the answer, as a company's own step would
+struct GlCodes;
+
+impl PostProcessor for GlCodes {
+    fn name(&self) -> &str {
+        "gl-codes"
+    }
+
+    fn process(&self, response: &mut ApiResponse, _context: &PostContext<'_>) -> Result<()> {
+        let vendor = response.answer["vendor"].as_str().unwrap_or_default().to_string();
+        response.answer["gl_code"] = json!(if vendor == "ACME" { "6100" } else { "6999" });
+        Ok(())
+    }
+}
+
+struct Failing;
+
+impl PostProcessor for Failing {
+    fn name(&self) -> &str {
+        "failing"
+    }
+
+    fn process(&self, _response: &mut ApiResponse, _context: &PostContext<'_>) -> Result<()> {
+        anyhow::bail!("mapping table unavailable")
+    }
+}
+
+#[test]
+fn default_chain_runs_the_built_in_steps_in_order() {
+    let names: Vec<String> = default_chain().iter().map(|s| s.name().to_string()).collect();
+    assert_eq!(names, DEFAULT_STEPS);
+    assert_eq!(names, ["json-repair", "schema-validate", "citation-verify", "arithmetic-check", "redact"]);
+}
+
+#[test]
+fn json_repair_parses_text_answers() {
+    let collection = Collection::from_category(&Category::Invoices);
+    let mut fenced = response(json!({"raw": "```json\n{\"answer\": 115.0, \"sources\": [\"inv_007.txt\"],}\n```"}));
+    run(JsonRepair, &mut fenced, &collection, None);
+    assert_eq!(fenced.answer["answer"], json!(115.0));
+    assert!(fenced.warnings.is_empty());
+
+    let mut prose = response(json!({"raw": "The total is 115.00"}));
+    run(JsonRepair, &mut prose, &collection, None);
+    assert_eq!(codes(&prose), [WarningCode::UnparsedAnswer]);
+}
+
+#[test]
+fn schema_validate_sets_schema_errors() {
+    let mut collection = Collection::from_category(&Category::Invoices);
+    let mut answer = response(json!({"answer": "115.00"}));
+    run(SchemaValidate, &mut answer, &collection, None);
+    assert!(answer.schema_errors.is_none());
+
+    collection.schema = Some(json!({"type": "object", "required": ["answer"], "properties": {"answer": {"type": "number"}}}));
+    run(SchemaValidate, &mut answer, &collection, None);
+    assert!(!answer.schema_errors.as_ref().unwrap().is_empty());
+    assert_eq!(codes(&answer), [WarningCode::SchemaMismatch]);
+}
+
+#[test]
+fn citation_verify_flags_sources_the_model_was_not_given() {
+    let collection = Collection::from_category(&Category::Invoices);
+    let mut grounded = response(json!({"answer": 115.0, "sources": ["inv_007.txt#p1c1"]}));
+    run(CitationVerify, &mut grounded, &collection, None);
+    assert!(grounded.warnings.is_empty(), "{:?}", codes(&grounded));
+
+    let mut invented = response(json!({"answer": 115.0, "sources": ["inv_999.txt", "inv_007.txt#p9c9"]}));
+    run(CitationVerify, &mut invented, &collection, None);
+    assert_eq!(codes(&invented), [WarningCode::UnknownSource, WarningCode::UnknownSource]);
+    assert_eq!(invented.warnings[0].file.as_deref(), Some("inv_999.txt"));
+}
+
+#[test]
+fn arithmetic_check_reports_failed_invoice_checks() {
+    let collection = Collection::from_category(&Category::Invoices);
+    let mut answer = response(json!({"answer": 120.0}));
+    answer.verification = Some(vec![doc_ai_server::check_invoice("inv_007.txt", "Net: 100.00\nVAT: 15.00\nTotal: 120.00\n")]);
+    run(ArithmeticCheck, &mut answer, &collection, None);
+    assert_eq!(codes(&answer), [WarningCode::VerificationFailed]);
+}
+
+#[test]
+fn redact_applies_the_output_profile() {
+    let collection = Collection::from_category(&Category::Invoices);
+    let profile = OutputProfile { allow: vec!["vendor".to_string()], ..Default::default() };
+    let mut answer = response(json!({"vendor": "ACME", "iban": "DE89370400440532013000"}));
+    run(Redact, &mut answer, &collection, None);
+    assert!(answer.redacted.is_empty());
+    run(Redact, &mut answer, &collection, Some(&profile));
+    assert_eq!(answer.redacted, ["iban"]);
+    assert_eq!(answer.answer, json!({"vendor": "ACME"}));
+}
+
+#[test]
+fn custom_steps_run_where_they_are_put() {
+    let query = Query::builder("Vendor of INV-7?").post_processor_before("redact", GlCodes).build();
+    let names: Vec<&str> = query.post_processors.iter().map(|s| s.name()).collect();
+    assert_eq!(names, ["json-repair", "schema-validate", "citation-verify", "arithmetic-check", "gl-codes", "redact"]);
+
+    // Before redact, so the profile decides whether callers see the account
+    let collection = Collection::from_category(&Category::Invoices);
+    let profile = OutputProfile { allow: vec!["vendor".to_string(), "gl_code".to_string()], ..Default::default() };
+    let documents = documents();
+    let context =
+        PostContext { question: "Vendor of INV-7?", collection: &collection, documents: &documents, profile: Some(&profile) };
+    let mut answer = response(json!({"raw": "{\"vendor\": \"ACME\", \"iban\": \"DE89370400440532013000\"}"}));
+    run_chain(&query.post_processors, &mut answer, &context).unwrap();
+    assert_eq!(answer.answer, json!({"vendor": "ACME", "gl_code": "6100"}));
+    assert!(matches!(answer.status, AnswerStatus::Answered));
+
+    let failing: Vec<Arc<dyn PostProcessor>> = vec![Arc::new(JsonRepair), Arc::new(Failing)];
+    let error = run_chain(&failing, &mut answer, &context).unwrap_err();
+    assert!(format!("{:#}", error).contains("'failing'"), "{:#}", error);
+    assert!(format!("{:#}", error).contains("mapping table unavailable"), "{:#}", error);
+}
+
+#[test]
+fn a_chain_without_redact_still_applies_the_profile() {
+    let collection = Collection::from_category(&Category::Invoices);
+    let profile = OutputProfile { allow: vec!["vendor".to_string()], ..Default::default() };
+    let documents = documents();
+    let context =
+        PostContext { question: "Vendor of INV-7?", collection: &collection, documents: &documents, profile: Some(&profile) };
+    let config: PostProcessConfig = toml::from_str(r#"steps = ["json-repair", "gl-codes"]"#).unwrap();
+    let chain = config.chain("ask", &[Arc::new(GlCodes)]).unwrap();
+
+    let mut answer = response(json!({"vendor": "ACME", "iban": "DE89370400440532013000"}));
+    run_chain(&chain, &mut answer, &context).unwrap();
+    assert_eq!(answer.answer, json!({"vendor": "ACME"}));
+    assert_eq!(answer.redacted, ["gl_code", "iban"]);
+
+    let without_profile = PostContext { profile: None, ..context };
+    let mut answer = response(json!({"vendor": "ACME", "iban": "DE89370400440532013000"}));
+    run_chain(&chain, &mut answer, &without_profile).unwrap();
+    assert_eq!(answer.answer["iban"], json!("DE89370400440532013000"));
+}
+
+#[test]
+fn config_picks_the_steps_per_command() {
+    let config: PostProcessConfig = toml::from_str(
+        r#"
+        steps = ["json-repair", "redact"]
+        [commands]
+        ask = ["json-repair", "gl-codes", "redact"]
+        "#,
+    )
+    .unwrap();
+    let custom: Vec<Arc<dyn PostProcessor>> = vec![Arc::new(GlCodes)];
+    let names = |command: &str| -> Vec<String> {
+        config.chain(command, &custom).unwrap().iter().map(|s| s.name().to_string()).collect()
+    };
+    assert_eq!(names("ask"), ["json-repair", "gl-codes", "redact"]);
+    assert_eq!(names("serve"), ["json-repair", "redact"]);
+
+    let error = config.chain("ask", &[]).unwrap_err().to_string();
+    assert!(error.contains("Unknown post-processing step 'gl-codes'"), "{}", error);
+    assert_eq!(PostProcessConfig::default().chain("chat", &[]).unwrap().len(), DEFAULT_STEPS.len());
+}
@@ -0,0 +1,331 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Property-based tests for the parsers that read financial figures and model
+// output: money strings, dates, almost-JSON, chunk citations and file names,
+// and the VAT checks built on them. Each property runs on a few hundred
+// generated inputs; proptest shrinks a failure to a minimal case and records
+// it under proptest-regressions/ so it is replayed on every later run.
+
+use proptest::prelude::*;
+use rust_decimal::Decimal;
+use serde_json::{json, Map, Value};
+
+use doc_ai_server::chunking::{chunk_document, Citation};
+use doc_ai_server::intake::sanitize_file_name;
+use doc_ai_server::json_repair::{is_cut_off, parse_lenient, PartialJson};
+use doc_ai_server::locale::locale;
+use doc_ai_server::records::{day_number, parse_date};
+use doc_ai_server::vat::{check_invoice, parse_amount};
+
+// Money
+
+/// `whole` with `sep` between groups of three digits
+fn grouped(whole: u64, sep: &str) -> String {
+    let digits = whole.to_string();
+    let mut out = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push_str(sep);
+        }
+        out.push(digit);
+    }
+    out
+}
+
+fn cents(whole: u64, cents: u64) -> Decimal {
+    Decimal::new((whole * 100 + cents) as i64, 2)
+}
+
+proptest! {
+    #[test]
+    fn money_strings_parse_to_their_amount(
+        whole in 0u64..1_000_000_000_000,
+        fraction in 0u64..100,
+        symbol in prop::sample::select(vec!["", "R", "$", "€", "£", "ZAR", "USD"]),
+        space in prop::sample::select(vec!["", " "]),
+        sep in prop::sample::select(vec!["", ",", " "]),
+    ) {
+        let text = format!("{}{}{}.{:02}", symbol, space, grouped(whole, sep), fraction);
+        prop_assert_eq!(parse_amount(&text), Some(cents(whole, fraction)), "{:?}", text);
+    }
+
+    #[test]
+    fn money_on_a_labelled_line_parses(whole in 0u64..10_000_000, fraction in 0u64..100) {
+        let text = format!("Total due: R {}.{:02}", grouped(whole, ","), fraction);
+        prop_assert_eq!(parse_amount(&text), Some(cents(whole, fraction)));
+    }
+
+    #[test]
+    fn locale_amounts_parse_back(
+        whole in 0u64..1_000_000_000,
+        fraction in 0u64..100,
+        tag in prop::sample::select(vec!["en-US", "en-GB"]),
+    ) {
+        let amount = cents(whole, fraction);
+        let text = locale(tag).unwrap().format_amount(amount, None);
+        prop_assert_eq!(parse_amount(&text), Some(amount), "{:?}", text);
+    }
+
+    #[test]
+    fn parse_amount_never_panics(text in any::<String>()) {
+        let _ = parse_amount(&text);
+    }
+
+    #[test]
+    fn long_digit_runs_are_rejected_not_wrapped(digits in "[1-9][0-9]{29,60}") {
+        // Beyond what a Decimal holds: no amount rather than a wrong one
+        prop_assert_eq!(parse_amount(&format!("{}.00", digits)), None);
+    }
+}
+
+// Dates
+
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November", "December",
+];
+
+/// (year, month, day) with days that exist in every month
+fn dates() -> impl Strategy<Value = (u32, u32, u32)> {
+    (1000u32..=9999, 1u32..=12, 1u32..=28)
+}
+
+fn iso((y, m, d): (u32, u32, u32)) -> String {
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+proptest! {
+    #[test]
+    fn iso_dates_are_read_as_they_are(date in dates(), label in "[A-Za-z :]{0,20}") {
+        prop_assert_eq!(parse_date(&format!("{} {}.", label, iso(date))), Some(iso(date)));
+    }
+
+    #[test]
+    fn written_dates_are_read_as_iso(
+        date in dates(),
+        suffix in prop::sample::select(vec!["", "st", "nd", "rd", "th"]),
+        short in any::<bool>(),
+        upper in any::<bool>(),
+    ) {
+        let (y, m, d) = date;
+        let name = MONTHS[m as usize - 1];
+        let name = if short { format!("{}.", &name[..3]) } else { name.to_string() };
+        let name = if upper { name.to_uppercase() } else { name };
+        let text = format!("Date: {}{} {} {}", d, suffix, name, y);
+        prop_assert_eq!(parse_date(&text), Some(iso(date)), "{:?}", text);
+    }
+
+    #[test]
+    fn parsed_dates_are_always_iso_shaped(text in any::<String>()) {
+        if let Some(date) = parse_date(&text) {
+            let shaped = date.len() == 10
+                && date.char_indices().all(|(i, c)| if i == 4 || i == 7 { c == '-' } else { c.is_ascii_digit() });
+            prop_assert!(shaped, "{:?} from {:?}", date, text);
+        }
+    }
+
+    #[test]
+    fn day_numbers_follow_the_calendar(a in dates(), b in dates()) {
+        let (na, nb) = (day_number(&iso(a)).unwrap(), day_number(&iso(b)).unwrap());
+        prop_assert_eq!(iso(a).cmp(&iso(b)), na.cmp(&nb));
+        let (y, m, d) = a;
+        if d < 28 {
+            prop_assert_eq!(day_number(&iso((y, m, d + 1))), Some(na + 1));
+        }
+    }
+}
+
+// Almost-JSON
+
+fn text() -> impl Strategy<Value = String> {
+    r#"[a-zA-Z0-9 .,:;!?#/'"\\-]{0,12}"#
+}
+
+/// Strings without quotes or backslashes, which survive a switch to single quotes
+fn plain_text() -> impl Strategy<Value = String> {
+    "[a-zA-Z0-9 .,:;!?#/-]{0,12}"
+}
+
+fn leaf(text: BoxedStrategy<String>) -> impl Strategy<Value = Value> {
+    prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::Bool),
+        any::<i64>().prop_map(Value::from),
+        text.prop_map(Value::String),
+    ]
+}
+
+/// Non-empty objects of nested (non-empty) objects, arrays and scalars
+fn objects(text: BoxedStrategy<String>) -> impl Strategy<Value = Value> {
+    let value = leaf(text).prop_recursive(3, 24, 4, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 1..4).prop_map(Value::Array),
+            prop::collection::btree_map("[a-z_]{1,8}", inner, 1..4).prop_map(|m| Value::Object(m.into_iter().collect())),
+        ]
+    });
+    prop::collection::btree_map("[a-z_]{1,8}", value, 1..5).prop_map(|m| Value::Object(m.into_iter().collect::<Map<_, _>>()))
+}
+
+proptest! {
+    #[test]
+    fn fenced_json_is_unwrapped(value in objects(text().boxed()), pretty in any::<bool>()) {
+        let json = if pretty { serde_json::to_string_pretty(&value).unwrap() } else { value.to_string() };
+        let fenced = format!("```json\n{}\n```", json);
+        prop_assert_eq!(parse_lenient(&fenced), Some(value));
+    }
+
+    #[test]
+    fn prose_around_json_is_ignored(value in objects(text().boxed())) {
+        let text = format!("Here is the answer:\n{}\n\nLet me know if you need anything else.", value);
+        prop_assert_eq!(parse_lenient(&text), Some(value));
+    }
+
+    #[test]
+    fn trailing_commas_are_dropped(value in objects(text().boxed())) {
+        // The strings hold no braces or brackets, so every one of them is structural
+        let text = value.to_string().replace('}', ",}").replace(']', ",]");
+        prop_assert_eq!(parse_lenient(&text), Some(value));
+    }
+
+    #[test]
+    fn single_quotes_are_accepted(value in objects(plain_text().boxed())) {
+        let text = value.to_string().replace('"', "'");
+        prop_assert_eq!(parse_lenient(&text), Some(value));
+    }
+
+    #[test]
+    fn cut_off_json_is_recognized(value in objects(text().boxed()), at in 0.0f64..1.0) {
+        let json = value.to_string();
+        let cut = &json[..1 + ((json.len() - 1) as f64 * at) as usize];
+        prop_assert!(cut.len() < json.len());
+        prop_assert!(is_cut_off(cut), "{:?}", cut);
+        if let Some(repaired) = parse_lenient(cut) {
+            prop_assert!(repaired.is_object(), "{:?} repaired to {}", cut, repaired);
+        }
+    }
+
+    #[test]
+    fn streamed_json_ends_with_the_whole_value(
+        value in objects(text().boxed()),
+        cuts in prop::collection::vec(0.0f64..1.0, 0..8),
+    ) {
+        let json = value.to_string();
+        let mut cuts: Vec<usize> = cuts.iter().map(|c| (json.len() as f64 * c) as usize).collect();
+        cuts.push(0);
+        cuts.push(json.len());
+        cuts.sort();
+        let mut partial = PartialJson::new();
+        let mut last = None;
+        for piece in cuts.windows(2).map(|w| &json[w[0]..w[1]]) {
+            if let Some(v) = partial.push(piece) {
+                last = Some(v);
+            }
+        }
+        prop_assert_eq!(last, Some(value));
+    }
+
+    #[test]
+    fn parse_lenient_never_panics(text in any::<String>()) {
+        let _ = parse_lenient(&text);
+        let _ = is_cut_off(&text);
+    }
+}
+
+// Citations, chunks and file names
+
+proptest! {
+    #[test]
+    fn chunk_citations_name_file_page_and_chunk(
+        file in "[A-Za-z0-9_. -]{1,24}",
+        page in 1usize..500,
+        n in 1usize..50,
+    ) {
+        let chunk = format!("p{}c{}", page, n);
+        let expected = Citation { file: file.clone(), page: Some(page), chunk: Some(chunk.clone()) };
+        prop_assert_eq!(Citation::from_value(&json!(format!("{}#{}", file, chunk))), Some(expected.clone()));
+        prop_assert_eq!(Citation::from_value(&json!({"file": file, "chunk": chunk})), Some(expected));
+    }
+
+    #[test]
+    fn chunks_cover_the_document(
+        lines in prop::collection::vec(
+            ("[a-zA-Z0-9é .]{0,60}", prop::sample::select(vec!["\n", "\n\n", "\n\u{c}"])),
+            0..80,
+        ),
+    ) {
+        let text: String = lines.iter().map(|(line, end)| format!("{}{}", line, end)).collect();
+        let chunks = chunk_document(&text);
+        let mut previous_end = 0;
+        let mut ids = std::collections::HashSet::new();
+        let mut covered = 0;
+        for chunk in &chunks {
+            prop_assert!(previous_end <= chunk.start && chunk.start < chunk.end && chunk.end <= text.len());
+            let body = text.get(chunk.start..chunk.end);
+            prop_assert!(body.is_some(), "{:?} is not on character boundaries", chunk);
+            let body = body.unwrap();
+            prop_assert!(!body.trim().is_empty());
+            prop_assert_eq!(chunk.page, 1 + text[..chunk.start].matches('\u{c}').count());
+            prop_assert!(ids.insert(chunk.id.clone()), "duplicate id {}", chunk.id);
+            covered += body.chars().filter(|c| !c.is_whitespace()).count();
+            previous_end = chunk.end;
+        }
+        // Only whitespace is left out
+        prop_assert_eq!(covered, text.chars().filter(|c| !c.is_whitespace()).count());
+    }
+
+    #[test]
+    fn sanitized_file_names_are_safe(name in any::<String>()) {
+        let safe = sanitize_file_name(&name);
+        prop_assert!(!safe.starts_with('.'));
+        prop_assert!(safe.chars().all(|c| c.is_alphanumeric() || matches!(c, '.' | '-' | '_')), "{:?}", safe);
+        prop_assert_eq!(sanitize_file_name(&safe), safe.clone());
+    }
+
+    #[test]
+    fn weird_file_names_keep_their_last_component(
+        dirs in prop::collection::vec("[a-z.]{1,8}", 0..4),
+        name in "[a-zA-Z0-9_-]{1,12}",
+        sep in prop::sample::select(vec!["/", "\\"]),
+    ) {
+        let path = dirs.iter().map(|d| format!("{}{}", d, sep)).collect::<String>() + name.as_str() + ".txt";
+        prop_assert_eq!(sanitize_file_name(&path), format!("{}.txt", name));
+    }
+}
+
+// Invoice checks
+
+/// Invoice text with these line items (quantity, unit price in cents), VAT at 15%
+/// and the gross total `error` cents too high
+fn invoice(items: &[(u32, i64)], error: i64) -> String {
+    let mut text = String::from("INVOICE INV-00042\nFrom: Acme Supplies\nDate: 2025-11-03\n\n");
+    let mut net = Decimal::ZERO;
+    for (i, (quantity, unit)) in items.iter().enumerate() {
+        let unit = Decimal::new(*unit, 2);
+        let total = Decimal::from(*quantity) * unit;
+        net += total;
+        text.push_str(&format!("Item {}  {}  {:.2}  {:.2}\n", i + 1, quantity, unit, total));
+    }
+    let vat = (net * Decimal::from(15) / Decimal::ONE_HUNDRED).round_dp(2);
+    let gross = net + vat + Decimal::new(error, 2);
+    text.push_str(&format!("\nSubtotal: R {:.2}\nVAT (15%): R {:.2}\nTotal: R {:.2}\n", net, vat, gross));
+    text
+}
+
+fn line_items() -> impl Strategy<Value = Vec<(u32, i64)>> {
+    prop::collection::vec((1u32..100, 1i64..10_000_000), 1..8)
+}
+
+proptest! {
+    #[test]
+    fn consistent_invoices_pass(items in line_items()) {
+        let report = check_invoice("inv.txt", &invoice(&items, 0));
+        prop_assert!(report.consistent, "{:?}", report.issues);
+        prop_assert_eq!(report.figures.line_items.len(), items.len());
+    }
+
+    #[test]
+    fn a_wrong_total_is_caught(items in line_items(), error in 2i64..=100_000) {
+        let report = check_invoice("inv.txt", &invoice(&items, error));
+        prop_assert!(!report.consistent);
+        prop_assert!(report.issues.iter().any(|i| i.code == "gross_mismatch"), "{:?}", report.issues);
+    }
+}
@@ -0,0 +1,120 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Queue intake over the in-memory queue: documents sent as text, base64 or a
+// URL (served by wiremock) are saved and read, the results published in
+// order with the event ids, and broken events answered with an error rather
+// than stopping the consumer. Every event is acknowledged after its result.
+
+use base64::Engine as _;
+use serde_json::{json, Value};
+use std::fs;
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use doc_ai_server::{Category, Collection, MemoryQueue, QueueConsumer};
+
+const INVOICE: &str = "Invoice INV-42\nVendor: ACME\nSubtotal: 100.00\nVAT (15%): 15.00\nTotal Due: 115.00\n";
+
+/// The invoices collection in a fresh folder under the system temp folder
+fn collection(name: &str) -> Collection {
+    let mut collection = Collection::from_category(&Category::Invoices);
+    collection.folder = std::env::temp_dir().join(format!("doc-ai-queue-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&collection.folder);
+    collection
+}
+
+fn queue(events: &[Value]) -> MemoryQueue {
+    MemoryQueue { incoming: events.iter().map(|e| serde_json::to_vec(e).unwrap()).collect(), ..Default::default() }
+}
+
+fn published(queue: &MemoryQueue) -> Vec<Value> {
+    queue.published.iter().map(|p| serde_json::from_slice(p).unwrap()).collect()
+}
+
+#[tokio::test]
+async fn documents_are_saved_read_and_published() {
+    let collections = [collection("saved")];
+    let consumer = QueueConsumer { collections: &collections, default_collection: "invoices", index: false, publishers: &[] };
+    let content = base64::engine::general_purpose::STANDARD.encode(INVOICE.replace("42", "43"));
+    let mut queue = queue(&[
+        json!({"id": "evt-1", "name": "inv_042.txt", "text": INVOICE}),
+        json!({"id": "evt-2", "collection": "invoice", "name": "inv_043.txt", "content": content}),
+        json!({"id": "evt-3", "name": "inv_042.txt", "text": INVOICE}),
+    ]);
+
+    assert_eq!(consumer.consume_batch(&mut queue, 10, Duration::ZERO).await.unwrap(), 3);
+    let results = published(&queue);
+    assert_eq!(queue.acked, 3);
+    assert_eq!(results[0]["id"], "evt-1");
+    assert_eq!(results[0]["outcome"], json!({"saved": "inv_042.txt"}));
+    assert_eq!(results[0]["record"]["vendor"], "ACME");
+    assert_eq!(results[0]["record"]["gross"], "115.00");
+    assert_eq!(results[0]["verification"]["consistent"], true);
+    assert_eq!(results[1]["record"]["id"], "INV-43");
+    // The same bytes again are the same document
+    assert_eq!(results[2]["outcome"], json!({"duplicate": "inv_042.txt"}));
+    assert_eq!(fs::read_dir(&collections[0].folder).unwrap().count(), 2);
+}
+
+#[tokio::test]
+async fn documents_are_downloaded() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/scans/inv_044.txt"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(INVOICE.replace("42", "44")))
+        .mount(&server)
+        .await;
+    let collections = [collection("download")];
+    let consumer = QueueConsumer { collections: &collections, default_collection: "invoices", index: false, publishers: &[] };
+    let mut queue = queue(&[
+        json!({"id": "evt-4", "url": format!("{}/scans/inv_044.txt?sig=abc", server.uri())}),
+        json!({"id": "evt-5", "url": format!("{}/scans/missing.txt", server.uri())}),
+    ]);
+
+    consumer.consume_batch(&mut queue, 10, Duration::ZERO).await.unwrap();
+    let results = published(&queue);
+    assert_eq!(results[0]["outcome"], json!({"saved": "inv_044.txt"}));
+    assert_eq!(results[0]["record"]["id"], "INV-44");
+    assert!(results[1]["error"].as_str().unwrap().contains("404"), "{}", results[1]);
+}
+
+#[tokio::test]
+async fn broken_events_get_an_error_result() {
+    let collections = [collection("broken")];
+    let consumer = QueueConsumer { collections: &collections, default_collection: "invoices", index: false, publishers: &[] };
+    let mut queue = queue(&[
+        json!({"id": "evt-6", "collection": "contracts", "name": "c.txt", "text": "x"}),
+        json!({"id": "evt-7", "name": "inv.txt"}),
+        json!({"id": "evt-8", "name": "inv.txt", "content": "not base64!"}),
+        json!({"id": "evt-9", "name": "scan.pdf", "text": INVOICE}),
+    ]);
+    queue.incoming.push_back(b"not json".to_vec());
+
+    assert_eq!(consumer.consume_batch(&mut queue, 10, Duration::ZERO).await.unwrap(), 5);
+    let results = published(&queue);
+    assert_eq!(queue.acked, 5);
+    let error = |n: usize| results[n]["error"].as_str().unwrap_or_default().to_string();
+    assert!(error(0).contains("Unknown collection 'contracts'"), "{}", error(0));
+    assert!(error(1).contains("no url, content or text"), "{}", error(1));
+    assert!(error(2).contains("not base64"), "{}", error(2));
+    assert!(results[3]["outcome"]["skipped"].is_string(), "{}", results[3]);
+    assert!(results[3].get("record").is_none());
+    assert!(error(4).contains("not a JSON document event"), "{}", error(4));
+    assert_eq!(results[4]["id"], Value::Null);
+}
+
+#[tokio::test]
+async fn batches_take_what_is_there() {
+    let collections = [collection("batches")];
+    let consumer = QueueConsumer { collections: &collections, default_collection: "invoices", index: false, publishers: &[] };
+    let events: Vec<Value> = (0..5)
+        .map(|n| json!({"id": format!("evt-{}", n), "name": format!("inv_{}.txt", n), "text": INVOICE.replace("42", &n.to_string())}))
+        .collect();
+    let mut queue = queue(&events);
+    assert_eq!(consumer.consume_batch(&mut queue, 2, Duration::ZERO).await.unwrap(), 2);
+    assert_eq!(consumer.consume_batch(&mut queue, 2, Duration::ZERO).await.unwrap(), 2);
+    assert_eq!(consumer.consume_batch(&mut queue, 2, Duration::ZERO).await.unwrap(), 1);
+    assert_eq!(consumer.consume_batch(&mut queue, 2, Duration::ZERO).await.unwrap(), 0);
+    assert_eq!(queue.published.len(), 5);
+}
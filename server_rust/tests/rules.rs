@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// `[[rule]]` validators: what a rule sees of an invoice, Rhai scripts for a
+// PO number format and a vendor's contract reference, violations reported
+// per invoice, scripts that fail to run, and (with `wasm`) a module.
+
+use serde_json::json;
+use std::fs;
+use std::path::PathBuf;
+
+use doc_ai_server::metadata::Metadata;
+use doc_ai_server::rules::{check_rules, load_rules, rule_input, LoadedRule, RhaiRule, RuleConfig};
+use doc_ai_server::InvoiceRecord;
+
+const PO_FORMAT: &str = r#"
+    let po = invoice.identifiers.filter(|id| id.starts_with("PO-"));
+    if po.is_empty() { return "no PO number"; }
+    po.filter(|id| !matches(id, "^PO-\\d{6}$")).map(|id| `${id} is not PO- and six digits`)
+"#;
+
+const ACME_CONTRACT: &str = r#"
+    invoice.vendor != "ACME" || invoice.text.contains("C-77")
+"#;
+
+/// A fresh folder under the system temp folder with the given invoices
+fn invoices(name: &str, files: &[(&str, &str)]) -> Vec<InvoiceRecord> {
+    let dir = std::env::temp_dir().join(format!("doc-ai-rules-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    files
+        .iter()
+        .map(|(file, text)| {
+            let path: PathBuf = dir.join(file);
+            fs::write(&path, text).unwrap();
+            InvoiceRecord::from_text("invoices", &path, text)
+        })
+        .collect()
+}
+
+fn rhai(name: &str, script: &str) -> LoadedRule {
+    LoadedRule { rule: Box::new(RhaiRule::new(name, script).unwrap()), collections: Vec::new() }
+}
+
+#[test]
+fn rules_see_the_record_identifiers_and_text() {
+    let text = "Invoice INV-7\nVendor: ACME\nOrder: PO-123456\nNet: 100.00\nVAT: 15.00\nTotal: 115.00\n";
+    let records = invoices("input", &[("inv_007.txt", text)]);
+    let input = rule_input(&records[0], text, &Metadata::default());
+    assert_eq!(input["vendor"], json!("ACME"));
+    assert_eq!(input["gross"], json!(115.0));
+    assert!(input["identifiers"].as_array().unwrap().contains(&json!("PO-123456")));
+    assert_eq!(input["extraction"], json!(null));
+    assert_eq!(input["text"], json!(text));
+}
+
+#[test]
+fn violations_are_reported_per_invoice() {
+    let records = invoices(
+        "report",
+        &[
+            ("inv_001.txt", "Invoice INV-1\nVendor: ACME\nOrder: PO-123456\nContract C-77\nTotal: 10.00\n"),
+            ("inv_002.txt", "Invoice INV-2\nVendor: ACME\nOrder: PO-12345\nTotal: 20.00\n"),
+            ("inv_003.txt", "Invoice INV-3\nVendor: Globex\nTotal: 30.00\n"),
+        ],
+    );
+    let rules = [rhai("po-format", PO_FORMAT), rhai("acme-contract", ACME_CONTRACT)];
+    let report = check_rules(&records, &rules, &Metadata::default());
+    assert_eq!((report.invoices, report.rules), (3, 2));
+    assert!(!report.passed());
+
+    let failed: Vec<(&str, Vec<(&str, &str)>)> = report
+        .failed
+        .iter()
+        .map(|i| (i.file.as_str(), i.violations.iter().map(|v| (v.rule.as_str(), v.message.as_str())).collect()))
+        .collect();
+    assert_eq!(
+        failed,
+        [
+            (
+                "inv_002.txt",
+                vec![("po-format", "PO-12345 is not PO- and six digits"), ("acme-contract", "acme-contract failed")]
+            ),
+            ("inv_003.txt", vec![("po-format", "no PO number")]),
+        ]
+    );
+    assert!(report.to_table().contains("invoices/inv_002.txt (INV-2)\n    [po-format] PO-12345 is not PO- and six digits"));
+}
+
+#[test]
+fn rules_apply_to_their_collections() {
+    let records = invoices("collections", &[("inv_003.txt", "Invoice INV-3\nVendor: Globex\nTotal: 30.00\n")]);
+    let mut rule = rhai("po-format", PO_FORMAT);
+    rule.collections = vec!["receipts".to_string()];
+    assert!(check_rules(&records, &[rule], &Metadata::default()).passed());
+}
+
+#[test]
+fn scripts_that_fail_count_as_violations() {
+    assert!(RhaiRule::new("broken", "let = ;").is_err());
+
+    let records = invoices("failing", &[("inv_001.txt", "Invoice INV-1\nTotal: 10.00\n")]);
+    let rules = [rhai("bad-regex", r#"matches(invoice.file, "(")"#), rhai("endless", "loop {}")];
+    let report = check_rules(&records, &rules, &Metadata::default());
+    let messages: Vec<&str> = report.failed[0].violations.iter().map(|v| v.message.as_str()).collect();
+    assert_eq!(messages.len(), 2);
+    assert!(messages.iter().all(|m| m.starts_with("rule failed:")), "{:?}", messages);
+}
+
+#[test]
+fn rules_load_by_extension() {
+    let dir = std::env::temp_dir().join(format!("doc-ai-rules-{}-load", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("po_format.rhai"), PO_FORMAT).unwrap();
+    let config = |file: &str| RuleConfig { name: "po-format".to_string(), path: dir.join(file), collections: Vec::new() };
+
+    let loaded = load_rules(&[config("po_format.rhai")]).unwrap();
+    assert_eq!(loaded[0].rule.name(), "po-format");
+    let error = format!("{:#}", load_rules(&[config("po_format.lua")]).err().unwrap());
+    assert!(error.contains("Unknown rule type '.lua'"), "{}", error);
+    assert!(load_rules(&[config("missing.rhai")]).is_err());
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn wasm_modules_return_their_messages() {
+    use doc_ai_server::rules::WasmRule;
+
+    // Answers `["always fails"]` (16 bytes at offset 16) whatever the invoice
+    let module = r#"
+        (module
+          (memory (export "memory") 1)
+          (data (i32.const 16) "[\"always fails\"]")
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "validate") (param i32 i32) (result i64)
+            (i64.or (i64.shl (i64.const 16) (i64.const 32)) (i64.const 16))))
+    "#;
+    let rule = WasmRule::new("always", module.as_bytes()).unwrap();
+    assert_eq!(rule.check(&json!({"file": "inv_001.txt"})).unwrap(), ["always fails"]);
+}
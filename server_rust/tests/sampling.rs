@@ -0,0 +1,56 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Self-consistency statistics: samples that agree, samples that scatter, and
+// the ones whose spread cannot be computed (a zero median, amounts at the
+// limit of a Decimal), which count as disagreeing instead of panicking.
+
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+
+use doc_ai_server::summarize;
+
+fn total(value: Value) -> Value {
+    json!({"total": value, "status": "answered"})
+}
+
+#[test]
+fn agreeing_samples_are_not_suspect() {
+    let answers = vec![total(json!("R8,866.50")); 3];
+    let (majority, consistency) = summarize(&answers).unwrap();
+    assert_eq!(majority, answers[0]);
+    assert!(!consistency.suspect);
+    assert_eq!(consistency.fields["total"].relative_spread, Some(Decimal::ZERO));
+}
+
+#[test]
+fn scattered_samples_are_suspect() {
+    let answers = [total(json!(100)), total(json!(100)), total(json!(150))];
+    let (_, consistency) = summarize(&answers).unwrap();
+    assert!(consistency.suspect);
+    assert_eq!(consistency.fields["total"].relative_spread, Some(Decimal::new(5, 1)));
+}
+
+#[test]
+fn a_zero_median_with_differing_samples_is_no_agreement() {
+    let answers = [total(json!(-5)), total(json!(0)), total(json!(5))];
+    let (_, consistency) = summarize(&answers).unwrap();
+    assert!(consistency.suspect);
+    assert_eq!(consistency.fields["total"].median, Some(Decimal::ZERO));
+    assert_eq!(consistency.fields["total"].relative_spread, None);
+
+    let (_, zeros) = summarize(&[total(json!(0)), total(json!(0))]).unwrap();
+    assert!(!zeros.suspect);
+}
+
+#[test]
+fn amounts_too_large_to_average_are_no_agreement() {
+    let largest = "79228162514264337593543950335.00";
+    let (_, apart) = summarize(&[total(json!(largest)), total(json!("79228162514264337593543950000.00"))]).unwrap();
+    assert_eq!(apart.fields["total"].median, None);
+    assert_eq!(apart.fields["total"].relative_spread, None);
+    assert!(apart.suspect);
+
+    let (_, same) = summarize(&[total(json!(largest)), total(json!(largest))]).unwrap();
+    assert_eq!(same.fields["total"].median, None);
+    assert!(!same.suspect);
+}
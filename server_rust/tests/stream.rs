@@ -0,0 +1,105 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// `GET /query/stream` of a running server, answered by a mock Ollama
+// (wiremock) that streams its answer: under an output profile no `delta`
+// events carry the raw model output, and neither the partial answers nor the
+// final one show a field the profile hides.
+
+use serde_json::{json, Value};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const PIECES: &[&str] = &["{\"vendor\": \"Acme Supplies Ltd\", ", "\"iban\": \"DE89370400440532013000\", ", "\"status\": \"answered\"}"];
+
+/// Kills the server when the test ends, passed or not
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// A working directory with the sample invoice and a config with a `clerk` profile that only sees the vendor
+fn workspace(name: &str, ollama: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("doc-ai-stream-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    for folder in ["invoices", "employment-contracts", "customer-support", "knowledge-base"] {
+        std::fs::create_dir_all(dir.join("data").join(folder)).unwrap();
+    }
+    std::fs::copy("../data/invoices/inv_001.txt", dir.join("data/invoices/inv_001.txt")).unwrap();
+    let config = format!("ollama_url = \"{}\"\nmodel = \"mock\"\n\n[output_profiles.clerk]\nallow = [\"vendor\"]\n", ollama);
+    std::fs::write(dir.join("doc-ai.toml"), config).unwrap();
+    dir
+}
+
+/// The (event, data) pairs of a Server-Sent Events body
+fn events(body: &str) -> Vec<(String, Value)> {
+    body.split("\n\n")
+        .filter_map(|block| {
+            let event = block.lines().find_map(|l| l.strip_prefix("event:"))?.trim().to_string();
+            let data = block.lines().find_map(|l| l.strip_prefix("data:"))?.trim();
+            Some((event, serde_json::from_str(data).ok()?))
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn a_profiled_stream_shows_only_redacted_answers() {
+    let ollama = MockServer::start().await;
+    let mut body = String::new();
+    for piece in PIECES {
+        body.push_str(&format!("{}\n", json!({"response": piece, "done": false})));
+    }
+    body.push_str(&format!("{}\n", json!({"response": "", "done": true, "eval_count": PIECES.len()})));
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(body, "application/x-ndjson"))
+        .mount(&ollama)
+        .await;
+    Mock::given(method("GET")).and(path("/api/tags")).respond_with(ResponseTemplate::new(200).set_body_json(json!({"models": [{"name": "mock"}]}))).mount(&ollama).await;
+
+    let port = free_port();
+    let _server = Server(
+        Command::new(env!("CARGO_BIN_EXE_doc-ai-server"))
+            .current_dir(workspace("profiled", &ollama.uri()))
+            .args(["--port", &port.to_string(), "--no-reload", "--output-profile", "clerk", "serve"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap(),
+    );
+
+    let url = format!("http://127.0.0.1:{}/query/stream?query=Who%20sent%20INV-2025-001%3F&collection=invoices", port);
+    let client = reqwest::Client::new();
+    let mut response = None;
+    for _ in 0..100 {
+        match client.get(&url).send().await {
+            Ok(r) => {
+                response = Some(r);
+                break;
+            }
+            Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+    let text = response.expect("the server did not start").text().await.unwrap();
+    let events = events(&text);
+
+    assert!(!events.iter().any(|(kind, _)| kind == "delta"), "{}", text);
+    for (_, data) in events.iter().filter(|(kind, _)| kind == "partial") {
+        assert!(data["answer"].get("iban").is_none(), "{}", data);
+    }
+    let (_, envelope) = events.iter().find(|(kind, _)| kind == "answer").expect("no answer event");
+    assert_eq!(envelope["success"], json!(true), "{}", envelope);
+    assert_eq!(envelope["data"]["answer"], json!({"vendor": "Acme Supplies Ltd", "status": "answered"}));
+    assert_eq!(envelope["data"]["redacted"], json!(["iban"]));
+}
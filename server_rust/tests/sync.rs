@@ -0,0 +1,123 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// POST /sync as the receiving side sees it (`sync::apply`): tags and notes
+// are taken in, status changes only along the allowed steps and approvals
+// only from as many different people as `[approval]` asks, so a peer cannot
+// approve or pay an invoice by sending its metadata. A refused document keeps
+// its metadata, and every document changed is in the audit log.
+
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use doc_ai_server::approval::{Approval, ApprovalConfig};
+use doc_ai_server::audit;
+use doc_ai_server::lifecycle::{InvoiceStatus, StatusChange};
+use doc_ai_server::metadata::{DocumentMeta, Metadata};
+use doc_ai_server::sync::{apply, shared, SyncPush};
+
+const INVOICE: &str = "data/invoices/inv_sync.txt";
+
+/// A working directory with one received invoice, made current
+fn workspace() {
+    let dir = std::env::temp_dir().join(format!("doc-ai-sync-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("data/invoices")).unwrap();
+    fs::write(dir.join(INVOICE), "Invoice INV-2025-901\nVendor: Acme Supplies Ltd\nTotal: 25,000.00\n").unwrap();
+    std::env::set_current_dir(&dir).unwrap();
+}
+
+fn step(from: InvoiceStatus, to: InvoiceStatus, at: u64) -> StatusChange {
+    StatusChange { from, to, at, forced: false, by: None }
+}
+
+fn approval(by: &str, at: u64) -> Approval {
+    Approval { by: by.to_string(), at }
+}
+
+/// What a peer sends for the invoice
+fn push(meta: DocumentMeta) -> SyncPush {
+    SyncPush { documents: BTreeMap::new(), metadata: BTreeMap::from([("invoices/inv_sync.txt".to_string(), meta)]) }
+}
+
+fn status() -> InvoiceStatus {
+    Metadata::load().unwrap().status(Path::new(INVOICE))
+}
+
+#[test]
+fn pushed_status_goes_through_the_lifecycle_and_the_approval_rule() {
+    workspace();
+    let shared = shared(None);
+    let rule = ApprovalConfig { threshold: Some(Decimal::ZERO), approvers: 2 };
+    let (received, extracted, approved, paid) =
+        (InvoiceStatus::Received, InvoiceStatus::Extracted, InvoiceStatus::Approved, InvoiceStatus::Paid);
+
+    // Straight to paid, skipping extraction and approval
+    let skipping = DocumentMeta {
+        status: Some(paid),
+        status_history: vec![StatusChange { forced: true, ..step(received, paid, 10) }],
+        tags: ["urgent".to_string()].into(),
+        ..DocumentMeta::default()
+    };
+    let applied = apply(&shared, &push(skipping), &rule, "peer").unwrap();
+    assert_eq!(applied.metadata, 0);
+    assert_eq!(applied.refused.len(), 1, "{:?}", applied.refused);
+    assert_eq!(status(), received);
+    assert!(Metadata::load().unwrap().get(Path::new(INVOICE)).is_none(), "the refused tags were kept");
+
+    // Approved by one person where two must approve
+    let one_approver = DocumentMeta {
+        status: Some(approved),
+        status_history: vec![step(received, extracted, 10), step(extracted, approved, 20)],
+        approvals: vec![approval("mallory", 20)],
+        ..DocumentMeta::default()
+    };
+    let applied = apply(&shared, &push(one_approver), &rule, "peer").unwrap();
+    assert_eq!(applied.refused.len(), 1, "{:?}", applied.refused);
+    assert_eq!(status(), received);
+
+    // Extracted, with tags and one approval so far: taken in, waiting for a second approver
+    let extracted_only = DocumentMeta {
+        status: Some(extracted),
+        status_history: vec![step(received, extracted, 10)],
+        approvals: vec![approval("alice", 15)],
+        tags: ["urgent".to_string()].into(),
+        ..DocumentMeta::default()
+    };
+    let applied = apply(&shared, &push(extracted_only), &rule, "peer").unwrap();
+    assert!(applied.refused.is_empty(), "{:?}", applied.refused);
+    assert_eq!(applied.metadata, 1);
+    assert_eq!(status(), extracted);
+    let meta = Metadata::load().unwrap().get(Path::new(INVOICE)).cloned().unwrap();
+    assert!(meta.tags.contains("urgent"));
+    assert_eq!(meta.approvals.len(), 1);
+
+    // A second, different approver: approved
+    let two_approvers = DocumentMeta {
+        status: Some(approved),
+        status_history: vec![step(received, extracted, 10), step(extracted, approved, 30)],
+        approvals: vec![approval("alice", 15), approval("bob", 30)],
+        ..DocumentMeta::default()
+    };
+    let applied = apply(&shared, &push(two_approvers), &rule, "peer").unwrap();
+    assert!(applied.refused.is_empty(), "{:?}", applied.refused);
+    assert_eq!(status(), approved);
+
+    // Paid on the other side afterwards: the step after the status here is made
+    let paid_there = DocumentMeta {
+        status: Some(paid),
+        status_history: vec![step(received, extracted, 10), step(extracted, approved, 30), step(approved, paid, 40)],
+        approvals: vec![approval("alice", 15), approval("bob", 30)],
+        ..DocumentMeta::default()
+    };
+    let applied = apply(&shared, &push(paid_there), &rule, "peer").unwrap();
+    assert!(applied.refused.is_empty(), "{:?}", applied.refused);
+    assert_eq!(status(), paid);
+
+    // One audit entry for each push that changed the document
+    let entries = audit::entries().unwrap();
+    let synced: Vec<_> = entries.iter().filter(|e| e.action == "sync").collect();
+    assert_eq!(synced.len(), 3, "{:?}", synced);
+    assert!(synced.iter().all(|e| e.actor == "peer" && e.target.as_deref() == Some(INVOICE)));
+}
@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Tenant isolation in the indexes: with two tenants holding their own copies
+// of the invoices, neither search nor the retrieval behind a query (keyword,
+// BM25, exact identifiers) returns a document of the other tenant, or of a
+// tenant at all when none is asking, even when the question names the other
+// tenant's invoice number.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use doc_ai_server::collections::{collections_from_config, find_collection, init_collections};
+use doc_ai_server::config::Config;
+use doc_ai_server::events::NoEvents;
+use doc_ai_server::retrieval::{retrieve, RetrievalConfig, RetrievalMode};
+use doc_ai_server::search::search;
+use doc_ai_server::tenants::key_hash;
+use doc_ai_server::Tenant;
+
+const ACME: &str = "Invoice INV-2025-101\nVendor: Acme Widgets\nWidgets delivered\nTotal: 100.00\n";
+const GLOBEX: &str = "Invoice INV-2025-202\nVendor: Globex Turbines\nTurbines and widgets delivered\nTotal: 9,999.00\n";
+
+/// A working directory with shared invoices and the invoices of tenants `acme` and `globex`, made current
+fn workspace() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("doc-ai-tenants-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let files = [
+        ("data/invoices/inv_shared.txt", "Invoice INV-2025-001\nVendor: Shared Supplies\nTotal: 10.00\n"),
+        ("data/tenants/acme/invoices/inv_acme.txt", ACME),
+        ("data/tenants/globex/invoices/inv_globex.txt", GLOBEX),
+    ];
+    for (path, text) in files {
+        fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+        fs::write(dir.join(path), text).unwrap();
+    }
+    let config = format!(
+        "[[tenant]]\nname = \"acme\"\nkey_sha256 = \"{}\"\ndata_dir = \"data/tenants/acme\"\n\n\
+         [[tenant]]\nname = \"globex\"\nkey_sha256 = \"{}\"\ndata_dir = \"data/tenants/globex\"\n",
+        key_hash("acme-key"),
+        key_hash("globex-key")
+    );
+    fs::write(dir.join("doc-ai.toml"), config).unwrap();
+    std::env::set_current_dir(&dir).unwrap();
+    dir
+}
+
+fn tenant(name: &str) -> Tenant {
+    Tenant { name: name.to_string() }
+}
+
+fn under(paths: &[PathBuf], folder: &str) -> bool {
+    paths.iter().any(|p| p.starts_with(Path::new(folder)))
+}
+
+#[tokio::test]
+async fn a_tenant_never_retrieves_another_tenants_documents() {
+    workspace();
+    let config = Config::load(Some(Path::new("doc-ai.toml")), None).unwrap();
+    init_collections(collections_from_config(&config).unwrap());
+    let acme = find_collection("acme/invoices").unwrap();
+    let globex = find_collection("globex/invoices").unwrap();
+
+    // Queries: Globex's invoice number and words, asked by Acme
+    let question = "What is the total of INV-2025-202 from Globex Turbines?";
+    for mode in [RetrievalMode::Keyword, RetrievalMode::Bm25] {
+        let retrieval = RetrievalConfig { mode, ..RetrievalConfig::default() };
+        let found: Vec<PathBuf> = retrieve(question, acme, 10, &retrieval, &NoEvents).await.into_iter().map(|(p, _)| p).collect();
+        assert!(!under(&found, "data/tenants/globex"), "{:?}: {:?}", mode, found);
+
+        let own: Vec<PathBuf> = retrieve(question, globex, 10, &retrieval, &NoEvents).await.into_iter().map(|(p, _)| p).collect();
+        assert_eq!(own, vec![PathBuf::from("data/tenants/globex/invoices/inv_globex.txt")], "{:?}", mode);
+    }
+
+    // Search, within each tenant and without one
+    let files = |hits: Vec<doc_ai_server::SearchHit>| hits.into_iter().map(|h| h.path).collect::<Vec<_>>();
+    let as_acme = files(search("INV-2025-202 turbines widgets", None, Some(&tenant("acme")), 10, &|m| m.to_string()));
+    assert!(!under(&as_acme, "data/tenants/globex"), "{:?}", as_acme);
+    assert!(under(&as_acme, "data/tenants/acme"), "{:?}", as_acme);
+
+    // Naming the other tenant's collection reads it within the own tenant: nothing there
+    let named = files(search("turbines", Some("globex/invoices"), Some(&tenant("acme")), 10, &|m| m.to_string()));
+    assert!(named.is_empty(), "{:?}", named);
+
+    let shared = files(search("INV-2025-101 INV-2025-202 widgets", None, None, 10, &|m| m.to_string()));
+    assert!(!under(&shared, "data/tenants"), "{:?}", shared);
+}
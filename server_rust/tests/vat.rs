@@ -0,0 +1,64 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Reading the figures off an invoice: each label is matched whole, so a
+// "Total VAT" line is the tax and not the gross total. Figures too large to
+// compute with are reported as an issue, not a panic.
+
+use rust_decimal::Decimal;
+
+use doc_ai_server::vat::{check_invoice, extract_figures};
+
+fn amount(cents: i64) -> Option<Decimal> {
+    Some(Decimal::new(cents, 2))
+}
+
+#[test]
+fn total_vat_is_the_tax_not_the_total() {
+    let figures = extract_figures(
+        "Subtotal:          R1,000.00\nTotal VAT (15%):   R150.00\nTotal:             R1,150.00\n",
+    );
+    assert_eq!(figures.net, amount(100_000));
+    assert_eq!(figures.tax, amount(15_000));
+    assert_eq!(figures.tax_rate, Some(Decimal::from(15)));
+    assert_eq!(figures.gross, amount(115_000));
+}
+
+#[test]
+fn total_vat_after_the_total_does_not_replace_it() {
+    let figures = extract_figures("Net amount: 200.00\nTotal due: 230.00\nTotal VAT 15% 30.00\n");
+    assert_eq!(figures.net, amount(20_000));
+    assert_eq!(figures.tax, amount(3_000));
+    assert_eq!(figures.gross, amount(23_000));
+}
+
+#[test]
+fn labels_that_only_start_like_a_figure_are_ignored() {
+    let figures = extract_figures("Network cabling     2    R50.00    R100.00\nTaxi fare: R80.00\nTotals by month: R999.00\n");
+    assert_eq!(figures.net, None);
+    assert_eq!(figures.tax, None);
+    assert_eq!(figures.gross, None);
+    assert_eq!(figures.line_items.len(), 1);
+}
+
+#[test]
+fn the_sample_invoice_is_consistent() {
+    let text = std::fs::read_to_string("../data/invoices/inv_001.txt").unwrap();
+    let report = check_invoice("inv_001.txt", &text);
+    assert!(report.consistent, "{:?}", report.issues);
+    assert_eq!(report.figures.gross, amount(886_650));
+}
+
+#[test]
+fn figures_too_large_to_compute_are_an_issue() {
+    let huge = format!("5{}", "0".repeat(28));
+    let text = format!(
+        "Bulk order     {huge}    R50.00    R100.00\n\
+         Freight     1    {huge}.00    {huge}.00\n\
+         Storage     1    {huge}.00    {huge}.00\n\
+         Subtotal: {huge}.00\nVAT (15%): {huge}.00\nTotal: {huge}.00\n"
+    );
+    let report = check_invoice("huge.txt", &text);
+    assert!(!report.consistent);
+    let overflows = report.issues.iter().filter(|i| i.code == "amount_overflow").count();
+    assert_eq!(overflows, 4, "{:?}", report.issues);
+}
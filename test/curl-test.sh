@@ -38,5 +38,17 @@ curl -X POST http://localhost:8001/query \
 echo -e "\n"
 
 
+echo "  Querying Invoices and Employment Contracts together..."
+curl -X POST http://localhost:8001/query \
+  -H "Content-Type: application/json" \
+  -d '{"query": "Which invoice was billed to the company that employs Alice?", "collections": ["invoices", "contracts"]}'
+echo -e "\n"
+
+
+echo "  Streaming an answer (Server-Sent Events)..."
+curl -N "http://localhost:8001/query/stream?query=What%20is%20the%20total%20due%20on%20INV-2025-001%3F&collection=invoices"
+echo -e "\n"
+
+
 duration=$SECONDS
 echo "Tests completed. $((duration / 60)) minutes and $((duration % 60)) seconds elapsed."